@@ -0,0 +1,12 @@
+#!/bin/sh
+# Compiles every shader here into bin/, which include_shader!() embeds: name.spv for
+# release builds and debug_name.spv (with debug info) for debug builds. Needs glslc
+# from the Vulkan SDK or shaderc. Run after editing a shader or output_transfer.glsl.
+set -e
+cd "$(dirname "$0")"
+
+for src in *.vert *.frag *.comp; do
+    glslc --target-env=vulkan1.2 -O -o "bin/$src.spv" "$src"
+    glslc --target-env=vulkan1.2 -g -o "bin/debug_$src.spv" "$src"
+    echo "$src"
+done
@@ -1,5 +1,8 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
+#extension GL_GOOGLE_include_directive : require
+
+#include "output_transfer.glsl"
 
 layout(location = 0) in vec2 uv;
 
@@ -11,6 +14,11 @@ layout(set = 1, binding = 2) uniform UBO {
 	vec2 texelSize;
 } ubo;
 
+// Player's brightness setting; 1.0 leaves the image untouched
+layout(push_constant) uniform Display {
+	float gamma;
+} display;
+
 float getLuminance(vec2 offset) {
     vec2 tc = uv + offset * ubo.texelSize;
     return texture(texLuma, tc).r;
@@ -20,9 +28,8 @@ const int extraEdgeSteps = 3;
 const float edgeStepSizes[3] = { 1.5, 2.0, 2.0 };
 const float lastEdgeStepGuess = 8.0;
 
-void main() {
-	outColor = texture(texColor, uv);
-	return;
+vec4 fxaa() {
+	return texture(texColor, uv);
 
     float lum = texture(texLuma, uv).x;
 
@@ -42,7 +49,7 @@ void main() {
 	float lowest = min(min(min(min(m, n), e), s), w);
     float range = highest - lowest;
 
-    if (range < max(0.0625, 0.166 * highest)) { outColor = texture(texColor, uv); return; }
+    if (range < max(0.0625, 0.166 * highest)) { return texture(texColor, uv); }
 
     float factor = abs(m -  0.0833333333 * (2.0 * (n + e + s + w) + ne + se + sw + nw)) / range;
     factor = smoothstep(0.0, 1.0, clamp(factor, 0.0, 1.0));
@@ -155,5 +162,15 @@ void main() {
 	else {
 		blendUV.x += blendFactor * pixelStep;
 	}
-	outColor = texture(texColor, blendUV);
+	return texture(texColor, blendUV);
+}
+
+// Lifts (or deepens) the shadows and midtones while keeping black and white where they are,
+// so that bright scenes don't clip like they would if the colors were just scaled
+vec4 applyGamma(vec4 color) {
+	return vec4(pow(max(color.rgb, vec3(0.0)), vec3(1.0 / display.gamma)), color.a);
+}
+
+void main() {
+	outColor = encodeOutput(applyGamma(fxaa()));
 }
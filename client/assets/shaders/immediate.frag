@@ -1,11 +1,46 @@
 #version 460
 #extension GL_ARB_separate_shader_objects : enable
+#extension GL_GOOGLE_include_directive : require
+
+#include "output_transfer.glsl"
 
 layout(location = 0) out vec4 finalColor;
 
 layout(location = 0) in vec4 color;
+layout(location = 1) in vec2 uv;
+layout(location = 2) in vec2 local_pos;
+layout(location = 3) flat in vec2 rect_size;
+layout(location = 4) flat in uint style;
+layout(location = 5) flat in vec4 border_color;
+
+layout(set = 0, binding = 0) uniform sampler2DArray blockTextures;
+layout(set = 0, binding = 2) uniform sampler2D mapTexture;
+
+const uint FLAG_TEXTURED = 1;
+const uint FLAG_MAP = 2;
 
 void main() {
-    finalColor = color;
-}
+    float radius = float(style & 0xFF);
+    float border = float((style >> 8) & 0xFF);
+    uint layer = (style >> 16) & 0xFF;
+    uint flags = style >> 24;
 
+    vec4 fill = color;
+    if ((flags & FLAG_TEXTURED) != 0) {
+        fill *= texture(blockTextures, vec3(uv, layer));
+    } else if ((flags & FLAG_MAP) != 0) {
+        fill *= texture(mapTexture, uv);
+    }
+
+    // Signed distance to the edge of the rounded rect, negative inside
+    vec2 half_size = rect_size * 0.5;
+    vec2 q = abs(local_pos - half_size) - half_size + radius;
+    float dist = length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) - radius;
+
+    vec4 result = border > 0.0 ? mix(fill, border_color, clamp(dist + border + 0.5, 0.0, 1.0)) : fill;
+    result.a *= clamp(0.5 - dist, 0.0, 1.0); // antialiased edge
+    if (result.a == 0.0) {
+        discard;
+    }
+    finalColor = encodeOutput(result);
+}
@@ -1,10 +1,21 @@
 #version 460
 #extension GL_ARB_separate_shader_objects : enable
 
+// See UiVertex in ui_pass.rs
 layout(location = 0) in uint xy;
-layout(location = 1) in uint color_or_uv;
+layout(location = 1) in uint rgba;
+layout(location = 2) in uint uv;
+layout(location = 3) in uint local_xy; // from the rect's bottom left corner, in pixels
+layout(location = 4) in uint size;     // of the rect, in pixels
+layout(location = 5) in uint style;    // corner radius, border width, texture layer, flags
+layout(location = 6) in uint border_rgba;
 
 layout(location = 0) out vec4 color;
+layout(location = 1) out vec2 out_uv;
+layout(location = 2) out vec2 local_pos;
+layout(location = 3) flat out vec2 rect_size;
+layout(location = 4) flat out uint out_style;
+layout(location = 5) flat out vec4 border_color;
 
 layout (push_constant) uniform constants {
     vec2 scale;
@@ -12,6 +23,10 @@ layout (push_constant) uniform constants {
 
 void main() {
     gl_Position = vec4(vec2(-1.0) + pushConstants.scale * vec2(xy & 0xFFFF, (xy >> 16) & 0xFFFF), 0, 1);
-    color = unpackUnorm4x8(color_or_uv), vec4(2.2);
+    color = unpackUnorm4x8(rgba);
+    out_uv = unpackUnorm2x16(uv);
+    local_pos = vec2(local_xy & 0xFFFF, local_xy >> 16);
+    rect_size = vec2(size & 0xFFFF, size >> 16);
+    out_style = style;
+    border_color = unpackUnorm4x8(border_rgba);
 }
-
@@ -0,0 +1,42 @@
+// Encodes the (sRGB-encoded) output for the swapchain. See vkcore::OutputTransfer.
+layout(constant_id = 0) const uint OUTPUT_TRANSFER = 0;
+
+const uint TRANSFER_NONE = 0;
+const uint TRANSFER_LINEAR = 1;
+const uint TRANSFER_SCRGB = 2;
+const uint TRANSFER_PQ = 3;
+
+// Brightness of SDR white on an HDR display
+const float PAPER_WHITE_NITS = 200.0;
+
+vec3 srgbToLinear(vec3 c) {
+    return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), greaterThan(c, vec3(0.04045)));
+}
+
+vec3 linearToPq(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+vec4 encodeOutput(vec4 color) {
+    if (OUTPUT_TRANSFER == TRANSFER_NONE) {
+        return color;
+    }
+    vec3 linear = srgbToLinear(color.rgb);
+    if (OUTPUT_TRANSFER == TRANSFER_SCRGB) {
+        linear *= PAPER_WHITE_NITS / 80.0;
+    } else if (OUTPUT_TRANSFER == TRANSFER_PQ) {
+        const mat3 bt709ToBt2020 = mat3(
+            0.6274, 0.0691, 0.0164,
+            0.3293, 0.9195, 0.0880,
+            0.0433, 0.0114, 0.8956
+        );
+        linear = linearToPq(bt709ToBt2020 * linear * PAPER_WHITE_NITS);
+    }
+    return vec4(linear, color.a);
+}
@@ -1,5 +1,8 @@
 #version 460
 #extension GL_ARB_separate_shader_objects : enable
+#extension GL_GOOGLE_include_directive : require
+
+#include "output_transfer.glsl"
 
 layout(location = 0) in vec2 uv;
 layout(location = 1) in vec4 color;
@@ -14,5 +17,5 @@ void main() {
         discard;
     }
 
-    outColor = col;
+    outColor = encodeOutput(col);
 }
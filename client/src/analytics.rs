@@ -0,0 +1,132 @@
+use std::{
+    fmt::Write,
+    fs, io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use vkcore::Device;
+
+// Performance summaries for attaching to bug reports, one per session in game, kept in a
+// local file. Off unless turned on in the settings (see Settings::local_analytics), and
+// nothing is ever sent anywhere. Only what says how the game ran goes in: the GPU, frame
+// rates and chunk counts, not the username, the server or where the player went.
+//
+// The file is a JSON array with a session per line, newest last:
+// [
+// {"ended_unix": 1665900000, "gpu": "...", "avg_fps": 143.2, ...},
+// ]
+const ANALYTICS_PATH: &str = "analytics/performance.json";
+// Older sessions are dropped
+const MAX_SESSIONS: usize = 100;
+
+// The session in game so far, see metrics::Resources::session
+pub struct Session {
+    gpu: String,
+    driver_version: String,
+    frames: u32,
+    secs: f64,
+    slowest_frame_ms: f32,
+    view_distance_sum: u64,
+    max_chunks_loaded: usize,
+    max_chunk_memory_bytes: usize,
+    chunks_generated: u32,
+}
+
+// As of the frame being recorded
+pub struct FrameStats {
+    pub frame_ms: f32,
+    pub view_distance: u32,
+    pub chunks_loaded: usize,
+    pub chunk_memory_bytes: usize,
+    // Since joining
+    pub chunks_generated: u32,
+}
+
+impl Session {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            gpu: device.name.clone(),
+            driver_version: device.driver_version.clone(),
+            frames: 0,
+            secs: 0.0,
+            slowest_frame_ms: 0.0,
+            view_distance_sum: 0,
+            max_chunks_loaded: 0,
+            max_chunk_memory_bytes: 0,
+            chunks_generated: 0,
+        }
+    }
+
+    pub fn record_frame(&mut self, frame: &FrameStats) {
+        self.frames += 1;
+        self.secs += frame.frame_ms as f64 / 1000.0;
+        self.slowest_frame_ms = self.slowest_frame_ms.max(frame.frame_ms);
+        self.view_distance_sum += frame.view_distance as u64;
+        self.max_chunks_loaded = self.max_chunks_loaded.max(frame.chunks_loaded);
+        self.max_chunk_memory_bytes = self.max_chunk_memory_bytes.max(frame.chunk_memory_bytes);
+        self.chunks_generated = frame.chunks_generated;
+    }
+
+    // Adds the session to the file, unless nothing was recorded
+    pub fn save(&self) -> anyhow::Result<()> {
+        if self.frames == 0 {
+            return Ok(());
+        }
+        let old = match fs::read_to_string(ANALYTICS_PATH) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut sessions = old
+            .lines()
+            .map(|line| line.trim().trim_end_matches(','))
+            .filter(|line| line.starts_with('{'))
+            .collect::<Vec<_>>();
+        let line = self.to_json();
+        sessions.push(&line);
+        let kept = &sessions[sessions.len().saturating_sub(MAX_SESSIONS)..];
+
+        let out = format!("[\n{}\n]\n", kept.join(",\n"));
+        fs::create_dir_all("analytics")?;
+        fs::write(ANALYTICS_PATH, out)?;
+        println!("Performance summary added to {ANALYTICS_PATH}");
+        Ok(())
+    }
+
+    fn to_json(&self) -> String {
+        let ended = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+        let avg_view_distance = self.view_distance_sum as f64 / self.frames as f64;
+        let mut out = String::from("{");
+        let _ = write!(out, "\"ended_unix\": {ended}, ");
+        let _ = write!(out, "\"version\": {}, ", json_string(env!("CARGO_PKG_VERSION")));
+        let _ = write!(out, "\"os\": {}, ", json_string(std::env::consts::OS));
+        let _ = write!(out, "\"gpu\": {}, ", json_string(&self.gpu));
+        let _ = write!(out, "\"driver\": {}, ", json_string(&self.driver_version));
+        let _ = write!(out, "\"duration_s\": {:.1}, ", self.secs);
+        let _ = write!(out, "\"frames\": {}, ", self.frames);
+        let _ = write!(out, "\"avg_fps\": {:.1}, ", self.frames as f64 / self.secs.max(1e-3));
+        let _ = write!(out, "\"slowest_frame_ms\": {:.1}, ", self.slowest_frame_ms);
+        let _ = write!(out, "\"avg_view_distance\": {avg_view_distance:.1}, ");
+        let _ = write!(out, "\"max_chunks_loaded\": {}, ", self.max_chunks_loaded);
+        let _ = write!(out, "\"max_chunk_memory_mb\": {:.1}, ", self.max_chunk_memory_bytes as f64 / (1 << 20) as f64);
+        let _ = write!(out, "\"chunks_generated\": {}", self.chunks_generated);
+        out.push('}');
+        out
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c < ' ' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,104 @@
+use std::{iter::Peekable, path::PathBuf};
+
+use anyhow::{bail, Context};
+
+pub const USAGE: &str = "\
+Usage: `./client [options]`
+
+Options:
+  --server <host:port>                     Connect right away, skipping the connect screen
+  --username <name>                        Join as this instead of the selected profile's username
+  --profile <username>                     Start with this profile selected, see config/profiles.txt
+  --windowed [<width>x<height>]            Keep the window at this size in game instead of maximizing
+                                           it, 1280x720 by default
+  --fullscreen                             Borderless fullscreen in game
+  --assets <dir>                           Where config/, resourcepacks/ and cache/ are, the working
+                                           directory by default. The built-in assets are compiled in.
+  --gpu <index | name>                     Use this GPU instead of the one in the settings
+  --bench <file.csv> [<secs> | <path>]     Write frame times to the file once in game, for 60 seconds,
+                                           the given time or along a camera path saved with /path
+  --record-input <file>                    Write the keyboard and mouse input to the file
+  --replay-input <file> [exit]             Play input written with --record-input back, then exit
+  --help                                   Show this";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum WindowMode {
+    // Over the whole monitor, with the window decorations
+    #[default]
+    Maximized,
+    Windowed { width: u32, height: u32 },
+    Fullscreen,
+}
+
+// Command line arguments, parsed once in main(). Errors on anything unknown, so that typos
+// don't go unnoticed in scripts.
+#[derive(Debug, Default)]
+pub struct Args {
+    // Consumed by the first connect screen, so that disconnecting doesn't reconnect
+    pub server: Option<String>,
+    pub username: Option<String>,
+    pub profile: Option<String>,
+    pub window_mode: WindowMode,
+    pub assets: Option<PathBuf>,
+    pub gpu: Option<String>,
+    // (CSV file, duration or camera path), see Bench
+    pub bench: Option<(PathBuf, Option<String>)>,
+    pub record_input: Option<PathBuf>,
+    // (file, exit when done), see InputReplay
+    pub replay_input: Option<(PathBuf, bool)>,
+    pub help: bool,
+}
+
+impl Args {
+    const DEFAULT_WINDOW_SIZE: (u32, u32) = (1280, 720);
+
+    pub fn parse(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter().peekable();
+        while let Some(flag) = args.next() {
+            let args = &mut args;
+            match flag.as_str() {
+                "--server" => parsed.server = Some(value(args, &flag, "an address, like localhost:29477")?),
+                "--username" => parsed.username = Some(value(args, &flag, "a username")?),
+                "--profile" => parsed.profile = Some(value(args, &flag, "the username of a profile")?),
+                "--windowed" => {
+                    let (width, height) = match optional(args) {
+                        Some(size) => parse_size(&size).with_context(|| format!("--windowed: bad size '{size}', expected e.g. 1280x720"))?,
+                        None => Self::DEFAULT_WINDOW_SIZE,
+                    };
+                    parsed.window_mode = WindowMode::Windowed { width, height };
+                }
+                "--fullscreen" => parsed.window_mode = WindowMode::Fullscreen,
+                "--assets" => parsed.assets = Some(value(args, &flag, "a directory")?.into()),
+                "--gpu" => parsed.gpu = Some(value(args, &flag, "the index or name of a GPU")?),
+                "--bench" => {
+                    let path = value(args, &flag, "the path of the CSV file to write")?;
+                    parsed.bench = Some((path.into(), optional(args)));
+                }
+                "--record-input" => parsed.record_input = Some(value(args, &flag, "the path of the file to write")?.into()),
+                "--replay-input" => {
+                    let path = value(args, &flag, "the path of a file written with --record-input")?;
+                    let exit_when_done = args.next_if(|arg| arg == "exit").is_some();
+                    parsed.replay_input = Some((path.into(), exit_when_done));
+                }
+                "--help" | "-h" => parsed.help = true,
+                _ => bail!("Unknown argument '{flag}'"),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+// Anything starting with "--" is the next flag
+fn optional(args: &mut Peekable<impl Iterator<Item = String>>) -> Option<String> {
+    args.next_if(|arg| !arg.starts_with("--"))
+}
+
+fn value(args: &mut Peekable<impl Iterator<Item = String>>, flag: &str, what: &str) -> anyhow::Result<String> {
+    optional(args).with_context(|| format!("{flag} needs {what}"))
+}
+
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let (width, height) = size.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?)).filter(|&(width, height)| width > 0 && height > 0)
+}
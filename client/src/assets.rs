@@ -44,6 +44,24 @@ pub mod ui_pipeline {
     pub const IMMEDIATE_MODE_SHADER_FRAG: &[u8] = include_shader!("immediate.frag");
 }
 
+pub mod debug_pipeline {
+    pub const DEBUG_LINES_SHADER_VERT: &[u8] = include_shader!("debug_lines.vert");
+    pub const DEBUG_LINES_SHADER_FRAG: &[u8] = include_shader!("debug_lines.frag");
+}
+
+pub mod culling_pipeline {
+    pub const CULL_SHADER_COMP: &[u8] = include_shader!("cull.comp");
+    pub const INSTANCED_SHADER_VERT: &[u8] = include_shader!("instanced.vert");
+}
+
+pub mod lang {
+    // (code, contents)
+    pub const LANGUAGES: [(&str, &[u8]); 2] = [
+        ("en", include_asset!("lang/en.lang")),
+        ("fi", include_asset!("lang/fi.lang")),
+    ];
+}
+
 pub mod textures {
     // Lz4-HC compressed
     pub const TEXTURES: &[u8] = include_asset!("textures/packed.bin");
@@ -0,0 +1,99 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::Context;
+
+// Benchmarking mode, enabled with `--bench path.csv [seconds | camera path]`. Once in game
+// and done loading, writes one CSV row per frame and exits after the given time, or once
+// the camera path saved with `/path save <name>` has been flown through, so that renderer
+// changes can be compared with otherwise identical runs.
+pub struct Bench {
+    pub camera_path: Option<String>,
+    path: PathBuf,
+    out: BufWriter<File>,
+    duration_secs: f32,
+    started_secs: Option<f32>,
+    rows: u32,
+}
+
+// Everything besides the time is as of the frame being recorded
+pub struct FrameRow {
+    pub frame_ms: f32,
+    pub draw_calls: u32,
+    pub chunks_generated: u32,
+    pub view_distance: u32,
+    pub bytes_sent: u64, // totals since connecting
+    pub bytes_received: u64,
+    pub ping_ms: u32,
+}
+
+impl Bench {
+    const DEFAULT_SECS: f32 = 60.0;
+    const HEADER: &str =
+        "time_s,frame_ms,draw_calls,chunks_generated,view_distance,bytes_sent,bytes_received,ping_ms";
+
+    // `arg` is what came after the path, see Args::bench
+    pub fn new(path: PathBuf, arg: Option<String>) -> anyhow::Result<Self> {
+        let (duration_secs, camera_path) = match arg {
+            Some(arg) if arg.starts_with(|c: char| c.is_ascii_digit()) => {
+                let secs = arg.parse().with_context(|| format!("--bench: bad duration '{arg}'"))?;
+                (secs, None)
+            }
+            // The path decides when to stop
+            Some(name) => (f32::INFINITY, Some(name)),
+            None => (Self::DEFAULT_SECS, None),
+        };
+
+        let file = File::create(&path).with_context(|| format!("--bench: can't create {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        writeln!(out, "{}", Self::HEADER)?;
+        match &camera_path {
+            Some(name) => println!("Benchmarking along camera path '{name}', writing to {}", path.display()),
+            None => println!("Benchmarking for {duration_secs}s once in game, writing to {}", path.display()),
+        }
+
+        Ok(Self {
+            camera_path,
+            path,
+            out,
+            duration_secs,
+            started_secs: None,
+            rows: 0,
+        })
+    }
+
+    // Returns false once the time is up
+    pub fn record(&mut self, now_secs: f32, row: &FrameRow) -> bool {
+        let started = *self.started_secs.get_or_insert(now_secs);
+        let time = now_secs - started;
+        let written = writeln!(
+            self.out,
+            "{:.4},{:.3},{},{},{},{},{},{}",
+            time,
+            row.frame_ms,
+            row.draw_calls,
+            row.chunks_generated,
+            row.view_distance,
+            row.bytes_sent,
+            row.bytes_received,
+            row.ping_ms,
+        );
+        if let Err(e) = written {
+            eprintln!("Failed to write to {}, stopping the benchmark: {e}", self.path.display());
+            return false;
+        }
+        self.rows += 1;
+        time < self.duration_secs
+    }
+
+    pub fn finish(&mut self) {
+        if let Err(e) = self.out.flush() {
+            eprintln!("Failed to write to {}: {e}", self.path.display());
+            return;
+        }
+        println!("Benchmark done: {} frames written to {}", self.rows, self.path.display());
+    }
+}
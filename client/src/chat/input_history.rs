@@ -0,0 +1,75 @@
+use std::{fs, io, path::Path};
+
+// Messages or commands the player has sent, oldest first, saved to disk after every
+// change so that they survive restarts (and crashes).
+pub struct InputHistory {
+    entries: Vec<Vec<char>>,
+    path: &'static str,
+}
+
+impl InputHistory {
+    const MAX_ENTRIES: usize = 100;
+
+    // One entry per line. Starts out empty if the file can't be read.
+    pub fn load(path: &'static str) -> Self {
+        let entries = match fs::read_to_string(path) {
+            Ok(text) => text.lines().map(|line| line.chars().collect()).collect(),
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    eprintln!("Failed to read {path}: {e}");
+                }
+                Vec::new()
+            }
+        };
+        let mut history = Self { entries, path };
+        history.truncate();
+        history
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, idx: usize) -> &[char] {
+        &self.entries[idx]
+    }
+
+    pub fn push(&mut self, entry: &[char]) {
+        // Sending the same thing again moves it to the end instead of duplicating it
+        self.entries.retain(|old| old != entry);
+        self.entries.push(entry.to_owned());
+        self.truncate();
+
+        if let Err(e) = self.save() {
+            eprintln!("Failed to save {}: {e}", self.path);
+        }
+    }
+
+    // The closest entry before `idx` that starts with `prefix`
+    pub fn find_prev(&self, prefix: &[char], idx: usize) -> Option<usize> {
+        (0..idx.min(self.entries.len())).rev().find(|&i| self.entries[i].starts_with(prefix))
+    }
+
+    // The closest entry after `idx` that starts with `prefix`
+    pub fn find_next(&self, prefix: &[char], idx: usize) -> Option<usize> {
+        (idx + 1..self.entries.len()).find(|&i| self.entries[i].starts_with(prefix))
+    }
+
+    fn truncate(&mut self) {
+        let excess = self.entries.len().saturating_sub(Self::MAX_ENTRIES);
+        self.entries.drain(..excess);
+    }
+
+    fn save(&self) -> io::Result<()> {
+        if let Some(dir) = Path::new(self.path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        for entry in &self.entries {
+            // The text box doesn't allow line breaks, but just in case
+            text.extend(entry.iter().map(|&c| if c == '\n' { ' ' } else { c }));
+            text.push('\n');
+        }
+        fs::write(self.path, text)
+    }
+}
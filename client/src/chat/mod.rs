@@ -1,3 +1,5 @@
+mod input_history;
+
 use flexstr::LocalStr;
 use glam::Vec2;
 use smallvec::SmallVec;
@@ -16,8 +18,17 @@ use crate::{
     },
     resources::{core::WindowSize, Resources},
     text_box::{TextBox, TextBoxBuilder},
+    tr,
 };
 
+use self::input_history::InputHistory;
+
+const CHAT_HISTORY_PATH: &str = "config/chat_history.txt";
+const COMMAND_HISTORY_PATH: &str = "config/command_history.txt";
+
+// Commands the client handles itself instead of sending them to the server
+const LOCAL_COMMANDS: [&str; 2] = ["/path", "/theme"];
+
 struct LineBreaks {
     max_width_px: u16,           // to check if the indices are outdated
     indices: SmallVec<[u16; 4]>, // byte positions
@@ -50,17 +61,26 @@ impl ChatHistory {
     }
 }
 
+// Recalling sent messages with Up/Down. Only entries starting with what was typed before
+// pressing Up are shown; commands if that starts with a '/', chat messages otherwise.
+struct HistoryBrowser {
+    commands: bool,
+    prefix: Vec<char>,
+    idx: usize, // == len() when back at the typed text
+}
+
 pub struct Chat {
     // Contains entries that are drawn in chat, including own messages,
     // but no commands
     history: ChatHistory,
-    own_messages: Vec<Vec<char>>, // includes commands!
+    own_messages: InputHistory,
+    own_commands: InputHistory,
 
     chat_open: bool,
     text_box: TextBox,
 
-    // for scrolling up and down own messages
-    message_browser_idx: Option<usize>,
+    browser: Option<HistoryBrowser>,
+    local_commands: Vec<String>,
 }
 
 impl Chat {
@@ -68,14 +88,17 @@ impl Chat {
         let text_box = TextBoxBuilder::new_at(10, 12)
             .with_length_limit(500)
             .with_width(win_width - 20)
+            .with_scroll_margin(48)
             .build();
 
         Self {
             history: ChatHistory::new(),
-            own_messages: Vec::new(),
+            own_messages: InputHistory::load(CHAT_HISTORY_PATH),
+            own_commands: InputHistory::load(COMMAND_HISTORY_PATH),
             chat_open: false,
             text_box,
-            message_browser_idx: None,
+            browser: None,
+            local_commands: Vec::new(),
         }
     }
 
@@ -96,7 +119,7 @@ impl Chat {
             self.chat_open = false;
 
             self.text_box.reset(time_secs);
-            self.message_browser_idx = None;
+            self.browser = None;
 
             Self::set_grab_and_center(window, window_size.xy, CursorGrabMode::Confined);
             window.set_cursor_visible(false);
@@ -113,6 +136,11 @@ impl Chat {
         self.chat_open
     }
 
+    // Sent since the last call, see LOCAL_COMMANDS
+    pub fn take_local_commands(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.local_commands)
+    }
+
     fn set_grab_and_center(wnd: &Window, win_size: Vec2, grab: CursorGrabMode) {
         if let Err(e) = wnd.set_cursor_position::<LogicalPosition<u32>>(
             (win_size / 2.0).as_uvec2().to_array().into(),
@@ -166,18 +194,20 @@ impl Chat {
                         ..
                     },
                 ..
-            } if !self.own_messages.is_empty()
-                && (!self.text_box.modified() || self.text_box.is_empty()) =>
-            {
-                if let Some(idx) = self.message_browser_idx.as_mut() {
-                    *idx = (*idx + 1).min(self.own_messages.len());
-                    if *idx == self.own_messages.len() {
-                        self.text_box.set_contents(&[], res.renderer.ui.text(), res.time.secs_f32);
-                    } else {
-                        self.text_box
-                            .set_contents(&self.own_messages[*idx], res.renderer.ui.text(), res.time.secs_f32);
+            } if self.browser.is_some() && !res.input.keyboard_mods.shift() => {
+                let browser = self.browser.as_mut().unwrap();
+                let list = if browser.commands { &self.own_commands } else { &self.own_messages };
+                let contents = match list.find_next(&browser.prefix, browser.idx) {
+                    Some(idx) => {
+                        browser.idx = idx;
+                        list.get(idx)
                     }
-                }
+                    None => {
+                        browser.idx = list.len();
+                        &browser.prefix
+                    }
+                };
+                self.text_box.set_contents(contents, res.renderer.ui.text(), res.time.secs_f32);
                 true
             }
 
@@ -189,19 +219,20 @@ impl Chat {
                         ..
                     },
                 ..
-            } if !self.own_messages.is_empty()
-                && (!self.text_box.modified() || self.text_box.is_empty()) =>
-            {
-                if let Some(idx) = self.message_browser_idx.as_mut() {
-                    *idx = (*idx).saturating_sub(1);
-                } else {
-                    self.message_browser_idx = Some(self.own_messages.len() - 1);
+            } if !res.input.keyboard_mods.shift() => {
+                // Shift+Up is left to the text box for selecting
+                let (own_messages, own_commands) = (&self.own_messages, &self.own_commands);
+                let browser = self.browser.get_or_insert_with(|| {
+                    let prefix = self.text_box.contents().to_owned();
+                    let commands = prefix.first() == Some(&'/');
+                    let idx = if commands { own_commands.len() } else { own_messages.len() };
+                    HistoryBrowser { commands, prefix, idx }
+                });
+                let list = if browser.commands { own_commands } else { own_messages };
+                if let Some(idx) = list.find_prev(&browser.prefix, browser.idx) {
+                    browser.idx = idx;
+                    self.text_box.set_contents(list.get(idx), res.renderer.ui.text(), res.time.secs_f32);
                 }
-                self.text_box.set_contents(
-                    &self.own_messages[self.message_browser_idx.unwrap()],
-                    res.renderer.ui.text(),
-                    res.time.secs_f32
-                );
                 true
             }
             &WindowEvent::KeyboardInput {
@@ -215,14 +246,22 @@ impl Chat {
             } => {
                 let contents = trim_message(self.text_box.contents());
                 if !contents.is_empty() {
-                    self.own_messages.push(contents.to_owned());
+                    if contents[0] == '/' {
+                        self.own_commands.push(contents);
+                    } else {
+                        self.own_messages.push(contents);
+                    }
 
-                    if let Some(channels) = connection.channels() && channels.chat.send(contents.iter().collect()).is_ok() {
+                    let text: String = contents.iter().collect();
+                    let local = text.split(' ').next().map_or(false, |cmd| LOCAL_COMMANDS.contains(&cmd));
+                    if local {
+                        self.local_commands.push(text);
+                    } else if let Some(channels) = connection.channels() && channels.chat.send(text).is_ok() {
                         // Success
                     } else {
                         self.add_chat_entry(
-                            "Failed to send message".into(),
-                            0xFF_00_00_FF.into(),
+                            tr!(res.lang, "chat.send_failed").into(),
+                            res.settings.accessibility.palette.colors().error.into(),
                             res.time.secs_f32,
                         );
                     }
@@ -233,23 +272,24 @@ impl Chat {
             event => {
                 let consumed = self.text_box.process_event(event, res);
                 if self.text_box.modified() {
-                    self.message_browser_idx = None;
+                    self.browser = None;
                 }
                 consumed
             }
         }
     }
 
-    pub fn draw(&mut self, time_secs: f32, renderer: &mut UiRenderer, win_size: &WindowSize) {
+    // `background`: RGBA color behind the messages and the input box
+    pub fn draw(&mut self, time_secs: f32, renderer: &mut UiRenderer, win_size: &WindowSize, background: u32, selection: u32) {
         if self.is_open() {
             let w = win_size.extent.width as u16;
             renderer.draw_rect_xy_wh(
                 (10 - 2 * 3, 12 - 2 * 3),
                 (w - 20 + 2 * 3, 10 * 3),
-                0x06_06_06_50,
+                background,
             );
             self.text_box
-                .draw(renderer, win_size.extent.height as _, time_secs);
+                .draw(renderer, win_size.extent.height as _, time_secs, selection);
         }
 
         let max_time_ago = if self.chat_open { f32::MAX } else { 10.0 };
@@ -311,7 +351,7 @@ impl Chat {
                     max_width_px + 2 * PAD,
                     lines_drawn as u16 * 30 + 2 * PAD - 10,
                 ),
-                0x06_06_06_50,
+                background,
             );
         }
     }
@@ -328,3 +368,15 @@ fn trim_message(mut msg: &[char]) -> &[char] {
 
     msg
 }
+
+// Whether `message` is a chat message from someone else that says `username`. Players'
+// messages arrive as "sender: text".
+pub fn mentions(message: &str, username: &str) -> bool {
+    let Some((sender, text)) = message.split_once(": ") else {
+        return false;
+    };
+    sender != username
+        && text
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .any(|word| word.eq_ignore_ascii_case(username))
+}
@@ -1,3 +1,4 @@
+use flexstr::SharedStr;
 use glam::{Vec2, Vec3};
 
 #[derive(Clone, Copy)]
@@ -14,3 +15,28 @@ pub struct OldHeadRotation(pub Vec2);
 
 #[derive(Clone, Copy)]
 pub struct Velocity(pub Vec3);
+
+// Synced from the server as entity metadata
+
+pub struct Username(pub SharedStr);
+
+#[derive(Clone, Copy)]
+pub struct Crouching(pub bool);
+
+#[derive(Clone, Copy)]
+pub struct Sprinting(pub bool);
+
+#[derive(Clone, Copy)]
+pub struct Skin(pub u8);
+
+#[derive(Clone, Copy)]
+pub struct Afk(pub bool);
+
+// The block id of an item lying on the ground
+#[derive(Clone, Copy)]
+pub struct DroppedItem(pub u16);
+
+// The server gave this client authority over the entity (MetadataKey::Owner): its moves
+// come from here, and only teleports come from the server
+#[derive(Clone, Copy)]
+pub struct Owned;
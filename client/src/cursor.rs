@@ -0,0 +1,81 @@
+use winit::window::{CursorIcon, Window};
+
+use crate::{
+    renderer::ui_renderer::{RectStyle, TextureRegion, UiRenderer},
+    resources::core::WindowSize,
+    theme::Theme,
+};
+
+// The mouse cursor's look, in one place. Widgets ask for an icon every frame the mouse is over
+// them, and the window's cursor is changed at the end of the frame if the result differs from
+// the last one. If nothing asks, it's the default arrow, so nobody needs to reset it when the
+// mouse leaves or the state changes.
+pub struct Cursor {
+    requested: CursorIcon,
+    shown: CursorIcon,
+    // Drawn in the middle of the screen in game instead of the two lines
+    crosshair_texture: Option<TextureRegion>,
+}
+
+// What the crosshair is on, for its color and shape
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CrosshairTarget {
+    Nothing,
+    Block,
+    // Drawn with a gap in the middle, so that the entity shows through
+    Entity,
+}
+
+impl Cursor {
+    pub fn new() -> Self {
+        Self {
+            requested: CursorIcon::Default,
+            shown: CursorIcon::Default,
+            crosshair_texture: None,
+        }
+    }
+
+    // For this frame. The last request wins, so draw order decides between overlapping widgets.
+    pub fn request(&mut self, icon: CursorIcon) {
+        self.requested = icon;
+    }
+
+    pub fn end_frame(&mut self, window: &Window) {
+        if self.requested != self.shown {
+            window.set_cursor_icon(self.requested);
+            self.shown = self.requested;
+        }
+        self.requested = CursorIcon::Default;
+    }
+
+    pub fn set_crosshair_texture(&mut self, texture: Option<TextureRegion>) {
+        self.crosshair_texture = texture;
+    }
+
+    pub fn draw_crosshair(&self, ui: &mut UiRenderer, win_size: &WindowSize, target: CrosshairTarget, theme: &Theme) {
+        const SIZE: u16 = 24;
+        const GAP: u16 = 5;
+        let (x, y) = (win_size.extent.width as u16 / 2, win_size.extent.height as u16 / 2);
+        let color = match target {
+            CrosshairTarget::Nothing => theme.crosshair,
+            CrosshairTarget::Block => theme.crosshair_block,
+            CrosshairTarget::Entity => theme.crosshair_entity,
+        };
+
+        if let Some(texture) = self.crosshair_texture {
+            // Untinted unless on something
+            let tint = if target == CrosshairTarget::Nothing { 0xFF_FF_FF_FF } else { color };
+            let style = RectStyle { texture: Some(texture), ..RectStyle::solid(tint) };
+            ui.draw_rect_styled((x - SIZE / 2, y - SIZE / 2), (SIZE, SIZE), &style);
+        } else if target == CrosshairTarget::Entity {
+            let arm = SIZE / 2 - GAP;
+            ui.draw_rect_xy_wh((x - SIZE / 2, y - 1), (arm, 2), color);
+            ui.draw_rect_xy_wh((x + GAP, y - 1), (arm, 2), color);
+            ui.draw_rect_xy_wh((x - 1, y - SIZE / 2), (2, arm), color);
+            ui.draw_rect_xy_wh((x - 1, y + GAP), (2, arm), color);
+        } else {
+            ui.draw_rect_xy_wh((x - SIZE / 2, y - 1), (SIZE, 2), color);
+            ui.draw_rect_xy_wh((x - 1, y - SIZE / 2), (2, SIZE), color);
+        }
+    }
+}
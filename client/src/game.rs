@@ -1,8 +1,8 @@
 use std::time::Instant;
 
+use anyhow::{bail, Context};
 use erupt::vk;
 use glam::{Vec2, Vec3};
-use rayon::ThreadPoolBuilder;
 use winit::{
     dpi::{LogicalPosition, LogicalSize, PhysicalSize},
     event::{Event, WindowEvent},
@@ -11,15 +11,32 @@ use winit::{
 };
 
 use crate::{
-    input::{self, Keyboard, Mouse},
-    renderer::renderer,
+    args::Args,
+    bench::Bench,
+    cursor::Cursor,
+    input::{
+        self,
+        recording::{InputRecorder, InputReplay},
+        settings::InputSettings,
+        Keyboard, Mouse,
+    },
+    jobs::Jobs,
+    localization::Localization,
+    platform,
+    renderer::{renderer, ui_renderer::TextureRegion},
     resources::{
         core::{Time, WindowSize},
         metrics, Resources,
     },
+    profiles::Profiles,
+    settings::Settings,
+    theme::Theme,
+    toasts::Toasts,
     states::{game::camera::Camera, username_query::UsernameQueryState},
 };
 
+pub mod schedule;
+
 pub trait State {
     fn on_enter(&mut self, resources: &mut Resources) -> anyhow::Result<()>;
     fn on_update(&mut self, resources: &mut Resources) -> Option<Box<StateChange>>;
@@ -35,13 +52,38 @@ pub enum StateChange {
 pub struct Game {
     pub resources: Box<Resources>,
     active_state: Box<dyn State>,
+    input_recorder: Option<InputRecorder>, // --record-input
+    input_replay: Option<InputReplay>,     // --replay-input
 }
 
 // Update logic
 impl Game {
     // Called after all events have been processed
     pub fn update(&mut self, flow: &mut ControlFlow) {
+        // Replayed events go in where the real ones would have, before the frame
+        if let Some(replay) = &mut self.input_replay {
+            let due = replay.due_events(self.resources.window_handle.id());
+            let done = replay.is_done();
+            let exit = replay.exit_when_done();
+            for event in due {
+                self.dispatch_event(event, flow);
+            }
+            if done {
+                println!("Input replay finished");
+                self.input_replay = None;
+                if exit {
+                    *flow = ControlFlow::Exit;
+                }
+            }
+        }
+
         self.update_core_resources();
+        Jobs::begin_frame();
+
+        // Before the active state, as states render the frame in on_update()
+        let res = &mut *self.resources;
+        let colors = res.settings.accessibility.palette.colors();
+        res.toasts.draw(&mut res.renderer.ui, res.time.secs_f32, &res.window_size, colors, &res.theme);
 
         if let Some(result) = self.active_state.on_update(&mut self.resources) {
             self.handle_state_change(result, flow);
@@ -49,6 +91,12 @@ impl Game {
 
         // Update mouse again at end of tick
         Mouse::last_tick(&mut self.resources.input.mouse);
+        self.resources.cursor.end_frame(&self.resources.window_handle);
+
+        if let Some(recorder) = &mut self.input_recorder {
+            recorder.flush();
+        }
+        Jobs::end_frame();
     }
 
     fn update_core_resources(&mut self) {
@@ -87,6 +135,12 @@ impl Game {
         if self.active_state.on_exit(&mut self.resources).is_err() {
             eprintln!("Error in state.on_exit()!");
         }
+        if let Some(bench) = &mut self.resources.bench {
+            bench.finish();
+        }
+        if let Some(recorder) = &mut self.input_recorder {
+            recorder.finish();
+        }
 
         self.resources.renderer.destroy_self();
     }
@@ -95,6 +149,16 @@ impl Game {
 // Event handling
 impl Game {
     pub fn on_event(&mut self, event: Event<()>, flow: &mut ControlFlow) {
+        if let Some(recorder) = &mut self.input_recorder {
+            recorder.record(&event);
+        }
+        if self.input_replay.is_some() && InputReplay::is_live_input(&event) {
+            return;
+        }
+        self.dispatch_event(event, flow);
+    }
+
+    fn dispatch_event(&mut self, event: Event<()>, flow: &mut ControlFlow) {
         match &event {
             Event::MainEventsCleared => self.update(flow),
             Event::LoopDestroyed => self.on_stop(),
@@ -156,8 +220,12 @@ impl Game {
 
 // Initialization
 impl Game {
-    pub fn init(event_loop: &EventLoop<()>) -> anyhow::Result<Self> {
+    pub fn init(event_loop: &EventLoop<()>, mut args: Args) -> anyhow::Result<Self> {
         println!("Starting game @ {}Hz tick rate", shared::TICKS_PER_SECOND);
+        // Before anything is read from or written to there
+        if let Some(dir) = &args.assets {
+            std::env::set_current_dir(dir).with_context(|| format!("--assets: can't use {}", dir.display()))?;
+        }
 
         let fullscreen_size = event_loop.primary_monitor().unwrap().size();
         let fullscreen_size =
@@ -165,7 +233,7 @@ impl Game {
 
         let window_size = LogicalSize::new(400, 480);
         let window = WindowBuilder::new()
-            .with_title("Game")
+            .with_title(platform::TITLE)
             .with_inner_size(window_size)
             .with_min_inner_size(LogicalSize::new(300, 450))
             .with_position(LogicalPosition::new(
@@ -178,7 +246,21 @@ impl Game {
         let time = Instant::now();
         let default_camera =
             Camera::new(Vec3::ZERO, Vec2::new(400.0, 480.0), f32::to_radians(80.0));
-        let renderer = renderer::init(&window, &default_camera)?;
+        let mut profiles = Profiles::load();
+        if let Some(name) = &args.profile && !profiles.preselect(name) {
+            let names = profiles.list().iter().map(|profile| profile.username.as_str()).collect::<Vec<_>>();
+            bail!("--profile: no profile '{name}', there are: {}", names.join(", "));
+        }
+        let mut settings = Settings::load();
+        let mut input_settings = InputSettings::default();
+        if let Some(profile) = profiles.selected() {
+            profile.apply(&mut settings, &mut input_settings);
+        }
+        // --gpu overrides the setting, see VkConfig::gpu
+        let gpu = args.gpu.clone().or_else(|| settings.graphics.gpu.clone());
+        platform::set_icon(&window);
+        let g = &settings.graphics;
+        let renderer = renderer::init(&window, &default_camera, gpu.as_deref(), g.anisotropy as f32, g.display_mode)?;
         //window.set_inner_size(LogicalSize::new(512, 512));
 
         // Allocate all but one core/thread to the threadpool
@@ -201,10 +283,7 @@ impl Game {
                 xy: Vec2::new(window_size.width as f32, window_size.height as f32),
                 monitor_size_px: fullscreen_size,
             },
-            thread_pool: ThreadPoolBuilder::new()
-                .num_threads(thread_pool_threads)
-                .thread_name(|i| format!("Worker thread #{i}"))
-                .build()?,
+            jobs: Jobs::new(thread_pool_threads)?,
             metrics: metrics::Resources {
                 frame_count: 0,
                 frame_time: metrics::FrameTime {
@@ -213,17 +292,48 @@ impl Game {
                     frametime_history: [1000.0 / 60.0; 32],
                     last_updated: time,
                 },
+                session: None,
             },
             renderer,
             input: input::init((window_size.width, window_size.height))?,
+            lang: Localization::load(),
+            settings,
+            profiles,
+            theme: Theme::load(),
+            toasts: Toasts::new(),
+            cursor: Cursor::new(),
+            bench: args.bench.take().map(|(path, arg)| Bench::new(path, arg)).transpose()?,
+            args,
         });
 
+        resources.input.settings = input_settings;
+        let text_effect = resources.settings.accessibility.text_effect;
+        resources.renderer.ui.text().set_effect(text_effect);
+        let crosshair = resources.settings.graphics.crosshair_texture.map(TextureRegion::whole);
+        resources.cursor.set_crosshair_texture(crosshair);
+        if let Some(pack) = resources.settings.graphics.resource_pack.clone() {
+            if let Err(e) = resources.renderer.set_resource_pack(Some(&pack)) {
+                eprintln!("Failed to load resource pack '{pack}', using the built-in textures: {e:#}");
+                resources.settings.graphics.resource_pack = None;
+            }
+        }
+
         let mut active_state = Box::new(UsernameQueryState::new()?);
         active_state.on_enter(&mut resources)?;
 
+        // Last, so that the timestamps line up between recording and replaying
+        let window_size = resources.window_handle.inner_size();
+        let args = &mut resources.args;
+        let input_recorder = args.record_input.take().map(|path| InputRecorder::new(path, window_size)).transpose()?;
+        let input_replay = args.replay_input.take()
+            .map(|(path, exit_when_done)| InputReplay::new(path, exit_when_done, window_size))
+            .transpose()?;
+
         Ok(Self {
             resources,
             active_state,
+            input_recorder,
+            input_replay,
         })
     }
 }
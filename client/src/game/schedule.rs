@@ -0,0 +1,55 @@
+use crate::resources::Resources;
+
+use super::StateChange;
+
+// Stages run in declaration order, once per frame
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    Input,      // Read keyboard/mouse state
+    NetIn,      // Process messages from the server
+    Simulate,   // Movement, physics, world updates
+    NetOut,     // Send this frame's results to the server
+    RenderPrep, // Queue UI and anything else that needs to be drawn this frame
+}
+
+impl Stage {
+    pub const COUNT: usize = 5;
+}
+
+// A system may request a state change, which stops the rest of the schedule from running
+pub type System<S> = fn(&mut S, &mut Resources) -> Option<Box<StateChange>>;
+
+pub struct Schedule<S> {
+    stages: [Vec<System<S>>; Stage::COUNT],
+}
+
+impl<S> Schedule<S> {
+    pub fn new() -> Self {
+        Self {
+            stages: Default::default(),
+        }
+    }
+
+    // Systems within a stage run in the order they were added
+    pub fn add_system(&mut self, stage: Stage, system: System<S>) -> &mut Self {
+        self.stages[stage as usize].push(system);
+        self
+    }
+
+    pub fn run(&self, state: &mut S, res: &mut Resources) -> Option<Box<StateChange>> {
+        for stage in &self.stages {
+            for system in stage {
+                if let Some(change) = system(state, res) {
+                    return Some(change);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<S> Default for Schedule<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,5 +1,6 @@
 pub mod keyboard;
 pub mod mouse;
+pub mod recording;
 pub mod settings;
 
 use arboard::Clipboard;
@@ -0,0 +1,297 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::PathBuf,
+    time::Instant,
+};
+
+use anyhow::Context;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{
+        DeviceEvent, DeviceId, ElementState, Event, KeyboardInput, ModifiersState, MouseButton,
+        MouseScrollDelta, TouchPhase, VirtualKeyCode, WindowEvent,
+    },
+    window::WindowId,
+};
+
+// Input recording for reproducing UI bugs, enabled with `--record-input path.txt`. Keyboard
+// and mouse events are written one per line with the milliseconds since startup, and
+// `--replay-input path.txt [exit]` feeds them back into `Game::on_event()` at the same
+// times, ignoring the real keyboard and mouse meanwhile. Cursor positions are in pixels, so
+// replays should be done with the window at the size it was recorded at.
+pub struct InputRecorder {
+    path: PathBuf,
+    out: BufWriter<File>,
+    started: Instant,
+    events: u32,
+}
+
+pub struct InputReplay {
+    events: Vec<(u32, Recorded)>, // (ms, event), in reverse order so the next one can be popped
+    started: Instant,
+    exit_when_done: bool,
+}
+
+// The parts of the winit events that matter to the game
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Recorded {
+    Key { scancode: u32, pressed: bool, key: Option<VirtualKeyCode> }, // DeviceEvent::Key
+    WindowKey { scancode: u32, pressed: bool, key: Option<VirtualKeyCode> },
+    Char(char),
+    Mods(ModifiersState),
+    Cursor(f64, f64),
+    Button(MouseButton, bool),
+    WheelLines(f32, f32),
+    WheelPixels(f64, f64),
+    Motion(f64, f64),
+    Focus(bool),
+}
+
+const HEADER: &str = "# input recording v1";
+
+impl InputRecorder {
+    pub fn new(path: PathBuf, window_size: PhysicalSize<u32>) -> anyhow::Result<Self> {
+        let file = File::create(&path).with_context(|| format!("--record-input: can't create {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        writeln!(out, "{HEADER}")?;
+        writeln!(out, "# size {} {}", window_size.width, window_size.height)?;
+        println!("Recording input to {}", path.display());
+
+        Ok(Self { path, out, started: Instant::now(), events: 0 })
+    }
+
+    pub fn record(&mut self, event: &Event<()>) {
+        let Some(recorded) = Recorded::from_event(event) else {
+            return;
+        };
+        let ms = self.started.elapsed().as_millis() as u32;
+        if let Err(e) = writeln!(self.out, "{ms} {}", recorded.to_line()) {
+            eprintln!("Failed to write to {}: {e}", self.path.display());
+        }
+        self.events += 1;
+    }
+
+    // Called once per frame, so that a crash loses at most a frame's worth of input
+    pub fn flush(&mut self) {
+        if let Err(e) = self.out.flush() {
+            eprintln!("Failed to write to {}: {e}", self.path.display());
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.flush();
+        println!("Recorded {} input events to {}", self.events, self.path.display());
+    }
+}
+
+impl InputReplay {
+    pub fn new(path: PathBuf, exit_when_done: bool, window_size: PhysicalSize<u32>) -> anyhow::Result<Self> {
+        let file = File::open(&path).with_context(|| format!("--replay-input: can't open {}", path.display()))?;
+        let mut events = Vec::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if let Some(recorded) = line.strip_prefix("# size ") {
+                let current = format!("{} {}", window_size.width, window_size.height);
+                if recorded != current {
+                    let (recorded, current) = (recorded.replace(' ', "x"), current.replace(' ', "x"));
+                    eprintln!("Input was recorded at {recorded}, replaying at {current}: cursor positions will be off");
+                }
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let event = line
+                .split_once(' ')
+                .and_then(|(ms, event)| Some((ms.parse().ok()?, Recorded::parse(event)?)))
+                .with_context(|| format!("{}:{}: bad event '{line}'", path.display(), i + 1))?;
+            events.push(event);
+        }
+        println!("Replaying {} input events from {}", events.len(), path.display());
+        events.reverse();
+
+        Ok(Self { events, started: Instant::now(), exit_when_done })
+    }
+
+    // The events that are due by now, in order
+    pub fn due_events(&mut self, window: WindowId) -> Vec<Event<'static, ()>> {
+        let now_ms = self.started.elapsed().as_millis() as u32;
+        let mut due = Vec::new();
+        while let Some(&(ms, event)) = self.events.last() {
+            if ms > now_ms {
+                break;
+            }
+            due.push(event.to_event(window));
+            self.events.pop();
+        }
+        due
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn exit_when_done(&self) -> bool {
+        self.exit_when_done
+    }
+
+    // Real input would interfere with the replay
+    pub fn is_live_input(event: &Event<()>) -> bool {
+        Recorded::from_event(event).is_some()
+    }
+}
+
+impl Recorded {
+    fn from_event(event: &Event<()>) -> Option<Self> {
+        Some(match event {
+            Event::DeviceEvent { event, .. } => match *event {
+                DeviceEvent::Key(KeyboardInput { scancode, state, virtual_keycode, .. }) => {
+                    Self::Key { scancode, pressed: state == ElementState::Pressed, key: virtual_keycode }
+                }
+                DeviceEvent::MouseMotion { delta: (x, y) } => Self::Motion(x, y),
+                _ => return None,
+            },
+            Event::WindowEvent { event, .. } => match *event {
+                WindowEvent::KeyboardInput { input: KeyboardInput { scancode, state, virtual_keycode, .. }, .. } => {
+                    Self::WindowKey { scancode, pressed: state == ElementState::Pressed, key: virtual_keycode }
+                }
+                WindowEvent::ReceivedCharacter(char) => Self::Char(char),
+                WindowEvent::ModifiersChanged(mods) => Self::Mods(mods),
+                WindowEvent::CursorMoved { position, .. } => Self::Cursor(position.x, position.y),
+                WindowEvent::MouseInput { button, state, .. } => Self::Button(button, state == ElementState::Pressed),
+                WindowEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(x, y), .. } => Self::WheelLines(x, y),
+                WindowEvent::MouseWheel { delta: MouseScrollDelta::PixelDelta(pos), .. } => Self::WheelPixels(pos.x, pos.y),
+                WindowEvent::Focused(focused) => Self::Focus(focused),
+                _ => return None,
+            },
+            _ => return None,
+        })
+    }
+
+    #[allow(deprecated)] // the `modifiers` fields
+    fn to_event(self, window_id: WindowId) -> Event<'static, ()> {
+        // Nothing looks at the device
+        let device_id = unsafe { DeviceId::dummy() };
+        let state = |pressed| if pressed { ElementState::Pressed } else { ElementState::Released };
+        let input = |scancode, pressed, key| KeyboardInput {
+            scancode,
+            state: state(pressed),
+            virtual_keycode: key,
+            modifiers: ModifiersState::empty(),
+        };
+        let modifiers = ModifiersState::empty();
+
+        let event = match self {
+            Self::Key { scancode, pressed, key } => {
+                return Event::DeviceEvent { device_id, event: DeviceEvent::Key(input(scancode, pressed, key)) };
+            }
+            Self::Motion(x, y) => {
+                return Event::DeviceEvent { device_id, event: DeviceEvent::MouseMotion { delta: (x, y) } };
+            }
+            Self::WindowKey { scancode, pressed, key } => WindowEvent::KeyboardInput {
+                device_id,
+                input: input(scancode, pressed, key),
+                is_synthetic: false,
+            },
+            Self::Char(char) => WindowEvent::ReceivedCharacter(char),
+            Self::Mods(mods) => WindowEvent::ModifiersChanged(mods),
+            Self::Cursor(x, y) => WindowEvent::CursorMoved { device_id, position: PhysicalPosition::new(x, y), modifiers },
+            Self::Button(button, pressed) => WindowEvent::MouseInput { device_id, state: state(pressed), button, modifiers },
+            Self::WheelLines(x, y) => WindowEvent::MouseWheel {
+                device_id,
+                delta: MouseScrollDelta::LineDelta(x, y),
+                phase: TouchPhase::Moved,
+                modifiers,
+            },
+            Self::WheelPixels(x, y) => WindowEvent::MouseWheel {
+                device_id,
+                delta: MouseScrollDelta::PixelDelta(PhysicalPosition::new(x, y)),
+                phase: TouchPhase::Moved,
+                modifiers,
+            },
+            Self::Focus(focused) => WindowEvent::Focused(focused),
+        };
+        Event::WindowEvent { window_id, event }
+    }
+
+    // "key 17 p 22", "char 97", "cursor 120.5 300"...
+    fn to_line(&self) -> String {
+        let key = |scancode, pressed: bool, key: Option<VirtualKeyCode>| {
+            let state = if pressed { 'p' } else { 'r' };
+            match key {
+                Some(key) => format!("{scancode} {state} {}", key as u32),
+                None => format!("{scancode} {state} -"),
+            }
+        };
+        match *self {
+            Self::Key { scancode, pressed, key: k } => format!("key {}", key(scancode, pressed, k)),
+            Self::WindowKey { scancode, pressed, key: k } => format!("window_key {}", key(scancode, pressed, k)),
+            // As a number, because the character could be whitespace
+            Self::Char(char) => format!("char {}", char as u32),
+            Self::Mods(mods) => format!("mods {}", mods.bits()),
+            Self::Cursor(x, y) => format!("cursor {x} {y}"),
+            Self::Button(button, pressed) => {
+                let button = match button {
+                    MouseButton::Left => "left".to_owned(),
+                    MouseButton::Right => "right".to_owned(),
+                    MouseButton::Middle => "middle".to_owned(),
+                    MouseButton::Other(n) => n.to_string(),
+                };
+                format!("button {button} {}", if pressed { 'p' } else { 'r' })
+            }
+            Self::WheelLines(x, y) => format!("wheel_lines {x} {y}"),
+            Self::WheelPixels(x, y) => format!("wheel_pixels {x} {y}"),
+            Self::Motion(x, y) => format!("motion {x} {y}"),
+            Self::Focus(focused) => format!("focus {}", focused as u8),
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split(' ');
+        let kind = words.next()?;
+        let mut next = || words.next();
+        let pressed = |word: Option<&str>| match word? {
+            "p" => Some(true),
+            "r" => Some(false),
+            _ => None,
+        };
+
+        Some(match kind {
+            "key" | "window_key" => {
+                let scancode = next()?.parse().ok()?;
+                let pressed = pressed(next())?;
+                let key = match next()? {
+                    "-" => None,
+                    key => Some(key_from_u32(key.parse().ok()?)?),
+                };
+                match kind {
+                    "key" => Self::Key { scancode, pressed, key },
+                    _ => Self::WindowKey { scancode, pressed, key },
+                }
+            }
+            "char" => Self::Char(char::from_u32(next()?.parse().ok()?)?),
+            "mods" => Self::Mods(ModifiersState::from_bits_truncate(next()?.parse().ok()?)),
+            "cursor" => Self::Cursor(next()?.parse().ok()?, next()?.parse().ok()?),
+            "button" => {
+                let button = match next()? {
+                    "left" => MouseButton::Left,
+                    "right" => MouseButton::Right,
+                    "middle" => MouseButton::Middle,
+                    n => MouseButton::Other(n.parse().ok()?),
+                };
+                Self::Button(button, pressed(next())?)
+            }
+            "wheel_lines" => Self::WheelLines(next()?.parse().ok()?, next()?.parse().ok()?),
+            "wheel_pixels" => Self::WheelPixels(next()?.parse().ok()?, next()?.parse().ok()?),
+            "motion" => Self::Motion(next()?.parse().ok()?, next()?.parse().ok()?),
+            "focus" => Self::Focus(next()? == "1"),
+            _ => return None,
+        })
+    }
+}
+
+fn key_from_u32(key: u32) -> Option<VirtualKeyCode> {
+    // VirtualKeyCode is a fieldless #[repr(u32)] enum, and Cut is its last variant
+    (key <= VirtualKeyCode::Cut as u32).then(|| unsafe { std::mem::transmute(key) })
+}
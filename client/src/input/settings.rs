@@ -7,7 +7,49 @@ pub struct Keybindings {
     pub right: Key,
     pub back: Key,
     pub jump: Key,
+    pub sneak: Key,
+    pub sprint: Key,
     pub open_chat: Key,
+    // Taking control of the entity under the crosshair, or letting go of it
+    pub interact: Key,
+    // Narrows the view while held
+    pub zoom: Key,
+}
+
+impl Keybindings {
+    // Names as in profiles, see Profile::apply(). Returns false if there is no such action.
+    pub fn set(&mut self, action: &str, key: Key) -> bool {
+        let binding = match action {
+            "fwd" => &mut self.fwd,
+            "left" => &mut self.left,
+            "right" => &mut self.right,
+            "back" => &mut self.back,
+            "jump" => &mut self.jump,
+            "sneak" => &mut self.sneak,
+            "sprint" => &mut self.sprint,
+            "open_chat" => &mut self.open_chat,
+            "interact" => &mut self.interact,
+            "zoom" => &mut self.zoom,
+            _ => return false,
+        };
+        *binding = key;
+        true
+    }
+}
+
+// Keys that can be bound, by their names in winit's VirtualKeyCode (A, Key1, LShift...)
+const BINDABLE: [Key; 56] = {
+    use winit::event::VirtualKeyCode::*;
+    [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+        Space, Return, Tab, Back, Escape, LShift, RShift, LControl, RControl, LAlt, RAlt,
+        Up, Down, Left, Right, Insert, Delete, Home, End, Capital,
+    ]
+};
+
+pub fn key_from_name(name: &str) -> Option<Key> {
+    BINDABLE.into_iter().find(|key| format!("{key:?}") == name)
 }
 
 impl Default for Keybindings {
@@ -18,7 +60,11 @@ impl Default for Keybindings {
             right: Key::D,
             back: Key::S,
             jump: Key::Space,
+            sneak: Key::LShift,
+            sprint: Key::LControl,
             open_chat: Key::Return,
+            interact: Key::E,
+            zoom: Key::Z,
         }
     }
 }
@@ -0,0 +1,98 @@
+use std::{
+    cell::Cell,
+    sync::mpsc::{self, Receiver, TryRecvError},
+};
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+// All work that runs off the main thread goes through here, so that there is one place to
+// look for what the worker threads are up to. Jobs are fire-and-forget closures whose result
+// is picked up later by polling the handle; the main loop must never sit waiting for one
+// while a frame is in progress, which debug builds check.
+
+thread_local! {
+    // Set between Jobs::begin_frame() and Jobs::end_frame(), on the main thread only
+    static IN_FRAME: Cell<bool> = Cell::new(false);
+}
+
+pub struct Jobs {
+    pool: ThreadPool,
+}
+
+impl Jobs {
+    pub fn new(threads: usize) -> anyhow::Result<Self> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("Worker thread #{i}"))
+            .build()?;
+        Ok(Self { pool })
+    }
+
+    pub fn spawn<T, F>(&self, job: F) -> JobHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (tx, rx) = mpsc::sync_channel(1);
+        self.pool.spawn(move || {
+            // The handle may have been dropped by now, in which case nobody wants the result
+            let _ = tx.send(job());
+        });
+        JobHandle { rx: Some(rx), result: None }
+    }
+
+    pub fn begin_frame() {
+        IN_FRAME.with(|f| f.set(true));
+    }
+
+    pub fn end_frame() {
+        IN_FRAME.with(|f| f.set(false));
+    }
+
+    pub fn in_frame() -> bool {
+        IN_FRAME.with(|f| f.get())
+    }
+}
+
+// Dropping the handle abandons the job: it still runs to completion, but the result is thrown away.
+pub struct JobHandle<T> {
+    rx: Option<Receiver<T>>, // None once the result has been received
+    result: Option<T>, // received by is_done() but not yet taken
+}
+
+impl<T> JobHandle<T> {
+    // The result if the job has finished, once; None after that.
+    pub fn poll(&mut self) -> Option<T> {
+        if self.result.is_none() {
+            self.result = self.try_receive();
+        }
+        self.result.take()
+    }
+
+    pub fn is_done(&mut self) -> bool {
+        if self.result.is_none() {
+            self.result = self.try_receive();
+        }
+        self.result.is_some()
+    }
+
+    // Blocks until the job is done. Only for loading screens and the like: never in a frame.
+    pub fn wait(mut self) -> T {
+        debug_assert!(!Jobs::in_frame(), "blocking on a job during a frame");
+        if let Some(result) = self.result.take() {
+            return result;
+        }
+        let rx = self.rx.take().expect("job result already taken");
+        rx.recv().expect("job panicked")
+    }
+
+    fn try_receive(&mut self) -> Option<T> {
+        let result = match self.rx.as_ref()?.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => panic!("job panicked"),
+        };
+        self.rx = None;
+        Some(result)
+    }
+}
@@ -0,0 +1,118 @@
+use std::{collections::HashMap, fmt::Display, fs, io};
+
+use crate::assets::lang::LANGUAGES;
+
+const FALLBACK_LANGUAGE: &str = "en";
+// The chosen language is remembered between sessions
+const LANGUAGE_PATH: &str = "config/language.txt";
+
+// UI strings by key, from the language files in assets/lang. Keys missing from the
+// current language come from English, and if missing there too, the key itself is shown.
+pub struct Localization {
+    language_idx: usize,
+    strings: HashMap<&'static str, &'static str>,
+    fallback: HashMap<&'static str, &'static str>,
+}
+
+// `tr!(res.lang, "menu.join")` gives a &str, `tr!(res.lang, "hud.fps", fps = 60)` a String
+#[macro_export]
+macro_rules! tr {
+    ($lang:expr, $key:literal) => {
+        $lang.get($key)
+    };
+    ($lang:expr, $key:literal, $($name:ident = $value:expr),+ $(,)?) => {
+        $lang.format($key, &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+])
+    };
+}
+
+impl Localization {
+    pub fn load() -> Self {
+        let fallback_idx = Self::index_of(FALLBACK_LANGUAGE).unwrap();
+        let language_idx = match fs::read_to_string(LANGUAGE_PATH) {
+            Ok(code) => Self::index_of(code.trim()).unwrap_or_else(|| {
+                eprintln!("Unknown language '{}' in {LANGUAGE_PATH}", code.trim());
+                fallback_idx
+            }),
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    eprintln!("Failed to read {LANGUAGE_PATH}: {e}");
+                }
+                fallback_idx
+            }
+        };
+
+        Self {
+            language_idx,
+            strings: parse(language_idx),
+            fallback: parse(fallback_idx),
+        }
+    }
+
+    pub fn language_name(&self) -> &str {
+        self.get("language.name")
+    }
+
+    // Switches to the next (dir > 0) or previous language, for the language setting
+    pub fn cycle_language(&mut self, dir: i32) {
+        let count = LANGUAGES.len() as i32;
+        self.language_idx = (self.language_idx as i32 + dir).rem_euclid(count) as usize;
+        self.strings = parse(self.language_idx);
+
+        let save = fs::create_dir_all("config").and_then(|_| fs::write(LANGUAGE_PATH, LANGUAGES[self.language_idx].0));
+        if let Err(e) = save {
+            eprintln!("Failed to save {LANGUAGE_PATH}: {e}");
+        }
+    }
+
+    pub fn get<'a>(&self, key: &'a str) -> &'a str {
+        self.strings.get(key).or_else(|| self.fallback.get(key)).copied().unwrap_or(key)
+    }
+
+    // Replaces each {name} with the argument of that name. Unknown names are left as-is.
+    pub fn format(&self, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        let mut template = self.get(key);
+        let mut out = String::with_capacity(template.len() + 16);
+        while let Some(start) = template.find('{') {
+            out.push_str(&template[..start]);
+            template = &template[start..];
+
+            let Some(end) = template.find('}') else {
+                break;
+            };
+            match args.iter().find(|(name, _)| *name == &template[1..end]) {
+                Some((_, value)) => out.push_str(&value.to_string()),
+                None => out.push_str(&template[..=end]),
+            }
+            template = &template[end + 1..];
+        }
+        out.push_str(template);
+        out
+    }
+
+    fn index_of(code: &str) -> Option<usize> {
+        LANGUAGES.iter().position(|&(lang, _)| lang == code)
+    }
+}
+
+fn parse(language_idx: usize) -> HashMap<&'static str, &'static str> {
+    let (code, contents) = LANGUAGES[language_idx];
+    let Ok(text) = std::str::from_utf8(contents) else {
+        eprintln!("Language file {code}.lang is not valid UTF-8");
+        return HashMap::new();
+    };
+
+    let mut strings = HashMap::new();
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key, value)) => {
+                strings.insert(key.trim(), value.trim());
+            }
+            None => eprintln!("{code}.lang:{}: expected 'key = value'", line_no + 1),
+        }
+    }
+    strings
+}
@@ -0,0 +1,59 @@
+use std::{fmt::Write, fs, io};
+
+const LOGIN_KEYS_PATH: &str = "config/login_keys.txt";
+
+// The key sent in c2s::Hello, made up the first time a username joins a server. Servers
+// tie the username to it for good, so losing the file means asking the server's owner to
+// free the username. Each server gets its own, so that none of them learns what would let
+// it join elsewhere as someone else. One `<key> <server> <username>` per line.
+pub fn key_for(username: &str, server: &str) -> [u8; 16] {
+    let text = match fs::read_to_string(LOGIN_KEYS_PATH) {
+        Ok(text) => text,
+        Err(e) => {
+            if e.kind() != io::ErrorKind::NotFound {
+                eprintln!("Failed to read {LOGIN_KEYS_PATH}: {e}");
+            }
+            String::new()
+        }
+    };
+
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.splitn(3, ' ');
+        let (Some(key), Some(line_server), Some(line_username)) = (parts.next(), parts.next(), parts.next()) else {
+            eprintln!("{LOGIN_KEYS_PATH}: ignoring '{line}'");
+            continue;
+        };
+        if line_server != server || line_username != username {
+            continue;
+        }
+        match parse_key(key) {
+            Some(key) => return key,
+            None => eprintln!("{LOGIN_KEYS_PATH}: ignoring '{line}'"),
+        }
+    }
+
+    let key = rand::random::<[u8; 16]>();
+    let mut out = match text.is_empty() {
+        true => String::from("# Keys for joining servers, see client/src/login_keys.rs. Don't share this file.\n"),
+        false => text.trim_end().to_owned() + "\n",
+    };
+    let _ = writeln!(out, "{} {server} {username}", key.iter().map(|byte| format!("{byte:02x}")).collect::<String>());
+    if let Err(e) = fs::create_dir_all("config").and_then(|_| fs::write(LOGIN_KEYS_PATH, out)) {
+        eprintln!("Failed to save {LOGIN_KEYS_PATH}: {e}");
+    }
+    key
+}
+
+fn parse_key(hex: &str) -> Option<[u8; 16]> {
+    let mut key = [0; 16];
+    if hex.len() != 2 * key.len() || !hex.is_ascii() {
+        return None;
+    }
+    for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+    Some(key)
+}
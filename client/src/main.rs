@@ -1,19 +1,32 @@
 #![feature(let_else)]
 
+pub mod analytics;
+pub mod args;
 pub mod assets;
+pub mod bench;
 pub mod chat;
 pub mod components;
+pub mod cursor;
 pub mod entities;
 pub mod game;
 pub mod input;
+pub mod jobs;
+pub mod localization;
+pub mod login_keys;
 pub mod networking;
+pub mod platform;
 pub mod player;
+pub mod profiles;
 pub mod renderer;
 pub mod resources;
+pub mod settings;
 pub mod states;
 pub mod text_box;
+pub mod theme;
+pub mod toasts;
 pub mod world;
 
+use args::Args;
 use game::Game;
 //use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
 use winit::event_loop::EventLoop;
@@ -26,7 +39,19 @@ pub fn main() {
             .with(tracing_tracy::TracyLayer::new()),
     ).expect("set up the subscriber"); */
 
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) if args.help => {
+            println!("{}", args::USAGE);
+            return;
+        }
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e:#}\n\n{}", args::USAGE);
+            std::process::exit(2);
+        }
+    };
+
     let event_loop = EventLoop::new();
-    let mut game = Game::init(&event_loop).unwrap();
+    let mut game = Game::init(&event_loop, args).unwrap();
     event_loop.run(move |event, _, flow| game.on_event(event, flow));
 }
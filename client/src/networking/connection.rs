@@ -1,11 +1,73 @@
+use std::{
+    fmt::Write,
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
 use quinn::{RecvStream, SendStream};
 
-use shared::bits_and_bytes::{ByteWriter, ByteReader};
+use shared::{bits_and_bytes::ByteReader, protocol::{Features, MessageError, PROTOCOL_VERSION}};
 use tokio::sync::mpsc::UnboundedReceiver;
 
 use tokio::sync::mpsc::Sender;
 
-use crate::networking::S2C;
+use crate::networking::{packet_log::{PacketEntry, PacketLog}, S2C};
+
+// Decompressed, see shared::protocol::batching
+const MAX_RECEIVED_BATCH_LEN: usize = 1 << 20;
+
+const PROTOCOL_ERROR_DIR: &str = "protocol_errors";
+
+// Something the server sent that couldn't be decoded. The stream drivers stop with this, which
+// ends the connection with DisconnectReason::ProtocolError rather than taking the game down.
+#[derive(Debug)]
+pub struct ProtocolError {
+    pub stream: &'static str,
+    pub error: MessageError,
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "protocol error on the {} stream: {}", self.stream, self.error)
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+// Also writes what was received to PROTOCOL_ERROR_DIR, for whoever gets to debug the server:
+// `frame` as read from the stream, and `message` if it got as far as decompressing one
+pub fn protocol_error(stream: &'static str, error: MessageError, features: Features, frame: &[u8], message: Option<&[u8]>) -> anyhow::Error {
+    let error = ProtocolError { stream, error };
+    match write_dump(&error, features, frame, message) {
+        Ok(path) => eprintln!("{error}, received data written to {}", path.display()),
+        Err(e) => eprintln!("{error}, and writing the received data failed: {e}"),
+    }
+    error.into()
+}
+
+fn write_dump(error: &ProtocolError, features: Features, frame: &[u8], message: Option<&[u8]>) -> anyhow::Result<PathBuf> {
+    let hex = |bytes: &[u8]| {
+        bytes.chunks(32).fold(String::new(), |mut out, line| {
+            for byte in line {
+                let _ = write!(out, "{byte:02x} ");
+            }
+            out.push('\n');
+            out
+        })
+    };
+    let mut text = format!("{error}\nProtocol version {PROTOCOL_VERSION}, features {:#010b}\n", features.0);
+    let _ = write!(text, "\nFrame as received, {} bytes:\n{}", frame.len(), hex(frame));
+    if let Some(message) = message {
+        let _ = write!(text, "\nMessage, {} bytes:\n{}", message.len(), hex(message));
+    }
+
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis());
+    let path = PathBuf::from(format!("{PROTOCOL_ERROR_DIR}/{millis}-{}.txt", error.stream));
+    std::fs::create_dir_all(PROTOCOL_ERROR_DIR)?;
+    std::fs::write(&path, text)?;
+    Ok(path)
+}
 
 pub async fn receive_bytes<'a>(stream: &mut RecvStream, buf: &'a mut Vec<u8>) -> anyhow::Result<ByteReader<'a>> {
     let mut header = [0u8; 2];
@@ -15,6 +77,9 @@ pub async fn receive_bytes<'a>(stream: &mut RecvStream, buf: &'a mut Vec<u8>) ->
     if length > 127 {
         length = length - 128 + ((header[1] as usize) << 7);
     }
+    if length == 0 {
+        anyhow::bail!("Received zero-length message from server");
+    }
     
     buf.resize(length, 0);
     let slice = if length > 127 {
@@ -30,25 +95,48 @@ pub async fn receive_bytes<'a>(stream: &mut RecvStream, buf: &'a mut Vec<u8>) ->
 
 pub(super) mod chat {
     use flexstr::{SharedStr, ToSharedStr};
+    use shared::protocol::{batching::{Batcher, Unbatcher}, s2c};
     use super::*;
 
-    pub async fn recv_driver(mut incoming: RecvStream, to_main: Sender<S2C>) -> anyhow::Result<()> {
+    // The server accepts batches of up to 4096 bytes; this leaves room for the message
+    // that goes over
+    const MAX_BATCH_LEN: usize = 2048;
+
+    pub async fn recv_driver(mut incoming: RecvStream, features: Features, to_main: Sender<S2C>, packet_log: Arc<PacketLog>) -> anyhow::Result<()> {
         let mut buf = Vec::new();
+        let mut unbatcher = Unbatcher::new(features, MAX_RECEIVED_BATCH_LEN);
         loop {
-            let mut stream = receive_bytes(&mut incoming, &mut buf).await?;
-
-            let msg = stream.read_str(stream.bytes_remaining());
-            let _ = to_main.send(S2C::Chat(msg.to_shared_str())).await;
+            let bytes = if unbatcher.has_batched() {
+                unbatcher.next_in_batch()
+            } else {
+                receive_bytes(&mut incoming, &mut buf).await?;
+                unbatcher.unpack(&buf)
+            };
+            let bytes = bytes.map_err(|e| protocol_error("chat", e, features, &buf, None))?;
+
+            let (flags, msg) = s2c::read_chat(&mut ByteReader::new(bytes))
+                .map_err(|e| protocol_error("chat", e, features, &buf, Some(bytes)))?;
+            let entry = PacketEntry { received: std::time::Instant::now(), kind: "Chat", bytes: bytes.len() as u32 };
+            packet_log.record(std::iter::once(entry));
+            let _ = to_main.send(S2C::Chat(msg.to_shared_str(), flags)).await;
         }
     }
 
-    pub async fn send_driver(mut outgoing: SendStream, mut messages: UnboundedReceiver<SharedStr>) -> anyhow::Result<()> {
-        let mut buf = [0u8; 512];
-        while let Some(message) = messages.recv().await {
-            let mut writer = ByteWriter::new_for_message(&mut buf);
-            writer.write(message.as_bytes());
-            writer.write_message_len();
-            outgoing.write_all(writer.bytes()).await?;
+    pub async fn send_driver(mut outgoing: SendStream, features: Features, mut messages: UnboundedReceiver<SharedStr>) -> anyhow::Result<()> {
+        let mut batcher = Batcher::new(features);
+        while let Some(mut message) = messages.recv().await {
+            // Along with whatever else is already queued
+            loop {
+                batcher.push(message.as_bytes());
+                if batcher.len() >= MAX_BATCH_LEN {
+                    break;
+                }
+                match messages.try_recv() {
+                    Ok(next) => message = next,
+                    Err(_) => break,
+                }
+            }
+            outgoing.write_all(batcher.finish()).await?;
         }
         Ok(())
     }
@@ -77,96 +165,89 @@ pub(super) mod entity_state {
         x NumEntries (Sorted ascending by entity id)
     */
 
-    use glam::{vec3, vec2};
-    use shared::{
-        protocol::{decode_angle_rad, decode_velocity, NetworkId},
-    };
+    use std::time::Instant;
 
-    use crate::networking::EntityStateMsg;
+    use shared::protocol::{batching::Unbatcher, s2c::{read_entity_state, read_entity_state_sized, EntityStateMsg}};
 
     use super::*;
 
     pub async fn recv_driver(
         mut incoming: RecvStream,
+        features: Features,
         to_main: Sender<S2C>,
+        packet_log: Arc<PacketLog>,
     ) -> anyhow::Result<()> {
         let mut recv_buf = Vec::new();
         let mut send_buf = Vec::new();
+        let mut sizes = Vec::new();
+        let mut unbatcher = Unbatcher::new(features, MAX_RECEIVED_BATCH_LEN);
 
         let mut prev_tag = u16::MAX; // Server has the same "uninitialized" tag
+        let mut received = Instant::now();
         loop {
             send_buf.clear();
 
-            let mut stream = receive_bytes(&mut incoming, &mut recv_buf).await?;
-            //println("Got {} bytes", stream.bytes_remaining());
+            let bytes = if unbatcher.has_batched() {
+                unbatcher.next_in_batch()
+            } else {
+                receive_bytes(&mut incoming, &mut recv_buf).await?;
+                // Timed here, as the main thread only looks once per frame
+                received = Instant::now();
+                unbatcher.unpack(&recv_buf)
+            };
+            let bytes = bytes.map_err(|e| protocol_error("entity_state", e, features, &recv_buf, None))?;
+            //println("Got {} bytes", bytes.len());
             
-            let tag = stream.read_u16();
-            if tag != prev_tag {
-                //println("> Tag: {tag}, prev tag: {prev_tag}");
-                // New info
-                send_buf.push(EntityStateMsg::InputValidated { 
-                    tag, 
-                    packets_lost: stream.read_u8(),
-                    server_pos: vec3(
-                        stream.read_f32(),
-                        stream.read_f32(),
-                        stream.read_f32()
-                    ), 
-                    server_head_rot: vec2(
-                        stream.read_f32(),
-                        stream.read_f32()
-                    )
-                });
-                prev_tag = tag;
+            let result = if packet_log.enabled() {
+                sizes.clear();
+                let result = read_entity_state_sized(&mut ByteReader::new(bytes), &mut prev_tag, &mut send_buf, &mut sizes);
+                let entries = send_buf.iter().zip(&sizes).map(|(msg, &bytes)| PacketEntry { received, kind: msg.name(), bytes });
+                packet_log.record(entries);
+                result
             } else {
-                //println("> Same tag");
-            }
-
-            while stream.bytes_remaining() > 0 {
-                let start = stream.read_varint15();
-                match start & 0b11 {
-                    0b00 => {
-                        //println("> EntityAdded @ {}", start >> 2);
-                        send_buf.push(EntityStateMsg::EntityAdded{
-                            id: NetworkId::from_raw(start >> 2),
-                            position: vec3(stream.read_f32(), stream.read_f32(), stream.read_f32()),
-                            head_rotation: vec2(stream.read_f32(), stream.read_f32()),
-                        });
-                    }
-                    0b10 => {
-                        //println("> EntityRemoved @ {}", start >> 2);
-                        send_buf.push(EntityStateMsg::EntityRemoved {
-                            id: NetworkId::from_raw(start >> 2),
-                        });
-                    }
-                    _ => {
-                        send_buf.push(EntityStateMsg::EntityMoved { 
-                            id: NetworkId::from_raw(start >> 1), 
-                            delta_pos: vec3(
-                                decode_velocity(stream.read_u16() as u32),
-                                decode_velocity(stream.read_u16() as u32),
-                                decode_velocity(stream.read_u16() as u32),
-                            ), 
-                            delta_head_rotation: vec2(
-                                decode_angle_rad(stream.read_u16()),
-                                decode_angle_rad(stream.read_u16()),
-                            )
-                        });
-                    }
+                read_entity_state(&mut ByteReader::new(bytes), &mut prev_tag, &mut send_buf)
+            };
+            result.map_err(|e| protocol_error("entity_state", e, features, &recv_buf, Some(bytes)))?;
+
+            let mut server_tick = None;
+            send_buf.retain(|msg| match *msg {
+                EntityStateMsg::ServerTick { tick } => {
+                    server_tick = Some(tick);
+                    false
                 }
+                _ => true,
+            });
+            if let Some(tick) = server_tick {
+                let _ = to_main.send(S2C::ServerTick { tick, received }).await;
+            }
+            // The server sends messages with nothing but the tick in them, only for the tick
+            if !send_buf.is_empty() {
+                let _ = to_main.send(S2C::EntityState(send_buf.as_slice().into())).await;
             }
+        }
+    }
+}
+
+pub(super) mod authority {
+    use shared::{bits_and_bytes::ByteWriter, protocol::c2s::AuthorityMsg};
 
-            let _ = to_main.send(S2C::EntityState(send_buf.as_slice().into())).await;
+    use super::*;
+
+    pub async fn send_driver(mut outgoing: SendStream, mut messages: UnboundedReceiver<AuthorityMsg>) -> anyhow::Result<()> {
+        let mut buf = [0u8; AuthorityMsg::MAX_LEN + 2];
+        while let Some(message) = messages.recv().await {
+            let mut writer = ByteWriter::new_for_message(&mut buf);
+            message.write(&mut writer);
+            writer.write_message_len();
+            outgoing.write_all(writer.bytes()).await?;
         }
+        Ok(())
     }
 }
 
 pub(super) mod player_state {
     use bytes::Bytes;
-    use glam::{Vec3, Vec2};
-    use shared::{bits_and_bytes::BitWriter, protocol::{encode_velocity, encode_angle_rad, wrap_angle}};
-
-    use crate::states::game::input_recorder::InputSnapshot;
+    use shared::{bits_and_bytes::BitWriter, prediction::InputSnapshot, protocol::c2s::write_player_state};
 
     use super::*;
 
@@ -181,7 +262,12 @@ pub(super) mod player_state {
         let mut dropped = 0;
         let mut total = 0; */
         while let Some(message) = messages.recv().await {
-            let _ = stats_in.send(S2C::Statistics{ ping: outgoing.rtt().as_millis() as u32 }).await;
+            let stats = outgoing.stats();
+            let _ = stats_in.send(S2C::Statistics{
+                ping: outgoing.rtt().as_millis() as u32,
+                bytes_sent: stats.udp_tx.bytes,
+                bytes_received: stats.udp_rx.bytes,
+            }).await;
 
             /* total += 1;
             if thread_rng().next_u32() % drop_chance == 0 {
@@ -200,53 +286,10 @@ pub(super) mod player_state {
 
             //println!("Dropped {dropped}/{total} ({:.2}%)", dropped as f32 / total as f32 * 100.0);
 
-            let latest = message.last().unwrap();
-            
+            let (latest, older) = message.split_last().unwrap();
             let mut writer = BitWriter::new(&mut buf);
-            writer.uint(latest.tag as u32, 16);
-
-            if writer.bool(latest.delta_position != Vec3::ZERO) {
-                writer.uint(encode_velocity(latest.delta_position.x), 16);
-                writer.uint(encode_velocity(latest.delta_position.y), 16);
-                writer.uint(encode_velocity(latest.delta_position.z), 16);
-                //println("Writing velocity: {}", latest.delta_position);
-            }
-            if writer.bool(latest.delta_rotation != Vec2::ZERO) {
-                writer.uint(encode_angle_rad(wrap_angle(latest.delta_rotation.x)) as u32, 16);
-                writer.uint(encode_angle_rad(wrap_angle(latest.delta_rotation.y)) as u32, 16);
-                //println("Writing rotation: {}", latest.delta_rotation);
-            }
-
-            // NOTE reverse order. Latest snapshot is first (above). This is so that 
-            // if no previous snapshots are missing, then there is no need to parse all of the
-            // snapshots just to get to the needed (latest) snapshot.
-
-            for snapshot in (&message[..message.len()-1]).iter().rev().take(6) {
-                writer.bool(true); // has next
-
-                let &InputSnapshot {
-                    tag: _,
-                    delta_position,
-                    delta_rotation,
-                    ..
-                } = snapshot;
-
-                ////println("Delta for tick {}: {:.8}, {:.8}, {:.8}, pos {:.8}, {:.8}, {:.8}", self.tick, velocity.x, velocity.y, velocity.z, origin.x, origin.y, origin.z);
-                if writer.bool(delta_position.length_squared() != 0.0) {
-                    ////println("Moved {} units in nw frame", velocity.length());
-                    writer.uint(encode_velocity(delta_position.x), 16);
-                    writer.uint(encode_velocity(delta_position.y), 16);
-                    writer.uint(encode_velocity(delta_position.z), 16);
-                }
-                if writer.bool(delta_rotation.length_squared() != 0.0) {
-                    ////println("Rot delta for tick {}: {:.8}, {:.8}, rot: {:.8}, {:.8}", self.tick, yaw.to_degrees(), pitch.to_degrees(), origin.x.to_degrees(), origin.y.to_degrees());
-                    writer.uint(encode_angle_rad(wrap_angle(delta_rotation.x)) as u32, 16);
-                    writer.uint(encode_angle_rad(wrap_angle(delta_rotation.y)) as u32, 16);
-                }
-                
-            }
-            writer.bool(false); // doesn't have next
-            writer.flush_partials();
+            // The game decides how many older inputs to resend; 16 always fits in `buf`
+            write_player_state(&mut writer, &latest.to_input(), older.iter().rev().take(16).map(InputSnapshot::to_input));
             let len = writer.compute_bytes_written();
 
             //println!("Sending {} bytes @ tag {}", len, latest.tag);
@@ -1,61 +1,38 @@
-use std::{net::SocketAddr, thread::JoinHandle, time::Instant};
+use std::{net::SocketAddr, sync::Arc, thread::JoinHandle, time::Instant};
 
 use flexstr::SharedStr;
-use glam::{Vec3, Vec2};
 use hecs::Entity;
-use shared::protocol::NetworkId;
 use tokio::sync::{
-    mpsc::{unbounded_channel, UnboundedSender},
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
     oneshot,
 };
 
-use crate::states::game::input_recorder::InputSnapshot;
+use shared::{prediction::InputSnapshot, protocol::c2s::AuthorityMsg};
 
-use self::network_thread::NetSideChannels;
+use self::{network_thread::NetSideChannels, packet_log::PacketLog};
 
 pub mod connection;
 mod network_thread;
+pub mod packet_log;
 
-pub struct LoginResponse {
-    pub nid: NetworkId,
-    pub position: Vec3,
-    pub head_rotation: Vec2,
-    pub world_seed: u64,
-}
-
-
-#[derive(Clone, Copy)]
-pub enum EntityStateMsg {
-    EntityAdded {
-        id: NetworkId,
-        position: Vec3,
-        head_rotation: Vec2
-    },
-    EntityRemoved {
-        id: NetworkId,
-    },
-    EntityMoved {
-        id: NetworkId,
-        delta_pos: Vec3,
-        delta_head_rotation: Vec2,
-    },
-    InputValidated {
-        tag: u16,
-        packets_lost: u8,
-        server_pos: Vec3,
-        server_head_rot: Vec2,
-    }
-}
+pub use shared::protocol::s2c::{LoginResponse, EntityStateMsg, ChatFlags};
 
 pub enum S2C {
-    Chat(SharedStr),
+    Chat(SharedStr, ChatFlags),
     EntityState(Box<[EntityStateMsg]>),
-    Statistics{ ping: u32, }
+    Statistics{ ping: u32, bytes_sent: u64, bytes_received: u64 },
+    // Taken out of EntityState, with when its message arrived. See ClockSync.
+    ServerTick { tick: u32, received: Instant },
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub enum DisconnectReason {
-    Unknown
+    Unknown,
+    // Closed on purpose, e.g. for a restart
+    ServerClosed,
+    Kicked(Box<str>),
+    // Sent something the client couldn't decode, see connection::ProtocolError
+    ProtocolError,
 }
 
 pub struct Channels {
@@ -63,6 +40,9 @@ pub struct Channels {
 
     pub chat: UnboundedSender<SharedStr>,
     pub player_state: UnboundedSender<Box<[InputSnapshot]>>,
+    // Dropped if the server doesn't support Features::AUTHORITY
+    pub authority: UnboundedSender<AuthorityMsg>,
+    pub packet_log: Arc<PacketLog>,
 
     pub on_disconnect: oneshot::Receiver<DisconnectReason>,
     pub stop_network_thread: Option<oneshot::Sender<()>>,
@@ -76,21 +56,28 @@ struct NetThreadHandle {
 pub struct Connecting {
     handle: Option<NetThreadHandle>,
     on_connect: oneshot::Receiver<Result<LoginResponse, Box<str>>>,
+    queue_position: UnboundedReceiver<u16>,
+    last_queue_position: Option<u16>,
 }
 
 impl Connecting {
-    pub fn init_connection(address: SocketAddr, username: SharedStr) -> Self {
+    pub fn init_connection(address: SocketAddr, username: SharedStr, key: [u8; 16]) -> Self {
         let (stop_command_send, stop_command_recv) = oneshot::channel();
         let (on_connect_send, on_connect_recv) = oneshot::channel();
+        let (queue_position_send, queue_position_recv) = unbounded_channel();
         let (on_lost_connection_send, on_lost_connection_recv) = oneshot::channel();
         let (incoming_send, incoming_recv) = tokio::sync::mpsc::channel(64);
         let (chat_send, chat_recv) = unbounded_channel();
         let (player_state_send, player_state_recv) = unbounded_channel();
+        let (authority_send, authority_recv) = unbounded_channel();
+        let packet_log = Arc::new(PacketLog::new());
 
         let channels = NetSideChannels {
             incoming: incoming_send,
             chat_recv: chat_recv,
             player_state: player_state_recv,
+            authority: authority_recv,
+            packet_log: packet_log.clone(),
             on_lost_connection: on_lost_connection_send,
             stop_command: stop_command_recv
         };
@@ -98,22 +85,34 @@ impl Connecting {
         Self {
             handle: Some(NetThreadHandle {
                 net_thread_handle: Some(std::thread::spawn(move || {
-                    network_thread::start(address, username, channels, on_connect_send)
+                    network_thread::start(address, username, key, channels, on_connect_send, queue_position_send)
                 })),
                 channels: Channels {
                     incoming: incoming_recv,
                     
                     chat: chat_send,
                     player_state: player_state_send,
+                    authority: authority_send,
+                    packet_log,
                     
                     on_disconnect: on_lost_connection_recv,
                     stop_network_thread: Some(stop_command_send),
                 },
             }),
             on_connect: on_connect_recv,
+            queue_position: queue_position_recv,
+            last_queue_position: None,
         }
     }
 
+    // Where we are in line if the server is full, 1 being next
+    pub fn queue_position(&mut self) -> Option<u16> {
+        while let Ok(position) = self.queue_position.try_recv() {
+            self.last_queue_position = Some(position);
+        }
+        self.last_queue_position
+    }
+
     // Returns Ok(None) until the connection has been established, after which
     // this will always return None.
     pub fn try_tick_connection(&mut self) -> Result<Option<(LoginResponse, Connection)>, Box<str>> {
@@ -125,6 +124,7 @@ impl Connecting {
                     // unwrap(): safe. on_connect is oneshot, this can never be reached twice.
                     handle: self.handle.take().unwrap(),
                     closed: false,
+                    disconnect_reason: DisconnectReason::Unknown,
                 },
             ))),
             Ok(Err(msg)) => Err(msg),
@@ -138,6 +138,7 @@ pub struct Connection {
     pub network_id_to_entity: Vec<Entity>,
     handle: NetThreadHandle,
     closed: bool,
+    disconnect_reason: DisconnectReason,
 }
 
 impl Connection {
@@ -145,6 +146,11 @@ impl Connection {
         self.closed
     }
 
+    // Why the server went away, once `closed()`
+    pub fn disconnect_reason(&self) -> DisconnectReason {
+        self.disconnect_reason.clone()
+    }
+
     pub fn send_disconnect(&mut self) {
         if self.closed {
             return; // guard mainly against Drop
@@ -182,7 +188,11 @@ impl Connection {
 
     pub fn tick(&mut self) {
         match self.handle.channels.on_disconnect.try_recv() {
-            Ok(_) | Err(oneshot::error::TryRecvError::Closed) => self.closed = true,
+            Ok(reason) => {
+                self.closed = true;
+                self.disconnect_reason = reason;
+            }
+            Err(oneshot::error::TryRecvError::Closed) => self.closed = true,
             Err(oneshot::error::TryRecvError::Empty) => {}
         }
     }
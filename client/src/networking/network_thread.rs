@@ -1,29 +1,32 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc};
 
 use flexstr::SharedStr;
-use glam::{Vec2, Vec3};
-use quinn::{Endpoint, NewConnection, VarInt};
+use quinn::{ApplicationClose, ConnectionError, Endpoint, NewConnection, ReadError, ReadExactError, VarInt, WriteError};
 use shared::{
-    bits_and_bytes::ByteWriter, protocol::NetworkId
+    bits_and_bytes::ByteWriter, prediction::InputSnapshot, protocol::{c2s::{AuthorityMsg, Hello}, s2c::LoginStatus, Features, CLOSE_KICKED, CLOSE_LOGIN_DENIED, CLOSE_SERVER_CLOSED, PROTOCOL_MAGIC, PROTOCOL_VERSION}
 };
 use tokio::{
     sync::{
-        mpsc::{UnboundedReceiver, Sender},
+        mpsc::{UnboundedReceiver, UnboundedSender, Sender},
         oneshot,
     },
-    task,
+    task::{self, JoinError},
 };
 
-use crate::{networking::connection::{self, receive_bytes}, states::game::input_recorder::InputSnapshot};
+use crate::networking::connection::{self, protocol_error, receive_bytes, ProtocolError};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
-use super::{DisconnectReason, S2C, LoginResponse};
+use super::{packet_log::PacketLog, DisconnectReason, S2C, LoginResponse};
+
+const CLIENT_FEATURES: Features = Features::COMPRESSION.union(Features::CLOCK_SYNC).union(Features::AUTHORITY).union(Features::ENTITY_CHECKSUM).union(Features::BLOCK_BREAKING).union(Features::BLOCK_PLACING);
 
 pub struct NetSideChannels {
     pub incoming: Sender<S2C>,
     pub chat_recv: UnboundedReceiver<SharedStr>,
     pub player_state: UnboundedReceiver<Box<[InputSnapshot]>>,
+    pub authority: UnboundedReceiver<AuthorityMsg>,
+    pub packet_log: Arc<PacketLog>,
     pub on_lost_connection: oneshot::Sender<DisconnectReason>,
 
     pub stop_command: oneshot::Receiver<()>,
@@ -32,10 +35,12 @@ pub struct NetSideChannels {
 pub fn start(
     server_address: SocketAddr,
     username: SharedStr,
+    key: [u8; 16],
     channels: NetSideChannels,
     on_connect: oneshot::Sender<Result<LoginResponse, Box<str>>>,
+    queue_position: UnboundedSender<u16>,
 ) {
-    if let Err(e) = start_inner(server_address, username, channels, on_connect) {
+    if let Err(e) = start_inner(server_address, username, key, channels, on_connect, queue_position) {
         println!("Error in network thread: {}", e);
     }
 }
@@ -44,24 +49,51 @@ pub fn start(
 async fn start_inner(
     server_address: SocketAddr,
     username: SharedStr,
+    key: [u8; 16],
     channels: NetSideChannels,
     on_connect: oneshot::Sender<Result<LoginResponse, Box<str>>>,
+    queue_position: UnboundedSender<u16>,
 ) -> Result<()> {
-    let (endpoint, mut new_conn, response) = match try_connect(server_address, &username).await {
+    let (endpoint, mut new_conn, response) = match try_connect(server_address, &username, key, queue_position).await {
         Ok(tuple) => tuple,
         Err(e) => {
             println!("Connection failed: {e}");
-            let _ = on_connect.send(Err(format!("Connection failed: {e}").into_boxed_str()));
+            let message = match closed_with(&e) {
+                Some(close) if close.error_code == VarInt::from_u32(CLOSE_LOGIN_DENIED) => {
+                    format!("Login denied: {}", String::from_utf8_lossy(&close.reason))
+                }
+                _ => format!("Connection failed: {e}"),
+            };
+            let _ = on_connect.send(Err(message.into_boxed_str()));
             return Ok(());
         }
     };
 
     dbg![new_conn.connection.max_datagram_size()];
+    let features = CLIENT_FEATURES.common(response.features);
 
     let (mut chat_send, chat_recv) = new_conn.connection.open_bi().await?;
     chat_send.write(&[0]).await?; // open up the channel on the server side as well
-    let chat_fut_1 = task::spawn(connection::chat::recv_driver(chat_recv, channels.incoming.clone()));
-    let chat_fut_2 = task::spawn(connection::chat::send_driver(chat_send, channels.chat_recv));
+    let chat_fut_1 = task::spawn(connection::chat::recv_driver(chat_recv, features, channels.incoming.clone(), channels.packet_log.clone()));
+    let chat_fut_2 = task::spawn(connection::chat::send_driver(chat_send, features, channels.chat_recv));
+
+    // The server expects it right after the chat stream. Without the feature, the task
+    // never returns.
+    let authority_send = match features.contains(Features::AUTHORITY) {
+        true => {
+            let (mut authority_send, _) = new_conn.connection.open_bi().await?;
+            authority_send.write(&[0]).await?;
+            Some(authority_send)
+        }
+        false => None,
+    };
+    let authority_recv = channels.authority;
+    let authority_fut = task::spawn(async move {
+        match authority_send {
+            Some(outgoing) => connection::authority::send_driver(outgoing, authority_recv).await,
+            None => std::future::pending().await,
+        }
+    });
 
     let mut player_state_send = new_conn.connection.open_uni().await?;
     player_state_send.write(&[0]).await?;
@@ -72,27 +104,35 @@ async fn start_inner(
         }
     });
 
-    let mut entity_state_recv = new_conn.uni_streams.next().await.unwrap()?;
+    let mut entity_state_recv = new_conn.uni_streams.next().await.context("connection closed before the entity state stream opened")??;
     entity_state_recv.read_exact(&mut [0u8]).await?; // Read the byte used to open the channel
     let entity_fut = task::spawn(connection::entity_state::recv_driver(
         entity_state_recv,
+        features,
         channels.incoming.clone(),
+        channels.packet_log,
     ));
 
     let disconnect = channels.stop_command;
+    let on_lost_connection = channels.on_lost_connection;
 
     if on_connect.send(Ok(response)).is_err() {
         println!("Main thread dropped on_connect channel");
         return Ok(());
     }
 
-    tokio::select!(
-        _ = chat_fut_1 => {println!("chat::recv_driver returned");},
-        _ = chat_fut_2 => {println!("chat::send_driver returned");}
-        _ = entity_fut => {println!("entity_state::recv_driver returned");}
-        _ = player_fut => {println!("player_state::send_driver returned");}
-        _ = disconnect => {}
+    // None if the main thread asked to disconnect
+    let lost_connection = tokio::select!(
+        res = chat_fut_1 => {println!("chat::recv_driver returned"); Some(disconnect_reason(res))},
+        res = chat_fut_2 => {println!("chat::send_driver returned"); Some(disconnect_reason(res))}
+        res = entity_fut => {println!("entity_state::recv_driver returned"); Some(disconnect_reason(res))}
+        res = authority_fut => {println!("authority::send_driver returned"); Some(disconnect_reason(res))}
+        _ = player_fut => {println!("player_state::send_driver returned"); Some(DisconnectReason::Unknown)}
+        _ = disconnect => None
     );
+    if let Some(reason) = lost_connection {
+        let _ = on_lost_connection.send(reason);
+    }
 
     println!("Stopping network thread");
     endpoint.close(VarInt::from_u32(1), &[]);
@@ -101,9 +141,42 @@ async fn start_inner(
     Ok(())
 }
 
+// The stream drivers fail with the error the connection was closed with
+fn disconnect_reason(result: Result<Result<()>, JoinError>) -> DisconnectReason {
+    let Ok(Err(e)) = result else {
+        return DisconnectReason::Unknown;
+    };
+    if e.downcast_ref::<ProtocolError>().is_some() {
+        return DisconnectReason::ProtocolError;
+    }
+    match closed_with(&e) {
+        Some(close) if close.error_code == VarInt::from_u32(CLOSE_SERVER_CLOSED) => DisconnectReason::ServerClosed,
+        Some(close) if close.error_code == VarInt::from_u32(CLOSE_KICKED) => {
+            DisconnectReason::Kicked(String::from_utf8_lossy(&close.reason).into())
+        }
+        _ => DisconnectReason::Unknown,
+    }
+}
+
+// How the server closed the connection, if that is what the error is about
+fn closed_with(e: &anyhow::Error) -> Option<&ApplicationClose> {
+    let error = match (e.downcast_ref(), e.downcast_ref(), e.downcast_ref()) {
+        (Some(ReadExactError::ReadError(ReadError::ConnectionLost(error))), _, _) => error,
+        (_, Some(ReadError::ConnectionLost(error)), _) => error,
+        (_, _, Some(WriteError::ConnectionLost(error))) => error,
+        _ => return None,
+    };
+    match error {
+        ConnectionError::ApplicationClosed(close) => Some(close),
+        _ => None,
+    }
+}
+
 async fn try_connect(
     server_address: SocketAddr,
     username: &SharedStr,
+    key: [u8; 16],
+    queue_position: UnboundedSender<u16>,
 ) -> Result<(Endpoint, NewConnection, LoginResponse)> {
     let endpoint = setup::make_client_endpoint().unwrap();
 
@@ -112,33 +185,30 @@ async fn try_connect(
 
     let mut buf = [0u8; 256];
     let mut writer = ByteWriter::new_for_message(&mut buf);
-    writer.write_u16(shared::protocol::PROTOCOL_MAGIC);
-    writer.write_u16(shared::protocol::PROTOCOL_VERSION);
-    writer.write_u8(username.len() as u8);
-    writer.write(username.as_str().as_bytes());
+    Hello {
+        magic: PROTOCOL_MAGIC,
+        version: PROTOCOL_VERSION,
+        username: username.as_str(),
+        skin: 0, // No skin selection yet
+        features: CLIENT_FEATURES,
+        key,
+    }.write(&mut writer);
     writer.write_message_len();
 
     let (mut hello_send, mut hello_recv) = conn.connection.open_bi().await?;
     hello_send.write_all(writer.bytes()).await?;
 
+    // Told our place in line for as long as the server is full
     let mut recv_buf = Vec::new();
-    let mut reader = receive_bytes(&mut hello_recv, &mut recv_buf).await?;
-    if reader.bytes_remaining() < 30 {
-        anyhow::bail!("Invalid login response from server, got only {} bytes", reader.bytes_remaining());
-    }
-
-    let response = LoginResponse {
-        nid: NetworkId::from_raw(reader.read_u16()),
-        position: Vec3 {
-            x: reader.read_f32(),
-            y: reader.read_f32(),
-            z: reader.read_f32(),
-        },
-        head_rotation: Vec2 {
-            x: reader.read_f32(), // Yaw
-            y: reader.read_f32(), // Pitch
-        },
-        world_seed: reader.read_u64(), // World seed
+    let response = loop {
+        let mut reader = receive_bytes(&mut hello_recv, &mut recv_buf).await?;
+        match LoginStatus::read(&mut reader) {
+            Ok(LoginStatus::Queued { position }) => {
+                let _ = queue_position.send(position);
+            }
+            Ok(LoginStatus::Accepted(response)) => break response,
+            Err(e) => return Err(protocol_error("hello", e, CLIENT_FEATURES, &recv_buf, None)),
+        }
     };
 
     Ok((endpoint, conn, response))
@@ -0,0 +1,57 @@
+use std::{
+    collections::VecDeque,
+    sync::{atomic::{AtomicBool, Ordering}, Mutex},
+    time::Instant,
+};
+
+// Received s2c messages, for the packet inspector (see states::game::packet_inspector).
+// Written by the network thread only while enabled, so that it costs nothing otherwise.
+pub struct PacketLog {
+    enabled: AtomicBool,
+    entries: Mutex<VecDeque<PacketEntry>>,
+}
+
+#[derive(Clone, Copy)]
+pub struct PacketEntry {
+    pub received: Instant,
+    pub kind: &'static str,
+    pub bytes: u32, // encoded, before compression
+}
+
+impl PacketLog {
+    // Whatever the main thread hasn't taken by then is dropped, oldest first
+    const MAX_ENTRIES: usize = 8192;
+
+    pub fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.entries.lock().unwrap().clear();
+        }
+    }
+
+    pub fn record(&self, entries: impl Iterator<Item = PacketEntry>) {
+        if !self.enabled() {
+            return;
+        }
+        let mut log = self.entries.lock().unwrap();
+        log.extend(entries);
+        let excess = log.len().saturating_sub(Self::MAX_ENTRIES);
+        log.drain(..excess);
+    }
+
+    // Oldest first
+    pub fn take(&self, out: &mut Vec<PacketEntry>) {
+        out.extend(self.entries.lock().unwrap().drain(..));
+    }
+}
@@ -0,0 +1,50 @@
+use winit::window::{Icon, UserAttentionType, Window};
+
+use crate::assets;
+
+// Window title outside of a server
+pub const TITLE: &str = "Game";
+
+// The icon is the stone texture of the built-in textures, scaled up without blurring
+const ICON_LAYER: usize = 13;
+const ICON_SCALE: usize = 4;
+
+pub fn set_icon(window: &Window) {
+    match load_icon() {
+        Ok(icon) => window.set_window_icon(Some(icon)),
+        Err(e) => eprintln!("Failed to set the window icon: {e}"),
+    }
+}
+
+fn load_icon() -> anyhow::Result<Icon> {
+    const SIZE: usize = 16;
+    let textures = lz4::block::decompress(assets::textures::TEXTURES, None)?;
+    let Some(layer) = textures.chunks_exact(SIZE * SIZE * 4).nth(ICON_LAYER) else {
+        anyhow::bail!("no texture {ICON_LAYER} for the icon");
+    };
+
+    let size = SIZE * ICON_SCALE;
+    let mut rgba = Vec::with_capacity(size * size * 4);
+    for y in 0..size {
+        for x in 0..size {
+            let idx = ((y / ICON_SCALE) * SIZE + x / ICON_SCALE) * 4;
+            rgba.extend_from_slice(&layer[idx..idx + 4]);
+        }
+    }
+    Ok(Icon::from_rgba(rgba, size as u32, size as u32)?)
+}
+
+// While in game, the title shows where and how well connected
+pub fn set_server_title(window: &Window, server: &str, ping_ms: u32) {
+    window.set_title(&format!("{TITLE} - {server} ({ping_ms} ms)"));
+}
+
+pub fn reset_title(window: &Window) {
+    window.set_title(TITLE);
+}
+
+// Flashes the taskbar entry or bounces the dock icon, depending on the platform; does
+// nothing where there is no such thing. Stops by itself once the window gets focus.
+pub fn request_attention(window: &Window) {
+    window.request_user_attention(Some(UserAttentionType::Informational));
+}
@@ -1,8 +1,25 @@
 use glam::Vec3;
+use shared::movement::MovementFlags;
+
+// How far the camera lowers while sneaking, in blocks
+pub const SNEAK_CAMERA_DROP: f32 = 0.3;
+// Field of view multiplier while sprinting
+pub const SPRINT_FOV_SCALE: f32 = 1.15;
+// Field of view multiplier while the zoom key is held
+pub const ZOOM_FOV_SCALE: f32 = 0.3;
+// Max time between two presses of the forward key to start sprinting
+pub const DOUBLE_TAP_SECS: f32 = 0.3;
 
 pub struct ThePlayer {
     pub pos: Vec3,
     pub vel: Vec3,
+    pub movement: MovementFlags,
+    pub last_fwd_press_secs: f32,
+    pub zooming: bool,
+
+    // Eased towards the targets of the current movement state
+    pub camera_drop: f32,
+    pub fov_scale: f32,
 }
 
 impl ThePlayer {
@@ -10,6 +27,11 @@ impl ThePlayer {
         Self {
             pos,
             vel: Vec3::ZERO,
+            movement: MovementFlags::NONE,
+            last_fwd_press_secs: f32::NEG_INFINITY,
+            zooming: false,
+            camera_drop: 0.0,
+            fov_scale: 1.0,
         }
     }
 }
@@ -0,0 +1,181 @@
+use std::{fmt::Write, fs, io};
+
+use crate::{
+    input::settings::{key_from_name, InputSettings},
+    renderer::ui_renderer::TextureRegion,
+    resources::Resources,
+    settings::Settings,
+};
+
+const PROFILES_PATH: &str = "config/profiles.txt";
+
+// Usernames to join with, picked from the connect screen, each remembering its server and
+// with settings of its own, for hopping between accounts when testing with several clients.
+// Joining with a new username adds a profile for it. The file is edited by hand to add
+// overrides, which are lines of the settings file, `key.<action> = <key>` bindings
+// (e.g. `key.jump = F`) and `mouse_sensitivity`:
+//
+//   selected = alice
+//
+//   [alice]
+//   server = localhost:29477
+//   key.fwd = Z
+//   palette = tritanopia
+//
+// The selected profile is applied at startup. Switching to another one later doesn't
+// reload the textures or the GPU, so its gpu, anisotropy and resource_pack overrides take
+// effect on the next start.
+pub struct Profiles {
+    profiles: Vec<Profile>,
+    selected: Option<usize>,
+}
+
+pub struct Profile {
+    pub username: String,
+    pub server: String,
+    overrides: Vec<(String, String)>,
+}
+
+impl Profile {
+    // On top of the saved settings; whatever another profile changed should be reset first
+    pub fn apply(&self, settings: &mut Settings, input: &mut InputSettings) {
+        for (key, value) in &self.overrides {
+            let ok = match key.strip_prefix("key.") {
+                Some(action) => key_from_name(value).map_or(false, |key| input.key_bindings.set(action, key)),
+                None if key == "mouse_sensitivity" => {
+                    value.parse().map(|sensitivity| input.mouse_sensitivity = sensitivity).is_ok()
+                }
+                None => {
+                    let ok = settings.apply(key, value);
+                    if ok {
+                        settings.overridden.push(key.clone());
+                    }
+                    ok
+                }
+            };
+            if !ok {
+                eprintln!("{PROFILES_PATH}: [{}] ignoring '{key} = {value}'", self.username);
+            }
+        }
+    }
+}
+
+impl Profiles {
+    pub fn load() -> Self {
+        let mut profiles = Self { profiles: Vec::new(), selected: None };
+        let text = match fs::read_to_string(PROFILES_PATH) {
+            Ok(text) => text,
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    eprintln!("Failed to read {PROFILES_PATH}: {e}");
+                }
+                return profiles;
+            }
+        };
+
+        let mut selected = None;
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(username) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                profiles.profiles.push(Profile {
+                    username: username.trim().to_owned(),
+                    server: String::new(),
+                    overrides: Vec::new(),
+                });
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                eprintln!("{PROFILES_PATH}: ignoring '{line}'");
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim().to_owned());
+            match (profiles.profiles.last_mut(), key) {
+                (None, "selected") => selected = Some(value),
+                (None, _) => eprintln!("{PROFILES_PATH}: ignoring '{line}' outside of a profile"),
+                (Some(profile), "server") => profile.server = value,
+                (Some(profile), _) => profile.overrides.push((key.to_owned(), value)),
+            }
+        }
+        profiles.selected = selected.and_then(|name| profiles.index_of(&name));
+        profiles
+    }
+
+    pub fn save(&self) {
+        let mut out = String::from("# Profiles for the connect screen, see client/src/profiles.rs\n");
+        if let Some(profile) = self.selected() {
+            let _ = writeln!(out, "selected = {}", profile.username);
+        }
+        for profile in &self.profiles {
+            let _ = writeln!(out, "\n[{}]", profile.username);
+            let _ = writeln!(out, "server = {}", profile.server);
+            for (key, value) in &profile.overrides {
+                let _ = writeln!(out, "{key} = {value}");
+            }
+        }
+        if let Err(e) = fs::create_dir_all("config").and_then(|_| fs::write(PROFILES_PATH, out)) {
+            eprintln!("Failed to save {PROFILES_PATH}: {e}");
+        }
+    }
+
+    pub fn list(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    pub fn selected(&self) -> Option<&Profile> {
+        self.profiles.get(self.selected?)
+    }
+
+    pub fn selected_index(&self) -> Option<usize> {
+        self.selected
+    }
+
+    // Selects a profile before Resources exist, without applying or saving it, see
+    // Game::init(). Returns false if there is no such profile.
+    pub fn preselect(&mut self, username: &str) -> bool {
+        let Some(idx) = self.index_of(username) else {
+            return false;
+        };
+        self.selected = Some(idx);
+        true
+    }
+
+    fn index_of(&self, username: &str) -> Option<usize> {
+        self.profiles.iter().position(|profile| profile.username == username)
+    }
+
+    // After joining: remembers the server of the profile, adding one for new usernames.
+    // Returns its index, see select().
+    pub fn remember(&mut self, username: &str, server: &str) -> usize {
+        let idx = self.index_of(username).unwrap_or_else(|| {
+            self.profiles.push(Profile {
+                username: username.to_owned(),
+                server: String::new(),
+                overrides: Vec::new(),
+            });
+            self.profiles.len() - 1
+        });
+        self.profiles[idx].server = server.to_owned();
+        self.save();
+        idx
+    }
+}
+
+// Switches to the profile at `idx`, replacing the previous one's overrides
+pub fn select(res: &mut Resources, idx: usize) {
+    if idx >= res.profiles.profiles.len() {
+        return;
+    }
+    res.profiles.selected = Some(idx);
+    res.profiles.save();
+
+    res.settings = Settings::load();
+    res.input.settings = InputSettings::default();
+    res.profiles.profiles[idx].apply(&mut res.settings, &mut res.input.settings);
+
+    // Otherwise only applied at startup or when changed in the settings screen
+    res.renderer.ui.text().set_effect(res.settings.accessibility.text_effect);
+    let crosshair = res.settings.graphics.crosshair_texture.map(TextureRegion::whole);
+    res.cursor.set_crosshair_texture(crosshair);
+}
@@ -0,0 +1,149 @@
+use std::ffi::c_void;
+
+use erupt::vk::{self, BufferUsageFlags};
+use glam::{Mat4, Vec3};
+use vkcore::{Buffer, Device, MemoryTag, UsageFlags, VkContext};
+
+use super::{passes::debug_line_pass::LineVertex, pipelines::Pipelines, renderer::RenderContext};
+
+// Immediate-mode world-space lines, for debug visualizations. Like the UI, everything
+// queued during a frame is drawn once and then cleared.
+pub struct DebugLines {
+    vertices: Vec<LineVertex>,
+    buffer: Buffer,
+
+    num_verts_to_draw: u32,
+}
+
+impl DebugLines {
+    pub const RED: [u8; 4] = [1, 0, 0, 1];
+    pub const GREEN: [u8; 4] = [0, 1, 0, 1];
+    pub const BLUE: [u8; 4] = [0, 0, 1, 1];
+    pub const YELLOW: [u8; 4] = [1, 1, 0, 1];
+    pub const CYAN: [u8; 4] = [0, 1, 1, 1];
+    pub const WHITE: [u8; 4] = [1, 1, 1, 1];
+    pub const BLACK: [u8; 4] = [0, 0, 0, 1];
+
+    pub fn create(vk: &mut VkContext) -> anyhow::Result<Self> {
+        let buffer = vk.allocator.allocate_buffer(
+            &vk.device,
+            &vkcore::BufferAllocation {
+                size: 16384, // 1024 vertices
+                usage: UsageFlags::UPLOAD,
+                vk_usage: BufferUsageFlags::VERTEX_BUFFER,
+                tag: MemoryTag::Debug,
+            },
+        )?;
+
+        Ok(Self {
+            vertices: Vec::with_capacity(1024),
+            buffer,
+            num_verts_to_draw: 0,
+        })
+    }
+
+    pub fn line(&mut self, from: Vec3, to: Vec3, color: [u8; 4]) {
+        self.vertices.push(LineVertex { pos: from, color });
+        self.vertices.push(LineVertex { pos: to, color });
+    }
+
+    pub fn aabb(&mut self, min: Vec3, max: Vec3, color: [u8; 4]) {
+        let corner = |i: usize| {
+            Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        };
+        // Each edge connects two corners that differ in exactly one bit
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corner(i), corner(i | bit), color);
+                }
+            }
+        }
+    }
+}
+
+impl DebugLines {
+    pub fn do_uploads(lines: &mut DebugLines, vk: &mut VkContext) -> anyhow::Result<()> {
+        if lines.vertices.is_empty() {
+            return Ok(());
+        }
+
+        let buffer = &mut lines.buffer;
+        let vertices = &lines.vertices;
+
+        let buffer_size = vertices.len() * std::mem::size_of::<LineVertex>();
+        if buffer.size < buffer_size as u64 {
+            println!(
+                "[debug_lines.rs] Buffer size is too small, reallocating! {} -> {} bytes",
+                buffer.size,
+                vertices.capacity() * std::mem::size_of::<LineVertex>()
+            );
+
+            vk.allocator.deallocate_buffer(buffer, &vk.device)?;
+            *buffer = vk.allocator.allocate_buffer(
+                &vk.device,
+                &vkcore::BufferAllocation {
+                    size: vertices.capacity() * std::mem::size_of::<LineVertex>(),
+                    usage: UsageFlags::UPLOAD,
+                    vk_usage: BufferUsageFlags::VERTEX_BUFFER,
+                    tag: MemoryTag::Debug,
+                },
+            )?;
+        }
+
+        vk.uploader
+            .upload_to_buffer(&vk.device, vertices, buffer, 0)?;
+
+        lines.num_verts_to_draw = lines.vertices.len() as _;
+        lines.vertices.clear();
+        Ok(())
+    }
+
+    // Must be called inside the terrain pass
+    pub fn render(
+        lines: &mut DebugLines,
+        device: &Device,
+        ctx: &RenderContext,
+        pipelines: &Pipelines,
+        proj_view: Mat4,
+    ) {
+        if lines.num_verts_to_draw == 0 {
+            return;
+        }
+
+        let commands = ctx.commands;
+        unsafe {
+            device.cmd_bind_pipeline(
+                commands,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipelines.debug_lines.handle,
+            );
+            let pv_ptr = &proj_view as *const Mat4 as *const c_void;
+            device.cmd_push_constants(
+                commands,
+                pipelines.debug_lines.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                std::mem::size_of::<Mat4>() as u32,
+                pv_ptr,
+            );
+
+            device.cmd_bind_vertex_buffers(commands, 0, &[lines.buffer.handle], &[0]);
+            device.cmd_draw(commands, lines.num_verts_to_draw, 1, 0, 0);
+        }
+
+        lines.num_verts_to_draw = 0;
+    }
+}
+
+impl DebugLines {
+    pub fn destroy_self(&mut self, vk: &mut VkContext) -> anyhow::Result<()> {
+        vk.allocator
+            .deallocate_buffer(&mut self.buffer, &vk.device)?;
+        Ok(())
+    }
+}
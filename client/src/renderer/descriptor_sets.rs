@@ -1,18 +1,18 @@
 use erupt::vk;
 use glam::{Mat4, Vec2};
 use vkcore::{
-    Buffer, BufferAllocation, Device, Image, ImageAllocation, Uploader, UsageFlags, VkAllocator,
-    VkContext,
+    Buffer, BufferAllocation, DescriptorAllocator, DescriptorLayoutBuilder, DescriptorWriter, Device, Image,
+    ImageAllocation, MemoryTag, Uploader, UsageFlags, VkAllocator, VkContext,
 };
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 
-use crate::assets;
+use crate::{assets, settings::RESOURCE_PACK_DIR};
 
 use super::renderer::FRAMES_IN_FLIGHT;
 
 pub struct DescriptorSets {
-    pub pool: vk::DescriptorPool,
+    pub allocator: DescriptorAllocator,
 
     pub textures: Textures,
     pub text_rendering: TextBuffers,
@@ -20,31 +20,17 @@ pub struct DescriptorSets {
 }
 
 impl DescriptorSets {
-    pub fn create(vk: &mut VkContext) -> Result<DescriptorSets> {
+    // `anisotropy`: see Textures::set_anisotropy()
+    pub fn create(vk: &mut VkContext, anisotropy: f32) -> Result<DescriptorSets> {
         println!("CREATING DESCRIPTOR SETS");
-        let pool = unsafe {
-            vk.device.create_descriptor_pool(
-                &vk::DescriptorPoolCreateInfoBuilder::new()
-                    .max_sets(10)
-                    .pool_sizes(&[
-                        vk::DescriptorPoolSizeBuilder::new()
-                            ._type(vk::DescriptorType::UNIFORM_BUFFER)
-                            .descriptor_count(10),
-                        vk::DescriptorPoolSizeBuilder::new()
-                            ._type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                            .descriptor_count(1),
-                    ]),
-                None,
-            )
-        }
-        .result()?;
+        let mut allocator = DescriptorAllocator::new(8);
 
-        let textures = Textures::create(&vk.device, pool, &mut vk.uploader, &mut vk.allocator)?;
-        let text_rendering = TextBuffers::create(&vk.device, pool)?;
-        let attachments = InputAttachments::create(&vk.device, pool, &mut vk.allocator)?;
+        let textures = Textures::create(&vk.device, &mut allocator, &mut vk.uploader, &mut vk.allocator, anisotropy)?;
+        let text_rendering = TextBuffers::create(&vk.device, &mut allocator)?;
+        let attachments = InputAttachments::create(&vk.device, &mut allocator, &mut vk.allocator)?;
 
         Ok(DescriptorSets {
-            pool,
+            allocator,
             textures,
             text_rendering,
             attachments,
@@ -56,9 +42,7 @@ impl DescriptorSets {
         self.text_rendering.destroy_self(device)?;
         self.attachments.destroy_self(device, alloc)?;
 
-        unsafe {
-            device.destroy_descriptor_pool(self.pool, None);
-        }
+        self.allocator.destroy_self(device);
         println!("All descriptor sets destroyed");
 
         Ok(())
@@ -71,6 +55,9 @@ pub struct Textures {
 
     pub sampler: vk::Sampler,
     pub texture: Image,
+    // Of the block texture array. Which layer a block uses depends on how many textures
+    // the blocks before it have, so when this changes, chunks need to be remeshed.
+    pub layers: u32,
 
     pub text_sampler: vk::Sampler,
     pub text_texture: Image,
@@ -78,55 +65,24 @@ pub struct Textures {
 impl Textures {
     fn create(
         device: &Device,
-        pool: vk::DescriptorPool,
+        descriptors: &mut DescriptorAllocator,
         uploader: &mut Uploader,
         allocator: &mut VkAllocator,
+        anisotropy: f32,
     ) -> Result<Self> {
-        let layout = unsafe {
-            device.create_descriptor_set_layout(
-                &vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&[
-                    vk::DescriptorSetLayoutBindingBuilder::new()
-                        .binding(0)
-                        .descriptor_count(1)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .stage_flags(vk::ShaderStageFlags::FRAGMENT),
-                    vk::DescriptorSetLayoutBindingBuilder::new()
-                        .binding(1)
-                        .descriptor_count(1)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .stage_flags(vk::ShaderStageFlags::FRAGMENT),
-                ]),
-                None,
-            )
-        }
-        .result()?;
-
-        let descriptor_set = unsafe {
-            device.allocate_descriptor_sets(
-                &vk::DescriptorSetAllocateInfoBuilder::new()
-                    .descriptor_pool(pool)
-                    .set_layouts(&[layout]),
-            )
-        }
-        .result()?[0];
+        // Block texture array, text atlas, and the map (written by MapTexture)
+        let layout = DescriptorLayoutBuilder::new()
+            .binding(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)
+            .binding(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)
+            .binding(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)
+            .build(device)?;
+        let descriptor_set = descriptors.allocate(device, layout)?;
 
-        let sampler = unsafe {
-            device.create_sampler(
-                &vk::SamplerCreateInfoBuilder::new()
-                    .min_filter(vk::Filter::NEAREST)
-                    .mag_filter(vk::Filter::NEAREST)
-                    .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
-                    .anisotropy_enable(false)
-                    .max_anisotropy(8.0)
-                    .mip_lod_bias(0.0)
-                    .min_lod(0.0)
-                    .max_lod(5.0),
-                None,
-            )
-        }
-        .result()?;
+        let sampler = Self::create_block_sampler(device, anisotropy)?;
 
-        let texture = Self::load_texture_array(device, uploader, allocator)?;
+        let bytes = Self::texture_array_bytes(None)?;
+        let texture = Self::load_texture_array(device, uploader, allocator, &bytes)?;
+        let layers = texture_layers(&bytes);
 
         let text_sampler = unsafe {
             device.create_sampler(
@@ -146,48 +102,124 @@ impl Textures {
 
         let text_texture = Self::load_text_atlas(device, uploader, allocator)?;
 
-        unsafe {
-            device.update_descriptor_sets(
-                &[
-                    vk::WriteDescriptorSetBuilder::new()
-                        .dst_binding(0)
-                        .dst_set(descriptor_set)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .image_info(&[vk::DescriptorImageInfoBuilder::new()
-                            .image_view(texture.view)
-                            .sampler(sampler)
-                            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]),
-                    vk::WriteDescriptorSetBuilder::new()
-                        .dst_binding(1)
-                        .dst_set(descriptor_set)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .image_info(&[vk::DescriptorImageInfoBuilder::new()
-                            .image_view(text_texture.view)
-                            .sampler(text_sampler)
-                            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]),
-                ],
-                &[],
-            );
-        }
+        DescriptorWriter::new()
+            .sampled_image(descriptor_set, 0, texture.view, sampler)
+            .sampled_image(descriptor_set, 1, text_texture.view, text_sampler)
+            .write(device);
 
         Ok(Self {
             layout,
             descriptor_set,
             sampler,
             texture,
+            layers,
             text_sampler,
             text_texture,
         })
     }
 
+    // Swaps the block textures for those of resource pack `pack` (a directory under
+    // RESOURCE_PACK_DIR with a packed.bin made by tools/texpack), or back to the built-in
+    // ones if None. Only the texture array binding of the existing descriptor set is
+    // rewritten. The GPU must be idle.
+    pub fn set_resource_pack(
+        &mut self,
+        device: &Device,
+        uploader: &mut Uploader,
+        allocator: &mut VkAllocator,
+        pack: Option<&str>,
+    ) -> Result<()> {
+        let bytes = Self::texture_array_bytes(pack)?;
+        self.set_texture_array(device, uploader, allocator, pack, &bytes)
+    }
+
+    // The second half of set_resource_pack(), for when `bytes` were decoded elsewhere
+    pub fn set_texture_array(
+        &mut self,
+        device: &Device,
+        uploader: &mut Uploader,
+        allocator: &mut VkAllocator,
+        pack: Option<&str>,
+        bytes: &[u8],
+    ) -> Result<()> {
+        // Created in full before the old one goes, so that a failure leaves things as they were
+        let mut texture = Self::load_texture_array(device, uploader, allocator, bytes)?;
+        if let Err(e) = uploader.flush_staged(device) {
+            allocator.deallocate_image(&mut texture, device)?;
+            return Err(e);
+        }
+        uploader.wait_fence_if_unfinished(device)?;
+
+        let mut old = std::mem::replace(&mut self.texture, texture);
+        self.write_block_descriptor(device);
+        allocator.deallocate_image(&mut old, device)?;
+
+        self.layers = texture_layers(bytes);
+        println!("Using {} textures ({} layers)", pack.unwrap_or("built-in"), self.layers);
+        Ok(())
+    }
+
+    // Anisotropic filtering of the block textures, clamped to what the device supports;
+    // 1.0 turns it off. The GPU must be idle.
+    pub fn set_anisotropy(&mut self, device: &Device, anisotropy: f32) -> Result<()> {
+        let old = std::mem::replace(&mut self.sampler, Self::create_block_sampler(device, anisotropy)?);
+        self.write_block_descriptor(device);
+        unsafe {
+            device.destroy_sampler(old, None);
+        }
+        Ok(())
+    }
+
+    fn create_block_sampler(device: &Device, anisotropy: f32) -> Result<vk::Sampler> {
+        let anisotropy = anisotropy.clamp(1.0, device.caps.max_anisotropy);
+        let sampler = unsafe {
+            device.create_sampler(
+                &vk::SamplerCreateInfoBuilder::new()
+                    .min_filter(vk::Filter::NEAREST)
+                    .mag_filter(vk::Filter::NEAREST)
+                    .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+                    .anisotropy_enable(anisotropy > 1.0)
+                    .max_anisotropy(anisotropy)
+                    .mip_lod_bias(0.0)
+                    .min_lod(0.0)
+                    .max_lod(5.0),
+                None,
+            )
+        }
+        .result()?;
+        Ok(sampler)
+    }
+
+    // Points the texture array binding at the current texture and sampler
+    fn write_block_descriptor(&self, device: &Device) {
+        DescriptorWriter::new()
+            .sampled_image(self.descriptor_set, 0, self.texture.view, self.sampler)
+            .write(device);
+    }
+
+    // Uncompressed 16x16 RGBA layers. Slow enough for big packs to belong on a worker thread.
+    pub fn texture_array_bytes(pack: Option<&str>) -> Result<Vec<u8>> {
+        let bytes = match pack {
+            None => lz4::block::decompress(assets::textures::TEXTURES, None)?,
+            Some(name) => {
+                let path = format!("{RESOURCE_PACK_DIR}/{name}/packed.bin");
+                let compressed = std::fs::read(&path).with_context(|| format!("can't read {path}"))?;
+                lz4::block::decompress(&compressed, None).with_context(|| format!("{path} is corrupt"))?
+            }
+        };
+        if bytes.is_empty() || bytes.len() % (16 * 16 * 4) != 0 {
+            bail!("texture array of {} bytes is not made of 16x16 RGBA textures", bytes.len());
+        }
+        Ok(bytes)
+    }
+
     fn load_texture_array(
         device: &Device,
         uploader: &mut Uploader,
         allocator: &mut VkAllocator,
+        bytes: &[u8],
     ) -> Result<Image> {
-        let bytes = lz4::block::decompress(assets::textures::TEXTURES, None)?;
-
-        let layers = bytes.len() as u32 / (16 * 16 * 4);
+        let layers = texture_layers(bytes);
         let mip_levels = (16u32).trailing_zeros() + 1; // floor(log2())
         println!("Mip levels for {} textures: {}", layers, mip_levels);
 
@@ -207,11 +239,12 @@ impl Textures {
                 vk_usage: vk::ImageUsageFlags::SAMPLED
                     | vk::ImageUsageFlags::TRANSFER_SRC
                     | vk::ImageUsageFlags::TRANSFER_DST,
+                tag: MemoryTag::Texture,
             },
         )?;
         uploader.upload_to_image(
             device,
-            &bytes,
+            bytes,
             &mut img,
             *vk::ImageSubresourceRangeBuilder::new()
                 .aspect_mask(vk::ImageAspectFlags::COLOR)
@@ -245,6 +278,7 @@ impl Textures {
                 usage: UsageFlags::FAST_DEVICE_ACCESS,
                 flags: vk::ImageAspectFlags::COLOR,
                 vk_usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+                tag: MemoryTag::Texture,
             },
         )?;
 
@@ -278,42 +312,24 @@ impl Textures {
     }
 }
 
+fn texture_layers(bytes: &[u8]) -> u32 {
+    bytes.len() as u32 / (16 * 16 * 4)
+}
+
 pub struct TextBuffers {
     pub layout: vk::DescriptorSetLayout,
     pub descriptor_sets: Vec<vk::DescriptorSet>,
 }
 
 impl TextBuffers {
-    fn create(device: &Device, pool: vk::DescriptorPool) -> Result<Self> {
-        let layout = unsafe {
-            device.create_descriptor_set_layout(
-                &vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&[
-                    vk::DescriptorSetLayoutBindingBuilder::new()
-                        .binding(0)
-                        .descriptor_count(1)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                        .stage_flags(vk::ShaderStageFlags::VERTEX),
-                    vk::DescriptorSetLayoutBindingBuilder::new()
-                        .binding(1)
-                        .descriptor_count(1)
-                        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                        .stage_flags(vk::ShaderStageFlags::VERTEX),
-                ]),
-                None,
-            )
-        }
-        .result()?;
+    fn create(device: &Device, descriptors: &mut DescriptorAllocator) -> Result<Self> {
+        let layout = DescriptorLayoutBuilder::new()
+            .binding(vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::VERTEX)
+            .binding(vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX)
+            .build(device)?;
 
         let layouts = [layout; FRAMES_IN_FLIGHT as usize];
-
-        let descriptor_sets = unsafe {
-            device.allocate_descriptor_sets(
-                &vk::DescriptorSetAllocateInfoBuilder::new()
-                    .descriptor_pool(pool)
-                    .set_layouts(&layouts),
-            )
-        }
-        .result()?;
+        let descriptor_sets = descriptors.allocate_many(device, &layouts)?;
 
         Ok(Self {
             layout,
@@ -357,92 +373,26 @@ pub struct SkyPushConstants {
 impl InputAttachments {
     fn create(
         device: &Device,
-        pool: vk::DescriptorPool,
+        descriptors: &mut DescriptorAllocator,
         allocator: &mut VkAllocator,
     ) -> Result<Self> {
-        let fxaa_layout = unsafe {
-            device.create_descriptor_set_layout(
-                &vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&[
-                    vk::DescriptorSetLayoutBindingBuilder::new()
-                        .binding(0)
-                        .descriptor_count(1)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .stage_flags(vk::ShaderStageFlags::FRAGMENT),
-                    vk::DescriptorSetLayoutBindingBuilder::new()
-                        .binding(1)
-                        .descriptor_count(1)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .stage_flags(vk::ShaderStageFlags::FRAGMENT),
-                    vk::DescriptorSetLayoutBindingBuilder::new()
-                        .binding(2)
-                        .descriptor_count(1)
-                        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                        .stage_flags(vk::ShaderStageFlags::FRAGMENT),
-                ]),
-                None,
-            )
-        }
-        .result()?;
-
-        let fxaa_descriptor_set = unsafe {
-            device.allocate_descriptor_sets(
-                &vk::DescriptorSetAllocateInfoBuilder::new()
-                    .descriptor_pool(pool)
-                    .set_layouts(&[fxaa_layout]),
-            )
-        }
-        .result()?[0];
-
-        let luma_layout = unsafe {
-            device.create_descriptor_set_layout(
-                &vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&[
-                    vk::DescriptorSetLayoutBindingBuilder::new()
-                        .binding(0)
-                        .descriptor_count(1)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .stage_flags(vk::ShaderStageFlags::FRAGMENT),
-                ]),
-                None,
-            )
-        }
-        .result()?;
-
-        let luma_descriptor_set = unsafe {
-            device.allocate_descriptor_sets(
-                &vk::DescriptorSetAllocateInfoBuilder::new()
-                    .descriptor_pool(pool)
-                    .set_layouts(&[luma_layout]),
-            )
-        }
-        .result()?[0];
-
-        /* let sky_layout = unsafe {
-            device.create_descriptor_set_layout(
-                &vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&[
-                    vk::DescriptorSetLayoutBindingBuilder::new()
-                        .binding(0)
-                        .descriptor_count(1)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .stage_flags(vk::ShaderStageFlags::FRAGMENT),
-                    vk::DescriptorSetLayoutBindingBuilder::new()
-                        .binding(1)
-                        .descriptor_count(1)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .stage_flags(vk::ShaderStageFlags::FRAGMENT),
-                ]),
-                None,
-            )
-        }
-        .result()?; */
-
-        /* let sky_descriptor_set = unsafe {
-            device.allocate_descriptor_sets(
-                &vk::DescriptorSetAllocateInfoBuilder::new()
-                    .descriptor_pool(pool)
-                    .set_layouts(&[sky_layout]),
-            )
-        }
-        .result()?[0]; */
+        let fxaa_layout = DescriptorLayoutBuilder::new()
+            .binding(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)
+            .binding(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)
+            .binding(vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::FRAGMENT)
+            .build(device)?;
+        let fxaa_descriptor_set = descriptors.allocate(device, fxaa_layout)?;
+
+        let luma_layout = DescriptorLayoutBuilder::new()
+            .binding(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)
+            .build(device)?;
+        let luma_descriptor_set = descriptors.allocate(device, luma_layout)?;
+
+        /* let sky_layout = DescriptorLayoutBuilder::new()
+            .binding(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)
+            .binding(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)
+            .build(device)?;
+        let sky_descriptor_set = descriptors.allocate(device, sky_layout)?; */
 
         let sampler = unsafe {
             device.create_sampler(
@@ -462,6 +412,7 @@ impl InputAttachments {
                 size: std::mem::size_of::<TexelSizeUBO>(),
                 usage: UsageFlags::HOST_ACCESS,
                 vk_usage: vk::BufferUsageFlags::UNIFORM_BUFFER,
+                tag: MemoryTag::Uniform,
             },
         )?;
 
@@ -0,0 +1,215 @@
+use erupt::vk;
+use smallvec::SmallVec;
+use vkcore::Device;
+
+use super::renderer::RenderContext;
+
+/*
+Which passes run in a frame, and in what order. Each pass declares the attachments it uses
+and how; `compile()` works out the order from that (a pass runs after every pass that writes
+what it reads), along with the barriers needed between them. The render loop then goes
+through `steps()` and records whichever pass each step names.
+
+Layout transitions stay with the render passes (initial/final layouts), so the barriers here
+are memory barriers only: they make a pass's writes visible to the passes using them, even
+if a render pass's own subpass dependencies don't cover it.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Pass {
+    Terrain,
+    Luma,
+    Fxaa,
+    Ui,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Attachment {
+    MainColor,
+    Depth,
+    Luma,
+    Swapchain,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Usage {
+    // Rendered to from scratch
+    Write,
+    // Rendered on top of, so after whatever wrote it
+    Blend,
+    // Sampled in a shader
+    Read,
+}
+
+struct PassDesc {
+    pass: Pass,
+    attachments: SmallVec<[(Attachment, Usage); 4]>,
+    enabled: bool,
+}
+
+pub struct Step {
+    pub pass: Pass,
+    // Recorded before the pass, if not empty
+    barrier: Option<(vk::PipelineStageFlags, vk::PipelineStageFlags, vk::MemoryBarrier)>,
+}
+
+pub struct FrameGraph {
+    passes: Vec<PassDesc>,
+    steps: Vec<Step>,
+}
+
+impl FrameGraph {
+    // The game's frame: terrain, then post-processing, then UI on top
+    pub fn game() -> Self {
+        use {Attachment::*, Usage::*};
+
+        let mut graph = Self { passes: Vec::new(), steps: Vec::new() };
+        graph
+            .add(Pass::Terrain, &[(MainColor, Write), (Depth, Write)])
+            .add(Pass::Luma, &[(MainColor, Read), (Luma, Write)])
+            .add(Pass::Fxaa, &[(MainColor, Read), (Luma, Read), (Swapchain, Write)])
+            .add(Pass::Ui, &[(Swapchain, Blend)]);
+        graph.compile().expect("the game's frame graph is valid");
+        graph
+    }
+
+    // Passes that have no dependency on each other run in the order they were added
+    pub fn add(&mut self, pass: Pass, attachments: &[(Attachment, Usage)]) -> &mut Self {
+        debug_assert!(self.passes.iter().all(|desc| desc.pass != pass), "{pass:?} added twice");
+        self.passes.push(PassDesc { pass, attachments: attachments.into(), enabled: true });
+        self
+    }
+
+    pub fn is_enabled(&self, pass: Pass) -> bool {
+        self.passes.iter().any(|desc| desc.pass == pass && desc.enabled)
+    }
+
+    // Takes effect from the next frame on. If the graph doesn't work without the pass (its
+    // output is read by another), nothing changes and the error says why.
+    pub fn set_enabled(&mut self, pass: Pass, enabled: bool) -> anyhow::Result<()> {
+        let Some(index) = self.passes.iter().position(|desc| desc.pass == pass) else {
+            anyhow::bail!("No {pass:?} pass in the frame graph");
+        };
+        if self.passes[index].enabled == enabled {
+            return Ok(());
+        }
+        self.passes[index].enabled = enabled;
+        if let Err(e) = self.compile() {
+            self.passes[index].enabled = !enabled;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn compile(&mut self) -> anyhow::Result<()> {
+        let enabled = self.passes.iter().filter(|desc| desc.enabled).collect::<Vec<_>>();
+
+        // dependencies[i]: the (enabled) passes that pass i has to run after
+        let mut dependencies = vec![Vec::new(); enabled.len()];
+        for (i, desc) in enabled.iter().enumerate() {
+            for &(attachment, usage) in &desc.attachments {
+                let writers = enabled.iter().enumerate().filter(|&(j, other)| {
+                    j != i && other.attachments.iter().any(|&(a, u)| match (usage, u) {
+                        // Blending goes after the pass that wrote the attachment from scratch
+                        (Usage::Read, Usage::Write | Usage::Blend) | (Usage::Blend, Usage::Write) => a == attachment,
+                        _ => false,
+                    })
+                });
+                let mut any_writer = false;
+                for (j, _) in writers {
+                    dependencies[i].push(j);
+                    any_writer = true;
+                }
+                if usage != Usage::Write && !any_writer {
+                    anyhow::bail!("{:?} uses {attachment:?}, which no enabled pass writes", desc.pass);
+                }
+            }
+        }
+
+        // Topological order, picking the earliest added pass whenever there's a choice
+        let mut done = vec![false; enabled.len()];
+        let mut order = Vec::with_capacity(enabled.len());
+        while order.len() < enabled.len() {
+            let Some(next) = (0..enabled.len()).find(|&i| !done[i] && dependencies[i].iter().all(|&j| done[j])) else {
+                let stuck = (0..enabled.len()).filter(|&i| !done[i]).map(|i| enabled[i].pass);
+                anyhow::bail!("Cycle in the frame graph between {:?}", stuck.collect::<Vec<_>>());
+            };
+            done[next] = true;
+            order.push(next);
+        }
+
+        self.steps = order
+            .into_iter()
+            .map(|i| Step {
+                pass: enabled[i].pass,
+                barrier: barrier_before(enabled[i], dependencies[i].iter().map(|&j| enabled[j])),
+            })
+            .collect();
+        Ok(())
+    }
+}
+
+impl Step {
+    pub fn record_barrier(&self, device: &Device, ctx: &RenderContext) {
+        let Some((src_stage, dst_stage, barrier)) = self.barrier else {
+            return;
+        };
+        unsafe {
+            device.cmd_pipeline_barrier(
+                ctx.commands,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::BY_REGION,
+                &[barrier.into_builder()],
+                &[],
+                &[],
+            );
+        }
+    }
+}
+
+// Everything `desc` uses that its dependencies wrote, merged into one barrier
+fn barrier_before<'a>(
+    desc: &PassDesc,
+    dependencies: impl Iterator<Item = &'a PassDesc>,
+) -> Option<(vk::PipelineStageFlags, vk::PipelineStageFlags, vk::MemoryBarrier)> {
+    let (mut src_stage, mut dst_stage) = (vk::PipelineStageFlags::empty(), vk::PipelineStageFlags::empty());
+    let (mut src_access, mut dst_access) = (vk::AccessFlags::empty(), vk::AccessFlags::empty());
+    for dependency in dependencies {
+        for &(attachment, usage) in &desc.attachments {
+            if usage == Usage::Write || !dependency.attachments.iter().any(|&(a, u)| a == attachment && u != Usage::Read) {
+                continue;
+            }
+            let (stage, access) = attachment_write(attachment);
+            src_stage |= stage;
+            src_access |= access;
+            let (stage, access) = match usage {
+                Usage::Read => (vk::PipelineStageFlags::FRAGMENT_SHADER, vk::AccessFlags::SHADER_READ),
+                _ => (
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                ),
+            };
+            dst_stage |= stage;
+            dst_access |= access;
+        }
+    }
+    (!src_stage.is_empty()).then(|| {
+        let barrier = vk::MemoryBarrierBuilder::new().src_access_mask(src_access).dst_access_mask(dst_access);
+        (src_stage, dst_stage, *barrier)
+    })
+}
+
+fn attachment_write(attachment: Attachment) -> (vk::PipelineStageFlags, vk::AccessFlags) {
+    match attachment {
+        Attachment::Depth => (
+            vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        ),
+        _ => (vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags::COLOR_ATTACHMENT_WRITE),
+    }
+}
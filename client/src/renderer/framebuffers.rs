@@ -1,5 +1,5 @@
 use erupt::vk;
-use vkcore::{Device, Image, ImageAllocation, UsageFlags, VkAllocator, VkContext};
+use vkcore::{Device, Image, ImageAllocation, MemoryTag, UsageFlags, VkAllocator, VkContext};
 
 pub struct FramebufferImages {
     pub main_pass_color: Image,
@@ -41,6 +41,7 @@ impl FramebufferImages {
                 flags: vk::ImageAspectFlags::DEPTH,
                 vk_usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
                     | vk::ImageUsageFlags::SAMPLED,
+                tag: MemoryTag::Framebuffer,
             },
         )?;
         self.luma = vk.allocator.allocate_image(
@@ -53,6 +54,7 @@ impl FramebufferImages {
                 usage: UsageFlags::FAST_DEVICE_ACCESS,
                 flags: vk::ImageAspectFlags::COLOR,
                 vk_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                tag: MemoryTag::Framebuffer,
             },
         )?;
 
@@ -83,6 +85,7 @@ fn alloc_color_fb(vk: &mut VkContext) -> anyhow::Result<Image> {
             usage: UsageFlags::FAST_DEVICE_ACCESS,
             flags: vk::ImageAspectFlags::COLOR,
             vk_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            tag: MemoryTag::Framebuffer,
         },
     )
 }
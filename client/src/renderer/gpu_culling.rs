@@ -0,0 +1,228 @@
+use std::ffi::c_void;
+
+use erupt::vk;
+use glam::{Mat4, Vec4};
+use vkcore::{
+    pipeline::{group_count, Pipeline},
+    Buffer, BufferAllocation, DescriptorLayoutBuilder, DescriptorWriter, Device, MemoryTag, RenderPass, UsageFlags,
+    VkContext,
+};
+
+use crate::assets;
+
+use super::{
+    descriptor_sets::DescriptorSets,
+    passes::terrain_pass::{self, TerrainDrawMode},
+    renderer::{RenderContext, FRAMES_IN_FLIGHT},
+};
+
+// Frustum culling on the GPU, as an alternative to EntityCulling's CPU test, for comparing the
+// two. Every candidate is uploaded with its bounding sphere; a compute pre-pass (cull.comp)
+// appends the model matrices of the visible ones to a storage buffer and counts them into an
+// indexed indirect draw command, which the terrain pass then draws with one
+// cmd_draw_indexed_indirect.
+pub struct GpuCulling {
+    layout: vk::DescriptorSetLayout,
+    cull: Pipeline,
+    draw: Pipeline,
+    frames: Vec<CullingFrame>,
+    count: u32, // candidates uploaded for the current frame
+}
+
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct CullCandidate {
+    pub model: Mat4,
+    pub sphere: Vec4, // center, radius
+}
+
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct CullParams {
+    planes: [Vec4; 5],
+    count: u32,
+    _pad: [u32; 3],
+}
+
+// VkDrawIndexedIndirectCommand
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct DrawIndexedIndirect {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    vertex_offset: i32,
+    first_instance: u32,
+}
+
+struct CullingFrame {
+    candidates: Buffer,
+    visible: Buffer,
+    indirect: Buffer,
+    capacity: usize, // candidates
+    descriptor_set: vk::DescriptorSet,
+}
+
+impl GpuCulling {
+    const LOCAL_SIZE: u32 = 64;
+    const INITIAL_CAPACITY: usize = 256;
+
+    pub fn create(vk: &mut VkContext, descriptors: &mut DescriptorSets, terrain: &RenderPass) -> anyhow::Result<Self> {
+        let layout = DescriptorLayoutBuilder::new()
+            .binding(vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE)
+            .binding(vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::VERTEX)
+            .binding(vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE)
+            .build(&vk.device)?;
+        let sets = descriptors.allocator.allocate_many(&vk.device, &[layout; FRAMES_IN_FLIGHT as usize])?;
+
+        let mut frames = Vec::with_capacity(sets.len());
+        for descriptor_set in sets {
+            frames.push(CullingFrame::create(vk, descriptor_set, Self::INITIAL_CAPACITY)?);
+        }
+
+        let (cull, draw) = Self::create_pipelines(vk, descriptors, terrain, layout)?;
+        Ok(Self { layout, cull, draw, frames, count: 0 })
+    }
+
+    fn create_pipelines(
+        vk: &VkContext,
+        descriptors: &DescriptorSets,
+        terrain: &RenderPass,
+        layout: vk::DescriptorSetLayout,
+    ) -> anyhow::Result<(Pipeline, Pipeline)> {
+        let cull = vk
+            .compute_pipeline_builder()
+            .shader(assets::culling_pipeline::CULL_SHADER_COMP)
+            .constants(&[Self::LOCAL_SIZE])
+            .layout(
+                vk::PipelineLayoutCreateInfoBuilder::new()
+                    .push_constant_ranges(&[vk::PushConstantRangeBuilder::new()
+                        .offset(0)
+                        .size(std::mem::size_of::<CullParams>() as _)
+                        .stage_flags(vk::ShaderStageFlags::COMPUTE)])
+                    .set_layouts(&[layout]),
+            )
+            .build()?;
+        let draw = terrain_pass::create_pipelines_with(
+            terrain,
+            vk,
+            assets::culling_pipeline::INSTANCED_SHADER_VERT,
+            &[descriptors.textures.layout, layout],
+            TerrainDrawMode::Normal,
+        )?;
+        Ok((cull, draw))
+    }
+
+    // The draw pipeline depends on the window size, like the other pipelines
+    pub fn handle_window_resize(&mut self, vk: &VkContext, descriptors: &DescriptorSets, terrain: &RenderPass) -> anyhow::Result<()> {
+        self.cull.destroy_self(&vk.device);
+        self.draw.destroy_self(&vk.device);
+        (self.cull, self.draw) = Self::create_pipelines(vk, descriptors, terrain, self.layout)?;
+        Ok(())
+    }
+
+    // `index_count`: of the mesh each visible candidate is drawn with
+    pub fn do_uploads(&mut self, vk: &mut VkContext, frame: usize, candidates: &[CullCandidate], index_count: u32) -> anyhow::Result<()> {
+        let culling_frame = &mut self.frames[frame];
+        if culling_frame.capacity < candidates.len() {
+            // This frame's previous commands have finished (see Renderer::start_frame()), so
+            // only this frame's buffers need replacing
+            culling_frame.destroy_self(vk)?;
+            *culling_frame = CullingFrame::create(vk, culling_frame.descriptor_set, candidates.len().next_power_of_two())?;
+        }
+
+        vk.uploader.upload_to_buffer(&vk.device, candidates, &mut culling_frame.candidates, 0)?;
+        let draw = DrawIndexedIndirect { index_count, instance_count: 0, first_index: 0, vertex_offset: 0, first_instance: 0 };
+        vk.uploader.upload_to_buffer(&vk.device, &[draw], &mut culling_frame.indirect, 0)?;
+        self.count = candidates.len() as u32;
+        Ok(())
+    }
+
+    // The compute pre-pass; outside of render passes
+    pub fn dispatch(&self, device: &Device, ctx: &RenderContext, planes: [Vec4; 5]) {
+        let params = CullParams { planes, count: self.count, _pad: [0; 3] };
+        let set = self.frames[ctx.frame].descriptor_set;
+        ctx.dispatch(device, &self.cull, &[set], bytemuck::bytes_of(&params), [group_count(self.count, Self::LOCAL_SIZE), 1, 1]);
+        ctx.compute_barrier(
+            device,
+            vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::VERTEX_SHADER,
+            vk::AccessFlags::INDIRECT_COMMAND_READ | vk::AccessFlags::SHADER_READ,
+        );
+    }
+
+    // Inside the terrain pass, with the mesh's vertex and index buffers bound
+    pub fn render(&self, device: &Device, ctx: &RenderContext, descriptors: &DescriptorSets, proj_view: Mat4) {
+        let frame = &self.frames[ctx.frame];
+        unsafe {
+            device.cmd_bind_pipeline(ctx.commands, vk::PipelineBindPoint::GRAPHICS, self.draw.handle);
+            device.cmd_push_constants(
+                ctx.commands,
+                self.draw.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                std::mem::size_of::<Mat4>() as u32,
+                &proj_view as *const Mat4 as *const c_void,
+            );
+            device.cmd_bind_descriptor_sets(
+                ctx.commands,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.draw.layout,
+                0,
+                &[descriptors.textures.descriptor_set, frame.descriptor_set],
+                &[],
+            );
+            device.cmd_draw_indexed_indirect(ctx.commands, frame.indirect.handle, 0, 1, 0);
+        }
+    }
+
+    pub fn destroy_self(&mut self, vk: &mut VkContext) -> anyhow::Result<()> {
+        self.cull.destroy_self(&vk.device);
+        self.draw.destroy_self(&vk.device);
+        for frame in &mut self.frames {
+            frame.destroy_self(vk)?;
+        }
+        unsafe {
+            vk.device.destroy_descriptor_set_layout(self.layout, None);
+        }
+        Ok(())
+    }
+}
+
+impl CullingFrame {
+    fn create(vk: &mut VkContext, descriptor_set: vk::DescriptorSet, capacity: usize) -> anyhow::Result<Self> {
+        let mut buffer = |size: usize, usage: UsageFlags, vk_usage: vk::BufferUsageFlags| {
+            vk.allocator.allocate_buffer(&vk.device, &BufferAllocation { size, usage, vk_usage, tag: MemoryTag::Mesh })
+        };
+        let candidates = buffer(
+            capacity * std::mem::size_of::<CullCandidate>(),
+            UsageFlags::UPLOAD,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+        )?;
+        let visible = buffer(
+            capacity * std::mem::size_of::<Mat4>(),
+            UsageFlags::FAST_DEVICE_ACCESS,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+        )?;
+        let indirect = buffer(
+            std::mem::size_of::<DrawIndexedIndirect>(),
+            UsageFlags::UPLOAD,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+        )?;
+
+        let ty = vk::DescriptorType::STORAGE_BUFFER;
+        DescriptorWriter::new()
+            .buffer(descriptor_set, 0, ty, candidates.handle, vk::WHOLE_SIZE)
+            .buffer(descriptor_set, 1, ty, visible.handle, vk::WHOLE_SIZE)
+            .buffer(descriptor_set, 2, ty, indirect.handle, vk::WHOLE_SIZE)
+            .write(&vk.device);
+
+        Ok(Self { candidates, visible, indirect, capacity, descriptor_set })
+    }
+
+    fn destroy_self(&mut self, vk: &mut VkContext) -> anyhow::Result<()> {
+        vk.allocator.deallocate_buffer(&mut self.candidates, &vk.device)?;
+        vk.allocator.deallocate_buffer(&mut self.visible, &vk.device)?;
+        vk.allocator.deallocate_buffer(&mut self.indirect, &vk.device)?;
+        Ok(())
+    }
+}
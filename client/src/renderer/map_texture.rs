@@ -0,0 +1,196 @@
+use erupt::vk;
+use vkcore::{
+    Buffer, BufferAllocation, DescriptorWriter, Device, Image, ImageAllocation, MemoryTag, UsageFlags, VkContext,
+};
+
+use super::{
+    descriptor_sets::DescriptorSets,
+    renderer::{RenderContext, FRAMES_IN_FLIGHT},
+};
+
+// The minimap's pixels on the GPU, bound to the UI shader next to the block textures
+// (binding 2 of `Textures`) and drawn with UiRenderer::draw_map_rect(). A new copy goes
+// through a staging buffer of the frame in flight, and is copied into the image by that
+// frame's own commands, so that a frame still reading the old one isn't disturbed.
+pub struct MapTexture {
+    image: Image,
+    sampler: vk::Sampler,
+    staging: Vec<Buffer>,
+    descriptor_set: vk::DescriptorSet,
+    size: u32, // width and height, in pixels
+}
+
+impl MapTexture {
+    pub fn create(vk: &mut VkContext, descriptors: &DescriptorSets) -> anyhow::Result<Self> {
+        let sampler = unsafe {
+            vk.device.create_sampler(
+                &vk::SamplerCreateInfoBuilder::new()
+                    .min_filter(vk::Filter::NEAREST)
+                    .mag_filter(vk::Filter::NEAREST)
+                    .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+                    .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .max_lod(0.0),
+                None,
+            )
+        }
+        .result()?;
+
+        // A transparent placeholder until there is a map, since the UI shader uses the
+        // binding in the menus too
+        let mut image = Self::allocate_image(vk, 1)?;
+        vk.uploader.upload_to_image(
+            &vk.device,
+            &[0; 4],
+            &mut image,
+            Self::subresource_range(),
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            false,
+        )?;
+
+        let mut staging = Vec::with_capacity(FRAMES_IN_FLIGHT as usize);
+        for _ in 0..FRAMES_IN_FLIGHT {
+            staging.push(Self::allocate_staging(vk, 4)?);
+        }
+
+        let texture = Self { image, sampler, staging, descriptor_set: descriptors.textures.descriptor_set, size: 1 };
+        texture.write_descriptor(&vk.device);
+        Ok(texture)
+    }
+
+    // Records the copy of `pixels` (0xRRGGBBAA, `size` x `size`, row by row) into the image.
+    // Outside of render passes, and before anything this frame binds the textures, since
+    // a new size means a new image and descriptor.
+    pub fn update(&mut self, vk: &mut VkContext, ctx: &RenderContext, size: u32, pixels: &[u32]) -> anyhow::Result<()> {
+        assert_eq!(pixels.len(), (size * size) as usize);
+        if size != self.size {
+            // Only when the loaded area changes size, so waiting doesn't matter
+            unsafe { vk.device.device_wait_idle() }.result()?;
+            let mut old = std::mem::replace(&mut self.image, Self::allocate_image(vk, size)?);
+            vk.allocator.deallocate_image(&mut old, &vk.device)?;
+            self.size = size;
+            self.write_descriptor(&vk.device);
+        }
+
+        let staging = &mut self.staging[ctx.frame];
+        if staging.size < std::mem::size_of_val(pixels) as u64 {
+            // This frame's previous commands have finished (see Renderer::start_frame())
+            vk.allocator.deallocate_buffer(staging, &vk.device)?;
+            *staging = Self::allocate_staging(vk, std::mem::size_of_val(pixels))?;
+        }
+        let bytes = pixels.iter().map(|pixel| pixel.to_be()).collect::<Vec<_>>();
+        vk.uploader.upload_to_buffer(&vk.device, &bytes, staging, 0)?;
+
+        let barrier = |old, new, src_access, dst_access| {
+            vk::ImageMemoryBarrierBuilder::new()
+                .image(self.image.handle)
+                .old_layout(old)
+                .new_layout(new)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .subresource_range(Self::subresource_range())
+        };
+        let commands = ctx.commands;
+        unsafe {
+            // Earlier frames may still be sampling it. The old contents don't matter.
+            vk.device.cmd_pipeline_barrier(
+                commands,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::TRANSFER_WRITE,
+                )],
+            );
+            vk.device.cmd_copy_buffer_to_image(
+                commands,
+                staging.handle,
+                self.image.handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[vk::BufferImageCopyBuilder::new()
+                    .image_extent(vk::Extent3D { width: size, height: size, depth: 1 })
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })],
+            );
+            vk.device.cmd_pipeline_barrier(
+                commands,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::SHADER_READ,
+                )],
+            );
+        }
+        Ok(())
+    }
+
+    fn allocate_image(vk: &mut VkContext, size: u32) -> anyhow::Result<Image> {
+        Ok(vk.allocator.allocate_image(
+            &vk.device,
+            &ImageAllocation {
+                // Like the other UI colors, already sRGB-encoded
+                format: vk::Format::R8G8B8A8_UNORM,
+                layers: 1,
+                mip_levels: 1,
+                extent: vk::Extent2D { width: size, height: size },
+                usage: UsageFlags::FAST_DEVICE_ACCESS,
+                flags: vk::ImageAspectFlags::COLOR,
+                vk_usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+                tag: MemoryTag::Texture,
+            },
+        )?)
+    }
+
+    fn allocate_staging(vk: &mut VkContext, size: usize) -> anyhow::Result<Buffer> {
+        Ok(vk.allocator.allocate_buffer(
+            &vk.device,
+            &BufferAllocation {
+                size,
+                usage: UsageFlags::UPLOAD,
+                vk_usage: vk::BufferUsageFlags::TRANSFER_SRC,
+                tag: MemoryTag::Staging,
+            },
+        )?)
+    }
+
+    fn subresource_range() -> vk::ImageSubresourceRange {
+        *vk::ImageSubresourceRangeBuilder::new()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1)
+    }
+
+    fn write_descriptor(&self, device: &Device) {
+        DescriptorWriter::new()
+            .sampled_image(self.descriptor_set, 2, self.image.view, self.sampler)
+            .write(device);
+    }
+
+    pub fn destroy_self(&mut self, vk: &mut VkContext) -> anyhow::Result<()> {
+        vk.allocator.deallocate_image(&mut self.image, &vk.device)?;
+        for staging in &mut self.staging {
+            vk.allocator.deallocate_buffer(staging, &vk.device)?;
+        }
+        unsafe {
+            vk.device.destroy_sampler(self.sampler, None);
+        }
+        Ok(())
+    }
+}
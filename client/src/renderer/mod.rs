@@ -1,5 +1,9 @@
+pub mod debug_lines;
 pub mod descriptor_sets;
+pub mod frame_graph;
 pub mod framebuffers;
+pub mod gpu_culling;
+pub mod map_texture;
 pub mod passes;
 pub mod pipelines;
 pub mod render_passes;
@@ -0,0 +1,81 @@
+use erupt::vk;
+use glam::{Mat4, Vec3};
+use vkcore::{pipeline::Pipeline, RenderPass, VkContext};
+
+use crate::assets;
+
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct LineVertex {
+    pub pos: Vec3,
+    // Read as an uvec3 and not normalized by the shader, so each channel is 0 or 1 in practice
+    pub color: [u8; 4],
+}
+
+// Drawn inside the terrain pass, so that lines are depth tested against the world
+pub fn create_pipelines(pass: &RenderPass, vk: &VkContext) -> anyhow::Result<Pipeline> {
+    use vk::ColorComponentFlags as CCF;
+    vk.graphics_pipeline_builder()
+        .render_pass(pass)
+        .vertex_code(assets::debug_pipeline::DEBUG_LINES_SHADER_VERT)
+        .fragment_code(assets::debug_pipeline::DEBUG_LINES_SHADER_FRAG)
+        .rasterization_state(
+            vk::PipelineRasterizationStateCreateInfoBuilder::new()
+                .cull_mode(vk::CullModeFlags::NONE)
+                .line_width(1.0)
+                .polygon_mode(vk::PolygonMode::FILL)
+                .depth_bias_enable(false)
+                .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+                .rasterizer_discard_enable(false),
+        )
+        .input_info(
+            vk::PipelineVertexInputStateCreateInfoBuilder::new()
+                .vertex_binding_descriptions(&[vk::VertexInputBindingDescriptionBuilder::new()
+                    .binding(0)
+                    .stride(std::mem::size_of::<LineVertex>() as _)
+                    .input_rate(vk::VertexInputRate::VERTEX)])
+                .vertex_attribute_descriptions(&[
+                    vk::VertexInputAttributeDescriptionBuilder::new()
+                        .binding(0)
+                        .format(vk::Format::R32G32B32_SFLOAT)
+                        .offset(0)
+                        .location(0),
+                    vk::VertexInputAttributeDescriptionBuilder::new()
+                        .binding(0)
+                        .format(vk::Format::R8G8B8A8_UINT)
+                        .offset(12)
+                        .location(1),
+                ]),
+        )
+        .blend_attachment(
+            vk::PipelineColorBlendAttachmentStateBuilder::new()
+                .blend_enable(false)
+                .color_write_mask(CCF::R | CCF::G | CCF::B | CCF::A),
+        )
+        .layout(
+            vk::PipelineLayoutCreateInfoBuilder::new()
+                .push_constant_ranges(&[vk::PushConstantRangeBuilder::new()
+                    .offset(0)
+                    .size((std::mem::size_of::<Mat4>()) as _)
+                    .stage_flags(vk::ShaderStageFlags::VERTEX)])
+                .set_layouts(&[]),
+        )
+        .multisampling(
+            vk::PipelineMultisampleStateCreateInfoBuilder::new()
+                .sample_shading_enable(false)
+                .rasterization_samples(vk::SampleCountFlagBits::_1),
+        )
+        .primitive_topology(vk::PrimitiveTopology::LINE_LIST)
+        .primitive_restart_enable(false)
+        .depth_stencil(
+            vk::PipelineDepthStencilStateCreateInfoBuilder::new()
+                .depth_test_enable(true)
+                .depth_write_enable(false)
+                .depth_bounds_test_enable(false)
+                .depth_compare_op(vk::CompareOp::GREATER_OR_EQUAL)
+                .min_depth_bounds(0.0)
+                .max_depth_bounds(1.0)
+                .stencil_test_enable(false),
+        )
+        .build()
+}
@@ -52,6 +52,7 @@ pub fn create_pipelines(
         .render_pass(pass)
         .vertex_code(assets::postprocess_pipelines::FULLSCREEN_SHADER_VERT)
         .fragment_code(assets::postprocess_pipelines::FXAA_SHADER_FRAG)
+        .fragment_constants(&[vk.swapchain.surface.transfer as u32])
         .rasterization_state(
             vk::PipelineRasterizationStateCreateInfoBuilder::new()
                 .cull_mode(vk::CullModeFlags::NONE)
@@ -73,7 +74,10 @@ pub fn create_pipelines(
         )
         .layout(
             vk::PipelineLayoutCreateInfoBuilder::new()
-                .push_constant_ranges(&[])
+                .push_constant_ranges(&[vk::PushConstantRangeBuilder::new()
+                    .offset(0)
+                    .size(std::mem::size_of::<f32>() as _) // gamma
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)])
                 .set_layouts(&[
                     descriptors.textures.layout,
                     descriptors.attachments.fxaa_layout,
@@ -1,3 +1,4 @@
+pub mod debug_line_pass;
 pub mod fxaa_pass;
 pub mod luminance_pass;
 pub mod sky_pass;
@@ -95,21 +95,64 @@ pub fn create_render_pass(vk: &VkContext, fbs: &FramebufferImages) -> Result<Ren
     })
 }
 
+// Debug views of the terrain, selectable at runtime
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TerrainDrawMode {
+    Normal,
+    // Polygon mode LINE; needs the fillModeNonSolid device feature
+    Wireframe,
+    // No depth test, additive blending: the more layers of geometry, the brighter
+    Overdraw,
+}
+
+impl TerrainDrawMode {
+    pub const ALL: [TerrainDrawMode; 3] = [Self::Normal, Self::Wireframe, Self::Overdraw];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
 pub fn create_pipelines(
     pass: &RenderPass,
     vk: &VkContext,
     descriptors: &DescriptorSets,
+    mode: TerrainDrawMode,
+) -> anyhow::Result<Pipeline> {
+    create_pipelines_with(
+        pass,
+        vk,
+        assets::terrain_pipeline::TERRAIN_SHADER_VERT,
+        &[descriptors.textures.layout],
+        mode,
+    )
+}
+
+// The terrain pipeline with another vertex shader, taking the same vertices and push constants
+pub fn create_pipelines_with(
+    pass: &RenderPass,
+    vk: &VkContext,
+    vertex_code: &[u8],
+    set_layouts: &[vk::DescriptorSetLayout],
+    mode: TerrainDrawMode,
 ) -> anyhow::Result<Pipeline> {
     use vk::ColorComponentFlags as CCF;
+
+    let (polygon_mode, cull_mode) = match mode {
+        TerrainDrawMode::Wireframe => (vk::PolygonMode::LINE, vk::CullModeFlags::NONE),
+        _ => (vk::PolygonMode::FILL, vk::CullModeFlags::BACK),
+    };
+    let overdraw = mode == TerrainDrawMode::Overdraw;
+
     vk.graphics_pipeline_builder()
         .render_pass(pass)
-        .vertex_code(assets::terrain_pipeline::TERRAIN_SHADER_VERT)
+        .vertex_code(vertex_code)
         .fragment_code(assets::terrain_pipeline::TERRAIN_SHADER_FRAG)
         .rasterization_state(
             vk::PipelineRasterizationStateCreateInfoBuilder::new()
-                .cull_mode(vk::CullModeFlags::BACK)
+                .cull_mode(cull_mode)
                 .line_width(1.0)
-                .polygon_mode(vk::PolygonMode::FILL)
+                .polygon_mode(polygon_mode)
                 .depth_bias_enable(false)
                 .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
                 .rasterizer_discard_enable(false),
@@ -139,17 +182,26 @@ pub fn create_pipelines(
                 ]),
         )
         .blend_attachment(
+            // Overdraw: dst += src * constant. The shader can't be told to output a flat
+            // color, so each layer's contribution still depends somewhat on its texture.
             vk::PipelineColorBlendAttachmentStateBuilder::new()
-                .blend_enable(false)
+                .blend_enable(overdraw)
+                .src_color_blend_factor(vk::BlendFactor::CONSTANT_COLOR)
+                .dst_color_blend_factor(vk::BlendFactor::ONE)
+                .color_blend_op(vk::BlendOp::ADD)
+                .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+                .alpha_blend_op(vk::BlendOp::ADD)
                 .color_write_mask(CCF::R | CCF::G | CCF::B | CCF::A),
         )
+        .blend_constants([0.25, 0.08, 0.02, 0.0])
         .layout(
             vk::PipelineLayoutCreateInfoBuilder::new()
                 .push_constant_ranges(&[vk::PushConstantRangeBuilder::new()
                     .offset(0)
                     .size((std::mem::size_of::<Mat4>()) as _)
                     .stage_flags(vk::ShaderStageFlags::VERTEX)])
-                .set_layouts(&[descriptors.textures.layout]),
+                .set_layouts(set_layouts),
         )
         .multisampling(
             vk::PipelineMultisampleStateCreateInfoBuilder::new()
@@ -160,8 +212,8 @@ pub fn create_pipelines(
         .primitive_restart_enable(false)
         .depth_stencil(
             vk::PipelineDepthStencilStateCreateInfoBuilder::new()
-                .depth_test_enable(true)
-                .depth_write_enable(true)
+                .depth_test_enable(!overdraw)
+                .depth_write_enable(!overdraw)
                 .depth_bounds_test_enable(false)
                 .depth_compare_op(vk::CompareOp::GREATER_OR_EQUAL)
                 .min_depth_bounds(0.0)
@@ -18,22 +18,45 @@ pub struct UiRenderPasses {
     pub game: RenderPass,
 }
 
-#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+// Every vertex of a rect carries the whole rect's style, so that any mix of rects goes
+// in one draw call. The fragment shader rounds the corners and draws the border based
+// on where in the rect the fragment is.
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
 #[repr(C)]
 pub struct UiVertex {
     pub x: u16,
     pub y: u16,
-    // Either R8G8B8A7 or U16V15.
-    // Lsb: 0 => colored, 1 => textured
-    pub color_or_uv: u32,
+    pub color: u32, // R8G8B8A8 in memory order; the texture is multiplied by it
+    pub u: u16, // 0..=65535 across the texture
+    pub v: u16,
+    pub local_x: u16, // from the bottom left corner of the rect, in pixels
+    pub local_y: u16,
+    pub width: u16, // of the rect
+    pub height: u16,
+    pub corner_radius: u8,
+    pub border_width: u8, // inside the rect
+    pub layer: u8, // of the block texture array
+    pub flags: u8, // TEXTURED or MAP
+    pub border_color: u32,
 }
 
 impl UiVertex {
+    pub const TEXTURED: u8 = 1;
+    // Textured with the map instead of the block textures, see MapTexture
+    pub const MAP: u8 = 2;
+
+    // A plain vertex of a shape that isn't a rect
     pub fn color(x: u16, y: u16, rgba: u32) -> Self {
         Self {
             x,
             y,
-            color_or_uv: rgba,
+            color: rgba,
+            // Never near an edge
+            width: u16::MAX,
+            height: u16::MAX,
+            local_x: u16::MAX / 2,
+            local_y: u16::MAX / 2,
+            ..Default::default()
         }
     }
 }
@@ -122,6 +145,7 @@ pub fn create_pipelines(
         .render_pass(pass)
         .vertex_code(assets::ui_pipeline::IMMEDIATE_MODE_SHADER_VERT)
         .fragment_code(assets::ui_pipeline::IMMEDIATE_MODE_SHADER_FRAG)
+        .fragment_constants(&[vk.swapchain.surface.transfer as u32])
         .rasterization_state(
             vk::PipelineRasterizationStateCreateInfoBuilder::new()
                 .cull_mode(vk::CullModeFlags::NONE)
@@ -137,18 +161,14 @@ pub fn create_pipelines(
                     .binding(0)
                     .stride(std::mem::size_of::<UiVertex>() as _)
                     .input_rate(vk::VertexInputRate::VERTEX)])
-                .vertex_attribute_descriptions(&[
+                // Seven u32s, unpacked in the shader
+                .vertex_attribute_descriptions(&[0, 1, 2, 3, 4, 5, 6].map(|i| {
                     vk::VertexInputAttributeDescriptionBuilder::new()
                         .binding(0)
                         .format(vk::Format::R32_UINT)
-                        .offset(0)
-                        .location(0),
-                    vk::VertexInputAttributeDescriptionBuilder::new()
-                        .binding(0)
-                        .format(vk::Format::R32_UINT)
-                        .offset(4)
-                        .location(1),
-                ]),
+                        .offset(i * 4)
+                        .location(i)
+                })),
         )
         .blend_attachment(
             vk::PipelineColorBlendAttachmentStateBuilder::new()
@@ -167,7 +187,7 @@ pub fn create_pipelines(
                     .offset(0)
                     .size((std::mem::size_of::<Vec2>()) as _)
                     .stage_flags(vk::ShaderStageFlags::VERTEX)])
-                .set_layouts(&[]),
+                .set_layouts(&[descriptors.textures.layout]),
         )
         .primitive_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
         .depth_stencil(
@@ -187,6 +207,7 @@ pub fn create_pipelines(
         .render_pass(pass)
         .vertex_code(assets::text::TEXT_SHADER_VERT)
         .fragment_code(assets::text::TEXT_SHADER_FRAG)
+        .fragment_constants(&[vk.swapchain.surface.transfer as u32])
         .dynamic_states(&[vk::DynamicState::SCISSOR])
         .rasterization_state(
             vk::PipelineRasterizationStateCreateInfoBuilder::new()
@@ -1,11 +1,16 @@
 use vkcore::{pipeline::Pipeline, Device, VkContext};
 
 use super::{
-    descriptor_sets::DescriptorSets, passes::ui_pass::UiPipelines, render_passes::RenderPasses,
+    descriptor_sets::DescriptorSets,
+    passes::{terrain_pass::TerrainDrawMode, ui_pass::UiPipelines},
+    render_passes::RenderPasses,
 };
 
 pub struct Pipelines {
     pub terrain: Pipeline,
+    pub terrain_wireframe: Option<Pipeline>, // None if the device doesn't support it
+    pub terrain_overdraw: Pipeline,
+    pub debug_lines: Pipeline,
     pub fxaa: Pipeline,
     pub luma: Pipeline,
     /* pub sky: Pipeline, */
@@ -19,8 +24,15 @@ impl Pipelines {
         descriptors: &DescriptorSets,
     ) -> anyhow::Result<Self> {
         use super::passes::*;
+        let wireframe_supported = vk.device.caps.wireframe;
         Ok(Self {
-            terrain: terrain_pass::create_pipelines(&passes.terrain, vk, descriptors)?,
+            terrain: terrain_pass::create_pipelines(&passes.terrain, vk, descriptors, TerrainDrawMode::Normal)?,
+            terrain_wireframe: match wireframe_supported {
+                true => Some(terrain_pass::create_pipelines(&passes.terrain, vk, descriptors, TerrainDrawMode::Wireframe)?),
+                false => None,
+            },
+            terrain_overdraw: terrain_pass::create_pipelines(&passes.terrain, vk, descriptors, TerrainDrawMode::Overdraw)?,
+            debug_lines: debug_line_pass::create_pipelines(&passes.terrain, vk)?,
             fxaa: fxaa_pass::create_pipelines(&passes.fxaa, vk, descriptors)?,
             luma: luminance_pass::create_pipelines(&passes.luma, vk, descriptors)?,
             /* sky: sky_pass::create_pipelines(&passes.sky, vk, descriptors, fbs)?, */
@@ -28,8 +40,22 @@ impl Pipelines {
         })
     }
 
+    // Falls back to the normal pipeline if the mode isn't supported
+    pub fn terrain_for(&self, mode: TerrainDrawMode) -> &Pipeline {
+        match mode {
+            TerrainDrawMode::Normal => &self.terrain,
+            TerrainDrawMode::Wireframe => self.terrain_wireframe.as_ref().unwrap_or(&self.terrain),
+            TerrainDrawMode::Overdraw => &self.terrain_overdraw,
+        }
+    }
+
     pub fn destroy_self(&mut self, device: &Device) {
         self.terrain.destroy_self(device);
+        if let Some(wireframe) = &self.terrain_wireframe {
+            wireframe.destroy_self(device);
+        }
+        self.terrain_overdraw.destroy_self(device);
+        self.debug_lines.destroy_self(device);
         self.fxaa.destroy_self(device);
         self.luma.destroy_self(device);
         /* self.sky.destroy_self(device); */
@@ -1,4 +1,4 @@
-use vkcore::{Device, RenderPass, VkContext};
+use vkcore::{DescriptorWriter, Device, RenderPass, VkContext};
 
 use erupt::vk;
 use glam::Vec2;
@@ -63,61 +63,15 @@ impl RenderPasses {
         )?;
         vk.uploader.flush_staged(&vk.device)?;
 
-        unsafe {
-            vk.device.update_descriptor_sets(
-                &[
-                    vk::WriteDescriptorSetBuilder::new()
-                        .dst_binding(0)
-                        .dst_set(descriptors.attachments.fxaa_descriptor_set)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .image_info(&[vk::DescriptorImageInfoBuilder::new()
-                            .image_view(fbs.main_pass_color.view)
-                            .sampler(descriptors.attachments.sampler)
-                            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]),
-                    vk::WriteDescriptorSetBuilder::new()
-                        .dst_binding(1)
-                        .dst_set(descriptors.attachments.fxaa_descriptor_set)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .image_info(&[vk::DescriptorImageInfoBuilder::new()
-                            .image_view(fbs.luma.view)
-                            .sampler(descriptors.attachments.sampler)
-                            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]),
-                    vk::WriteDescriptorSetBuilder::new()
-                        .dst_binding(2)
-                        .dst_set(descriptors.attachments.fxaa_descriptor_set)
-                        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                        .buffer_info(&[vk::DescriptorBufferInfoBuilder::new()
-                            .range(vk::WHOLE_SIZE)
-                            .buffer(descriptors.attachments.fxaa_ubo_buf.handle)
-                            .offset(0)]),
-                    /*                     vk::WriteDescriptorSetBuilder::new()
-                        .dst_binding(0)
-                        .dst_set(descriptors.attachments.sky_descriptor_set)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .image_info(&[vk::DescriptorImageInfoBuilder::new()
-                            .image_view(fbs.main_pass_color.view)
-                            .sampler(descriptors.attachments.sampler)
-                            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]),
-                    vk::WriteDescriptorSetBuilder::new()
-                        .dst_binding(1)
-                        .dst_set(descriptors.attachments.sky_descriptor_set)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .image_info(&[vk::DescriptorImageInfoBuilder::new()
-                            .image_view(fbs.depth.view)
-                            .sampler(descriptors.attachments.sampler)
-                            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]), */
-                    vk::WriteDescriptorSetBuilder::new()
-                        .dst_binding(0)
-                        .dst_set(descriptors.attachments.luma_descriptor_set)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .image_info(&[vk::DescriptorImageInfoBuilder::new()
-                            .image_view(fbs.main_pass_color.view)
-                            .sampler(descriptors.attachments.sampler)
-                            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]),
-                ],
-                &[],
-            );
-        }
+        let attachments = &descriptors.attachments;
+        DescriptorWriter::new()
+            .sampled_image(attachments.fxaa_descriptor_set, 0, fbs.main_pass_color.view, attachments.sampler)
+            .sampled_image(attachments.fxaa_descriptor_set, 1, fbs.luma.view, attachments.sampler)
+            .buffer(attachments.fxaa_descriptor_set, 2, vk::DescriptorType::UNIFORM_BUFFER, attachments.fxaa_ubo_buf.handle, vk::WHOLE_SIZE)
+            /* .sampled_image(attachments.sky_descriptor_set, 0, fbs.main_pass_color.view, attachments.sampler)
+            .sampled_image(attachments.sky_descriptor_set, 1, fbs.depth.view, attachments.sampler) */
+            .sampled_image(attachments.luma_descriptor_set, 0, fbs.main_pass_color.view, attachments.sampler)
+            .write(&vk.device);
         Ok(())
     }
 
@@ -1,15 +1,16 @@
-use std::fmt::Display;
+use std::{ffi::c_void, fmt::Display};
 
 use erupt::vk;
 use smallvec::SmallVec;
-use vkcore::{Device, RenderPass, Validation, VkContext};
+use vkcore::{pipeline::Pipeline, DisplayMode, Device, RenderPass, Validation, VkContext};
 use winit::window::Window;
 
 use crate::states::game::camera::Camera;
 
 use super::{
-    descriptor_sets::DescriptorSets, framebuffers::FramebufferImages, pipelines::Pipelines,
-    render_passes::RenderPasses, ui_renderer::UiRenderer,
+    debug_lines::DebugLines, descriptor_sets::DescriptorSets, frame_graph::FrameGraph, framebuffers::FramebufferImages,
+    gpu_culling::GpuCulling, map_texture::MapTexture, pipelines::Pipelines, render_passes::RenderPasses,
+    ui_renderer::UiRenderer,
 };
 
 pub const FRAMES_IN_FLIGHT: u32 = 2;
@@ -21,6 +22,7 @@ pub struct RendererState {
     pub render_passes: RenderPasses,
     pub pipelines: Pipelines,
     pub framebuffers: FramebufferImages,
+    pub frame_graph: FrameGraph,
 }
 
 pub enum Clear {
@@ -92,11 +94,69 @@ impl RenderContext {
             device.cmd_end_render_pass(self.commands);
         }
     }
+
+    // Records a compute dispatch; outside of render passes only. Descriptor sets bind from
+    // set 0, push constants go at offset 0. See vkcore::pipeline::group_count().
+    pub fn dispatch(
+        &self,
+        device: &Device,
+        pipeline: &Pipeline,
+        descriptor_sets: &[vk::DescriptorSet],
+        push_constants: &[u8],
+        groups: [u32; 3],
+    ) {
+        unsafe {
+            device.cmd_bind_pipeline(self.commands, vk::PipelineBindPoint::COMPUTE, pipeline.handle);
+            if !descriptor_sets.is_empty() {
+                device.cmd_bind_descriptor_sets(
+                    self.commands,
+                    vk::PipelineBindPoint::COMPUTE,
+                    pipeline.layout,
+                    0,
+                    descriptor_sets,
+                    &[],
+                );
+            }
+            if !push_constants.is_empty() {
+                device.cmd_push_constants(
+                    self.commands,
+                    pipeline.layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    push_constants.len() as u32,
+                    push_constants.as_ptr() as *const c_void,
+                );
+            }
+            device.cmd_dispatch(self.commands, groups[0], groups[1], groups[2]);
+        }
+    }
+
+    // Makes what earlier dispatches wrote visible to `dst_stage`: DRAW_INDIRECT for indirect
+    // draw commands, VERTEX_INPUT for vertex data, FRAGMENT_SHADER for sampling...
+    pub fn compute_barrier(&self, device: &Device, dst_stage: vk::PipelineStageFlags, dst_access: vk::AccessFlags) {
+        let barrier = vk::MemoryBarrierBuilder::new()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(dst_access);
+        unsafe {
+            device.cmd_pipeline_barrier(
+                self.commands,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[barrier],
+                &[],
+                &[],
+            );
+        }
+    }
 }
 
 pub struct Renderer {
     pub vk: VkContext,
     pub ui: UiRenderer,
+    pub debug_lines: DebugLines,
+    pub gpu_culling: GpuCulling,
+    pub map_texture: MapTexture,
     pub state: RendererState,
     frame: usize,
 }
@@ -187,6 +247,11 @@ impl Renderer {
                 println!("Check queue_present_khr! {}", e);
             }
         }
+
+        // Used from the next frame on, which waits for the uploads in `start_frame()`
+        if let Err(e) = vk.uploader.process_queue(&vk.device) {
+            eprintln!("Failed to upload queued data: {e}");
+        }
         self.frame += 1; // Increment frame counter
     }
 
@@ -212,6 +277,27 @@ impl Renderer {
 
         Ok(())
     }
+
+    // See Textures::set_anisotropy()
+    pub fn set_anisotropy(&mut self, anisotropy: f32) -> anyhow::Result<()> {
+        let vk = &mut self.vk;
+        unsafe { vk.device.device_wait_idle() }.unwrap(); // Fails if device lost or OOM
+        self.state.descriptors.textures.set_anisotropy(&vk.device, anisotropy)
+    }
+
+    // See Textures::set_resource_pack()
+    pub fn set_resource_pack(&mut self, pack: Option<&str>) -> anyhow::Result<()> {
+        let vk = &mut self.vk;
+        unsafe { vk.device.device_wait_idle() }.unwrap(); // Fails if device lost or OOM
+        self.state.descriptors.textures.set_resource_pack(&vk.device, &mut vk.uploader, &mut vk.allocator, pack)
+    }
+
+    // See Textures::set_texture_array()
+    pub fn set_texture_array(&mut self, pack: Option<&str>, bytes: &[u8]) -> anyhow::Result<()> {
+        let vk = &mut self.vk;
+        unsafe { vk.device.device_wait_idle() }.unwrap(); // Fails if device lost or OOM
+        self.state.descriptors.textures.set_texture_array(&vk.device, &mut vk.uploader, &mut vk.allocator, pack, bytes)
+    }
 }
 
 impl Renderer {
@@ -232,6 +318,10 @@ impl Renderer {
             Pipelines::init(vk, &self.state.render_passes, &self.state.descriptors).unwrap();
         // TODO unwrap()
 
+        self.gpu_culling
+            .handle_window_resize(vk, &self.state.descriptors, &self.state.render_passes.terrain)
+            .unwrap(); // TODO unwrap()
+
         UiRenderer::handle_window_resize(&mut self.ui, vk);
     }
 }
@@ -246,6 +336,18 @@ impl Renderer {
             eprintln!("Error destroying UI renderer: {e}");
         }
 
+        if let Err(e) = self.debug_lines.destroy_self(&mut self.vk) {
+            eprintln!("Error destroying debug line renderer: {e}");
+        }
+
+        if let Err(e) = self.gpu_culling.destroy_self(&mut self.vk) {
+            eprintln!("Error destroying GPU culling: {e}");
+        }
+
+        if let Err(e) = self.map_texture.destroy_self(&mut self.vk) {
+            eprintln!("Error destroying the map texture: {e}");
+        }
+
         self.state.pipelines.destroy_self(&self.vk.device);
         self.state.render_passes.destroy_self(&self.vk.device);
 
@@ -271,32 +373,49 @@ impl Renderer {
     }
 }
 
-pub fn init(window: &Window, camera: &Camera) -> anyhow::Result<Renderer> {
+// `gpu`: see VkConfig::gpu. `anisotropy`: see Textures::set_anisotropy(). Hdr10/ScRgb
+// `display_mode`s are used only if the display supports them.
+pub fn init(
+    window: &Window,
+    camera: &Camera,
+    gpu: Option<&str>,
+    anisotropy: f32,
+    display_mode: DisplayMode,
+) -> anyhow::Result<Renderer> {
     let mut vk = vkcore::VkContext::new(
         window,
         vkcore::VkConfig {
             present_mode: PRESENT_MODE,
+            display_mode,
             validation: VALIDATION,
             frames_in_flight: FRAMES_IN_FLIGHT,
+            gpu,
             ..Default::default()
         },
     )?;
 
-    let mut descriptors = DescriptorSets::create(&mut vk)?;
+    let mut descriptors = DescriptorSets::create(&mut vk, anisotropy)?;
     let framebuffers = FramebufferImages::init(&mut vk)?;
     let render_passes = RenderPasses::init(&mut vk, &mut descriptors, &framebuffers)?;
     let pipelines = Pipelines::init(&mut vk, &render_passes, &descriptors)?;
 
     let ui = UiRenderer::create(&mut vk, &descriptors, camera)?;
+    let debug_lines = DebugLines::create(&mut vk)?;
+    let gpu_culling = GpuCulling::create(&mut vk, &mut descriptors, &render_passes.terrain)?;
+    let map_texture = MapTexture::create(&mut vk, &descriptors)?;
 
     Ok(Renderer {
         vk,
         ui,
+        debug_lines,
+        gpu_culling,
+        map_texture,
         state: RendererState {
             descriptors,
             framebuffers,
             pipelines,
             render_passes,
+            frame_graph: FrameGraph::game(),
         },
         frame: 0,
     })
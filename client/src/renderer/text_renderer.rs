@@ -4,17 +4,27 @@ use bytemuck::{Pod, Zeroable};
 use erupt::vk;
 
 use anyhow::Result;
-use glam::Mat4;
+use glam::{Mat4, Vec3};
 use smallvec::SmallVec;
-use vkcore::{Buffer, BufferAllocation, Device, UsageFlags, VkContext};
+use vkcore::{Buffer, BufferAllocation, DescriptorWriter, Device, MemoryTag, UsageFlags, VkContext};
 
 use super::{
     descriptor_sets::DescriptorSets,
     pipelines::Pipelines,
     renderer::{RenderContext, FRAMES_IN_FLIGHT},
+    wrappers::quad_indices,
 };
 
 const DEFAULT_TEXT_COLOR: TextColor = TextColor::from_rgba(0xFF, 0xFF, 0xFF, 0xFF);
+// Per frame; the most the 9-bit index in the shader can address
+const MAX_TRANSFORMS: usize = 512;
+// Per frame; the size of the glyph array in text.vert. Raising it means rebuilding the shader.
+const MAX_GLYPHS: usize = 8191;
+// The per-frame buffers start out this big and double as needed, up to the maximums
+const INITIAL_GLYPHS: usize = 1023; // + 1 for the scale, see `do_uploads()`
+const INITIAL_TRANSFORMS: usize = 64;
+// The index buffer covers this many glyphs; longer runs are drawn in several batches
+const GLYPHS_PER_DRAW: u32 = 4096;
 
 #[derive(Clone, Copy)]
 pub enum Align {
@@ -29,6 +39,37 @@ impl Default for Align {
     }
 }
 
+// Drawn behind 2D text for readability over bright backgrounds
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextEffect {
+    None,
+    Shadow,
+    Outline,
+}
+
+impl TextEffect {
+    pub const ALL: [TextEffect; 3] = [TextEffect::None, TextEffect::Shadow, TextEffect::Outline];
+
+    const COLOR: TextColor = TextColor::from_rgba(0x10, 0x10, 0x10, 0xC0);
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TextEffect::None => "none",
+            TextEffect::Shadow => "shadow",
+            TextEffect::Outline => "outline",
+        }
+    }
+
+    // In screen pixels; 3 is the scale, so these are one font pixel
+    fn offsets(self) -> &'static [(i32, i32)] {
+        match self {
+            TextEffect::None => &[],
+            TextEffect::Shadow => &[(3, -3)],
+            TextEffect::Outline => &[(-3, 0), (3, 0), (0, -3), (0, 3)],
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct Style<'a> {
     pub align: Align,
@@ -159,6 +200,17 @@ struct GlyphData {
 pub struct PerFrameBuffers {
     glyphs: Buffer,
     transforms: Buffer,
+    descriptor_set: vk::DescriptorSet, // points at the two above
+}
+
+// How close to the limits text has come, for the debug HUD
+#[derive(Clone, Copy, Default)]
+pub struct TextStats {
+    pub peak_glyphs: usize,
+    pub peak_transforms: usize,
+    pub max_glyphs: usize,
+    pub max_transforms: usize,
+    pub dropped_glyphs: u64, // in total, for lack of room
 }
 
 pub struct RenderResources {
@@ -199,6 +251,11 @@ pub struct TextRenderer {
     proj_view: Mat4,
 
     glyphs: Box<[GlyphData; 256]>,
+    effect: TextEffect,
+
+    stats: TextStats,
+    dropped_this_frame: usize,
+    was_overflowing: bool, // so that the warning isn't printed every frame
 }
 
 // Public interface
@@ -241,6 +298,14 @@ impl TextRenderer {
         };
     }
 
+    pub fn set_effect(&mut self, effect: TextEffect) {
+        self.effect = effect;
+    }
+
+    pub fn stats(&self) -> TextStats {
+        self.stats
+    }
+
     /// (x, y) in in pixels. Returns text width, also in pixels.
     pub fn draw_2d(&mut self, str: &str, x: u16, y: u16, style: Style) -> (u16, u16) {
         if str.is_empty() {
@@ -296,9 +361,83 @@ impl TextRenderer {
                 vert.d1 = vert.d1.wrapping_sub(x_offset); // wrong
             }
         }
+        self.add_effect(start_idx);
+        self.drop_overflow();
         (x as u16, y as u16)
     }
 
+    /// Text placed in the world, e.g. nameplates. `model` maps from the text's own space, where
+    /// (0, 0) is the bottom left of the first glyph and a unit is a pixel of 2D text, to world
+    /// space. Text effects aren't applied. Returns false if there was no room for the text.
+    pub fn draw_3d(&mut self, str: &str, model: Mat4, color: TextColor) -> bool {
+        // The shader has only 7 bits for the x offset of 3D glyphs, and none for the
+        // baseline, so every glyph gets a transform of its own
+        let glyph_count = str.chars()
+            .filter(|&c| c != ' ' && self.glyphs[c as usize & 0xFF].char == c as u32)
+            .count();
+        if self.transform_buffer.len() + glyph_count > MAX_TRANSFORMS
+            || self.text_buffer.len() - 1 + glyph_count > MAX_GLYPHS
+        {
+            self.dropped_this_frame += glyph_count;
+            return false;
+        }
+
+        let transform = self.proj_view * model;
+        let mut x = 0;
+        for char in str.chars() {
+            let glyph = self.glyphs[char as usize & 0xFF];
+            if glyph.char != char as u32 {
+                continue;
+            }
+
+            if char != ' ' {
+                let base = ((glyph.base_and_dims >> 7) & 7) as f32 * 3.0 - 2.0 * 3.0;
+                let idx = self.transform_buffer.len() as u32;
+                self.transform_buffer.push(TextTransform(
+                    transform * Mat4::from_translation(Vec3::new(x as f32, base, 0.0)),
+                ));
+                self.text_buffer.push(GlyphVertex {
+                    d1: (1 << 31) | ((glyph.layer as u32) << 24) | (idx << 7),
+                    d2: (color.0 << 11) | (glyph.base_and_dims as u32),
+                });
+            }
+
+            x += glyph.advance as u32 * 3;
+        }
+        true
+    }
+
+    // Copies of the glyphs from `start_idx` onwards, offset and recolored, inserted
+    // before them so that they're drawn underneath
+    fn add_effect(&mut self, start_idx: usize) {
+        let offsets = self.effect.offsets();
+        if offsets.is_empty() {
+            return;
+        }
+
+        let glyphs: SmallVec<[GlyphVertex; 64]> = self.text_buffer[start_idx..].iter().copied().collect();
+        let copies = offsets.iter().flat_map(|&(dx, dy)| {
+            glyphs.iter().map(move |glyph| {
+                let x = ((glyph.d1 & 0xFFF) as i32 + dx).clamp(0, 0xFFF) as u32;
+                let y = (((glyph.d1 >> 12) & 0xFFF) as i32 + dy).clamp(0, 0xFFF) as u32;
+                GlyphVertex {
+                    d1: (glyph.d1 & 0xFF00_0000) | (y << 12) | x,
+                    d2: (TextEffect::COLOR.0 << 11) | (glyph.d2 & 0x7FF),
+                }
+            })
+        });
+        self.text_buffer.splice(start_idx..start_idx, copies);
+    }
+
+    // Cuts off whatever doesn't fit in the shader's glyph array, which would otherwise be drawn
+    // as garbage or not at all. Slot 0 is the scale, see `do_uploads()`.
+    fn drop_overflow(&mut self) {
+        if self.text_buffer.len() > MAX_GLYPHS + 1 {
+            self.dropped_this_frame += self.text_buffer.len() - (MAX_GLYPHS + 1);
+            self.text_buffer.truncate(MAX_GLYPHS + 1);
+        }
+    }
+
     pub fn compute_glyph_idx_at_pos(&self, str: &str, pos_px: u16) -> usize {
         self.compute_glyph_idx_at_pos_chars(str.chars(), pos_px)
     }
@@ -408,6 +547,20 @@ impl TextRenderer {
     ) -> anyhow::Result<()> {
         // -1 because first glyph is at index 1, because index 0 is for the scale...
         let num_glyphs = renderer.text_buffer.len() - 1;
+        let num_transforms = renderer.transform_buffer.len();
+        let stats = &mut renderer.stats;
+        stats.peak_glyphs = stats.peak_glyphs.max(num_glyphs);
+        stats.peak_transforms = stats.peak_transforms.max(num_transforms);
+        stats.dropped_glyphs += renderer.dropped_this_frame as u64;
+        let overflowing = renderer.dropped_this_frame > 0;
+        if overflowing && !renderer.was_overflowing {
+            eprintln!(
+                "  WARN  Too much text on screen, dropped {} glyphs (at most {MAX_GLYPHS} glyphs and {MAX_TRANSFORMS} 3D glyphs fit)",
+                renderer.dropped_this_frame
+            );
+        }
+        renderer.was_overflowing = overflowing;
+        renderer.dropped_this_frame = 0;
         if num_glyphs == 0 {
             return Ok(());
         }
@@ -422,6 +575,27 @@ impl TextRenderer {
             extent: size,
         };
 
+        let buffers = &mut renderer.rendering.buffers[frame];
+        let glyph_bytes = renderer.text_buffer.len() * std::mem::size_of::<GlyphVertex>();
+        let transform_bytes = num_transforms * std::mem::size_of::<TextTransform>();
+        if buffers.glyphs.size < glyph_bytes as u64 || buffers.transforms.size < transform_bytes as u64 {
+            let glyph_slots = (buffers.glyphs.size as usize / std::mem::size_of::<GlyphVertex>())
+                .max(renderer.text_buffer.len().next_power_of_two())
+                .min(MAX_GLYPHS + 1);
+            let transforms = (buffers.transforms.size as usize / std::mem::size_of::<TextTransform>())
+                .max(num_transforms.next_power_of_two())
+                .min(MAX_TRANSFORMS);
+            println!(
+                "[text_renderer.rs] Buffers too small, reallocating! {} glyphs, {} transforms",
+                glyph_slots - 1,
+                transforms
+            );
+            let descriptor_set = buffers.descriptor_set;
+            vk.allocator.deallocate_buffer(&mut buffers.glyphs, &vk.device)?;
+            vk.allocator.deallocate_buffer(&mut buffers.transforms, &vk.device)?;
+            *buffers = allocate_frame_buffers(vk, descriptor_set, glyph_slots - 1, transforms)?;
+        }
+
         let device = &vk.device;
         let uploader = &mut vk.uploader;
 
@@ -489,7 +663,7 @@ impl TextRenderer {
             // a scissor *must* be set before drawing.
             // Under Description at https://www.khronos.org/registry/vulkan/specs/1.3-extensions/man/html/VkDynamicState.html
 
-            let mut first_index = 0;
+            let mut first_glyph = 0;
             for scissor in renderer.scissors.drain(..) {
                 device.cmd_set_scissor(
                     ctx.commands,
@@ -498,15 +672,20 @@ impl TextRenderer {
                         .offset(scissor.area.offset)
                         .extent(scissor.area.extent)],
                 );
-                device.cmd_draw_indexed(
-                    ctx.commands,
-                    scissor.glyph_count * 6,
-                    1,
-                    first_index * 6,
-                    0,
-                    0,
-                );
-                first_index += scissor.glyph_count;
+                // The vertex offset moves the index buffer's glyphs along, 4 vertices each
+                let end = first_glyph + scissor.glyph_count;
+                while first_glyph < end {
+                    let count = (end - first_glyph).min(GLYPHS_PER_DRAW);
+                    device.cmd_draw_indexed(
+                        ctx.commands,
+                        count * 6,
+                        1,
+                        0,
+                        (first_glyph * 4) as i32,
+                        0,
+                    );
+                    first_glyph += count;
+                }
             }
         }
     }
@@ -544,8 +723,7 @@ fn init_text_renderer(
         d2: (1.0 / ws.height as f32).to_bits(),
     });
 
-    let mut transform_buffer = Vec::new();
-    transform_buffer.resize(128, TextTransform::default());
+    let transform_buffer = Vec::with_capacity(INITIAL_TRANSFORMS);
 
     let mut buffers = [(); FRAMES_IN_FLIGHT as usize].map(|_| Default::default());
     for (i, dset) in descriptors
@@ -555,65 +733,21 @@ fn init_text_renderer(
         .copied()
         .enumerate()
     {
-        let glyphs = vk.allocator.allocate_buffer(
-            &vk.device,
-            &BufferAllocation {
-                size: 65536, // 65536 / 8 = 8192 glyphs
-                usage: UsageFlags::UPLOAD,
-                vk_usage: vk::BufferUsageFlags::STORAGE_BUFFER,
-            },
-        )?;
-        let transforms = vk.allocator.allocate_buffer(
-            &vk.device,
-            &BufferAllocation {
-                size: 32768, // 32768 / 16 = 2048 transforms
-                usage: UsageFlags::UPLOAD,
-                vk_usage: vk::BufferUsageFlags::UNIFORM_BUFFER,
-            },
-        )?;
-
-        unsafe {
-            vk.device.update_descriptor_sets(
-                &[
-                    vk::WriteDescriptorSetBuilder::new()
-                        .dst_binding(0)
-                        .dst_set(dset)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                        .buffer_info(&[vk::DescriptorBufferInfoBuilder::new()
-                            .range(65536)
-                            .buffer(glyphs.handle)
-                            .offset(0)]),
-                    vk::WriteDescriptorSetBuilder::new()
-                        .dst_binding(1)
-                        .dst_set(dset)
-                        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                        .buffer_info(&[vk::DescriptorBufferInfoBuilder::new()
-                            .range(32768)
-                            .buffer(transforms.handle)
-                            .offset(0)]),
-                ],
-                &[],
-            );
-        }
-
-        buffers[i] = PerFrameBuffers { glyphs, transforms };
+        buffers[i] = allocate_frame_buffers(vk, dset, INITIAL_GLYPHS, INITIAL_TRANSFORMS)?;
     }
 
     let mut index_buffer = vk.allocator.allocate_buffer(
         &vk.device,
         &BufferAllocation {
-            size: 4096 * 6 * 2, // max glyphs*indices per glyph*sizeof(u16)
+            size: GLYPHS_PER_DRAW as usize * 6 * 2, // glyphs*indices per glyph*sizeof(u16)
             usage: UsageFlags::FAST_DEVICE_ACCESS,
             vk_usage: vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            tag: MemoryTag::Text,
         },
     )?;
 
-    let mut ibo_contents: Vec<u16> = Vec::with_capacity(4096 * 6);
-    // 0,1,2  1,2,3  4,5,6  5,6,7 ...
-    for i in 0..4096 * 6 as u16 {
-        let value = if i % 6 > 2 { i - 2 } else { i };
-        ibo_contents.push(value - i / 6 * 2);
-    }
+    // 0,1,2  2,1,3  4,5,6  6,5,7 ...
+    let ibo_contents = quad_indices(GLYPHS_PER_DRAW).map(|i| i as u16).collect::<Vec<u16>>();
     let ibo_content_bytes: &[u8] = bytemuck::cast_slice(&ibo_contents);
     vk.uploader
         .upload_bytes_to_buffer(&vk.device, ibo_content_bytes, &mut index_buffer, 0)?;
@@ -635,8 +769,57 @@ fn init_text_renderer(
 
         viewport_size: vk.swapchain.surface.extent,
         proj_view,
+        effect: TextEffect::None,
 
         glyphs,
+
+        stats: TextStats {
+            max_glyphs: MAX_GLYPHS,
+            max_transforms: MAX_TRANSFORMS,
+            ..Default::default()
+        },
+        dropped_this_frame: 0,
+        was_overflowing: false,
+    })
+}
+
+// Room for `glyphs` glyphs (and the scale) and `transforms` transforms, bound to `dset`
+fn allocate_frame_buffers(
+    vk: &mut VkContext,
+    dset: vk::DescriptorSet,
+    glyphs: usize,
+    transforms: usize,
+) -> Result<PerFrameBuffers> {
+    let glyph_bytes = (glyphs + 1) * std::mem::size_of::<GlyphVertex>();
+    let transform_bytes = transforms * std::mem::size_of::<TextTransform>();
+    let glyph_buffer = vk.allocator.allocate_buffer(
+        &vk.device,
+        &BufferAllocation {
+            size: glyph_bytes,
+            usage: UsageFlags::UPLOAD,
+            vk_usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+            tag: MemoryTag::Text,
+        },
+    )?;
+    let transform_buffer = vk.allocator.allocate_buffer(
+        &vk.device,
+        &BufferAllocation {
+            size: transform_bytes,
+            usage: UsageFlags::UPLOAD,
+            vk_usage: vk::BufferUsageFlags::UNIFORM_BUFFER,
+            tag: MemoryTag::Text,
+        },
+    )?;
+
+    DescriptorWriter::new()
+        .buffer(dset, 0, vk::DescriptorType::STORAGE_BUFFER, glyph_buffer.handle, glyph_bytes as u64)
+        .buffer(dset, 1, vk::DescriptorType::UNIFORM_BUFFER, transform_buffer.handle, transform_bytes as u64)
+        .write(&vk.device);
+
+    Ok(PerFrameBuffers {
+        glyphs: glyph_buffer,
+        transforms: transform_buffer,
+        descriptor_set: dset,
     })
 }
 
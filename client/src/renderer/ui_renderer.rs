@@ -2,7 +2,7 @@ use std::ffi::c_void;
 
 use erupt::vk::{self, BufferUsageFlags};
 use glam::{IVec2, Vec2, Vec4};
-use vkcore::{Buffer, Device, UsageFlags, VkContext};
+use vkcore::{Buffer, Device, MemoryTag, UsageFlags, VkContext};
 
 use crate::states::game::camera::Camera;
 
@@ -14,6 +14,49 @@ use super::{
     text_renderer::{ColorRange, Style, TextColor, TextRenderer},
 };
 
+// A part of one layer of the block texture array
+#[derive(Clone, Copy)]
+pub struct TextureRegion {
+    pub layer: u8,
+    pub uv_min: Vec2, // at the bottom left corner of the rect
+    pub uv_max: Vec2,
+}
+
+impl TextureRegion {
+    pub fn whole(layer: u8) -> Self {
+        Self { layer, uv_min: Vec2::new(0.0, 1.0), uv_max: Vec2::new(1.0, 0.0) }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RectStyle {
+    pub color: u32, // RGBA, multiplies the texture if there is one
+    pub corner_radius: u8,
+    pub border_width: u8,
+    pub border_color: u32,
+    pub texture: Option<TextureRegion>,
+}
+
+impl RectStyle {
+    pub const fn solid(color: u32) -> Self {
+        Self {
+            color,
+            corner_radius: 0,
+            border_width: 0,
+            border_color: 0,
+            texture: None,
+        }
+    }
+
+    pub const fn rounded(color: u32, corner_radius: u8) -> Self {
+        Self { corner_radius, ..Self::solid(color) }
+    }
+
+    pub const fn with_border(self, border_width: u8, border_color: u32) -> Self {
+        Self { border_width, border_color, ..self }
+    }
+}
+
 pub struct UiRenderer {
     vertices: Vec<UiVertex>,
     buffer: Buffer,
@@ -32,9 +75,10 @@ impl UiRenderer {
         let buffer = vk.allocator.allocate_buffer(
             &vk.device,
             &vkcore::BufferAllocation {
-                size: 8192, // 1024 vertices
+                size: 1024 * std::mem::size_of::<UiVertex>(),
                 usage: UsageFlags::UPLOAD,
                 vk_usage: BufferUsageFlags::VERTEX_BUFFER,
+                tag: MemoryTag::Ui,
             },
         )?;
 
@@ -78,15 +122,61 @@ impl UiRenderer {
         self.vertices.extend_from_slice(vertices);
     }
 
-    pub fn draw_rect_xy_wh(&mut self, (x, y): (u16, u16), (w, h): (u16, u16), color: u32) {
-        let color = color.to_be();
+    pub fn draw_rect_xy_wh(&mut self, pos: (u16, u16), size: (u16, u16), color: u32) {
+        self.draw_rect_styled(pos, size, &RectStyle::solid(color));
+    }
+
+    pub fn draw_rect_styled(&mut self, pos: (u16, u16), size: (u16, u16), style: &RectStyle) {
+        let flags = if style.texture.is_some() { UiVertex::TEXTURED } else { 0 };
+        self.draw_rect_with_flags(pos, size, style, flags);
+    }
+
+    // A part of the map texture (see MapTexture), as is. `uv_min` is at the bottom left
+    // corner of the rect, like in TextureRegion.
+    pub fn draw_map_rect(&mut self, pos: (u16, u16), size: (u16, u16), uv_min: Vec2, uv_max: Vec2) {
+        let style = RectStyle {
+            texture: Some(TextureRegion { layer: 0, uv_min, uv_max }),
+            ..RectStyle::solid(0xFF_FF_FF_FF)
+        };
+        self.draw_rect_with_flags(pos, size, &style, UiVertex::MAP);
+    }
+
+    fn draw_rect_with_flags(&mut self, (x, y): (u16, u16), (w, h): (u16, u16), style: &RectStyle, flags: u8) {
+        let radius = style.corner_radius.min((w.min(h) / 2).min(255) as u8);
+        let (uv_min, uv_max, layer) = match style.texture {
+            Some(region) => (region.uv_min, region.uv_max, region.layer),
+            None => (Vec2::ZERO, Vec2::ZERO, 0),
+        };
+        let to_unorm = |uv: f32| (uv.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16;
+        let corner = |right: bool, top: bool| {
+            let uv = Vec2::new(
+                if right { uv_max.x } else { uv_min.x },
+                if top { uv_max.y } else { uv_min.y },
+            );
+            UiVertex {
+                x: if right { x + w } else { x },
+                y: if top { y + h } else { y },
+                color: style.color.to_be(),
+                u: to_unorm(uv.x),
+                v: to_unorm(uv.y),
+                local_x: if right { w } else { 0 },
+                local_y: if top { h } else { 0 },
+                width: w,
+                height: h,
+                corner_radius: radius,
+                border_width: style.border_width,
+                layer,
+                flags,
+                border_color: style.border_color.to_be(),
+            }
+        };
         self.draw(&[
-            UiVertex::color(x, y, color),
-            UiVertex::color(x, y + h, color),
-            UiVertex::color(x + w, y, color),
-            UiVertex::color(x + w, y, color),
-            UiVertex::color(x, y + h, color),
-            UiVertex::color(x + w, y + h, color),
+            corner(false, false),
+            corner(false, true),
+            corner(true, false),
+            corner(true, false),
+            corner(false, true),
+            corner(true, true),
         ]);
     }
 
@@ -98,11 +188,7 @@ impl UiRenderer {
             let color = color.as_uvec4();
             let color = (color.x << 24) | (color.y << 16) | (color.z << 8) | color.w;
 
-            self.vertices.push(UiVertex {
-                x: pos.x as u16,
-                y: pos.y as u16,
-                color_or_uv: color,
-            });
+            self.vertices.push(UiVertex::color(pos.x as u16, pos.y as u16, color));
         }
     }
 
@@ -139,6 +225,7 @@ impl UiRenderer {
                     size: vertices.capacity() * std::mem::size_of::<UiVertex>(),
                     usage: UsageFlags::UPLOAD,
                     vk_usage: BufferUsageFlags::VERTEX_BUFFER,
+                    tag: MemoryTag::Ui,
                 },
             )?;
         }
@@ -179,6 +266,14 @@ impl UiRenderer {
                 pvm_ptr,
             );
 
+            device.cmd_bind_descriptor_sets(
+                commands,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipelines.ui.shapes.layout,
+                0,
+                &[descriptors.textures.descriptor_set],
+                &[],
+            );
             device.cmd_bind_vertex_buffers(commands, 0, &[renderer.buffer.handle], &[0]);
             device.cmd_draw(commands, renderer.num_verts_to_draw, 1, 0, 0);
         }
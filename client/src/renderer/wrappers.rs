@@ -1,12 +1,117 @@
 use erupt::vk;
-use vkcore::Buffer;
+use vkcore::{Buffer, BufferAllocation, Device, MemoryTag, UsageFlags, VkContext};
+
+use super::passes::terrain_pass::Vertex;
 
 pub struct IndexBuffer {
     pub buffer: Buffer,
     pub index_type: vk::IndexType,
+    pub index_count: u32,
 }
 
 pub struct VertexBuffer {
     pub buffer: Buffer,
     pub vertex_count: u32,
 }
+
+pub struct IndexedMesh {
+    pub vertices: VertexBuffer,
+    pub indices: IndexBuffer,
+}
+
+// Two triangles per quad, corners in strip order: 0 1 2, 2 1 3. Both keep the first one's winding.
+pub const QUAD_INDICES: [u32; 6] = [0, 1, 2, 2, 1, 3];
+
+// Indices for `quads` quads of 4 consecutive vertices each
+pub fn quad_indices(quads: u32) -> impl Iterator<Item = u32> {
+    (0..quads).flat_map(|quad| QUAD_INDICES.map(|i| quad * 4 + i))
+}
+
+// 16-bit if every index fits, which halves the index memory
+pub fn index_type_for(indices: &[u32]) -> vk::IndexType {
+    match indices.iter().all(|&i| i <= u16::MAX as u32) {
+        true => vk::IndexType::UINT16,
+        false => vk::IndexType::UINT32,
+    }
+}
+
+impl IndexBuffer {
+    pub fn null() -> Self {
+        Self { buffer: Buffer::null(), index_type: vk::IndexType::UINT16, index_count: 0 }
+    }
+
+    // Stored as 16-bit indices where they fit, see index_type_for()
+    pub fn create(vk: &mut VkContext, indices: &[u32], tag: MemoryTag) -> anyhow::Result<Self> {
+        let index_type = index_type_for(indices);
+        let narrow: Vec<u16>;
+        let bytes: &[u8] = match index_type {
+            vk::IndexType::UINT16 => {
+                narrow = indices.iter().map(|&i| i as u16).collect();
+                bytemuck::cast_slice(&narrow)
+            }
+            _ => bytemuck::cast_slice(indices),
+        };
+
+        let mut buffer = vk.allocator.allocate_buffer(
+            &vk.device,
+            &BufferAllocation {
+                size: bytes.len().max(1),
+                usage: UsageFlags::FAST_DEVICE_ACCESS,
+                vk_usage: vk::BufferUsageFlags::INDEX_BUFFER,
+                tag,
+            },
+        )?;
+        vk.uploader.upload_bytes_to_buffer(&vk.device, bytes, &mut buffer, 0)?;
+
+        Ok(Self { buffer, index_type, index_count: indices.len() as u32 })
+    }
+}
+
+impl VertexBuffer {
+    pub fn null() -> Self {
+        Self { buffer: Buffer::null(), vertex_count: 0 }
+    }
+
+    pub fn create(vk: &mut VkContext, vertices: &[Vertex], tag: MemoryTag) -> anyhow::Result<Self> {
+        let mut buffer = vk.allocator.allocate_buffer(
+            &vk.device,
+            &BufferAllocation {
+                size: std::mem::size_of_val(vertices).max(1),
+                usage: UsageFlags::FAST_DEVICE_ACCESS,
+                vk_usage: vk::BufferUsageFlags::VERTEX_BUFFER,
+                tag,
+            },
+        )?;
+        vk.uploader.upload_to_buffer(&vk.device, vertices, &mut buffer, 0)?;
+
+        Ok(Self { buffer, vertex_count: vertices.len() as u32 })
+    }
+}
+
+impl IndexedMesh {
+    pub fn null() -> Self {
+        Self { vertices: VertexBuffer::null(), indices: IndexBuffer::null() }
+    }
+
+    pub fn create(vk: &mut VkContext, vertices: &[Vertex], indices: &[u32], tag: MemoryTag) -> anyhow::Result<Self> {
+        Ok(Self {
+            vertices: VertexBuffer::create(vk, vertices, tag)?,
+            indices: IndexBuffer::create(vk, indices, tag)?,
+        })
+    }
+
+    // With a pipeline taking terrain_pass::Vertex bound
+    pub fn draw(&self, device: &Device, commands: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_bind_vertex_buffers(commands, 0, &[self.vertices.buffer.handle], &[0]);
+            device.cmd_bind_index_buffer(commands, self.indices.buffer.handle, 0, self.indices.index_type);
+            device.cmd_draw_indexed(commands, self.indices.index_count, 1, 0, 0, 0);
+        }
+    }
+
+    pub fn destroy_self(&mut self, vk: &mut VkContext) -> anyhow::Result<()> {
+        vk.allocator.deallocate_buffer(&mut self.vertices.buffer, &vk.device)?;
+        vk.allocator.deallocate_buffer(&mut self.indices.buffer, &vk.device)?;
+        Ok(())
+    }
+}
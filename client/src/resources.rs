@@ -2,9 +2,7 @@
 // Should preferably be imported from here for consistency and convenience,
 // although in practice there is no difference.
 
-use rayon::ThreadPool;
-
-use crate::renderer::renderer::Renderer;
+use crate::{args::Args, bench::Bench, cursor::Cursor, jobs::Jobs, localization::Localization, renderer::renderer::Renderer, profiles::Profiles, settings::Settings, theme::Theme, toasts::Toasts};
 
 // The main resources struct contains resources shared between
 // all states (main menu, settings, game...)
@@ -13,11 +11,19 @@ pub struct Resources {
     pub window_handle: winit::window::Window,
     pub window_size: core::WindowSize,
 
-    pub thread_pool: ThreadPool,
+    pub jobs: Jobs,
 
     pub metrics: metrics::Resources,
     pub renderer: Renderer,
     pub input: input::Resources,
+    pub lang: Localization,
+    pub settings: Settings,
+    pub profiles: Profiles,
+    pub theme: Theme,
+    pub toasts: Toasts,
+    pub cursor: Cursor,
+    pub bench: Option<Bench>, // --bench
+    pub args: Args,
 }
 
 pub mod core {
@@ -47,6 +53,8 @@ pub mod metrics {
     pub struct Resources {
         pub frame_count: u32,
         pub frame_time: FrameTime,
+        // Some while in game if Settings::local_analytics is on
+        pub session: Option<crate::analytics::Session>,
     }
 }
 
@@ -74,9 +82,11 @@ pub mod game_state {
         player::ThePlayer,
         states::game::camera::Camera,
         states::game::input_recorder::InputRecorder,
+        states::game::interaction::InteractionTarget,
         world::{
             chunk_renderer::ChunkRenderer,
             dimension::{Chunks, ECS},
+            minimap::Minimap,
         },
     };
 
@@ -87,8 +97,10 @@ pub mod game_state {
         pub net: Net,
         pub entities: ECS,
         pub chunks: Chunks,
+        pub minimap: Minimap,
         pub the_player: ThePlayer,
         pub input_recorder: InputRecorder,
+        pub interaction_target: InteractionTarget,
 
         pub chunk_renderer: ChunkRenderer,
     }
@@ -99,5 +111,14 @@ pub mod game_state {
         pub network_tick_count: u32,
         pub next_network_tick: f32,
         pub nid_to_entity_mapping: Vec<(NetworkId, Entity)>,
+        // Ids removed lately, with when. Messages about them are expected for a while after
+        // dropping everything for a resync, so they aren't errors.
+        pub tombstones: Vec<(NetworkId, std::time::Instant)>,
+        // When AuthorityMsg::Resync was last sent, until an EntityChecksum matches
+        pub resync_requested: Option<std::time::Instant>,
+        // Supported by the server. The client supports all of them, see network_thread::CLIENT_FEATURES.
+        pub features: shared::protocol::Features,
+        // The one the player is in. Entering another one replaces `Resources::chunks`.
+        pub dimension: shared::dimension::DimensionId,
     }
 }
@@ -0,0 +1,305 @@
+use std::{fmt::Write, fs, io};
+
+use vkcore::DisplayMode;
+
+use crate::{renderer::text_renderer::TextEffect, theme::Theme};
+
+const SETTINGS_PATH: &str = "config/settings.txt";
+// Each resource pack is a directory here, with a packed.bin made by tools/texpack
+pub const RESOURCE_PACK_DIR: &str = "resourcepacks";
+
+// Player-adjustable settings, edited in the settings screen and saved as
+// `key = value` lines. Missing or unrecognized values keep their defaults.
+pub struct Settings {
+    pub graphics: Graphics,
+    pub accessibility: Accessibility,
+    // Whether performance summaries are written to a local file, see analytics
+    pub local_analytics: bool,
+    // Keys set by the profile picked on the connect screen, see Profile::apply(). save()
+    // leaves them as they are in the file.
+    pub overridden: Vec<String>,
+}
+
+pub struct Graphics {
+    // Applied to the final image, see fxaa.frag. Above 1 brightens dark areas.
+    pub gamma: f32,
+    // None for the built-in textures
+    pub resource_pack: Option<String>,
+    // None to pick automatically, see VkConfig::gpu. Only read at startup.
+    pub gpu: Option<String>,
+    // Anisotropic filtering of block textures, 1 for none. The device may support less.
+    pub anisotropy: u8,
+    // A layer of the block texture array to draw as the crosshair, None for the plain cross.
+    // Only read at startup.
+    pub crosshair_texture: Option<u8>,
+    // Most memory loaded chunks may take, in MiB, 0 for no limit. Beyond it, chunks out of
+    // view are unloaded even within the view distance. Only read when joining a server.
+    pub chunk_memory_mb: u32,
+    // Vertical field of view in degrees, before sprinting or zooming changes it
+    pub fov: u8,
+    // Hdr10/ScRgb need an HDR display, and fall back to Sdr without one. Only read at startup.
+    pub display_mode: DisplayMode,
+}
+
+impl Graphics {
+    pub const MIN_GAMMA: f32 = 0.5;
+    pub const MAX_GAMMA: f32 = 2.5;
+    pub const ANISOTROPY_LEVELS: [u8; 5] = [1, 2, 4, 8, 16];
+    pub const MIN_FOV: u8 = 50;
+    pub const MAX_FOV: u8 = 110;
+}
+
+impl Default for Graphics {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            resource_pack: None,
+            gpu: None,
+            anisotropy: 1,
+            crosshair_texture: None,
+            chunk_memory_mb: 256,
+            fov: 80,
+            display_mode: DisplayMode::Sdr,
+        }
+    }
+}
+
+pub struct Accessibility {
+    pub palette: Palette,
+    pub chat_background_opacity: u8, // 0..=255
+    pub text_effect: TextEffect,
+}
+
+impl Accessibility {
+    pub fn chat_background(&self, theme: &Theme) -> u32 {
+        (theme.chat_background & 0xFF_FF_FF_00) | self.chat_background_opacity as u32
+    }
+}
+
+impl Default for Accessibility {
+    fn default() -> Self {
+        Self {
+            palette: Palette::Default,
+            chat_background_opacity: 0x50,
+            text_effect: TextEffect::None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Palette {
+    Default,
+    Deuteranopia, // also works for protanopia
+    Tritanopia,
+    HighContrast,
+}
+
+// Colors that carry meaning, RGBA
+pub struct UiColors {
+    pub text: u32,
+    pub menu_text: u32,
+    pub error: u32,
+    pub good: u32,
+    pub fair: u32,
+    pub poor: u32,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 4] = [
+        Palette::Default,
+        Palette::Deuteranopia,
+        Palette::Tritanopia,
+        Palette::HighContrast,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Default => "default",
+            Palette::Deuteranopia => "deuteranopia",
+            Palette::Tritanopia => "tritanopia",
+            Palette::HighContrast => "high_contrast",
+        }
+    }
+
+    pub fn colors(self) -> &'static UiColors {
+        match self {
+            Palette::Default => &UiColors {
+                text: 0xFF_FF_FF_FF,
+                menu_text: 0xA7_A4_BF_FF,
+                error: 0xDC_32_3C_FF,
+                good: 0x40_D0_40_FF,
+                fair: 0xE0_C0_30_FF,
+                poor: 0xE0_40_30_FF,
+            },
+            // Red and green are easily confused, so blue/orange (Okabe-Ito colors) instead
+            Palette::Deuteranopia => &UiColors {
+                text: 0xFF_FF_FF_FF,
+                menu_text: 0xA7_A4_BF_FF,
+                error: 0xE6_9F_00_FF,
+                good: 0x56_B4_E9_FF,
+                fair: 0xF0_E4_42_FF,
+                poor: 0xE6_9F_00_FF,
+            },
+            // Blue and yellow are easily confused, so teal/pink/red
+            Palette::Tritanopia => &UiColors {
+                text: 0xFF_FF_FF_FF,
+                menu_text: 0xA7_A4_BF_FF,
+                error: 0xD5_5E_00_FF,
+                good: 0x00_9E_73_FF,
+                fair: 0xCC_79_A7_FF,
+                poor: 0xD5_5E_00_FF,
+            },
+            Palette::HighContrast => &UiColors {
+                text: 0xFF_FF_FF_FF,
+                menu_text: 0xFF_FF_FF_FF,
+                error: 0xFF_50_50_FF,
+                good: 0x00_FF_FF_FF,
+                fair: 0xFF_FF_00_FF,
+                poor: 0xFF_00_FF_FF,
+            },
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|palette| palette.name() == name)
+    }
+}
+
+const DISPLAY_MODE_NAMES: [(DisplayMode, &str); 3] = [
+    (DisplayMode::Sdr, "sdr"),
+    (DisplayMode::Hdr10, "hdr10"),
+    (DisplayMode::ScRgb, "scrgb"),
+];
+
+fn display_mode_name(mode: DisplayMode) -> &'static str {
+    DISPLAY_MODE_NAMES.iter().find(|&&(m, _)| m == mode).map_or("sdr", |&(_, name)| name)
+}
+
+fn display_mode_from_name(name: &str) -> Option<DisplayMode> {
+    DISPLAY_MODE_NAMES.iter().find(|&&(_, n)| n == name).map(|&(mode, _)| mode)
+}
+
+fn text_effect_from_name(name: &str) -> Option<TextEffect> {
+    TextEffect::ALL.into_iter().find(|effect| effect.name() == name)
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        let mut settings = Self {
+            graphics: Graphics::default(),
+            accessibility: Accessibility::default(),
+            local_analytics: false,
+            overridden: Vec::new(),
+        };
+
+        let text = match fs::read_to_string(SETTINGS_PATH) {
+            Ok(text) => text,
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    eprintln!("Failed to read {SETTINGS_PATH}: {e}");
+                }
+                return settings;
+            }
+        };
+
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            if !settings.apply(key.trim(), value.trim()) {
+                eprintln!("{SETTINGS_PATH}: ignoring '{}'", line.trim());
+            }
+        }
+        settings
+    }
+
+    // A `key = value` line of the settings file. Returns false if either isn't recognized.
+    pub fn apply(&mut self, key: &str, value: &str) -> bool {
+        let (g, a) = (&mut self.graphics, &mut self.accessibility);
+        match key {
+            "gamma" => value
+                .parse::<f32>()
+                .map(|gamma| g.gamma = gamma.clamp(Graphics::MIN_GAMMA, Graphics::MAX_GAMMA))
+                .is_ok(),
+            "resource_pack" => {
+                g.resource_pack = (!value.is_empty()).then(|| value.to_owned());
+                true
+            }
+            "anisotropy" => value
+                .parse::<u8>()
+                .ok()
+                .filter(|level| Graphics::ANISOTROPY_LEVELS.contains(level))
+                .map(|level| g.anisotropy = level)
+                .is_some(),
+            "gpu" => {
+                g.gpu = (!value.is_empty()).then(|| value.to_owned());
+                true
+            }
+            "crosshair_texture" if value.is_empty() => {
+                g.crosshair_texture = None;
+                true
+            }
+            "crosshair_texture" => value.parse().map(|layer| g.crosshair_texture = Some(layer)).is_ok(),
+            "chunk_memory_mb" => value.parse().map(|mb| g.chunk_memory_mb = mb).is_ok(),
+            "fov" => value
+                .parse::<u8>()
+                .map(|fov| g.fov = fov.clamp(Graphics::MIN_FOV, Graphics::MAX_FOV))
+                .is_ok(),
+            "display_mode" => display_mode_from_name(value).map(|mode| g.display_mode = mode).is_some(),
+            "palette" => Palette::from_name(value).map(|p| a.palette = p).is_some(),
+            "chat_background_opacity" => value.parse().map(|o| a.chat_background_opacity = o).is_ok(),
+            "text_effect" => text_effect_from_name(value).map(|e| a.text_effect = e).is_some(),
+            "local_analytics" => value.parse().map(|on| self.local_analytics = on).is_ok(),
+            _ => false,
+        }
+    }
+
+    pub fn save(&self) {
+        let a = &self.accessibility;
+        let mut out = String::new();
+        let _ = writeln!(out, "gamma = {:.1}", self.graphics.gamma);
+        let _ = writeln!(out, "resource_pack = {}", self.graphics.resource_pack.as_deref().unwrap_or(""));
+        let _ = writeln!(out, "anisotropy = {}", self.graphics.anisotropy);
+        let _ = writeln!(out, "gpu = {}", self.graphics.gpu.as_deref().unwrap_or(""));
+        let crosshair = self.graphics.crosshair_texture.map(|layer| layer.to_string());
+        let _ = writeln!(out, "crosshair_texture = {}", crosshair.as_deref().unwrap_or(""));
+        let _ = writeln!(out, "chunk_memory_mb = {}", self.graphics.chunk_memory_mb);
+        let _ = writeln!(out, "fov = {}", self.graphics.fov);
+        let _ = writeln!(out, "display_mode = {}", display_mode_name(self.graphics.display_mode));
+        let _ = writeln!(out, "palette = {}", a.palette.name());
+        let _ = writeln!(out, "chat_background_opacity = {}", a.chat_background_opacity);
+        let _ = writeln!(out, "text_effect = {}", a.text_effect.name());
+        let _ = writeln!(out, "local_analytics = {}", self.local_analytics);
+
+        if !self.overridden.is_empty() {
+            // What the profile set isn't the player's own choice, so keep the file's lines
+            let old = fs::read_to_string(SETTINGS_PATH).unwrap_or_default();
+            let key_of = |line: &str| line.split_once('=').map(|(key, _)| key.trim().to_owned());
+            let overridden = |line: &str| key_of(line).map_or(false, |key| self.overridden.contains(&key));
+            let kept = old.lines().filter(|line| overridden(line)).collect::<Vec<_>>();
+            out = out
+                .lines()
+                .filter(|line| !overridden(line))
+                .chain(kept)
+                .fold(String::new(), |out, line| out + line + "\n");
+        }
+
+        if let Err(e) = fs::create_dir_all("config").and_then(|_| fs::write(SETTINGS_PATH, out)) {
+            eprintln!("Failed to save {SETTINGS_PATH}: {e}");
+        }
+    }
+}
+
+// Names of the resource packs in RESOURCE_PACK_DIR, sorted
+pub fn resource_packs() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(RESOURCE_PACK_DIR) else {
+        return Vec::new();
+    };
+    let mut packs = entries
+        .flatten()
+        .filter(|entry| entry.path().join("packed.bin").is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect::<Vec<_>>();
+    packs.sort();
+    packs
+}
@@ -9,18 +9,24 @@ use winit::{
 use crate::{
     game::{State, StateChange},
     input::{Key, self},
+    localization::Localization,
     renderer::{
         renderer::{Clear, OutdatedSwapchain, RendererState},
         text_renderer::TextColor,
         ui_renderer::UiRenderer,
     },
     resources::Resources,
+    theme::Theme,
+    tr,
 };
 
+use crate::networking::DisconnectReason;
+
 use super::username_query::UsernameQueryState;
 
 pub struct ConnectionLostState {
     hovered: bool,
+    reason: DisconnectReason,
 }
 
 impl State for ConnectionLostState {
@@ -33,6 +39,7 @@ impl State for ConnectionLostState {
 
         let _ = res.window_handle.set_cursor_grab(CursorGrabMode::None);
         res.window_handle.set_cursor_visible(true);
+        res.window_handle.set_fullscreen(None);
         res.window_handle.set_maximized(false);
         res.window_handle.set_inner_size(LogicalSize::new(400, 480));
         res.window_handle
@@ -59,7 +66,11 @@ impl State for ConnectionLostState {
             ))));
         }
 
-        self.draw_ui(&mut renderer.ui, wsize, self.hovered);
+        if self.hovered {
+            res.cursor.request(CursorIcon::Hand);
+        }
+        let text = TextColor::from_rgba32(res.settings.accessibility.palette.colors().menu_text);
+        self.draw_ui(&mut renderer.ui, &res.lang, &res.theme, text, wsize, self.hovered);
 
         if let Err(e) = self.render(res) {
             eprintln!("WARN: render() Err: {e}");
@@ -69,7 +80,6 @@ impl State for ConnectionLostState {
     }
 
     fn on_exit(&mut self, res: &mut crate::resources::Resources) -> anyhow::Result<()> {
-        res.window_handle.set_cursor_icon(CursorIcon::Default);
         res.input.keyboard.clear_all();
         Ok(())
     }
@@ -92,14 +102,7 @@ impl State for ConnectionLostState {
                     (position.x as u16, wsize.1.saturating_sub(position.y as u16)),
                 );
 
-                if hover != self.hovered {
-                    self.hovered = hover;
-                    if hover {
-                        res.window_handle.set_cursor_icon(CursorIcon::Hand);
-                    } else {
-                        res.window_handle.set_cursor_icon(CursorIcon::Default);
-                    }
-                }
+                self.hovered = hover;
             }
             Event::WindowEvent {
                 event: WindowEvent::MouseInput { state, button, .. },
@@ -124,54 +127,68 @@ impl State for ConnectionLostState {
 }
 
 impl ConnectionLostState {
-    fn draw_ui(&mut self, ui: &mut UiRenderer, win_size: (u16, u16), hover: bool) {
+    fn draw_ui(
+        &mut self,
+        ui: &mut UiRenderer,
+        lang: &Localization,
+        theme: &Theme,
+        text: TextColor,
+        win_size: (u16, u16),
+        hover: bool,
+    ) {
         let (w, h) = win_size;
         let (x1, y1) = (0, 0);
         let (x2, y2) = (w - 48, h - 48);
 
-        const TEXT: TextColor = TextColor::from_rgba32(0xa7a4bfFF);
-        const SELECTED: u32 = 0x4c4964FF;
-        const HOVERED: u32 = 0x5d5b7aFF;
-
         // (Outline, fill)
-        let mut colors = (SELECTED, SELECTED);
+        let mut colors = (theme.menu_selected, theme.menu_selected);
         if hover {
-            colors = (HOVERED, SELECTED);
+            colors = (theme.menu_hovered, theme.menu_selected);
         }
 
         // 4 corners
-        ui.draw_rect_xy_wh((x1, y1), (48, 48), 0x4c4964FF);
-        ui.draw_rect_xy_wh((x1 + 16, y1 + 16), (16, 16), 0x28263cFF);
+        ui.draw_rect_xy_wh((x1, y1), (48, 48), theme.menu_selected);
+        ui.draw_rect_xy_wh((x1 + 16, y1 + 16), (16, 16), theme.menu_background);
 
-        ui.draw_rect_xy_wh((x1, y2), (48, 48), 0x4c4964FF);
-        ui.draw_rect_xy_wh((x1 + 16, y2 + 16), (16, 16), 0x28263cFF);
+        ui.draw_rect_xy_wh((x1, y2), (48, 48), theme.menu_selected);
+        ui.draw_rect_xy_wh((x1 + 16, y2 + 16), (16, 16), theme.menu_background);
 
-        ui.draw_rect_xy_wh((x2, y1), (48, 48), 0x4c4964FF);
-        ui.draw_rect_xy_wh((x2 + 16, y1 + 16), (16, 16), 0x28263cFF);
+        ui.draw_rect_xy_wh((x2, y1), (48, 48), theme.menu_selected);
+        ui.draw_rect_xy_wh((x2 + 16, y1 + 16), (16, 16), theme.menu_background);
 
-        ui.draw_rect_xy_wh((x2, y2), (48, 48), 0x4c4964FF);
-        ui.draw_rect_xy_wh((x2 + 16, y2 + 16), (16, 16), 0x28263cFF);
+        ui.draw_rect_xy_wh((x2, y2), (48, 48), theme.menu_selected);
+        ui.draw_rect_xy_wh((x2 + 16, y2 + 16), (16, 16), theme.menu_background);
 
         // Edges
-        ui.draw_rect_xy_wh((x1 + 64, y1), (x2 - x1 - 80, 32), 0x3c3a53FF);
-        ui.draw_rect_xy_wh((x1 + 64, y2 + 16), (x2 - x1 - 80, 32), 0x3c3a53FF);
-        ui.draw_rect_xy_wh((x1, y1 + 64), (32, y2 - y1 - 80), 0x3c3a53FF);
-        ui.draw_rect_xy_wh((x2 + 16, y1 + 64), (32, y2 - y1 - 80), 0x3c3a53FF);
-
-        ui.draw_rect_xy_wh((x1 + 80, y1), (x2 - x1 - 112, 16), 0x28263cFF);
-        ui.draw_rect_xy_wh((x1 + 80, y2 + 32), (x2 - x1 - 112, 16), 0x28263cFF);
-        ui.draw_rect_xy_wh((x1, y1 + 80), (16, y2 - y1 - 112), 0x28263cFF);
-        ui.draw_rect_xy_wh((x2 + 32, y1 + 80), (16, y2 - y1 - 112), 0x28263cFF);
-
-        ui.draw_text("Connection lost", w / 2 - 195 / 2, h / 2 + 30);
+        ui.draw_rect_xy_wh((x1 + 64, y1), (x2 - x1 - 80, 32), theme.menu_unselected);
+        ui.draw_rect_xy_wh((x1 + 64, y2 + 16), (x2 - x1 - 80, 32), theme.menu_unselected);
+        ui.draw_rect_xy_wh((x1, y1 + 64), (32, y2 - y1 - 80), theme.menu_unselected);
+        ui.draw_rect_xy_wh((x2 + 16, y1 + 64), (32, y2 - y1 - 80), theme.menu_unselected);
+
+        ui.draw_rect_xy_wh((x1 + 80, y1), (x2 - x1 - 112, 16), theme.menu_background);
+        ui.draw_rect_xy_wh((x1 + 80, y2 + 32), (x2 - x1 - 112, 16), theme.menu_background);
+        ui.draw_rect_xy_wh((x1, y1 + 80), (16, y2 - y1 - 112), theme.menu_background);
+        ui.draw_rect_xy_wh((x2 + 32, y1 + 80), (16, y2 - y1 - 112), theme.menu_background);
+
+        let title = match &self.reason {
+            DisconnectReason::Kicked(reason) => tr!(lang, "connection_lost.kicked", reason = reason),
+            DisconnectReason::ServerClosed => tr!(lang, "connection_lost.server_closed").to_owned(),
+            DisconnectReason::ProtocolError => tr!(lang, "connection_lost.protocol_error").to_owned(),
+            DisconnectReason::Unknown => tr!(lang, "connection_lost.title").to_owned(),
+        };
+        let title = title.as_str();
+        let title_w = ui.text().compute_width(title);
+        ui.draw_text(title, w / 2 - title_w / 2, h / 2 + 30);
 
         // Join button
-        ui.draw_text_colored("Ok", w / 2 - 33 / 2, h / 2 - 45 + 15, TEXT);
+        let label = tr!(lang, "connection_lost.ok");
+        let label_w = ui.text().compute_width(label);
+        ui.draw_text_colored(label, w / 2 - label_w / 2, h / 2 - 45 + 15, text);
         ui.draw_rect_xy_wh((w / 2 - 86 / 2, h / 2 - 45), (86, 49), colors.0);
         ui.draw_rect_xy_wh(
             (w / 2 - 86 / 2 + 2, h / 2 + 2 - 45),
             (86 - 4, 49 - 4),
-            0x28263cFF,
+            theme.menu_background,
         );
         ui.draw_rect_xy_wh(
             (w / 2 - 86 / 2 + 4, h / 2 + 4 - 45),
@@ -236,7 +253,7 @@ impl ConnectionLostState {
 
 // Initialization
 impl ConnectionLostState {
-    pub fn new() -> Self {
-        Self { hovered: false }
+    pub fn new(reason: DisconnectReason) -> Self {
+        Self { hovered: false, reason }
     }
 }
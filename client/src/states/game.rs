@@ -1,73 +1,165 @@
+pub mod adaptive_distance;
+pub mod block_breaking;
 pub mod camera;
+pub mod camera_path;
+pub mod chunk_budget;
+pub mod clock_sync;
+pub mod connection_quality;
+pub mod debug_render;
+pub mod entity_lod;
 pub mod input_recorder;
+pub mod interaction;
+pub mod loading_screen;
+pub mod map_view;
+pub mod nameplates;
+pub mod packet_inspector;
+pub mod pause_menu;
+pub mod view_model;
 
-use std::{f32::consts::PI, ffi::c_void, time::Instant};
+use std::{ffi::c_void, time::{Duration, Instant}};
 
-use erupt::vk::{self, BufferUsageFlags};
+use erupt::vk;
 use flexstr::{SharedStr, ToLocalStr};
-use glam::{vec2, EulerRot, Mat4, Vec2, Vec3};
+use glam::{vec2, IVec3, Mat4, Vec2, Vec3};
 use hecs::Entity;
 use shared::{
-    jitter_prevention::{JitterPrevention, DELAY_MS},
-    protocol::NetworkId,
+    coords::ChunkPos,
+    dimension::DimensionId,
+    jitter_prevention::JitterPrevention,
+    movement::{self, MovementFlags},
+    prediction::InputSnapshot,
+    protocol::{Features, NetworkId, c2s::{AuthorityMsg, BlockAction}, s2c::{self, MetadataKey, MetadataValue}},
 };
-use vkcore::{Buffer, BufferAllocation, UsageFlags, VkContext};
+use vkcore::{MemoryTag, UploadPriority, VkContext};
 use winit::{
-    dpi::LogicalPosition,
-    event::{DeviceEvent, ElementState, Event, KeyboardInput, WindowEvent},
-    window::CursorGrabMode,
+    dpi::{LogicalPosition, LogicalSize},
+    event::{DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, WindowEvent},
+    window::{CursorGrabMode, Fullscreen},
 };
 
 use crate::{
-    chat::Chat,
+    analytics::{FrameStats, Session},
+    args::WindowMode,
+    bench::FrameRow,
+    chat::{self, Chat},
+    cursor::CrosshairTarget,
     components::{
-        HeadRotation, OldHeadRotation, OldPosition, Position
+        HeadRotation, OldHeadRotation, OldPosition, Position, Username, Crouching, Skin, Sprinting, Afk, DroppedItem, Owned
     },
-    game::{State, StateChange},
+    game::{State, StateChange, schedule::{Schedule, Stage}},
     input::{self, Key},
-    networking::{Connection, S2C, LoginResponse, EntityStateMsg},
-    player::ThePlayer,
+    networking::{Connection, S2C, LoginResponse, EntityStateMsg, ChatFlags},
+    platform,
+    player::{ThePlayer, DOUBLE_TAP_SECS, SNEAK_CAMERA_DROP, SPRINT_FOV_SCALE, ZOOM_FOV_SCALE},
     renderer::{
-        passes::terrain_pass::Vertex,
+        debug_lines::DebugLines,
+        frame_graph::Pass,
+        passes::terrain_pass::{TerrainDrawMode, Vertex},
         renderer::Clear,
         text_renderer::TextColor,
         ui_renderer::UiRenderer,
-        wrappers::VertexBuffer,
+        wrappers::{quad_indices, IndexedMesh},
     },
     resources::{
         core::{Time, WindowSize},
         game_state, Resources,
     },
+    theme::Theme,
+    toasts::{Toast, ToastIcon},
+    tr,
     world::{
+        block::Block,
         chunk_renderer::ChunkRenderer,
-        dimension::{Chunks, ECS}, chunk::WorldBlockPosExt,
+        dimension::{Chunks, ECS},
+        minimap::Minimap,
     },
 };
 
 use self::{
+    adaptive_distance::AdaptiveDistance,
+    block_breaking::{BlockBreaking, BreakEvent},
     camera::Camera,
-    input_recorder::{InputRecorder, YawPitch, InputSnapshot},
+    camera_path::{CameraPath, CameraPaths},
+    chunk_budget::ChunkBudget,
+    clock_sync::ClockSync,
+    connection_quality::{ConnectionQuality, Quality},
+    debug_render::DebugRender,
+    entity_lod::{EntityCulling, EntityLod},
+    input_recorder::{InputRecorder, YawPitch},
+    interaction::{EntityKind, InteractionTarget},
+    loading_screen::LoadingScreen,
+    map_view::MapView,
+    nameplates::Nameplate,
+    packet_inspector::PacketInspector,
+    pause_menu::{PauseAction, PauseMenu},
+    view_model::ViewModel,
 };
 
-use super::connection_lost::ConnectionLostState;
+use super::{connection_lost::ConnectionLostState, username_query::UsernameQueryState};
+
+// Range the adaptive view distance may pick from, in chunks
+const MIN_RENDER_DISTANCE: u32 = 6;
+const MAX_RENDER_DISTANCE: u32 = 24;
+const TARGET_FPS: f32 = 60.0;
+
+// From the eyes, for placing and breaking blocks
+const REACH: f32 = 6.0;
+
+// How long removed entity ids are remembered, see game_state::Net::tombstones
+const TOMBSTONE_TIMEOUT: Duration = Duration::from_secs(5);
+// Before asking for another resync if the checksums still don't match. A few checksum
+// intervals, so that those sent before the server got the request don't count.
+const RESYNC_TIMEOUT: Duration = Duration::from_secs(10);
+
+// F3 + this prints the GPU memory allocation report to stdout
+const MEMORY_REPORT_KEY: Key = Key::V;
 
 pub struct GameState {
     pub res: game_state::Resources,
 
+    // Everything on_update() does before rendering
+    schedule: Schedule<GameState>,
+
     jitter_buf: JitterPrevention<Box<[EntityStateMsg]>>,
 
     _artificial_delay: JitterPrevention<Box<[InputSnapshot]>>,
 
     is_network_tick: bool,
+    server: String,
+    // Whether the window has focus; mentions in chat flash the taskbar when it doesn't
+    focused: bool,
     packets_lost: u32,
     packets_sent: u32,
     ping: u32,
+    bytes_sent: u64,
+    bytes_received: u64,
+    connection_quality: ConnectionQuality,
+    clock_sync: ClockSync,
 
     // Raw mouse motion; for camera only
     mouse_move_accumulator: Vec2,
 
-    grid_vbo: VertexBuffer,
-    cube_vbo: VertexBuffer,
+    debug_render: DebugRender,
+    packet_inspector: PacketInspector,
+    map_view: MapView,
+    adaptive_distance: AdaptiveDistance,
+    view_model: ViewModel,
+    block_breaking: BlockBreaking,
+    // Some until the spawn area is ready; the player has no control until then
+    loading: Option<LoadingScreen>,
+    pause_menu: Option<PauseMenu>,
+    // Flythroughs with `/path`; the HUD is hidden and the player has no control while one plays
+    camera_paths: CameraPaths,
+    entity_culling: EntityCulling,
+    chunk_budget: ChunkBudget,
+    // Of the block texture array; see Textures::layers
+    texture_layers: u32,
+
+    // Draws of the last frame, not counting the UI and debug lines
+    draw_calls: u32,
+
+    grid: IndexedMesh,
+    cube: IndexedMesh,
 }
 
 impl State for GameState {
@@ -81,55 +173,80 @@ impl State for GameState {
             .unwrap()
             .size()
             .to_logical::<u32>(res.window_handle.scale_factor());
+        let size = match res.args.window_mode {
+            WindowMode::Maximized => {
+                res.window_handle.set_inner_size(size);
+                res.window_handle.set_maximized(true);
+                size
+            }
+            WindowMode::Windowed { width, height } => {
+                let size = LogicalSize::new(width, height);
+                res.window_handle.set_inner_size(size);
+                size
+            }
+            WindowMode::Fullscreen => {
+                res.window_handle.set_fullscreen(Some(Fullscreen::Borderless(None)));
+                size
+            }
+        };
         println!("Window size: {size:?}");
-        res.window_handle.set_inner_size(size);
-        res.window_handle.set_maximized(true);
         res.window_handle
             .set_cursor_position(LogicalPosition::new(size.width / 2, size.height / 2))?;
         res.window_handle
             .set_cursor_grab(CursorGrabMode::Confined)?;
         res.window_handle.set_cursor_visible(false);
+        platform::set_server_title(&res.window_handle, &self.server, self.ping);
         println!("Entering GameState");
 
-        self.grid_vbo = create_debug_grid(&mut res.renderer.vk)?;
+        self.grid = create_debug_grid(&mut res.renderer.vk)?;
         res.renderer
             .vk
             .uploader
             .flush_staged(&res.renderer.vk.device)?;
 
-        self.cube_vbo = create_debug_cube(&mut res.renderer.vk)?;
+        self.cube = create_debug_cube(&mut res.renderer.vk)?;
 
+        if res.settings.local_analytics {
+            res.metrics.session = Some(Session::new(&res.renderer.vk.device));
+        }
         Ok(())
     }
 
     fn on_update(&mut self, res: &mut Resources) -> Option<Box<StateChange>> {
         self.is_network_tick = false;
-        self.do_player_movement(res);
-        self.update_net(res);
-        if self.res.net.connection.closed() {
-            return Some(Box::new(StateChange::SwitchTo(Box::new(
-                ConnectionLostState::new(),
-            ))));
-        }
-        self.update_camera(res);
 
-        if let Err(e) = self.res.chunks.tick(res) {
-            eprintln!("Error in Chunks::tick(): {e}");
-            return Some(Box::new(StateChange::Exit));
+        // Systems need all of `self`, so temporarily move the schedule out
+        let schedule = std::mem::take(&mut self.schedule);
+        let change = schedule.run(self, res);
+        self.schedule = schedule;
+        if change.is_some() {
+            return change;
         }
 
-        self.draw_debug_hud(res);
-
         if let Err(e) = self.render(res) {
             eprintln!("render() error: {e}");
         }
-        None
+        self.record_analytics_frame(res);
+        self.record_bench_frame(res)
     }
 
     fn on_exit(&mut self, res: &mut Resources) -> anyhow::Result<()> {
         println!("Exiting GameState");
         self.res.net.connection.send_disconnect();
+        platform::reset_title(&res.window_handle);
         res.input.keyboard.clear_all();
+
+        // Turning it off in the pause menu discards the session too
+        if let Some(session) = res.metrics.session.take() && res.settings.local_analytics {
+            if let Err(e) = session.save() {
+                eprintln!("Failed to save the performance summary: {e:#}");
+            }
+        }
+
+        let vk = &mut res.renderer.vk;
+        unsafe { vk.device.device_wait_idle() }.result()?;
+        self.grid.destroy_self(vk)?;
+        self.cube.destroy_self(vk)?;
         Ok(())
     }
 
@@ -164,6 +281,7 @@ impl State for GameState {
                 self.res.camera.on_window_resize(res.window_size.xy);
             }
             WindowEvent::Focused(focus_gained) => {
+                self.focused = *focus_gained;
                 if !focus_gained {
                     self.open_chat(res);
                 }
@@ -188,7 +306,24 @@ impl State for GameState {
                     },
                 ..
             } => {
-                return Some(Box::new(StateChange::Exit));
+                // Consumed here, so that the menu doesn't see the same press
+                res.input.keyboard.release(Key::Escape);
+                if self.camera_paths.is_playing() {
+                    self.camera_paths.stop();
+                    return None;
+                }
+                let action = match &mut self.pause_menu {
+                    Some(menu) => menu.back(res),
+                    None => {
+                        self.open_pause_menu(res);
+                        None
+                    }
+                };
+                return action.and_then(|action| self.on_pause_action(action, res));
+            }
+            WindowEvent::MouseInput { state: ElementState::Pressed, button, .. } => {
+                let action = self.pause_menu.as_mut()?.on_click(*button, res)?;
+                return self.on_pause_action(action, res);
             }
             _ => {}
         }
@@ -196,6 +331,182 @@ impl State for GameState {
     }
 }
 
+// Systems
+impl GameState {
+    fn build_schedule() -> Schedule<GameState> {
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Input, |state, res| state.update_pause_menu(res))
+            .add_system(Stage::Input, |state, res| { state.run_local_commands(res); None })
+            .add_system(Stage::Input, |state, res| { state.do_player_movement(res); None })
+            .add_system(Stage::Input, |state, res| { state.handle_debug_keys(res); None })
+            .add_system(Stage::Input, |state, res| { state.handle_map_input(res); None })
+            .add_system(Stage::Input, |state, _| { state.update_interaction_target(); None })
+            .add_system(Stage::Input, |state, res| { state.place_block(res); None })
+            .add_system(Stage::Input, |state, res| { state.break_block(res); None })
+            .add_system(Stage::Input, |state, res| { state.interact(res); None })
+            .add_system(Stage::NetIn, |state, res| { state.update_net(res); None })
+            .add_system(Stage::NetIn, |state, _| state.check_connection())
+            .add_system(Stage::NetIn, |state, res| { state.warn_connection_quality(res); None })
+            .add_system(Stage::Simulate, |state, res| state.update_loading(res))
+            .add_system(Stage::Simulate, |state, res| { state.update_camera(res); None })
+            .add_system(Stage::Simulate, |state, res| { state.update_view_distance(res); None })
+            .add_system(Stage::Simulate, |state, res| { state.update_view_model(res); None })
+            .add_system(Stage::Simulate, |state, res| { state.update_chunk_budget(res); None })
+            .add_system(Stage::Simulate, |state, res| state.tick_chunks(res))
+            .add_system(Stage::Simulate, |state, _| { state.res.minimap.update(&mut state.res.chunks); None })
+            .add_system(Stage::NetOut, |state, _| { state.send_player_state(); None })
+            .add_system(Stage::RenderPrep, |state, res| { state.update_entity_culling(res); None })
+            .add_system(Stage::RenderPrep, |state, res| { state.check_texture_layers(res); None })
+            .add_system(Stage::RenderPrep, |state, res| { state.draw_debug_lines(res); None })
+            .add_system(Stage::RenderPrep, |state, res| { state.draw_block_breaking(res); None })
+            .add_system(Stage::RenderPrep, |state, res| { state.draw_nameplates(res); None })
+            .add_system(Stage::RenderPrep, |state, res| { state.draw_map(res); None })
+            .add_system(Stage::RenderPrep, |state, res| { state.draw_connection_icon(res); None })
+            .add_system(Stage::RenderPrep, |state, res| { state.draw_interaction_hint(res); None })
+            .add_system(Stage::RenderPrep, |state, res| { state.draw_debug_hud(res); None })
+            .add_system(Stage::RenderPrep, |state, res| { state.draw_packet_inspector(res); None })
+            .add_system(Stage::RenderPrep, |state, res| { state.draw_loading_screen(res); None })
+            .add_system(Stage::RenderPrep, |state, res| { state.draw_pause_menu(res); None });
+        schedule
+    }
+
+    fn check_connection(&mut self) -> Option<Box<StateChange>> {
+        if self.res.net.connection.closed() {
+            return Some(Box::new(StateChange::SwitchTo(Box::new(
+                ConnectionLostState::new(self.res.net.connection.disconnect_reason()),
+            ))));
+        }
+        None
+    }
+
+    fn record_analytics_frame(&mut self, res: &mut Resources) {
+        if self.loading.is_some() {
+            return;
+        }
+        let Some(session) = &mut res.metrics.session else {
+            return;
+        };
+        session.record_frame(&FrameStats {
+            frame_ms: res.time.dt_secs * 1000.0,
+            view_distance: self.res.chunks.view_distance(),
+            chunks_loaded: self.res.chunks.loaded(),
+            chunk_memory_bytes: self.res.chunks.memory_bytes(),
+            chunks_generated: self.res.chunks.chunks_generated(),
+        });
+    }
+
+    // Ends the game once the benchmark (if any) is done
+    fn record_bench_frame(&mut self, res: &mut Resources) -> Option<Box<StateChange>> {
+        if self.loading.is_some() {
+            return None;
+        }
+        let bench = res.bench.as_mut()?;
+        let row = FrameRow {
+            frame_ms: res.time.dt_secs * 1000.0,
+            draw_calls: self.draw_calls,
+            chunks_generated: self.res.chunks.chunks_generated(),
+            view_distance: self.res.chunks.view_distance(),
+            bytes_sent: self.bytes_sent,
+            bytes_received: self.bytes_received,
+            ping_ms: self.ping,
+        };
+        let path_done = bench.camera_path.is_some() && !self.camera_paths.is_playing();
+        if bench.record(res.time.secs_f32, &row) && !path_done {
+            return None;
+        }
+        Some(Box::new(StateChange::Exit))
+    }
+
+    fn update_pause_menu(&mut self, res: &mut Resources) -> Option<Box<StateChange>> {
+        let action = self.pause_menu.as_mut()?.update(res)?;
+        self.on_pause_action(action, res)
+    }
+
+    fn on_pause_action(&mut self, action: PauseAction, res: &mut Resources) -> Option<Box<StateChange>> {
+        match action {
+            PauseAction::Resume => {
+                self.close_pause_menu(res);
+                None
+            }
+            PauseAction::Disconnect => match UsernameQueryState::new() {
+                Ok(menu) => Some(Box::new(StateChange::SwitchTo(Box::new(menu)))),
+                Err(e) => {
+                    eprintln!("Failed to open the main menu: {e}");
+                    Some(Box::new(StateChange::Exit))
+                }
+            },
+            PauseAction::Quit => Some(Box::new(StateChange::Exit)),
+        }
+    }
+
+    fn draw_pause_menu(&mut self, res: &mut Resources) {
+        if let Some(menu) = &self.pause_menu {
+            menu.draw(res);
+        }
+    }
+
+    fn update_loading(&mut self, res: &mut Resources) -> Option<Box<StateChange>> {
+        if let Some(loading) = &mut self.loading && loading.update(&mut self.res.chunks) {
+            println!("Spawn area ready after {} chunks", self.res.chunks.chunks_generated());
+            self.loading = None;
+
+            // Benchmarks along a camera path start right away
+            let name = res.bench.as_ref()?.camera_path.as_deref()?;
+            if let Err(e) = CameraPath::load(name).and_then(|path| self.camera_paths.play(path)) {
+                eprintln!("--bench: can't play camera path '{name}': {e}");
+                return Some(Box::new(StateChange::Exit));
+            }
+        }
+        None
+    }
+
+    // `/path` commands, see CameraPaths, and `/theme reload`
+    fn run_local_commands(&mut self, res: &mut Resources) {
+        for command in self.res.chat.take_local_commands() {
+            let colors = res.settings.accessibility.palette.colors();
+            let result = match command.split_once(' ').unwrap_or((&command, "")) {
+                ("/path", args) => self.camera_paths.run_command(args, &self.res.camera),
+                ("/theme", "reload") => {
+                    res.theme = Theme::load();
+                    Ok("Reloaded the theme".to_owned())
+                }
+                ("/theme", _) => Err(anyhow::anyhow!("Usage: /theme reload")),
+                _ => continue,
+            };
+            let (reply, color) = match result {
+                Ok(reply) => (reply, TextColor::default()),
+                Err(e) => (e.to_string(), colors.error.into()),
+            };
+            self.res.chat.add_chat_entry(reply.to_local_str(), color, res.time.secs_f32);
+        }
+    }
+
+    fn draw_loading_screen(&mut self, res: &mut Resources) {
+        if let Some(loading) = &self.loading {
+            let colors = res.settings.accessibility.palette.colors();
+            loading.draw(&mut res.renderer.ui, &res.lang, &res.theme, &res.window_size, colors);
+        }
+    }
+
+    fn update_view_distance(&mut self, res: &mut Resources) {
+        let distance = self.adaptive_distance.update(res.metrics.frame_time.avg_frametime_ms, res.time.dt_secs);
+        self.res.chunks.set_view_distance(distance);
+    }
+
+    fn update_chunk_budget(&mut self, res: &mut Resources) {
+        self.chunk_budget.update(&mut self.res.chunks, &self.res.camera, res.time.secs_f32);
+    }
+
+    fn tick_chunks(&mut self, res: &mut Resources) -> Option<Box<StateChange>> {
+        if let Err(e) = self.res.chunks.tick(res) {
+            eprintln!("Error in Chunks::tick(): {e}");
+            return Some(Box::new(StateChange::Exit));
+        }
+        None
+    }
+}
+
 // Networking
 impl GameState {
     fn update_net(&mut self, res: &mut Resources) {
@@ -206,18 +517,35 @@ impl GameState {
         if let Some(channels) = self.res.net.connection.channels() {
             while let Ok(message) = channels.incoming.try_recv() {
                 match message {
-                    S2C::Chat(msg) => {
-                        self.res.chat.add_chat_entry(
-                            msg.to_local_str(),
-                            TextColor::default(),
-                            res.time.secs_f32,
-                        );
+                    S2C::Chat(msg, flags) => {
+                        // Sent on join for context: dimmed, so it's clear it happened earlier
+                        let color = if flags.contains(ChatFlags::HISTORY) {
+                            TextColor::from_rgba(160, 160, 160, 200)
+                        } else {
+                            TextColor::default()
+                        };
+                        if flags.contains(ChatFlags::NOTICE) && !flags.contains(ChatFlags::HISTORY) {
+                            res.toasts.push(Toast::new(ToastIcon::Info, msg.clone(), ""));
+                        }
+                        if !self.focused && flags == ChatFlags::NONE && chat::mentions(msg.as_str(), &self.res.username) {
+                            platform::request_attention(&res.window_handle);
+                        }
+                        self.res.chat.add_chat_entry(msg.to_local_str(), color, res.time.secs_f32);
                     },
                     S2C::EntityState(changes) => {
                         self.jitter_buf.push(changes, res.time.ms_u32);
                     },
-                    S2C::Statistics { ping } => {
+                    S2C::Statistics { ping, bytes_sent, bytes_received } => {
+                        if ping != self.ping {
+                            platform::set_server_title(&res.window_handle, &self.server, ping);
+                        }
                         self.ping = ping;
+                        self.bytes_sent = bytes_sent;
+                        self.bytes_received = bytes_received;
+                        self.clock_sync.set_rtt(ping);
+                    }
+                    S2C::ServerTick { tick, received } => {
+                        self.clock_sync.add_sample(tick, (received - res.time.at_launch).as_secs_f64());
                     }
                 }
             }
@@ -232,28 +560,56 @@ impl GameState {
             }
 
             self.is_network_tick = true;
+            self.connection_quality.on_network_tick();
 
             self.res.net.network_tick_count += 1;
-            self.res.net.next_network_tick =
-                (self.res.net.network_tick_count as f64 * shared::TICK_DURATION.as_secs_f64()) as f32;
+            self.res.net.next_network_tick = self
+                .clock_sync
+                .next_tick_at(self.res.net.network_tick_count, self.res.net.next_network_tick as f64)
+                as f32;
 
             for (_, (&Position(new), OldPosition(old))) in self.res.entities.query_mut::<(&Position, &mut OldPosition)>() {
                 *old = new;
             }
 
-            if let Some(changes) = self.jitter_buf.pop(res.time.ms_u32, DELAY_MS) {
+            if let Some(changes) = self.jitter_buf.pop(res.time.ms_u32, self.clock_sync.interpolation_delay_ms()) {
                 self.process_entity_state_msg(changes);
             }
         }
     }
 
+    fn warn_connection_quality(&mut self, res: &mut Resources) {
+        let lang = &res.lang;
+        let toast = match self.connection_quality.new_warning(self.ping) {
+            Some(Quality::Poor) => Toast::new(
+                ToastIcon::Warning,
+                tr!(lang, "toast.connection_poor"),
+                tr!(lang, "toast.connection_poor.body",
+                    loss = format!("{:.1}", self.connection_quality.loss_ratio() * 100.0),
+                    ping = self.ping,
+                ),
+            ),
+            Some(Quality::Unresponsive) => Toast::new(
+                ToastIcon::Error,
+                tr!(lang, "toast.connection_unresponsive"),
+                tr!(lang, "toast.connection_unresponsive.body"),
+            ),
+            _ => return,
+        };
+        res.toasts.push(toast.with_tag("connection_quality"));
+    }
+
     fn process_entity_state_msg(&mut self, updates: Box<[EntityStateMsg]>) {
         let ecs = &mut self.res.entities;
         let net = &mut self.res.net;
         
         let own_id = net.nid;
+        let now = Instant::now();
+        net.tombstones.retain(|&(_, removed)| now.duration_since(removed) < TOMBSTONE_TIMEOUT);
+        let tombstoned = |tombstones: &[(NetworkId, Instant)], id: NetworkId| tombstones.iter().any(|&(removed, _)| removed == id);
+        let mut entered_dimension = None;
 
-        for msg in updates.iter().copied() {
+        for msg in updates.into_vec() {
             match msg {
                 EntityStateMsg::EntityAdded { id, position, head_rotation } => {
                     if id == own_id { continue; }
@@ -263,26 +619,32 @@ impl GameState {
                         OldPosition(position),
                         HeadRotation(head_rotation),
                         OldHeadRotation(head_rotation),
+                        EntityLod::new(),
+                        Nameplate::new(),
                     ));
 
                     if net.nid_to_entity_mapping.len() <= id.raw() as usize {
                         net.nid_to_entity_mapping.resize(id.raw() as usize + 1, (NetworkId::INVALID, Entity::DANGLING));
                     }
 
+                    // Its EntityRemoved went missing and the id got reused. Whatever else is
+                    // off gets caught by the next EntityChecksum.
                     if net.nid_to_entity_mapping[id.raw() as usize].0 != NetworkId::INVALID {
-                        eprintln!("  ERROR  EntityAdded error: id {id} is already mapped to an entity!");
-                        ecs.despawn(net.nid_to_entity_mapping[id.raw() as usize].1).unwrap();
+                        println!("EntityAdded: id {id} was still mapped, replacing the old entity");
+                        let _ = ecs.despawn(net.nid_to_entity_mapping[id.raw() as usize].1);
                     }
 
                     net.nid_to_entity_mapping[id.raw() as usize] = (id, entity);
+                    net.tombstones.retain(|&(removed, _)| removed != id);
                 },
                 EntityStateMsg::EntityRemoved { id } => {
                     if id == own_id { continue; }
                     let mapping = net.nid_to_entity_mapping.get(id.raw() as usize).copied();
                     if let Some((check_id, entity)) = mapping && check_id == id {
-                        ecs.despawn(entity).unwrap();
+                        let _ = ecs.despawn(entity);
                         net.nid_to_entity_mapping[id.raw() as usize] = (NetworkId::INVALID, Entity::DANGLING);
-                    } else {
+                        net.tombstones.push((id, now));
+                    } else if !tombstoned(&net.tombstones, id) {
                         eprintln!("  ERROR  Tried to remove entity with id {id} but it does not exist");
                     }
                 },
@@ -290,6 +652,10 @@ impl GameState {
                     if id == own_id { continue; }
                     let mapping = net.nid_to_entity_mapping.get(id.raw() as usize).copied();
                     if let Some((check_id, entity)) = mapping && check_id == id {
+                        // Our own moves coming back
+                        if ecs.get::<&Owned>(entity).is_ok() {
+                            continue;
+                        }
                         /* println!("Moving entity #{id} from {} by {}", 
                             ecs.get::<&mut Position>(entity).unwrap().0, 
                             delta_pos
@@ -297,21 +663,140 @@ impl GameState {
                         //println!("MOVING ENTITY by {delta_pos} (len {:.4})", delta_pos.length());
                         ecs.get::<&mut Position>(entity).unwrap().0 += delta_pos;
                         ecs.get::<&mut HeadRotation>(entity).unwrap().0 += delta_head_rotation;
-                    } else {
+                    } else if !tombstoned(&net.tombstones, id) {
                         eprintln!("  ERROR  Tried to move entity with id {id} but it does not exist");
                     }
                 },
+                EntityStateMsg::MetadataChanged { id, key, value } => {
+                    if id == own_id { continue; }
+                    let mapping = net.nid_to_entity_mapping.get(id.raw() as usize).copied();
+                    if let Some((check_id, entity)) = mapping && check_id == id {
+                        let _ = match (key, value) {
+                            (MetadataKey::Username, MetadataValue::Str(name)) => ecs.insert_one(entity, Username(name.as_ref().into())),
+                            (MetadataKey::Crouching, MetadataValue::Bool(crouching)) => ecs.insert_one(entity, Crouching(crouching)),
+                            (MetadataKey::Sprinting, MetadataValue::Bool(sprinting)) => ecs.insert_one(entity, Sprinting(sprinting)),
+                            (MetadataKey::Skin, MetadataValue::Uint(skin)) => ecs.insert_one(entity, Skin(skin as u8)),
+                            (MetadataKey::Afk, MetadataValue::Bool(afk)) => ecs.insert_one(entity, Afk(afk)),
+                            (MetadataKey::Item, MetadataValue::Uint(block)) => ecs.insert_one(entity, DroppedItem(block as u16)),
+                            (MetadataKey::Owner, MetadataValue::Uint(owner)) if owner == own_id.raw() as u32 => ecs.insert_one(entity, Owned),
+                            (MetadataKey::Owner, MetadataValue::Uint(_)) => {
+                                let _ = ecs.remove_one::<Owned>(entity);
+                                Ok(())
+                            }
+                            (key, value) => {
+                                eprintln!("  ERROR  Metadata {key:?} has unexpected value {value:?}");
+                                continue;
+                            }
+                        };
+                    } else if !tombstoned(&net.tombstones, id) {
+                        eprintln!("  ERROR  Tried to set metadata of entity with id {id} but it does not exist");
+                    }
+                },
+                EntityStateMsg::EntityTeleported { id, position, head_rotation } => {
+                    if id == own_id { continue; }
+                    let mapping = net.nid_to_entity_mapping.get(id.raw() as usize).copied();
+                    if let Some((check_id, entity)) = mapping && check_id == id {
+                        // Both old and new, so that it doesn't get interpolated across the jump
+                        let _ = ecs.insert(entity, (
+                            Position(position),
+                            OldPosition(position),
+                            HeadRotation(head_rotation),
+                            OldHeadRotation(head_rotation),
+                            EntityLod::new(),
+                        ));
+                    } else if !tombstoned(&net.tombstones, id) {
+                        eprintln!("  ERROR  Tried to teleport entity with id {id} but it does not exist");
+                    }
+                },
+                EntityStateMsg::BlocksChanged { changes } => {
+                    for (pos, block) in changes {
+                        self.res.chunks.set_block(pos, Block::from_raw(block));
+                    }
+                },
+                EntityStateMsg::Teleport { tag, pos, yaw, pitch, flags, dimension } => {
+                    self.res.input_recorder.teleport(tag, pos, vec2(yaw, pitch), flags);
+                    self.res.the_player.vel = Vec3::ZERO;
+                    if let Some((dimension, seed)) = dimension && dimension != net.dimension {
+                        // The server starts over with the entities of the new dimension, which
+                        // come after this, and says nothing more of the old one's
+                        for (id, entity) in net.nid_to_entity_mapping.iter_mut().filter(|(id, _)| *id != NetworkId::INVALID) {
+                            let _ = ecs.despawn(*entity);
+                            (*id, *entity) = (NetworkId::INVALID, Entity::DANGLING);
+                        }
+                        net.dimension = dimension;
+                        entered_dimension = Some((dimension, seed, pos));
+                    }
+                },
                 EntityStateMsg::InputValidated { tag, packets_lost, server_pos, server_head_rot } => {
                     self.packets_lost += packets_lost as u32;
+                    self.connection_quality.on_input_validated(tag, packets_lost);
                     self.res.input_recorder
                         .process_server_authoritative_state(tag, server_pos, server_head_rot);
                 }
+                // Taken out by the network thread, see S2C::ServerTick
+                EntityStateMsg::ServerTick { .. } => {}
+                EntityStateMsg::EntityChecksum { count, checksum } => {
+                    let mapped = net.nid_to_entity_mapping.iter().map(|&(id, _)| id).filter(|&id| id != NetworkId::INVALID);
+                    let (own_count, own_checksum) = s2c::entity_checksum(mapped);
+                    if (own_count, own_checksum) == (count, checksum) {
+                        net.resync_requested = None;
+                        continue;
+                    }
+                    if net.resync_requested.map_or(false, |requested| now.duration_since(requested) < RESYNC_TIMEOUT) {
+                        continue;
+                    }
+                    eprintln!("Entities out of sync: {own_count} here, {count} on the server. Resyncing.");
+                    // The server sends everything in range again, which is simpler than
+                    // finding out what exactly is off
+                    for (id, entity) in net.nid_to_entity_mapping.iter_mut().filter(|(id, _)| *id != NetworkId::INVALID) {
+                        let _ = ecs.despawn(*entity);
+                        net.tombstones.push((*id, now));
+                        (*id, *entity) = (NetworkId::INVALID, Entity::DANGLING);
+                    }
+                    if let Some(channels) = net.connection.channels() {
+                        let _ = channels.authority.send(AuthorityMsg::Resync);
+                    }
+                    net.resync_requested = Some(now);
+                }
             }
         }
+
+        if let Some((dimension, seed, pos)) = entered_dimension {
+            self.enter_dimension(dimension, seed, pos);
+        }
+    }
+
+    // Drops the chunks of the old dimension and starts generating the new one's around `pos`,
+    // behind the loading screen. The position of a teleport into another dimension is
+    // absolute in practice; if it weren't, the chunks would re-center as the player moves.
+    fn enter_dimension(&mut self, dimension: DimensionId, seed: u64, pos: Vec3) {
+        println!("Entering dimension {dimension}");
+        let view_distance = self.res.chunks.view_distance();
+        self.res.chunks = Chunks::new(seed, MAX_RENDER_DISTANCE, ChunkPos::containing(pos));
+        self.res.chunks.set_view_distance(view_distance);
+        self.res.minimap = Minimap::new(&self.res.chunks);
+        self.block_breaking = BlockBreaking::new();
+        self.loading = Some(LoadingScreen::new());
     }
 }
 
 impl GameState {
+    fn open_pause_menu(&mut self, res: &mut Resources) {
+        res.input.keyboard.clear_all();
+        self.pause_menu = Some(PauseMenu::new());
+        let _ = res.window_handle.set_cursor_grab(CursorGrabMode::None);
+        res.window_handle.set_cursor_visible(true);
+    }
+
+    fn close_pause_menu(&mut self, res: &mut Resources) {
+        res.input.keyboard.clear_all();
+        self.pause_menu = None;
+        let size = res.window_size.xy / 2.0;
+        let _ = res.window_handle.set_cursor_position(LogicalPosition::new(size.x as u32, size.y as u32));
+        let _ = res.window_handle.set_cursor_grab(CursorGrabMode::Confined);
+        res.window_handle.set_cursor_visible(false);
+    }
+
     fn open_chat(&mut self, res: &mut Resources) {
         if !self.res.chat.is_open() {
             res.input.keyboard.clear_all();
@@ -320,15 +805,36 @@ impl GameState {
     }
 
     fn do_player_movement(&mut self, res: &mut Resources) {
-        if self.res.chat.is_open() {
+        let player = &mut self.res.the_player;
+        if self.res.chat.is_open() || self.map_view.open || self.loading.is_some() || self.pause_menu.is_some()
+            || self.camera_paths.is_playing()
+        {
+            player.movement = MovementFlags::NONE;
+            player.zooming = false;
             return;
         }
         
         let keyboard = &mut res.input.keyboard;
+        let bindings = &res.input.settings.key_bindings;
+        player.zooming = keyboard.pressed(bindings.zoom);
+
+        // Sprinting starts with a double tap of forward or with the sprint key, and lasts
+        // until forward is released. Sneaking (also flying down for now) cancels it.
+        let sneaking = keyboard.pressed(bindings.sneak);
+        let mut sprinting = player.movement.contains(MovementFlags::SPRINTING);
+        if keyboard.just_pressed(bindings.fwd) {
+            sprinting |= res.time.secs_f32 - player.last_fwd_press_secs < DOUBLE_TAP_SECS;
+            player.last_fwd_press_secs = res.time.secs_f32;
+        }
+        sprinting |= keyboard.pressed(bindings.sprint);
+        sprinting &= keyboard.pressed(bindings.fwd) && !sneaking;
+        player.movement = MovementFlags::NONE
+            .with(MovementFlags::SPRINTING, sprinting)
+            .with(MovementFlags::SNEAKING, sneaking);
         
-        let right = keyboard.get_axis(Key::D, Key::A);
-        let up = keyboard.get_axis(Key::Space, Key::LShift);
-        let fwd = keyboard.get_axis(Key::W, Key::S);
+        let right = keyboard.get_axis(bindings.right, bindings.left);
+        let up = keyboard.get_axis(bindings.jump, bindings.sneak);
+        let fwd = keyboard.get_axis(bindings.fwd, bindings.back);
         
         if right != 0 || up != 0 || fwd != 0 {
             let (ys, yc) = self.res.camera.yaw().sin_cos();
@@ -337,10 +843,9 @@ impl GameState {
             let right_dir = fwd_dir.cross(up_dir);
             
             let hor_acc = (right as f32 * right_dir + fwd as f32 * fwd_dir).normalize_or_zero();
-            let acc = (hor_acc + up as f32 * up_dir) * 1.0;
+            let acc = hor_acc * movement::acceleration(player.movement) + up as f32 * up_dir;
             
-            let velocity = &mut self.res.the_player.vel;
-            *velocity += acc;//.clamp_length_max(20.0);
+            player.vel = movement::clamp_horizontal_velocity(player.vel + acc, player.movement);
         }
     } 
 
@@ -349,74 +854,449 @@ impl GameState {
 
 
 
-        let mouse_speed = res.input.settings.mouse_sensitivity * 0.0025;
+        if self.map_view.open || self.loading.is_some() || self.pause_menu.is_some() || self.camera_paths.is_playing() {
+            // Mouse is used for dragging the map or for menus, or not at all while loading
+            // or playing a camera path
+            self.mouse_move_accumulator = Vec2::ZERO;
+        }
+        // Turns slower while zoomed in, so that the view moves about as far on screen
+        let base_fov = (res.settings.graphics.fov as f32).to_radians();
+        let zoom = self.res.the_player.fov_scale.min(1.0);
+        let zoom_compensation = (base_fov * zoom * 0.5).tan() / (base_fov * 0.5).tan();
+        let mouse_speed = res.input.settings.mouse_sensitivity * 0.0025 * zoom_compensation;
         let mouse_motion = self.mouse_move_accumulator * mouse_speed;
         self.mouse_move_accumulator = Vec2::ZERO;
 
         let (Position(new_pos), YawPitch(new_yaw, new_pitch)) = self.res.input_recorder.record(
             self.res.the_player.vel,
             mouse_motion,
-            res.time.dt_secs
+            res.time.dt_secs,
+            self.res.the_player.movement,
         );
-        camera.move_to(new_pos);
-        camera.set_rotation(new_yaw, new_pitch);
-        self.res.the_player.pos = new_pos;
 
+        // Ease the camera down while sneaking, widen the view while sprinting and narrow it
+        // while zooming. The FOV setting may change in the pause menu, so that's applied here too.
+        let player = &mut self.res.the_player;
+        let ease = 1.0 - (-12.0 * res.time.dt_secs).exp();
+        let target_drop = if player.movement.contains(MovementFlags::SNEAKING) { SNEAK_CAMERA_DROP } else { 0.0 };
+        let target_fov = match (player.zooming, player.movement.contains(MovementFlags::SPRINTING)) {
+            (true, _) => ZOOM_FOV_SCALE,
+            (false, true) => SPRINT_FOV_SCALE,
+            (false, false) => 1.0,
+        };
+        player.camera_drop += (target_drop - player.camera_drop) * ease;
+        player.fov_scale += (target_fov - player.fov_scale) * ease;
+        let fov = base_fov * player.fov_scale;
+        if (fov - camera.fov()).abs() > 1e-4 {
+            camera.set_fov(fov);
+        }
+
+        if let Some(keyframe) = self.camera_paths.update(res.time.dt_secs) {
+            camera.move_to(keyframe.pos);
+            camera.set_rotation(keyframe.yaw, keyframe.pitch);
+        } else {
+            // Drawn where the player seems to be, while `player.pos` is the exact prediction
+            let visual_pos = new_pos + self.res.input_recorder.visual_offset();
+            camera.move_to(visual_pos - Vec3::Y * player.camera_drop);
+            camera.set_rotation(new_yaw, new_pitch);
+        }
+        player.pos = new_pos;
+        camera.update();
+    }
+
+    fn send_player_state(&mut self) {
         let predictions = self.res.input_recorder.predictions();
+        // Latest input plus as many older ones as the packet loss calls for
+        let redundancy = self.connection_quality.redundancy();
+        let predictions = &predictions[predictions.len().saturating_sub(redundancy + 1)..];
         if self.is_network_tick && !predictions.is_empty() && let Some(channels) = self.res.net.connection.channels() {
             // Wrong place to handle the network thread crashing down, ignore result
             let _ = channels.player_state.send(predictions.into());
             self.packets_sent += 1;
 
+            for (_, (&id, &Position(position), &HeadRotation(head_rotation))) in self.res.entities
+                .query_mut::<(&NetworkId, &Position, &HeadRotation)>()
+                .with::<&Owned>()
+            {
+                let _ = channels.authority.send(AuthorityMsg::Move { id, position, head_rotation });
+            }
+
             /* self.artificial_delay.push(predictions.into(), res.time.ms_u32);
             if let Some(msg) = self.artificial_delay.pop(res.time.ms_u32, 300) {
                 let _ = channels.player_state.send(msg);
                 self.packets_sent += 1;
             } */
         }
-        camera.update();
     }
 }
 
 impl GameState {
     #[rustfmt::skip]
     fn draw_debug_hud(&self, res: &mut Resources) {
+        if self.loading.is_some() || self.camera_paths.is_playing() {
+            return;
+        }
         let ui = &mut res.renderer.ui;
         let mut h = res.window_size.extent.height as u16 - 30;
         macro_rules! hud {
             ($($arg:tt)+) => {
                 h -= 30;
                 let w = ui.draw_text(&format!($($arg)*), 30, h).0;
-                ui.draw_rect_xy_wh((25, h-5), (w-20, 30), 0x06_06_06_90);
+                ui.draw_rect_xy_wh((25, h-5), (w-20, 30), res.theme.hud_background);
             };
         }
 
-        hud!("FPS: {:.1}", res.metrics.frame_time.avg_fps);
+        let lang = &res.lang;
+        hud!("{}", tr!(lang, "hud.fps", fps = format!("{:.1}", res.metrics.frame_time.avg_fps)));
         hud!("X: {:.4}", self.res.camera.pos().x);
         hud!("Y: {:.4}", self.res.camera.pos().y);
         hud!("Z: {:.4}", self.res.camera.pos().z);
         hud!("Yaw: {:.3}", self.res.camera.yaw().to_degrees());
         hud!("Pitch: {:.3}", self.res.camera.pitch().to_degrees());
-        hud!("Packets lost/total: {}/{} ({:.2})", 
-            self.packets_lost, 
-            self.packets_sent, 
-            self.packets_lost as f32 / self.packets_sent as f32
+        hud!("{}", tr!(lang, "hud.packets",
+            lost = self.packets_lost,
+            sent = self.packets_sent,
+            ratio = format!("{:.2}", self.packets_lost as f32 / self.packets_sent as f32),
+        ));
+        hud!("{}", tr!(lang, "hud.ping",
+            ping = self.ping,
+            quality = format!("{:?}", self.connection_quality.quality(self.ping)),
+            loss = format!("{:.1}", self.connection_quality.loss_ratio() * 100.0),
+            redundancy = self.connection_quality.redundancy(),
+        ));
+        if let Some((drift_ppm, jitter_ms)) = self.clock_sync.stats() {
+            hud!("Clock sync: drift {:+.0} ppm, jitter {:.1} ms, interpolation delay {} ms",
+                drift_ppm, jitter_ms, self.clock_sync.interpolation_delay_ms());
+        } else {
+            hud!("Clock sync: no samples yet");
+        }
+        let adaptive = &self.adaptive_distance;
+        hud!("{}", tr!(lang, "hud.view_distance",
+            distance = self.res.chunks.view_distance(),
+            mode = if adaptive.enabled { tr!(lang, "hud.view_distance.adaptive") } else { tr!(lang, "hud.view_distance.fixed") },
+            min = adaptive.min,
+            max = adaptive.max,
+            fps = format!("{:.0}", adaptive.target_fps),
+            frametime = format!("{:.1}", adaptive.smoothed_frametime_ms()),
+        ));
+
+        const MIB: f32 = 1024.0 * 1024.0;
+        let mem = res.renderer.vk.allocator.stats();
+        hud!("GPU mem: {:.1} MiB device-local ({}), {:.1} MiB host-visible ({}) | F3+{:?} report",
+            mem.device_local.bytes as f32 / MIB, mem.device_local.allocations,
+            mem.host_visible.bytes as f32 / MIB, mem.host_visible.allocations,
+            MEMORY_REPORT_KEY,
+        );
+        hud!("Textures: {:.1} MiB | Framebuffers: {:.1} MiB | Meshes: {:.1} MiB ({})",
+            mem.of(MemoryTag::Texture).bytes as f32 / MIB,
+            mem.of(MemoryTag::Framebuffer).bytes as f32 / MIB,
+            mem.of(MemoryTag::Mesh).bytes as f32 / MIB, mem.of(MemoryTag::Mesh).allocations,
+        );
+        const KIB: f32 = 1024.0;
+        let uploads = res.renderer.vk.uploader.stats();
+        hud!("Uploads: {:.1} KiB last frame | queued: {} UI, {} near, {} far ({:.1} KiB)",
+            uploads.last_frame_bytes as f32 / KIB,
+            uploads.queued[UploadPriority::Ui as usize],
+            uploads.queued[UploadPriority::NearChunks as usize],
+            uploads.queued[UploadPriority::FarChunks as usize],
+            uploads.queued_bytes as f32 / KIB,
+        );
+        let text = ui.text().stats();
+        hud!("Text: peak {}/{} glyphs, {}/{} 3D glyphs, {} dropped",
+            text.peak_glyphs, text.max_glyphs,
+            text.peak_transforms, text.max_transforms,
+            text.dropped_glyphs,
+        );
+
+        let on_off = |b: bool| if b { "on" } else { "off" };
+        hud!("F3+{:?} chunk borders: {} | F3+{:?} hitboxes: {} | F3+{:?} raycast: {}",
+            DebugRender::CHUNK_BORDERS_KEY, on_off(self.debug_render.chunk_borders),
+            DebugRender::HITBOXES_KEY, on_off(self.debug_render.hitboxes),
+            DebugRender::RAYCAST_KEY, on_off(self.debug_render.raycast),
+        );
+        let wireframe_supported = res.renderer.state.pipelines.terrain_wireframe.is_some();
+        let modes = TerrainDrawMode::ALL.map(|mode| {
+            let selected = if mode == self.debug_render.terrain_mode { ">" } else { " " };
+            let unsupported = if mode == TerrainDrawMode::Wireframe && !wireframe_supported { " (unsupported)" } else { "" };
+            format!("{selected}{mode:?}{unsupported}")
+        });
+        hud!("F3+{:?} terrain view: {}", DebugRender::TERRAIN_MODE_KEY, modes.join(" "));
+        let entities = self.entity_culling.counts();
+        let gpu_culling = self.debug_render.gpu_culling;
+        let culling = if gpu_culling { "GPU" } else { "CPU" };
+        hud!("F3+{:?} entity culling: {}", DebugRender::GPU_CULLING_KEY, culling);
+        hud!("F3+{:?} packet inspector: {}", PacketInspector::TOGGLE_KEY, on_off(self.packet_inspector.open));
+        if gpu_culling {
+            // Which ones the GPU culled never comes back to the CPU
+            hud!("Entities: {} sent to GPU culling ({} at reduced rate)", entities.drawn, entities.reduced);
+        } else {
+            hud!("Entities: {} drawn ({} at reduced rate), {} culled", entities.drawn, entities.reduced, entities.culled);
+        }
+        let budget = match self.chunk_budget.max_bytes() {
+            usize::MAX => "no limit".to_owned(),
+            max => format!("{} MiB", max >> 20),
+        };
+        hud!("Chunk memory: {} MiB of {budget}, {} loaded, {} evicted ({} total)",
+            self.res.chunks.memory_bytes() >> 20, self.res.chunks.loaded(),
+            self.res.chunks.evicted().len(), self.chunk_budget.evictions(),
         );
-        hud!("Ping: {}ms", self.ping);
+        if let Some(hit) = self.debug_render.last_hit {
+            hud!("{}", tr!(lang, "hud.looking_at",
+                block = hit.block_pos,
+                normal = hit.normal,
+                distance = format!("{:.2}", hit.distance),
+            ));
+        }
+    }
+
+    fn handle_debug_keys(&mut self, res: &mut Resources) {
+        if !self.res.chat.is_open() {
+            self.debug_render.handle_input(&res.input.keyboard);
+            let log = self.res.net.connection.channels().map(|channels| channels.packet_log.clone());
+            self.packet_inspector.handle_input(&res.input.keyboard, log.as_deref());
+
+            let keyboard = &res.input.keyboard;
+            if keyboard.pressed(Key::F3) && keyboard.just_pressed(MEMORY_REPORT_KEY) {
+                res.renderer.vk.allocator.report();
+            }
+        }
     }
 
-    fn draw_crosshair(ui: &mut UiRenderer, win_size: &WindowSize) {
-        let (w, h) = (win_size.extent.width as u16, win_size.extent.height as u16);
-        ui.draw_rect_xy_wh((w / 2 - 12, h / 2 - 1), (24, 2), 0x99_99_99_FF);
-        ui.draw_rect_xy_wh((w / 2 - 1, h / 2 - 12), (2, 24), 0x99_99_99_FF);
+    fn draw_packet_inspector(&mut self, res: &mut Resources) {
+        if !self.packet_inspector.open || self.loading.is_some() || self.camera_paths.is_playing() {
+            return;
+        }
+        let now = Instant::now();
+        if let Some(channels) = self.res.net.connection.channels() {
+            self.packet_inspector.update(&channels.packet_log, self.bytes_received, now);
+        }
+        self.packet_inspector.draw(&mut res.renderer.ui, &res.theme, res.window_size.extent.width as u16, now);
+    }
+
+    // Right click places the held block on the face being looked at. The server places it and
+    // sends it back, see c2s::BlockAction::Place; servers without Features::BLOCK_PLACING
+    // don't hear about it, and it's placed client-side only.
+    fn place_block(&mut self, res: &mut Resources) {
+        if !self.has_control() || !res.input.mouse.just_pressed(MouseButton::Right) {
+            return;
+        }
+        let InteractionTarget::Block { pos, normal, .. } = self.res.interaction_target else {
+            return;
+        };
+        if normal == IVec3::ZERO {
+            return; // Inside a block
+        }
+
+        let target = pos + normal;
+        if self.res.chunks.block_at(target) != Some(Block::AIR) {
+            return;
+        }
+        let held = self.view_model.held;
+        if !self.res.net.features.contains(Features::BLOCK_PLACING) {
+            self.res.chunks.set_block(target, held);
+        } else if let Some(channels) = self.res.net.connection.channels() {
+            let _ = channels.authority.send(AuthorityMsg::Block(BlockAction::Place { pos: target, block: held.raw() }));
+        }
+    }
+
+    // Holding left click breaks the block being looked at, see BlockBreaking. Servers without
+    // Features::BLOCK_BREAKING don't hear about it, and it's broken client-side only.
+    fn break_block(&mut self, res: &mut Resources) {
+        let holding = self.has_control() && res.input.mouse.pressed(MouseButton::Left);
+        let looking_at = self.res.interaction_target.block();
+        let Some(event) = self.block_breaking.update(holding, looking_at, res.time.secs_f32) else {
+            return;
+        };
+        let server_side = self.res.net.features.contains(Features::BLOCK_BREAKING);
+        let action = match event {
+            BreakEvent::Start(pos) => BlockAction::StartBreaking { pos },
+            BreakEvent::Cancel => BlockAction::CancelBreaking,
+            BreakEvent::Finish(pos) => {
+                if !server_side {
+                    self.res.chunks.set_block(pos, Block::AIR);
+                }
+                BlockAction::FinishBreaking { pos }
+            }
+        };
+        if server_side && let Some(channels) = self.res.net.connection.channels() {
+            let _ = channels.authority.send(AuthorityMsg::Block(action));
+        }
+    }
+
+    fn update_interaction_target(&mut self) {
+        let res = &mut self.res;
+        res.interaction_target = match self.loading.is_none() && !self.camera_paths.is_playing() {
+            true => InteractionTarget::find(res.camera.pos(), res.camera.facing(), REACH, &res.chunks, &mut res.entities),
+            false => InteractionTarget::Nothing,
+        };
+    }
+
+    // The interact key takes control of the entity under the crosshair, or lets go of it. The
+    // server decides, see c2s::AuthorityMsg::Claim; the hint shows what pressing it would try.
+    fn interact(&mut self, res: &mut Resources) {
+        if !self.has_control() || !res.input.keyboard.just_pressed(res.input.settings.key_bindings.interact) {
+            return;
+        }
+        let InteractionTarget::Entity { id, kind: EntityKind::Other { owned }, .. } = self.res.interaction_target else {
+            return;
+        };
+        let msg = if owned { AuthorityMsg::Release { id } } else { AuthorityMsg::Claim { id } };
+        if let Some(channels) = self.res.net.connection.channels() {
+            let _ = channels.authority.send(msg);
+        }
+    }
+
+    fn has_control(&self) -> bool {
+        !self.res.chat.is_open() && !self.map_view.open && self.loading.is_none() && self.pause_menu.is_none()
+            && !self.camera_paths.is_playing()
+    }
+
+    // Under the crosshair
+    fn draw_interaction_hint(&mut self, res: &mut Resources) {
+        if !self.has_control() {
+            return;
+        }
+        let InteractionTarget::Entity { kind: EntityKind::Other { owned }, .. } = self.res.interaction_target else {
+            return;
+        };
+        let key = format!("{:?}", res.input.settings.key_bindings.interact);
+        let hint = match owned {
+            true => tr!(res.lang, "hud.hint.let_go", key = key),
+            false => tr!(res.lang, "hud.hint.take_control", key = key),
+        };
+        let ui = &mut res.renderer.ui;
+        let (w, h) = (res.window_size.extent.width as u16, res.window_size.extent.height as u16);
+        let width = ui.text().compute_width(&hint);
+        let (x, y) = ((w / 2).saturating_sub(width / 2), h / 2 + 30);
+        ui.draw_text(&hint, x, y);
+        ui.draw_rect_xy_wh((x.saturating_sub(5), y - 5), (width + 10, 30), res.theme.hud_background);
+    }
+
+    fn draw_block_breaking(&mut self, res: &mut Resources) {
+        if self.loading.is_some() || self.camera_paths.is_playing() {
+            return;
+        }
+        let chunks = &self.res.chunks;
+        self.block_breaking.draw(&mut res.renderer.debug_lines, res.time.secs_f32, |pos| chunks.block_at(pos));
+    }
+
+    // Both mouse buttons swing the hand, whether or not they hit anything
+    fn update_view_model(&mut self, res: &mut Resources) {
+        let mouse = &res.input.mouse;
+        let clicked = mouse.just_pressed(MouseButton::Left) || mouse.just_pressed(MouseButton::Right);
+        // Over and over while breaking a block
+        let breaking = self.block_breaking.is_breaking() && !self.view_model.is_swinging();
+        if (clicked && self.has_control()) || breaking {
+            self.view_model.swing();
+        }
+        self.view_model.update(self.res.the_player.vel, res.time.dt_secs);
+    }
+
+    fn handle_map_input(&mut self, res: &mut Resources) {
+        if !self.res.chat.is_open() && self.loading.is_none() && self.pause_menu.is_none() && !self.camera_paths.is_playing() {
+            self.map_view.handle_input(
+                &res.input.keyboard,
+                &res.input.mouse,
+                self.res.the_player.pos,
+                res.time.dt_secs,
+            );
+        }
+    }
+
+    fn draw_map(&mut self, res: &mut Resources) {
+        if self.loading.is_some() || self.camera_paths.is_playing() {
+            return;
+        }
+        self.map_view.draw(
+            &mut res.renderer.ui,
+            &res.theme,
+            &self.res.minimap,
+            self.res.the_player.pos,
+            &self.res.entities,
+            &res.window_size,
+        );
+    }
+
+    // Left of the minimap
+    fn draw_connection_icon(&mut self, res: &mut Resources) {
+        if self.map_view.open || self.loading.is_some() || self.camera_paths.is_playing() {
+            return;
+        }
+        let (w, h) = (res.window_size.extent.width as u16, res.window_size.extent.height as u16);
+        let pos = (w - MapView::MINIMAP_SIZE - 60, h - 40);
+        let colors = res.settings.accessibility.palette.colors();
+        self.connection_quality.draw_icon(&mut res.renderer.ui, pos, self.ping, colors);
+    }
+
+    fn draw_debug_lines(&mut self, res: &mut Resources) {
+        let t = self.entity_interpolation_t(res.time.secs_f32);
+        self.debug_render.draw(
+            &mut res.renderer.debug_lines,
+            self.res.camera.pos(),
+            self.res.camera.facing(),
+            &self.res.chunks,
+            &mut self.res.entities,
+            t,
+        );
+    }
+
+    // Before the rest of the UI, so that the HUD is drawn over them
+    fn draw_nameplates(&mut self, res: &mut Resources) {
+        if self.loading.is_some() || self.camera_paths.is_playing() {
+            return;
+        }
+        let t = self.entity_interpolation_t(res.time.secs_f32);
+        nameplates::draw(
+            res.renderer.ui.text(),
+            &mut self.res.entities,
+            &self.res.chunks,
+            &self.res.camera,
+            t,
+            res.time.dt_secs,
+        );
+    }
+
+    fn update_entity_culling(&mut self, res: &mut Resources) {
+        let t = self.entity_interpolation_t(res.time.secs_f32);
+        let gpu = self.debug_render.gpu_culling;
+        self.entity_culling.update(&mut self.res.entities, &self.res.camera, t, res.time.secs_f32, gpu);
+    }
+
+    // The resource pack may have been switched from the pause menu
+    fn check_texture_layers(&mut self, res: &mut Resources) {
+        let texture_layers = res.renderer.state.descriptors.textures.layers;
+        if texture_layers != self.texture_layers {
+            self.texture_layers = texture_layers;
+            self.res.chunks.mark_all_dirty();
+        }
+    }
+
+    // How far between the previous and the latest network tick entities should be drawn
+    fn entity_interpolation_t(&self, secs: f32) -> f32 {
+        const NW_TICK: f32 = 1.0 / shared::TICKS_PER_SECOND as f32;
+        (secs - (self.res.net.next_network_tick - NW_TICK)) / NW_TICK
     }
 
     fn render(&mut self, res: &mut Resources) -> anyhow::Result<()> {
-        Self::draw_crosshair(&mut res.renderer.ui, &res.window_size);
+        let hud_hidden = self.loading.is_some() || self.camera_paths.is_playing();
+        if !hud_hidden {
+            let target = match self.res.interaction_target {
+                InteractionTarget::Nothing => CrosshairTarget::Nothing,
+                InteractionTarget::Block { .. } => CrosshairTarget::Block,
+                InteractionTarget::Entity { .. } => CrosshairTarget::Entity,
+            };
+            res.cursor.draw_crosshair(&mut res.renderer.ui, &res.window_size, target, &res.theme);
+        }
 
-        self.res
-            .chat
-            .draw(res.time.secs_f32, &mut res.renderer.ui, &res.window_size);
+        if !self.camera_paths.is_playing() || self.res.chat.is_open() {
+            let background = res.settings.accessibility.chat_background(&res.theme);
+            self.res
+                .chat
+                .draw(res.time.secs_f32, &mut res.renderer.ui, &res.window_size, background, res.theme.text_selection);
+        }
+
+        let gamma = res.settings.graphics.gamma;
+        let held_pvm = self.view_model.matrix(res.window_size.xy);
 
         let renderer = &mut res.renderer;
         let ctx = renderer.start_frame()?;
@@ -424,137 +1304,232 @@ impl GameState {
         let vk = &mut renderer.vk;
         let passes = &renderer.state.render_passes;
 
+        // First, as it may replace the image the textures descriptor set points to
+        let map_size = self.res.minimap.size() as u32;
+        if let Some(pixels) = self.res.minimap.take_changes() {
+            renderer.map_texture.update(vk, &ctx, map_size, pixels)?;
+        }
         UiRenderer::do_uploads(&mut renderer.ui, vk, ctx.frame)?;
+        DebugLines::do_uploads(&mut renderer.debug_lines, vk)?;
+
+        // The culling pre-pass has to go before any render pass begins
+        let gpu_culling = if self.debug_render.gpu_culling {
+            let gpu_culling = &mut renderer.gpu_culling;
+            gpu_culling.do_uploads(vk, ctx.frame, self.entity_culling.candidates(), self.cube.indices.index_count)?;
+            gpu_culling.dispatch(&vk.device, &ctx, self.res.camera.frustum().planes());
+            Some(&*gpu_culling)
+        } else {
+            None
+        };
 
-        ctx.render_pass(
-            &vk.device,
-            &passes.terrain,
-            0,
-            Clear::ColorAndDepth([0.1, 0.1, 0.1], 0.0),
-            || unsafe {
-                vk.device.cmd_bind_pipeline(
-                    ctx.commands,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    renderer.state.pipelines.terrain.handle,
-                );
-                let pv = self.res.camera.proj_view_matrix();
-                let pvm_ptr = &pv as *const Mat4 as *const c_void;
-                vk.device.cmd_push_constants(
-                    ctx.commands,
-                    renderer.state.pipelines.terrain.layout,
-                    vk::ShaderStageFlags::VERTEX,
-                    0,
-                    std::mem::size_of::<Mat4>() as u32,
-                    pvm_ptr,
-                );
-                vk.device.cmd_bind_descriptor_sets(
-                    ctx.commands,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    renderer.state.pipelines.terrain.layout,
-                    0,
-                    &[renderer.state.descriptors.textures.descriptor_set],
-                    &[],
-                );
-                vk.device.cmd_bind_vertex_buffers(
-                    ctx.commands,
-                    0,
-                    &[self.grid_vbo.buffer.handle],
-                    &[0],
-                );
-                vk.device
-                    .cmd_draw(ctx.commands, self.grid_vbo.vertex_count, 1, 0, 0);
-
-                vk.device.cmd_bind_vertex_buffers(
-                    ctx.commands,
-                    0,
-                    &[self.cube_vbo.buffer.handle],
-                    &[0],
-                );
-
-                const NW_TICK: f32 = 1.0 / shared::TICKS_PER_SECOND as f32;
-                let t = (res.time.secs_f32 - (self.res.net.next_network_tick - NW_TICK)) / NW_TICK;
-
-                self.res
-                    .entities
-                    .query_mut::<(&OldPosition, &Position, &HeadRotation)>()
-                    .into_iter()
-                    .for_each(|(_, (old_pos, new_pos, rot))| {
-                        let pv = self.res.camera.proj_view_matrix()
-                            * Mat4::from_translation((new_pos.0 - old_pos.0) * t + old_pos.0)
-                            * Mat4::from_euler(EulerRot::YXZ, -rot.0.x + PI / 2.0, -rot.0.y, 0.0);
-                        let pvm_ptr = &pv as *const Mat4 as *const c_void;
-                        vk.device.cmd_push_constants(
+        let mut draw_calls = 0;
+
+        for step in renderer.state.frame_graph.steps() {
+            step.record_barrier(&vk.device, &ctx);
+            match step.pass {
+                Pass::Terrain => {
+                    ctx.render_pass(
+                        &vk.device,
+                        &passes.terrain,
+                        0,
+                        Clear::ColorAndDepth([0.1, 0.1, 0.1], 0.0),
+                        || unsafe {
+                            let terrain = renderer.state.pipelines.terrain_for(self.debug_render.terrain_mode);
+                            vk.device.cmd_bind_pipeline(
+                                ctx.commands,
+                                vk::PipelineBindPoint::GRAPHICS,
+                                terrain.handle,
+                            );
+                            let pv = self.res.camera.proj_view_matrix();
+                            let pvm_ptr = &pv as *const Mat4 as *const c_void;
+                            vk.device.cmd_push_constants(
+                                ctx.commands,
+                                terrain.layout,
+                                vk::ShaderStageFlags::VERTEX,
+                                0,
+                                std::mem::size_of::<Mat4>() as u32,
+                                pvm_ptr,
+                            );
+                            vk.device.cmd_bind_descriptor_sets(
+                                ctx.commands,
+                                vk::PipelineBindPoint::GRAPHICS,
+                                terrain.layout,
+                                0,
+                                &[renderer.state.descriptors.textures.descriptor_set],
+                                &[],
+                            );
+                            self.grid.draw(&vk.device, ctx.commands);
+                            draw_calls += 1;
+
+                            vk.device.cmd_bind_vertex_buffers(
+                                ctx.commands,
+                                0,
+                                &[self.cube.vertices.buffer.handle],
+                                &[0],
+                            );
+                            vk.device.cmd_bind_index_buffer(
+                                ctx.commands,
+                                self.cube.indices.buffer.handle,
+                                0,
+                                self.cube.indices.index_type,
+                            );
+
+                            if let Some(gpu_culling) = gpu_culling {
+                                gpu_culling.render(
+                                    &vk.device,
+                                    &ctx,
+                                    &renderer.state.descriptors,
+                                    self.res.camera.proj_view_matrix(),
+                                );
+                                draw_calls += 1;
+                            }
+                            self.entity_culling
+                                .models()
+                                .iter()
+                                .for_each(|model| {
+                                    let pv = self.res.camera.proj_view_matrix() * *model;
+                                    let pvm_ptr = &pv as *const Mat4 as *const c_void;
+                                    vk.device.cmd_push_constants(
+                                        ctx.commands,
+                                        terrain.layout,
+                                        vk::ShaderStageFlags::VERTEX,
+                                        0,
+                                        std::mem::size_of::<Mat4>() as u32,
+                                        pvm_ptr,
+                                    );
+                                    vk.device
+                                        .cmd_draw_indexed(ctx.commands, self.cube.indices.index_count, 1, 0, 0, 0);
+                                    draw_calls += 1;
+                                });
+
+                            DebugLines::render(
+                                &mut renderer.debug_lines,
+                                &vk.device,
+                                &ctx,
+                                &renderer.state.pipelines,
+                                self.res.camera.proj_view_matrix(),
+                            );
+
+                            if hud_hidden {
+                                return;
+                            }
+                            // Held block last, on top of everything: clear depth and switch projection
+                            let clear_depth = vk::ClearAttachmentBuilder::new()
+                                .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                                .clear_value(vk::ClearValue {
+                                    depth_stencil: vk::ClearDepthStencilValue { depth: 0.0, stencil: 0 },
+                                });
+                            let whole_pass = vk::ClearRectBuilder::new()
+                                .rect(vk::Rect2D {
+                                    offset: vk::Offset2D { x: 0, y: 0 },
+                                    extent: passes.terrain.extent,
+                                })
+                                .layer_count(1);
+                            vk.device.cmd_clear_attachments(ctx.commands, &[clear_depth], &[whole_pass]);
+
+                            vk.device.cmd_bind_pipeline(
+                                ctx.commands,
+                                vk::PipelineBindPoint::GRAPHICS,
+                                terrain.handle,
+                            );
+                            vk.device.cmd_bind_descriptor_sets(
+                                ctx.commands,
+                                vk::PipelineBindPoint::GRAPHICS,
+                                terrain.layout,
+                                0,
+                                &[renderer.state.descriptors.textures.descriptor_set],
+                                &[],
+                            );
+                            vk.device.cmd_push_constants(
+                                ctx.commands,
+                                terrain.layout,
+                                vk::ShaderStageFlags::VERTEX,
+                                0,
+                                std::mem::size_of::<Mat4>() as u32,
+                                &held_pvm as *const Mat4 as *const c_void,
+                            );
+                            self.cube.draw(&vk.device, ctx.commands);
+                            draw_calls += 1;
+                        },
+                    );
+                }
+                Pass::Luma => {
+                    ctx.render_pass(&vk.device, &passes.luma, 0, Clear::None, || unsafe {
+                        vk.device.cmd_bind_pipeline(
                             ctx.commands,
-                            renderer.state.pipelines.terrain.layout,
-                            vk::ShaderStageFlags::VERTEX,
-                            0,
-                            std::mem::size_of::<Mat4>() as u32,
-                            pvm_ptr,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            renderer.state.pipelines.luma.handle,
+                        );
+                        vk.device.cmd_bind_descriptor_sets(
+                            ctx.commands,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            renderer.state.pipelines.luma.layout,
+                            1,
+                            &[renderer.state.descriptors.attachments.luma_descriptor_set],
+                            &[],
                         );
-                        vk.device
-                            .cmd_draw(ctx.commands, self.grid_vbo.vertex_count, 1, 0, 0);
-                    });
-            },
-        );
-
-        ctx.render_pass(&vk.device, &passes.luma, 0, Clear::None, || unsafe {
-            vk.device.cmd_bind_pipeline(
-                ctx.commands,
-                vk::PipelineBindPoint::GRAPHICS,
-                renderer.state.pipelines.luma.handle,
-            );
-            vk.device.cmd_bind_descriptor_sets(
-                ctx.commands,
-                vk::PipelineBindPoint::GRAPHICS,
-                renderer.state.pipelines.luma.layout,
-                1,
-                &[renderer.state.descriptors.attachments.luma_descriptor_set],
-                &[],
-            );
 
-            vk.device.cmd_draw(ctx.commands, 3, 1, 0, 0);
-        });
-        ctx.render_pass(
-            &vk.device,
-            &passes.fxaa,
-            ctx.swapchain_img_idx,
-            Clear::Color(0.0, 0.0, 0.0),
-            || unsafe {
-                vk.device.cmd_bind_pipeline(
-                    ctx.commands,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    renderer.state.pipelines.fxaa.handle,
-                );
-                vk.device.cmd_bind_descriptor_sets(
-                    ctx.commands,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    renderer.state.pipelines.fxaa.layout,
-                    1,
-                    &[renderer.state.descriptors.attachments.fxaa_descriptor_set],
-                    &[],
-                );
-
-                vk.device.cmd_draw(ctx.commands, 3, 1, 0, 0);
-            },
-        );
-        ctx.render_pass(
-            &vk.device,
-            &passes.ui.game,
-            ctx.swapchain_img_idx,
-            Clear::None,
-            || {
-                UiRenderer::render(
-                    &mut renderer.ui,
-                    &vk.device,
-                    &ctx,
-                    &renderer.state.pipelines,
-                    &renderer.state.descriptors,
-                    res.window_size.xy,
-                );
-            },
-        );
+                        vk.device.cmd_draw(ctx.commands, 3, 1, 0, 0);
+                        draw_calls += 1;
+                    });
+                }
+                Pass::Fxaa => {
+                    ctx.render_pass(
+                        &vk.device,
+                        &passes.fxaa,
+                        ctx.swapchain_img_idx,
+                        Clear::Color(0.0, 0.0, 0.0),
+                        || unsafe {
+                            vk.device.cmd_bind_pipeline(
+                                ctx.commands,
+                                vk::PipelineBindPoint::GRAPHICS,
+                                renderer.state.pipelines.fxaa.handle,
+                            );
+                            vk.device.cmd_push_constants(
+                                ctx.commands,
+                                renderer.state.pipelines.fxaa.layout,
+                                vk::ShaderStageFlags::FRAGMENT,
+                                0,
+                                std::mem::size_of::<f32>() as u32,
+                                &gamma as *const f32 as *const c_void,
+                            );
+                            vk.device.cmd_bind_descriptor_sets(
+                                ctx.commands,
+                                vk::PipelineBindPoint::GRAPHICS,
+                                renderer.state.pipelines.fxaa.layout,
+                                1,
+                                &[renderer.state.descriptors.attachments.fxaa_descriptor_set],
+                                &[],
+                            );
+
+                            vk.device.cmd_draw(ctx.commands, 3, 1, 0, 0);
+                            draw_calls += 1;
+                        },
+                    );
+                }
+                Pass::Ui => {
+                    ctx.render_pass(
+                        &vk.device,
+                        &passes.ui.game,
+                        ctx.swapchain_img_idx,
+                        Clear::None,
+                        || {
+                            UiRenderer::render(
+                                &mut renderer.ui,
+                                &vk.device,
+                                &ctx,
+                                &renderer.state.pipelines,
+                                &renderer.state.descriptors,
+                                res.window_size.xy,
+                            );
+                        },
+                    );
+                }
+            }
+        }
 
         renderer.end_frame(ctx);
+        self.draw_calls = draw_calls;
         Ok(())
     }
 }
@@ -563,6 +1538,7 @@ impl GameState {
 impl GameState {
     pub fn init(
         username: SharedStr,
+        server: String, // address as typed, for the window title
         login: LoginResponse,
         connection: Connection,
         res: &mut Resources,
@@ -576,6 +1552,12 @@ impl GameState {
             dt_secs: 0.0,
         };
 
+        let chunks = Chunks::new(
+            login.seed,
+            MAX_RENDER_DISTANCE,
+            ChunkPos::containing(login.position),
+        );
+
         Self {
             res: game_state::Resources {
                 username,
@@ -586,99 +1568,76 @@ impl GameState {
                     network_tick_count: 0,
                     next_network_tick: shared::TICK_DURATION.as_secs_f32(),
                     nid_to_entity_mapping: Vec::with_capacity(512),
+                    tombstones: Vec::new(),
+                    resync_requested: None,
+                    features: login.features,
+                    dimension: login.dimension,
                 },
-                camera: Camera::new(login.position, res.window_size.xy, f32::to_radians(80.0)),
+                camera: Camera::new(login.position, res.window_size.xy, (res.settings.graphics.fov as f32).to_radians()),
                 input_recorder: InputRecorder::new(login.position),
+                interaction_target: InteractionTarget::Nothing,
                 entities: ECS::new(),
-                chunks: Chunks::new(
-                    login.world_seed,
-                    24,
-                    login.position.as_ivec3().to_chunk_pos(),
-                ),
+                minimap: Minimap::new(&chunks),
+                chunks,
                 the_player: ThePlayer::new(login.position),
                 chunk_renderer: ChunkRenderer::new(),
             },
+            schedule: Self::build_schedule(),
             jitter_buf: JitterPrevention::new(),
             _artificial_delay: JitterPrevention::new(),
             is_network_tick: false,
+            server,
+            focused: true,
             packets_lost: 0,
+            connection_quality: ConnectionQuality::new(),
+            clock_sync: ClockSync::new(),
             packets_sent: 0,
             ping: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+            draw_calls: 0,
             mouse_move_accumulator: Vec2::ZERO,
-            grid_vbo: VertexBuffer {
-                buffer: Buffer::null(),
-                vertex_count: 0,
-            },
-            cube_vbo: VertexBuffer {
-                buffer: Buffer::null(),
-                vertex_count: 0,
-            },
+            debug_render: DebugRender::new(),
+            packet_inspector: PacketInspector::new(),
+            map_view: MapView::new(),
+            adaptive_distance: AdaptiveDistance::new(MIN_RENDER_DISTANCE, MAX_RENDER_DISTANCE, TARGET_FPS),
+            view_model: ViewModel::new(Block::STONE),
+            block_breaking: BlockBreaking::new(),
+            loading: Some(LoadingScreen::new()),
+            pause_menu: None,
+            camera_paths: CameraPaths::new(),
+            entity_culling: EntityCulling::new(),
+            chunk_budget: ChunkBudget::new(res.settings.graphics.chunk_memory_mb),
+            texture_layers: res.renderer.state.descriptors.textures.layers,
+            grid: IndexedMesh::null(),
+            cube: IndexedMesh::null(),
         }
     }
 }
 
-fn create_debug_grid(vk: &mut VkContext) -> anyhow::Result<VertexBuffer> {
+fn create_debug_grid(vk: &mut VkContext) -> anyhow::Result<IndexedMesh> {
     let mut vertices: Vec<Vertex> = Vec::new();
 
     for x in -50..50 {
         for z in -50..50 {
             let (x, z) = (x as f32 * 2.0, z as f32 * 2.0);
-            vertices.push(Vertex {
-                pos: Vec3::new(x, 0.0, z),
-                col: Vec3::ZERO,
-                uv: (Vec2::new(x, z) / 100.0 + 0.5) * 100.0 / 16.0,
-            });
-            vertices.push(Vertex {
-                pos: Vec3::new(x, 0.0, z + 1.0),
-                col: Vec3::ZERO,
-                uv: (Vec2::new(x, z + 1.0) / 100.0 + 0.5) * 100.0 / 16.0,
-            });
-            vertices.push(Vertex {
-                pos: Vec3::new(x + 1.0, 0.0, z),
-                col: Vec3::ZERO,
-                uv: (Vec2::new(x + 1.0, z) / 100.0 + 0.5) * 100.0 / 16.0,
-            });
-
-            vertices.push(Vertex {
-                pos: Vec3::new(x + 1.0, 0.0, z),
-                col: Vec3::ZERO,
-                uv: (Vec2::new(x + 1.0, z) / 100.0 + 0.5) * 100.0 / 16.0,
-            });
-            vertices.push(Vertex {
-                pos: Vec3::new(x, 0.0, z + 1.0),
-                col: Vec3::ZERO,
-                uv: (Vec2::new(x, z + 1.0) / 100.0 + 0.5) * 100.0 / 16.0,
-            });
-            vertices.push(Vertex {
-                pos: Vec3::new(x + 1.0, 0.0, z + 1.0),
-                col: Vec3::ZERO,
-                uv: (Vec2::new(x + 1.0, z + 1.0) / 100.0 + 0.5) * 100.0 / 16.0,
-            });
-        }
-    }
-
-    let mut buffer = vk.allocator.allocate_buffer(
-        &vk.device,
-        &BufferAllocation {
-            size: vertices.len() * std::mem::size_of::<Vertex>(),
-            usage: UsageFlags::FAST_DEVICE_ACCESS,
-            vk_usage: BufferUsageFlags::VERTEX_BUFFER,
-        },
-    )?;
-
-    vk.uploader
-        .upload_to_buffer(&vk.device, &vertices[..], &mut buffer, 0)?;
-
-    Ok(VertexBuffer {
-        buffer,
-        vertex_count: vertices.len() as u32,
-    })
+            // Corners in the order wrappers::quad_indices() expects
+            for (dx, dz) in [(0.0, 0.0), (0.0, 1.0), (1.0, 0.0), (1.0, 1.0)] {
+                vertices.push(Vertex {
+                    pos: Vec3::new(x + dx, 0.0, z + dz),
+                    col: Vec3::ZERO,
+                    uv: (Vec2::new(x + dx, z + dz) / 100.0 + 0.5) * 100.0 / 16.0,
+                });
+            }
+        }
+    }
+
+    let indices = quad_indices(vertices.len() as u32 / 4).collect::<Vec<_>>();
+    IndexedMesh::create(vk, &vertices, &indices, MemoryTag::Mesh)
 }
 
 #[rustfmt::skip]
-fn create_debug_cube(vk: &mut VkContext) -> anyhow::Result<VertexBuffer> {
-    let mut vertices: Vec<Vertex> = Vec::new();
-
+fn create_debug_cube(vk: &mut VkContext) -> anyhow::Result<IndexedMesh> {
     let corners = [
         Vertex { pos: Vec3::new(-0.5, -0.5, -0.5), col: Vec3::ZERO, uv: Vec2::ZERO },
         Vertex { pos: Vec3::new(-0.5, -0.5, 0.5), col: Vec3::ZERO, uv: Vec2::ZERO },
@@ -690,7 +1649,7 @@ fn create_debug_cube(vk: &mut VkContext) -> anyhow::Result<VertexBuffer> {
         Vertex { pos: Vec3::new(0.5, 0.5, 0.5), col: Vec3::ZERO, uv: Vec2::ZERO },
     ];
 
-    let indices = [
+    let indices: [[u32; 3]; 12] = [
         [0, 1, 2], [2, 1, 3], // -X
         [4, 6, 5], [5, 6, 7], // +X
         [0, 2, 4], [4, 2, 6], // -Z
@@ -699,24 +1658,5 @@ fn create_debug_cube(vk: &mut VkContext) -> anyhow::Result<VertexBuffer> {
         [0, 4, 1], [1, 4, 5], // -Y
     ];
 
-    for i in indices.iter().flatten().copied() {
-        vertices.push(corners[i]);
-    }
-
-    let mut buffer = vk.allocator.allocate_buffer(
-        &vk.device,
-        &BufferAllocation {
-            size: vertices.len() * std::mem::size_of::<Vertex>(),
-            usage: UsageFlags::FAST_DEVICE_ACCESS,
-            vk_usage: BufferUsageFlags::VERTEX_BUFFER,
-        },
-    )?;
-
-    vk.uploader
-        .upload_to_buffer(&vk.device, &vertices[..], &mut buffer, 0)?;
-
-    Ok(VertexBuffer {
-        buffer,
-        vertex_count: vertices.len() as u32,
-    })
+    IndexedMesh::create(vk, &corners, &indices.concat(), MemoryTag::Mesh)
 }
@@ -0,0 +1,76 @@
+// Lowers the view distance when frames take too long, and raises it back when there's
+// headroom. Only CPU frame times are available (no GPU timestamp queries yet), but those
+// include waiting for the GPU, so being GPU-bound shows up too.
+pub struct AdaptiveDistance {
+    pub enabled: bool,
+    pub min: u32,
+    pub max: u32,
+    pub target_fps: f32,
+
+    distance: u32,
+    smoothed_ms: f32,
+    cooldown: f32, // Seconds until the next change is allowed
+    headroom_secs: f32, // How long frame times have been well below target
+}
+
+impl AdaptiveDistance {
+    // Frame time above target * this -> lower
+    const LOWER_THRESHOLD: f32 = 1.15;
+    // Frame time below target * this for RAISE_AFTER_SECS -> raise
+    const RAISE_THRESHOLD: f32 = 0.75;
+    const RAISE_AFTER_SECS: f32 = 3.0;
+    const COOLDOWN_SECS: f32 = 1.0;
+
+    pub fn new(min: u32, max: u32, target_fps: f32) -> Self {
+        Self {
+            enabled: true,
+            min,
+            max,
+            target_fps,
+            distance: max,
+            smoothed_ms: 1000.0 / target_fps,
+            cooldown: 0.0,
+            headroom_secs: 0.0,
+        }
+    }
+
+    pub fn distance(&self) -> u32 {
+        self.distance
+    }
+
+    pub fn smoothed_frametime_ms(&self) -> f32 {
+        self.smoothed_ms
+    }
+
+    // Returns the distance to use this frame
+    pub fn update(&mut self, avg_frametime_ms: f32, dt_secs: f32) -> u32 {
+        if !self.enabled {
+            self.distance = self.max;
+            return self.distance;
+        }
+
+        // The input is already a rolling average; this smooths out spikes some more
+        self.smoothed_ms += (avg_frametime_ms - self.smoothed_ms) * (dt_secs * 2.0).min(1.0);
+        self.cooldown = (self.cooldown - dt_secs).max(0.0);
+
+        let target_ms = 1000.0 / self.target_fps;
+        if self.smoothed_ms < target_ms * Self::RAISE_THRESHOLD {
+            self.headroom_secs += dt_secs;
+        } else {
+            self.headroom_secs = 0.0;
+        }
+
+        if self.cooldown == 0.0 {
+            if self.smoothed_ms > target_ms * Self::LOWER_THRESHOLD && self.distance > self.min {
+                self.distance -= 1;
+                self.cooldown = Self::COOLDOWN_SECS;
+            } else if self.headroom_secs >= Self::RAISE_AFTER_SECS && self.distance < self.max {
+                self.distance += 1;
+                self.cooldown = Self::COOLDOWN_SECS;
+                self.headroom_secs = 0.0;
+            }
+        }
+        self.distance = self.distance.clamp(self.min, self.max);
+        self.distance
+    }
+}
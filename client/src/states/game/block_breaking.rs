@@ -0,0 +1,127 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use glam::{vec2, IVec3, Vec2, Vec3};
+use shared::{blocks, TICKS_PER_SECOND};
+
+use crate::{renderer::debug_lines::DebugLines, world::block::Block};
+
+// Holding the button on a block breaks it after shared::blocks::break_ticks(), with cracks
+// spreading over its faces meanwhile. Looking at another block or letting go starts over.
+// The server has the final say (see c2s::BlockAction::StartBreaking): the block only goes
+// away once the change comes back from it, so there is nothing to undo if it disagrees.
+// Until then, and for a short cooldown after each block, the cracks stay at full.
+pub struct BlockBreaking {
+    target: Option<Target>,
+    // The last block broken, until `cooldown_until`
+    broken: Option<(IVec3, Block)>,
+    cooldown_until: f32,
+}
+
+struct Target {
+    pos: IVec3,
+    block: Block,
+    started: f32,
+    secs: f32,
+}
+
+pub enum BreakEvent {
+    Start(IVec3),
+    Cancel,
+    Finish(IVec3),
+}
+
+// (normal, and the two axes along the face)
+const FACES: [(Vec3, Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::Y, Vec3::Z),
+    (Vec3::NEG_X, Vec3::Y, Vec3::Z),
+    (Vec3::Y, Vec3::X, Vec3::Z),
+    (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+    (Vec3::Z, Vec3::X, Vec3::Y),
+    (Vec3::NEG_Z, Vec3::X, Vec3::Y),
+];
+
+impl BlockBreaking {
+    const COOLDOWN_SECS: f32 = 0.25;
+    // The cracks on each face branch out four ways from the middle, this many segments in all
+    const CRACK_SEGMENTS: usize = 8;
+
+    pub fn new() -> Self {
+        Self { target: None, broken: None, cooldown_until: 0.0 }
+    }
+
+    pub fn is_breaking(&self) -> bool {
+        self.target.is_some()
+    }
+
+    // `looking_at`: the block under the crosshair, if within reach. Times are in seconds.
+    pub fn update(&mut self, holding: bool, looking_at: Option<(IVec3, Block)>, now: f32) -> Option<BreakEvent> {
+        let wanted = looking_at
+            .filter(|_| holding && now >= self.cooldown_until)
+            .and_then(|(pos, block)| Some((pos, block, blocks::break_ticks(block.raw())?)));
+
+        match (&self.target, wanted) {
+            (Some(target), Some((pos, block, _))) if target.pos == pos && target.block == block => {
+                if now - target.started < target.secs {
+                    return None;
+                }
+                self.target = None;
+                self.broken = Some((pos, block));
+                self.cooldown_until = now + Self::COOLDOWN_SECS;
+                Some(BreakEvent::Finish(pos))
+            }
+            (_, Some((pos, block, ticks))) => {
+                let secs = ticks as f32 / TICKS_PER_SECOND as f32;
+                self.target = Some(Target { pos, block, started: now, secs });
+                Some(BreakEvent::Start(pos))
+            }
+            (Some(_), None) => {
+                self.target = None;
+                Some(BreakEvent::Cancel)
+            }
+            (None, None) => None,
+        }
+    }
+
+    pub fn draw(&self, lines: &mut DebugLines, now: f32, block_at: impl Fn(IVec3) -> Option<Block>) {
+        let (pos, progress) = match (&self.target, self.broken) {
+            // Instant ones come out as NaN or infinity, which min() turns into 1 either way
+            (Some(target), _) => (target.pos, ((now - target.started) / target.secs).min(1.0)),
+            (None, Some((pos, block))) if now < self.cooldown_until && block_at(pos) == Some(block) => (pos, 1.0),
+            _ => return,
+        };
+        let segments = (progress * Self::CRACK_SEGMENTS as f32).ceil() as usize;
+
+        let center = pos.as_vec3() + 0.5;
+        let hash = (pos.x.wrapping_mul(73_856_093) ^ pos.y.wrapping_mul(19_349_663) ^ pos.z.wrapping_mul(83_492_791)) as u32;
+        for (face, &(normal, u, v)) in FACES.iter().enumerate() {
+            // Slightly in front of the face to avoid z-fighting with it
+            let to_world = |p: Vec2| center + normal * 0.502 + u * p.x + v * p.y;
+
+            // The same cracks every time for a given face, growing one segment at a time
+            let mut seed = (hash ^ (face as u32 + 1).wrapping_mul(0x9E37_79B9)) | 1;
+            let base_angle = random(&mut seed) * TAU;
+            let mut tips = [Vec2::ZERO; 4];
+            let mut angles = [0.0f32; 4];
+            for (branch, angle) in angles.iter_mut().enumerate() {
+                *angle = base_angle + branch as f32 * FRAC_PI_2;
+            }
+            for i in 0..segments {
+                let branch = i % tips.len();
+                angles[branch] += (random(&mut seed) - 0.5) * 1.2;
+                let length = 0.15 + random(&mut seed) * 0.15;
+                let from = tips[branch];
+                let to = (from + vec2(angles[branch].cos(), angles[branch].sin()) * length).clamp(Vec2::splat(-0.5), Vec2::splat(0.5));
+                lines.line(to_world(from), to_world(to), DebugLines::BLACK);
+                tips[branch] = to;
+            }
+        }
+    }
+}
+
+// Xorshift, 0..1
+fn random(seed: &mut u32) -> f32 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 17;
+    *seed ^= *seed << 5;
+    (*seed >> 8) as f32 / (1 << 24) as f32
+}
@@ -1,6 +1,6 @@
 use std::f32::consts::PI;
 
-use glam::{Mat4, Vec2, Vec3};
+use glam::{Mat4, Vec2, Vec3, Vec4};
 
 pub struct Camera {
     projection: Mat4,
@@ -15,12 +15,15 @@ pub struct Camera {
     pos: Vec3,
 
     fov: f32,
+    // Width over height, kept from the last window size that wasn't zero
+    aspect: f32,
 }
 
 impl Camera {
     pub fn new(pos: Vec3, win_size: Vec2, fov_rad: f32) -> Self {
         let facing = euler_to_vec(0.0, 0.0);
-        let projection = Self::create_projection_matrix(fov_rad, win_size);
+        let aspect = aspect_ratio(win_size).unwrap_or(16.0 / 9.0);
+        let projection = Self::create_projection_matrix(fov_rad, aspect);
         let view = Mat4::look_at_rh(pos, pos + facing, Vec3::Y);
         Camera {
             projection,
@@ -32,6 +35,7 @@ impl Camera {
             pitch: 0.0,
             pos,
             fov: fov_rad,
+            aspect,
         }
     }
 
@@ -57,13 +61,21 @@ impl Camera {
         self.right = compute_right(self.facing);
     }
 
-    pub fn set_fov(&mut self, fov_rad: f32, win_size: Vec2) {
+    pub fn set_fov(&mut self, fov_rad: f32) {
         self.fov = fov_rad;
-        self.on_window_resize(win_size);
+        self.projection = Self::create_projection_matrix(self.fov, self.aspect);
     }
 
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    // Minimizing resizes the window to zero, which keeps the old projection
     pub fn on_window_resize(&mut self, new_size: Vec2) {
-        self.projection = Self::create_projection_matrix(self.fov, new_size);
+        if let Some(aspect) = aspect_ratio(new_size) {
+            self.aspect = aspect;
+            self.projection = Self::create_projection_matrix(self.fov, aspect);
+        }
     }
 
     pub fn move_by(&mut self, velocity: Vec3) {
@@ -106,8 +118,39 @@ impl Camera {
         self.view
     }
 
-    fn create_projection_matrix(fov_rad: f32, win_size: Vec2) -> Mat4 {
-        Mat4::perspective_infinite_reverse_rh(fov_rad, win_size.x / win_size.y, 0.1)
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.proj_view)
+    }
+
+    fn create_projection_matrix(fov_rad: f32, aspect: f32) -> Mat4 {
+        Mat4::perspective_infinite_reverse_rh(fov_rad, aspect, 0.1)
+    }
+}
+
+fn aspect_ratio(win_size: Vec2) -> Option<f32> {
+    (win_size.x >= 1.0 && win_size.y >= 1.0).then(|| win_size.x / win_size.y)
+}
+
+// Side and near planes of the view frustum, pointing inwards. The projection is infinite,
+// so there is no far plane.
+pub struct Frustum {
+    planes: [Vec4; 5],
+}
+
+impl Frustum {
+    pub fn from_matrix(proj_view: Mat4) -> Self {
+        let (x, y, z, w) = (proj_view.row(0), proj_view.row(1), proj_view.row(2), proj_view.row(3));
+        // Reverse Z: the near plane is at depth 1, i.e. z <= w
+        let planes = [w + x, w - x, w + y, w - y, w - z].map(|plane| plane / plane.truncate().length());
+        Self { planes }
+    }
+
+    pub fn planes(&self) -> [Vec4; 5] {
+        self.planes
+    }
+
+    pub fn contains_sphere(&self, center: Vec3, radius: f32) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
     }
 }
 
@@ -0,0 +1,209 @@
+use std::{f32::consts::PI, fs, path::PathBuf};
+
+use anyhow::{bail, Context};
+use glam::Vec3;
+
+use super::camera::Camera;
+
+const CAMERA_PATH_DIR: &str = "config/camera_paths";
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub pos: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+// Keyframes of a flythrough, one per line as "x y z yaw pitch" on disk (angles in radians).
+// Yaw is kept unwrapped, i.e. it may leave -PI..PI, so that turning past the seam doesn't
+// make playback spin all the way around.
+#[derive(Clone, Default)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    // Flying speed during playback; short segments still take a while, so that turning
+    // on the spot doesn't happen instantly
+    const BLOCKS_PER_SEC: f32 = 8.0;
+    const MIN_SEGMENT_SECS: f32 = 1.0;
+
+    pub fn keyframe_count(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    pub fn push(&mut self, mut keyframe: Keyframe) {
+        if let Some(last) = self.keyframes.last() {
+            keyframe.yaw = last.yaw + wrap_angle(keyframe.yaw - last.yaw);
+        }
+        self.keyframes.push(keyframe);
+    }
+
+    pub fn duration_secs(&self) -> f32 {
+        self.keyframes.windows(2).map(|pair| Self::segment_secs(&pair[0], &pair[1])).sum()
+    }
+
+    // Catmull-Rom through the keyframes, with the end points repeated. None past the end.
+    pub fn sample(&self, mut secs: f32) -> Option<Keyframe> {
+        let frames = &self.keyframes;
+        for i in 0..frames.len().saturating_sub(1) {
+            let segment = Self::segment_secs(&frames[i], &frames[i + 1]);
+            if secs > segment {
+                secs -= segment;
+                continue;
+            }
+            let p0 = &frames[i.saturating_sub(1)];
+            let p3 = &frames[(i + 2).min(frames.len() - 1)];
+            let t = secs / segment;
+            return Some(Keyframe {
+                pos: catmull_rom(p0.pos, frames[i].pos, frames[i + 1].pos, p3.pos, t),
+                yaw: catmull_rom(p0.yaw, frames[i].yaw, frames[i + 1].yaw, p3.yaw, t),
+                pitch: catmull_rom(p0.pitch, frames[i].pitch, frames[i + 1].pitch, p3.pitch, t)
+                    .clamp(-PI / 2.0 + 0.001, PI / 2.0 - 0.001),
+            });
+        }
+        None
+    }
+
+    pub fn save(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let path = Self::path_of(name)?;
+        let text: String = self
+            .keyframes
+            .iter()
+            .map(|k| format!("{} {} {} {} {}\n", k.pos.x, k.pos.y, k.pos.z, k.yaw, k.pitch))
+            .collect();
+        fs::create_dir_all(CAMERA_PATH_DIR)?;
+        fs::write(&path, text).with_context(|| format!("can't write {}", path.display()))?;
+        Ok(path)
+    }
+
+    pub fn load(name: &str) -> anyhow::Result<Self> {
+        let path = Self::path_of(name)?;
+        let text = fs::read_to_string(&path).with_context(|| format!("can't read {}", path.display()))?;
+
+        let mut keyframes = Vec::new();
+        for (line_idx, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let values = line
+                .split_whitespace()
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>()
+                .ok()
+                .filter(|values| values.len() == 5);
+            let Some(v) = values else {
+                bail!("{}:{}: expected \"x y z yaw pitch\"", path.display(), line_idx + 1);
+            };
+            keyframes.push(Keyframe { pos: Vec3::new(v[0], v[1], v[2]), yaw: v[3], pitch: v[4] });
+        }
+        Ok(Self { keyframes })
+    }
+
+    // Names end up in file paths, so only allow what is safe there
+    fn path_of(name: &str) -> anyhow::Result<PathBuf> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+        if name.is_empty() || !name.chars().all(valid) {
+            bail!("bad path name '{name}': use letters, numbers, '_' and '-'");
+        }
+        Ok(PathBuf::from(format!("{CAMERA_PATH_DIR}/{name}.txt")))
+    }
+
+    fn segment_secs(from: &Keyframe, to: &Keyframe) -> f32 {
+        (from.pos.distance(to.pos) / Self::BLOCKS_PER_SEC).max(Self::MIN_SEGMENT_SECS)
+    }
+}
+
+// The path being recorded with `/path add`, and the one being played back, if any.
+// Playback only moves the camera: the player stays where it was, as far as the server
+// is concerned.
+pub struct CameraPaths {
+    recording: CameraPath,
+    playback: Option<(CameraPath, f32)>, // and seconds played
+}
+
+impl CameraPaths {
+    const USAGE: &str = "/path add | clear | save <name> | load <name> | play [name] | stop";
+
+    pub fn new() -> Self {
+        Self {
+            recording: CameraPath::default(),
+            playback: None,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    pub fn play(&mut self, path: CameraPath) -> anyhow::Result<()> {
+        if path.keyframe_count() < 2 {
+            bail!("a path needs at least 2 keyframes to play, has {}", path.keyframe_count());
+        }
+        self.playback = Some((path, 0.0));
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        self.playback = None;
+    }
+
+    // Where the camera should be this frame; None once playback ends
+    pub fn update(&mut self, dt_secs: f32) -> Option<Keyframe> {
+        let (path, secs) = self.playback.as_mut()?;
+        *secs += dt_secs;
+        let keyframe = path.sample(*secs);
+        if keyframe.is_none() {
+            self.playback = None;
+        }
+        keyframe
+    }
+
+    // `args` is everything after "/path". Returns the reply to show in chat.
+    pub fn run_command(&mut self, args: &str, camera: &Camera) -> anyhow::Result<String> {
+        let mut args = args.split_whitespace();
+        match (args.next(), args.next()) {
+            (Some("add"), None) => {
+                self.recording.push(Keyframe { pos: camera.pos(), yaw: camera.yaw(), pitch: camera.pitch() });
+                Ok(format!("Added keyframe {}", self.recording.keyframe_count()))
+            }
+            (Some("clear"), None) => {
+                self.recording = CameraPath::default();
+                Ok("Cleared the keyframes".to_owned())
+            }
+            (Some("save"), Some(name)) => {
+                let path = self.recording.save(name)?;
+                Ok(format!("Saved {} keyframes to {}", self.recording.keyframe_count(), path.display()))
+            }
+            (Some("load"), Some(name)) => {
+                self.recording = CameraPath::load(name)?;
+                Ok(format!("Loaded {} keyframes", self.recording.keyframe_count()))
+            }
+            (Some("play"), name) => {
+                let path = match name {
+                    Some(name) => CameraPath::load(name)?,
+                    None => self.recording.clone(),
+                };
+                let secs = path.duration_secs();
+                self.play(path)?;
+                Ok(format!("Playing {secs:.1}s, Escape to stop"))
+            }
+            (Some("stop"), None) => {
+                self.stop();
+                Ok("Stopped".to_owned())
+            }
+            _ => bail!("usage: {}", Self::USAGE),
+        }
+    }
+}
+
+fn catmull_rom<T>(p0: T, p1: T, p2: T, p3: T, t: f32) -> T
+where
+    T: Copy + std::ops::Add<Output = T> + std::ops::Sub<Output = T> + std::ops::Mul<f32, Output = T>,
+{
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0 + (p2 - p0) * t + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2 + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+fn wrap_angle(rad: f32) -> f32 {
+    (rad + PI).rem_euclid(2.0 * PI) - PI
+}
@@ -0,0 +1,88 @@
+use glam::{Vec3, Vec3Swizzles};
+
+use crate::world::{
+    chunk::{Chunk, CHUNK_SIZE},
+    dimension::Chunks,
+};
+
+use super::camera::Camera;
+
+// Keeps the chunk data within a memory budget, even if that means less than the whole view
+// distance is loaded. Once at the budget, chunks that haven't been in view for a while are
+// evicted, those seen longest ago and farthest away first. Evicted chunks stay unloaded
+// until they come into view again, so that generation and eviction don't chase each other.
+// There are no chunk meshes on the GPU yet; once there are, they should go with the chunk.
+pub struct ChunkBudget {
+    max_bytes: usize, // usize::MAX for no limit
+    evictions: u64,
+}
+
+impl ChunkBudget {
+    // Chunks seen this recently are never evicted
+    const GRACE_SECS: f32 = 5.0;
+    // Evicting stops at this fraction of the budget, so that there's room to generate into
+    const LOW_WATERMARK: f32 = 0.9;
+    // Bounding sphere of a chunk
+    const RADIUS: f32 = CHUNK_SIZE as f32 * 0.87;
+
+    // 0 for no limit
+    pub fn new(max_mb: u32) -> Self {
+        Self {
+            max_bytes: if max_mb == 0 { usize::MAX } else { (max_mb as usize) << 20 },
+            evictions: 0,
+        }
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    pub fn update(&mut self, chunks: &mut Chunks, camera: &Camera, secs: f32) {
+        let max_chunks = self.max_bytes / std::mem::size_of::<Chunk>();
+        chunks.set_max_loaded(max_chunks);
+
+        let frustum = camera.frustum();
+        let eye = camera.pos();
+        let view_distance = (chunks.view_distance() as usize * CHUNK_SIZE) as f32;
+        let in_view = |center: Vec3| {
+            center.xz().distance_squared(eye.xz()) <= view_distance * view_distance
+                && frustum.contains_sphere(center, Self::RADIUS)
+        };
+
+        let loaded = chunks.loaded_positions().collect::<Vec<_>>();
+        for &pos in &loaded {
+            if in_view(pos.center()) && let Some(chunk) = chunks.get_at_mut(pos) {
+                chunk.last_visible = secs;
+            }
+        }
+        let back_in_view = chunks.evicted().iter().copied().filter(|pos| in_view(pos.center())).collect::<Vec<_>>();
+        for pos in back_in_view {
+            chunks.restore(pos);
+        }
+
+        if chunks.loaded() < max_chunks {
+            return;
+        }
+        let mut candidates = loaded
+            .into_iter()
+            .filter_map(|pos| {
+                let last_visible = chunks.get_at(pos)?.last_visible;
+                (secs - last_visible > Self::GRACE_SECS).then(|| (last_visible, -pos.center().distance_squared(eye), pos))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_unstable_by(|a, b| (a.0, a.1).partial_cmp(&(b.0, b.1)).unwrap());
+
+        let target = (max_chunks as f32 * Self::LOW_WATERMARK) as usize;
+        for (_, _, pos) in candidates {
+            if chunks.loaded() <= target {
+                break;
+            }
+            chunks.evict(pos);
+            self.evictions += 1;
+        }
+    }
+}
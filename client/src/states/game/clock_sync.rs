@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+
+use shared::{jitter_prevention::DELAY_MS, TICK_DURATION};
+
+// Estimates the server's clock from the tick numbers it stamps into entity state messages
+// (Features::CLOCK_SYNC), so that network ticks can be lined up with the server's rather
+// than drifting along with the local clock.
+//
+// Each sample is the server time of a tick plus half the round trip, minus when it arrived:
+// the offset between the clocks, minus however long the message got held up on the way.
+// Delays only ever make a sample smaller, so the largest samples are the most accurate;
+// the estimate is a line fitted through the largest sample of each part of the window,
+// its slope being the drift between the clocks. How far typical samples fall below that
+// line is the jitter, which the interpolation delay is sized by.
+pub struct ClockSync {
+    samples: VecDeque<Sample>, // oldest first
+    rtt_secs: f64,
+    estimate: Option<Estimate>,
+}
+
+#[derive(Clone, Copy)]
+struct Sample {
+    local: f64,  // seconds since launch
+    offset: f64, // server time - local time
+}
+
+#[derive(Clone, Copy)]
+struct Estimate {
+    at: f64, // local time the offset is for
+    offset: f64,
+    drift: f64, // change in offset per second
+    jitter: f64,
+}
+
+impl ClockSync {
+    // With the server stamping every 8th tick, about 16 seconds' worth
+    const MAX_SAMPLES: usize = 64;
+    const BUCKETS: usize = 4;
+    // Clocks that disagree by more than 1% are broken, not drifting
+    const MAX_DRIFT: f64 = 0.01;
+    // Further off than this, the server has restarted its tick count or the client was
+    // suspended; start over
+    const MAX_JUMP_SECS: f64 = 1.0;
+    // How far apart consecutive ticks may be when lining up with the server, in ticks
+    const MIN_STEP: f64 = 0.75;
+    const MAX_STEP: f64 = 1.25;
+
+    pub fn new() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(Self::MAX_SAMPLES),
+            rtt_secs: 0.0,
+            estimate: None,
+        }
+    }
+
+    pub fn set_rtt(&mut self, rtt_ms: u32) {
+        self.rtt_secs = rtt_ms as f64 / 1000.0;
+    }
+
+    // `received`: local time in seconds since launch, as measured by the network thread
+    pub fn add_sample(&mut self, server_tick: u32, received: f64) {
+        let server = server_tick as f64 * TICK_DURATION.as_secs_f64();
+        let sample = Sample { local: received, offset: server + self.rtt_secs / 2.0 - received };
+
+        if let Some(estimate) = self.estimate && (estimate.offset_at(received) - sample.offset).abs() > Self::MAX_JUMP_SECS {
+            self.samples.clear();
+        }
+        if self.samples.len() == Self::MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        self.estimate = Self::estimate(&self.samples);
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.estimate.is_some()
+    }
+
+    pub fn server_secs(&self, local: f64) -> Option<f64> {
+        self.estimate.map(|estimate| local + estimate.offset_at(local))
+    }
+
+    pub fn local_secs(&self, server: f64) -> Option<f64> {
+        // server = local + offset + drift * (local - at), solved for local
+        self.estimate.map(|e| (server - e.offset + e.drift * e.at) / (1.0 + e.drift))
+    }
+
+    // When network tick `tick_count` should happen, the previous one having been due at
+    // `prev`. Lined up with the server's ticks once there's an estimate, moving at most a
+    // quarter tick per tick towards them; every TICK_DURATION from launch until then.
+    pub fn next_tick_at(&self, tick_count: u32, prev: f64) -> f64 {
+        let tick = TICK_DURATION.as_secs_f64();
+        let Some(server) = self.server_secs(prev) else {
+            return tick_count as f64 * tick;
+        };
+        // The server tick after the one closest to `prev`
+        let next = ((server / tick).round() + 1.0) * tick;
+        let next = self.local_secs(next).unwrap();
+        next.clamp(prev + Self::MIN_STEP * tick, prev + Self::MAX_STEP * tick)
+    }
+
+    // How long entity state is held back before being applied, see JitterPrevention
+    pub fn interpolation_delay_ms(&self) -> u32 {
+        let Some(estimate) = self.estimate else {
+            return DELAY_MS;
+        };
+        let tick_ms = TICK_DURATION.as_secs_f64() * 1000.0;
+        let delay = tick_ms + 2.0 * estimate.jitter * 1000.0;
+        delay.clamp(tick_ms, 4.0 * tick_ms) as u32
+    }
+
+    // (drift in parts per million, jitter in ms), for the debug HUD
+    pub fn stats(&self) -> Option<(f64, f64)> {
+        self.estimate.map(|estimate| (estimate.drift * 1e6, estimate.jitter * 1000.0))
+    }
+
+    fn estimate(samples: &VecDeque<Sample>) -> Option<Estimate> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        // The least delayed sample of each bucket
+        let (front, back) = samples.as_slices();
+        let all = [front, back].concat();
+        let bucket_len = (all.len() + Self::BUCKETS - 1) / Self::BUCKETS;
+        let points = all
+            .chunks(bucket_len)
+            .map(|bucket| *bucket.iter().max_by(|a, b| a.offset.total_cmp(&b.offset)).unwrap())
+            .collect::<Vec<_>>();
+
+        // Least squares line through them
+        let n = points.len() as f64;
+        let at = points.iter().map(|p| p.local).sum::<f64>() / n;
+        let offset = points.iter().map(|p| p.offset).sum::<f64>() / n;
+        let (mut covariance, mut variance) = (0.0, 0.0);
+        for p in &points {
+            covariance += (p.local - at) * (p.offset - offset);
+            variance += (p.local - at) * (p.local - at);
+        }
+        let drift = match variance > 1e-6 {
+            true => (covariance / variance).clamp(-Self::MAX_DRIFT, Self::MAX_DRIFT),
+            false => 0.0,
+        };
+
+        let mut estimate = Estimate { at, offset, drift, jitter: 0.0 };
+        // 90th percentile of how far below the line samples are
+        let mut residuals = all.iter().map(|s| (estimate.offset_at(s.local) - s.offset).max(0.0)).collect::<Vec<_>>();
+        residuals.sort_unstable_by(f64::total_cmp);
+        estimate.jitter = residuals[residuals.len() * 9 / 10];
+        Some(estimate)
+    }
+}
+
+impl Estimate {
+    fn offset_at(&self, local: f64) -> f64 {
+        self.offset + self.drift * (local - self.at)
+    }
+}
@@ -0,0 +1,143 @@
+use shared::TICKS_PER_SECOND;
+
+use crate::{renderer::ui_renderer::UiRenderer, settings::UiColors};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Quality {
+    Good,
+    Fair,
+    Poor,
+    Unresponsive, // No inputs acknowledged for a while
+}
+
+impl Quality {
+    fn color(self, colors: &UiColors) -> u32 {
+        match self {
+            Quality::Good => colors.good,
+            Quality::Fair => colors.fair,
+            Quality::Poor => colors.poor,
+            Quality::Unresponsive => (colors.poor & 0xFF_FF_FF_00) | 0x90,
+        }
+    }
+
+    fn bars(self) -> u16 {
+        match self {
+            Quality::Good => 3,
+            Quality::Fair => 2,
+            Quality::Poor | Quality::Unresponsive => 1,
+        }
+    }
+}
+
+// Tracks packet loss of the player state datagrams (as reported back by the server in
+// InputValidated) and decides how many older inputs to resend along with each new one.
+// Older inputs are only resent until the server acknowledges them, so this is an upper
+// limit rather than a fixed amount.
+pub struct ConnectionQuality {
+    // Exponentially decaying sums, weighted per input
+    lost: f32,
+    total: f32,
+    last_acked_tag: Option<u16>,
+    ticks_since_ack: u32,
+    high_loss: bool,
+    last_warned: Quality,
+}
+
+impl ConnectionQuality {
+    // Roughly the number of most recent inputs the loss rate is measured over
+    const WINDOW: f32 = 128.0;
+    // Hysteresis, so that redundancy doesn't flicker on and off around one value
+    const HIGH_LOSS_ON: f32 = 0.02;
+    const HIGH_LOSS_OFF: f32 = 0.005;
+
+    // As many as were always resent before loss was measured; only ever more than that
+    const LOW_LOSS_REDUNDANCY: usize = 6;
+    const HIGH_LOSS_REDUNDANCY: usize = 12;
+
+    const UNRESPONSIVE_AFTER_TICKS: u32 = 2 * TICKS_PER_SECOND;
+
+    pub fn new() -> Self {
+        Self {
+            lost: 0.0,
+            total: 0.0,
+            last_acked_tag: None,
+            ticks_since_ack: 0,
+            high_loss: false,
+            last_warned: Quality::Good,
+        }
+    }
+
+    pub fn on_input_validated(&mut self, tag: u16, packets_lost: u8) {
+        let inputs = match self.last_acked_tag {
+            Some(last) => tag.wrapping_sub(last).max(1) as f32,
+            None => 1.0,
+        };
+        self.last_acked_tag = Some(tag);
+        self.ticks_since_ack = 0;
+
+        let decay = (1.0 - 1.0 / Self::WINDOW).powf(inputs);
+        self.lost = self.lost * decay + packets_lost as f32;
+        self.total = self.total * decay + inputs.max(packets_lost as f32);
+
+        let loss = self.loss_ratio();
+        if loss > Self::HIGH_LOSS_ON {
+            self.high_loss = true;
+        } else if loss < Self::HIGH_LOSS_OFF {
+            self.high_loss = false;
+        }
+    }
+
+    pub fn on_network_tick(&mut self) {
+        self.ticks_since_ack = self.ticks_since_ack.saturating_add(1);
+    }
+
+    pub fn loss_ratio(&self) -> f32 {
+        if self.total > 0.0 { self.lost / self.total } else { 0.0 }
+    }
+
+    // Number of older inputs to include in each player state datagram
+    pub fn redundancy(&self) -> usize {
+        if self.high_loss {
+            Self::HIGH_LOSS_REDUNDANCY
+        } else {
+            Self::LOW_LOSS_REDUNDANCY
+        }
+    }
+
+    pub fn quality(&self, ping_ms: u32) -> Quality {
+        let loss = self.loss_ratio();
+        if self.ticks_since_ack > Self::UNRESPONSIVE_AFTER_TICKS {
+            Quality::Unresponsive
+        } else if loss < 0.01 && ping_ms < 100 {
+            Quality::Good
+        } else if loss < 0.05 && ping_ms < 250 {
+            Quality::Fair
+        } else {
+            Quality::Poor
+        }
+    }
+
+    // Some(quality) when it has dropped to Poor or worse since the last call, so that the
+    // player is warned once rather than every frame
+    pub fn new_warning(&mut self, ping_ms: u32) -> Option<Quality> {
+        let quality = self.quality(ping_ms);
+        let previous = std::mem::replace(&mut self.last_warned, quality);
+        let worse = match quality {
+            Quality::Poor => matches!(previous, Quality::Good | Quality::Fair),
+            Quality::Unresponsive => previous != Quality::Unresponsive,
+            Quality::Good | Quality::Fair => false,
+        };
+        worse.then_some(quality)
+    }
+
+    // Signal bars icon; `pos` is the bottom left corner
+    pub fn draw_icon(&self, ui: &mut UiRenderer, pos: (u16, u16), ping_ms: u32, colors: &UiColors) {
+        const EMPTY: u32 = 0x06_06_06_90;
+        let quality = self.quality(ping_ms);
+
+        for i in 0..3 {
+            let color = if i < quality.bars() { quality.color(colors) } else { EMPTY };
+            ui.draw_rect_xy_wh((pos.0 + i * 8, pos.1), (6, 8 + i * 6), color);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use glam::Vec3;
+use shared::coords::ChunkPos;
+
+use crate::{
+    components::{OldPosition, Position},
+    input::{Key, Keyboard},
+    renderer::{debug_lines::DebugLines, passes::terrain_pass::TerrainDrawMode},
+    world::{
+        block::BlockId,
+        chunk::CHUNK_SIZE,
+        dimension::{Chunks, ECS, WORLD_HEIGHT},
+        raycast::{raycast, RayHit},
+    },
+};
+
+// World-space debug visualizations, toggled with F3 + key
+pub struct DebugRender {
+    pub chunk_borders: bool,
+    pub hitboxes: bool,
+    pub raycast: bool,
+    pub terrain_mode: TerrainDrawMode,
+    // Frustum cull entities with a compute shader instead of on the CPU, if available
+    pub gpu_culling: bool,
+
+    pub last_hit: Option<RayHit>,
+}
+
+impl DebugRender {
+    pub const CHUNK_BORDERS_KEY: Key = Key::G;
+    pub const HITBOXES_KEY: Key = Key::B;
+    pub const RAYCAST_KEY: Key = Key::N;
+    pub const TERRAIN_MODE_KEY: Key = Key::M;
+    pub const GPU_CULLING_KEY: Key = Key::C;
+
+    const RAYCAST_DISTANCE: f32 = 64.0;
+    // Entities are drawn as unit cubes for now
+    const ENTITY_HALF_EXTENTS: Vec3 = Vec3::splat(0.5);
+
+    pub fn new() -> Self {
+        Self {
+            chunk_borders: false,
+            hitboxes: false,
+            raycast: false,
+            terrain_mode: TerrainDrawMode::Normal,
+            gpu_culling: false,
+            last_hit: None,
+        }
+    }
+
+    pub fn handle_input(&mut self, keyboard: &Keyboard) {
+        if !keyboard.pressed(Key::F3) {
+            return;
+        }
+        if keyboard.just_pressed(Self::CHUNK_BORDERS_KEY) {
+            self.chunk_borders = !self.chunk_borders;
+        }
+        if keyboard.just_pressed(Self::HITBOXES_KEY) {
+            self.hitboxes = !self.hitboxes;
+        }
+        if keyboard.just_pressed(Self::RAYCAST_KEY) {
+            self.raycast = !self.raycast;
+        }
+        if keyboard.just_pressed(Self::TERRAIN_MODE_KEY) {
+            self.terrain_mode = self.terrain_mode.next();
+        }
+        if keyboard.just_pressed(Self::GPU_CULLING_KEY) {
+            self.gpu_culling = !self.gpu_culling;
+        }
+    }
+
+    // `t`: interpolation factor between the previous and current entity positions
+    pub fn draw(&mut self, lines: &mut DebugLines, eye: Vec3, facing: Vec3, chunks: &Chunks, entities: &mut ECS, t: f32) {
+        if self.chunk_borders {
+            Self::draw_chunk_borders(lines, eye);
+        }
+
+        if self.hitboxes {
+            for (_, (old_pos, new_pos)) in entities.query_mut::<(&OldPosition, &Position)>() {
+                let pos = (new_pos.0 - old_pos.0) * t + old_pos.0;
+                lines.aabb(pos - Self::ENTITY_HALF_EXTENTS, pos + Self::ENTITY_HALF_EXTENTS, DebugLines::GREEN);
+            }
+        }
+
+        self.last_hit = None;
+        if self.raycast {
+            self.last_hit = raycast(eye, facing, Self::RAYCAST_DISTANCE, |pos| {
+                chunks.block_at(pos).map_or(false, |block| block.id() != BlockId::AIR)
+            });
+
+            if let Some(hit) = self.last_hit {
+                // Slightly larger than the block to avoid z-fighting with its faces
+                let min = hit.block_pos.as_vec3();
+                lines.aabb(min - 0.002, min + 1.002, DebugLines::WHITE);
+
+                let normal = hit.normal.as_vec3();
+                let face_center = min + 0.5 + normal * 0.5;
+                lines.line(face_center, face_center + normal * 0.75, DebugLines::CYAN);
+            }
+        }
+    }
+
+    fn draw_chunk_borders(lines: &mut DebugLines, eye: Vec3) {
+        let size = CHUNK_SIZE as i32;
+        let ChunkPos(chunk) = ChunkPos::containing(eye);
+
+        let min = (chunk * size).as_vec3();
+        lines.aabb(min, min + size as f32, DebugLines::YELLOW);
+
+        // Vertical lines at the corners of the surrounding chunk columns
+        for dx in -2..=3 {
+            for dz in -2..=3 {
+                let x = ((chunk.x + dx) * size) as f32;
+                let z = ((chunk.z + dz) * size) as f32;
+                lines.line(Vec3::new(x, 0.0, z), Vec3::new(x, WORLD_HEIGHT as f32, z), DebugLines::RED);
+            }
+        }
+    }
+}
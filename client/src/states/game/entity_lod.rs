@@ -0,0 +1,126 @@
+use std::f32::consts::PI;
+
+use glam::{EulerRot, Mat4, Vec3};
+use shared::protocol::NetworkId;
+
+use crate::{
+    components::{DroppedItem, HeadRotation, OldPosition, Position},
+    renderer::gpu_culling::CullCandidate,
+    world::dimension::ECS,
+};
+
+use super::camera::Camera;
+
+// Per-entity render state: the model matrix as of the last time it was computed
+pub struct EntityLod {
+    model: Mat4,
+    stale: bool, // must be recomputed before use
+}
+
+impl EntityLod {
+    pub fn new() -> Self {
+        Self {
+            model: Mat4::IDENTITY,
+            stale: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct EntityCounts {
+    pub drawn: u32,
+    pub reduced: u32, // of the drawn ones, how many reused an older transform
+    pub culled: u32,
+}
+
+// Decides which entities get drawn this frame and with which transform. Entities outside
+// the view frustum are skipped entirely; far away ones are only moved every few frames.
+// Once entities have animations, those get skipped at a distance as well.
+//
+// With `gpu` set, the frustum test is left to GpuCulling: every entity becomes a candidate
+// instead, and `counts().culled` stays 0 since the CPU never learns which ones were culled.
+pub struct EntityCulling {
+    models: Vec<Mat4>, // of the entities to draw this frame
+    candidates: Vec<CullCandidate>, // of every entity, when culling on the GPU
+    counts: EntityCounts,
+    frame: u32,
+}
+
+impl EntityCulling {
+    // Beyond this, entity transforms are updated every FAR_UPDATE_INTERVAL frames
+    const FULL_RATE_DISTANCE: f32 = 32.0;
+    const FAR_UPDATE_INTERVAL: u32 = 4;
+    // Bounding sphere of the entity cube
+    const RADIUS: f32 = 1.0;
+    // Dropped items are drawn as small cubes that spin and bob up and down
+    const ITEM_SCALE: f32 = 0.25;
+    const ITEM_SPIN_SPEED: f32 = 2.0; // radians per second
+    const ITEM_BOB_HEIGHT: f32 = 0.1;
+
+    pub fn new() -> Self {
+        Self {
+            models: Vec::new(),
+            candidates: Vec::new(),
+            counts: EntityCounts::default(),
+            frame: 0,
+        }
+    }
+
+    // `t`: how far between the previous and the latest network tick entities are,
+    // `time`: seconds since launch, for animating items
+    pub fn update(&mut self, entities: &mut ECS, camera: &Camera, t: f32, time: f32, gpu: bool) {
+        self.models.clear();
+        self.candidates.clear();
+        self.counts = EntityCounts::default();
+        self.frame = self.frame.wrapping_add(1);
+
+        let frustum = camera.frustum();
+        let query = entities.query_mut::<(&NetworkId, &OldPosition, &Position, &HeadRotation, Option<&DroppedItem>, &mut EntityLod)>();
+        for (_, (id, old_pos, new_pos, rot, item, lod)) in query {
+            let pos = (new_pos.0 - old_pos.0) * t + old_pos.0;
+            if !gpu && !frustum.contains_sphere(pos, Self::RADIUS) {
+                // Up to date again as soon as it comes into view
+                lod.stale = true;
+                self.counts.culled += 1;
+                continue;
+            }
+
+            let far = pos.distance_squared(camera.pos()) > Self::FULL_RATE_DISTANCE * Self::FULL_RATE_DISTANCE;
+            // Staggered by id, so that the far updates are spread evenly over frames
+            let due = self.frame.wrapping_add(id.raw() as u32) % Self::FAR_UPDATE_INTERVAL == 0;
+            if lod.stale || !far || due {
+                lod.model = match item {
+                    Some(_) => {
+                        // Offset by id so that items dropped together don't move in lockstep
+                        let phase = time + id.raw() as f32 * 0.7;
+                        let bob = Vec3::Y * (Self::ITEM_BOB_HEIGHT * (phase * 2.0).sin() + Self::ITEM_BOB_HEIGHT);
+                        Mat4::from_translation(pos + bob)
+                            * Mat4::from_rotation_y(phase * Self::ITEM_SPIN_SPEED)
+                            * Mat4::from_scale(Vec3::splat(Self::ITEM_SCALE))
+                    }
+                    None => Mat4::from_translation(pos) * Mat4::from_euler(EulerRot::YXZ, -rot.0.x + PI / 2.0, -rot.0.y, 0.0),
+                };
+                lod.stale = false;
+            } else {
+                self.counts.reduced += 1;
+            }
+            match gpu {
+                true => self.candidates.push(CullCandidate { model: lod.model, sphere: pos.extend(Self::RADIUS) }),
+                false => self.models.push(lod.model),
+            }
+            self.counts.drawn += 1;
+        }
+    }
+
+    pub fn models(&self) -> &[Mat4] {
+        &self.models
+    }
+
+    pub fn candidates(&self) -> &[CullCandidate] {
+        &self.candidates
+    }
+
+    pub fn counts(&self) -> EntityCounts {
+        self.counts
+    }
+}
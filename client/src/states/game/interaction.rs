@@ -0,0 +1,83 @@
+use glam::{IVec3, Vec3};
+use hecs::Entity;
+use shared::{blocks, protocol::NetworkId};
+
+use crate::{
+    components::{DroppedItem, Owned, Position, Username},
+    world::{
+        block::Block,
+        dimension::{Chunks, ECS},
+        raycast::raycast,
+    },
+};
+
+// What the crosshair is on, if it's within reach. Found once per frame, before the systems
+// that act on it (placing and breaking blocks, the interact key) and the crosshair and hint
+// that show it.
+#[derive(Clone, Copy, PartialEq)]
+pub enum InteractionTarget {
+    Nothing,
+    Block { pos: IVec3, block: Block, normal: IVec3 },
+    Entity { entity: Entity, id: NetworkId, kind: EntityKind },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EntityKind {
+    Player,
+    Item,
+    // Anything else, which the interact key tries to take control of or lets go of, see
+    // c2s::AuthorityMsg::Claim. Whether the server lets it is up to the server.
+    Other { owned: bool },
+}
+
+impl InteractionTarget {
+    // Entities are picked as unit cubes, like the debug hitboxes
+    const ENTITY_HALF_EXTENTS: Vec3 = Vec3::splat(0.5);
+
+    // `facing` must be normalized
+    pub fn find(eye: Vec3, facing: Vec3, reach: f32, chunks: &Chunks, entities: &mut ECS) -> Self {
+        // Only what can be broken; water and air are looked through
+        let hit = raycast(eye, facing, reach, |pos| {
+            chunks.block_at(pos).map_or(false, |block| blocks::break_ticks(block.raw()).is_some())
+        });
+        let mut nearest = hit.map_or(reach, |hit| hit.distance);
+        let mut target = hit
+            .and_then(|hit| Some(Self::Block { pos: hit.block_pos, block: chunks.block_at(hit.block_pos)?, normal: hit.normal }))
+            .unwrap_or(Self::Nothing);
+
+        for (entity, (&id, &Position(pos), player, item, owned)) in entities
+            .query_mut::<(&NetworkId, &Position, Option<&Username>, Option<&DroppedItem>, Option<&Owned>)>()
+        {
+            let Some(distance) = ray_box_distance(eye, facing, pos - Self::ENTITY_HALF_EXTENTS, pos + Self::ENTITY_HALF_EXTENTS) else {
+                continue;
+            };
+            if distance < nearest {
+                nearest = distance;
+                let kind = match (player, item) {
+                    (Some(_), _) => EntityKind::Player,
+                    (_, Some(_)) => EntityKind::Item,
+                    _ => EntityKind::Other { owned: owned.is_some() },
+                };
+                target = Self::Entity { entity, id, kind };
+            }
+        }
+        target
+    }
+
+    pub fn block(self) -> Option<(IVec3, Block)> {
+        match self {
+            Self::Block { pos, block, .. } => Some((pos, block)),
+            _ => None,
+        }
+    }
+}
+
+// Slab test. Zero if the ray starts inside the box.
+fn ray_box_distance(origin: Vec3, dir: Vec3, min: Vec3, max: Vec3) -> Option<f32> {
+    let inv = 1.0 / dir;
+    let (t1, t2) = ((min - origin) * inv, (max - origin) * inv);
+    // NaN on axes the ray is parallel to and starts right on a face of, which min and max skip
+    let near = t1.min(t2).max_element().max(0.0);
+    let far = t1.max(t2).min_element();
+    (near <= far).then_some(near)
+}
@@ -0,0 +1,57 @@
+use crate::{
+    localization::Localization,
+    renderer::{text_renderer::TextColor, ui_renderer::UiRenderer},
+    resources::core::WindowSize,
+    settings::UiColors,
+    theme::Theme,
+    tr,
+    world::dimension::Chunks,
+};
+
+// Covers the world after joining until the chunks around the spawn point exist, so that
+// the player can't move or look around in a world that is still being built up.
+pub struct LoadingScreen {
+    // (done, total) per step, as of the last update
+    terrain: (u32, u32),
+}
+
+impl LoadingScreen {
+    // Columns within this many chunks of the spawn point must be ready
+    const SPAWN_RADIUS: u32 = 4;
+    // Chunks generated per frame while loading; the frame rate doesn't matter here
+    const GENERATE_PER_FRAME: usize = 256;
+
+    const BAR_WIDTH: u16 = 600;
+    const BAR_HEIGHT: u16 = 24;
+
+    pub fn new() -> Self {
+        Self { terrain: (0, 1) }
+    }
+
+    // Returns true once everything is ready
+    pub fn update(&mut self, chunks: &mut Chunks) -> bool {
+        chunks.generate_nearest(Self::GENERATE_PER_FRAME);
+        self.terrain = chunks.area_progress(Self::SPAWN_RADIUS);
+        self.terrain.0 >= self.terrain.1
+    }
+
+    pub fn draw(&self, ui: &mut UiRenderer, lang: &Localization, theme: &Theme, win_size: &WindowSize, colors: &UiColors) {
+        let (w, h) = (win_size.extent.width as u16, win_size.extent.height as u16);
+        ui.draw_rect_xy_wh((0, 0), (w, h), theme.loading_background);
+
+        let text = TextColor::from_rgba32(colors.menu_text);
+        let x = w / 2 - Self::BAR_WIDTH / 2;
+        let mut y = h / 2 + 60;
+        let title = tr!(lang, "loading.title");
+        ui.draw_text_colored(title, w / 2 - ui.text().compute_width(title) / 2, y, text);
+
+        // Terrain is generated locally from the dimension's seed, so it's the only step for now.
+        // Received chunks and built meshes get their own bars once those exist.
+        let (done, total) = self.terrain;
+        y -= 70;
+        ui.draw_text_colored(&tr!(lang, "loading.terrain", done = done, total = total), x, y + 30, text);
+        ui.draw_rect_xy_wh((x, y), (Self::BAR_WIDTH, Self::BAR_HEIGHT), theme.loading_bar);
+        let filled = (Self::BAR_WIDTH as u32 * done.min(total) / total.max(1)) as u16;
+        ui.draw_rect_xy_wh((x, y), (filled, Self::BAR_HEIGHT), colors.good);
+    }
+}
@@ -0,0 +1,113 @@
+use glam::{ivec2, IVec2, Vec2, Vec3, Vec3Swizzles};
+use winit::event::MouseButton;
+
+use crate::{
+    components::Position,
+    input::{Key, Keyboard, Mouse},
+    renderer::ui_renderer::UiRenderer,
+    resources::core::WindowSize,
+    theme::Theme,
+    world::{dimension::ECS, minimap::Minimap},
+};
+
+// Corner minimap, and the fullscreen map (toggled with M) that can be panned with
+// WASD or by dragging, and zoomed with the scroll wheel. North (-Z) is up.
+pub struct MapView {
+    pub open: bool,
+    center: Vec2, // World XZ at the center of the fullscreen map
+    zoom: u16,    // Screen pixels per block
+}
+
+impl MapView {
+    pub const TOGGLE_KEY: Key = Key::M;
+
+    pub const MINIMAP_SIZE: u16 = 160;
+    const MINIMAP_ZOOM: u16 = 2;
+    const MAX_ZOOM: u16 = 16;
+    const PAN_SPEED: f32 = 600.0; // Screen pixels per second
+
+    const PLAYER_COLOR: u32 = 0xFF_FF_FF_FF;
+    const ENTITY_COLOR: u32 = 0xFF_D0_20_FF;
+
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            center: Vec2::ZERO,
+            zoom: 4,
+        }
+    }
+
+    pub fn handle_input(&mut self, keyboard: &Keyboard, mouse: &Mouse, player_pos: Vec3, dt_secs: f32) {
+        // F3+M is taken by the terrain debug views
+        if keyboard.just_pressed(Self::TOGGLE_KEY) && !keyboard.pressed(Key::F3) {
+            self.open = !self.open;
+            self.center = player_pos.xz();
+        }
+        if !self.open {
+            return;
+        }
+
+        let zoom = self.zoom as f32;
+        let pan = Vec2::new(
+            keyboard.get_axis(Key::D, Key::A) as f32,
+            keyboard.get_axis(Key::S, Key::W) as f32,
+        );
+        self.center += pan * Self::PAN_SPEED * dt_secs / zoom;
+        if mouse.pressed(MouseButton::Left) {
+            self.center -= mouse.pos_delta() / zoom;
+        }
+
+        let scroll = mouse.scroll_pos() - mouse.prev_scroll_pos();
+        if scroll > 0.0 && self.zoom < Self::MAX_ZOOM {
+            self.zoom *= 2;
+        } else if scroll < 0.0 && self.zoom > 1 {
+            self.zoom /= 2;
+        }
+    }
+
+    pub fn draw(&self, ui: &mut UiRenderer, theme: &Theme, minimap: &Minimap, player_pos: Vec3, entities: &ECS, win_size: &WindowSize) {
+        let (w, h) = (win_size.extent.width as u16, win_size.extent.height as u16);
+        let (origin, size, center, zoom) = if self.open {
+            ((0, 0), (w, h), self.center, self.zoom)
+        } else {
+            let s = Self::MINIMAP_SIZE;
+            // No room for it in a window this small
+            if w < s + 20 || h < s + 20 {
+                return;
+            }
+            ((w - s - 20, h - s - 20), (s, s), player_pos.xz(), Self::MINIMAP_ZOOM)
+        };
+
+        ui.draw_rect_xy_wh(origin, size, theme.panel_background);
+
+        let (cols, rows) = ((size.0 / zoom) as i32, (size.1 / zoom) as i32);
+        let min = center.floor().as_ivec2() - ivec2(cols / 2, rows / 2);
+
+        // The part of the view that the map texture covers, in blocks. Rows go down from the
+        // top edge of the view, north first, as do the texture's.
+        let map_min = minimap.corner_block();
+        let map_size = minimap.size() as i32;
+        let (lo, hi) = (min.max(map_min), (min + ivec2(cols, rows)).min(map_min + map_size));
+        if lo.cmplt(hi).all() {
+            let x = origin.0 + (lo.x - min.x) as u16 * zoom;
+            let y = origin.1 + size.1 - (hi.y - min.y) as u16 * zoom;
+            let wh = ((hi.x - lo.x) as u16 * zoom, (hi.y - lo.y) as u16 * zoom);
+            let (uv_lo, uv_hi) = ((lo - map_min).as_vec2() / map_size as f32, (hi - map_min).as_vec2() / map_size as f32);
+            ui.draw_map_rect((x, y), wh, Vec2::new(uv_lo.x, uv_hi.y), Vec2::new(uv_hi.x, uv_lo.y));
+        }
+
+        let marker = |ui: &mut UiRenderer, pos: Vec3, color: u32| {
+            let local = ((pos.xz() - min.as_vec2()) * zoom as f32).as_ivec2();
+            if local.cmplt(IVec2::splat(2)).any() || local.cmpgt(ivec2(size.0 as i32 - 2, size.1 as i32 - 2)).any() {
+                return;
+            }
+            let (x, y) = (origin.0 + local.x as u16, origin.1 + size.1 - local.y as u16);
+            ui.draw_rect_xy_wh((x - 2, y - 2), (4, 4), color);
+        };
+
+        for (_, pos) in entities.query::<&Position>().iter() {
+            marker(ui, pos.0, Self::ENTITY_COLOR);
+        }
+        marker(ui, player_pos, Self::PLAYER_COLOR);
+    }
+}
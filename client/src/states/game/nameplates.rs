@@ -0,0 +1,93 @@
+use glam::{Mat4, Vec3};
+
+use crate::{
+    components::{OldPosition, Position, Username},
+    renderer::text_renderer::{TextColor, TextRenderer},
+    world::{dimension::{Chunks, ECS}, raycast::raycast},
+};
+
+use super::camera::Camera;
+
+// Per-entity nameplate state. Entities without a username have no nameplate to show.
+pub struct Nameplate {
+    alpha: f32,
+}
+
+impl Nameplate {
+    pub fn new() -> Self {
+        Self { alpha: 0.0 }
+    }
+}
+
+// Above the top of the entity cube
+const HEIGHT: f32 = 0.85;
+const FADE_START_DISTANCE: f32 = 24.0;
+const MAX_DISTANCE: f32 = 48.0;
+// Alpha per second, towards the target
+const FADE_SPEED: f32 = 4.0;
+// World units per text pixel up close, so that text is about a quarter block tall
+const SCALE: f32 = 1.0 / 96.0;
+// Beyond this, the plates grow, shrinking on screen at half the rate
+const GROW_DISTANCE: f32 = 8.0;
+
+// Usernames above other players, drawn with the 3D text pass. They fade out with distance
+// and behind walls, and stay readable further away than they would at a fixed size.
+// `t`: how far between the previous and the latest network tick entities are
+pub fn draw(text: &mut TextRenderer, entities: &mut ECS, chunks: &Chunks, camera: &Camera, t: f32, dt: f32) {
+    TextRenderer::on_camera_change(text, camera.proj_view_matrix());
+
+    let eye = camera.pos();
+    let frustum = camera.frustum();
+    let (right, facing) = (camera.right(), camera.facing());
+    let up = right.cross(facing);
+
+    let query = entities.query_mut::<(&Username, &OldPosition, &Position, &mut Nameplate)>();
+    for (_, (Username(name), old_pos, new_pos, plate)) in query {
+        let pos = (new_pos.0 - old_pos.0) * t + old_pos.0 + Vec3::Y * HEIGHT;
+        let to_plate = pos - eye;
+        let distance = to_plate.length();
+
+        let mut target = 0.0;
+        if distance < MAX_DISTANCE && distance > 0.01 {
+            // Anything solid in between, not counting the block the plate is in
+            let occluded = raycast(eye, to_plate / distance, distance - 0.5, |pos| {
+                chunks.block_at(pos).map_or(false, |block| !block.id().is_transparent())
+            })
+            .is_some();
+            if !occluded {
+                let fade = (distance - FADE_START_DISTANCE) / (MAX_DISTANCE - FADE_START_DISTANCE);
+                target = 1.0 - smoothstep(fade.clamp(0.0, 1.0));
+            }
+        }
+
+        let step = FADE_SPEED * dt;
+        plate.alpha = if plate.alpha < target {
+            (plate.alpha + step).min(target)
+        } else {
+            (plate.alpha - step).max(target)
+        };
+
+        let alpha = (plate.alpha * 255.0) as u8;
+        // Alpha only has 3 bits in the text shader
+        if alpha < 32 || !frustum.contains_sphere(pos, 0.5) {
+            continue;
+        }
+
+        let scale = SCALE * (distance / GROW_DISTANCE).max(1.0).sqrt();
+        let width = text.compute_width(name) as f32;
+        let model = Mat4::from_cols(
+            (right * scale).extend(0.0),
+            (up * scale).extend(0.0),
+            (-facing * scale).extend(0.0),
+            pos.extend(1.0),
+        ) * Mat4::from_translation(Vec3::new(-width / 2.0, 0.0, 0.0));
+
+        if !text.draw_3d(name, model, TextColor::from_rgba(255, 255, 255, alpha)) {
+            break; // Out of room for this frame
+        }
+    }
+}
+
+fn smoothstep(x: f32) -> f32 {
+    x * x * (3.0 - 2.0 * x)
+}
@@ -0,0 +1,159 @@
+use std::{collections::VecDeque, time::{Duration, Instant}};
+
+use crate::{
+    input::{Key, Keyboard},
+    networking::packet_log::{PacketEntry, PacketLog},
+    renderer::{text_renderer::TextColor, ui_renderer::UiRenderer},
+    theme::Theme,
+};
+
+// Debug overlay listing the s2c messages as they arrive, with per-type rates, for working on
+// the protocol. F3+P opens it, which also starts the network thread's PacketLog; while open,
+// F3+O pauses the list and F3+I cycles through showing only one type of message.
+pub struct PacketInspector {
+    pub open: bool,
+    paused: bool,
+    filter: Option<&'static str>,
+
+    // Newest last
+    recent: VecDeque<PacketEntry>,
+    // Per message type, over the last RATE_WINDOW; sorted by name
+    rates: Vec<(&'static str, VecDeque<(Instant, u32)>)>,
+    // UDP bytes received so far as counted by quinn, sampled every frame over RATE_WINDOW
+    udp_window: VecDeque<(Instant, u64)>,
+    scratch: Vec<PacketEntry>,
+}
+
+impl PacketInspector {
+    pub const TOGGLE_KEY: Key = Key::P;
+    pub const PAUSE_KEY: Key = Key::O;
+    pub const FILTER_KEY: Key = Key::I;
+
+    const ROWS: usize = 24;
+    const RATE_WINDOW: Duration = Duration::from_secs(1);
+    const WIDTH: u16 = 560;
+
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            paused: false,
+            filter: None,
+            recent: VecDeque::with_capacity(Self::ROWS),
+            rates: Vec::new(),
+            udp_window: VecDeque::new(),
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn handle_input(&mut self, keyboard: &Keyboard, log: Option<&PacketLog>) {
+        if !keyboard.pressed(Key::F3) {
+            return;
+        }
+        if keyboard.just_pressed(Self::TOGGLE_KEY) {
+            self.open = !self.open;
+            if !self.open {
+                self.recent.clear();
+                self.rates.clear();
+                self.udp_window.clear();
+            }
+            if let Some(log) = log {
+                log.set_enabled(self.open);
+            }
+        }
+        if !self.open {
+            return;
+        }
+        if keyboard.just_pressed(Self::PAUSE_KEY) {
+            self.paused = !self.paused;
+        }
+        if keyboard.just_pressed(Self::FILTER_KEY) {
+            // All, then each type seen so far in turn
+            let next = match self.filter {
+                None => 0,
+                Some(kind) => self.rates.iter().position(|&(k, _)| k == kind).map_or(0, |i| i + 1),
+            };
+            self.filter = self.rates.get(next).map(|&(kind, _)| kind);
+        }
+    }
+
+    // `udp_bytes_received`: from S2C::Statistics
+    pub fn update(&mut self, log: &PacketLog, udp_bytes_received: u64, now: Instant) {
+        if !self.open {
+            return;
+        }
+        self.scratch.clear();
+        log.take(&mut self.scratch);
+
+        for entry in &self.scratch {
+            let i = match self.rates.binary_search_by(|(kind, _)| kind.cmp(&entry.kind)) {
+                Ok(i) => i,
+                Err(i) => {
+                    self.rates.insert(i, (entry.kind, VecDeque::new()));
+                    i
+                }
+            };
+            self.rates[i].1.push_back((entry.received, entry.bytes));
+
+            if !self.paused && self.filter.map_or(true, |kind| kind == entry.kind) {
+                if self.recent.len() == Self::ROWS {
+                    self.recent.pop_front();
+                }
+                self.recent.push_back(*entry);
+            }
+        }
+
+        for (_, window) in &mut self.rates {
+            while window.front().map_or(false, |&(at, _)| now.saturating_duration_since(at) > Self::RATE_WINDOW) {
+                window.pop_front();
+            }
+        }
+        self.udp_window.push_back((now, udp_bytes_received));
+        while self.udp_window.front().map_or(false, |&(at, _)| now.saturating_duration_since(at) > Self::RATE_WINDOW) {
+            self.udp_window.pop_front();
+        }
+    }
+
+    pub fn draw(&self, ui: &mut UiRenderer, theme: &Theme, window_width: u16, now: Instant) {
+        if !self.open {
+            return;
+        }
+        const KIB: f32 = 1024.0;
+        const GRAY: TextColor = TextColor::from_rgba32(0xA0_A0_A0_FF);
+        let x = window_width.saturating_sub(Self::WIDTH + 30).max(5);
+        let mut y = 30;
+        let mut line = |ui: &mut UiRenderer, text: &str, color: Option<TextColor>| {
+            ui.draw_rect_xy_wh((x - 5, y - 5), (Self::WIDTH, 30), theme.panel_background);
+            match color {
+                Some(color) => ui.draw_text_colored(text, x, y, color),
+                None => ui.draw_text(text, x, y),
+            };
+            y += 30;
+        };
+
+        let window_secs = Self::RATE_WINDOW.as_secs_f32();
+        let message_bytes: u32 = self.rates.iter().flat_map(|(_, window)| window.iter().map(|&(_, bytes)| bytes)).sum();
+        let udp_bytes = match (self.udp_window.front(), self.udp_window.back()) {
+            (Some(&(_, first)), Some(&(_, last))) => last.saturating_sub(first),
+            _ => 0,
+        };
+        line(ui, &format!("Packet inspector{} | F3+{:?} pause, F3+{:?} filter: {}",
+            if self.paused { " (paused)" } else { "" },
+            Self::PAUSE_KEY, Self::FILTER_KEY, self.filter.unwrap_or("all"),
+        ), None);
+        line(ui, &format!("{:.1} KiB/s in messages, {:.1} KiB/s UDP (compressed, with overhead)",
+            message_bytes as f32 / KIB / window_secs, udp_bytes as f32 / KIB / window_secs,
+        ), None);
+
+        for (kind, window) in &self.rates {
+            let bytes: u32 = window.iter().map(|&(_, bytes)| bytes).sum();
+            let color = (self.filter == Some(*kind)).then_some(TextColor::from_rgba32(0xFF_E0_60_FF));
+            line(ui, &format!("  {kind}: {:.0}/s, {:.2} KiB/s", window.len() as f32 / window_secs, bytes as f32 / KIB / window_secs), color);
+        }
+
+        // Newest first
+        for entry in self.recent.iter().rev() {
+            let age_ms = now.saturating_duration_since(entry.received).as_millis();
+            line(ui, &format!("{age_ms:>6} ms ago  {:<18}{:>6} B", entry.kind, entry.bytes), Some(GRAY));
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use winit::{event::MouseButton, window::CursorIcon};
+
+use crate::{
+    input::Key,
+    renderer::text_renderer::TextColor,
+    resources::Resources,
+    states::settings::{self, SettingsPanel, ROW_H, ROW_SPACING, ROW_W},
+    tr,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PauseAction {
+    Resume,
+    Disconnect,
+    Quit,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Button {
+    Resume,
+    Settings,
+    Disconnect,
+    Quit,
+}
+
+impl Button {
+    const ALL: [Button; 4] = [Button::Resume, Button::Settings, Button::Disconnect, Button::Quit];
+
+    fn label_key(self) -> &'static str {
+        match self {
+            Button::Resume => "pause.resume",
+            Button::Settings => "pause.settings",
+            Button::Disconnect => "pause.disconnect",
+            Button::Quit => "menu.quit",
+        }
+    }
+}
+
+// Opened with Escape over the running game. Only input is paused: the world keeps being
+// drawn and the connection keeps ticking, as the server doesn't stop for anyone.
+pub struct PauseMenu {
+    selected: usize,
+    hovered: Option<usize>,
+    settings: Option<SettingsPanel>,
+}
+
+impl PauseMenu {
+    pub fn new() -> Self {
+        Self {
+            selected: 0,
+            hovered: None,
+            settings: None,
+        }
+    }
+
+    // Escape: back out of the settings, or close the menu
+    pub fn back(&mut self, res: &mut Resources) -> Option<PauseAction> {
+        if self.settings.take().is_some() {
+            res.settings.save();
+            return None;
+        }
+        Some(PauseAction::Resume)
+    }
+
+    pub fn update(&mut self, res: &mut Resources) -> Option<PauseAction> {
+        let wsize = res.window_size.extent;
+        let wsize = (wsize.width as u16, wsize.height as u16);
+
+        if let Some(settings) = &mut self.settings {
+            if settings.update(res, wsize) {
+                self.back(res);
+            }
+            return None;
+        }
+
+        let mouse_pos = res.input.mouse.pos();
+        let hover = Self::get_hovering(wsize, (mouse_pos.x as u16, wsize.1.saturating_sub(mouse_pos.y as u16)));
+        self.hovered = hover;
+        if hover.is_some() {
+            res.cursor.request(CursorIcon::Hand);
+        }
+
+        let kb = &mut res.input.keyboard;
+        if kb.release(Key::Up) {
+            self.selected = (self.selected + Button::ALL.len() - 1) % Button::ALL.len();
+        }
+        if kb.release(Key::Down) || kb.release(Key::Tab) {
+            self.selected = (self.selected + 1) % Button::ALL.len();
+        }
+        if kb.release(Key::Return) || kb.release(Key::Space) {
+            return self.press(Button::ALL[self.selected]);
+        }
+        None
+    }
+
+    pub fn on_click(&mut self, button: MouseButton, res: &mut Resources) -> Option<PauseAction> {
+        if let Some(settings) = &mut self.settings {
+            if settings.on_click(button, res) {
+                self.back(res);
+            }
+            return None;
+        }
+
+        let idx = self.hovered?;
+        self.selected = idx;
+        if button == MouseButton::Left {
+            return self.press(Button::ALL[idx]);
+        }
+        None
+    }
+
+    fn press(&mut self, button: Button) -> Option<PauseAction> {
+        match button {
+            Button::Resume => Some(PauseAction::Resume),
+            Button::Settings => {
+                self.settings = Some(SettingsPanel::new());
+                self.hovered = None;
+                None
+            }
+            Button::Disconnect => Some(PauseAction::Disconnect),
+            Button::Quit => Some(PauseAction::Quit),
+        }
+    }
+
+    pub fn draw(&self, res: &mut Resources) {
+        let (w, h) = (res.window_size.extent.width as u16, res.window_size.extent.height as u16);
+        res.renderer.ui.draw_rect_xy_wh((0, 0), (w, h), res.theme.pause_background);
+
+        if let Some(settings) = &self.settings {
+            settings.draw(res, (w, h));
+            return;
+        }
+
+        let text = TextColor::from_rgba32(res.settings.accessibility.palette.colors().menu_text);
+        let title = tr!(res.lang, "pause.title");
+        let labels = Button::ALL.map(|button| res.lang.get(button.label_key()));
+        let (ui, theme) = (&mut res.renderer.ui, &res.theme);
+
+        let title_w = ui.text().compute_width(title);
+        ui.draw_text_colored(title, w / 2 - title_w / 2, h / 2 + 165, text);
+        for (idx, label) in labels.iter().enumerate() {
+            let highlight = (self.hovered == Some(idx), self.selected == idx);
+            settings::draw_row(ui, theme, label, text, w, Self::row_y(h, idx), highlight, None);
+        }
+    }
+
+    fn row_y(h: u16, idx: usize) -> u16 {
+        (h / 2 + 60).saturating_sub(idx as u16 * ROW_SPACING)
+    }
+
+    fn get_hovering(win_size: (u16, u16), mouse_xy: (u16, u16)) -> Option<usize> {
+        let (w, h) = win_size;
+        let (x, y) = mouse_xy;
+
+        if x < w / 2 - ROW_W / 2 || x > w / 2 + ROW_W / 2 {
+            return None;
+        }
+        (0..Button::ALL.len()).find(|&idx| {
+            let row_y = Self::row_y(h, idx);
+            y >= row_y && y <= row_y + ROW_H
+        })
+    }
+}
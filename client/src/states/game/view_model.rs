@@ -0,0 +1,78 @@
+use std::f32::consts::PI;
+
+use glam::{Mat4, Quat, Vec2, Vec3};
+use shared::movement::WALK_MAX_SPEED;
+
+use crate::world::block::Block;
+
+// The block held in the lower right corner of the screen. It has a projection of its own,
+// so the world FOV widening while sprinting doesn't stretch it, and it's drawn over
+// everything else in the terrain pass so it never sinks into walls.
+pub struct ViewModel {
+    pub held: Block,
+
+    bob_phase: f32,
+    bob_amount: f32, // 0 standing still, 1 at walking speed; eased
+    swing_t: Option<f32>, // 0..1 through the swing animation
+}
+
+impl ViewModel {
+    const FOV_DEGREES: f32 = 70.0;
+    const SWING_SECS: f32 = 0.25;
+    // Steps per second at walking speed
+    const BOB_FREQUENCY: f32 = 1.8;
+
+    pub fn new(held: Block) -> Self {
+        Self {
+            held,
+            bob_phase: 0.0,
+            bob_amount: 0.0,
+            swing_t: None,
+        }
+    }
+
+    // Restarts the swing if one is already going, so that fast clicks don't get lost
+    pub fn swing(&mut self) {
+        self.swing_t = Some(0.0);
+    }
+
+    pub fn is_swinging(&self) -> bool {
+        self.swing_t.is_some()
+    }
+
+    pub fn update(&mut self, velocity: Vec3, dt_secs: f32) {
+        let speed = (velocity.x * velocity.x + velocity.z * velocity.z).sqrt() / WALK_MAX_SPEED;
+        let ease = 1.0 - (-10.0 * dt_secs).exp();
+        self.bob_amount += (speed.min(1.5) - self.bob_amount) * ease;
+        self.bob_phase = (self.bob_phase + speed * Self::BOB_FREQUENCY * dt_secs) % 1.0;
+
+        if let Some(t) = &mut self.swing_t {
+            *t += dt_secs / Self::SWING_SECS;
+            if *t >= 1.0 {
+                self.swing_t = None;
+            }
+        }
+    }
+
+    // Projection * view-space transform of the held block, for the debug cube mesh
+    pub fn matrix(&self, win_size: Vec2) -> Mat4 {
+        let projection = Mat4::perspective_infinite_reverse_rh(
+            Self::FOV_DEGREES.to_radians(),
+            win_size.x / win_size.y,
+            0.05,
+        );
+
+        // Figure-eight, dipping down on every step
+        let angle = self.bob_phase * 2.0 * PI;
+        let bob = Vec3::new(angle.sin() * 0.04, -(angle * 2.0).sin().abs() * 0.03, 0.0) * self.bob_amount;
+
+        // Forward and down towards the crosshair, then back
+        let swing = self.swing_t.map_or(0.0, |t| (t * PI).sin());
+        let swing_offset = Vec3::new(-0.25, 0.1, -0.2) * swing;
+
+        projection
+            * Mat4::from_translation(Vec3::new(0.55, -0.5, -1.0) + bob + swing_offset)
+            * Mat4::from_quat(Quat::from_rotation_x(-swing * 0.9) * Quat::from_rotation_y(PI / 5.0))
+            * Mat4::from_scale(Vec3::splat(0.35))
+    }
+}
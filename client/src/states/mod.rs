@@ -1,3 +1,4 @@
 pub mod connection_lost;
 pub mod game;
+pub mod settings;
 pub mod username_query;
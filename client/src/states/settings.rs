@@ -0,0 +1,484 @@
+use anyhow::bail;
+use erupt::vk;
+use winit::{
+    event::{ElementState, Event, MouseButton, WindowEvent},
+    window::CursorIcon,
+};
+
+use crate::{
+    game::{State, StateChange},
+    input::{self, Key},
+    jobs::JobHandle,
+    renderer::{
+        descriptor_sets::Textures,
+        renderer::{Clear, OutdatedSwapchain, RendererState},
+        text_renderer::{TextColor, TextEffect},
+        ui_renderer::UiRenderer,
+    },
+    resources::Resources,
+    settings::{self, Graphics, Palette},
+    theme::Theme,
+    tr,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Row {
+    Language,
+    Brightness,
+    Fov,
+    ResourcePack,
+    Anisotropy,
+    Palette,
+    ChatBackground,
+    TextEffect,
+    Analytics,
+    Back,
+}
+
+impl Row {
+    const ALL: [Row; 10] = [
+        Row::Language,
+        Row::Brightness,
+        Row::Fov,
+        Row::ResourcePack,
+        Row::Anisotropy,
+        Row::Palette,
+        Row::ChatBackground,
+        Row::TextEffect,
+        Row::Analytics,
+        Row::Back,
+    ];
+}
+
+pub const ROW_W: u16 = 260;
+pub const ROW_H: u16 = 44;
+pub const ROW_SPACING: u16 = 46;
+
+// Opened from the main menu; returns to `previous` when closed
+pub struct SettingsState {
+    previous: Option<Box<dyn State>>,
+    panel: SettingsPanel,
+}
+
+// The title and the rows of settings, without a background. Also shown by the pause
+// menu in game.
+pub struct SettingsPanel {
+    selected: usize,
+    hovered: Option<usize>,
+    // Resource pack being decoded, switched to once done
+    loading_pack: Option<(Option<String>, JobHandle<anyhow::Result<Vec<u8>>>)>,
+}
+
+impl State for SettingsState {
+    fn on_enter(&mut self, res: &mut Resources) -> anyhow::Result<()> {
+        res.renderer
+            .set_present_mode(vk::PresentModeKHR::FIFO_KHR)?; // strong vsync
+        Ok(())
+    }
+
+    fn on_update(&mut self, res: &mut Resources) -> Option<Box<StateChange>> {
+        let wsize = res.window_size.extent;
+        let wsize = (wsize.width as u16, wsize.height as u16);
+
+        if res.input.keyboard.release(Key::Escape) || self.panel.update(res, wsize) {
+            return self.close();
+        }
+
+        Self::draw_frame(res, wsize);
+        self.panel.draw(res, wsize);
+
+        if let Err(e) = self.render(res) {
+            eprintln!("WARN: render() Err: {e}");
+        }
+
+        None
+    }
+
+    fn on_exit(&mut self, res: &mut Resources) -> anyhow::Result<()> {
+        res.settings.save();
+        res.input.keyboard.clear_all();
+        Ok(())
+    }
+
+    fn on_event(&mut self, event: &Event<()>, res: &mut Resources) -> Option<Box<StateChange>> {
+        if input::handle_event(event, &mut res.input) {
+            return None;
+        }
+
+        if let Event::WindowEvent {
+            event: WindowEvent::MouseInput { state: ElementState::Pressed, button, .. },
+            ..
+        } = event
+        {
+            if self.panel.on_click(*button, res) {
+                return self.close();
+            }
+        }
+        None
+    }
+}
+
+impl SettingsState {
+    pub fn new(previous: Box<dyn State>) -> Self {
+        Self {
+            previous: Some(previous),
+            panel: SettingsPanel::new(),
+        }
+    }
+
+    fn close(&mut self) -> Option<Box<StateChange>> {
+        let previous = self.previous.take()?;
+        Some(Box::new(StateChange::SwitchTo(previous)))
+    }
+}
+
+impl SettingsPanel {
+    pub fn new() -> Self {
+        Self {
+            selected: 0,
+            hovered: None,
+            loading_pack: None,
+        }
+    }
+
+    // Hover and keyboard navigation. Returns true if Back was chosen.
+    pub fn update(&mut self, res: &mut Resources, wsize: (u16, u16)) -> bool {
+        self.poll_resource_pack(res);
+
+        let mouse_pos = res.input.mouse.pos();
+        let hover = Self::get_hovering(wsize, (mouse_pos.x as u16, wsize.1.saturating_sub(mouse_pos.y as u16)));
+        self.hovered = hover;
+        if hover.is_some() {
+            res.cursor.request(CursorIcon::Hand);
+        }
+
+        let kb = &mut res.input.keyboard;
+        if kb.release(Key::Up) {
+            self.selected = (self.selected + Row::ALL.len() - 1) % Row::ALL.len();
+        }
+        if kb.release(Key::Down) || kb.release(Key::Tab) {
+            self.selected = (self.selected + 1) % Row::ALL.len();
+        }
+        if res.input.keyboard.release(Key::Left) {
+            self.change(Row::ALL[self.selected], -1, res);
+        }
+        if res.input.keyboard.release(Key::Right) {
+            self.change(Row::ALL[self.selected], 1, res);
+        }
+        let kb = &mut res.input.keyboard;
+        if kb.release(Key::Return) || kb.release(Key::Space) {
+            if Row::ALL[self.selected] == Row::Back {
+                return true;
+            }
+            self.change(Row::ALL[self.selected], 1, res);
+        }
+        false
+    }
+
+    fn poll_resource_pack(&mut self, res: &mut Resources) {
+        let Some((pack, job)) = &mut self.loading_pack else {
+            return;
+        };
+        let Some(bytes) = job.poll() else {
+            return;
+        };
+        let pack = pack.take();
+        self.loading_pack = None;
+        match bytes.and_then(|bytes| res.renderer.set_texture_array(pack.as_deref(), &bytes)) {
+            Ok(()) => res.settings.graphics.resource_pack = pack,
+            Err(e) => eprintln!("Failed to switch resource packs: {e:#}"),
+        }
+    }
+
+    // Returns true if Back was clicked
+    pub fn on_click(&mut self, button: MouseButton, res: &mut Resources) -> bool {
+        let Some(idx) = self.hovered else {
+            return false;
+        };
+        self.selected = idx;
+
+        match (Row::ALL[idx], button) {
+            (Row::Back, MouseButton::Left) => return true,
+            (row, MouseButton::Left) => self.change(row, 1, res),
+            (row, MouseButton::Right) => self.change(row, -1, res),
+            _ => {}
+        }
+        false
+    }
+
+    // Steps the setting on `row` forwards or backwards, wrapping around
+    fn change(&mut self, row: Row, dir: i32, res: &mut Resources) {
+        fn step<T: Copy + PartialEq>(all: &[T], current: T, dir: i32) -> T {
+            let idx = all.iter().position(|&v| v == current).unwrap_or(0) as i32;
+            all[(idx + dir).rem_euclid(all.len() as i32) as usize]
+        }
+
+        let a = &mut res.settings.accessibility;
+        match row {
+            Row::Language => res.lang.cycle_language(dir),
+            Row::Brightness => {
+                let g = &mut res.settings.graphics;
+                let gamma = (g.gamma * 10.0).round() + dir as f32;
+                g.gamma = (gamma / 10.0).clamp(Graphics::MIN_GAMMA, Graphics::MAX_GAMMA);
+            }
+            // The game picks it up the next frame, see update_camera()
+            Row::Fov => {
+                let g = &mut res.settings.graphics;
+                let fov = g.fov as i32 + dir * 5;
+                g.fov = fov.clamp(Graphics::MIN_FOV as i32, Graphics::MAX_FOV as i32) as u8;
+            }
+            Row::ResourcePack => {
+                // Steps from the one still loading if any, which then gets abandoned
+                let current = match &self.loading_pack {
+                    Some((pack, _)) => pack,
+                    None => &res.settings.graphics.resource_pack,
+                };
+                let mut packs = vec![None];
+                packs.extend(settings::resource_packs().into_iter().map(Some));
+                let idx = packs.iter().position(|pack| pack == current).unwrap_or(0) as i32;
+                let pack = packs.swap_remove((idx + dir).rem_euclid(packs.len() as i32) as usize);
+                let name = pack.clone();
+                let job = res.jobs.spawn(move || Textures::texture_array_bytes(name.as_deref()));
+                self.loading_pack = Some((pack, job));
+            }
+            Row::Anisotropy => {
+                // Only offers what the device supports
+                let max = res.renderer.vk.device.caps.max_anisotropy;
+                let levels = Graphics::ANISOTROPY_LEVELS.into_iter().filter(|&level| level as f32 <= max).collect::<Vec<_>>();
+                let g = &mut res.settings.graphics;
+                let level = step(&levels, g.anisotropy, dir);
+                if level != g.anisotropy {
+                    match res.renderer.set_anisotropy(level as f32) {
+                        Ok(()) => g.anisotropy = level,
+                        Err(e) => eprintln!("Failed to change anisotropic filtering: {e:#}"),
+                    }
+                }
+            }
+            Row::Palette => a.palette = step(&Palette::ALL, a.palette, dir),
+            Row::ChatBackground => {
+                let percent = (a.chat_background_opacity as i32 * 100 + 127) / 255;
+                let percent = ((percent + dir * 10) / 10 * 10).clamp(0, 100);
+                a.chat_background_opacity = ((percent * 255 + 50) / 100) as u8;
+            }
+            Row::TextEffect => {
+                a.text_effect = step(&TextEffect::ALL, a.text_effect, dir);
+                res.renderer.ui.text().set_effect(a.text_effect);
+            }
+            // Takes effect the next time in game
+            Row::Analytics => res.settings.local_analytics = !res.settings.local_analytics,
+            Row::Back => {}
+        }
+    }
+
+    fn row_label(row: Row, res: &Resources) -> String {
+        let (lang, a) = (&res.lang, &res.settings.accessibility);
+        match row {
+            Row::Language => tr!(lang, "settings.language", value = lang.language_name()),
+            Row::Brightness => {
+                tr!(lang, "settings.brightness", value = format!("{:.1}", res.settings.graphics.gamma))
+            }
+            Row::Fov => tr!(lang, "settings.fov", value = res.settings.graphics.fov),
+            Row::ResourcePack => {
+                let value = match &res.settings.graphics.resource_pack {
+                    Some(pack) => pack.as_str(),
+                    None => tr!(lang, "settings.resource_pack.default"),
+                };
+                tr!(lang, "settings.resource_pack", value = value)
+            }
+            Row::Anisotropy => {
+                let max = res.renderer.vk.device.caps.max_anisotropy;
+                let level = res.settings.graphics.anisotropy.min(max as u8);
+                let value = match (max > 1.0, level > 1) {
+                    (false, _) => tr!(lang, "settings.anisotropy.unsupported").to_owned(),
+                    (true, false) => tr!(lang, "settings.anisotropy.off").to_owned(),
+                    (true, true) => format!("{level}x"),
+                };
+                tr!(lang, "settings.anisotropy", value = value)
+            }
+            Row::Palette => {
+                let value = match a.palette {
+                    Palette::Default => tr!(lang, "settings.palette.default"),
+                    Palette::Deuteranopia => tr!(lang, "settings.palette.deuteranopia"),
+                    Palette::Tritanopia => tr!(lang, "settings.palette.tritanopia"),
+                    Palette::HighContrast => tr!(lang, "settings.palette.high_contrast"),
+                };
+                tr!(lang, "settings.palette", value = value)
+            }
+            Row::ChatBackground => {
+                let percent = (a.chat_background_opacity as u32 * 100 + 127) / 255;
+                tr!(lang, "settings.chat_background", value = percent)
+            }
+            Row::TextEffect => {
+                let value = match a.text_effect {
+                    TextEffect::None => tr!(lang, "settings.text_effect.none"),
+                    TextEffect::Shadow => tr!(lang, "settings.text_effect.shadow"),
+                    TextEffect::Outline => tr!(lang, "settings.text_effect.outline"),
+                };
+                tr!(lang, "settings.text_effect", value = value)
+            }
+            Row::Analytics => {
+                let value = match res.settings.local_analytics {
+                    true => tr!(lang, "settings.analytics.on"),
+                    false => tr!(lang, "settings.analytics.off"),
+                };
+                tr!(lang, "settings.analytics", value = value)
+            }
+            Row::Back => tr!(lang, "settings.back").to_owned(),
+        }
+    }
+
+    // 0..=1, for the rows that are sliders
+    fn slider_fraction(row: Row, res: &Resources) -> Option<f32> {
+        match row {
+            Row::Brightness => {
+                let gamma = res.settings.graphics.gamma;
+                Some((gamma - Graphics::MIN_GAMMA) / (Graphics::MAX_GAMMA - Graphics::MIN_GAMMA))
+            }
+            Row::Fov => {
+                let fov = (res.settings.graphics.fov - Graphics::MIN_FOV) as f32;
+                Some(fov / (Graphics::MAX_FOV - Graphics::MIN_FOV) as f32)
+            }
+            Row::ChatBackground => Some(res.settings.accessibility.chat_background_opacity as f32 / 255.0),
+            _ => None,
+        }
+    }
+
+    fn row_y(h: u16, idx: usize) -> u16 {
+        (h / 2 + 110).saturating_sub(idx as u16 * ROW_SPACING)
+    }
+
+    pub fn draw(&self, res: &mut Resources, win_size: (u16, u16)) {
+        let (w, h) = win_size;
+        let text = TextColor::from_rgba32(res.settings.accessibility.palette.colors().menu_text);
+
+        let labels = Row::ALL.map(|row| Self::row_label(row, res));
+        let sliders = Row::ALL.map(|row| Self::slider_fraction(row, res));
+        let title = tr!(res.lang, "settings.title");
+        let (ui, theme) = (&mut res.renderer.ui, &res.theme);
+
+        let title_w = ui.text().compute_width(title);
+        ui.draw_text_colored(title, w / 2 - title_w / 2, h / 2 + 165, text);
+
+        for (idx, label) in labels.iter().enumerate() {
+            let highlight = (self.hovered == Some(idx), self.selected == idx);
+            draw_row(ui, theme, label, text, w, Self::row_y(h, idx), highlight, sliders[idx]);
+        }
+    }
+
+    fn get_hovering(win_size: (u16, u16), mouse_xy: (u16, u16)) -> Option<usize> {
+        let (w, h) = win_size;
+        let (x, y) = mouse_xy;
+
+        if x < w / 2 - ROW_W / 2 || x > w / 2 + ROW_W / 2 {
+            return None;
+        }
+        (0..Row::ALL.len()).find(|&idx| {
+            let row_y = Self::row_y(h, idx);
+            y >= row_y && y <= row_y + ROW_H
+        })
+    }
+}
+
+// A button or setting centered horizontally in a window `win_w` wide, with its bottom
+// at `y`. `slider` is drawn as a bar along the top, if any.
+pub fn draw_row(
+    ui: &mut UiRenderer,
+    theme: &Theme,
+    label: &str,
+    text: TextColor,
+    win_w: u16,
+    y: u16,
+    (hovered, selected): (bool, bool),
+    slider: Option<f32>,
+) {
+    let w = win_w;
+    let (outline, fill) = match (hovered, selected) {
+        (true, _) => (theme.menu_hovered, theme.menu_selected),
+        (false, true) => (theme.menu_selected, theme.menu_selected),
+        (false, false) => (theme.menu_unselected, theme.menu_unselected),
+    };
+
+    let label_w = ui.text().compute_width(label);
+    ui.draw_text_colored(label, w / 2 - label_w / 2, y + 13, text);
+    ui.draw_rect_xy_wh((w / 2 - ROW_W / 2, y), (ROW_W, ROW_H), outline);
+    ui.draw_rect_xy_wh((w / 2 - ROW_W / 2 + 2, y + 2), (ROW_W - 4, ROW_H - 4), theme.menu_background);
+    ui.draw_rect_xy_wh((w / 2 - ROW_W / 2 + 4, y + 4), (ROW_W - 8, ROW_H - 8), fill);
+
+    if let Some(fraction) = slider {
+        let filled = ((ROW_W - 16) as f32 * fraction.clamp(0.0, 1.0)) as u16;
+        ui.draw_rect_xy_wh((w / 2 - ROW_W / 2 + 8, y + 6), (filled, 4), theme.menu_hovered);
+    }
+}
+
+impl SettingsState {
+    fn draw_frame(res: &mut Resources, win_size: (u16, u16)) {
+        let (w, h) = win_size;
+        let (x1, y1) = (0, 0);
+        let (x2, y2) = (w - 48, h - 48);
+        let (ui, theme) = (&mut res.renderer.ui, &res.theme);
+
+        // 4 corners
+        ui.draw_rect_xy_wh((x1, y1), (48, 48), theme.menu_selected);
+        ui.draw_rect_xy_wh((x1 + 16, y1 + 16), (16, 16), theme.menu_background);
+
+        ui.draw_rect_xy_wh((x1, y2), (48, 48), theme.menu_selected);
+        ui.draw_rect_xy_wh((x1 + 16, y2 + 16), (16, 16), theme.menu_background);
+
+        ui.draw_rect_xy_wh((x2, y1), (48, 48), theme.menu_selected);
+        ui.draw_rect_xy_wh((x2 + 16, y1 + 16), (16, 16), theme.menu_background);
+
+        ui.draw_rect_xy_wh((x2, y2), (48, 48), theme.menu_selected);
+        ui.draw_rect_xy_wh((x2 + 16, y2 + 16), (16, 16), theme.menu_background);
+
+        // Edges
+        ui.draw_rect_xy_wh((x1 + 64, y1), (x2 - x1 - 80, 32), theme.menu_unselected);
+        ui.draw_rect_xy_wh((x1 + 64, y2 + 16), (x2 - x1 - 80, 32), theme.menu_unselected);
+        ui.draw_rect_xy_wh((x1, y1 + 64), (32, y2 - y1 - 80), theme.menu_unselected);
+        ui.draw_rect_xy_wh((x2 + 16, y1 + 64), (32, y2 - y1 - 80), theme.menu_unselected);
+
+        ui.draw_rect_xy_wh((x1 + 80, y1), (x2 - x1 - 112, 16), theme.menu_background);
+        ui.draw_rect_xy_wh((x1 + 80, y2 + 32), (x2 - x1 - 112, 16), theme.menu_background);
+        ui.draw_rect_xy_wh((x1, y1 + 80), (16, y2 - y1 - 112), theme.menu_background);
+        ui.draw_rect_xy_wh((x2 + 32, y1 + 80), (16, y2 - y1 - 112), theme.menu_background);
+    }
+
+    fn render(&mut self, res: &mut Resources) -> anyhow::Result<()> {
+        let renderer = &mut res.renderer;
+        let ctx = match renderer.start_frame() {
+            Ok(ctx) => ctx,
+            Err(OutdatedSwapchain) => bail!("Outdated swapchain"),
+        };
+
+        if let Err(e) = UiRenderer::do_uploads(&mut renderer.ui, &mut renderer.vk, ctx.frame) {
+            bail!("UiRenderer failed to upload vertices: {e}");
+        };
+
+        let vk = &renderer.vk;
+        let RendererState {
+            descriptors,
+            render_passes,
+            pipelines,
+            framebuffers: _,
+        } = &renderer.state;
+
+        ctx.render_pass(
+            &vk.device,
+            &render_passes.ui.menu,
+            ctx.swapchain_img_idx,
+            Clear::Color(40.0 / 255.0, 38.0 / 255.0, 60.0 / 255.0),
+            || {
+                UiRenderer::render(
+                    &mut renderer.ui,
+                    &vk.device,
+                    &ctx,
+                    pipelines,
+                    descriptors,
+                    res.window_size.xy,
+                );
+            },
+        );
+
+        renderer.end_frame(ctx);
+        Ok(())
+    }
+}
@@ -11,19 +11,23 @@ use winit::{
 use crate::{
     game::{State, StateChange},
     input::{self, Key},
+    localization::Localization,
+    login_keys,
     networking::Connecting,
+    profiles::{self, Profiles},
     renderer::{
         renderer::{Clear, OutdatedSwapchain, RendererState},
         text_renderer::{self, ColorRange, TextColor},
         ui_renderer::UiRenderer,
     },
     resources::Resources,
-    text_box::{self, TextBox, TextBoxBuilder},
+    settings::UiColors,
+    text_box::{self, TextBox, TextBoxBuilder, Validator, Value},
+    theme::Theme,
+    tr,
 };
 
-use super::game::GameState;
-
-const ERR_COLOR: TextColor = TextColor::from_rgba(220, 50, 60, 255);
+use super::{game::GameState, settings::SettingsState};
 
 pub struct UsernameQueryState {
     username_box: TextBox,
@@ -35,7 +39,14 @@ pub struct UsernameQueryState {
     hovered: u32,
 
     message: String,
-    message_color: TextColor,
+    message_is_error: bool,
+
+    // Set when drawn, for hit testing the settings button
+    settings_label_width: u16,
+    // The profile dropdown in the bottom right corner, see Profiles. Widths set when drawn.
+    profile_menu_open: bool,
+    profile_label_width: u16,
+    profile_list_width: u16,
 }
 
 impl State for UsernameQueryState {
@@ -50,6 +61,19 @@ impl State for UsernameQueryState {
             .set_contents(&"localhost:29477".chars().collect::<Vec<char>>(), text, res.time.secs_f32);
         self.selected = 2; */
 
+        if self.username_box.contents().is_empty() && let Some(idx) = res.profiles.selected_index() {
+            self.fill_from_profile(res, idx);
+        }
+
+        // --username and --server, only the first time around
+        let text = res.renderer.ui.text();
+        if let Some(username) = res.args.username.take() {
+            self.username_box.set_contents(&username.chars().collect::<Vec<_>>(), text, res.time.secs_f32);
+        }
+        if let Some(server) = res.args.server.take() {
+            self.address_box.set_contents(&server.chars().collect::<Vec<_>>(), text, res.time.secs_f32);
+            self.press_join_button(&res.lang);
+        }
         Ok(())
     }
 
@@ -57,7 +81,6 @@ impl State for UsernameQueryState {
         &mut self,
         res: &mut crate::resources::Resources,
     ) -> Option<Box<crate::game::StateChange>> {
-        let renderer = &mut res.renderer;
         let wsize = res.window_size.extent;
         let wsize = (wsize.width as u16, wsize.height as u16);
 
@@ -71,36 +94,44 @@ impl State for UsernameQueryState {
             self.connecting.is_some(),
         );
 
-        if hover != self.hovered {
-            self.hovered = hover;
-            if hover != u32::MAX {
-                if (hover == 0 || hover == 1) && self.connecting.is_none() {
-                    res.window_handle.set_cursor_icon(CursorIcon::Text);
-                } else {
-                    res.window_handle.set_cursor_icon(CursorIcon::Hand);
-                }
+        self.hovered = hover;
+        let profile_hovered = self.connecting.is_none()
+            && (self.is_hovering_profiles(res) || self.hovered_profile(res).is_some());
+        if profile_hovered {
+            res.cursor.request(CursorIcon::Hand);
+        } else if hover != u32::MAX {
+            if (hover == 0 || hover == 1) && self.connecting.is_none() {
+                res.cursor.request(CursorIcon::Text);
             } else {
-                res.window_handle.set_cursor_icon(CursorIcon::Default);
+                res.cursor.request(CursorIcon::Hand);
             }
         }
 
         let kb = &mut res.input.keyboard;
         if self.connecting.is_some() {
             let anim_idx = (res.time.ms_u32 / 1000 % 4) as usize;
-            self.message = "Connecting".to_owned() + &"...   "[3 - anim_idx..6 - anim_idx];
+            self.message = match self.connecting.as_mut().unwrap().queue_position() {
+                Some(position) => tr!(res.lang, "menu.queued", position = position),
+                None => tr!(res.lang, "menu.connecting").to_owned(),
+            } + &"...   "[3 - anim_idx..6 - anim_idx];
 
             let mut error = false;
             match self.connecting.as_mut().unwrap().try_tick_connection() {
                 Ok(None) => {} // still connecting
                 Ok(Some((response, connection))) => {
-                    let username = self.username_box.contents().iter().collect();
-                    let new_state = GameState::init(username, response, connection, res);
+                    let username: String = self.username_box.contents().iter().collect();
+                    let server = self.address_box.contents().iter().collect::<String>().trim().to_owned();
+                    let idx = res.profiles.remember(&username, &server);
+                    if res.profiles.selected_index() != Some(idx) {
+                        profiles::select(res, idx);
+                    }
+                    let new_state = GameState::init(username, server, response, connection, res);
 
                     return Some(Box::new(StateChange::SwitchTo(Box::new(new_state))));
                 }
                 Err(err) => {
                     self.message = err.to_string();
-                    self.message_color = ERR_COLOR;
+                    self.message_is_error = true;
                     error = true;
                 }
             }
@@ -114,15 +145,32 @@ impl State for UsernameQueryState {
             }
         } else {
             if kb.release(Key::Return) || (self.selected == 2 && kb.release(Key::Space)) {
-                self.press_join_button();
+                self.press_join_button(&res.lang);
             }
 
             if self.selected == 3 && kb.release(Key::Space) {
                 return Some(Box::new(StateChange::Exit));
             }
+
+            // Quick switching
+            let count = res.profiles.list().len();
+            let step = match (kb.release(Key::PageUp), kb.release(Key::PageDown)) {
+                (true, false) => count.saturating_sub(1),
+                (false, true) => 1,
+                _ => 0,
+            };
+            if step != 0 && count > 0 {
+                let idx = res.profiles.selected_index().map_or(0, |idx| (idx + step) % count);
+                self.profile_menu_open = false;
+                self.switch_profile(res, idx);
+            }
         }
 
-        self.draw_ui(&mut renderer.ui, wsize, self.hovered, res.time.secs_f32);
+        let colors = res.settings.accessibility.palette.colors();
+        let profile_hovered = self.hovered_profile(res);
+        let renderer = &mut res.renderer;
+        self.draw_ui(&mut renderer.ui, &res.lang, &res.theme, colors, wsize, self.hovered, res.time.secs_f32);
+        self.draw_profiles(&mut renderer.ui, &res.lang, &res.theme, &res.profiles, colors, wsize, profile_hovered);
 
         if let Err(e) = self.render(res) {
             eprintln!("WARN: render() Err: {e}");
@@ -132,7 +180,6 @@ impl State for UsernameQueryState {
     }
 
     fn on_exit(&mut self, res: &mut crate::resources::Resources) -> anyhow::Result<()> {
-        res.window_handle.set_cursor_icon(CursorIcon::Default);
         res.input.keyboard.clear_all();
         Ok(())
     }
@@ -171,6 +218,28 @@ impl State for UsernameQueryState {
                 }
             }
 
+            WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Left, .. }
+                if self.connecting.is_none() && self.is_hovering_profiles(res) =>
+            {
+                self.profile_menu_open = !self.profile_menu_open;
+            }
+
+            WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Left, .. }
+                if self.profile_menu_open =>
+            {
+                if let Some(idx) = self.hovered_profile(res) {
+                    self.switch_profile(res, idx);
+                }
+                self.profile_menu_open = false;
+            }
+
+            WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Left, .. }
+                if self.connecting.is_none() && self.is_hovering_settings(res) =>
+            {
+                let menu = std::mem::replace(self, Self::new().unwrap());
+                return Some(Box::new(StateChange::SwitchTo(Box::new(SettingsState::new(Box::new(menu))))));
+            }
+
             WindowEvent::MouseInput { state, button, .. } => {
                 if self.hovered != u32::MAX
                     && *state == ElementState::Pressed
@@ -199,7 +268,7 @@ impl State for UsernameQueryState {
                         }
                     } else {
                         if self.hovered == 2 {
-                            self.press_join_button();
+                            self.press_join_button(&res.lang);
                         }
                         if self.hovered == 3 {
                             return Some(Box::new(StateChange::Exit));
@@ -215,7 +284,27 @@ impl State for UsernameQueryState {
 }
 
 impl UsernameQueryState {
-    fn press_join_button(&mut self) {
+    fn switch_profile(&mut self, res: &mut Resources, idx: usize) {
+        profiles::select(res, idx);
+        self.fill_from_profile(res, idx);
+        self.message.clear();
+    }
+
+    fn fill_from_profile(&mut self, res: &mut Resources, idx: usize) {
+        let Some(profile) = res.profiles.list().get(idx) else {
+            return;
+        };
+        let username = profile.username.chars().collect::<Vec<_>>();
+        let server = profile.server.chars().collect::<Vec<_>>();
+        let text = res.renderer.ui.text();
+        self.username_box.set_contents(&username, text, res.time.secs_f32);
+        self.address_box.set_contents(&server, text, res.time.secs_f32);
+        if !server.is_empty() {
+            self.selected = 2; // Join button
+        }
+    }
+
+    fn press_join_button(&mut self, lang: &Localization) {
         if self.connecting.is_some() {
             panic!("Bug: press_join_button() but self.connecting.is_some()");
         }
@@ -224,99 +313,119 @@ impl UsernameQueryState {
 
         let username: String = self.username_box.contents().iter().collect();
         if username.len() < 3 {
-            self.message = "Username is too short".to_owned();
-            self.message_color = ERR_COLOR;
+            self.message = tr!(lang, "menu.username_too_short").to_owned();
+            self.message_is_error = true;
             return;
         }
 
-        let address_str: String = self.address_box.contents().iter().collect();
-        println!("Parsing '{address_str}'");
-        let address = match address_str.trim().to_socket_addrs() {
+        let (host, port) = match self.address_box.value() {
+            Ok(Value::HostPort(host, port)) => (host, port),
+            Ok(_) => unreachable!(),
+            Err(e) => {
+                self.message = tr!(lang, "menu.invalid_address", error = e.message(lang));
+                self.message_is_error = true;
+                return;
+            }
+        };
+        println!("Resolving '{host}' port {port}");
+        let address = match (host.as_str(), port).to_socket_addrs() {
             Ok(mut iter) => match iter.next() {
                 Some(address) => address,
                 None => {
-                    self.message = format!("No such address");
-                    self.message_color = ERR_COLOR;
+                    self.message = tr!(lang, "menu.no_such_address").to_owned();
+                    self.message_is_error = true;
                     return;
                 }
             },
             Err(e) => {
-                self.message = format!("Invalid address: {e}");
-                self.message_color = ERR_COLOR;
+                self.message = tr!(lang, "menu.invalid_address", error = e);
+                self.message_is_error = true;
                 return;
             }
         };
 
+        let server = self.address_box.contents().iter().collect::<String>().trim().to_owned();
         self.connecting = Some(Connecting::init_connection(
             address,
             username.to_shared_str(),
+            login_keys::key_for(&username, &server),
         ));
-        self.message = "Connecting...".to_owned();
-        self.message_color = TextColor::from_rgba32(0xa7a4bfFF);
+        self.message = tr!(lang, "menu.connecting").to_owned() + "...";
+        self.message_is_error = false;
     }
 
-    fn draw_ui(&mut self, ui: &mut UiRenderer, win_size: (u16, u16), hover: u32, time_secs: f32) {
+    fn draw_ui(
+        &mut self,
+        ui: &mut UiRenderer,
+        lang: &Localization,
+        theme: &Theme,
+        colors: &UiColors,
+        win_size: (u16, u16),
+        hover: u32,
+        time_secs: f32,
+    ) {
         let (w, h) = win_size;
         let (x1, y1) = (0, 0);
         let (x2, y2) = (w - 48, h - 48);
 
-        const TEXT: TextColor = TextColor::from_rgba32(0xa7a4bfFF);
-        const SELECTED: u32 = 0x4c4964FF;
-        const UNSELECTED: u32 = 0x3c3a53FF;
-        const HOVERED: u32 = 0x5d5b7aFF;
-
+        let text = TextColor::from_rgba32(colors.menu_text);
         let mut tbox_style = text_box::Style {
-            cursor_color: 0xa7a4bfFF,
-            text_color: TEXT,
+            cursor_color: theme.menu_cursor,
+            text_color: text,
+            error_color: TextColor::from_rgba32(colors.error),
+            selection_color: theme.text_selection,
         };
+        let error_color = tbox_style.error_color;
 
         // (Outline, fill)
-        let mut colors = [(UNSELECTED, UNSELECTED); 4];
-        colors[self.selected as usize] = (SELECTED, SELECTED);
+        let mut colors = [(theme.menu_unselected, theme.menu_unselected); 4];
+        colors[self.selected as usize] = (theme.menu_selected, theme.menu_selected);
 
         if hover != u32::MAX {
-            colors[hover as usize] = (HOVERED, SELECTED);
+            colors[hover as usize] = (theme.menu_hovered, theme.menu_selected);
         }
 
         let mut selected = self.selected;
 
         if self.connecting.is_some() {
             selected = u32::MAX;
-            colors = [(UNSELECTED, 0x302F43FF); 4];
-            tbox_style.text_color = TextColor::from_rgba32(0x4c4964FF);
+            colors = [(theme.menu_unselected, theme.menu_disabled); 4];
+            tbox_style.text_color = TextColor::from_rgba32(theme.menu_selected);
         }
 
         // 4 corners
-        ui.draw_rect_xy_wh((x1, y1), (48, 48), 0x4c4964FF);
-        ui.draw_rect_xy_wh((x1 + 16, y1 + 16), (16, 16), 0x28263cFF);
+        ui.draw_rect_xy_wh((x1, y1), (48, 48), theme.menu_selected);
+        ui.draw_rect_xy_wh((x1 + 16, y1 + 16), (16, 16), theme.menu_background);
 
-        ui.draw_rect_xy_wh((x1, y2), (48, 48), 0x4c4964FF);
-        ui.draw_rect_xy_wh((x1 + 16, y2 + 16), (16, 16), 0x28263cFF);
+        ui.draw_rect_xy_wh((x1, y2), (48, 48), theme.menu_selected);
+        ui.draw_rect_xy_wh((x1 + 16, y2 + 16), (16, 16), theme.menu_background);
 
-        ui.draw_rect_xy_wh((x2, y1), (48, 48), 0x4c4964FF);
-        ui.draw_rect_xy_wh((x2 + 16, y1 + 16), (16, 16), 0x28263cFF);
+        ui.draw_rect_xy_wh((x2, y1), (48, 48), theme.menu_selected);
+        ui.draw_rect_xy_wh((x2 + 16, y1 + 16), (16, 16), theme.menu_background);
 
-        ui.draw_rect_xy_wh((x2, y2), (48, 48), 0x4c4964FF);
-        ui.draw_rect_xy_wh((x2 + 16, y2 + 16), (16, 16), 0x28263cFF);
+        ui.draw_rect_xy_wh((x2, y2), (48, 48), theme.menu_selected);
+        ui.draw_rect_xy_wh((x2 + 16, y2 + 16), (16, 16), theme.menu_background);
 
         // Edges
-        ui.draw_rect_xy_wh((x1 + 64, y1), (x2 - x1 - 80, 32), 0x3c3a53FF);
-        ui.draw_rect_xy_wh((x1 + 64, y2 + 16), (x2 - x1 - 80, 32), 0x3c3a53FF);
-        ui.draw_rect_xy_wh((x1, y1 + 64), (32, y2 - y1 - 80), 0x3c3a53FF);
-        ui.draw_rect_xy_wh((x2 + 16, y1 + 64), (32, y2 - y1 - 80), 0x3c3a53FF);
+        ui.draw_rect_xy_wh((x1 + 64, y1), (x2 - x1 - 80, 32), theme.menu_unselected);
+        ui.draw_rect_xy_wh((x1 + 64, y2 + 16), (x2 - x1 - 80, 32), theme.menu_unselected);
+        ui.draw_rect_xy_wh((x1, y1 + 64), (32, y2 - y1 - 80), theme.menu_unselected);
+        ui.draw_rect_xy_wh((x2 + 16, y1 + 64), (32, y2 - y1 - 80), theme.menu_unselected);
 
-        ui.draw_rect_xy_wh((x1 + 80, y1), (x2 - x1 - 112, 16), 0x28263cFF);
-        ui.draw_rect_xy_wh((x1 + 80, y2 + 32), (x2 - x1 - 112, 16), 0x28263cFF);
-        ui.draw_rect_xy_wh((x1, y1 + 80), (16, y2 - y1 - 112), 0x28263cFF);
-        ui.draw_rect_xy_wh((x2 + 32, y1 + 80), (16, y2 - y1 - 112), 0x28263cFF);
+        ui.draw_rect_xy_wh((x1 + 80, y1), (x2 - x1 - 112, 16), theme.menu_background);
+        ui.draw_rect_xy_wh((x1 + 80, y2 + 32), (x2 - x1 - 112, 16), theme.menu_background);
+        ui.draw_rect_xy_wh((x1, y1 + 80), (16, y2 - y1 - 112), theme.menu_background);
+        ui.draw_rect_xy_wh((x2 + 32, y1 + 80), (16, y2 - y1 - 112), theme.menu_background);
 
         // Text boxes
-        ui.draw_text_colored("Username", w / 2 - 246 / 2 + 60, h / 2 + 60 + 63, TEXT);
+        let label = tr!(lang, "menu.username");
+        let label_w = ui.text().compute_width(label);
+        ui.draw_text_colored(label, w / 2 - label_w / 2, h / 2 + 60 + 63, text);
         ui.draw_rect_xy_wh((w / 2 - 246 / 2, h / 2 + 60), (246, 53), colors[0].0);
         ui.draw_rect_xy_wh(
             (w / 2 - 246 / 2 + 2, h / 2 + 60 + 2),
             (246 - 4, 53 - 4),
-            0x28263cFF,
+            theme.menu_background,
         );
         ui.draw_rect_xy_wh(
             (w / 2 - 246 / 2 + 4, h / 2 + 60 + 4),
@@ -328,17 +437,14 @@ impl UsernameQueryState {
             .set_pos((w / 2 - 246 / 2 + 16, h / 2 + 60 + 17));
         self.username_box.draw_styled(ui, h, time_secs, tbox_style);
 
-        ui.draw_text_colored(
-            "Server address",
-            w / 2 - 246 / 2 + 22,
-            h / 2 - 41 + 63,
-            TEXT,
-        );
+        let label = tr!(lang, "menu.server_address");
+        let label_w = ui.text().compute_width(label);
+        ui.draw_text_colored(label, w / 2 - label_w / 2, h / 2 - 41 + 63, text);
         ui.draw_rect_xy_wh((w / 2 - 246 / 2, h / 2 - 41), (246, 53), colors[1].0);
         ui.draw_rect_xy_wh(
             (w / 2 - 246 / 2 + 2, h / 2 - 41 + 2),
             (246 - 4, 53 - 4),
-            0x28263cFF,
+            theme.menu_background,
         );
         ui.draw_rect_xy_wh(
             (w / 2 - 246 / 2 + 4, h / 2 - 41 + 4),
@@ -349,28 +455,35 @@ impl UsernameQueryState {
         self.address_box
             .set_pos((w / 2 - 246 / 2 + 16, h / 2 - 41 + 17));
         self.address_box.draw_styled(ui, h, time_secs, tbox_style);
+        if self.connecting.is_none() {
+            self.address_box.draw_error(ui, lang, error_color);
+        }
 
         if self.connecting.is_some() {
-            ui.draw_text_colored("Cancel", w / 2 - 78 / 2, h / 2 - 128 + 15, TEXT);
-            ui.draw_rect_xy_wh((w / 2 - 112 / 2, h / 2 - 128), (112, 49), SELECTED);
+            let label = tr!(lang, "menu.cancel");
+            let label_w = ui.text().compute_width(label);
+            ui.draw_text_colored(label, w / 2 - label_w / 2, h / 2 - 128 + 15, text);
+            ui.draw_rect_xy_wh((w / 2 - 112 / 2, h / 2 - 128), (112, 49), theme.menu_selected);
             ui.draw_rect_xy_wh(
                 (w / 2 - 112 / 2 + 2, h / 2 - 128 + 2),
                 (112 - 4, 49 - 4),
-                0x28263cFF,
+                theme.menu_background,
             );
             ui.draw_rect_xy_wh(
                 (w / 2 - 112 / 2 + 4, h / 2 - 128 + 4),
                 (112 - 8, 49 - 8),
-                SELECTED,
+                theme.menu_selected,
             );
         } else {
             // Join button
-            ui.draw_text_colored("Join", w / 2 - 86 / 2 + 16 - 60, h / 2 - 128 + 15, TEXT);
+            let label = tr!(lang, "menu.join");
+            let label_w = ui.text().compute_width(label);
+            ui.draw_text_colored(label, w / 2 - 60 - label_w / 2, h / 2 - 128 + 15, text);
             ui.draw_rect_xy_wh((w / 2 - 86 / 2 - 60, h / 2 - 128), (86, 49), colors[2].0);
             ui.draw_rect_xy_wh(
                 (w / 2 - 86 / 2 + 2 - 60, h / 2 - 128 + 2),
                 (86 - 4, 49 - 4),
-                0x28263cFF,
+                theme.menu_background,
             );
             ui.draw_rect_xy_wh(
                 (w / 2 - 86 / 2 + 4 - 60, h / 2 - 128 + 4),
@@ -378,21 +491,28 @@ impl UsernameQueryState {
                 colors[2].1,
             );
 
-            ui.draw_text_colored("Quit", w / 2 - 86 / 2 + 16 + 60, h / 2 - 128 + 15, TEXT);
+            let label = tr!(lang, "menu.quit");
+            let label_w = ui.text().compute_width(label);
+            ui.draw_text_colored(label, w / 2 + 60 - label_w / 2, h / 2 - 128 + 15, text);
             ui.draw_rect_xy_wh((w / 2 - 86 / 2 + 60, h / 2 - 128), (86, 49), colors[3].0);
             ui.draw_rect_xy_wh(
                 (w / 2 - 86 / 2 + 2 + 60, h / 2 - 128 + 2),
                 (86 - 4, 49 - 4),
-                0x28263cFF,
+                theme.menu_background,
             );
             ui.draw_rect_xy_wh(
                 (w / 2 - 86 / 2 + 4 + 60, h / 2 - 128 + 4),
                 (86 - 8, 49 - 8),
                 colors[3].1,
             );
+
+            let label = tr!(lang, "menu.settings");
+            self.settings_label_width = ui.text().compute_width(label);
+            ui.draw_text_colored(label, 64, 56, text);
         }
 
         if !self.message.is_empty() {
+            let message_color = if self.message_is_error { TextColor::from_rgba32(colors.error) } else { text };
             let max_w = w - 60;
             let lines = ui.text().compute_linebreaks(&self.message, max_w);
 
@@ -407,7 +527,7 @@ impl UsernameQueryState {
                     w / 2 - length / 2,
                     y,
                     text_renderer::Style {
-                        colors: &[ColorRange::new(self.message_color, u32::MAX)],
+                        colors: &[ColorRange::new(message_color, u32::MAX)],
                         ..Default::default()
                     },
                 );
@@ -420,6 +540,94 @@ impl UsernameQueryState {
         }
     }
 
+    fn draw_profiles(
+        &mut self,
+        ui: &mut UiRenderer,
+        lang: &Localization,
+        theme: &Theme,
+        profiles: &Profiles,
+        colors: &UiColors,
+        win_size: (u16, u16),
+        hovered: Option<usize>,
+    ) {
+        if profiles.list().is_empty() || self.connecting.is_some() {
+            return;
+        }
+        let w = win_size.0;
+        let text = TextColor::from_rgba32(colors.menu_text);
+
+        let label = match profiles.selected() {
+            Some(profile) => tr!(lang, "menu.profile", name = profile.username),
+            None => tr!(lang, "menu.profiles").to_owned(),
+        };
+        self.profile_label_width = ui.text().compute_width(&label);
+        ui.draw_text_colored(&label, w.saturating_sub(64 + self.profile_label_width), 56, text);
+
+        if !self.profile_menu_open {
+            return;
+        }
+        self.profile_list_width = profiles
+            .list()
+            .iter()
+            .map(|profile| ui.text().compute_width(&profile.username))
+            .max()
+            .unwrap_or(0);
+        let x = w.saturating_sub(64 + self.profile_list_width);
+        for (idx, profile) in profiles.list().iter().enumerate() {
+            let y = Self::profile_entry_y(idx);
+            let background = match (hovered == Some(idx), profiles.selected_index() == Some(idx)) {
+                (true, _) => theme.menu_hovered,
+                (false, true) => theme.menu_selected,
+                (false, false) => theme.menu_unselected,
+            };
+            ui.draw_rect_xy_wh((x - 8, y), (self.profile_list_width + 16, 34), background);
+            ui.draw_text_colored(&profile.username, x, y + 6, text);
+        }
+    }
+
+    // The settings button sits in the bottom left corner
+    fn is_hovering_settings(&self, res: &Resources) -> bool {
+        let h = res.window_size.extent.height as u16;
+        let pos = res.input.mouse.pos();
+        let (x, y) = (pos.x as u16, h.saturating_sub(pos.y as u16));
+
+        x >= 64 && x <= 64 + self.settings_label_width && y >= 50 && y <= 50 + 30
+    }
+
+    // The profile dropdown sits in the bottom right corner, opening upwards
+    fn is_hovering_profiles(&self, res: &Resources) -> bool {
+        let (w, h) = (res.window_size.extent.width as u16, res.window_size.extent.height as u16);
+        let pos = res.input.mouse.pos();
+        let (x, y) = (pos.x as u16, h.saturating_sub(pos.y as u16));
+
+        !res.profiles.list().is_empty()
+            && x >= w.saturating_sub(64 + self.profile_label_width)
+            && x <= w.saturating_sub(64)
+            && y >= 50
+            && y <= 50 + 30
+    }
+
+    fn hovered_profile(&self, res: &Resources) -> Option<usize> {
+        if !self.profile_menu_open || self.connecting.is_some() {
+            return None;
+        }
+        let (w, h) = (res.window_size.extent.width as u16, res.window_size.extent.height as u16);
+        let pos = res.input.mouse.pos();
+        let (x, y) = (pos.x as u16, h.saturating_sub(pos.y as u16));
+
+        if x < w.saturating_sub(64 + 8 + self.profile_list_width) || x > w.saturating_sub(56) {
+            return None;
+        }
+        (0..res.profiles.list().len()).find(|&idx| {
+            let entry_y = Self::profile_entry_y(idx);
+            y >= entry_y && y <= entry_y + 36
+        })
+    }
+
+    fn profile_entry_y(idx: usize) -> u16 {
+        50 + 36 * (idx as u16 + 1)
+    }
+
     fn get_hovering(win_size: (u16, u16), mouse_xy: (u16, u16), connecting: bool) -> u32 {
         let (w, h) = win_size;
         let (x, y) = mouse_xy;
@@ -526,13 +734,19 @@ impl UsernameQueryState {
             address_box: TextBoxBuilder::new_at(93, 216)
                 .with_length_limit(24)
                 .with_valid_chars(valid_address_chars)
+                .with_validator(Validator::HostPort)
                 .with_width(246 - 2 * 16)
+                .with_scroll_margin(16)
                 .build(),
             connecting: None,
             selected: 0,
             hovered: u32::MAX,
             message: String::new(),
-            message_color: TextColor::default(),
+            message_is_error: false,
+            settings_label_width: 0,
+            profile_menu_open: false,
+            profile_label_width: 0,
+            profile_list_width: 0,
         })
     }
 }
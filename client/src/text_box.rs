@@ -2,6 +2,9 @@ const DEFAULT_VALID_INPUT_CHARS : &str = " abcdefghijklmnopqrstuvwxyzåäöABCDE
 const CTRL_SEL_STOPPERS: &str = " \t\n.,_-:"; // all only if they're not followed by whitespace
 
 const BACKSPACE: char = '\x08';
+// How far below the text the error message of a validated box goes, clearing the frame
+// the menus draw around their boxes
+const ERROR_OFFSET: u16 = 43;
 
 use arboard::Clipboard;
 use bevy_utils::HashSet;
@@ -13,7 +16,10 @@ use crate::{
         text_renderer::{self, ColorRange, TextColor, TextRenderer},
         ui_renderer::UiRenderer,
     },
+    localization::Localization,
     resources::Resources,
+    theme::Theme,
+    tr,
 };
 
 pub struct TextBoxBuilder {
@@ -22,6 +28,8 @@ pub struct TextBoxBuilder {
     x: u16,
     y: u16,
     width: u16,
+    scroll_margin: u16,
+    validator: Option<Validator>,
 }
 
 impl TextBoxBuilder {
@@ -32,6 +40,8 @@ impl TextBoxBuilder {
             x,
             y,
             width: u16::MAX,
+            scroll_margin: 0,
+            validator: None,
         }
     }
 
@@ -40,20 +50,38 @@ impl TextBoxBuilder {
         self
     }
 
+    // Pixels of text kept visible on either side of the caret when scrolling, where possible
+    pub const fn with_scroll_margin(mut self, margin: u16) -> Self {
+        self.scroll_margin = margin;
+        self
+    }
+
     pub fn with_valid_chars(mut self, chars: HashSet<char>) -> Self {
         self.valid_chars = Some(chars);
         self
     }
 
+    // The contents are checked as they are typed, see TextBox::value(). Numeric validators
+    // limit the valid chars to what numbers are made of, unless given explicitly.
+    pub const fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
     pub const fn with_length_limit(mut self, limit: usize) -> Self {
         self.length_limit = limit;
         self
     }
 
     pub fn build(self) -> TextBox {
-        let valid_chars = self
-            .valid_chars
-            .unwrap_or_else(|| DEFAULT_VALID_INPUT_CHARS.chars().collect());
+        let valid_chars = self.valid_chars.unwrap_or_else(|| {
+            let chars = match self.validator {
+                Some(Validator::Int { .. }) => "-0123456789",
+                Some(Validator::Float { .. }) => "-0123456789.",
+                _ => DEFAULT_VALID_INPUT_CHARS,
+            };
+            chars.chars().collect()
+        });
 
         TextBox {
             buffer: Vec::with_capacity(self.length_limit.min(256) as usize),
@@ -67,12 +95,103 @@ impl TextBoxBuilder {
             last_mouse_click: 0.0,
             last_mouse_pos: 0,
             dragging_mouse: false,
+            dragging_words: None,
             modified: false,
             active: true,
             x: self.x,
             y: self.y,
             width: self.width,
             visible_start: 0,
+            scroll_margin: self.scroll_margin,
+            validator: self.validator,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Validator {
+    Int { min: i64, max: i64 },
+    Float { min: f64, max: f64 },
+    // "host:port", with IPv6 addresses in brackets: "[::1]:29477"
+    HostPort,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String), // no validator
+    Int(i64),
+    Float(f64),
+    HostPort(String, u16), // without the brackets
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    Empty,
+    NotANumber,
+    OutOfRange { min: String, max: String },
+    MissingPort,
+    InvalidPort,
+    MissingHost,
+    InvalidHost,
+}
+
+impl ValidationError {
+    pub fn message(&self, lang: &Localization) -> String {
+        match self {
+            Self::Empty => tr!(lang, "input.empty").to_owned(),
+            Self::NotANumber => tr!(lang, "input.not_a_number").to_owned(),
+            Self::OutOfRange { min, max } => tr!(lang, "input.out_of_range", min = min, max = max),
+            Self::MissingPort => tr!(lang, "input.missing_port").to_owned(),
+            Self::InvalidPort => tr!(lang, "input.invalid_port").to_owned(),
+            Self::MissingHost => tr!(lang, "input.missing_host").to_owned(),
+            Self::InvalidHost => tr!(lang, "input.invalid_host").to_owned(),
+        }
+    }
+}
+
+impl Validator {
+    pub fn validate(&self, text: &str) -> Result<Value, ValidationError> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(ValidationError::Empty);
+        }
+        match *self {
+            Self::Int { min, max } => {
+                let value = text.parse::<i64>().map_err(|_| ValidationError::NotANumber)?;
+                if value < min || value > max {
+                    return Err(ValidationError::OutOfRange { min: min.to_string(), max: max.to_string() });
+                }
+                Ok(Value::Int(value))
+            }
+            Self::Float { min, max } => {
+                let value = text.parse::<f64>().map_err(|_| ValidationError::NotANumber)?;
+                if !(value >= min && value <= max) {
+                    return Err(ValidationError::OutOfRange { min: min.to_string(), max: max.to_string() });
+                }
+                Ok(Value::Float(value))
+            }
+            Self::HostPort => {
+                let Some((host, port)) = text.rsplit_once(':') else {
+                    return Err(ValidationError::MissingPort);
+                };
+                let host = match host.strip_prefix('[') {
+                    Some(host) => host.strip_suffix(']').ok_or(ValidationError::InvalidHost)?,
+                    // A bare IPv6 address, where the last group was taken for the port
+                    None if host.contains(':') => return Err(ValidationError::InvalidHost),
+                    None if host.contains(['[', ']']) => return Err(ValidationError::InvalidHost),
+                    None => host,
+                };
+                if host.is_empty() {
+                    return Err(ValidationError::MissingHost);
+                }
+                if port.is_empty() {
+                    return Err(ValidationError::MissingPort);
+                }
+                match port.parse::<u16>() {
+                    Ok(port) if port != 0 => Ok(Value::HostPort(host.to_owned(), port)),
+                    _ => Err(ValidationError::InvalidPort),
+                }
+            }
         }
     }
 }
@@ -81,6 +200,8 @@ impl TextBoxBuilder {
 pub struct Style {
     pub cursor_color: u32,
     pub text_color: TextColor,
+    pub error_color: TextColor, // for text that doesn't pass validation
+    pub selection_color: u32,
 }
 
 impl Default for Style {
@@ -88,6 +209,8 @@ impl Default for Style {
         Self {
             cursor_color: 0x99_99_99_FF,
             text_color: Default::default(),
+            error_color: TextColor::from_rgba32(0xDC_32_3C_FF),
+            selection_color: Theme::DEFAULT.text_selection,
         }
     }
 }
@@ -135,6 +258,7 @@ pub struct TextBox {
     last_mouse_click: f32,
     last_mouse_pos: i32,
     dragging_mouse: bool,
+    dragging_words: Option<Selection>, // the word double-clicked on, while the button is held
 
     modified: bool,
     active: bool,
@@ -143,6 +267,8 @@ pub struct TextBox {
     y: u16,
     width: u16,
     visible_start: u16,
+    scroll_margin: u16,
+    validator: Option<Validator>,
 }
 
 impl TextBox {
@@ -206,6 +332,49 @@ impl TextBox {
         self.visible_start = self.visible_start.max(start);
     }
 
+    // The contents, parsed according to the validator
+    pub fn value(&self) -> Result<Value, ValidationError> {
+        let text = self.buffer.iter().collect::<String>();
+        match &self.validator {
+            Some(validator) => validator.validate(&text),
+            None => Ok(Value::Text(text)),
+        }
+    }
+
+    pub fn int_value(&self) -> Option<i64> {
+        match self.value() {
+            Ok(Value::Int(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn float_value(&self) -> Option<f64> {
+        match self.value() {
+            Ok(Value::Float(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    // Why the contents don't pass validation. An empty box isn't flagged until submitted.
+    pub fn error(&self) -> Option<ValidationError> {
+        match self.value() {
+            Err(ValidationError::Empty) => None,
+            result => result.err(),
+        }
+    }
+
+    // Where the caret is drawn on screen: its left edge and the text baseline, in the same
+    // coordinates as the box itself. For anchoring popups and such to the caret.
+    pub fn caret_pos(&mut self, text_renderer: &TextRenderer) -> (u16, u16) {
+        self.recompute_visible_start_if_needed(text_renderer);
+        let caret_x = text_renderer
+            .compute_width_chars(self.buffer[..self.cursor_pos as usize].iter().copied());
+        let x = (self.x + caret_x)
+            .saturating_sub(self.visible_start)
+            .clamp(self.x, self.x.saturating_add(self.width));
+        (x, self.y)
+    }
+
     pub fn reset(&mut self, time_secs: f32) {
         self.buffer.clear();
         self.selection.clear_to(0);
@@ -247,6 +416,11 @@ impl TextBox {
                     Key::Up => self.clear_to(0),
                     Key::Down => self.clear_to(i32::MAX),
 
+                    Key::Home if shift => self.select_range(self.selection.start, 0),
+                    Key::End if shift => self.select_range(self.selection.start, i32::MAX),
+                    Key::Home => self.clear_to(0),
+                    Key::End => self.clear_to(i32::MAX),
+
                     Key::Left if shift && ctrl => {
                         self.select_range(self.selection.start, self.find_left_delim_idx())
                     }
@@ -307,9 +481,11 @@ impl TextBox {
                     self.last_mouse_click = res.time.secs_f32;
                     self.last_mouse_pos = pos;
                     self.dragging_mouse = self.mouse_clicks == 1;
+                    self.dragging_words = None;
 
                     if self.mouse_clicks == 2 {
                         self.select_word();
+                        self.dragging_words = Some(self.selection);
                     } else if self.mouse_clicks == 3 {
                         self.select_all();
                     } else if self.mouse_clicks == 4 {
@@ -323,27 +499,37 @@ impl TextBox {
                 ..
             } => {
                 self.dragging_mouse = false;
+                self.dragging_words = None;
             }
             &WindowEvent::CursorMoved { .. } => {
-                if self.dragging_mouse {
+                if self.dragging_mouse || self.dragging_words.is_some() {
                     let mouse_x =
                         (res.input.mouse.pos().x + self.visible_start as f32).max(0.0) as u16;
                     let mouse_y =
                         res.window_size.extent.height as i32 - res.input.mouse.pos().y as i32;
-                    if mouse_y - self.y as i32 > 40 {
-                        self.selection.end = 0;
-                        self.cursor_pos = 0;
+                    let pos = if mouse_y - self.y as i32 > 40 {
+                        0
                     } else if mouse_y - (self.y as i32) < -40 {
-                        self.selection.end = self.buffer.len() as _;
-                        self.cursor_pos = self.selection.end;
+                        self.buffer.len() as i32
                     } else {
                         let rel_x = mouse_x.max(self.x) - self.x;
-                        let pos = res
-                            .renderer
+                        res.renderer
                             .ui
                             .text()
                             .compute_glyph_idx_at_pos_chars(self.buffer.iter().copied(), rel_x)
-                            as i32;
+                            as i32
+                    };
+
+                    if let Some(word) = self.dragging_words {
+                        // Whole words at a time, always keeping the one double-clicked on
+                        if pos < word.start {
+                            self.select_range(word.end, self.word_at(pos).start);
+                        } else if pos > word.end {
+                            self.select_range(word.start, self.word_at(pos).end);
+                        } else {
+                            self.select_range(word.start, word.end);
+                        }
+                    } else {
                         self.selection.end = pos;
                         self.cursor_pos = pos;
                     }
@@ -492,16 +678,24 @@ impl TextBox {
     }
 
     fn select_word(&mut self) {
-        // Select word around cursor, delimited by whitespace
+        let word = self.word_at(self.cursor_pos);
+        self.selection = word;
+        self.cursor_pos = word.end;
+    }
 
-        let cursor_pos = (self.cursor_pos as usize).min(self.buffer.len() - 1);
+    // The word around the given index, delimited by whitespace
+    fn word_at(&self, idx: i32) -> Selection {
+        if self.buffer.is_empty() {
+            return Selection { start: 0, end: 0 };
+        }
+        let pos = (idx.max(0) as usize).min(self.buffer.len() - 1);
 
-        let mut start = cursor_pos.max(1) - 1;
+        let mut start = pos.max(1) - 1;
         while start > 0 && !CTRL_SEL_STOPPERS.contains(self.buffer[start]) {
             start -= 1;
         }
 
-        let mut end = cursor_pos;
+        let mut end = pos;
         while end < self.buffer.len() && !CTRL_SEL_STOPPERS.contains(self.buffer[end]) {
             end += 1;
         }
@@ -510,22 +704,21 @@ impl TextBox {
             start += 1;
         }
 
-        self.selection.start = start as _;
-        self.selection.end = end as _;
-        self.cursor_pos = end as _;
+        Selection { start: start as _, end: end as _ }
     }
 }
 
 // Rendering
 impl TextBox {
-    pub fn draw(&mut self, renderer: &mut UiRenderer, window_height: u16, time: f32) -> (u16, u16) {
+    pub fn draw(&mut self, renderer: &mut UiRenderer, window_height: u16, time: f32, selection_color: u32) -> (u16, u16) {
         self.draw_styled(
             renderer,
             window_height,
             time,
             Style {
                 cursor_color: 0xFF_FF_FF_FF,
-                text_color: TextColor::default(),
+                selection_color,
+                ..Default::default()
             },
         )
     }
@@ -546,14 +739,18 @@ impl TextBox {
             (self.width as _, 30),
         );
 
+        let text_color = match self.error() {
+            Some(_) => style.error_color,
+            None => style.text_color,
+        };
         let sel = self.selection.sorted();
-        let mut colors = [ColorRange::new(style.text_color, u32::MAX); 3];
+        let mut colors = [ColorRange::new(text_color, u32::MAX); 3];
 
         if !sel.is_empty() {
-            colors[0] = ColorRange::new(style.text_color, sel.start as u32);
+            colors[0] = ColorRange::new(text_color, sel.start as u32);
             colors[1] =
                 ColorRange::from_rgba_n(0x11, 0x11, 0xFF, 0xFF, (sel.end - sel.start) as u32);
-            colors[2] = ColorRange::new(style.text_color, u32::MAX);
+            colors[2] = ColorRange::new(text_color, u32::MAX);
 
             let sel_start_x = renderer
                 .text()
@@ -576,7 +773,7 @@ impl TextBox {
             renderer.draw_rect_xy_wh(
                 (x.clamp(self.x, self.x + self.width), y - 2 * SCALE),
                 (width, 10 * SCALE),
-                0xA0_C7_F2_FF,
+                style.selection_color,
             );
         }
 
@@ -608,29 +805,34 @@ impl TextBox {
         (end_x, end_y)
     }
 
+    // The validation error, if any, under the box
+    pub fn draw_error(&self, renderer: &mut UiRenderer, lang: &Localization, color: TextColor) {
+        if let Some(error) = self.error() {
+            let message = error.message(lang);
+            renderer.draw_text_colored(&message, self.x, self.y.saturating_sub(ERROR_OFFSET), color);
+        }
+    }
+
     fn recompute_visible_start_if_needed(&mut self, text_renderer: &TextRenderer) {
         if self.old_cursor_pos != self.cursor_pos {
             let new_pos = self.cursor_pos;
-            if new_pos < self.old_cursor_pos {
-                let start = text_renderer
-                    .compute_width_chars(self.buffer[..new_pos as usize].iter().copied());
-                let rem_length = text_renderer
+            let caret_x = text_renderer
+                .compute_width_chars(self.buffer[..new_pos as usize].iter().copied());
+            let full = caret_x
+                + text_renderer
                     .compute_width_chars(self.buffer[new_pos as usize..].iter().copied());
+            // Never more than a third of the box, or the caret would have nowhere to be
+            let margin = self.scroll_margin.min(self.width / 3);
 
-                let full = start + rem_length;
-
+            if new_pos < self.old_cursor_pos {
                 if self.visible_start + self.width > full {
-                    self.visible_start = self
-                        .visible_start
-                        .min((start + rem_length).saturating_sub(self.width));
-                } else {
-                    self.visible_start = self.visible_start.min(start);
+                    self.visible_start = self.visible_start.min(full.saturating_sub(self.width));
                 }
+                self.visible_start = self.visible_start.min(caret_x.saturating_sub(margin));
             } else {
-                let end = text_renderer
-                    .compute_width_chars(self.buffer[..new_pos as usize].iter().copied());
-                let start = end.saturating_sub(self.width);
-                self.visible_start = self.visible_start.max(start);
+                // Can't scroll past the end of the text to make room
+                let end = (caret_x + margin).min(full);
+                self.visible_start = self.visible_start.max(end.saturating_sub(self.width));
             }
             self.old_cursor_pos = self.cursor_pos;
         }
@@ -0,0 +1,128 @@
+use std::{fmt::Write, fs, io};
+
+const THEME_PATH: &str = "config/theme.txt";
+
+// Colors of the menus and HUD, RGBA, from `key = RRGGBBAA` lines in THEME_PATH. Loaded at
+// startup, written with the defaults if there is none to have something to edit, and
+// reloaded in game with `/theme reload`. Colors that carry meaning are in the accessibility
+// palette instead, see settings::Palette.
+pub struct Theme {
+    // Only the color: the opacity is the chat_background_opacity setting
+    pub chat_background: u32,
+    pub text_selection: u32,
+    // The menu palette, for the window frame and the buttons
+    pub menu_background: u32,
+    pub menu_unselected: u32,
+    pub menu_selected: u32,
+    pub menu_hovered: u32,
+    pub menu_disabled: u32,
+    pub menu_cursor: u32,
+    // Behind the debug HUD
+    pub hud_background: u32,
+    // Behind the map view and the packet inspector
+    pub panel_background: u32,
+    pub toast_background: u32,
+    pub pause_background: u32,
+    pub loading_background: u32,
+    pub loading_bar: u32,
+    // The plain crosshair, and when it's on a block or an entity within reach
+    pub crosshair: u32,
+    pub crosshair_block: u32,
+    pub crosshair_entity: u32,
+}
+
+impl Theme {
+    pub const DEFAULT: Theme = Theme {
+        chat_background: 0x06_06_06_00,
+        text_selection: 0xA0_C7_F2_FF,
+        menu_background: 0x28_26_3C_FF,
+        menu_unselected: 0x3C_3A_53_FF,
+        menu_selected: 0x4C_49_64_FF,
+        menu_hovered: 0x5D_5B_7A_FF,
+        menu_disabled: 0x30_2F_43_FF,
+        menu_cursor: 0xA7_A4_BF_FF,
+        hud_background: 0x06_06_06_90,
+        panel_background: 0x06_06_06_B0,
+        toast_background: 0x06_06_06_C0,
+        pause_background: 0x10_10_18_A0,
+        loading_background: 0x10_10_10_FF,
+        loading_bar: 0x30_30_30_FF,
+        crosshair: 0x99_99_99_FF,
+        crosshair_block: 0xEE_EE_EE_FF,
+        crosshair_entity: 0xF2_C9_4C_FF,
+    };
+
+    fn colors(&mut self) -> [(&'static str, &mut u32); 17] {
+        [
+            ("chat_background", &mut self.chat_background),
+            ("text_selection", &mut self.text_selection),
+            ("menu_background", &mut self.menu_background),
+            ("menu_unselected", &mut self.menu_unselected),
+            ("menu_selected", &mut self.menu_selected),
+            ("menu_hovered", &mut self.menu_hovered),
+            ("menu_disabled", &mut self.menu_disabled),
+            ("menu_cursor", &mut self.menu_cursor),
+            ("hud_background", &mut self.hud_background),
+            ("panel_background", &mut self.panel_background),
+            ("toast_background", &mut self.toast_background),
+            ("pause_background", &mut self.pause_background),
+            ("loading_background", &mut self.loading_background),
+            ("loading_bar", &mut self.loading_bar),
+            ("crosshair", &mut self.crosshair),
+            ("crosshair_block", &mut self.crosshair_block),
+            ("crosshair_entity", &mut self.crosshair_entity),
+        ]
+    }
+
+    // Never fails: missing or invalid colors keep their defaults, with a warning for the latter
+    pub fn load() -> Self {
+        let mut theme = Self::DEFAULT;
+        let text = match fs::read_to_string(THEME_PATH) {
+            Ok(text) => text,
+            Err(e) => {
+                if e.kind() == io::ErrorKind::NotFound {
+                    theme.save_defaults();
+                } else {
+                    eprintln!("Failed to read {THEME_PATH}: {e}");
+                }
+                return theme;
+            }
+        };
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parsed = line.split_once('=').and_then(|(key, value)| {
+                let value = value.trim();
+                let hex = value.strip_prefix("0x").or_else(|| value.strip_prefix('#')).unwrap_or(value);
+                let hex = hex.replace('_', "");
+                let rgba = u32::from_str_radix(&hex, 16).ok().filter(|_| hex.len() == 8)?;
+                Some((key.trim(), rgba))
+            });
+            let color = parsed.and_then(|(key, rgba)| {
+                theme.colors().into_iter().find(|(name, _)| *name == key).map(|(_, color)| *color = rgba)
+            });
+            if color.is_none() {
+                eprintln!("{THEME_PATH}: ignoring '{line}'");
+            }
+        }
+        theme
+    }
+
+    fn save_defaults(&mut self) {
+        let mut out = String::from("# RRGGBBAA per color, see client/src/theme.rs\n");
+        for (name, color) in self.colors() {
+            let _ = writeln!(out, "{name} = {:08X}", *color);
+        }
+        if let Err(e) = fs::create_dir_all("config").and_then(|_| fs::write(THEME_PATH, out)) {
+            eprintln!("Failed to save {THEME_PATH}: {e}");
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+
+use flexstr::SharedStr;
+
+use crate::{
+    renderer::{
+        text_renderer::{ColorRange, Style, TextColor},
+        ui_renderer::{RectStyle, UiRenderer},
+    },
+    resources::core::WindowSize,
+    settings::UiColors,
+    theme::Theme,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ToastIcon {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl ToastIcon {
+    fn color(self, colors: &UiColors) -> u32 {
+        match self {
+            ToastIcon::Info => colors.menu_text,
+            ToastIcon::Success => colors.good,
+            ToastIcon::Warning => colors.fair,
+            ToastIcon::Error => colors.error,
+        }
+    }
+}
+
+pub struct Toast {
+    pub icon: ToastIcon,
+    pub title: SharedStr,
+    pub body: SharedStr, // may be empty
+    pub timeout_secs: f32,
+    // A toast replaces any other with the same tag instead of piling up next to it,
+    // e.g. for a warning that may be raised again while still on screen
+    pub tag: Option<&'static str>,
+}
+
+impl Toast {
+    pub const DEFAULT_TIMEOUT_SECS: f32 = 4.0;
+
+    pub fn new(icon: ToastIcon, title: impl Into<SharedStr>, body: impl Into<SharedStr>) -> Self {
+        Self {
+            icon,
+            title: title.into(),
+            body: body.into(),
+            timeout_secs: Self::DEFAULT_TIMEOUT_SECS,
+            tag: None,
+        }
+    }
+
+    pub fn with_tag(self, tag: &'static str) -> Self {
+        Self { tag: Some(tag), ..self }
+    }
+}
+
+// Short notices stacked in the top right corner, below the minimap. Anything with access
+// to Resources can show one with `res.toasts.push()`. Only a few are shown at a time and
+// the rest wait in line, so a burst of them doesn't cover the screen.
+pub struct Toasts {
+    shown: Vec<(Toast, f32)>, // and when it was first drawn
+    queued: VecDeque<Toast>,
+}
+
+impl Toasts {
+    const MAX_SHOWN: usize = 4;
+    const MAX_QUEUED: usize = 32;
+
+    const WIDTH: u16 = 480;
+    const MARGIN: u16 = 20;
+    const TOP: u16 = 200; // from the top of the window, to stay clear of the minimap
+    const PAD: u16 = 2 * 3; // 3 is the font scale
+    const LINE_HEIGHT: u16 = 30;
+    const FADE_SECS: f32 = 0.3;
+
+    const BODY_COLOR: u32 = 0xC8_C8_C8_FF;
+
+    pub fn new() -> Self {
+        Self {
+            shown: Vec::with_capacity(Self::MAX_SHOWN),
+            queued: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, toast: Toast) {
+        if let Some(tag) = toast.tag {
+            // Shown again from the start, ahead of the line
+            if let Some(i) = self.shown.iter().position(|(shown, _)| shown.tag == Some(tag)) {
+                self.shown.remove(i);
+                self.queued.push_front(toast);
+                return;
+            }
+            if let Some(queued) = self.queued.iter_mut().find(|queued| queued.tag == Some(tag)) {
+                *queued = toast;
+                return;
+            }
+        }
+
+        if self.queued.len() == Self::MAX_QUEUED {
+            self.queued.pop_front();
+        }
+        self.queued.push_back(toast);
+    }
+
+    pub fn draw(&mut self, ui: &mut UiRenderer, time_secs: f32, win_size: &WindowSize, colors: &UiColors, theme: &Theme) {
+        self.shown.retain(|(toast, shown_at)| time_secs - shown_at < toast.timeout_secs);
+        while self.shown.len() < Self::MAX_SHOWN {
+            let Some(toast) = self.queued.pop_front() else { break; };
+            self.shown.push((toast, time_secs));
+        }
+
+        let (w, h) = (win_size.extent.width as u16, win_size.extent.height as u16);
+        let x = w - Self::WIDTH - Self::MARGIN;
+        let text_x = x + Self::PAD + 28;
+        let text_width = Self::WIDTH - (text_x - x) - Self::PAD;
+        let mut top = h.saturating_sub(Self::TOP);
+
+        for (toast, shown_at) in &self.shown {
+            let remaining = toast.timeout_secs - (time_secs - shown_at);
+            let alpha = (remaining / Self::FADE_SECS).clamp(0.0, 1.0);
+            let fade = |rgba: u32| (rgba & 0xFF_FF_FF_00) | ((rgba & 0xFF) as f32 * alpha) as u32;
+
+            let linebreaks = if toast.body.is_empty() {
+                Default::default()
+            } else {
+                ui.text().compute_linebreaks(&toast.body, text_width)
+            };
+            let lines = 1 + linebreaks.len() as u16;
+            let height = lines * Self::LINE_HEIGHT + 2 * Self::PAD;
+            let bottom = top.saturating_sub(height);
+
+            let icon_color = fade(toast.icon.color(colors));
+            let background = RectStyle::rounded(fade(theme.toast_background), 8).with_border(2, (icon_color & 0xFF_FF_FF_00) | ((icon_color & 0xFF) / 2));
+            ui.draw_rect_styled((x, bottom), (Self::WIDTH, height), &background);
+            // Icon: a colored dot next to the title
+            let title_y = top - Self::PAD - Self::LINE_HEIGHT + 4;
+            ui.draw_rect_styled((x + Self::PAD + 4, title_y + 2), (18, 18), &RectStyle::rounded(icon_color, 9));
+            ui.draw_text_colored(&toast.title, text_x, title_y, TextColor::from_rgba32(fade(colors.text)));
+
+            let body_color = [ColorRange::new(TextColor::from_rgba32(fade(Self::BODY_COLOR)), u32::MAX)];
+            let mut line_y = title_y;
+            let mut start_idx = 0;
+            for end_idx in linebreaks.iter().copied() {
+                line_y -= Self::LINE_HEIGHT;
+                let line = &toast.body[start_idx as usize..end_idx as usize];
+                ui.draw_text_styled(line.trim_start(), text_x, line_y, Style { colors: &body_color, ..Default::default() });
+                start_idx = end_idx;
+            }
+
+            top = bottom.saturating_sub(Self::PAD * 2);
+        }
+    }
+}
+
+impl Default for Toasts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
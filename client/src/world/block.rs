@@ -16,12 +16,21 @@ pub struct BlockId(u16);
 impl BlockId {
     pub const AIR: BlockId = BlockId(0);
     pub const STONE: BlockId = BlockId(1);
+    pub const TORCH: BlockId = BlockId(2);
+    pub const LOG: BlockId = BlockId(3);
+    pub const LEAVES: BlockId = BlockId(4);
+    // The level is in the data bits, see shared::fluid
+    pub const WATER: BlockId = BlockId(5);
+
+    pub const fn raw(self) -> u16 {
+        self.0
+    }
 }
 
 impl BlockId {
     // Either full or partial transparency
     pub fn is_transparent(self) -> bool {
-        self == Self::AIR
+        self == Self::AIR || self == Self::TORCH || self == Self::LEAVES || self == Self::WATER
     }
 }
 
@@ -33,6 +42,10 @@ impl Block {
         Self(id.0)
     }
 
+    pub const fn from_raw(raw: u16) -> Self {
+        Self(raw)
+    }
+
     pub const fn raw(self) -> u16 {
         self.0
     }
@@ -49,6 +62,10 @@ impl Block {
 impl Block {
     pub const AIR: Block = Block::new(BlockId::AIR);
     pub const STONE: Block = Block::new(BlockId::STONE);
+    pub const TORCH: Block = Block::new(BlockId::TORCH);
+    pub const LOG: Block = Block::new(BlockId::LOG);
+    pub const LEAVES: Block = Block::new(BlockId::LEAVES);
+    pub const WATER: Block = Block::new(BlockId::WATER);
 }
 
 impl From<Block> for BlockId {
@@ -1,4 +1,4 @@
-use glam::IVec3;
+use shared::coords::LocalPos;
 
 use super::block::Block;
 
@@ -6,79 +6,19 @@ pub const CHUNK_SIZE_LOG2: usize = 4;
 pub const CHUNK_SIZE: usize = 1 << CHUNK_SIZE_LOG2;
 pub const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
 
-pub type WorldBlockPos = IVec3;
-
-pub trait WorldBlockPosExt {
-    fn to_block_index(self) -> usize;
-
-    fn to_local(self) -> ChunkBlockPos;
-
-    fn to_chunk_pos(self) -> IVec3;
-}
-
-impl WorldBlockPosExt for WorldBlockPos {
-    fn to_block_index(self) -> usize {
-        const XZ_BITS: usize = 24;
-
-        ((self.y as u32 as usize) << (2 * XZ_BITS))
-            | ((self.z as u32 as usize) << XZ_BITS)
-            | (self.x as u32 as usize)
-    }
-
-    fn to_local(self) -> ChunkBlockPos {
-        ChunkBlockPos::new(self.x as u8, self.y as u8, self.z as u8)
-    }
-
-    fn to_chunk_pos(self) -> IVec3 {
-        self >> CHUNK_SIZE_LOG2 as i32
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct ChunkBlockPos {
-    pub x: u8,
-    pub y: u8,
-    pub z: u8,
-    pub extra: u8,
-}
-
-impl ChunkBlockPos {
-    pub const COORD_MASK: u8 = CHUNK_SIZE as u8 - 1;
-
-    pub const fn new(x: u8, y: u8, z: u8) -> Self {
-        Self {
-            x: x & Self::COORD_MASK,
-            y: y & Self::COORD_MASK,
-            z: z & Self::COORD_MASK,
-            extra: 0,
-        }
-    }
-
-    pub const fn to_block_index(self) -> usize {
-        self.z as usize * CHUNK_SIZE * CHUNK_SIZE + self.x as usize * CHUNK_SIZE + self.y as usize
-    }
-}
-
-impl<T> From<(T, T, T)> for ChunkBlockPos
-where
-    T: Into<u8>,
-{
-    fn from((x, y, z): (T, T, T)) -> Self {
-        Self::new(x.into(), y.into(), z.into())
-    }
-}
-
-impl From<WorldBlockPos> for ChunkBlockPos {
-    fn from(pos: WorldBlockPos) -> Self {
-        pos.to_local()
-    }
+// Not the same layout as shared::coords::LocalPos::index(): z, then x, then y fastest
+fn index_of(local: LocalPos) -> usize {
+    (local.z() as usize * CHUNK_SIZE + local.x() as usize) * CHUNK_SIZE + local.y() as usize
 }
 
 pub struct Chunk {
     blocks: [Block; CHUNK_VOLUME],
+    // Mesh is out of date
     pub dirty: bool,
     // Id of the 2³ chunk group this chunk belongs to
     pub group_id: thunderdome::Index,
+    // Seconds since launch when the chunk was last in view, see ChunkBudget
+    pub last_visible: f32,
 
     pub neighbor_indices: [u32; 6],
 }
@@ -125,15 +65,11 @@ impl std::ops::Index<usize> for Chunk {
     }
 }
 
-impl<T> std::ops::Index<T> for Chunk
-where
-    T: Into<ChunkBlockPos>,
-{
+impl std::ops::Index<LocalPos> for Chunk {
     type Output = Block;
 
-    fn index(&self, index: T) -> &Self::Output {
-        let pos: ChunkBlockPos = index.into();
-        &self[pos.to_block_index()]
+    fn index(&self, local: LocalPos) -> &Self::Output {
+        &self[index_of(local)]
     }
 }
 
@@ -143,13 +79,9 @@ impl std::ops::IndexMut<usize> for Chunk {
     }
 }
 
-impl<T> std::ops::IndexMut<T> for Chunk
-where
-    T: Into<ChunkBlockPos>,
-{
-    fn index_mut(&mut self, index: T) -> &mut Self::Output {
-        let pos: ChunkBlockPos = index.into();
-        &mut self[pos.to_block_index()]
+impl std::ops::IndexMut<LocalPos> for Chunk {
+    fn index_mut(&mut self, local: LocalPos) -> &mut Self::Output {
+        &mut self[index_of(local)]
     }
 }
 
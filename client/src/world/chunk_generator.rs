@@ -1,9 +1,50 @@
+use shared::{coords::{ChunkPos, LocalPos}, worldgen::{self, structures::{Piece, StructureQueue}}};
+
+use super::{
+    block::{Block, BlockId},
+    chunk::Chunk,
+};
+
+// Terrain is generated locally by shared::worldgen, from the seed of the dimension the
+// server sent. That stays the case until the server generates and streams chunks itself.
 pub struct ChunkGenerator {
-    world_seed: u64,
+    seed: u64,
+    structures: StructureQueue,
 }
 
 impl ChunkGenerator {
-    pub fn new(world_seed: u64) -> Self {
-        Self { world_seed }
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            structures: StructureQueue::new(),
+        }
+    }
+
+    // Returns structure pieces that belong to chunks generated earlier, see `Chunks::generate()`
+    pub fn generate(&mut self, chunk_pos: ChunkPos, chunk: &mut Chunk) -> Vec<Piece> {
+        let (blocks, late_pieces) = self.structures.generate_chunk(self.seed, chunk_pos.0);
+        for local in LocalPos::all() {
+            chunk[local] = to_block(blocks[local.index()]);
+        }
+        late_pieces
+    }
+}
+
+pub fn to_block(id: worldgen::BlockId) -> Block {
+    match id {
+        worldgen::STONE => Block::STONE,
+        worldgen::LOG => Block::LOG,
+        worldgen::LEAVES => Block::LEAVES,
+        _ => Block::AIR,
+    }
+}
+
+pub fn from_block(block: Block) -> worldgen::BlockId {
+    match block.id() {
+        BlockId::STONE => worldgen::STONE,
+        BlockId::LOG => worldgen::LOG,
+        BlockId::LEAVES => worldgen::LEAVES,
+        BlockId::AIR => worldgen::AIR,
+        other => other.raw(),
     }
 }
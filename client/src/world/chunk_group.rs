@@ -1,15 +1,28 @@
-use thunderdome::Arena;
+use std::collections::HashMap;
+
+use glam::IVec3;
+use thunderdome::{Arena, Index};
 
 pub struct ChunkGroupData {}
 
 pub struct ChunkGroups {
     groups: Arena<ChunkGroupData>, // *The* arena responsible for index generation
+    by_pos: HashMap<IVec3, Index>, // keyed by chunk position >> 1
 }
 
 impl ChunkGroups {
     pub fn new() -> Self {
         Self {
             groups: Arena::new(),
+            by_pos: HashMap::new(),
         }
     }
+
+    // Group of the chunk at `chunk_pos`, created on first use
+    pub fn group_of(&mut self, chunk_pos: IVec3) -> Index {
+        let groups = &mut self.groups;
+        *self.by_pos
+            .entry(chunk_pos >> 1)
+            .or_insert_with(|| groups.insert(ChunkGroupData {}))
+    }
 }
@@ -1,10 +1,14 @@
-use glam::{IVec2, IVec3, Vec3Swizzles};
+use std::collections::HashSet;
+
+use glam::{ivec3, IVec2, IVec3, Vec3Swizzles};
+use shared::{coords::{BlockPos, ChunkPos}, worldgen::structures};
 
 use crate::resources::Resources;
 
 use super::{
+    block::Block,
     chunk::{Chunk, CHUNK_SIZE},
-    chunk_generator::ChunkGenerator,
+    chunk_generator::{self, ChunkGenerator},
     chunk_group::ChunkGroups,
 };
 
@@ -17,55 +21,313 @@ pub const WORLD_HEIGHT_CHUNKS: usize = WORLD_HEIGHT / CHUNK_SIZE;
 pub struct Chunks {
     corner_chunk_pos: IVec2,
     chunks: Box<[Option<Box<Chunk>>]>,
-    render_distance: u32,
+    render_distance: u32, // Maximum; the storage is sized for this
+    view_distance: u32,   // Currently active radius, <= render_distance
 
     groups: ChunkGroups,
     generator: ChunkGenerator,
+
+    // XZ chunk positions of columns that were loaded or modified since last drained
+    changed_columns: Vec<IVec2>,
+
+    // Offsets from the center, nearest columns first, and how far along generation is
+    load_order: Box<[IVec3]>,
+    load_cursor: usize,
+    chunks_generated: u32,
+
+    // Generation stops at this many chunks, see ChunkBudget
+    max_loaded: usize,
+    loaded: usize,
+    // Unloaded to stay within the budget. Not generated again until restored.
+    evicted: HashSet<ChunkPos>,
 }
 
 impl Chunks {
-    pub fn new(world_seed: u64, render_distance: u32, player_chunk_pos: IVec3) -> Self {
+    // New chunks generated per tick, once the spawn area is done
+    const GENERATE_PER_TICK: usize = 32;
+
+    // `seed`: of the dimension, see shared::dimension::seed()
+    pub fn new(seed: u64, render_distance: u32, player_chunk_pos: ChunkPos) -> Self {
         let n = 2 * render_distance as usize;
 
+        let r = render_distance as i32;
+        let mut load_order = Vec::with_capacity(n * n * WORLD_HEIGHT_CHUNKS);
+        for x in -r..r {
+            for z in -r..r {
+                for y in 0..WORLD_HEIGHT_CHUNKS as i32 {
+                    load_order.push(ivec3(x, y, z));
+                }
+            }
+        }
+        // A column at a time, so that the minimap sees whole columns
+        load_order.sort_by_key(|offset| (offset.x * offset.x + offset.z * offset.z, offset.x, offset.z, offset.y));
+
         let chunks = std::iter::repeat_with(|| None::<Box<Chunk>>)
             .take(n * n * WORLD_HEIGHT_CHUNKS)
             .collect::<Box<[_]>>();
 
         Self {
-            corner_chunk_pos: player_chunk_pos.xz() - render_distance as i32,
+            corner_chunk_pos: player_chunk_pos.0.xz() - render_distance as i32,
             chunks,
             render_distance,
-            generator: ChunkGenerator::new(world_seed),
+            view_distance: render_distance,
+            generator: ChunkGenerator::new(seed),
             groups: ChunkGroups::new(),
+            changed_columns: Vec::new(),
+            load_order: load_order.into_boxed_slice(),
+            load_cursor: 0,
+            chunks_generated: 0,
+            max_loaded: usize::MAX,
+            loaded: 0,
+            evicted: HashSet::new(),
+        }
+    }
+
+    pub fn corner_chunk_pos(&self) -> IVec2 {
+        self.corner_chunk_pos
+    }
+
+    pub fn render_distance(&self) -> u32 {
+        self.render_distance
+    }
+
+    pub fn view_distance(&self) -> u32 {
+        self.view_distance
+    }
+
+    pub fn set_view_distance(&mut self, distance: u32) {
+        self.view_distance = distance.clamp(2, self.render_distance);
+    }
+
+    // Chunks outside of the loaded area are dropped
+    pub fn insert(&mut self, pos: ChunkPos, mut chunk: Box<Chunk>) {
+        let Some(idx) = self.pos_to_idx(pos) else {
+            return;
+        };
+        chunk.dirty = false;
+        if self.chunks[idx as usize].replace(chunk).is_none() {
+            self.loaded += 1;
+        }
+        self.evicted.remove(&pos);
+        self.changed_columns.push(pos.0.xz());
+        self.mark_neighbors_dirty(pos);
+    }
+
+    // Faces and ambient occlusion along the borders of the neighbours change when a chunk
+    // comes or goes
+    fn mark_neighbors_dirty(&mut self, pos: ChunkPos) {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                for x in -1..=1 {
+                    self.mark_dirty(ChunkPos(pos.0 + ivec3(x, y, z)));
+                }
+            }
+        }
+    }
+
+    // Generates the terrain of a new chunk. Trees and such spilling over from it into
+    // neighbouring chunks that were generated earlier are added to them here.
+    pub fn generate(&mut self, pos: ChunkPos, mut chunk: Box<Chunk>) {
+        let late_pieces = self.generator.generate(pos, &mut chunk);
+        self.insert(pos, chunk);
+
+        for piece in late_pieces {
+            let Some(existing) = self.block_at(piece.pos) else {
+                continue; // Unloaded since; nothing saves chunks yet
+            };
+            let merged = structures::merge(chunk_generator::from_block(existing), piece.block);
+            self.set_block(piece.pos, chunk_generator::to_block(merged));
+        }
+    }
+
+    // Returns false if the chunk isn't loaded
+    pub fn set_block(&mut self, pos: IVec3, block: Block) -> bool {
+        let (chunk_pos, local) = (BlockPos(pos).chunk(), BlockPos(pos).local());
+        let Some(chunk) = self.get_at_mut(chunk_pos) else {
+            return false;
+        };
+        chunk[local] = block;
+        self.changed_columns.push(chunk_pos.0.xz());
+
+        // The faces and ambient occlusion of every block touching this one may change,
+        // and on a corner those are in up to 7 other chunks
+        let local = local.as_ivec3();
+        let around = |c: i32| (if c == 0 { -1 } else { 0 })..=(if c == CHUNK_SIZE as i32 - 1 { 1 } else { 0 });
+        for y in around(local.y) {
+            for z in around(local.z) {
+                for x in around(local.x) {
+                    self.mark_dirty(ChunkPos(chunk_pos.0 + ivec3(x, y, z)));
+                }
+            }
+        }
+        true
+    }
+
+    // For when something all meshes depend on changes, like the texture indices
+    pub fn mark_all_dirty(&mut self) {
+        let n = 2 * self.render_distance as i32;
+        for y in 0..WORLD_HEIGHT_CHUNKS as i32 {
+            for x in 0..n {
+                for z in 0..n {
+                    self.mark_dirty(ChunkPos::new(self.corner_chunk_pos.x + x, y, self.corner_chunk_pos.y + z));
+                }
+            }
+        }
+    }
+
+    fn mark_dirty(&mut self, pos: ChunkPos) {
+        if let Some(chunk) = self.get_at_mut(pos) {
+            chunk.dirty = true;
         }
     }
 
-    pub fn get_at(&self, pos: IVec3) -> Option<&Chunk> {
-        self.chunks[self.pos_to_idx(pos) as usize].as_deref()
+    pub fn drain_changed_columns(&mut self) -> std::vec::Drain<IVec2> {
+        self.changed_columns.drain(..)
     }
 
-    pub fn get_at_mut(&mut self, pos: IVec3) -> Option<&mut Chunk> {
-        self.chunks[self.pos_to_idx(pos) as usize].as_deref_mut()
+    pub fn get_at(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks[self.pos_to_idx(pos)? as usize].as_deref()
+    }
+
+    pub fn get_at_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
+        let idx = self.pos_to_idx(pos)?;
+        self.chunks[idx as usize].as_deref_mut()
+    }
+
+    // None if the chunk isn't loaded
+    pub fn block_at(&self, pos: IVec3) -> Option<Block> {
+        let pos = BlockPos(pos);
+        self.get_at(pos.chunk()).map(|chunk| chunk[pos.local()])
+    }
+
+    pub fn chunks_generated(&self) -> u32 {
+        self.chunks_generated
+    }
+
+    // Loaded chunks and all chunks in the columns within `radius` of the center
+    pub fn area_progress(&self, radius: u32) -> (u32, u32) {
+        let center = self.corner_chunk_pos + self.render_distance as i32;
+        let r = radius.min(self.render_distance) as i32;
+        let (mut loaded, mut total) = (0, 0);
+        for x in -r..r {
+            for z in -r..r {
+                if x * x + z * z > r * r {
+                    continue;
+                }
+                for y in 0..WORLD_HEIGHT_CHUNKS as i32 {
+                    total += 1;
+                    loaded += self.get_at(ChunkPos::new(center.x + x, y, center.y + z)).is_some() as u32;
+                }
+            }
+        }
+        (loaded, total)
+    }
+
+    // Generates up to `max` missing chunks within the view distance, nearest first.
+    // Returns how many were generated.
+    pub fn generate_nearest(&mut self, max: usize) -> usize {
+        let center = self.corner_chunk_pos + self.render_distance as i32;
+        let view_distance = self.view_distance as i32;
+        let mut generated = 0;
+        while generated < max && self.load_cursor < self.load_order.len() {
+            let offset = self.load_order[self.load_cursor];
+            if offset.x * offset.x + offset.z * offset.z > view_distance * view_distance {
+                break; // Rest is further away still
+            }
+            self.load_cursor += 1;
+
+            let pos = ChunkPos::new(center.x + offset.x, offset.y, center.y + offset.z);
+            if self.get_at(pos).is_some() || self.evicted.contains(&pos) {
+                continue;
+            }
+            if self.loaded >= self.max_loaded {
+                self.load_cursor -= 1; // Tried again once there is room
+                break;
+            }
+            let neighbor_indices = [IVec3::X, -IVec3::X, IVec3::Y, -IVec3::Y, IVec3::Z, -IVec3::Z]
+                .map(|dir| self.pos_to_idx(ChunkPos(pos.0 + dir)).unwrap_or(u32::MAX));
+            let chunk = Chunk::new(self.groups.group_of(pos), neighbor_indices);
+            self.generate(pos, chunk);
+            self.chunks_generated += 1;
+            generated += 1;
+        }
+        generated
     }
 
     pub fn remove(&mut self, index: ChunkIndex) -> Option<Box<Chunk>> {
         let chunk = std::mem::take(&mut self.chunks[index as usize]);
+        self.loaded -= chunk.is_some() as usize;
         chunk
     }
 
-    fn pos_to_idx(&self, pos: IVec3) -> ChunkIndex {
-        let grid_xz = (pos.xz() - self.corner_chunk_pos).as_uvec2() & 127;
-        ((pos.y as u32 * 128 * 128) | (grid_xz.x * 128) | grid_xz.y) as ChunkIndex
+    pub fn set_max_loaded(&mut self, max: usize) {
+        self.max_loaded = max;
+    }
+
+    pub fn loaded(&self) -> usize {
+        self.loaded
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.loaded * std::mem::size_of::<Chunk>()
+    }
+
+    // Positions of the loaded chunks, in no particular order
+    pub fn loaded_positions(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        let n = 2 * self.render_distance as usize;
+        let corner = self.corner_chunk_pos;
+        self.chunks.iter().enumerate().filter(|(_, chunk)| chunk.is_some()).map(move |(idx, _)| {
+            let (y, x, z) = (idx / (n * n), idx / n % n, idx % n);
+            ChunkPos::new(corner.x + x as i32, y as i32, corner.y + z as i32)
+        })
+    }
+
+    // Unloads the chunk so that it isn't generated again until restored
+    pub fn evict(&mut self, pos: ChunkPos) {
+        let Some(idx) = self.pos_to_idx(pos) else {
+            return;
+        };
+        if self.remove(idx).is_none() {
+            return;
+        }
+        self.evicted.insert(pos);
+        self.changed_columns.push(pos.0.xz());
+        self.mark_neighbors_dirty(pos);
+    }
+
+    pub fn evicted(&self) -> &HashSet<ChunkPos> {
+        &self.evicted
+    }
+
+    // Lets an evicted chunk be generated again, in turn with the rest
+    pub fn restore(&mut self, pos: ChunkPos) {
+        if self.evicted.remove(&pos) {
+            self.load_cursor = 0;
+        }
+    }
+
+    // None if outside of the loaded area
+    fn pos_to_idx(&self, ChunkPos(pos): ChunkPos) -> Option<ChunkIndex> {
+        let n = 2 * self.render_distance as i32;
+        let grid_xz = pos.xz() - self.corner_chunk_pos;
+        if grid_xz.cmplt(IVec2::ZERO).any() || grid_xz.cmpge(IVec2::splat(n)).any() {
+            return None;
+        }
+        if pos.y < 0 || pos.y >= WORLD_HEIGHT_CHUNKS as i32 {
+            return None;
+        }
+        Some(((pos.y * n + grid_xz.x) * n + grid_xz.y) as ChunkIndex)
     }
 
-    pub fn on_player_exited_chunk(&mut self, new_chunk_pos: IVec3) {
-        let new_corner_pos = new_chunk_pos.xz() - self.render_distance as i32;
+    pub fn on_player_exited_chunk(&mut self, new_chunk_pos: ChunkPos) {
+        let new_corner_pos = new_chunk_pos.0.xz() - self.render_distance as i32;
         let change = new_corner_pos - self.corner_chunk_pos;
     }
 }
 
 impl Chunks {
     pub fn tick(&mut self, res: &mut Resources) -> anyhow::Result<()> {
+        self.generate_nearest(Self::GENERATE_PER_TICK);
         Ok(())
     }
 }
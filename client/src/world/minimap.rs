@@ -0,0 +1,127 @@
+use glam::IVec2;
+use shared::coords::{ChunkPos, LocalPos};
+
+use super::{
+    block::{Block, BlockId},
+    chunk::CHUNK_SIZE,
+    dimension::{Chunks, WORLD_HEIGHT_CHUNKS},
+};
+
+// Top-down colors of the loaded area, one pixel (0xRRGGBBAA) per block column.
+// Updated incrementally from the columns `Chunks` reports as loaded or changed, and
+// drawn from a copy on the GPU, see `renderer::map_texture`.
+pub struct Minimap {
+    corner_chunk_pos: IVec2,
+    size: usize, // in blocks
+    pixels: Box<[u32]>,
+    changed: bool,
+}
+
+impl Minimap {
+    // Columns with nothing loaded in them
+    pub const UNKNOWN: u32 = 0x00_00_00_00;
+
+    pub fn new(chunks: &Chunks) -> Self {
+        let size = 2 * chunks.render_distance() as usize * CHUNK_SIZE;
+        Self {
+            corner_chunk_pos: chunks.corner_chunk_pos(),
+            size,
+            pixels: vec![Self::UNKNOWN; size * size].into_boxed_slice(),
+            changed: true,
+        }
+    }
+
+    pub fn update(&mut self, chunks: &mut Chunks) {
+        if self.corner_chunk_pos != chunks.corner_chunk_pos() {
+            // Loaded area moved. Rare enough that redoing everything is fine.
+            self.corner_chunk_pos = chunks.corner_chunk_pos();
+            let diameter = 2 * chunks.render_distance() as i32;
+            for x in 0..diameter {
+                for z in 0..diameter {
+                    self.update_column(chunks, self.corner_chunk_pos + IVec2::new(x, z));
+                }
+            }
+            chunks.drain_changed_columns();
+            return;
+        }
+
+        let mut changed = chunks.drain_changed_columns().collect::<Vec<_>>();
+        changed.sort_unstable_by_key(|pos| (pos.x, pos.y));
+        changed.dedup();
+        for column in changed {
+            self.update_column(chunks, column);
+        }
+    }
+
+    // Width and height, in blocks
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // World block XZ position of the first pixel
+    pub fn corner_block(&self) -> IVec2 {
+        self.corner_chunk_pos * CHUNK_SIZE as i32
+    }
+
+    // All pixels, row by row starting from the -Z edge, if any changed since the last call
+    pub fn take_changes(&mut self) -> Option<&[u32]> {
+        std::mem::take(&mut self.changed).then_some(&*self.pixels)
+    }
+
+    fn update_column(&mut self, chunks: &Chunks, chunk_xz: IVec2) {
+        let local = (chunk_xz - self.corner_chunk_pos) * CHUNK_SIZE as i32;
+        if local.cmplt(IVec2::ZERO).any() || local.cmpge(IVec2::splat(self.size as i32)).any() {
+            return;
+        }
+
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let color = Self::top_color(chunks, chunk_xz, x, z);
+                self.pixels[(local.y as usize + z) * self.size + local.x as usize + x] = color;
+            }
+        }
+        self.changed = true;
+    }
+
+    fn top_color(chunks: &Chunks, chunk_xz: IVec2, x: usize, z: usize) -> u32 {
+        let mut any_loaded = false;
+        for cy in (0..WORLD_HEIGHT_CHUNKS as i32).rev() {
+            let Some(chunk) = chunks.get_at(ChunkPos::new(chunk_xz.x, cy, chunk_xz.y)) else {
+                continue;
+            };
+            any_loaded = true;
+            for y in (0..CHUNK_SIZE).rev() {
+                let block = chunk[LocalPos::new(x as i32, y as i32, z as i32).unwrap()];
+                if block.id() != BlockId::AIR {
+                    return Self::shade(Self::block_color(block), cy as usize * CHUNK_SIZE + y);
+                }
+            }
+        }
+        if any_loaded {
+            0x10_10_10_FF // Loaded, but nothing there
+        } else {
+            Self::UNKNOWN
+        }
+    }
+
+    fn block_color(block: Block) -> u32 {
+        match block.id() {
+            BlockId::STONE => 0x80_80_80_FF,
+            BlockId::TORCH => 0xFF_C0_40_FF,
+            BlockId::LOG => 0x6B_4A_2B_FF,
+            BlockId::LEAVES => 0x3C_8C_32_FF,
+            // No per-block colors yet; derive something stable from the id
+            _ => {
+                let h = (block.id().raw() as u32).wrapping_mul(0x9E37_79B9);
+                (h & 0xFF_FF_FF_00) | 0xFF
+            }
+        }
+    }
+
+    // Higher = brighter, so that height differences are visible
+    fn shade(color: u32, y: usize) -> u32 {
+        let factor = 0.6 + 0.8 * (y as f32 / (WORLD_HEIGHT_CHUNKS * CHUNK_SIZE) as f32);
+        let channel = |shift: u32| (((color >> shift) & 0xFF) as f32 * factor).min(255.0) as u32;
+        (channel(24) << 24) | (channel(16) << 16) | (channel(8) << 8) | (color & 0xFF)
+    }
+}
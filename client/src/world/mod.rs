@@ -4,3 +4,5 @@ pub mod chunk_generator;
 pub mod chunk_group;
 pub mod chunk_renderer;
 pub mod dimension;
+pub mod minimap;
+pub mod raycast;
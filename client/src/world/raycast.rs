@@ -0,0 +1,49 @@
+use glam::{IVec3, Vec3};
+use shared::coords::BlockPos;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub block_pos: IVec3,
+    // Normal of the face that was hit; zero if the ray started inside a solid block
+    pub normal: IVec3,
+    pub distance: f32,
+}
+
+// Voxel traversal (Amanatides & Woo). `dir` must be normalized.
+pub fn raycast(origin: Vec3, dir: Vec3, max_distance: f32, mut is_solid: impl FnMut(IVec3) -> bool) -> Option<RayHit> {
+    let BlockPos(mut block_pos) = BlockPos::containing(origin);
+    let step = dir.signum().as_ivec3();
+
+    // Distance along the ray between two block boundaries, per axis
+    let delta = (1.0 / dir).abs();
+    // Distance along the ray to the first block boundary, per axis
+    let mut next = Vec3::select(
+        dir.cmpgt(Vec3::ZERO),
+        (block_pos.as_vec3() + 1.0 - origin) * delta,
+        (origin - block_pos.as_vec3()) * delta,
+    );
+    // Never crosses a boundary on axes the ray is parallel to (and avoids 0 * inf = NaN)
+    next = Vec3::select(dir.cmpeq(Vec3::ZERO), Vec3::splat(f32::INFINITY), next);
+
+    let mut normal = IVec3::ZERO;
+    let mut distance = 0.0;
+    while distance <= max_distance {
+        if is_solid(block_pos) {
+            return Some(RayHit { block_pos, normal, distance });
+        }
+
+        let axis = if next.x < next.y && next.x < next.z {
+            0
+        } else if next.y < next.z {
+            1
+        } else {
+            2
+        };
+        distance = next[axis];
+        next[axis] += delta[axis];
+        block_pos[axis] += step[axis];
+        normal = IVec3::ZERO;
+        normal[axis] = -step[axis];
+    }
+    None
+}
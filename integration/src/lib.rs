@@ -0,0 +1,402 @@
+// End-to-end smoke test: boots the real server binary on a free local port and logs into it
+// over QUIC, the way the client does. The client itself can't run without a window and a GPU,
+// so `SmokeClient` speaks the protocol directly, using the same `shared` code as
+// `client::networking`. Run with `cargo test` in this directory; the server is built first.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, UdpSocket},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use quinn::{Endpoint, NewConnection, RecvStream, SendStream, VarInt};
+use shared::{
+    bits_and_bytes::{BitWriter, ByteReader, ByteWriter},
+    protocol::{
+        batching::{Batcher, Unbatcher},
+        c2s::{write_player_state, Hello, PlayerInput},
+        s2c::{self, read_entity_state, EntityStateMsg, LoginResponse, LoginStatus},
+        Features, PROTOCOL_MAGIC, PROTOCOL_VERSION,
+    },
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+const CLIENT_FEATURES: Features = Features::COMPRESSION;
+const MAX_RECEIVED_BATCH_LEN: usize = 1 << 20;
+
+// How long the server gets to build, start and stop
+const BUILD_TIMEOUT: Duration = Duration::from_secs(600);
+const START_TIMEOUT: Duration = Duration::from_secs(30);
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct SmokeServer {
+    process: Child,
+    stdin: ChildStdin,
+    stdout: mpsc::Receiver<String>,
+    pub address: SocketAddr,
+    pub dir: PathBuf,
+}
+
+impl SmokeServer {
+    // Builds the server and starts it in a fresh directory, with a `server.cfg` made of
+    // `config_lines`. Nothing is read from or written to the server's own directory.
+    pub fn start(name: &str, config_lines: &[&str]) -> Result<Self> {
+        let server_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../server");
+        let build = Command::new(env!("CARGO"))
+            .args(["build", "--manifest-path"])
+            .arg(server_dir.join("Cargo.toml"))
+            .status()
+            .context("Failed to run cargo")?;
+        if !build.success() {
+            bail!("Building the server failed");
+        }
+        let binary = server_dir.join("target/debug/server");
+
+        let dir = std::env::temp_dir().join(format!("voxel-smoke-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("server.cfg"), config_lines.join("\n") + "\n")?;
+
+        // Free right now at least; the server binds it a moment later
+        let address = UdpSocket::bind("127.0.0.1:0")?.local_addr()?;
+
+        let mut process = Command::new(binary)
+            .arg(address.to_string())
+            .current_dir(&dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Failed to start the server")?;
+        let stdin = process.stdin.take().unwrap();
+
+        // Forwarded (and echoed, for when the test fails) from a thread, so that reads can time out
+        let (lines_in, stdout) = mpsc::channel();
+        let reader = BufReader::new(process.stdout.take().unwrap());
+        std::thread::spawn(move || {
+            for line in reader.lines().flatten() {
+                println!("[server] {line}");
+                if lines_in.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut server = Self { process, stdin, stdout, address, dir };
+        server.wait_for_output("Server running @", START_TIMEOUT)?;
+        Ok(server)
+    }
+
+    // Skips output until a line containing `text`
+    pub fn wait_for_output(&mut self, text: &str, timeout: Duration) -> Result<String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.stdout.recv_timeout(remaining) {
+                Ok(line) if line.contains(text) => return Ok(line),
+                Ok(_) => {}
+                Err(_) => bail!("Server didn't print '{text}' within {timeout:?}"),
+            }
+        }
+    }
+
+    pub fn console(&mut self, command: &str) -> Result<()> {
+        writeln!(self.stdin, "{command}")?;
+        Ok(self.stdin.flush()?)
+    }
+
+    // Through the console, the way an admin would; killed if that doesn't work
+    pub fn stop(mut self) -> Result<()> {
+        self.console("stop")?;
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        while Instant::now() < deadline {
+            if let Some(status) = self.process.try_wait()? {
+                if !status.success() {
+                    bail!("Server exited with {status}");
+                }
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let _ = self.process.kill();
+        bail!("Server didn't stop within {STOP_TIMEOUT:?}")
+    }
+
+    pub fn read_file(&self, name: &str) -> Result<String> {
+        Ok(std::fs::read_to_string(self.dir.join(name))?)
+    }
+}
+
+impl Drop for SmokeServer {
+    fn drop(&mut self) {
+        // Does nothing if it already stopped
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+// A logged in player without a game around it
+pub struct SmokeClient {
+    endpoint: Endpoint,
+    connection: quinn::Connection,
+    chat_send: SendStream,
+    chat_batcher: Batcher,
+    pub login: LoginResponse,
+    pub chat: UnboundedReceiver<String>,
+    pub entity_state: UnboundedReceiver<EntityStateMsg>,
+    next_tag: u16,
+}
+
+impl SmokeClient {
+    // Same order of streams as client::networking::network_thread
+    pub async fn connect(address: SocketAddr, username: &str) -> Result<Self> {
+        let endpoint = setup::make_client_endpoint()?;
+        let mut new_conn = endpoint.connect(address, "localhost")?.await?;
+        let login = login(&new_conn, username).await?;
+        let features = CLIENT_FEATURES.common(login.features);
+
+        let (mut chat_send, chat_recv) = new_conn.connection.open_bi().await?;
+        chat_send.write_all(&[0]).await?;
+        let (chat_in, chat) = unbounded_channel();
+        tokio::spawn(async move {
+            let mut buf = Vec::new();
+            let mut unbatcher = Unbatcher::new(features, MAX_RECEIVED_BATCH_LEN);
+            let mut chat_recv = chat_recv;
+            loop {
+                let bytes = if unbatcher.has_batched() {
+                    unbatcher.next_in_batch()?
+                } else {
+                    receive_bytes(&mut chat_recv, &mut buf).await?;
+                    unbatcher.unpack(&buf)?
+                };
+                let (_, message) = s2c::read_chat(&mut ByteReader::new(bytes))?;
+                if chat_in.send(message.to_owned()).is_err() {
+                    return anyhow::Ok(());
+                }
+            }
+        });
+
+        let mut player_state_send = new_conn.connection.open_uni().await?;
+        player_state_send.write_all(&[0]).await?;
+
+        let mut entity_state_recv = new_conn.uni_streams.next().await.context("No entity state stream")??;
+        entity_state_recv.read_exact(&mut [0u8]).await?;
+        let (entity_state_in, entity_state) = unbounded_channel();
+        tokio::spawn(async move {
+            let mut buf = Vec::new();
+            let mut messages = Vec::new();
+            let mut unbatcher = Unbatcher::new(features, MAX_RECEIVED_BATCH_LEN);
+            let mut prev_tag = u16::MAX;
+            loop {
+                let bytes = if unbatcher.has_batched() {
+                    unbatcher.next_in_batch()?
+                } else {
+                    receive_bytes(&mut entity_state_recv, &mut buf).await?;
+                    unbatcher.unpack(&buf)?
+                };
+                messages.clear();
+                read_entity_state(&mut ByteReader::new(bytes), &mut prev_tag, &mut messages)?;
+                for message in messages.drain(..) {
+                    if entity_state_in.send(message).is_err() {
+                        return anyhow::Ok(());
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            endpoint,
+            connection: new_conn.connection,
+            chat_send,
+            chat_batcher: Batcher::new(features),
+            login,
+            chat,
+            entity_state,
+            next_tag: 0,
+        })
+    }
+
+    pub async fn send_chat(&mut self, message: &str) -> Result<()> {
+        self.chat_batcher.push(message.as_bytes());
+        Ok(self.chat_send.write_all(self.chat_batcher.finish()).await?)
+    }
+
+    // One tick worth of movement; returns the input's tag
+    pub fn send_input(&mut self, delta_pos: glam::Vec3) -> Result<u16> {
+        let input = PlayerInput {
+            tag: self.next_tag,
+            delta_pos: Some(delta_pos),
+            delta_yaw_pitch: None,
+            movement: shared::movement::MovementFlags::NONE,
+        };
+        self.next_tag = self.next_tag.wrapping_add(1);
+
+        let mut buf = [0u8; 64];
+        let mut writer = BitWriter::new(&mut buf);
+        write_player_state(&mut writer, &input, std::iter::empty());
+        let len = writer.compute_bytes_written();
+        self.connection.send_datagram(Bytes::copy_from_slice(&buf[..len]))?;
+        Ok(input.tag)
+    }
+
+    // Waits for a chat message satisfying `accept`
+    pub async fn wait_for_chat(&mut self, timeout: Duration, accept: impl Fn(&str) -> bool) -> Result<String> {
+        let wait = async {
+            while let Some(message) = self.chat.recv().await {
+                if accept(&message) {
+                    return Ok(message);
+                }
+            }
+            bail!("Chat stream closed")
+        };
+        tokio::time::timeout(timeout, wait).await.context("Timed out waiting for chat")?
+    }
+
+    // The server's position for the latest input it has validated so far, if any
+    pub fn latest_server_pos(&mut self) -> Option<(u16, glam::Vec3)> {
+        let mut latest = None;
+        while let Ok(message) = self.entity_state.try_recv() {
+            if let EntityStateMsg::InputValidated { tag, server_pos, .. } = message {
+                latest = Some((tag, server_pos));
+            }
+        }
+        latest
+    }
+
+    pub async fn disconnect(self) {
+        self.connection.close(VarInt::from_u32(1), &[]);
+        self.endpoint.wait_idle().await;
+    }
+}
+
+async fn login(new_conn: &NewConnection, username: &str) -> Result<LoginResponse> {
+    let mut buf = [0u8; 256];
+    let mut writer = ByteWriter::new_for_message(&mut buf);
+    Hello {
+        magic: PROTOCOL_MAGIC,
+        version: PROTOCOL_VERSION,
+        username,
+        skin: 0,
+        features: CLIENT_FEATURES,
+        key: [0; 16], // only checked against the identities file, which the tests turn off
+    }.write(&mut writer);
+    writer.write_message_len();
+
+    let (mut hello_send, mut hello_recv) = new_conn.connection.open_bi().await?;
+    hello_send.write_all(writer.bytes()).await?;
+
+    let mut recv_buf = Vec::new();
+    loop {
+        let mut reader = receive_bytes(&mut hello_recv, &mut recv_buf).await?;
+        match LoginStatus::read(&mut reader) {
+            Ok(LoginStatus::Queued { .. }) => {}
+            Ok(LoginStatus::Accepted(response)) => return Ok(response),
+            Err(e) => bail!("Invalid login response from server: {e}"),
+        }
+    }
+}
+
+// Same framing as client::networking::connection::receive_bytes
+async fn receive_bytes<'a>(stream: &mut RecvStream, buf: &'a mut Vec<u8>) -> Result<ByteReader<'a>> {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header).await?;
+
+    let mut length = header[0] as usize;
+    if length > 127 {
+        length = length - 128 + ((header[1] as usize) << 7);
+    }
+    if length == 0 {
+        bail!("Received zero-length message from server");
+    }
+
+    buf.resize(length, 0);
+    let slice = if length > 127 {
+        &mut buf[..length]
+    } else {
+        buf[0] = header[1];
+        &mut buf[1..length]
+    };
+    stream.read_exact(slice).await?;
+    Ok(ByteReader::new(&mut buf[..]))
+}
+
+mod setup {
+    use super::*;
+
+    pub(super) fn make_client_endpoint() -> Result<Endpoint> {
+        let mut endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
+        let crypto = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(SkipServerVerification))
+            .with_no_client_auth();
+        endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+        Ok(endpoint)
+    }
+
+    // The server makes up a self-signed certificate every start
+    struct SkipServerVerification;
+
+    impl rustls::client::ServerCertVerifier for SkipServerVerification {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &rustls::Certificate,
+            _intermediates: &[rustls::Certificate],
+            _server_name: &rustls::ServerName,
+            _scts: &mut dyn Iterator<Item = &[u8]>,
+            _ocsp_response: &[u8],
+            _now: std::time::SystemTime,
+        ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+            Ok(rustls::client::ServerCertVerified::assertion())
+        }
+    }
+}
+
+mod tests {
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_smoke() {
+        use std::time::Duration;
+        use glam::vec3;
+        use shared::TICK_DURATION;
+        use super::{SmokeClient, SmokeServer};
+
+        let server = SmokeServer::start("smoke", &[
+            "world_file =",
+            "shutdown_countdown = 0",
+            "ops_file =",
+            "bans_file =",
+            "whitelist_file =",
+            "identities_file =",
+        ]).unwrap();
+        let mut client = SmokeClient::connect(server.address, "smoke").await.unwrap();
+        let spawn = client.login.position;
+
+        client.wait_for_chat(Duration::from_secs(5), |message| message == "smoke joined").await.unwrap();
+
+        // Walking speed: 0.25 blocks per tick is well below what the server lets through
+        let mut last_tag = 0;
+        for _ in 0..100 {
+            last_tag = client.send_input(vec3(0.25, 0.0, 0.0)).unwrap();
+            tokio::time::sleep(TICK_DURATION).await;
+        }
+        tokio::time::sleep(TICK_DURATION * 10).await;
+        let (tag, server_pos) = client.latest_server_pos().expect("no inputs validated");
+        // Datagrams may be dropped even locally, but not most of them
+        assert!(last_tag.wrapping_sub(tag) < 10, "latest validated {tag}, sent {last_tag}");
+        assert!(server_pos.x - spawn.x > 10.0, "spawned at {spawn}, server has {server_pos}");
+
+        client.send_chat("hello from the smoke test").await.unwrap();
+        client.wait_for_chat(Duration::from_secs(5), |message| message == "smoke: hello from the smoke test").await.unwrap();
+        client.send_chat("/list").await.unwrap();
+        client.wait_for_chat(Duration::from_secs(5), |message| message == "Online (1): smoke").await.unwrap();
+
+        client.disconnect().await;
+        let chat_log = server.read_file("chat.log").unwrap();
+        server.stop().unwrap();
+        assert!(chat_log.lines().any(|line| line.ends_with("] smoke joined")), "{chat_log}");
+        assert!(chat_log.lines().any(|line| line.ends_with("] smoke: hello from the smoke test")), "{chat_log}");
+    }
+}
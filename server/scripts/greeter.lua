@@ -0,0 +1,13 @@
+-- Example script: greets joining players and answers to "!ping".
+
+function on_player_join(player)
+    send_message(player, "Welcome, " .. player .. "!")
+end
+
+function on_chat(player, message)
+    if message == "!ping" then
+        send_message(player, "pong")
+        return true
+    end
+    return false
+end
@@ -0,0 +1,235 @@
+use hecs::Entity;
+use shared::{protocol::s2c::{MetadataKey, MetadataValue}, TICKS_PER_SECOND};
+
+use crate::{
+    chat,
+    components::{HeadYawPitch, Metadata, OldPosition, PlayerId, Position, Username, YawPitch},
+    config::ServerConfig,
+    game_builder::{GameBuilder, Stage},
+    resources::Resources,
+    scheduler::TaskHandle,
+};
+
+// Players who haven't done anything for a while are marked AFK (visible to others through
+// the `Afk` metadata and `/list`), and optionally kicked after a longer while. Each player
+// has a scheduled check for when that would next happen, which pushes itself back if they
+// did something meanwhile. Coming back is noticed right away.
+pub struct AfkSettings {
+    // In ticks
+    mark_after: Option<u32>,
+    kick_after: Option<u32>,
+}
+
+// Player component
+pub struct Activity {
+    // Turning, walking, toggling sneak or sprint, or chatting. Being moved doesn't count.
+    last_input_tick: u32,
+    afk: bool,
+    check: Option<TaskHandle>,
+}
+
+impl AfkSettings {
+    fn from_config(config: &ServerConfig) -> Self {
+        let ticks = |secs: u64| (secs > 0).then(|| secs.saturating_mul(TICKS_PER_SECOND as u64).min(u32::MAX as u64) as u32);
+        Self { mark_after: ticks(config.afk_after), kick_after: ticks(config.afk_kick_after) }
+    }
+
+    // The tick of the next check for a player with this activity, if any is needed
+    fn next_check(&self, activity: &Activity) -> Option<u32> {
+        let mark = self.mark_after.filter(|_| !activity.afk);
+        [mark, self.kick_after].into_iter().flatten().min().map(|after| activity.last_input_tick.saturating_add(after))
+    }
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    let settings = builder.resource::<ServerConfig>()
+        .map_or(AfkSettings { mark_after: None, kick_after: None }, AfkSettings::from_config);
+
+    builder
+        .insert_resource(settings)
+        .add_system(Stage::Update, track_activity)
+        .on_player_join(start_tracking)
+        .on_player_leave(stop_tracking)
+        // Before the chat commands, which would otherwise hide the message
+        .on_chat(note_chat_activity)
+        .on_chat(list_from_chat)
+        .on_console_command(list_from_console);
+}
+
+// See `reload`. Idle time counted so far carries over.
+pub fn reconfigure(res: &mut Resources, config: &ServerConfig) {
+    if let Some(settings) = res.extra.get_mut::<AfkSettings>() {
+        *settings = AfkSettings::from_config(config);
+    }
+    let players = res.main_world.query_mut::<&Activity>().into_iter().map(|(player, _)| player).collect::<Vec<_>>();
+    for player in players {
+        schedule_check(res, player);
+    }
+}
+
+fn start_tracking(res: &mut Resources, player: Entity) {
+    let activity = Activity { last_input_tick: res.current_tick, afk: false, check: None };
+    if let Ok(metadata) = res.main_world.query_one_mut::<&mut Metadata>(player) {
+        metadata.set(MetadataKey::Afk, MetadataValue::Bool(false));
+    }
+    let _ = res.main_world.insert_one(player, activity);
+    schedule_check(res, player);
+}
+
+fn stop_tracking(res: &mut Resources, player: Entity) {
+    let check = res.main_world.get::<&mut Activity>(player).ok().and_then(|mut activity| activity.check.take());
+    if let Some(check) = check {
+        res.scheduler.cancel(check);
+    }
+}
+
+// Replaces the player's pending check, if any
+fn schedule_check(res: &mut Resources, player: Entity) {
+    let Ok(mut activity) = res.main_world.get::<&mut Activity>(player) else {
+        return;
+    };
+    if let Some(check) = activity.check.take() {
+        res.scheduler.cancel(check);
+    }
+    let next = res.extra.get::<AfkSettings>().and_then(|settings| settings.next_check(&activity));
+    activity.check = next.map(|tick| res.scheduler.at(tick, move |res| check_idle(res, player)));
+}
+
+fn note_chat_activity(res: &mut Resources, sender: Entity, _message: &str) -> bool {
+    if let Ok(mut activity) = res.main_world.get::<&mut Activity>(sender) {
+        activity.last_input_tick = res.current_tick;
+    }
+    let back = res.main_world.get::<&Activity>(sender).map_or(false, |activity| activity.afk);
+    if back {
+        set_back(res, sender);
+    }
+    false
+}
+
+fn track_activity(res: &mut Resources) -> anyhow::Result<()> {
+    let now = res.current_tick;
+    let mut back = Vec::new();
+    for (player, (position, old_position, head_rotation, metadata, activity)) in res.main_world
+        .query_mut::<(&Position, &OldPosition, &HeadYawPitch, &Metadata, &mut Activity)>() {
+
+        // Walking, but not vertically: that is falling more often than not
+        let delta = position.0 - old_position.0;
+        let walked = delta.x != 0.0 || delta.z != 0.0;
+        let turned = head_rotation.delta != YawPitch::ZERO;
+        let toggled = metadata.changed_entries().any(|(key, _)| matches!(key, MetadataKey::Crouching | MetadataKey::Sprinting));
+        if walked || turned || toggled {
+            activity.last_input_tick = now;
+            if activity.afk {
+                back.push(player);
+            }
+        }
+    }
+    for player in back {
+        set_back(res, player);
+    }
+    Ok(())
+}
+
+fn set_back(res: &mut Resources, player: Entity) {
+    let Ok((Username(name), metadata, activity)) = res.main_world.query_one_mut::<(&Username, &mut Metadata, &mut Activity)>(player) else {
+        return;
+    };
+    activity.afk = false;
+    metadata.set(MetadataKey::Afk, MetadataValue::Bool(false));
+    let name = name.clone();
+    chat::broadcast_notice(res, format!("{name} is no longer AFK").into());
+    // Marking them as AFK again needs a check of its own
+    schedule_check(res, player);
+}
+
+fn check_idle(res: &mut Resources, player: Entity) {
+    let now = res.current_tick;
+    let Some(settings) = res.extra.get::<AfkSettings>() else {
+        return;
+    };
+    let (mark_after, kick_after) = (settings.mark_after, settings.kick_after);
+    let Ok((&player_id, Username(name), metadata, activity)) = res.main_world
+        .query_one_mut::<(&PlayerId, &Username, &mut Metadata, &mut Activity)>(player) else {
+        return;
+    };
+    activity.check = None;
+    let name = name.clone();
+
+    let idle = now.saturating_sub(activity.last_input_tick);
+    if kick_after.map_or(false, |after| idle >= after) {
+        if res.net.kick(player_id, "Idle for too long".into()) {
+            println!("Kicked {name} for being idle");
+            chat::broadcast_notice(res, format!("{name} was kicked for being idle").into());
+        }
+        return;
+    }
+    if !activity.afk && mark_after.map_or(false, |after| idle >= after) {
+        activity.afk = true;
+        metadata.set(MetadataKey::Afk, MetadataValue::Bool(true));
+        chat::broadcast_notice(res, format!("{name} is now AFK").into());
+    }
+    schedule_check(res, player);
+}
+
+// "Online (2): alice, bob (AFK)"
+pub fn player_list(res: &mut Resources) -> String {
+    let mut players = res.main_world.query_mut::<(&PlayerId, &Username, Option<&Activity>)>()
+        .into_iter()
+        .map(|(_, (_, Username(name), activity))| match activity {
+            Some(activity) if activity.afk => format!("{name} (AFK)"),
+            _ => name.to_string(),
+        })
+        .collect::<Vec<_>>();
+    players.sort_unstable();
+    format!("Online ({}): {}", players.len(), players.join(", "))
+}
+
+fn list_from_chat(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    if message.trim_end() != "/list" {
+        return false;
+    }
+    let Ok(&player_id) = res.main_world.get::<&PlayerId>(sender).as_deref() else {
+        return true;
+    };
+    let list = player_list(res);
+    res.net.send_chat(player_id, list.into());
+    true
+}
+
+fn list_from_console(res: &mut Resources, command: &str, _args: &str) -> bool {
+    if command != "list" {
+        return false;
+    }
+    println!("{}", player_list(res));
+    true
+}
+
+mod tests {
+    #[test]
+    fn test_afk() {
+        use glam::{Vec2, Vec3};
+        use shared::{movement::MovementFlags, protocol::s2c::{MetadataKey, MetadataValue}, TICKS_PER_SECOND};
+        use crate::{config::ServerConfig, testing::TestServer};
+
+        let config = ServerConfig { afk_after: 2, afk_kick_after: 4, ..TestServer::config() };
+        let mut server = TestServer::with_config(config);
+        let alice = server.connect("alice");
+        let bob = server.connect("bob");
+        server.run_ticks(2 * TICKS_PER_SECOND + 2);
+        assert_eq!(server.metadata(alice, MetadataKey::Afk), Some(MetadataValue::Bool(true)));
+        assert!(server.received_chat(alice).iter().any(|(_, message)| message.as_str() == "bob is now AFK"));
+
+        server.send_input(alice, Vec3::ZERO, Vec2::new(0.1, 0.0), MovementFlags::NONE);
+        server.run_ticks(4);
+        assert_eq!(server.metadata(alice, MetadataKey::Afk), Some(MetadataValue::Bool(false)));
+        assert!(server.received_chat(bob).iter().any(|(_, message)| message.as_str() == "alice is no longer AFK"));
+
+        server.send_chat(alice, "/list");
+        server.tick();
+        assert!(server.received_chat(alice).iter().any(|(_, message)| message.as_str() == "Online (2): alice, bob (AFK)"));
+
+        server.run_ticks(2 * TICKS_PER_SECOND);
+        assert!(server.kicked(bob).is_some());
+        assert!(server.kicked(alice).is_none());
+    }
+}
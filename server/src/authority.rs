@@ -0,0 +1,202 @@
+use hecs::Entity;
+use shared::{
+    protocol::{c2s::{AuthorityMsg, BlockAction}, s2c::{MetadataKey, MetadataValue, TeleportFlags}, NetworkId},
+    TICK_DURATION,
+};
+
+use crate::{
+    block_breaking,
+    block_placing,
+    components::{Authority, HeadYawPitch, Metadata, OldPosition, PlayerId, Position},
+    game_builder::{GameBuilder, Stage},
+    net,
+    resources::Resources,
+};
+
+// Clients taking over the simulation of entities, such as the minecart they're riding, so
+// that they can predict it like they predict the player. The server still checks what they
+// do with it: only claimable entities within reach can be claimed, only the owner may move
+// or release one, and moves faster than MAX_SPEED get the entity teleported back.
+//
+// Nothing makes entities claimable yet; gameplay code does that with `set_authority()`.
+
+// From the player to the entity
+const CLAIM_RANGE: f32 = 6.0;
+// Owners further away than this lose their authority
+const RELEASE_RANGE: f32 = 32.0;
+// Blocks per second, over a tick
+const MAX_SPEED: f32 = 16.0;
+
+pub fn plugin(builder: &mut GameBuilder) {
+    builder
+        // Before net::update_entity_trackers, so that accepted moves go out this tick
+        .add_system(Stage::NetIn, process_authority_msgs)
+        .add_system(Stage::Update, release_abandoned)
+        .on_player_leave(release_all);
+}
+
+// Also tells everybody tracking the entity who the owner is, through MetadataKey::Owner
+pub fn set_authority(res: &mut Resources, entity: Entity, authority: Authority) -> anyhow::Result<()> {
+    let owner = match authority {
+        Authority::Client(owner) => owner,
+        Authority::Server | Authority::Claimable => NetworkId::INVALID,
+    };
+    res.main_world.query_one_mut::<&mut Metadata>(entity)?
+        .set(MetadataKey::Owner, MetadataValue::Uint(owner.raw() as u32));
+    res.main_world.insert_one(entity, authority)?;
+    Ok(())
+}
+
+pub fn authority_of(res: &Resources, entity: Entity) -> Authority {
+    res.main_world.get::<&Authority>(entity).map_or(Authority::Server, |authority| *authority)
+}
+
+fn process_authority_msgs(res: &mut Resources) -> anyhow::Result<()> {
+    while let Some((sender, msg)) = res.net.poll_authority() {
+        // Either may have just gone away
+        let Some(player) = res.net.entity_of(sender) else {
+            continue;
+        };
+        let id = match msg {
+            AuthorityMsg::Claim { id } | AuthorityMsg::Release { id } | AuthorityMsg::Move { id, .. } => id,
+            AuthorityMsg::Resync => {
+                if let Ok(&player_id) = res.main_world.get::<&PlayerId>(player).as_deref() {
+                    res.net.resync_entities(player_id);
+                }
+                continue;
+            }
+            AuthorityMsg::Block(action) => {
+                match action {
+                    BlockAction::StartBreaking { pos } => block_breaking::start(res, player, pos),
+                    BlockAction::CancelBreaking => block_breaking::cancel(res, player),
+                    BlockAction::FinishBreaking { pos } => block_breaking::finish(res, player, pos),
+                    BlockAction::Place { pos, block } => block_placing::place(res, player, pos, block),
+                }
+                continue;
+            }
+        };
+        let Some(entity) = res.net.entity_of(id) else {
+            continue;
+        };
+
+        match (msg, authority_of(res, entity)) {
+            (AuthorityMsg::Claim { .. }, Authority::Claimable) => {
+                let player_pos = res.main_world.get::<&Position>(player)?.0;
+                let entity_pos = res.main_world.get::<&Position>(entity)?.0;
+                if player_pos.distance(entity_pos) <= CLAIM_RANGE {
+                    set_authority(res, entity, Authority::Client(sender))?;
+                }
+            }
+            (AuthorityMsg::Release { .. }, Authority::Client(owner)) if owner == sender => {
+                set_authority(res, entity, Authority::Claimable)?;
+            }
+            (AuthorityMsg::Move { position, head_rotation, .. }, Authority::Client(owner)) if owner == sender => {
+                let (Position(current), &OldPosition(old), head) = res.main_world
+                    .query_one_mut::<(&mut Position, &OldPosition, &mut HeadYawPitch)>(entity)?;
+                // Against where it was at the start of the tick, so that splitting a move up
+                // doesn't get around the limit
+                if position.distance(old) <= MAX_SPEED * TICK_DURATION.as_secs_f32() {
+                    *current = position;
+                    head.delta += head_rotation - head.value;
+                    head.value = head_rotation;
+                } else {
+                    let (current, yaw_pitch) = (*current, head.value);
+                    net::teleport(res, entity, current, yaw_pitch, TeleportFlags::ABSOLUTE)?;
+                }
+            }
+            // Claimed by someone else first, released twice and such: nothing to tell
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn release_abandoned(res: &mut Resources) -> anyhow::Result<()> {
+    let owned = res.main_world.query_mut::<(&Authority, &Position)>()
+        .into_iter()
+        .filter_map(|(entity, (&authority, &Position(position)))| match authority {
+            Authority::Client(owner) => Some((entity, owner, position)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for (entity, owner, position) in owned {
+        let owner_pos = res.net.entity_of(owner).and_then(|player| res.main_world.get::<&Position>(player).ok().map(|pos| pos.0));
+        if owner_pos.map_or(true, |owner_pos| owner_pos.distance(position) > RELEASE_RANGE) {
+            set_authority(res, entity, Authority::Claimable)?;
+        }
+    }
+    Ok(())
+}
+
+fn release_all(res: &mut Resources, player: Entity) {
+    let Ok(owner) = res.main_world.get::<&NetworkId>(player).map(|nid| *nid) else {
+        return;
+    };
+    let owned = res.main_world.query_mut::<&Authority>()
+        .into_iter()
+        .filter(|(_, &authority)| authority == Authority::Client(owner))
+        .map(|(entity, _)| entity)
+        .collect::<Vec<_>>();
+    for entity in owned {
+        if let Err(e) = set_authority(res, entity, Authority::Claimable) {
+            eprintln!("Failed to release entity {entity:?}: {e}");
+        }
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_authority() {
+        use glam::{vec2, vec3, Vec2};
+        use shared::protocol::{c2s::AuthorityMsg, s2c::{MetadataKey, MetadataValue, TeleportFlags}, NetworkId};
+        use crate::{authority, components::{Authority, Metadata, MobKind, Position}, net, networking::client_connection::entity_state::EntityStateMsg, spawning, testing::TestServer};
+
+        let mut server = TestServer::new();
+        let alice = server.connect("alice");
+        let bob = server.connect("bob");
+        let cart = spawning::spawn_mob(&mut server.res, MobKind::Pig, vec3(3.0, 0.5, 0.0)).unwrap();
+        let id = *server.res.main_world.get::<&NetworkId>(cart).unwrap();
+        let owner = |server: &TestServer| server.res.main_world.get::<&Metadata>(cart).unwrap().get(MetadataKey::Owner).cloned();
+        let position = |server: &TestServer| server.res.main_world.get::<&Position>(cart).unwrap().0;
+
+        // Not claimable
+        server.send_authority(alice, AuthorityMsg::Claim { id });
+        server.tick();
+        assert_eq!(authority::authority_of(&server.res, cart), Authority::Server);
+
+        authority::set_authority(&mut server.res, cart, Authority::Claimable).unwrap();
+        let bob_entity = server.client(bob).entity;
+        net::teleport(&mut server.res, bob_entity, vec3(20.0, 0.0, 0.0), Vec2::ZERO, TeleportFlags::ABSOLUTE).unwrap();
+        server.tick();
+        // Out of reach
+        server.send_authority(bob, AuthorityMsg::Claim { id });
+        server.tick();
+        assert_eq!(authority::authority_of(&server.res, cart), Authority::Claimable);
+
+        let alice_id = server.client(alice).network_id;
+        server.send_authority(alice, AuthorityMsg::Claim { id });
+        server.send_authority(bob, AuthorityMsg::Release { id });
+        server.tick();
+        assert_eq!(authority::authority_of(&server.res, cart), Authority::Client(alice_id));
+        assert_eq!(owner(&server), Some(MetadataValue::Uint(alice_id.raw() as u32)));
+
+        // Only the owner moves it, and not too fast
+        server.received_entity_states(alice);
+        server.send_authority(bob, AuthorityMsg::Move { id, position: vec3(3.0, 0.5, 0.3), head_rotation: Vec2::ZERO });
+        server.send_authority(alice, AuthorityMsg::Move { id, position: vec3(3.25, 0.5, 0.0), head_rotation: vec2(0.5, 0.0) });
+        server.tick();
+        assert_eq!(position(&server), vec3(3.25, 0.5, 0.0));
+
+        server.send_authority(alice, AuthorityMsg::Move { id, position: vec3(13.0, 0.5, 0.0), head_rotation: Vec2::ZERO });
+        server.tick();
+        assert_eq!(position(&server), vec3(3.25, 0.5, 0.0));
+        let corrected = server.received_entity_states(alice).into_iter().flat_map(|state| state.changes).any(|(nid, msg)| {
+            nid == id && matches!(msg, EntityStateMsg::EntityTeleported { position, .. } if position == vec3(3.25, 0.5, 0.0))
+        });
+        assert!(corrected);
+
+        server.disconnect(alice);
+        assert_eq!(authority::authority_of(&server.res, cart), Authority::Claimable);
+        assert_eq!(owner(&server), Some(MetadataValue::Uint(0)));
+    }
+}
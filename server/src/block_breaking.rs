@@ -0,0 +1,118 @@
+use glam::IVec3;
+use hecs::Entity;
+use shared::blocks;
+
+use crate::{
+    components::Position,
+    dimensions,
+    items,
+    resources::Resources,
+    world::BlockId,
+};
+
+// Breaking blocks by holding the button on them, for clients with Features::BLOCK_BREAKING.
+// The client says when it starts on a block and when it's done, and the block is broken
+// (and dropped as an item, see `items::break_block`) if it's within reach and the player
+// spent about shared::blocks::break_ticks() on it. The messages come on the authority
+// stream, see `authority::process_authority_msgs`.
+
+// From the player's feet to the center of the block. The client measures from the eyes.
+pub const REACH: f32 = 8.0;
+// Ticks a finish may come early by, as the two messages needn't arrive as far apart as they
+// were sent
+const TIMING_SLACK: u32 = 4;
+
+// Player component, while breaking a block
+struct Breaking {
+    pos: IVec3,
+    block: BlockId,
+    started_tick: u32,
+}
+
+pub fn start(res: &mut Resources, player: Entity, pos: IVec3) {
+    let block = dimensions::blocks(res, dimensions::of(res, player)).block_at(pos);
+    let in_reach = res.main_world.get::<&Position>(player)
+        .map_or(false, |position| position.0.distance(pos.as_vec3() + 0.5) <= REACH);
+    if !in_reach || blocks::break_ticks(block).is_none() {
+        cancel(res, player);
+        return;
+    }
+    let _ = res.main_world.insert_one(player, Breaking { pos, block, started_tick: res.current_tick });
+}
+
+pub fn cancel(res: &mut Resources, player: Entity) {
+    let _ = res.main_world.remove_one::<Breaking>(player);
+}
+
+// Returns true if the block was broken
+pub fn finish(res: &mut Resources, player: Entity, pos: IVec3) -> bool {
+    let Ok(breaking) = res.main_world.remove_one::<Breaking>(player) else {
+        return false;
+    };
+    // Changed meanwhile, by someone else or a block update, or the player changed dimension
+    let dimension = dimensions::of(res, player);
+    if breaking.pos != pos || dimensions::blocks(res, dimension).block_at(pos) != breaking.block {
+        return false;
+    }
+    let Some(break_ticks) = blocks::break_ticks(breaking.block) else {
+        return false;
+    };
+    let elapsed = res.current_tick.wrapping_sub(breaking.started_tick);
+    if elapsed + TIMING_SLACK < break_ticks {
+        return false;
+    }
+    items::break_block(res, dimension, pos)
+}
+
+mod tests {
+    #[test]
+    fn test_block_breaking() {
+        use glam::ivec3;
+        use shared::{blocks, protocol::c2s::{AuthorityMsg, BlockAction}, worldgen::STONE};
+        use crate::testing::TestServer;
+
+        let mut server = TestServer::new();
+        let player = server.connect("miner");
+        let (near, other, far) = (ivec3(2, 0, 0), ivec3(2, 1, 0), ivec3(30, 0, 0));
+        for pos in [near, other, far] {
+            server.res.blocks.set_block(pos, STONE);
+        }
+        let break_ticks = blocks::break_ticks(STONE).unwrap();
+
+        // Too soon
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::StartBreaking { pos: near }));
+        server.tick();
+        server.run_ticks(break_ticks / 2);
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::FinishBreaking { pos: near }));
+        server.tick();
+        assert!(server.res.blocks.is_solid(near));
+
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::StartBreaking { pos: near }));
+        server.tick();
+        server.run_ticks(break_ticks);
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::FinishBreaking { pos: near }));
+        server.tick();
+        assert!(!server.res.blocks.is_solid(near));
+
+        // Cancelled, or finishing another block than the one started
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::StartBreaking { pos: other }));
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::CancelBreaking));
+        server.tick();
+        server.run_ticks(break_ticks);
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::FinishBreaking { pos: other }));
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::StartBreaking { pos: other }));
+        server.tick();
+        server.run_ticks(break_ticks);
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::FinishBreaking { pos: far }));
+        server.tick();
+        assert!(server.res.blocks.is_solid(other));
+
+        // Out of reach
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::StartBreaking { pos: far }));
+        server.tick();
+        server.run_ticks(break_ticks);
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::FinishBreaking { pos: far }));
+        server.tick();
+        assert!(server.res.blocks.is_solid(far));
+    }
+}
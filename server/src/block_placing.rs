@@ -0,0 +1,103 @@
+use glam::IVec3;
+use hecs::Entity;
+use shared::blocks;
+
+use crate::{
+    block_breaking::REACH,
+    components::{Inventory, Position},
+    dimensions,
+    game_builder,
+    resources::Resources,
+    world::{BlockId, AIR},
+};
+
+// Placing blocks, for clients with Features::BLOCK_PLACING. The block goes in if the player
+// has one in their `Inventory`, the spot is within reach and empty, and no on_block_place
+// handler (such as a script's, see `scripting::plugin`) cancels it. The message comes on the
+// authority stream, see `authority::process_authority_msgs`.
+
+// Returns true if the block was placed
+pub fn place(res: &mut Resources, player: Entity, pos: IVec3, block: BlockId) -> bool {
+    // The id is straight from the client. Nothing the server doesn't know, and nothing that
+    // couldn't be broken again, such as air or water.
+    if !blocks::is_known(block) || blocks::break_ticks(block).is_none() {
+        return false;
+    }
+    let has_block = res.main_world.get::<&Inventory>(player)
+        .map_or(false, |inventory| inventory.count(block) > 0);
+    if !has_block {
+        return false;
+    }
+    let dimension = dimensions::of(res, player);
+    let in_reach = res.main_world.get::<&Position>(player)
+        .map_or(false, |position| position.0.distance(pos.as_vec3() + 0.5) <= REACH);
+    if !in_reach || dimensions::blocks(res, dimension).block_at(pos) != AIR {
+        return false;
+    }
+    if game_builder::dispatch_block_place(res, player, pos, block) {
+        return false;
+    }
+    if !dimensions::blocks_mut(res, dimension).set_block(pos, block) {
+        return false;
+    }
+    if let Ok(inventory) = res.main_world.query_one_mut::<&mut Inventory>(player) {
+        inventory.take(block);
+    }
+    true
+}
+
+mod tests {
+    #[test]
+    fn test_block_placing() {
+        use glam::ivec3;
+        use shared::{protocol::c2s::{AuthorityMsg, BlockAction}, worldgen::{AIR, LOG, STONE}};
+        use crate::{components::Inventory, scripting::ScriptHost, testing::TestServer};
+
+        let mut server = TestServer::new();
+        let player = server.connect("builder");
+        let entity = server.client(player).entity;
+
+        // Nothing to place yet
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::Place { pos: ivec3(2, 0, 0), block: STONE }));
+        server.tick();
+        assert_eq!(server.res.blocks.block_at(ivec3(2, 0, 0)), AIR);
+
+        let inventory = Inventory([(STONE, 3), (LOG, 1), (0x3FF, 1)].into_iter().collect());
+        server.res.main_world.insert_one(entity, inventory).unwrap();
+
+        // A script that keeps x < 0 free of blocks
+        let dir = std::env::temp_dir().join(format!("block-placing-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("capabilities.cfg"), "guard = blocks\n").unwrap();
+        std::fs::write(dir.join("guard.lua"), "function on_block_place(player, x, y, z, block) return x < 0 end\n").unwrap();
+        let mut host = ScriptHost::new(dir.clone());
+        host.reload().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        server.res.extra.insert(host);
+
+        let (pos, guarded, far) = (ivec3(2, 0, 0), ivec3(-2, 0, 0), ivec3(30, 0, 0));
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::Place { pos, block: STONE }));
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::Place { pos: guarded, block: STONE }));
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::Place { pos: far, block: STONE }));
+        server.tick();
+        assert_eq!(server.res.blocks.block_at(pos), STONE);
+        assert_eq!(server.res.blocks.block_at(guarded), AIR);
+        assert_eq!(server.res.blocks.block_at(far), AIR);
+
+        // Only into empty space, and only blocks that can be broken again
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::Place { pos, block: LOG }));
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::Place { pos: pos + ivec3(0, 1, 0), block: AIR }));
+        server.tick();
+        assert_eq!(server.res.blocks.block_at(pos), STONE);
+        assert_eq!(server.res.blocks.block_at(pos + ivec3(0, 1, 0)), AIR);
+
+        // Nor ids the server doesn't know, whatever the inventory says
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::Place { pos: ivec3(3, 0, 0), block: 0x3FF }));
+        server.tick();
+        assert_eq!(server.res.blocks.block_at(ivec3(3, 0, 0)), AIR);
+
+        // Each placed block is taken from the inventory
+        let inventory = server.res.main_world.get::<&Inventory>(entity).unwrap();
+        assert_eq!((inventory.count(STONE), inventory.count(LOG)), (2, 1));
+    }
+}
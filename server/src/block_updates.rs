@@ -0,0 +1,145 @@
+use std::collections::{HashSet, VecDeque};
+
+use glam::{IVec3, Vec3};
+use shared::{dimension::DimensionId, worldgen::TORCH};
+
+use crate::{
+    config::ServerConfig,
+    game_builder::{self, GameBuilder, Stage},
+    items,
+    resources::Resources,
+    world::AIR,
+};
+
+// Blocks that depend on their neighbors, like a torch needing something to stand on. When a
+// block changes, those of its six neighbors that have a handler registered with
+// `GameBuilder::on_neighbor_update()` are queued, and the handlers run during the next tick's
+// Update stage, at most `block_updates_per_tick` of them. Whatever the handlers change goes
+// out to clients and into the save like any other change, and queues its own neighbors in
+// turn, so chain reactions spread one block per tick.
+pub struct BlockUpdates {
+    // (block to update, the neighbor that changed)
+    queue: VecDeque<(IVec3, IVec3)>,
+    queued: HashSet<IVec3>,
+    max_updates: usize,
+}
+
+impl BlockUpdates {
+    const MAX_QUEUED: usize = 1 << 16;
+
+    // Returns false if the queue is full
+    fn push(&mut self, pos: IVec3, neighbor: IVec3) -> bool {
+        if self.queued.contains(&pos) {
+            return true;
+        }
+        if self.queue.len() >= Self::MAX_QUEUED {
+            return false;
+        }
+        self.queued.insert(pos);
+        self.queue.push_back((pos, neighbor));
+        true
+    }
+}
+
+const NEIGHBORS: [IVec3; 6] = [IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z];
+
+pub fn plugin(builder: &mut GameBuilder) {
+    let max_updates = builder
+        .resource::<ServerConfig>()
+        .map_or(ServerConfig::default().block_updates_per_tick, |config| config.block_updates_per_tick);
+
+    builder
+        .insert_resource(BlockUpdates { queue: VecDeque::new(), queued: HashSet::new(), max_updates })
+        .add_system(Stage::Update, update_blocks)
+        // Before the changes are cleared in server::plugin
+        .add_system(Stage::PostTick, queue_block_updates)
+        .on_neighbor_update(TORCH, pop_unsupported_torch);
+}
+
+// See `reload`. Whatever is queued stays queued.
+pub fn reconfigure(res: &mut Resources, config: &ServerConfig) {
+    if let Some(updates) = res.extra.get_mut::<BlockUpdates>() {
+        updates.max_updates = config.block_updates_per_tick;
+    }
+}
+
+fn update_blocks(res: &mut Resources) -> anyhow::Result<()> {
+    let Some(updates) = res.extra.get_mut::<BlockUpdates>() else {
+        return Ok(());
+    };
+    let count = updates.queue.len().min(updates.max_updates);
+    let batch = updates.queue.drain(..count).collect::<Vec<_>>();
+    for (pos, _) in &batch {
+        updates.queued.remove(pos);
+    }
+
+    for (pos, neighbor) in batch {
+        // May have changed since it was queued, by an earlier handler among others
+        let block = res.blocks.block_at(pos);
+        if res.handlers.has_neighbor_update_handler(block) {
+            game_builder::dispatch_neighbor_update(res, pos, block, neighbor);
+        }
+    }
+    Ok(())
+}
+
+fn queue_block_updates(res: &mut Resources) -> anyhow::Result<()> {
+    let Some(updates) = res.extra.get_mut::<BlockUpdates>() else {
+        return Ok(());
+    };
+
+    let mut dropped = 0;
+    for &changed in res.blocks.changed_blocks() {
+        for pos in NEIGHBORS.map(|dir| changed + dir) {
+            if res.handlers.has_neighbor_update_handler(res.blocks.block_at(pos)) && !updates.push(pos, changed) {
+                dropped += 1;
+            }
+        }
+    }
+    if dropped > 0 {
+        eprintln!("Block update queue is full, dropped {dropped} updates");
+    }
+    Ok(())
+}
+
+// Torches stand on the block below them, and drop as an item once it's gone
+fn pop_unsupported_torch(res: &mut Resources, pos: IVec3, _block: u16, neighbor: IVec3) {
+    let below = pos - IVec3::Y;
+    if neighbor != below || res.blocks.is_solid(below) || below.y < 0 {
+        return;
+    }
+    res.blocks.set_block(pos, AIR);
+    items::drop_item(res, DimensionId::OVERWORLD, TORCH, pos.as_vec3() + 0.5, Vec3::Y * 2.0);
+}
+
+mod tests {
+    #[test]
+    fn test_block_updates() {
+        use glam::ivec3;
+        use shared::worldgen::{AIR, STONE, TORCH};
+        use crate::{components::DroppedItem, networking::client_connection::entity_state::EntityStateMsg, testing::TestServer};
+
+        let mut server = TestServer::new();
+        let player = server.connect("builder");
+        for x in 0..3 {
+            server.res.blocks.set_block(ivec3(x, 10, 0), STONE);
+            server.res.blocks.set_block(ivec3(x, 11, 0), TORCH);
+        }
+        server.run_ticks(2);
+        server.received_entity_states(player);
+
+        // Only the torch on top loses its support; a neighbor to the side changing doesn't matter
+        server.res.blocks.set_block(ivec3(1, 10, 0), AIR);
+        server.res.blocks.set_block(ivec3(0, 12, 0), STONE);
+        server.run_ticks(2);
+        assert_eq!(server.res.blocks.block_at(ivec3(1, 11, 0)), AIR);
+        assert_eq!(server.res.blocks.block_at(ivec3(0, 11, 0)), TORCH);
+        assert_eq!(server.res.blocks.block_at(ivec3(2, 11, 0)), TORCH);
+        assert_eq!(server.res.main_world.query_mut::<&DroppedItem>().into_iter().filter(|(_, item)| item.block == TORCH).count(), 1);
+
+        let sent = server.received_entity_states(player).into_iter().flat_map(|state| state.changes).any(|(_, msg)| {
+            matches!(msg, EntityStateMsg::BlocksChanged { changes } if changes.contains(&(ivec3(1, 11, 0), AIR)))
+        });
+        assert!(sent);
+    }
+}
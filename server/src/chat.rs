@@ -0,0 +1,183 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use flexstr::SharedStr;
+use hecs::Entity;
+use shared::protocol::s2c::ChatFlags;
+
+use crate::{
+    components::{Ignoring, PlayerId, Username},
+    config::ServerConfig,
+    game_builder::{GameBuilder, Stage, self},
+    resources::Resources,
+};
+
+// Everything broadcast to all players: kept in memory for players joining later, and
+// appended to the chat log.
+pub struct ChatHistory {
+    recent: VecDeque<SharedStr>,
+    max_len: usize,
+    log: Option<File>,
+}
+
+impl ChatHistory {
+    fn push(&mut self, message: &SharedStr) {
+        if self.max_len > 0 {
+            if self.recent.len() == self.max_len {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(message.clone());
+        }
+
+        if let Some(log) = &mut self.log {
+            if let Err(e) = writeln!(log, "[{}] {message}", utc_timestamp()) {
+                eprintln!("Failed to write to the chat log, disabling it: {e}");
+                self.log = None;
+            }
+        }
+    }
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    let (log_path, max_len) = builder.resource::<ServerConfig>()
+        .map_or((None, 0), |config| (config.chat_log.clone(), config.chat_history));
+
+    let log = log_path.and_then(|path| {
+        match File::options().create(true).append(true).open(&path) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                eprintln!("Failed to open chat log {}: {e}", path.display());
+                None
+            }
+        }
+    });
+
+    builder
+        .insert_resource(ChatHistory { recent: VecDeque::with_capacity(max_len), max_len, log })
+        .add_system(Stage::NetIn, broadcast_chat_messages)
+        .on_player_join(announce_join)
+        .on_player_leave(announce_leave)
+        .on_shutdown(flush_log);
+}
+
+// See `reload`. The log can't be moved without a restart.
+pub fn reconfigure(res: &mut Resources, config: &ServerConfig) {
+    if let Some(history) = res.extra.get_mut::<ChatHistory>() {
+        history.max_len = config.chat_history;
+        while history.recent.len() > history.max_len {
+            history.recent.pop_front();
+        }
+    }
+}
+
+fn flush_log(res: &mut Resources) {
+    let Some(log) = res.extra.get_mut::<ChatHistory>().and_then(|history| history.log.as_mut()) else {
+        return;
+    };
+    if let Err(e) = log.sync_all() {
+        eprintln!("Failed to flush the chat log: {e}");
+    }
+}
+
+// Sends the message to everybody and records it in the chat history
+pub fn broadcast(res: &mut Resources, message: SharedStr) {
+    if let Some(history) = res.extra.get_mut::<ChatHistory>() {
+        history.push(&message);
+    }
+    res.net.broadcast_chat(message);
+}
+
+// Like `broadcast()`, but also shown as a notification
+pub fn broadcast_notice(res: &mut Resources, message: SharedStr) {
+    if let Some(history) = res.extra.get_mut::<ChatHistory>() {
+        history.push(&message);
+    }
+    res.net.broadcast_chat_with_flags(ChatFlags::NOTICE, message);
+}
+
+// Broadcast recent chat messages to everybody, unless a chat handler took care of them
+fn broadcast_chat_messages(res: &mut Resources) -> anyhow::Result<()> {
+    while let Some((nid, message)) = res.net.poll_chat() {
+        let Some(sender) = res.net.entity_of(nid) else {
+            continue; // Fine: might have just disconnected
+        };
+
+        if game_builder::dispatch_chat(res, sender, message.as_str()) {
+            continue;
+        }
+
+        let Ok(username) = res.main_world.get::<&Username>(sender).map(|name| name.0.clone()) else {
+            continue;
+        };
+        let message = match game_builder::filter_chat(res, sender, message.as_str()) {
+            Ok(message) => message,
+            Err(reason) => {
+                if let Ok(&player_id) = res.main_world.get::<&PlayerId>(sender).as_deref() {
+                    res.net.send_chat(player_id, reason.into());
+                }
+                continue;
+            }
+        };
+        broadcast_from(res, &username, username.clone() + ": " + message.as_str());
+    }
+    Ok(())
+}
+
+// Like `broadcast()`, but skips players ignoring the sender
+fn broadcast_from(res: &mut Resources, sender: &SharedStr, message: SharedStr) {
+    if let Some(history) = res.extra.get_mut::<ChatHistory>() {
+        history.push(&message);
+    }
+    for (_, (&player_id, Ignoring(ignored))) in res.main_world.query_mut::<(&PlayerId, &Ignoring)>() {
+        if !ignored.contains(sender) {
+            res.net.send_chat(player_id, message.clone());
+        }
+    }
+}
+
+fn announce_join(res: &mut Resources, player: Entity) {
+    // History first, so that the join message is the newest one
+    if let (Some(history), Ok(&player_id)) = (res.extra.get::<ChatHistory>(), res.main_world.get::<&PlayerId>(player).as_deref()) {
+        for message in &history.recent {
+            res.net.send_chat_with_flags(player_id, ChatFlags::HISTORY, message.clone());
+        }
+    }
+
+    if let Ok(username) = res.main_world.get::<&Username>(player).map(|name| name.0.clone()) {
+        broadcast_notice(res, format!("{username} joined").into());
+    }
+}
+
+fn announce_leave(res: &mut Resources, player: Entity) {
+    if let Ok(username) = res.main_world.get::<&Username>(player).map(|name| name.0.clone()) {
+        broadcast_notice(res, format!("{username} disconnected").into());
+    }
+}
+
+// "YYYY-MM-DD HH:MM:SS" in UTC
+fn utc_timestamp() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+    let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // Days since 1970-01-01 to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
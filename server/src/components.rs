@@ -3,9 +3,15 @@
 // Should preferably be imported from here for consistency and convenience,
 // although in practice there is no difference.
 
+use std::time::Instant;
+
+use bevy_utils::{HashMap, HashSet};
 use flexstr::SharedStr;
 use glam::{Vec3, Vec2};
 use hecs::{Entity, World};
+use shared::{dimension::DimensionId, protocol::s2c::{MetadataKey, MetadataValue}};
+
+use crate::world::BlockId;
 
 pub type YawPitch = Vec2;
 
@@ -43,6 +49,123 @@ pub struct HeadYawPitch {
 
 pub struct Username(pub SharedStr);
 
+// Usernames whose chat messages the player doesn't want to see
+#[derive(Default)]
+pub struct Ignoring(pub HashSet<SharedStr>);
+
+// Players on the ops list, who may use the commands registered with `GameBuilder::require_op()`
+pub struct Op;
+
+// Of entities outside of the overworld, see `dimensions`
+#[derive(Clone, Copy)]
+pub struct InDimension(pub DimensionId);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MobKind {
+    Zombie,
+    Pig,
+}
+
+impl MobKind {
+    pub const ALL: [MobKind; 2] = [MobKind::Zombie, MobKind::Pig];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MobKind::Zombie => "zombie",
+            MobKind::Pig => "pig",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    // Clients draw every entity as a unit cube for now
+    pub fn half_extents(self) -> Vec3 {
+        Vec3::splat(0.5)
+    }
+}
+
+pub struct Mob(pub MobKind);
+
+// Who simulates the entity. Entities without this are the server's, like `Server`. While a
+// client has authority, its moves are taken as they come (within limits, see `authority`),
+// and systems that move entities should leave the entity alone.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Authority {
+    Server,
+    // The server's until a nearby player claims it, e.g. an empty minecart
+    Claimable,
+    // Predicted by this player's client. Back to `Claimable` once released.
+    Client(NetworkId),
+}
+
+// An item lying on the ground, see `items`
+pub struct DroppedItem {
+    pub block: BlockId,
+    pub velocity: Vec3,
+    pub pickup_after: Instant, // so that it doesn't go straight back to whoever dropped it
+    pub despawn_at: Instant,
+}
+
+// Picked up items, block id -> count
+#[derive(Default)]
+pub struct Inventory(pub HashMap<BlockId, u32>);
+
+impl Inventory {
+    pub fn count(&self, block: BlockId) -> u32 {
+        self.0.get(&block).copied().unwrap_or(0)
+    }
+
+    // Returns false if there was none to take
+    pub fn take(&mut self, block: BlockId) -> bool {
+        match self.0.get_mut(&block) {
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                self.0.remove(&block);
+            }
+            None => return false,
+        }
+        true
+    }
+}
+
+// Entity metadata that is synced to every client tracking the entity. Sent in full
+// when the entity gets added to a tracker, and after that only the changed values.
+// Change flags are cleared at the end of each tick.
+#[derive(Default)]
+pub struct Metadata {
+    entries: Vec<(MetadataKey, MetadataValue)>,
+    changed: u32, // bit per MetadataKey
+}
+
+impl Metadata {
+    pub fn set(&mut self, key: MetadataKey, value: MetadataValue) {
+        match self.entries.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, old)) if *old == value => return,
+            Some((_, old)) => *old = value,
+            None => self.entries.push((key, value)),
+        }
+        self.changed |= 1 << key.raw();
+    }
+
+    pub fn get(&self, key: MetadataKey) -> Option<&MetadataValue> {
+        self.entries.iter().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    pub fn entries(&self) -> &[(MetadataKey, MetadataValue)] {
+        &self.entries
+    }
+
+    pub fn changed_entries(&self) -> impl Iterator<Item = &(MetadataKey, MetadataValue)> {
+        self.entries.iter().filter(|(k, _)| self.changed & (1 << k.raw()) != 0)
+    }
+
+    pub fn clear_changed(&mut self) {
+        self.changed = 0;
+    }
+}
+
 // A server-internal player index. Kept as close to zero as possible
 // so that data structures don't need to allocate much unnecessary space.
 #[derive(Clone, Copy)]
@@ -65,15 +188,57 @@ pub struct PlayerBundle {
     pub nid: NetworkId,
     pub player_id: PlayerId,
     pub username: SharedStr,
+    pub skin: u8,
     pub position: Vec3,
     pub head_rotation: YawPitch,
 }
 
+pub fn spawn_mob(ecs: &mut World, nid: NetworkId, kind: MobKind, position: Vec3) -> Entity {
+    ecs.spawn((
+        nid,
+        Mob(kind),
+        Metadata::default(),
+        Position(position),
+        OldPosition(position),
+        Facing(YawPitch::ZERO.as_yaw_pitch_to_dir()),
+        HeadYawPitch {
+            value: YawPitch::ZERO,
+            delta: YawPitch::ZERO,
+        },
+    ))
+}
+
+pub fn spawn_item(ecs: &mut World, nid: NetworkId, item: DroppedItem, position: Vec3) -> Entity {
+    let mut metadata = Metadata::default();
+    metadata.set(MetadataKey::Item, MetadataValue::Uint(item.block as u32));
+
+    ecs.spawn((
+        nid,
+        item,
+        metadata,
+        Position(position),
+        OldPosition(position),
+        Facing(YawPitch::ZERO.as_yaw_pitch_to_dir()),
+        HeadYawPitch {
+            value: YawPitch::ZERO,
+            delta: YawPitch::ZERO,
+        },
+    ))
+}
+
 pub fn spawn_player(ecs: &mut World, bundle: PlayerBundle) -> Entity {
+    let mut metadata = Metadata::default();
+    metadata.set(MetadataKey::Username, MetadataValue::Str(bundle.username.as_str().into()));
+    metadata.set(MetadataKey::Crouching, MetadataValue::Bool(false));
+    metadata.set(MetadataKey::Sprinting, MetadataValue::Bool(false));
+    metadata.set(MetadataKey::Skin, MetadataValue::Uint(bundle.skin as u32));
+
     ecs.spawn((
         bundle.nid,
         bundle.player_id,
         Username(bundle.username),
+        Ignoring::default(),
+        metadata,
         Position(bundle.position),
         OldPosition(bundle.position),
         Facing(bundle.head_rotation.as_yaw_pitch_to_dir()),
@@ -0,0 +1,148 @@
+use std::{net::SocketAddr, path::{Path, PathBuf}};
+
+// Where the server reads its settings from, in the working directory
+pub const CONFIG_PATH: &str = "server.cfg";
+
+// Server settings, read from `server.cfg` in the working directory if it exists. Most can be
+// changed while the server runs and applied with `reload`, see `reload::apply` for which.
+// One `key = value` per line, `#` starts a comment. Unknown keys are warned about and ignored.
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    pub bind_address: SocketAddr,
+    // Serve Prometheus-style metrics over HTTP at `http://<address>/metrics`. Disabled if unset.
+    pub metrics_address: Option<SocketAddr>,
+    // Broadcast chat is appended here. Disabled if set to nothing.
+    pub chat_log: Option<PathBuf>,
+    // How many recent messages are sent to players as they join
+    pub chat_history: usize,
+    // Case-insensitive regexes; matching parts of chat messages are replaced with asterisks.
+    // The key can be given multiple times.
+    pub chat_filters: Vec<String>,
+    // Never sent to clients. They get the seed of the dimension they are in, derived from
+    // this (see shared::dimension::seed), and generate its terrain from that like the
+    // server does.
+    pub world_seed: u64,
+    // Random blocks visited per chunk per tick, see `random_tick`. 0 disables random ticks.
+    pub random_tick_speed: u32,
+    // Most water blocks updated per tick; the rest wait for the next one
+    pub fluid_updates_per_tick: usize,
+    // Most blocks told about a changed neighbor per tick (see `block_updates`); the rest wait
+    // for the next one
+    pub block_updates_per_tick: usize,
+    // Seconds between asking the server to stop (Ctrl-C or `stop`) and it actually stopping,
+    // counted down in chat. Logins are refused meanwhile. 0 stops right away.
+    pub shutdown_countdown: u64,
+    // Seconds without input before a player is shown as AFK. 0 disables.
+    pub afk_after: u64,
+    // Seconds without input before a player is kicked. 0 disables.
+    pub afk_kick_after: u64,
+    // Operators, `"username" = "<key>"` lines with the key from `identities_file` they had
+    // when made one. Kept in memory only if set to nothing, as are the other lists.
+    pub ops_file: Option<PathBuf>,
+    // Banned players, `"username" = "reason"` lines
+    pub bans_file: Option<PathBuf>,
+    // Only let in operators and the players in `whitelist_file`
+    pub whitelist: bool,
+    // Whitelisted players, `"username" = true` lines
+    pub whitelist_file: Option<PathBuf>,
+    // The key each username first joined with, `"username" = "<key>"` lines. Joining with
+    // another one is refused, see shared::protocol::c2s::Hello.
+    pub identities_file: Option<PathBuf>,
+    // Players online at once, at most shared::protocol::MAX_ONLINE_PLAYERS
+    pub max_players: usize,
+    // Players who may wait in line for a place while the server is full. Further ones, or
+    // any if 0, are turned away.
+    pub join_queue_size: usize,
+    // The blocks of the world are loaded from and saved here, see `savefile`. Nothing is
+    // saved if set to nothing.
+    pub world_file: Option<PathBuf>,
+    // Seconds between saves of a changed world, besides the one on shutdown. 0 disables.
+    pub autosave_interval: u64,
+    // Offer clients LZ4 compression of batched messages, see shared::protocol::batching
+    pub compression: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "0.0.0.0:29477".parse().unwrap(),
+            metrics_address: None,
+            chat_log: Some(PathBuf::from("chat.log")),
+            chat_history: 50,
+            chat_filters: Vec::new(),
+            world_seed: 0,
+            random_tick_speed: 3,
+            fluid_updates_per_tick: 2048,
+            block_updates_per_tick: 4096,
+            shutdown_countdown: 10,
+            afk_after: 300,
+            afk_kick_after: 0,
+            ops_file: Some(PathBuf::from("ops.toml")),
+            bans_file: Some(PathBuf::from("bans.toml")),
+            whitelist: false,
+            whitelist_file: Some(PathBuf::from("whitelist.toml")),
+            identities_file: Some(PathBuf::from("identities.toml")),
+            max_players: shared::protocol::MAX_ONLINE_PLAYERS as usize,
+            join_queue_size: 0,
+            world_file: Some(PathBuf::from("world.dat")),
+            autosave_interval: 300,
+            compression: true,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut config = Self::default();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(config),
+            Err(e) => anyhow::bail!("Failed to read {}: {e}", path.display()),
+        };
+
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                anyhow::bail!("{}:{}: expected 'key = value'", path.display(), line_no + 1);
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "bind_address" => config.bind_address = parse(path, line_no, value)?,
+                "metrics_address" => config.metrics_address = Some(parse(path, line_no, value)?),
+                "chat_log" => config.chat_log = (!value.is_empty()).then(|| PathBuf::from(value)),
+                "chat_history" => config.chat_history = parse(path, line_no, value)?,
+                "chat_filter" => config.chat_filters.push(value.to_owned()),
+                "world_seed" => config.world_seed = parse(path, line_no, value)?,
+                "random_tick_speed" => config.random_tick_speed = parse(path, line_no, value)?,
+                "fluid_updates_per_tick" => config.fluid_updates_per_tick = parse(path, line_no, value)?,
+                "block_updates_per_tick" => config.block_updates_per_tick = parse(path, line_no, value)?,
+                "shutdown_countdown" => config.shutdown_countdown = parse(path, line_no, value)?,
+                "afk_after" => config.afk_after = parse(path, line_no, value)?,
+                "afk_kick_after" => config.afk_kick_after = parse(path, line_no, value)?,
+                "ops_file" => config.ops_file = (!value.is_empty()).then(|| PathBuf::from(value)),
+                "bans_file" => config.bans_file = (!value.is_empty()).then(|| PathBuf::from(value)),
+                "whitelist" => config.whitelist = parse(path, line_no, value)?,
+                "whitelist_file" => config.whitelist_file = (!value.is_empty()).then(|| PathBuf::from(value)),
+                "identities_file" => config.identities_file = (!value.is_empty()).then(|| PathBuf::from(value)),
+                "max_players" => config.max_players = parse(path, line_no, value)?,
+                "join_queue_size" => config.join_queue_size = parse(path, line_no, value)?,
+                "world_file" => config.world_file = (!value.is_empty()).then(|| PathBuf::from(value)),
+                "autosave_interval" => config.autosave_interval = parse(path, line_no, value)?,
+                "compression" => config.compression = parse(path, line_no, value)?,
+                _ => eprintln!("{}:{}: unknown setting '{key}'", path.display(), line_no + 1),
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn parse<T: std::str::FromStr>(path: &Path, line_no: usize, value: &str) -> anyhow::Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    value.parse().map_err(|e| anyhow::anyhow!("{}:{}: invalid value '{value}': {e}", path.display(), line_no + 1))
+}
@@ -0,0 +1,50 @@
+use crossbeam_channel::Receiver;
+
+use crate::{
+    game_builder::{GameBuilder, Stage, self},
+    resources::Resources,
+};
+
+// Commands typed into the server's terminal. Lines are read on a separate thread
+// and handed to the console handlers registered in the `GameBuilder`.
+pub struct Console {
+    lines: Receiver<String>,
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    let (send, recv) = crossbeam_channel::unbounded();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if send.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    builder
+        .insert_resource(Console { lines: recv })
+        .add_system(Stage::NetIn, run_console_commands);
+}
+
+fn run_console_commands(res: &mut Resources) -> anyhow::Result<()> {
+    let Some(console) = res.extra.get::<Console>() else {
+        return Ok(());
+    };
+    let lines = console.lines.try_iter().collect::<Vec<_>>();
+
+    for line in &lines {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        if !game_builder::dispatch_console_command(res, command, args.trim()) {
+            println!("Unknown command '{command}'");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use glam::{IVec3, Vec3};
+use hecs::Entity;
+use shared::{dimension::{self, DimensionId}, worldgen};
+
+use crate::{
+    components::{InDimension, PlayerId, Position},
+    config::ServerConfig,
+    game_builder::GameBuilder,
+    net,
+    resources::Resources,
+    world::BlockWorld,
+};
+
+// Worlds besides the overworld, see shared::dimension. The overworld's blocks are
+// `Resources::blocks`, the others' are kept in `Dimensions`. Entities with an `InDimension`
+// are in that one, the rest in the overworld, and players are only sent the blocks and
+// entities of their own.
+//
+// The other dimensions only have what players do in them for now: breaking blocks and
+// dropped items work there, but fluids, block updates, random ticks, spawning and saving
+// only cover the overworld. Their terrain is generated like the overworld's, from a seed of
+// their own, by both the clients and the server (see BlockWorld).
+
+// By DimensionId
+pub const NAMES: [&str; 2] = ["overworld", "nether"];
+
+pub struct Dimensions {
+    // Indexed by DimensionId - 1
+    others: Vec<BlockWorld>,
+}
+
+impl Dimensions {
+    pub fn new() -> Self {
+        Self { others: (1..NAMES.len()).map(|_| BlockWorld::new()).collect() }
+    }
+
+    // `overworld` is `Resources::blocks`. Panics on a dimension that doesn't exist.
+    pub fn blocks<'a>(&'a self, overworld: &'a BlockWorld, dimension: DimensionId) -> &'a BlockWorld {
+        match dimension {
+            DimensionId::OVERWORLD => overworld,
+            DimensionId(id) => &self.others[id as usize - 1],
+        }
+    }
+
+    pub fn others(&self) -> impl Iterator<Item = (DimensionId, &BlockWorld)> {
+        self.others.iter().enumerate().map(|(i, blocks)| (DimensionId(i as u8 + 1), blocks))
+    }
+
+    pub fn others_mut(&mut self) -> impl Iterator<Item = (DimensionId, &mut BlockWorld)> {
+        self.others.iter_mut().enumerate().map(|(i, blocks)| (DimensionId(i as u8 + 1), blocks))
+    }
+}
+
+impl Default for Dimensions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn by_name(name: &str) -> Option<DimensionId> {
+    NAMES.iter().position(|&n| n == name).map(|id| DimensionId(id as u8))
+}
+
+pub fn name(dimension: DimensionId) -> &'static str {
+    NAMES.get(dimension.0 as usize).copied().unwrap_or("unknown")
+}
+
+// The dimension the entity is in
+pub fn of(res: &Resources, entity: Entity) -> DimensionId {
+    res.main_world.get::<&InDimension>(entity).map_or(DimensionId::OVERWORLD, |dimension| dimension.0)
+}
+
+pub fn blocks(res: &Resources, dimension: DimensionId) -> &BlockWorld {
+    res.dimensions.blocks(&res.blocks, dimension)
+}
+
+pub fn blocks_mut(res: &mut Resources, dimension: DimensionId) -> &mut BlockWorld {
+    match dimension {
+        DimensionId::OVERWORLD => &mut res.blocks,
+        DimensionId(id) => &mut res.dimensions.others[id as usize - 1],
+    }
+}
+
+// What the client generates from this dimension's seed, see shared::dimension::seed()
+pub fn seed(res: &Resources, dimension: DimensionId) -> u64 {
+    let world_seed = res.extra.get::<ServerConfig>().map_or(0, |config| config.world_seed);
+    dimension::seed(world_seed, dimension)
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    builder
+        .require_op("/dimension")
+        .on_chat(dimension_from_chat);
+}
+
+// `/dimension [name]`: moves the player to the same x and z in another dimension, on top of
+// its terrain, or tells which one they're in
+fn dimension_from_chat(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    let Some(args) = message.strip_prefix("/dimension").filter(|rest| rest.is_empty() || rest.starts_with(' ')) else {
+        return false;
+    };
+    let Ok((&player_id, &Position(pos))) = res.main_world.query_one_mut::<(&PlayerId, &Position)>(sender) else {
+        return true;
+    };
+
+    let current = of(res, sender);
+    let reply = match args.trim() {
+        "" => format!("You are in the {}. Dimensions: {}", name(current), NAMES.join(", ")),
+        target => match by_name(target) {
+            None => format!("No dimension named '{target}'. Dimensions: {}", NAMES.join(", ")),
+            Some(dimension) if dimension == current => format!("You are already in the {target}"),
+            Some(dimension) => {
+                let block = pos.floor().as_ivec3();
+                let height = worldgen::terrain_height(seed(res, dimension), block.x, block.z);
+                let target_pos = IVec3::new(block.x, height, block.z).as_vec3() + Vec3::new(0.5, 0.0, 0.5);
+                match net::teleport_to_dimension(res, sender, dimension, target_pos) {
+                    Ok(()) => format!("Moved to the {target}"),
+                    Err(e) => e.to_string(),
+                }
+            }
+        },
+    };
+    res.net.send_chat(player_id, reply.into());
+    true
+}
+
+mod tests {
+    #[test]
+    fn test_dimensions() {
+        use glam::{ivec3, IVec3, Vec2, Vec3};
+        use shared::{blocks, protocol::{c2s::{AuthorityMsg, BlockAction}, s2c::TeleportFlags}, worldgen::STONE};
+        use crate::{components::Inventory, dimensions, game_builder, net, networking::client_connection::entity_state::EntityStateMsg, scripting::ScriptHost, testing::TestServer};
+
+        let mut server = TestServer::new();
+        let alice = server.connect("alice");
+        let bob = server.connect("bob");
+        assert!(game_builder::dispatch_console_command(&mut server.res, "op", "alice"));
+        server.tick();
+        assert!(server.is_tracking(alice, bob) && server.is_tracking(bob, alice));
+
+        let nether = dimensions::by_name("nether").unwrap();
+        server.send_chat(alice, "/dimension nether");
+        server.tick();
+        let entity = server.client(alice).entity;
+        assert_eq!(dimensions::of(&server.res, entity), nether);
+        assert!(!server.is_tracking(alice, bob) && !server.is_tracking(bob, alice));
+        let told = server.received_entity_states(alice).iter()
+            .flat_map(|state| &state.changes)
+            .any(|(_, msg)| matches!(msg, EntityStateMsg::Teleport { dimension: Some((dimension, _)), .. } if *dimension == nether));
+        assert!(told);
+
+        // The same position is a different block in each dimension
+        let pos = ivec3(2, 0, 0);
+        server.res.blocks.set_block(pos, STONE);
+        dimensions::blocks_mut(&mut server.res, nether).set_block(pos, STONE);
+        net::teleport(&mut server.res, entity, Vec3::ZERO, Vec2::ZERO, TeleportFlags::ABSOLUTE).unwrap();
+        server.send_authority(alice, AuthorityMsg::Block(BlockAction::StartBreaking { pos }));
+        server.tick();
+        server.run_ticks(blocks::break_ticks(STONE).unwrap());
+        server.send_authority(alice, AuthorityMsg::Block(BlockAction::FinishBreaking { pos }));
+        server.tick();
+        assert!(!dimensions::blocks(&server.res, nether).is_solid(pos));
+        assert!(server.res.blocks.is_solid(pos));
+
+        // Scripts change blocks in the dimension of the player the hook is about
+        let dir = std::env::temp_dir().join(format!("dimensions-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("capabilities.cfg"), "stack = blocks\n").unwrap();
+        std::fs::write(dir.join("stack.lua"), "function on_block_place(player, x, y, z, block) set_block(x, y + 1, z, block) end\n").unwrap();
+        let mut host = ScriptHost::new(dir.clone());
+        host.reload().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        server.res.extra.insert(host);
+        server.res.main_world.insert_one(entity, Inventory([(STONE, 1)].into_iter().collect())).unwrap();
+        server.send_authority(alice, AuthorityMsg::Block(BlockAction::Place { pos, block: STONE }));
+        server.tick();
+        assert!(dimensions::blocks(&server.res, nether).is_solid(pos + IVec3::Y));
+        assert!(!server.res.blocks.is_solid(pos + IVec3::Y));
+
+        server.send_chat(alice, "/dimension overworld");
+        server.tick();
+        assert!(server.res.main_world.get::<&crate::components::InDimension>(entity).is_err());
+        server.tick();
+        assert!(server.is_tracking(alice, bob) && server.is_tracking(bob, alice));
+    }
+
+    // The server generates the same terrain as clients, so that what they see can be broken
+    // and what falls on it stays there
+}
@@ -0,0 +1,151 @@
+use std::collections::{HashSet, VecDeque};
+
+use glam::IVec3;
+use shared::{fluid, worldgen::STONE};
+
+use crate::{
+    config::ServerConfig,
+    game_builder::{GameBuilder, Stage},
+    resources::Resources,
+    world::{BlockId, BlockWorld, AIR},
+};
+
+// Water flow (see shared::fluid for the rules). Blocks next to anything that changed are
+// queued and re-evaluated a step at a time, at most `fluid_updates_per_tick` of them per
+// step, so that a flood can't take the tick time down with it; the rest just flows later.
+pub struct Fluids {
+    queue: VecDeque<IVec3>,
+    queued: HashSet<IVec3>,
+    max_updates: usize,
+}
+
+impl Fluids {
+    const MAX_QUEUED: usize = 1 << 16;
+    // Water moves one block per this many ticks
+    const FLOW_INTERVAL_TICKS: u32 = 5;
+
+    // Returns false if the queue is full
+    fn push(&mut self, pos: IVec3) -> bool {
+        if self.queued.contains(&pos) {
+            return true;
+        }
+        if self.queue.len() >= Self::MAX_QUEUED {
+            return false;
+        }
+        self.queued.insert(pos);
+        self.queue.push_back(pos);
+        true
+    }
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    let max_updates = builder
+        .resource::<ServerConfig>()
+        .map_or(ServerConfig::default().fluid_updates_per_tick, |config| config.fluid_updates_per_tick);
+
+    builder
+        .insert_resource(Fluids { queue: VecDeque::new(), queued: HashSet::new(), max_updates })
+        .add_system(Stage::Update, update_fluids)
+        // Before the changes are cleared in server::plugin
+        .add_system(Stage::PostTick, queue_fluid_updates);
+}
+
+// See `reload`. Whatever is queued stays queued.
+pub fn reconfigure(res: &mut Resources, config: &ServerConfig) {
+    if let Some(fluids) = res.extra.get_mut::<Fluids>() {
+        fluids.max_updates = config.fluid_updates_per_tick;
+    }
+}
+
+// Below the world counts as solid, so that water comes to rest at the bottom
+fn block_at(blocks: &BlockWorld, pos: IVec3) -> BlockId {
+    if pos.y < 0 {
+        STONE
+    } else {
+        blocks.block_at(pos)
+    }
+}
+
+fn update_fluids(res: &mut Resources) -> anyhow::Result<()> {
+    if res.simulated_ticks % Fluids::FLOW_INTERVAL_TICKS != 0 {
+        return Ok(());
+    }
+    let Some(fluids) = res.extra.get_mut::<Fluids>() else {
+        return Ok(());
+    };
+
+    // All decided before any of it is applied, so that water moves exactly one block per
+    // step no matter in which order the queue happens to be
+    let count = fluids.queue.len().min(fluids.max_updates);
+    let mut changes = Vec::new();
+    for pos in fluids.queue.drain(..count) {
+        fluids.queued.remove(&pos);
+        let block = fluid::next_block(|pos| block_at(&res.blocks, pos), pos);
+        if block != res.blocks.block_at(pos) {
+            changes.push((pos, block));
+        }
+    }
+
+    for (pos, block) in changes {
+        res.blocks.set_block(pos, block);
+    }
+    Ok(())
+}
+
+fn queue_fluid_updates(res: &mut Resources) -> anyhow::Result<()> {
+    let Some(fluids) = res.extra.get_mut::<Fluids>() else {
+        return Ok(());
+    };
+
+    let mut dropped = 0;
+    for &changed in res.blocks.changed_blocks() {
+        for pos in fluid::affected_by(changed) {
+            let block = res.blocks.block_at(pos);
+            if (block == AIR || fluid::is_flowing(block)) && pos.y >= 0 && !fluids.push(pos) {
+                dropped += 1;
+            }
+        }
+    }
+    if dropped > 0 {
+        eprintln!("Fluid update queue is full, dropped {dropped} updates");
+    }
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn test_water_flow() {
+        use glam::ivec3;
+        use shared::{fluid::{self, water_level}, worldgen::STONE};
+        use crate::{networking::client_connection::entity_state::EntityStateMsg, testing::TestServer};
+
+        let mut server = TestServer::new();
+        let player = server.connect("swimmer");
+        for z in -4..=4 {
+            for x in -4..=4 {
+                server.res.blocks.set_block(ivec3(x, 10, z), STONE);
+            }
+        }
+        server.res.blocks.set_block(ivec3(0, 11, 0), fluid::water(0));
+        server.run_ticks(150);
+
+        let level_at = |server: &TestServer, x, y, z| water_level(server.res.blocks.block_at(ivec3(x, y, z)));
+        assert_eq!(level_at(&server, 2, 11, 0), Some(2));
+        assert_eq!(level_at(&server, 5, 11, 0), Some(5));
+        assert_eq!(level_at(&server, 6, 11, 0), None);
+        // Off the edge of the platform and down to the bottom of the world
+        assert_eq!(level_at(&server, 5, 4, 0), Some(1));
+        assert_eq!(level_at(&server, 6, 0, 0), Some(2));
+        assert!(!server.res.blocks.is_solid(ivec3(1, 11, 0)));
+
+        let sent = server.received_entity_states(player).into_iter().flat_map(|state| state.changes).any(|(_, msg)| {
+            matches!(msg, EntityStateMsg::BlocksChanged { changes } if changes.contains(&(ivec3(1, 11, 0), fluid::water(1))))
+        });
+        assert!(sent);
+
+        server.res.blocks.set_block(ivec3(0, 11, 0), STONE);
+        server.run_ticks(400);
+        assert_eq!(level_at(&server, 1, 11, 0), None);
+        assert_eq!(level_at(&server, 6, 0, 0), None);
+    }
+}
@@ -1,91 +1,326 @@
-/*use bevy_ecs::{prelude::World, schedule::{Schedule, IntoSystemDescriptor, StageLabel, SystemStage, SystemSet}, system::Resource};
+use std::time::{Duration, Instant};
 
-use crate::CoreStage;
+use glam::IVec3;
+use hecs::Entity;
 
+use crate::{
+    components::{Op, PlayerId},
+    resources::{Resources, ResourceMap},
+    scheduler::Scheduler,
+    tick_control,
+};
 
+// Stages run in declaration order, once per tick
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    NetIn,    // Logins, chat and player input received from the network thread
+    Update,   // Gameplay
+    NetOut,   // Send this tick's results to the players
+    PostTick, // Bookkeeping that must happen after everything has been sent out
+}
+
+impl Stage {
+    pub const COUNT: usize = 4;
+    pub const ALL: [Stage; Stage::COUNT] = [Stage::NetIn, Stage::Update, Stage::NetOut, Stage::PostTick];
+}
+
+pub type System = fn(&mut Resources) -> anyhow::Result<()>;
+
+// Returns true if the message was handled and should not be passed on to later handlers
+// (nor broadcast to everybody).
+pub type ChatHandler = fn(&mut Resources, sender: Entity, message: &str) -> bool;
+pub type PlayerHandler = fn(&mut Resources, player: Entity);
+// Returns true if the placement should be cancelled
+pub type BlockPlaceHandler = fn(&mut Resources, player: Entity, pos: IVec3, block: u16) -> bool;
+// Called for blocks of the type it was registered for, see `random_tick`
+pub type RandomTickHandler = fn(&mut Resources, pos: IVec3, block: u16);
+// Called for blocks of the type it was registered for when `neighbor`, one of the six next
+// to it, has changed; see `block_updates`
+pub type NeighborUpdateHandler = fn(&mut Resources, pos: IVec3, block: u16, neighbor: IVec3);
+// Returns true if the command was recognized
+pub type ConsoleHandler = fn(&mut Resources, command: &str, args: &str) -> bool;
+// Runs on chat messages that no chat handler took, before they are broadcast
+pub type ChatFilter = fn(&mut Resources, sender: Entity, message: &str) -> ChatVerdict;
+// Runs once as the server stops, while the players are still connected
+pub type ShutdownHandler = fn(&mut Resources);
+
+pub enum ChatVerdict {
+    Allow,
+    // Broadcast this instead
+    Replace(String),
+    // Not broadcast; the reason is sent back to the sender
+    Block(String),
+}
+
+#[derive(Default)]
+pub struct Handlers {
+    chat: Vec<ChatHandler>,
+    player_join: Vec<PlayerHandler>,
+    player_leave: Vec<PlayerHandler>,
+    block_place: Vec<BlockPlaceHandler>,
+    console: Vec<ConsoleHandler>,
+    chat_filters: Vec<ChatFilter>,
+    random_tick: Vec<(u16, RandomTickHandler)>,
+    neighbor_update: Vec<(u16, NeighborUpdateHandler)>,
+    shutdown: Vec<ShutdownHandler>,
+    op_commands: Vec<&'static str>,
+}
+
+impl Handlers {
+    pub fn has_random_tick_handlers(&self) -> bool {
+        !self.random_tick.is_empty()
+    }
+
+    pub fn has_neighbor_update_handler(&self, block: u16) -> bool {
+        self.neighbor_update.iter().any(|&(handled_block, _)| handled_block == block)
+    }
+}
+
+// Handlers are plain function pointers, so they can be copied out one by one while
+// `res` is mutably borrowed.
+pub fn dispatch_chat(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    let command = message.split_whitespace().next().unwrap_or("");
+    if res.handlers.op_commands.contains(&command) && res.main_world.get::<&Op>(sender).is_err() {
+        if let Ok(&player_id) = res.main_world.get::<&PlayerId>(sender).as_deref() {
+            res.net.send_chat(player_id, format!("You don't have permission to use {command}").into());
+        }
+        return true;
+    }
+
+    let mut i = 0;
+    while let Some(&handler) = res.handlers.chat.get(i) {
+        if handler(res, sender, message) {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+// Called once the player entity has been spawned
+pub fn dispatch_player_join(res: &mut Resources, player: Entity) {
+    let mut i = 0;
+    while let Some(&handler) = res.handlers.player_join.get(i) {
+        handler(res, player);
+        i += 1;
+    }
+}
+
+// Called just before the player entity is despawned
+pub fn dispatch_player_leave(res: &mut Resources, player: Entity) {
+    let mut i = 0;
+    while let Some(&handler) = res.handlers.player_leave.get(i) {
+        handler(res, player);
+        i += 1;
+    }
+}
+
+// Called before a player places a block, see `block_placing::place`. Returns true if cancelled.
+pub fn dispatch_block_place(res: &mut Resources, player: Entity, pos: IVec3, block: u16) -> bool {
+    let mut i = 0;
+    while let Some(&handler) = res.handlers.block_place.get(i) {
+        if handler(res, player, pos, block) {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+pub fn dispatch_console_command(res: &mut Resources, command: &str, args: &str) -> bool {
+    let mut i = 0;
+    while let Some(&handler) = res.handlers.console.get(i) {
+        if handler(res, command, args) {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+pub fn dispatch_random_tick(res: &mut Resources, pos: IVec3, block: u16) {
+    let mut i = 0;
+    while let Some(&(handled_block, handler)) = res.handlers.random_tick.get(i) {
+        if handled_block == block {
+            handler(res, pos, block);
+        }
+        i += 1;
+    }
+}
+
+pub fn dispatch_neighbor_update(res: &mut Resources, pos: IVec3, block: u16, neighbor: IVec3) {
+    let mut i = 0;
+    while let Some(&(handled_block, handler)) = res.handlers.neighbor_update.get(i) {
+        if handled_block == block {
+            handler(res, pos, block, neighbor);
+        }
+        i += 1;
+    }
+}
+
+pub fn dispatch_shutdown(res: &mut Resources) {
+    let mut i = 0;
+    while let Some(&handler) = res.handlers.shutdown.get(i) {
+        handler(res);
+        i += 1;
+    }
+}
+
+// Returns the message to broadcast, or the reason it was blocked. Filters see the
+// message as replaced by earlier filters.
+pub fn filter_chat(res: &mut Resources, sender: Entity, message: &str) -> Result<String, String> {
+    let mut message = message.to_owned();
+    let mut i = 0;
+    while let Some(&filter) = res.handlers.chat_filters.get(i) {
+        match filter(res, sender, &message) {
+            ChatVerdict::Allow => {}
+            ChatVerdict::Replace(replacement) => message = replacement,
+            ChatVerdict::Block(reason) => return Err(reason),
+        }
+        i += 1;
+    }
+    Ok(message)
+}
+
+pub struct TickSchedule {
+    stages: [Vec<System>; Stage::COUNT],
+}
+
+impl TickSchedule {
+    // An error stops the rest of the tick from running
+    pub fn run(&self, res: &mut Resources) -> anyhow::Result<()> {
+        let simulate = tick_control::begin_tick(res);
+        for (i, stage) in self.stages.iter().enumerate() {
+            if i == Stage::Update as usize && !simulate {
+                res.time.stage_durations[i] = Duration::ZERO;
+                continue;
+            }
+            let start = Instant::now();
+            for system in stage {
+                system(res)?;
+            }
+            res.time.stage_durations[i] = start.elapsed();
+            if i == Stage::Update as usize {
+                res.simulated_ticks += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Features are added as plugins (plain functions taking the builder) that register
+// their own systems, handlers and resources, so that nothing needs to be added to
+// `server::tick()` by hand.
 pub struct GameBuilder {
-    pub world: World,
-    runtime_schedule: Schedule,
-    resize_listeners: SystemStage, // ran when window resizes
-    cleanup_systems: SystemStage,
-    runner: Box<dyn FnOnce(GameBuilder)>,
+    stages: [Vec<System>; Stage::COUNT],
+    handlers: Handlers,
+    resources: ResourceMap,
+    scheduler: Scheduler,
 }
 
 impl GameBuilder {
-    pub fn new_with_runner(runner: impl FnOnce(GameBuilder) + 'static) -> Self {
+    pub fn new() -> Self {
         Self {
-            world: World::new(),
-            runtime_schedule: Schedule::default(),
-            resize_listeners: SystemStage::single_threaded(),
-            cleanup_systems: SystemStage::single_threaded(),
-            runner: Box::new(runner),
+            stages: Default::default(),
+            handlers: Handlers::default(),
+            resources: ResourceMap::default(),
+            scheduler: Scheduler::default(),
         }
     }
 
-    pub fn run(mut self) {
-        let runner = std::mem::replace(&mut self.runner, Box::new(trap));
-        (runner)(self);
+    pub fn add_plugin(&mut self, plugin: fn(&mut GameBuilder)) -> &mut Self {
+        plugin(self);
+        self
+    }
+
+    // Systems within a stage run in the order they were added
+    pub fn add_system(&mut self, stage: Stage, system: System) -> &mut Self {
+        self.stages[stage as usize].push(system);
+        self
+    }
+
+    // Replaces any existing resource of the same type
+    pub fn insert_resource<T: 'static>(&mut self, resource: T) -> &mut Self {
+        self.resources.insert(resource);
+        self
     }
 
-    pub fn run_cleanup(&mut self) {
-        bevy_ecs::schedule::Stage::run(&mut self.cleanup_systems, &mut self.world);
+    // For plugins that need to look at e.g. the `ServerConfig` while registering
+    pub fn resource<T: 'static>(&self) -> Option<&T> {
+        self.resources.get::<T>()
     }
 
-    pub fn window_resized(&mut self) {
-        bevy_ecs::schedule::Stage::run(&mut self.resize_listeners, &mut self.world);
+    // For tasks that start with the server, at tick 0. Moved into `Resources::scheduler`.
+    pub fn scheduler(&mut self) -> &mut Scheduler {
+        &mut self.scheduler
     }
 
-    pub fn update(&mut self) {
-        bevy_ecs::schedule::Stage::run(&mut self.runtime_schedule, &mut self.world);
+    pub fn on_chat(&mut self, handler: ChatHandler) -> &mut Self {
+        self.handlers.chat.push(handler);
+        self
     }
 
-    pub fn insert_resource<T>(&mut self, resource: T) -> &mut Self
-    where
-        T: Resource,
-    {
-        self.world.insert_resource(resource);
+    pub fn on_player_join(&mut self, handler: PlayerHandler) -> &mut Self {
+        self.handlers.player_join.push(handler);
         self
     }
 
-    pub fn add_system<Params>(&mut self, system: impl IntoSystemDescriptor<Params>) -> &mut Self {
-        self.add_system_to_stage(CoreStage::GameTick, system)
+    pub fn on_player_leave(&mut self, handler: PlayerHandler) -> &mut Self {
+        self.handlers.player_leave.push(handler);
+        self
     }
 
-    pub fn add_stage<S: bevy_ecs::schedule::Stage>(&mut self, label: impl StageLabel, stage: S) -> &mut Self {
-        self.runtime_schedule.add_stage(label, stage);
+    pub fn on_block_place(&mut self, handler: BlockPlaceHandler) -> &mut Self {
+        self.handlers.block_place.push(handler);
         self
     }
 
-    pub fn add_system_to_stage<Params>(
-        &mut self,
-        stage_label: impl StageLabel,
-        system: impl IntoSystemDescriptor<Params>,
-    ) -> &mut Self {
-        self.runtime_schedule.add_system_to_stage(stage_label, system);
+    pub fn on_console_command(&mut self, handler: ConsoleHandler) -> &mut Self {
+        self.handlers.console.push(handler);
         self
     }
 
-    pub fn add_system_set_to_stage(
-        &mut self,
-        stage_label: impl StageLabel,
-        system_set: SystemSet,
-    ) -> &mut Self {
-        self.runtime_schedule
-            .add_system_set_to_stage(stage_label, system_set);
+    pub fn on_random_tick(&mut self, block: u16, handler: RandomTickHandler) -> &mut Self {
+        self.handlers.random_tick.push((block, handler));
         self
     }
 
-    pub fn add_cleanup_system<Params>(&mut self, system: impl IntoSystemDescriptor<Params>) -> &mut Self {
-        self.cleanup_systems.add_system(system);
+    pub fn on_neighbor_update(&mut self, block: u16, handler: NeighborUpdateHandler) -> &mut Self {
+        self.handlers.neighbor_update.push((block, handler));
         self
     }
 
-    pub fn add_resize_listener_system<Params>(&mut self, system: impl IntoSystemDescriptor<Params>) -> &mut Self {
-        self.resize_listeners.add_system(system);
+    pub fn on_shutdown(&mut self, handler: ShutdownHandler) -> &mut Self {
+        self.handlers.shutdown.push(handler);
         self
     }
+
+    // Only players with the `Op` component may use the chat command, e.g. "/tp". Others are
+    // told so, and the message goes no further.
+    pub fn require_op(&mut self, command: &'static str) -> &mut Self {
+        self.handlers.op_commands.push(command);
+        self
+    }
+
+    // Filters run in the order they were added
+    pub fn add_chat_filter(&mut self, filter: ChatFilter) -> &mut Self {
+        self.handlers.chat_filters.push(filter);
+        self
+    }
+
+    // Moves the registered handlers and resources into `res`
+    pub fn into_tick_schedule(self, res: &mut Resources) -> TickSchedule {
+        res.handlers = self.handlers;
+        res.scheduler = self.scheduler;
+        res.extra.extend(self.resources);
+        TickSchedule {
+            stages: self.stages,
+        }
+    }
 }
 
-fn trap(_: GameBuilder) {
-    panic!("App run() called twice");
-}*/
\ No newline at end of file
+impl Default for GameBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,238 @@
+use std::time::Duration;
+
+use glam::{IVec3, Vec3};
+use hecs::Entity;
+use shared::{dimension::DimensionId, TICK_DURATION};
+
+use crate::{
+    components::{self, DroppedItem, InDimension, Inventory, NetworkId, PlayerId, Position},
+    dimensions,
+    game_builder::{GameBuilder, Stage},
+    resources::Resources,
+    world::{BlockId, AIR},
+};
+
+// Dropped items are small cubes that fall, bounce and slide to a stop, get pulled towards
+// nearby players and end up in their `Inventory`. Ones nobody picks up eventually despawn.
+
+const HALF_EXTENT: f32 = 0.125;
+const GRAVITY: f32 = 20.0;
+const MAX_FALL_SPEED: f32 = 40.0;
+// Fraction of the vertical speed kept when hitting the ground, and the speed under which
+// it stops bouncing altogether
+const BOUNCINESS: f32 = 0.4;
+const MIN_BOUNCE_SPEED: f32 = 1.0;
+// Fraction of the horizontal speed kept per tick while on the ground
+const GROUND_FRICTION: f32 = 0.8;
+
+const MAGNET_RADIUS: f32 = 3.0;
+const MAGNET_SPEED: f32 = 8.0;
+const PICKUP_RADIUS: f32 = 1.0;
+const PICKUP_DELAY: Duration = Duration::from_millis(500);
+const LIFETIME: Duration = Duration::from_secs(300);
+
+pub fn plugin(builder: &mut GameBuilder) {
+    builder
+        .add_system(Stage::Update, simulate_items)
+        .on_player_join(give_inventory)
+        .on_chat(inventory_from_chat);
+}
+
+// Replaces the block with air and drops it as an item. Returns false if there was nothing
+// to break.
+pub fn break_block(res: &mut Resources, dimension: DimensionId, pos: IVec3) -> bool {
+    let blocks = dimensions::blocks_mut(res, dimension);
+    let block = blocks.block_at(pos);
+    if !blocks.is_solid(pos) || !blocks.set_block(pos, AIR) {
+        return false;
+    }
+    // Pops up a little, in a direction that varies from block to block
+    let hash = (pos.x.wrapping_mul(73_856_093) ^ pos.y.wrapping_mul(19_349_663) ^ pos.z.wrapping_mul(83_492_791)) as u32;
+    let angle = (hash % 360) as f32 * std::f32::consts::PI / 180.0;
+    let velocity = Vec3::new(angle.cos(), 0.0, angle.sin()) * 1.5 + Vec3::Y * 4.0;
+    drop_item(res, dimension, block, pos.as_vec3() + 0.5, velocity);
+    true
+}
+
+pub fn drop_item(res: &mut Resources, dimension: DimensionId, block: BlockId, position: Vec3, velocity: Vec3) -> Entity {
+    let item = DroppedItem {
+        block,
+        velocity,
+        pickup_after: res.time.now + PICKUP_DELAY,
+        despawn_at: res.time.now + LIFETIME,
+    };
+    let nid = res.net.allocate_network_id();
+    let entity = components::spawn_item(&mut res.main_world, nid, item, position);
+    if dimension != DimensionId::OVERWORLD {
+        let _ = res.main_world.insert_one(entity, InDimension(dimension));
+    }
+    if let Err(e) = res.net.track_entity_add(entity, nid) {
+        // Shouldn't happen, the id was just allocated
+        eprintln!("drop_item: {e}");
+    }
+    entity
+}
+
+fn give_inventory(res: &mut Resources, player: Entity) {
+    let _ = res.main_world.insert_one(player, Inventory::default());
+}
+
+fn simulate_items(res: &mut Resources) -> anyhow::Result<()> {
+    let dt = TICK_DURATION.as_secs_f32();
+    let now = res.time.now;
+    let dimension_of = |in_dimension: Option<&InDimension>| in_dimension.map_or(DimensionId::OVERWORLD, |d| d.0);
+    let players = res.main_world.query_mut::<(&PlayerId, &Position, Option<&InDimension>)>()
+        .into_iter()
+        .map(|(entity, (_, pos, in_dimension))| (entity, pos.0, dimension_of(in_dimension)))
+        .collect::<Vec<_>>();
+
+    let mut picked_up = Vec::new();
+    let mut expired = Vec::new();
+    let query = res.main_world.query_mut::<(&NetworkId, &mut DroppedItem, &mut Position, Option<&InDimension>)>();
+    for (_, (&nid, item, Position(pos), in_dimension)) in query {
+        let dimension = dimension_of(in_dimension);
+        let blocks = res.dimensions.blocks(&res.blocks, dimension);
+        // Fell out of the world or lay around for too long
+        if now >= item.despawn_at || pos.y < 0.0 {
+            expired.push(nid);
+            continue;
+        }
+
+        let nearest = players.iter()
+            .filter(|&&(_, _, player_dimension)| player_dimension == dimension)
+            .map(|&(player, player_pos, _)| (player, player_pos, player_pos.distance(*pos)))
+            .filter(|&(_, _, distance)| distance < MAGNET_RADIUS)
+            .min_by(|a, b| a.2.total_cmp(&b.2));
+        match nearest {
+            Some((player, _, distance)) if now >= item.pickup_after && distance < PICKUP_RADIUS => {
+                picked_up.push((nid, player, item.block));
+                continue;
+            }
+            Some((_, player_pos, _)) if now >= item.pickup_after => {
+                let pull = (player_pos - *pos).normalize_or_zero() * MAGNET_SPEED;
+                item.velocity = item.velocity.lerp(pull, 0.25);
+            }
+            _ => item.velocity.y = (item.velocity.y - GRAVITY * dt).max(-MAX_FALL_SPEED),
+        }
+
+        // One axis at a time, so that it slides along whatever it hits
+        let mut on_ground = false;
+        for axis in 0..3 {
+            let mut moved = *pos;
+            moved[axis] += item.velocity[axis] * dt;
+            if !blocks.collides(moved - HALF_EXTENT, moved + HALF_EXTENT) {
+                *pos = moved;
+            } else if axis == 1 {
+                on_ground = item.velocity.y < 0.0;
+                if on_ground {
+                    // Right on top of the block it landed on
+                    pos.y = (moved.y - HALF_EXTENT).floor() + 1.0 + HALF_EXTENT;
+                }
+                item.velocity.y = -item.velocity.y * BOUNCINESS;
+                if item.velocity.y.abs() < MIN_BOUNCE_SPEED {
+                    item.velocity.y = 0.0;
+                }
+            } else {
+                item.velocity[axis] = 0.0;
+            }
+        }
+        if on_ground {
+            item.velocity.x *= GROUND_FRICTION;
+            item.velocity.z *= GROUND_FRICTION;
+        }
+    }
+
+    for (nid, player, block) in picked_up {
+        if let Ok(inventory) = res.main_world.query_one_mut::<&mut Inventory>(player) {
+            *inventory.0.entry(block).or_default() += 1;
+        }
+        remove_item(res, nid);
+    }
+    for nid in expired {
+        remove_item(res, nid);
+    }
+    Ok(())
+}
+
+fn remove_item(res: &mut Resources, nid: NetworkId) {
+    match res.net.remove_entity(nid) {
+        Ok(entity) => {
+            let _ = res.main_world.despawn(entity);
+        }
+        Err(e) => eprintln!("remove_item: {e}"),
+    }
+}
+
+// `/inventory`: lists what the player has picked up
+fn inventory_from_chat(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    if message.trim_end() != "/inventory" {
+        return false;
+    }
+    let Ok((&player_id, inventory)) = res.main_world.query_one_mut::<(&PlayerId, &Inventory)>(sender) else {
+        return true;
+    };
+    let mut items = inventory.0.iter().map(|(&block, &count)| (block, count)).collect::<Vec<_>>();
+    items.sort_unstable();
+    let reply = match items.is_empty() {
+        true => "Your inventory is empty".to_owned(),
+        false => {
+            let items = items.iter().map(|(block, count)| format!("{count} x block {block}")).collect::<Vec<_>>();
+            format!("Inventory: {}", items.join(", "))
+        }
+    };
+    res.net.send_chat(player_id, reply.into());
+    true
+}
+
+mod tests {
+    #[test]
+    fn test_dropped_items() {
+        use glam::{ivec3, Vec2};
+        use shared::{dimension::DimensionId, protocol::s2c::{MetadataKey, MetadataValue, TeleportFlags}, worldgen::STONE, TICKS_PER_SECOND};
+        use crate::{components::{DroppedItem, Inventory, Position}, items, net, networking::client_connection::entity_state::EntityStateMsg, testing::TestServer};
+
+        let mut server = TestServer::new();
+        let player = server.connect("miner");
+        for z in -4..=4 {
+            for x in -4..=4 {
+                server.res.blocks.set_block(ivec3(x, 10, z), STONE);
+            }
+        }
+        server.res.blocks.set_block(ivec3(0, 11, 0), STONE);
+        assert!(items::break_block(&mut server.res, DimensionId::OVERWORLD, ivec3(0, 11, 0)));
+        assert!(!items::break_block(&mut server.res, DimensionId::OVERWORLD, ivec3(0, 11, 0)));
+        assert!(!server.res.blocks.is_solid(ivec3(0, 11, 0)));
+
+        // Bounces to rest on the platform
+        server.run_ticks(2 * TICKS_PER_SECOND);
+        let item_pos = {
+            let items = server.res.main_world.query_mut::<(&DroppedItem, &Position)>();
+            let (_, (item, pos)) = items.into_iter().next().expect("no item was dropped");
+            assert_eq!(item.block, STONE);
+            pos.0
+        };
+        assert!((item_pos.y - 11.125).abs() < 0.01, "item at {item_pos}");
+
+        // Out of reach for now, the player is at the origin
+        let sent_item = server.received_entity_states(player).into_iter().flat_map(|state| state.changes).any(|(_, msg)| {
+            matches!(msg, EntityStateMsg::MetadataChanged { key: MetadataKey::Item, value: MetadataValue::Uint(1) })
+        });
+        assert!(sent_item);
+
+        let entity = server.client(player).entity;
+        net::teleport(&mut server.res, entity, item_pos + glam::vec3(2.0, 0.5, 0.0), Vec2::ZERO, TeleportFlags::ABSOLUTE).unwrap();
+        server.run_ticks(TICKS_PER_SECOND);
+        assert_eq!(server.res.main_world.query_mut::<&DroppedItem>().into_iter().count(), 0);
+        assert_eq!(server.res.main_world.get::<&Inventory>(entity).unwrap().0.get(&STONE), Some(&1));
+
+        server.send_chat(player, "/inventory");
+        server.tick();
+        assert!(server.received_chat(player).iter().any(|(_, message)| message.as_str() == "Inventory: 1 x block 1"));
+
+        // Nothing below, falls out of the world
+        server.res.blocks.set_block(ivec3(100, 5, 0), STONE);
+        assert!(items::break_block(&mut server.res, DimensionId::OVERWORLD, ivec3(100, 5, 0)));
+        server.run_ticks(2 * TICKS_PER_SECOND);
+        assert_eq!(server.res.main_world.query_mut::<&DroppedItem>().into_iter().count(), 0);
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use flexstr::SharedStr;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+    components::NetworkId,
+    config::ServerConfig,
+    game_builder::{GameBuilder, Stage},
+    net,
+    networking::LoginResponse,
+    resources::Resources,
+    shutdown,
+};
+
+// Players reminded of their place in line this often even if it hasn't changed, which also
+// notices ones who have given up
+const REMINDER_INTERVAL: Duration = Duration::from_secs(5);
+
+// Logins that came in while the server was full (see `ServerConfig::max_players`), let in
+// first come first served as players leave. The clients are told their place in line as it
+// changes.
+pub struct JoinQueue {
+    max_players: usize,
+    capacity: usize,
+    waiting: VecDeque<Waiting>,
+}
+
+struct Waiting {
+    channel: UnboundedSender<(NetworkId, LoginResponse)>,
+    username: SharedStr,
+    told_position: usize,
+    told_at: Instant,
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    let config = builder.resource::<ServerConfig>();
+    let max_players = config.map_or(usize::MAX, |config| config.max_players).min(MAX_ONLINE_PLAYERS);
+    let capacity = config.map_or(0, |config| config.join_queue_size);
+
+    builder
+        .insert_resource(JoinQueue { max_players, capacity, waiting: VecDeque::new() })
+        // After `net` has taken in new logins
+        .add_system(Stage::NetIn, admit_waiting)
+        .on_console_command(list_from_console);
+}
+
+const MAX_ONLINE_PLAYERS: usize = shared::protocol::MAX_ONLINE_PLAYERS as usize;
+
+// See `reload`. Lowering the limits turns nobody away who is already online or in line;
+// they only apply to newcomers.
+pub fn reconfigure(res: &mut Resources, config: &ServerConfig) {
+    if let Some(queue) = res.extra.get_mut::<JoinQueue>() {
+        queue.max_players = config.max_players.min(MAX_ONLINE_PLAYERS);
+        queue.capacity = config.join_queue_size;
+    }
+}
+
+// Whether a new login has to get in line, either because the server is full or because
+// others are already waiting
+pub fn must_wait(res: &Resources) -> bool {
+    let Some(queue) = res.extra.get::<JoinQueue>() else {
+        return false;
+    };
+    !queue.waiting.is_empty() || res.net.player_count() >= queue.max_players
+}
+
+// Puts the player at the end of the line, or turns them away if it's full too
+pub fn enqueue(res: &mut Resources, channel: UnboundedSender<(NetworkId, LoginResponse)>, username: SharedStr) {
+    let now = res.time.now;
+    let Some(queue) = res.extra.get_mut::<JoinQueue>() else {
+        return;
+    };
+    if queue.waiting.len() >= queue.capacity {
+        println!("Denied login from {username}: server full");
+        let _ = channel.send((NetworkId::INVALID, LoginResponse::Denied("Server is full".into())));
+        return;
+    }
+
+    let position = queue.waiting.len() + 1;
+    println!("{username} is waiting to join, number {position} in line");
+    if channel.send((NetworkId::INVALID, LoginResponse::Queued(position as u16))).is_ok() {
+        queue.waiting.push_back(Waiting { channel, username, told_position: position, told_at: now });
+    }
+}
+
+fn admit_waiting(res: &mut Resources) -> anyhow::Result<()> {
+    let now = res.time.now;
+    let shutting_down = shutdown::in_progress(res);
+    let Some(queue) = res.extra.get_mut::<JoinQueue>() else {
+        return Ok(());
+    };
+    if shutting_down {
+        for waiting in queue.waiting.drain(..) {
+            let _ = waiting.channel.send((NetworkId::INVALID, LoginResponse::Denied("Server is shutting down".into())));
+        }
+        return Ok(());
+    }
+    // The login task drops its end once the client is gone
+    queue.waiting.retain(|waiting| !waiting.channel.is_closed());
+    let max_players = queue.max_players;
+
+    while res.net.player_count() < max_players {
+        let Some(waiting) = res.extra.get_mut::<JoinQueue>().and_then(|queue| queue.waiting.pop_front()) else {
+            break;
+        };
+        println!("Letting {} in from the queue", waiting.username);
+        net::accept_login(res, waiting.channel);
+    }
+
+    let Some(queue) = res.extra.get_mut::<JoinQueue>() else {
+        return Ok(());
+    };
+    for (i, waiting) in queue.waiting.iter_mut().enumerate() {
+        let position = i + 1;
+        if position != waiting.told_position || now - waiting.told_at >= REMINDER_INTERVAL {
+            let _ = waiting.channel.send((NetworkId::INVALID, LoginResponse::Queued(position as u16)));
+            waiting.told_position = position;
+            waiting.told_at = now;
+        }
+    }
+    Ok(())
+}
+
+fn list_from_console(res: &mut Resources, command: &str, _args: &str) -> bool {
+    if command != "queue" {
+        return false;
+    }
+    let Some(queue) = res.extra.get::<JoinQueue>() else {
+        return true;
+    };
+    if queue.waiting.is_empty() {
+        println!("Nobody is waiting to join");
+    } else {
+        let names = queue.waiting.iter().map(|waiting| waiting.username.as_str()).collect::<Vec<_>>();
+        println!("Waiting to join: {}", names.join(", "));
+    }
+    true
+}
+
+mod tests {
+    #[test]
+    fn test_join_queue() {
+        use crate::{config::ServerConfig, networking::LoginResponse, testing::TestServer};
+
+        let mut server = TestServer::with_config(ServerConfig { max_players: 2, join_queue_size: 2, ..TestServer::config() });
+        let alice = server.connect("alice");
+        let bob = server.connect("bob");
+
+        let mut carol = server.request_login("carol");
+        let mut dave = server.request_login("dave");
+        let mut erin = server.request_login("erin");
+        server.tick();
+        assert!(matches!(carol.try_recv(), Ok((_, LoginResponse::Queued(1)))));
+        assert!(matches!(dave.try_recv(), Ok((_, LoginResponse::Queued(2)))));
+        let Ok((_, LoginResponse::Denied(reason))) = erin.try_recv() else {
+            panic!("player wasn't turned away from a full queue");
+        };
+        assert_eq!(reason.as_str(), "Server is full");
+
+        // Carol gets Alice's place, and Dave moves up
+        server.disconnect(alice);
+        server.tick();
+        let Ok((network_id, LoginResponse::Success(..))) = carol.try_recv() else {
+            panic!("first in line wasn't let in");
+        };
+        assert!(matches!(dave.try_recv(), Ok((_, LoginResponse::Queued(1)))));
+        server.finish_login("carol", network_id);
+
+        // Still full, and nothing new to tell Dave until the reminder is due
+        server.tick();
+        assert!(dave.try_recv().is_err());
+        server.run_ticks(6 * shared::TICKS_PER_SECOND);
+        assert!(matches!(dave.try_recv(), Ok((_, LoginResponse::Queued(1)))));
+
+        // Giving up makes room in the queue
+        drop(dave);
+        server.tick();
+        let mut erin = server.request_login("erin");
+        server.tick();
+        assert!(matches!(erin.try_recv(), Ok((_, LoginResponse::Queued(1)))));
+
+        // Erin is let in but goes away before connecting, and the network thread hands her id
+        // back. Her place goes to the next one.
+        server.disconnect(bob);
+        server.tick();
+        let Ok((network_id, LoginResponse::Success(..))) = erin.try_recv() else {
+            panic!("first in line wasn't let in");
+        };
+        server.abandon_login(network_id);
+        let mut frank = server.request_login("frank");
+        server.tick();
+        assert!(matches!(frank.try_recv(), Ok((_, LoginResponse::Success(..)))));
+    }
+}
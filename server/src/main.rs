@@ -1,54 +1,111 @@
 #![feature(let_else)]
 
+pub mod afk;
+pub mod authority;
+pub mod block_breaking;
+pub mod block_placing;
+pub mod block_updates;
+pub mod chat;
+pub mod config;
+pub mod dimensions;
+pub mod fluids;
+pub mod console;
 pub mod game_builder;
+pub mod items;
+pub mod join_queue;
 pub mod networking;
 pub mod server;
+pub mod shutdown;
 pub mod resources;
 pub mod components;
+pub mod metrics;
+pub mod moderation;
 pub mod net;
+pub mod permissions;
+pub mod scripting;
+pub mod spawning;
+pub mod stress;
+pub mod teleport;
+pub mod testing;
+pub mod tick_control;
+pub mod pathfinding;
+pub mod random_tick;
+pub mod reload;
+pub mod savefile;
+pub mod scheduler;
+pub mod schematics;
+pub mod world;
 
 use std::{
-    time::{Duration, Instant}, sync::atomic::{AtomicBool, Ordering}, net::SocketAddr,
+    time::{Duration, Instant}, sync::atomic::{AtomicU32, Ordering}, path::Path,
 };
 
+use config::ServerConfig;
+
 pub fn main() {
-    if let Some(address) = get_bind_address() {
-        runner(address);
+    if let Some(config) = get_config() {
+        runner(config);
         println!("Server stopped.");
     }
 }
 
-fn get_bind_address() -> Option<SocketAddr> {
+// `server.cfg`, with the bind address optionally overridden by the first argument
+fn get_config() -> Option<ServerConfig> {
+    let mut config = match ServerConfig::load(Path::new(config::CONFIG_PATH)) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("Invalid config: {e}");
+            return None;
+        }
+    };
+
     if let Some(address) = std::env::args().skip(1).next() {
         match address.parse() {
-            Ok(address) => Some(address),
+            Ok(address) => config.bind_address = address,
             Err(e) => {
                 println!("Invalid bind address '{address}': {e}");
-                None
+                return None;
             }
         }
-    } else {
-        Some("0.0.0.0:29477".parse().unwrap())
     }
+    Some(config)
 }
 
-pub fn runner(address: SocketAddr) {
-    let mut state = server::init(address).unwrap();
+pub fn runner(config: ServerConfig) {
+    let (mut state, schedule) = match server::init(config) {
+        Ok(server) => server,
+        Err(e) => {
+            println!("Failed to start the server: {e:#}");
+            return;
+        }
+    };
 
     println!("Server running @ {}Hz tick rate", shared::TICKS_PER_SECOND);
 
-    static SHOULD_STOP : AtomicBool = AtomicBool::new(false);
+    // The first Ctrl-C starts the shutdown countdown, the second one skips it
+    static INTERRUPTS : AtomicU32 = AtomicU32::new(0);
     ctrlc::set_handler(|| {
         println!();
-        SHOULD_STOP.store(true, Ordering::Relaxed);
+        if INTERRUPTS.fetch_add(1, Ordering::Relaxed) == 0 {
+            println!("Press Ctrl-C again to stop immediately");
+        }
     }).unwrap();
 
     let mut last_sec = Instant::now();
     let mut updates = 0;
 
     let server_start_time = Instant::now();
-    while !SHOULD_STOP.load(Ordering::Relaxed) {
-        if let Err(e) = server::tick(&mut state) {
+    loop {
+        match INTERRUPTS.load(Ordering::Relaxed) {
+            0 => {}
+            1 => shutdown::begin(&mut state),
+            _ => break,
+        }
+        if shutdown::countdown_over(&state) {
+            break;
+        }
+
+        if let Err(e) = server::tick(&mut state, &schedule) {
             eprintln!("Error while ticking server: {e}");
         }
 
@@ -0,0 +1,386 @@
+// Prometheus-style metrics over plain HTTP, enabled by setting `metrics_address` in `server.cfg`.
+// The page is rendered on the main thread once per second and served as-is by a small
+// listener thread, so scraping never touches game state.
+//
+// Without any monitoring set up, ops can see the last minute of the same numbers in chat:
+//
+//   /debug tps | net | mem
+//
+// The console takes the same without the slash.
+
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use hecs::Entity;
+use shared::{dimension::DimensionId, TICKS_PER_SECOND};
+
+use crate::{
+    components::PlayerId,
+    config::ServerConfig,
+    dimensions,
+    game_builder::{GameBuilder, Stage},
+    networking::stats::NET_STATS,
+    resources::Resources,
+};
+
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+// Samples kept for /debug, one per UPDATE_INTERVAL
+const HISTORY_LEN: usize = 60;
+
+// Once per UPDATE_INTERVAL, for /debug
+#[derive(Clone, Copy)]
+pub struct Sample {
+    pub tps: f32,
+    pub avg_tick_ms: f32,
+    pub max_tick_ms: f32,
+    // Per second, all channels
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub players: u32,
+    pub entities: u32,
+    pub memory_bytes: Option<u64>,
+}
+
+pub struct MetricsHistory {
+    samples: VecDeque<Sample>,
+    last_sample: Instant,
+    last_sample_tick: u32,
+    last_bytes: (u64, u64),
+    // Since the last sample
+    tick_time: Duration,
+    max_tick_time: Duration,
+}
+
+impl MetricsHistory {
+    // Oldest first
+    pub fn samples(&self) -> impl Iterator<Item = &Sample> + '_ {
+        self.samples.iter()
+    }
+}
+
+pub struct MetricsExporter {
+    page: Arc<Mutex<String>>,
+    last_update: Instant,
+    last_update_tick: u32,
+    // Summed over the ticks since the last update
+    stage_durations: [Duration; Stage::COUNT],
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    builder
+        .insert_resource(MetricsHistory {
+            samples: VecDeque::with_capacity(HISTORY_LEN),
+            last_sample: Instant::now(),
+            last_sample_tick: 0,
+            last_bytes: total_bytes(),
+            tick_time: Duration::ZERO,
+            max_tick_time: Duration::ZERO,
+        })
+        .add_system(Stage::PostTick, record_history)
+        .require_op("/debug")
+        .on_chat(debug_from_chat)
+        .on_console_command(debug_from_console);
+
+    let Some(address) = builder.resource::<ServerConfig>().and_then(|config| config.metrics_address) else {
+        return;
+    };
+
+    let page = Arc::new(Mutex::new(String::new()));
+    if let Err(e) = start_listener(address, page.clone()) {
+        eprintln!("Failed to start metrics endpoint at {address}: {e}");
+        return;
+    }
+    println!("Serving metrics @ http://{address}/metrics");
+
+    builder
+        .insert_resource(MetricsExporter {
+            page,
+            last_update: Instant::now(),
+            last_update_tick: 0,
+            stage_durations: [Duration::ZERO; Stage::COUNT],
+        })
+        .add_system(Stage::PostTick, update_metrics);
+}
+
+fn update_metrics(res: &mut Resources) -> anyhow::Result<()> {
+    let (now, current_tick) = (res.time.now, res.current_tick);
+    let num_players = res.main_world.query_mut::<&PlayerId>().into_iter().count();
+    let loaded_chunks = std::iter::once((DimensionId::OVERWORLD, &res.blocks))
+        .chain(res.dimensions.others())
+        .map(|(dimension, blocks)| (dimensions::name(dimension), blocks.chunk_positions().count()))
+        .collect::<Vec<_>>();
+
+    let Some(exporter) = res.extra.get_mut::<MetricsExporter>() else {
+        return Ok(());
+    };
+    for (sum, duration) in exporter.stage_durations.iter_mut().zip(res.time.stage_durations) {
+        *sum += duration;
+    }
+
+    let elapsed = now - exporter.last_update;
+    if elapsed < UPDATE_INTERVAL {
+        return Ok(());
+    }
+    let ticks = current_tick.wrapping_sub(exporter.last_update_tick).max(1);
+
+    let mut page = String::new();
+    header(&mut page, "server_players", "gauge", "Number of connected players");
+    let _ = writeln!(page, "server_players {num_players}");
+
+    header(&mut page, "server_tps", "gauge", "Ticks per second since the previous update");
+    let _ = writeln!(page, "server_tps {}", ticks as f32 / elapsed.as_secs_f32());
+
+    header(&mut page, "server_tick_stage_seconds", "gauge", "Average time spent in each tick stage");
+    for (stage, sum) in Stage::ALL.iter().zip(exporter.stage_durations) {
+        let _ = writeln!(page, "server_tick_stage_seconds{{stage=\"{stage:?}\"}} {}", sum.as_secs_f64() / ticks as f64);
+    }
+
+    header(&mut page, "server_network_bytes_total", "counter", "Bytes transferred per channel, including message headers");
+    for (channel, stats) in NET_STATS.channels() {
+        let _ = writeln!(page, "server_network_bytes_total{{channel=\"{channel}\",direction=\"in\"}} {}", stats.bytes_in());
+        let _ = writeln!(page, "server_network_bytes_total{{channel=\"{channel}\",direction=\"out\"}} {}", stats.bytes_out());
+    }
+
+    header(&mut page, "server_loaded_chunks", "gauge", "Chunks held in memory (written to at some point), per dimension");
+    for (dimension, count) in loaded_chunks {
+        let _ = writeln!(page, "server_loaded_chunks{{dimension=\"{dimension}\"}} {count}");
+    }
+
+    if let Some(bytes) = resident_memory_bytes() {
+        header(&mut page, "server_resident_memory_bytes", "gauge", "Resident set size of the server process");
+        let _ = writeln!(page, "server_resident_memory_bytes {bytes}");
+    }
+
+    *exporter.page.lock().unwrap() = page;
+    exporter.last_update = now;
+    exporter.last_update_tick = current_tick;
+    exporter.stage_durations = [Duration::ZERO; Stage::COUNT];
+    Ok(())
+}
+
+fn record_history(res: &mut Resources) -> anyhow::Result<()> {
+    let (now, current_tick) = (res.time.now, res.current_tick);
+    let players = res.main_world.query_mut::<&PlayerId>().into_iter().count() as u32;
+    let Some(history) = res.extra.get_mut::<MetricsHistory>() else {
+        return Ok(());
+    };
+    let tick_time = res.time.stage_durations.iter().sum::<Duration>();
+    history.tick_time += tick_time;
+    history.max_tick_time = history.max_tick_time.max(tick_time);
+
+    let elapsed = now.saturating_duration_since(history.last_sample);
+    if elapsed < UPDATE_INTERVAL {
+        return Ok(());
+    }
+    let ticks = current_tick.wrapping_sub(history.last_sample_tick).max(1);
+    let bytes = total_bytes();
+    let per_second = |bytes: u64| (bytes as f64 / elapsed.as_secs_f64()) as u64;
+    let sample = Sample {
+        tps: ticks as f32 / elapsed.as_secs_f32(),
+        avg_tick_ms: history.tick_time.as_secs_f32() * 1000.0 / ticks as f32,
+        max_tick_ms: history.max_tick_time.as_secs_f32() * 1000.0,
+        bytes_in: per_second(bytes.0.saturating_sub(history.last_bytes.0)),
+        bytes_out: per_second(bytes.1.saturating_sub(history.last_bytes.1)),
+        players,
+        entities: res.main_world.len(),
+        memory_bytes: resident_memory_bytes(),
+    };
+
+    if history.samples.len() == HISTORY_LEN {
+        history.samples.pop_front();
+    }
+    history.samples.push_back(sample);
+    history.last_sample = now;
+    history.last_sample_tick = current_tick;
+    history.last_bytes = bytes;
+    history.tick_time = Duration::ZERO;
+    history.max_tick_time = Duration::ZERO;
+    Ok(())
+}
+
+// (in, out) over all channels
+fn total_bytes() -> (u64, u64) {
+    NET_STATS
+        .channels()
+        .iter()
+        .fold((0, 0), |(bytes_in, bytes_out), (_, stats)| (bytes_in + stats.bytes_in(), bytes_out + stats.bytes_out()))
+}
+
+// One character per value, from '_' for 0 to '#' for `max` and above
+pub fn sparkline(values: impl Iterator<Item = f32>, max: f32) -> String {
+    const LEVELS: &[u8] = b"_.-:=+*#";
+    let top = (LEVELS.len() - 1) as f32;
+    values
+        .map(|value| {
+            let level = if max > 0.0 { (value / max * top).round().clamp(0.0, top) } else { 0.0 };
+            LEVELS[level as usize] as char
+        })
+        .collect()
+}
+
+fn debug_from_chat(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    let (command, args) = message.split_once(' ').unwrap_or((message, ""));
+    if command != "/debug" {
+        return false;
+    }
+    let Ok(&player_id) = res.main_world.get::<&PlayerId>(sender).as_deref() else {
+        return true;
+    };
+    for line in debug_report(res, args.trim()) {
+        res.net.send_chat(player_id, line.into());
+    }
+    true
+}
+
+fn debug_from_console(res: &mut Resources, command: &str, args: &str) -> bool {
+    if command != "debug" {
+        return false;
+    }
+    for line in debug_report(res, args.trim()) {
+        println!("{line}");
+    }
+    true
+}
+
+fn debug_report(res: &Resources, what: &str) -> Vec<String> {
+    let Some(history) = res.extra.get::<MetricsHistory>() else {
+        return vec!["Metrics are disabled".to_owned()];
+    };
+    let samples = history.samples().copied().collect::<Vec<_>>();
+    let Some(latest) = samples.last() else {
+        return vec!["No samples yet, try again in a second".to_owned()];
+    };
+    let secs = samples.len();
+    let peak = |value: fn(&Sample) -> f32| samples.iter().map(value).fold(0.0, f32::max);
+    let line = |value: fn(&Sample) -> f32, max: f32| sparkline(samples.iter().map(value), max);
+    let kib = |bytes: f32| bytes / 1024.0;
+
+    match what {
+        "tps" => {
+            let target = TICKS_PER_SECOND as f32;
+            let budget_ms = 1000.0 / target;
+            let min_tps = samples.iter().map(|s| s.tps).fold(f32::MAX, f32::min);
+            vec![
+                format!("TPS over {secs}s: now {:.1}, lowest {min_tps:.1}, target {target}", latest.tps),
+                line(|s| s.tps, target.max(peak(|s| s.tps))),
+                format!(
+                    "Longest tick of each second, ms: now {:.1} (average {:.1}), peak {:.1}, budget {budget_ms:.1}",
+                    latest.max_tick_ms,
+                    latest.avg_tick_ms,
+                    peak(|s| s.max_tick_ms)
+                ),
+                line(|s| s.max_tick_ms, budget_ms.max(peak(|s| s.max_tick_ms))),
+            ]
+        }
+        "net" => {
+            let (peak_in, peak_out) = (peak(|s| s.bytes_in as f32), peak(|s| s.bytes_out as f32));
+            vec![
+                format!("In over {secs}s, KiB/s: now {:.1}, peak {:.1}", kib(latest.bytes_in as f32), kib(peak_in)),
+                line(|s| s.bytes_in as f32, peak_in),
+                format!("Out over {secs}s, KiB/s: now {:.1}, peak {:.1}", kib(latest.bytes_out as f32), kib(peak_out)),
+                line(|s| s.bytes_out as f32, peak_out),
+            ]
+        }
+        "mem" => {
+            let peak_entities = peak(|s| s.entities as f32);
+            let mut lines = vec![
+                format!("Entities over {secs}s: now {}, peak {peak_entities}, {} players", latest.entities, latest.players),
+                line(|s| s.entities as f32, peak_entities),
+            ];
+            match latest.memory_bytes {
+                Some(bytes) => {
+                    let mib = |s: &Sample| s.memory_bytes.unwrap_or(0) as f32 / (1024.0 * 1024.0);
+                    let peak_mib = samples.iter().map(mib).fold(0.0, f32::max);
+                    lines.push(format!("Resident memory, MiB: now {:.1}, peak {peak_mib:.1}", bytes as f32 / (1024.0 * 1024.0)));
+                    lines.push(sparkline(samples.iter().map(mib), peak_mib));
+                }
+                None => lines.push("Resident memory isn't available on this platform".to_owned()),
+            }
+            lines
+        }
+        _ => vec!["Usage: /debug tps | net | mem".to_owned()],
+    }
+}
+
+fn header(page: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(page, "# HELP {name} {help}");
+    let _ = writeln!(page, "# TYPE {name} {kind}");
+}
+
+#[cfg(target_os = "linux")]
+fn resident_memory_bytes() -> Option<u64> {
+    // Second field is the resident set size in pages, which aren't 4 KiB everywhere (arm64
+    // kernels are often built with 16 or 64 KiB pages)
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages = statm.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    let page_size = u64::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).ok()?;
+    Some(pages * page_size)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_memory_bytes() -> Option<u64> {
+    None
+}
+
+fn start_listener(address: SocketAddr, page: Arc<Mutex<String>>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(2)));
+
+            let mut request = [0u8; 1024];
+            let len = stream.read(&mut request).unwrap_or(0);
+
+            let response = if request[..len].starts_with(b"GET /metrics ") {
+                let body = page.lock().unwrap().clone();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+            };
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn test_debug_metrics() {
+        use shared::TICKS_PER_SECOND;
+        use crate::{game_builder, metrics::{self, MetricsHistory}, testing::TestServer};
+
+        assert_eq!(metrics::sparkline([0.0, 1.0, 2.0, 4.0, 8.0].into_iter(), 4.0), "_-=##");
+        assert_eq!(metrics::sparkline([3.0].into_iter(), 0.0), "_");
+
+        let mut server = TestServer::new();
+        let alice = server.connect("alice");
+        server.run_ticks(3 * TICKS_PER_SECOND);
+        let history = server.res.extra.get::<MetricsHistory>().unwrap();
+        let latest = *history.samples().last().unwrap();
+        assert!(history.samples().count() >= 2);
+        assert_eq!(latest.players, 1);
+        assert!(latest.entities >= 1);
+        assert!((latest.tps - TICKS_PER_SECOND as f32).abs() < 1.0, "{}", latest.tps);
+
+        assert!(game_builder::dispatch_console_command(&mut server.res, "op", "alice"));
+        server.tick();
+        server.received_chat(alice);
+        server.send_chat(alice, "/debug tps");
+        server.run_ticks(2);
+        let replies = server.received_chat(alice);
+        assert!(replies.iter().any(|(_, message)| message.starts_with("TPS over")));
+        assert!(replies.iter().any(|(_, message)| message.chars().all(|c| "_.-:=+*#".contains(c))));
+    }
+}
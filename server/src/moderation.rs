@@ -0,0 +1,173 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use flexstr::{SharedStr, ToSharedStr};
+use hecs::Entity;
+use regex::Regex;
+
+use crate::{
+    components::{Ignoring, PlayerId, Username},
+    config::ServerConfig,
+    game_builder::{ChatVerdict, GameBuilder},
+    resources::Resources,
+};
+
+pub struct Moderation {
+    // Username -> until when. None = until unmuted.
+    muted: HashMap<SharedStr, Option<Instant>>,
+    filters: Vec<Regex>,
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    let patterns = builder.resource::<ServerConfig>().map_or(Vec::new(), |config| config.chat_filters.clone());
+    let filters = compile_filters(&patterns);
+
+    builder
+        .insert_resource(Moderation { muted: HashMap::new(), filters })
+        .add_chat_filter(reject_muted)
+        .add_chat_filter(censor_filtered_words)
+        .on_chat(ignore_commands)
+        .on_console_command(mute_commands);
+}
+
+fn compile_filters(patterns: &[String]) -> Vec<Regex> {
+    patterns
+        .iter()
+        .filter_map(|pattern| match Regex::new(&format!("(?i){pattern}")) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                eprintln!("Invalid chat filter '{pattern}', ignoring: {e}");
+                None
+            }
+        })
+        .collect()
+}
+
+// See `reload`
+pub fn reconfigure(res: &mut Resources, config: &ServerConfig) {
+    if let Some(moderation) = res.extra.get_mut::<Moderation>() {
+        moderation.filters = compile_filters(&config.chat_filters);
+    }
+}
+
+fn reject_muted(res: &mut Resources, sender: Entity, _: &str) -> ChatVerdict {
+    let (Some(moderation), Ok(username)) = (res.extra.get_mut::<Moderation>(), res.main_world.get::<&Username>(sender)) else {
+        return ChatVerdict::Allow;
+    };
+    match moderation.muted.get(&username.0) {
+        Some(None) => ChatVerdict::Block("You are muted".to_owned()),
+        Some(&Some(until)) if until > res.time.now => {
+            let minutes = (until - res.time.now).as_secs() / 60 + 1;
+            ChatVerdict::Block(format!("You are muted for {minutes} more minute(s)"))
+        }
+        Some(Some(_)) => {
+            moderation.muted.remove(&username.0);
+            ChatVerdict::Allow
+        }
+        None => ChatVerdict::Allow,
+    }
+}
+
+fn censor_filtered_words(res: &mut Resources, sender: Entity, message: &str) -> ChatVerdict {
+    let Some(moderation) = res.extra.get::<Moderation>() else {
+        return ChatVerdict::Allow;
+    };
+
+    let mut censored = message.to_owned();
+    for filter in &moderation.filters {
+        censored = filter
+            .replace_all(&censored, |captures: &regex::Captures| "*".repeat(captures[0].chars().count()))
+            .into_owned();
+    }
+    if censored == message {
+        return ChatVerdict::Allow;
+    }
+
+    if let Ok(&player_id) = res.main_world.get::<&PlayerId>(sender).as_deref() {
+        res.net.send_chat(player_id, "Parts of your message were filtered".into());
+    }
+    ChatVerdict::Replace(censored)
+}
+
+// `/ignore [player]` and `/unignore <player>`. Only lasts until the player disconnects.
+fn ignore_commands(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    let (command, name) = message.split_once(' ').unwrap_or((message, ""));
+    if command != "/ignore" && command != "/unignore" {
+        return false;
+    }
+    let Ok((&player_id, Username(own_name), Ignoring(ignored))) = res.main_world
+        .query_one_mut::<(&PlayerId, &Username, &mut Ignoring)>(sender) else {
+        return true;
+    };
+    let name = name.trim();
+
+    let reply = match (command, name) {
+        ("/ignore", "") if ignored.is_empty() => "You aren't ignoring anybody".to_owned(),
+        ("/ignore", "") => {
+            let mut names = ignored.iter().map(SharedStr::as_str).collect::<Vec<_>>();
+            names.sort_unstable();
+            format!("Ignoring: {}", names.join(", "))
+        }
+        ("/ignore", name) if name == own_name.as_str() => "You can't ignore yourself".to_owned(),
+        ("/ignore", name) => {
+            ignored.insert(name.to_shared_str());
+            format!("Ignoring messages from {name}")
+        }
+        (_, "") => "Usage: /unignore <player>".to_owned(),
+        (_, name) => match ignored.remove(name) {
+            true => format!("No longer ignoring {name}"),
+            false => format!("You weren't ignoring {name}"),
+        },
+    };
+    res.net.send_chat(player_id, reply.into());
+    true
+}
+
+// `mute <player> [minutes]` and `unmute <player>`
+fn mute_commands(res: &mut Resources, command: &str, args: &str) -> bool {
+    if command != "mute" && command != "unmute" {
+        return false;
+    }
+    let mut args = args.split_whitespace();
+    let Some(name) = args.next().map(|name| name.to_shared_str()) else {
+        println!("Usage: mute <player> [minutes] | unmute <player>");
+        return true;
+    };
+    let minutes = args.next().map(str::parse::<u64>);
+
+    let now = res.time.now;
+    let Some(moderation) = res.extra.get_mut::<Moderation>() else {
+        return true;
+    };
+    let (reply, notice) = match (command, minutes) {
+        ("mute", Some(Err(_))) => {
+            println!("Usage: mute <player> [minutes]");
+            return true;
+        }
+        ("mute", Some(Ok(minutes))) => {
+            moderation.muted.insert(name.clone(), Some(now + Duration::from_secs(minutes * 60)));
+            (format!("Muted {name} for {minutes} minute(s)"), Some(format!("You have been muted for {minutes} minute(s)")))
+        }
+        ("mute", None) => {
+            moderation.muted.insert(name.clone(), None);
+            (format!("Muted {name}"), Some("You have been muted".to_owned()))
+        }
+        _ => match moderation.muted.remove(&name) {
+            Some(_) => (format!("Unmuted {name}"), Some("You are no longer muted".to_owned())),
+            None => (format!("{name} wasn't muted"), None),
+        },
+    };
+    println!("{reply}");
+
+    // Let them know, if they're online
+    let player_id = res.main_world.query_mut::<(&Username, &PlayerId)>()
+        .into_iter()
+        .find(|(_, (username, _))| username.0 == name)
+        .map(|(_, (_, &player_id))| player_id);
+    if let (Some(player_id), Some(notice)) = (player_id, notice) {
+        res.net.send_chat(player_id, notice.into());
+    }
+    true
+}
@@ -1,22 +1,26 @@
-use std::{collections::BinaryHeap, net::SocketAddr};
+use std::{collections::BinaryHeap, net::SocketAddr, time::{Duration, Instant}};
 
 use bevy_utils::HashSet;
 use flexstr::SharedStr;
-use glam::Vec3;
+use glam::{Vec3, Vec2};
 use hecs::Entity;
-use shared::{protocol::{NetworkId, RawNetworkId}, bits_and_bytes::ByteWriter, jitter_prevention::JitterPrevention};
-use tokio::sync::mpsc::UnboundedSender;
+use shared::{dimension::DimensionId, protocol::{Features, NetworkId, RawNetworkId, s2c::{self, ChatFlags, MetadataKey, MetadataValue, TeleportFlags}}, bits_and_bytes::ByteWriter, jitter_prevention::JitterPrevention, movement::{self, MovementFlags}};
+use tokio::sync::{mpsc::UnboundedSender, oneshot};
 
 use anyhow::Result;
 
 use crate::{
-    components::{OldPosition, Position, HeadYawPitch, self, PlayerBundle, YawPitch, Username, PlayerId},
-    networking::{NetHandle, PlayersChanged, LoginResponse, client_connection::entity_state::{EntityStateMsg, EntityStateOut}, network_thread::PlayerStateMsg},
-    resources::Resources,
+    components::{OldPosition, Position, HeadYawPitch, self, PlayerBundle, YawPitch, PlayerId, Metadata, InDimension},
+    networking::{NetHandle, PlayersChanged, LoginResponse, client_connection::entity_state::{EntityStateMsg, EntityStateOut}, network_thread::{AuthorityMsg, PlayerStateMsg}},
+    resources::Resources, game_builder::{GameBuilder, Stage, self}, config::ServerConfig, shutdown, permissions, join_queue, dimensions,
 };
 
+// How long a player let in by `accept_login()` keeps their slot before being connected
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(30);
+
 struct Channels {
-    chat: Vec<Option<UnboundedSender<SharedStr>>>,
+    chat: Vec<Option<UnboundedSender<(ChatFlags, SharedStr)>>>,
+    kick: Vec<Option<oneshot::Sender<SharedStr>>>,
 }
 
 struct EntityStateTracker {
@@ -28,6 +32,10 @@ struct EntityStateTracker {
 
     last_player_input_tag: Option<u16>,
     packets_lost: u8,
+
+    // Sent to the player itself during the next NetOut, as given to `teleport()`, with the
+    // dimension and its seed if entering another one
+    pending_teleport: Option<(Vec3, YawPitch, TeleportFlags, Option<(DimensionId, u64)>)>,
 }
 
 // A main-thread controller for anything related to networking.
@@ -44,6 +52,12 @@ pub struct Network {
     entity_state_buf: Vec<(NetworkId, EntityStateMsg)>,
 
     removed_entities: Vec<(Entity, NetworkId)>,
+    // Of entities removed with `remove_entity()`, freed once the removals have been sent
+    freed_network_ids: Vec<NetworkId>,
+    // Moved with `teleport()` this tick
+    teleported_entities: HashSet<Entity>,
+    // Let in by `accept_login()` but not connected yet, and since when
+    pending_logins: Vec<(NetworkId, Instant)>,
 }
 
 impl Network {
@@ -51,6 +65,16 @@ impl Network {
         !self.handle.closed()
     }
 
+    // Connected players, and those let in who haven't finished connecting yet
+    pub fn player_count(&self) -> usize {
+        self.entity_trackers.iter().flatten().count() + self.pending_logins.len()
+    }
+
+    // Disconnects everybody; see `NetHandle::shutdown()`
+    pub fn shutdown(&mut self, timeout: Duration) {
+        self.handle.shutdown(timeout);
+    }
+
     pub fn track_entity_add(&mut self, new_entity: Entity, nid: NetworkId) -> anyhow::Result<()> {
         self.entity_mapping.add_mapping(nid, new_entity)
     }
@@ -61,9 +85,78 @@ impl Network {
         Ok(entity)
     }
 
+    // For entities other than players: stops tracking the entity and frees its id at the end
+    // of the tick, so that an entity added later this tick can't be sent out under the same id
+    // before the EntityRemoved. The caller despawns the entity.
+    pub fn remove_entity(&mut self, nid: NetworkId) -> anyhow::Result<Entity> {
+        let entity = self.track_entity_remove(nid)?;
+        self.freed_network_ids.push(nid);
+        Ok(entity)
+    }
+
+    pub fn allocate_network_id(&mut self) -> NetworkId {
+        NetworkId::from_raw(self.network_id_allocator.allocate() as RawNetworkId)
+    }
+
+    pub fn entity_of(&self, nid: NetworkId) -> Option<Entity> {
+        self.entity_mapping.get(nid)
+    }
+
+    // Whether `player` has been told about `entity` (and not told it's gone since)
+    pub fn is_tracking(&self, player: PlayerId, entity: Entity) -> bool {
+        self.entity_trackers.get(player.raw() as usize)
+            .and_then(Option::as_ref)
+            .map_or(false, |tracker| tracker.entities.contains(&entity))
+    }
+
+    // Forgets what `player` has been told about, after their client found it didn't match
+    // (see AuthorityMsg::Resync). Everything in range goes out again as newly added.
+    pub fn resync_entities(&mut self, player: PlayerId) {
+        if let Some(tracker) = self.entity_trackers.get_mut(player.raw() as usize).and_then(Option::as_mut) {
+            tracker.entities.clear();
+        }
+    }
+
+    // Chat messages as sent by the players, without the username
+    pub fn poll_chat(&mut self) -> Option<(NetworkId, SharedStr)> {
+        self.handle.channels.chat_recv.try_recv().ok()
+    }
+
+    // See `authority`
+    pub fn poll_authority(&mut self) -> Option<(NetworkId, AuthorityMsg)> {
+        self.handle.channels.authority_recv.try_recv().ok()
+    }
+
+    // Closes the player's connection, showing them the reason. The player is removed once
+    // the disconnect comes back from the network thread, as usual. Returns false if the
+    // player was already kicked.
+    pub fn kick(&mut self, player: PlayerId, reason: SharedStr) -> bool {
+        let Some(channel) = self.channels.kick.get_mut(player.raw() as usize).and_then(Option::take) else {
+            return false;
+        };
+        let _ = channel.send(reason); // Err if already disconnecting
+        true
+    }
+
+    pub fn send_chat(&mut self, player: PlayerId, message: SharedStr) {
+        self.send_chat_with_flags(player, ChatFlags::NONE, message);
+    }
+
+    pub fn send_chat_with_flags(&mut self, player: PlayerId, flags: ChatFlags, message: SharedStr) {
+        if let Some(Some(channel)) = self.channels.chat.get(player.raw() as usize) {
+            if let Err(e) = channel.send((flags, message)) {
+                eprintln!("Failed to send chat message: {e}");
+            }
+        }
+    }
+
     pub fn broadcast_chat(&mut self, message: SharedStr) {
+        self.broadcast_chat_with_flags(ChatFlags::NONE, message);
+    }
+
+    pub fn broadcast_chat_with_flags(&mut self, flags: ChatFlags, message: SharedStr) {
         for channel in self.channels.chat.iter_mut().flatten() {
-            if let Err(e) = channel.send(message.clone()) {
+            if let Err(e) = channel.send((flags, message.clone())) {
                 eprintln!("Failed to send chat message: {e}");
             }
         }
@@ -71,27 +164,84 @@ impl Network {
 }
 
 
-pub fn tick(res: &mut Resources) -> anyhow::Result<()> {
-    // Process any incoming login attempts and add new players to the server
-    poll_joins(res)?;
-    // Broadcast recent chat messages to everybody
-    broadcast_chat_messages(res);
-    // Process received player state messages (position, facing)
-    // Should be before `update_entity_trackers` to immediately send back
-    // the tag of the most recently processed input
-    process_player_state(res);    
-    // For each player: 
-    // - detect entities the player can now see that it previously couldn't and send spawn message,
-    // - detect entities the player can no longer see, send despawn message
-    // - send entity data update message for each currently visible entity
-    update_entity_trackers(res);
+pub fn plugin(builder: &mut GameBuilder) {
+    builder
+        // Process any incoming login attempts and add new players to the server
+        .add_system(Stage::NetIn, poll_joins)
+        // Process received player state messages (position, facing)
+        // Should be before `update_entity_trackers` to immediately send back
+        // the tag of the most recently processed input
+        .add_system(Stage::NetIn, process_player_state)
+        // For each player: 
+        // - detect entities the player can now see that it previously couldn't and send spawn message,
+        // - detect entities the player can no longer see, send despawn message
+        // - send entity data update message for each currently visible entity
+        .add_system(Stage::NetOut, update_entity_trackers)
+        .add_system(Stage::PostTick, clear_removed_entities);
+}
 
+fn clear_removed_entities(res: &mut Resources) -> anyhow::Result<()> {
     res.net.removed_entities.clear();
+    for nid in res.net.freed_network_ids.drain(..) {
+        res.net.network_id_allocator.free(nid.raw() as u16);
+    }
+    res.net.teleported_entities.clear();
+    Ok(())
+}
+
+// Moves an entity without interpolation. Relative components in `flags` are offsets from
+// the current position/rotation. Players get their client-side prediction reset; inputs
+// the server hasn't received yet still apply on top of the new position.
+pub fn teleport(res: &mut Resources, entity: Entity, pos: Vec3, yaw_pitch: YawPitch, flags: TeleportFlags) -> Result<()> {
+    let (Position(position), OldPosition(old_position), head_rotation, player_id) = res.main_world
+        .query_one_mut::<(&mut Position, &mut OldPosition, &mut HeadYawPitch, Option<&PlayerId>)>(entity)?;
+
+    let relative = |flag, current: f32, value: f32| if flags.contains(flag) { current + value } else { value };
+    *position = Vec3::new(
+        relative(TeleportFlags::RELATIVE_X, position.x, pos.x),
+        relative(TeleportFlags::RELATIVE_Y, position.y, pos.y),
+        relative(TeleportFlags::RELATIVE_Z, position.z, pos.z),
+    );
+    *old_position = *position;
+    head_rotation.value = Vec2::new(
+        relative(TeleportFlags::RELATIVE_YAW, head_rotation.value.x, yaw_pitch.x),
+        relative(TeleportFlags::RELATIVE_PITCH, head_rotation.value.y, yaw_pitch.y),
+    );
+    head_rotation.delta = YawPitch::ZERO;
+
+    if let Some(tracker) = player_id.and_then(|id| res.net.entity_trackers[id.raw() as usize].as_mut()) {
+        // Entering another dimension earlier this tick still has to reach the client
+        let dimension = tracker.pending_teleport.and_then(|(.., dimension)| dimension);
+        tracker.pending_teleport = Some((pos, yaw_pitch, flags, dimension));
+    }
+    res.net.teleported_entities.insert(entity);
+    Ok(())
+}
 
+// Moves the entity to `pos` in another dimension, keeping its rotation. The player's client
+// drops everything of the old dimension, so their tracker starts over; other players stop
+// tracking them during the next `update_entity_trackers`.
+pub fn teleport_to_dimension(res: &mut Resources, entity: Entity, dimension: DimensionId, pos: Vec3) -> Result<()> {
+    teleport(res, entity, pos, YawPitch::ZERO, TeleportFlags::RELATIVE_ROTATION)?;
+    // The entity exists, or teleport() would have failed
+    if dimension == DimensionId::OVERWORLD {
+        let _ = res.main_world.remove_one::<InDimension>(entity);
+    } else {
+        let _ = res.main_world.insert_one(entity, InDimension(dimension));
+    }
+
+    let seed = dimensions::seed(res, dimension);
+    let player_id = res.main_world.get::<&PlayerId>(entity).ok().map(|id| *id);
+    if let Some(tracker) = player_id.and_then(|id| res.net.entity_trackers[id.raw() as usize].as_mut()) {
+        tracker.entities.clear();
+        if let Some(teleport) = &mut tracker.pending_teleport {
+            teleport.3 = Some((dimension, seed));
+        }
+    }
     Ok(())
 }
 
-fn process_player_state(res: &mut Resources) {
+fn process_player_state(res: &mut Resources) -> anyhow::Result<()> {
     let net = &mut res.net;
     let handle = &mut net.handle;
     while let Ok(entry) = handle.channels.player_state_recv.try_recv() {
@@ -104,8 +254,8 @@ fn process_player_state(res: &mut Resources) {
         }
     }
 
-    for (_, (id, Position(position), head_rotation)) 
-        in res.main_world.query_mut::<(&PlayerId, &mut Position, &mut HeadYawPitch)>() {
+    for (_, (id, Position(position), head_rotation, metadata)) 
+        in res.main_world.query_mut::<(&PlayerId, &mut Position, &mut HeadYawPitch, &mut Metadata)>() {
 
         let Some(tracker) = net.entity_trackers[id.raw() as usize].as_mut() else {
             continue;
@@ -118,18 +268,21 @@ fn process_player_state(res: &mut Resources) {
         tracker.last_player_input_tag = Some(msg.tag);
         tracker.packets_lost = tracker.packets_lost.wrapping_add(packet_loss as u8);
 
-        if let Some(delta) = msg.delta_pos {
-            *position += delta;
-        }
+        // Too fast: ignored, and the client gets corrected by the next InputValidated
+        movement::apply_input(position, &msg);
 
         if let Some(delta) = msg.delta_yaw_pitch {
             head_rotation.value += delta;
             head_rotation.delta += delta;
         }
+
+        metadata.set(MetadataKey::Crouching, MetadataValue::Bool(msg.movement.contains(MovementFlags::SNEAKING)));
+        metadata.set(MetadataKey::Sprinting, MetadataValue::Bool(msg.movement.contains(MovementFlags::SPRINTING)));
     }
+    Ok(())
 }
 
-fn update_entity_trackers(res: &mut Resources) {
+fn update_entity_trackers(res: &mut Resources) -> anyhow::Result<()> {
     const ADD_THRESHOLD_SQ : f32 = 144.0 * 144.0;
     const REMOVE_THRESHOLD_SQ : f32 = 160.0 * 160.0;
 
@@ -137,32 +290,74 @@ fn update_entity_trackers(res: &mut Resources) {
     // when an entity crosses a chunk boundary, after which it is enough to iterate over only seen entities.
     // At that point, consider replacing HashSet with a dense tree structure (such as binary heap modified to
     // remove duplicates)
+    // Block changes are sent to everybody in the dimension, as there's no interest management
+    // for chunks yet. Split up so that no message grows past what its length prefix can express.
+    const MAX_BLOCK_CHANGES_PER_MESSAGE: usize = 1024;
+    // In ticks. Plenty of samples for the client's clock sync, at a few bytes each.
+    const CLOCK_SYNC_INTERVAL: u32 = 8;
+    // In ticks. Only catches clients that went out of sync, so it can be rare.
+    const ENTITY_CHECKSUM_INTERVAL: u32 = 64;
+    let mut changes_by_dimension = vec![(DimensionId::OVERWORLD, res.blocks.changes())];
+    changes_by_dimension.extend(res.dimensions.others().map(|(dimension, blocks)| (dimension, blocks.changes())));
+
     let buf = &mut res.net.entity_state_buf;
     
     for tracker in res.net.entity_trackers.iter_mut().flatten() {
         let player_pos = res.main_world.get::<&Position>(tracker.player_entity).unwrap().0;
+        let dimension_of = |in_dimension: Option<&InDimension>| in_dimension.map_or(DimensionId::OVERWORLD, |d| d.0);
+        let player_dimension = dimension_of(res.main_world.get::<&InDimension>(tracker.player_entity).ok().as_deref());
+
+        let block_changes = changes_by_dimension.iter()
+            .find(|(dimension, _)| *dimension == player_dimension)
+            .map_or(&[][..], |(_, changes)| &changes[..]);
+        let mut block_changes = block_changes.chunks(MAX_BLOCK_CHANGES_PER_MESSAGE);
+        let first_block_changes = block_changes.next();
         
         buf.clear();
-        for (entity, (&Position(position), &OldPosition(old_position), &id, &head_rotation)) 
-            in res.main_world.query_mut::<(&Position, &OldPosition, &NetworkId, &HeadYawPitch)>() {
-            let d = player_pos.distance_squared(position);
+        // First, so that a client entering another dimension drops the entities of the old
+        // one before hearing of those in the new one
+        if let Some((pos, yaw_pitch, flags, dimension)) = tracker.pending_teleport.take() {
+            buf.push((NetworkId::INVALID, EntityStateMsg::Teleport { pos, yaw_pitch, flags, dimension }));
+        }
+        for (entity, (&Position(position), &OldPosition(old_position), &id, &head_rotation, metadata, in_dimension)) 
+            in res.main_world.query_mut::<(&Position, &OldPosition, &NetworkId, &HeadYawPitch, &Metadata, Option<&InDimension>)>() {
+            // Those in other dimensions are as good as infinitely far away
+            let d = match dimension_of(in_dimension) == player_dimension {
+                true => player_pos.distance_squared(position),
+                false => f32::INFINITY,
+            };
             if d < ADD_THRESHOLD_SQ && tracker.entities.insert(entity) {
                 // Newly tracked, send spawn packet
                 buf.push((id, EntityStateMsg::EntityAdded {
                     position, 
                     head_rotation: head_rotation.value 
                 }));
+                for (key, value) in metadata.entries() {
+                    buf.push((id, EntityStateMsg::MetadataChanged { key: *key, value: value.clone() }));
+                }
                 println!("Adding entity {entity:?} to player {:?}'s tracker (d={d})", tracker.player_entity);
             } 
             else if d > REMOVE_THRESHOLD_SQ && tracker.entities.remove(&entity) {
                 buf.push((id, EntityStateMsg::EntityRemoved));
                 println!("Removing entity {entity:?} from player {:?}'s tracker (d={d})", tracker.player_entity);
             } 
+            else if res.net.teleported_entities.contains(&entity) && tracker.entities.contains(&entity) {
+                buf.push((id, EntityStateMsg::EntityTeleported {
+                    position,
+                    head_rotation: head_rotation.value
+                }));
+                for (key, value) in metadata.changed_entries() {
+                    buf.push((id, EntityStateMsg::MetadataChanged { key: *key, value: value.clone() }));
+                }
+            }
             else if tracker.entities.contains(&entity) {
                 buf.push((id, EntityStateMsg::EntityMoved { 
                     delta_pos: position - old_position, 
                     delta_head_rotation: head_rotation.delta 
                 }));
+                for (key, value) in metadata.changed_entries() {
+                    buf.push((id, EntityStateMsg::MetadataChanged { key: *key, value: value.clone() }));
+                }
             }
         }
 
@@ -172,11 +367,26 @@ fn update_entity_trackers(res: &mut Resources) {
             }
         }
 
+        if let Some(changes) = first_block_changes {
+            buf.push((NetworkId::INVALID, EntityStateMsg::BlocksChanged { changes: changes.to_vec() }));
+        }
+        if res.current_tick % CLOCK_SYNC_INTERVAL == 0 {
+            buf.push((NetworkId::INVALID, EntityStateMsg::ServerTick { tick: res.current_tick }));
+        }
+        if res.current_tick % ENTITY_CHECKSUM_INTERVAL == 0 {
+            let ids = tracker.entities.iter()
+                .filter(|&&entity| entity != tracker.player_entity)
+                .filter_map(|&entity| res.main_world.get::<&NetworkId>(entity).ok().map(|id| *id));
+            let (count, checksum) = s2c::entity_checksum(ids);
+            buf.push((NetworkId::INVALID, EntityStateMsg::EntityChecksum { count, checksum }));
+        }
+
+        let player_head_rot = res.main_world.get::<&HeadYawPitch>(tracker.player_entity).unwrap().value;
         let msg = EntityStateOut {
             player_input_tag: tracker.last_player_input_tag,
             packets_lost: tracker.packets_lost,
             player_pos,
-            player_head_rot: res.main_world.get::<&HeadYawPitch>(tracker.player_entity).unwrap().value,
+            player_head_rot,
             changes: buf.clone(), // Does not allocate if empty
         };
         
@@ -184,78 +394,147 @@ fn update_entity_trackers(res: &mut Resources) {
             eprintln!("Failed to send entity state");
         }
 
+        for changes in block_changes.clone() {
+            let msg = EntityStateOut {
+                player_input_tag: None,
+                packets_lost: 0,
+                player_pos,
+                player_head_rot,
+                changes: vec![(NetworkId::INVALID, EntityStateMsg::BlocksChanged { changes: changes.to_vec() })],
+            };
+            if tracker.entity_state_channel.send(msg).is_err() {
+                eprintln!("Failed to send entity state");
+            }
+        }
+
         tracker.last_player_input_tag = None;
         tracker.packets_lost = 0;
     }
+    Ok(())
 }
 
-fn broadcast_chat_messages(res: &mut Resources) {
-    while let Ok((_, message)) = res.net.handle.channels.chat_recv.try_recv() {
-        res.net.broadcast_chat(message);
+// Lets the player in. The network thread finishes the login, after which they come back
+// as `PlayersChanged::Connected`.
+pub fn accept_login(res: &mut Resources, channel: UnboundedSender<(NetworkId, LoginResponse)>) {
+    // Everyone starts out in the overworld
+    let dimension = DimensionId::OVERWORLD;
+    let seed = dimensions::seed(res, dimension);
+    let features = match res.extra.get::<ServerConfig>().map_or(true, |config| config.compression) {
+        true => Features::COMPRESSION.union(Features::CLOCK_SYNC).union(Features::AUTHORITY).union(Features::ENTITY_CHECKSUM).union(Features::BLOCK_BREAKING).union(Features::BLOCK_PLACING),
+        false => Features::CLOCK_SYNC.union(Features::AUTHORITY).union(Features::ENTITY_CHECKSUM).union(Features::BLOCK_BREAKING).union(Features::BLOCK_PLACING),
+    };
+    let net = &mut res.net;
+    let id = NetworkId::from_raw(net.network_id_allocator.allocate() as RawNetworkId);
+
+    let mut response_buf = [0u8; 128];
+    let mut writer = ByteWriter::new_for_message(&mut response_buf);
+    s2c::LoginStatus::Accepted(s2c::LoginResponse {
+        nid: id,
+        position: Vec3::ZERO,
+        head_rotation: Vec2::ZERO,
+        dimension,
+        seed,
+        features,
+    }).write(&mut writer);
+    writer.write_message_len();
+
+    if channel.send((id, LoginResponse::Success(writer.bytes().into(), features))).is_err() {
+        eprintln!("Failed to send network id to network thread!");
+        net.network_id_allocator.free(id.raw());
+        return;
     }
+    net.pending_logins.push((id, res.time.now));
 }
 
 fn poll_joins(res: &mut Resources) -> anyhow::Result<()> {
-    let net = &mut res.net;
-    while let Some(evt) = net.handle.poll_joins() {
+    // Whoever took this long has given up, or failed to connect, and no longer counts toward
+    // the player limit. The id comes back with the Disconnect once the network thread notices.
+    let now = res.time.now;
+    res.net.pending_logins.retain(|&(_, since)| now - since < LOGIN_TIMEOUT);
+
+    let shutting_down = shutdown::in_progress(res);
+    while let Some(evt) = res.net.handle.poll_joins() {
+        let denied = match &evt {
+            PlayersChanged::LoginRequest { .. } if shutting_down => Some(SharedStr::from("Server is shutting down")),
+            PlayersChanged::LoginRequest { username, key, .. } => permissions::ban_message(res, username)
+                .or_else(|| permissions::whitelist_message(res, username))
+                .or_else(|| permissions::identity_message(res, username, *key)),
+            _ => None,
+        };
+        let net = &mut res.net;
         match evt {
-            PlayersChanged::LoginRequest { channel, username: _ } => {
-                let id = NetworkId::from_raw(net.network_id_allocator.allocate() as RawNetworkId);
-
-                let mut response_buf = [0u8; 128];
-                let mut writer = ByteWriter::new_for_message(&mut response_buf);
-                writer.write_u16(id.raw() as u16);
-                writer.write_f32(0.0); // X
-                writer.write_f32(0.0); // Y
-                writer.write_f32(0.0); // Z
-                writer.write_f32(0.0); // Yaw
-                writer.write_f32(0.0); // Pitch
-                writer.write_u64(0); // World seed
-                writer.write_message_len();
-
-                if channel.send((id, LoginResponse::Success(writer.bytes().into()))).is_err() {
-                    eprintln!("Failed to send network id to network thread!");
+            PlayersChanged::LoginRequest { channel, username, .. } => {
+                if let Some(reason) = denied {
+                    println!("Denied login from {username}: {reason}");
+                    if channel.send((NetworkId::INVALID, LoginResponse::Denied(reason))).is_err() {
+                        eprintln!("Failed to send login response to network thread!");
+                    }
+                    continue;
+                }
+                if join_queue::must_wait(res) {
+                    join_queue::enqueue(res, channel, username);
+                    continue;
                 }
+                accept_login(res, channel);
             }
             PlayersChanged::Connected {
                 username,
+                skin,
                 network_id,
                 channels,
+                key,
             } => {
                 println!("Player login finished! Username: {username}, network id: {network_id}");
-
-                net.broadcast_chat(format!("{username} joined").into());
+                net.pending_logins.retain(|&(nid, _)| nid != network_id);
+                // Someone else joining under the same name got in first. The Disconnect that
+                // follows the kick frees the id.
+                if let Some(reason) = permissions::claim_identity(res, &username, key) {
+                    println!("Kicked {username} right after joining: {reason}");
+                    let _ = channels.kick.send(reason);
+                    continue;
+                }
+                let net = &mut res.net;
 
                 let player_id = PlayerId::from_raw(net.player_id_allocator.allocate() as _);
                 let entity = components::spawn_player(&mut res.main_world, PlayerBundle {
                     nid: network_id,
                     player_id,
                     username,
+                    skin,
                     position: Vec3::ZERO,
                     head_rotation: YawPitch::ZERO,
                 });
                 net.track_entity_add(entity, network_id)?;
                 place_at(&mut net.channels.chat, player_id.raw() as usize, Some(channels.chat_send));
+                place_at(&mut net.channels.kick, player_id.raw() as usize, Some(channels.kick));
                 place_at(&mut net.entity_trackers, player_id.raw() as usize, Some(EntityStateTracker {
                     player_entity: entity,
                     entities: HashSet::new(),
                     entity_state_channel: channels.entity_state,
                     input_queue: JitterPrevention::new(),
                     last_player_input_tag: None,
-                    packets_lost: 0
+                    packets_lost: 0,
+                    pending_teleport: None,
                 }));
+
+                game_builder::dispatch_player_join(res, entity);
             }
             PlayersChanged::Disconnect { network_id } => {
-                let entity = net.track_entity_remove(network_id)?;
                 net.network_id_allocator.free(network_id.raw() as u16);
+                // Never spawned: gone before Connected, or kicked there
+                if net.entity_mapping.get(network_id).is_none() {
+                    net.pending_logins.retain(|&(nid, _)| nid != network_id);
+                    continue;
+                }
+                let entity = net.track_entity_remove(network_id)?;
                 println!("Player with network id {network_id} disconnected");
 
-                let player_id = *res.main_world.get::<&PlayerId>(entity).unwrap();
-                let username = &res.main_world.remove_one::<Username>(entity).unwrap().0;
-
-                net.broadcast_chat(format!("{username} disconnected").into());
+                game_builder::dispatch_player_leave(res, entity);
 
+                let net = &mut res.net;
+                let player_id = *res.main_world.get::<&PlayerId>(entity).unwrap();
                 place_at(&mut net.channels.chat, player_id.raw() as usize, None);
+                place_at(&mut net.channels.kick, player_id.raw() as usize, None);
                 place_at(&mut net.entity_trackers, player_id.raw() as usize, None);
                 if res.main_world.despawn(entity).is_err() {
                     eprintln!("disconnect: entity was already despawned");
@@ -312,7 +591,7 @@ impl NidEntityMapping {
     }
 
     pub fn get(&self, id: NetworkId) -> Option<Entity> {
-        let (mapped_id, entity) = self.mapping[id.raw() as usize];
+        let (mapped_id, entity) = *self.mapping.get(id.raw() as usize)?;
         if mapped_id != id {
             None
         } else {
@@ -371,21 +650,63 @@ impl IdAllocator {
 
 #[derive(Debug)]
 pub struct PlayerChannels {
-    pub chat_send: UnboundedSender<SharedStr>,
+    pub chat_send: UnboundedSender<(ChatFlags, SharedStr)>,
     pub entity_state: UnboundedSender<EntityStateOut>,
+    pub kick: oneshot::Sender<SharedStr>,
 }
 
 pub fn init(address: SocketAddr) -> Result<Network> {
-    Ok(Network {
-        handle: crate::networking::init(address)?,
+    Ok(with_handle(crate::networking::init(address)?))
+}
+
+pub fn with_handle(handle: NetHandle) -> Network {
+    Network {
+        handle,
         entity_mapping: NidEntityMapping::with_capacity(128),
         network_id_allocator: IdAllocator::with_capacity(128),
         player_id_allocator: IdAllocator::with_capacity(8),
         channels: Channels {
-            chat: vec![None]
+            chat: vec![None],
+            kick: vec![None],
         },
         entity_trackers: vec![None],
         entity_state_buf: Vec::new(),
         removed_entities: Vec::new(),
-    })
+        freed_network_ids: Vec::new(),
+        teleported_entities: HashSet::new(),
+        pending_logins: Vec::new(),
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_entity_checksum_and_resync() {
+        use shared::protocol::{c2s::AuthorityMsg, s2c};
+        use crate::{networking::client_connection::entity_state::EntityStateMsg, testing::TestServer};
+
+        let mut server = TestServer::new();
+        let alice = server.connect("alice");
+        let bob = server.connect("bob");
+        let bob_id = server.client(bob).network_id;
+
+        // Of everything Alice knows about but herself
+        server.run_ticks(64);
+        let checksums = server.received_entity_states(alice).into_iter().flat_map(|state| state.changes)
+            .filter_map(|(_, msg)| match msg {
+                EntityStateMsg::EntityChecksum { count, checksum } => Some((count, checksum)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(checksums.last(), Some(&s2c::entity_checksum([bob_id])));
+
+        // Bob comes again as if new, without being removed first
+        server.send_authority(alice, AuthorityMsg::Resync);
+        server.tick();
+        let changes = server.received_entity_states(alice).into_iter().flat_map(|state| state.changes).collect::<Vec<_>>();
+        assert!(changes.iter().any(|(id, msg)| *id == bob_id && matches!(msg, EntityStateMsg::EntityAdded { .. })));
+        assert!(!changes.iter().any(|(_, msg)| matches!(msg, EntityStateMsg::EntityRemoved)));
+        assert!(server.is_tracking(alice, bob));
+        assert!(server.received_entity_states(bob).into_iter().flat_map(|state| state.changes)
+            .all(|(_, msg)| !matches!(msg, EntityStateMsg::EntityAdded { .. })));
+    }
 }
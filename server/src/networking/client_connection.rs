@@ -4,7 +4,9 @@ use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
 use anyhow::Result;
 
-pub async fn receive_bytes<'a>(stream: &mut RecvStream, buf: &'a mut Vec<u8>, max_length: usize) -> anyhow::Result<ByteReader<'a>> {
+use super::stats::{ChannelStats, NET_STATS};
+
+pub async fn receive_bytes<'a>(stream: &mut RecvStream, buf: &'a mut Vec<u8>, max_length: usize, stats: &ChannelStats) -> anyhow::Result<ByteReader<'a>> {
     let mut header = [0u8; 2];
     stream.read_exact(&mut header[0..2]).await?;
 
@@ -31,28 +33,38 @@ pub async fn receive_bytes<'a>(stream: &mut RecvStream, buf: &'a mut Vec<u8>, ma
     };
 
     stream.read_exact(slice).await?;
+    stats.add_in(header.len() + slice.len());
     Ok(ByteReader::new(&mut buf[..]))
 }
 
 pub(super) mod chat {
     use flexstr::SharedStr;
-    use shared::{protocol::NetworkId, bits_and_bytes::ByteWriter};
+    use shared::{protocol::{batching::{Batcher, Unbatcher}, Features, NetworkId, s2c::{self, ChatFlags}}, bits_and_bytes::ByteWriter};
 
     use super::*;
 
+    // Of the messages coalesced into one write, before compression
+    const MAX_BATCH_LEN: usize = 4096;
+
     pub async fn recv_driver(
         mut incoming: RecvStream,
-        username: SharedStr,
         id: NetworkId,
+        features: Features,
         to_server: UnboundedSender<(NetworkId, SharedStr)>,
     ) -> Result<()> {
         //println!("chat::recv_driver ready");
 
         let mut buf = Vec::new();
+        let mut unbatcher = Unbatcher::new(features, MAX_BATCH_LEN);
         loop {
-            let mut stream = receive_bytes(&mut incoming, &mut buf, 600).await?;
-            
-            let message = username.clone() + ": " + stream.read_str(stream.bytes_remaining());
+            let bytes = if unbatcher.has_batched() {
+                unbatcher.next_in_batch()?
+            } else {
+                receive_bytes(&mut incoming, &mut buf, 600, &NET_STATS.chat).await?;
+                unbatcher.unpack(&buf)?
+            };
+
+            let message = SharedStr::from(std::str::from_utf8(bytes)?);
             //println!("Received '{}' (length {})", message, message.len());
             let _ = to_server.send((id, message));
         }
@@ -60,27 +72,42 @@ pub(super) mod chat {
 
     pub async fn send_driver(
         mut outgoing: SendStream,
-        mut messages: UnboundedReceiver<SharedStr>,
+        features: Features,
+        mut messages: UnboundedReceiver<(ChatFlags, SharedStr)>,
     ) -> Result<()> {
         //println!("chat::send_driver ready");
         let mut buf = [0u8; 512];
-        while let Some(message) = messages.recv().await {
-            debug_assert!(message.len() < buf.len(), "chat::send_driver: message too long! ({}/{} bytes)", message.len(), buf.len());
-
-            let mut writer = ByteWriter::new_for_message(&mut buf);
-            writer.write(message.as_bytes());
-            writer.write_message_len();
+        let mut batcher = Batcher::new(features);
+        while let Some(mut next) = messages.recv().await {
+            // Along with whatever else is already queued, such as the chat history on join
+            loop {
+                let (flags, message) = next;
+                debug_assert!(message.len() < buf.len() - 1, "chat::send_driver: message too long! ({}/{} bytes)", message.len(), buf.len());
+
+                let mut writer = ByteWriter::new(&mut buf);
+                s2c::write_chat(&mut writer, flags, message.as_str());
+                batcher.push(writer.bytes());
+
+                if batcher.len() >= MAX_BATCH_LEN {
+                    break;
+                }
+                match messages.try_recv() {
+                    Ok(message) => next = message,
+                    Err(_) => break,
+                }
+            }
 
-            outgoing.write_all(&writer.bytes()).await?;
+            let bytes = batcher.finish();
+            outgoing.write_all(bytes).await?;
+            NET_STATS.chat.add_out(bytes.len());
         }
         Ok(())
     }
 }
 
 pub(super) mod player_state {
-    use glam::{vec3, vec2};
     use quinn::Datagrams;
-    use shared::{protocol::{NetworkId, decode_angle_rad, decode_velocity}, bits_and_bytes::BitReader};
+    use shared::protocol::{NetworkId, c2s::read_player_state};
 
     use crate::networking::network_thread::PlayerStateMsg;
 
@@ -95,55 +122,19 @@ pub(super) mod player_state {
         let mut msg_buf = Vec::new();
         while let Some(datagram) = incoming.next().await {
             let buf = &(&datagram?)[..];
+            NET_STATS.player_state.add_in(buf.len());
             //receive_bytes(&mut incoming, &mut buf, 512).await?;   
             
-            let mut reader = BitReader::new(buf);
-            let mut tag = reader.uint(16) as u16;
+            msg_buf.clear();
+            let tag = read_player_state(buf, prev_tag, &mut msg_buf);
             //println!("Received {} bytes @ tag: {tag}", buf.len());
 
-            let latest_input = PlayerStateMsg {
-                tag,
-                delta_pos: reader.bool().then(|| vec3(
-                    decode_velocity(reader.uint(16)),
-                    decode_velocity(reader.uint(16)),
-                    decode_velocity(reader.uint(16)),
-                )),
-                delta_yaw_pitch: reader.bool().then(|| vec2(
-                    decode_angle_rad(reader.uint(16) as u16),
-                    decode_angle_rad(reader.uint(16) as u16),
-                )),
-            };
-            //println!("Got: {latest_input:?}");
-
             if tag == prev_tag {
                 continue;
             }
             let mut packets_lost = tag.wrapping_sub(prev_tag);
             prev_tag = tag;
 
-            msg_buf.clear();
-            msg_buf.push(latest_input);
-
-            let mut num_missing = packets_lost;
-            while num_missing > 1 && reader.bool() {
-                tag = tag.wrapping_sub(1);
-                num_missing -= 1;
-
-                msg_buf.push(PlayerStateMsg {
-                    tag,
-                    delta_pos: reader.bool().then(|| vec3(
-                        decode_velocity(reader.uint(16)),
-                        decode_velocity(reader.uint(16)),
-                        decode_velocity(reader.uint(16)),
-                    )),
-                    delta_yaw_pitch: reader.bool().then(|| vec2(
-                        decode_angle_rad(reader.uint(16) as u16),
-                        decode_angle_rad(reader.uint(16) as u16),
-                    )),
-                });
-                //println!("Recovered {tag}: {:?}", msg_buf.last().unwrap());
-            }
-
             for msg in msg_buf.drain(..).rev() {
                 let _ = to_server.send((id, packets_lost as u32-1, msg));
                 packets_lost = 1;
@@ -153,9 +144,28 @@ pub(super) mod player_state {
     }
 }
 
+pub(super) mod authority {
+    use shared::protocol::{c2s::AuthorityMsg, NetworkId};
+
+    use super::*;
+
+    pub async fn recv_driver(
+        mut incoming: RecvStream,
+        id: NetworkId,
+        to_server: UnboundedSender<(NetworkId, AuthorityMsg)>,
+    ) -> Result<()> {
+        let mut buf = Vec::new();
+        loop {
+            let mut reader = receive_bytes(&mut incoming, &mut buf, AuthorityMsg::MAX_LEN + 2, &NET_STATS.authority).await?;
+            let msg = AuthorityMsg::read(&mut reader)?;
+            let _ = to_server.send((id, msg));
+        }
+    }
+}
+
 pub mod entity_state {
-    use glam::Vec3;
-    use shared::{bits_and_bytes::ByteWriter, protocol::{encode_velocity, encode_angle_rad, wrap_angle}};
+    use glam::{IVec3, Vec3};
+    use shared::{bits_and_bytes::ByteWriter, dimension::DimensionId, protocol::{batching::{Batcher, MAX_FRAME_LEN}, s2c::{self, MetadataKey, MetadataValue, TeleportFlags}, Features}};
 
     use crate::components::{YawPitch, NetworkId};
 
@@ -169,7 +179,7 @@ pub mod entity_state {
         pub changes: Vec<(NetworkId, EntityStateMsg)>,
     }
 
-    #[derive(Clone, Copy)]
+    #[derive(Clone)]
     pub enum EntityStateMsg {
         EntityAdded {
             position: Vec3,
@@ -179,79 +189,135 @@ pub mod entity_state {
         EntityMoved {
             delta_pos: Vec3,
             delta_head_rotation: YawPitch,
-        }
+        },
+        MetadataChanged {
+            key: MetadataKey,
+            value: MetadataValue,
+        },
+        EntityTeleported {
+            position: Vec3,
+            head_rotation: YawPitch,
+        },
+        // Of the player itself; the id is ignored. With the dimension and its seed if
+        // entering another one.
+        Teleport {
+            pos: Vec3,
+            yaw_pitch: YawPitch,
+            flags: TeleportFlags,
+            dimension: Option<(DimensionId, u64)>,
+        },
+        // The id is ignored
+        BlocksChanged {
+            changes: Vec<(IVec3, u16)>,
+        },
+        // The id is ignored. Dropped for clients without Features::CLOCK_SYNC.
+        ServerTick {
+            tick: u32,
+        },
+        // The id is ignored. Dropped for clients without Features::ENTITY_CHECKSUM.
+        EntityChecksum {
+            count: u32,
+            checksum: u32,
+        },
     }
 
     pub async fn send_driver(
         mut outgoing: SendStream,
+        features: Features,
         mut messages: UnboundedReceiver<EntityStateOut>,
     ) -> Result<()> {
         //println!("entity_state::send_driver ready");
         let mut send_buf = vec![0u8; 3072];
         let mut prev_input_tag = u16::MAX; // Client has the same "uninitialized" tag
-        while let Some(msg) = messages.recv().await {
-            let EntityStateOut { 
-                player_input_tag, 
-                packets_lost,
-                player_pos, 
-                player_head_rot, 
-                changes 
-            } = msg;
-
-            let mut writer = ByteWriter::new_for_message(&mut send_buf);
-            if let Some(tag) = player_input_tag {
-                //println!("Out tag: {tag}");
-                if tag == prev_input_tag {
-                    panic!("Some(tag) = prev_tag");
+        let mut batcher = Batcher::new(features);
+        while let Some(mut msg) = messages.recv().await {
+            // Coalesced with the ticks queued up meanwhile, if the network thread fell behind
+            loop {
+                let EntityStateOut { 
+                    player_input_tag, 
+                    packets_lost,
+                    player_pos, 
+                    player_head_rot, 
+                    changes 
+                } = msg;
+
+                // Everything else fits in the default size
+                let block_bytes: usize = changes.iter().map(|(_, change)| match change {
+                    EntityStateMsg::BlocksChanged { changes } => s2c::blocks_changed_max_len(changes.len()),
+                    _ => 0,
+                }).sum();
+                if send_buf.len() < 3072 + block_bytes {
+                    send_buf.resize(3072 + block_bytes, 0);
                 }
 
-                writer.write_u16(tag);
-                writer.write_u8(packets_lost);
-                writer.write_f32(player_pos.x);
-                writer.write_f32(player_pos.y);
-                writer.write_f32(player_pos.z);
+                let mut writer = ByteWriter::new(&mut send_buf);
+                if let Some(tag) = player_input_tag {
+                    //println!("Out tag: {tag}");
+                    if tag == prev_input_tag {
+                        panic!("Some(tag) = prev_tag");
+                    }
+
+                    s2c::write_input_validated(&mut writer, tag, packets_lost, player_pos, player_head_rot);
+                    prev_input_tag = tag;
+                } else {
+                    s2c::write_input_tag(&mut writer, prev_input_tag);
+                }
+                let base_length = writer.bytes_written();
+
+                for (id, event) in changes {
+                    match event {
+                        EntityStateMsg::EntityAdded { position, head_rotation } => {
+                            s2c::write_entity_added(&mut writer, id, position, head_rotation);
+                        },
+                        EntityStateMsg::EntityRemoved => {
+                            s2c::write_entity_removed(&mut writer, id);
+                        },
+                        EntityStateMsg::EntityMoved { delta_pos, delta_head_rotation } => {
+                            s2c::write_entity_moved(&mut writer, id, delta_pos, delta_head_rotation);
+                        },
+                        EntityStateMsg::MetadataChanged { key, value } => {
+                            s2c::write_entity_metadata(&mut writer, id, key, &value);
+                        },
+                        EntityStateMsg::EntityTeleported { position, head_rotation } => {
+                            s2c::write_entity_teleported(&mut writer, id, position, head_rotation);
+                        },
+                        EntityStateMsg::Teleport { pos, yaw_pitch, flags, dimension } => {
+                            s2c::write_teleport(&mut writer, pos, yaw_pitch.x, yaw_pitch.y, flags, dimension);
+                        },
+                        EntityStateMsg::BlocksChanged { changes } => {
+                            s2c::write_blocks_changed(&mut writer, &changes);
+                        },
+                        EntityStateMsg::ServerTick { tick } => {
+                            if features.contains(Features::CLOCK_SYNC) {
+                                s2c::write_server_tick(&mut writer, tick);
+                            }
+                        },
+                        EntityStateMsg::EntityChecksum { count, checksum } => {
+                            if features.contains(Features::ENTITY_CHECKSUM) {
+                                s2c::write_entity_checksum(&mut writer, count, checksum);
+                            }
+                        },
+                    }
+                }
+                if writer.bytes_written() > base_length {
+                    batcher.push(writer.bytes());
+                }
 
-                writer.write_f32(player_head_rot.x);
-                writer.write_f32(player_head_rot.y);
-                prev_input_tag = tag;
-            } else {
-                writer.write_u16(prev_input_tag);
-                // Client will know there is no associated data because this tag was previously processed
-            }
-            let base_length = writer.bytes_written();
-
-            for (id, event) in changes {
-                match event {
-                    EntityStateMsg::EntityAdded { position, head_rotation } => {
-                        // TODO, this way of writing the IDs
-                        // - consumes more bandwidth than necessary
-                        // - limits max entity count in the ENTIRE world to 2^(15-2)=8192
-                        writer.write_varint15((id.raw() << 2) | 0b00);
-                        writer.write_f32(position.x);
-                        writer.write_f32(position.y);
-                        writer.write_f32(position.z);
-                        writer.write_f32(head_rotation.x);
-                        writer.write_f32(head_rotation.y);
-                    },
-                    EntityStateMsg::EntityRemoved => {
-                        writer.write_varint15((id.raw() << 2) | 0b10);
-                    },
-                    EntityStateMsg::EntityMoved { delta_pos, delta_head_rotation } => {
-                        writer.write_varint15(((id.raw()) << 1) | 0b1);
-                        writer.write_u16(encode_velocity(delta_pos.x) as u16);
-                        writer.write_u16(encode_velocity(delta_pos.y) as u16);
-                        writer.write_u16(encode_velocity(delta_pos.z) as u16);
-                        writer.write_u16(encode_angle_rad(wrap_angle(delta_head_rotation.x)));
-                        writer.write_u16(encode_angle_rad(wrap_angle(delta_head_rotation.y)));
-                    },
+                if batcher.len() >= MAX_FRAME_LEN {
+                    break;
+                }
+                match messages.try_recv() {
+                    Ok(next) => msg = next,
+                    Err(_) => break,
                 }
             }
-            writer.write_message_len();
 
-            if writer.bytes_written() > base_length {
-                outgoing.write_all(writer.bytes()).await?;
+            if !batcher.is_empty() {
+                let bytes = batcher.finish();
+                outgoing.write_all(bytes).await?;
+                NET_STATS.entity_state.add_out(bytes.len());
             }
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
\ No newline at end of file
@@ -1,14 +1,14 @@
 use flexstr::{SharedStr, ToSharedStr};
 use quinn::{NewConnection, VarInt};
-use shared::{protocol::{NetworkId, PROTOCOL_MAGIC, PROTOCOL_VERSION}};
+use shared::{bits_and_bytes::ByteWriter, protocol::{Features, NetworkId, PROTOCOL_MAGIC, PROTOCOL_VERSION, CLOSE_INVALID_LOGIN, CLOSE_KICKED, CLOSE_LOGIN_DENIED, c2s::Hello, s2c::LoginStatus}};
 use tokio::{
     sync::{
-        mpsc::unbounded_channel, oneshot,
+        mpsc::{unbounded_channel, UnboundedSender}, oneshot,
     },
     task,
 };
 
-use crate::{networking::{client_connection::receive_bytes, LoginResponse}, net::PlayerChannels};
+use crate::{networking::{client_connection::receive_bytes, LoginResponse, stats::NET_STATS}, net::PlayerChannels};
 
 use super::{client_connection, PlayersChanged, network_thread::NetSideChannels};
 
@@ -20,57 +20,100 @@ pub(super) async fn login(
     let (mut hello_send, mut hello_recv) = connection.bi_streams.next().await.unwrap()?;
 
     let mut recv_buf = Vec::new();
-    let mut reader = receive_bytes(&mut hello_recv, &mut recv_buf, 32).await?;
+    let mut reader = receive_bytes(&mut hello_recv, &mut recv_buf, 49, &NET_STATS.login).await?;
     println!("Received login message! Length: {}", reader.bytes_remaining());
     
-    if reader.bytes_remaining() < 6 // magic + protocol ver + username length + username >= 6
-        || reader.read_u16() != PROTOCOL_MAGIC 
-        || reader.read_u16() != PROTOCOL_VERSION 
-    { 
-        connection.connection.close(VarInt::from_u32(1), b"Invalid login request");
-        anyhow::bail!("Invalid login request");
-    }
-    
-    let username_len = reader.read_u8() as usize;
-    let username = reader.read_str(username_len).to_shared_str();
+    let (username, skin, client_features, key) = match Hello::read(&mut reader) {
+        Ok(Hello { magic: PROTOCOL_MAGIC, version: PROTOCOL_VERSION, username, skin, features, key }) => (username.to_shared_str(), skin, features, key),
+        _ => {
+            connection.connection.close(VarInt::from_u32(CLOSE_INVALID_LOGIN), b"Invalid login request");
+            anyhow::bail!("Invalid login request");
+        }
+    };
     if username.len() < 3 {
-        connection.connection.close(VarInt::from_u32(2), b"Username too short");
+        connection.connection.close(VarInt::from_u32(CLOSE_LOGIN_DENIED), b"Username too short");
         anyhow::bail!("Username too short");
     }
 
     println!("Username: {username}. Generating network ID...");
 
-    let (id_send, id_recv) = oneshot::channel();
+    let (status_send, mut status_recv) = unbounded_channel();
     channels.player_join_send
-        .send(PlayersChanged::LoginRequest { channel: id_send, username: username.clone() })
+        .send(PlayersChanged::LoginRequest { channel: status_send, username: username.clone(), key })
         .unwrap();
-        
-    let (network_id, login_response) = id_recv.await?;
-    match login_response {
-        LoginResponse::Success(response_bytes) => hello_send.write_all(&response_bytes).await?,
-        LoginResponse::Denied(reason) => {
-            connection.connection.close(VarInt::from_u32(2), reason);
-            anyhow::bail!("Invalid login request");
-        },
-    }
+
+    // Failing to tell a queued client its place means it's gone, and dropping `status_recv`
+    // takes it out of the queue
+    let (features, id_guard) = loop {
+        let Some((network_id, login_response)) = status_recv.recv().await else {
+            anyhow::bail!("Login request dropped by the server");
+        };
+        match login_response {
+            LoginResponse::Success(response_bytes, server_features) => {
+                let id_guard = NetworkIdGuard { network_id, player_join_send: Some(channels.player_join_send.clone()) };
+                hello_send.write_all(&response_bytes).await?;
+                NET_STATS.login.add_out(response_bytes.len());
+                break (client_features.common(server_features), id_guard);
+            }
+            LoginResponse::Denied(reason) => {
+                connection.connection.close(VarInt::from_u32(CLOSE_LOGIN_DENIED), reason.as_bytes());
+                anyhow::bail!("Login denied: {reason}");
+            }
+            LoginResponse::Queued(position) => {
+                let mut buf = [0u8; 8];
+                let mut writer = ByteWriter::new_for_message(&mut buf);
+                LoginStatus::Queued { position }.write(&mut writer);
+                writer.write_message_len();
+                hello_send.write_all(writer.bytes()).await?;
+                NET_STATS.login.add_out(writer.bytes().len());
+            }
+        }
+    };
     hello_send.finish().await?;
 
     task::spawn(async move {
-        if let Err(e) = client_connection(connection, username, network_id, channels).await {
+        if let Err(e) = client_connection(connection, username, skin, key, id_guard, features, channels).await {
             println!("Error in client connection: {e}");
         }
     });
     Ok(())
 }
 
+// Hands the network id the main thread gave out back to it, with a Disconnect, if the client
+// goes away before `client_connection` reports it Connected. From then on, it sends the
+// Disconnect itself.
+struct NetworkIdGuard {
+    network_id: NetworkId,
+    player_join_send: Option<UnboundedSender<PlayersChanged>>, // None once Connected
+}
+
+impl NetworkIdGuard {
+    fn disarm(mut self) {
+        self.player_join_send = None;
+    }
+}
+
+impl Drop for NetworkIdGuard {
+    fn drop(&mut self) {
+        if let Some(player_join_send) = &self.player_join_send {
+            let _ = player_join_send.send(PlayersChanged::Disconnect { network_id: self.network_id });
+        }
+    }
+}
+
 async fn client_connection(
     mut connection: NewConnection,
     username: SharedStr,
-    network_id: NetworkId,
+    skin: u8,
+    key: [u8; 16],
+    id_guard: NetworkIdGuard,
+    features: Features,
     channels: NetSideChannels
 ) -> anyhow::Result<()> {
+    let network_id = id_guard.network_id;
     let (chat_send_main, chat_recv_self) = unbounded_channel(); // c -> s
     let (entity_state_send, entity_state_recv) = unbounded_channel(); // s -> c
+    let (kick_send, kick_recv) = oneshot::channel();
 
     let (chat_recv_driver, chat_send_driver) = {
         let (outgoing, mut incoming) = connection.bi_streams.next().await.unwrap()?;
@@ -80,18 +123,38 @@ async fn client_connection(
 
         let chat_recv_driver = task::spawn(client_connection::chat::recv_driver(
             incoming,
-            username.clone(),
             network_id,
+            features,
             channels.chat_send,
         ));
         let chat_send_driver = task::spawn(client_connection::chat::send_driver(
             outgoing,
+            features,
             chat_recv_self,
         ));
 
         (chat_recv_driver, chat_send_driver)
     };
 
+    // Opened by the client after the chat stream. Without the feature, this never returns.
+    let authority_recv_driver = {
+        let incoming = match features.contains(Features::AUTHORITY) {
+            true => {
+                let (_, mut incoming) = connection.bi_streams.next().await.unwrap()?;
+                incoming.read_exact(&mut [0u8]).await?;
+                Some(incoming)
+            }
+            false => None,
+        };
+        let authority_send = channels.authority_send;
+        task::spawn(async move {
+            match incoming {
+                Some(incoming) => client_connection::authority::recv_driver(incoming, network_id, authority_send).await,
+                None => std::future::pending().await,
+            }
+        })
+    };
+
     let player_state_recv_driver = {
 /*         let mut stream = connection.uni_streams.next().await.unwrap()?;
         stream.read_exact(&mut [0u8]).await?;
@@ -103,19 +166,23 @@ async fn client_connection(
         let mut stream = connection.connection.open_uni().await?;
         stream.write_all(&[0u8]).await?;
 
-        task::spawn(client_connection::entity_state::send_driver(stream, entity_state_recv))
+        task::spawn(client_connection::entity_state::send_driver(stream, features, entity_state_recv))
     };
 
     // Keep at the end so that Disconnect is definitely sent (no more early exits).
     // Disconnect must be sent to avoid leaking network ids
+    id_guard.disarm();
     channels.player_join_send
         .send(PlayersChanged::Connected {
             username: username.clone(),
+            skin,
             network_id,
             channels: PlayerChannels {
                 chat_send: chat_send_main,
                 entity_state: entity_state_send,
-            }
+                kick: kick_send,
+            },
+            key,
         })
         .unwrap();
 
@@ -124,7 +191,11 @@ async fn client_connection(
         _ = chat_recv_driver => {println!("chat::recv_driver returned")},
         _ = chat_send_driver => {println!("chat::send_driver returned")},
         _ = player_state_recv_driver => {println!("player_state::recv_driver returned")},
+        _ = authority_recv_driver => {println!("authority::recv_driver returned")},
         _ = entity_state_send_driver => {println!("entity_state::send_driver returned")},
+        Ok(reason) = kick_recv => {
+            connection.connection.close(VarInt::from_u32(CLOSE_KICKED), reason.as_bytes());
+        },
     );
 
     channels.player_join_send
@@ -1,35 +1,44 @@
-use std::{thread::JoinHandle, net::SocketAddr};
+use std::{thread::JoinHandle, net::SocketAddr, time::{Duration, Instant}};
 
 use anyhow::bail;
 use flexstr::SharedStr;
-use tokio::sync::{mpsc::{UnboundedReceiver, unbounded_channel}, oneshot};
+use shared::protocol::Features;
+use tokio::sync::{mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel}, oneshot};
 
 use anyhow::Result;
 
 use crate::{components::NetworkId, net::PlayerChannels};
 
-use self::network_thread::{NetSideChannels, PlayerStateMsg};
+use self::network_thread::{AuthorityMsg, NetSideChannels, PlayerStateMsg};
 
 pub mod network_thread;
 pub mod client_connection;
 pub mod login;
+pub mod stats;
 
 #[derive(Debug)]
 pub enum LoginResponse {
-    Success(Box<[u8]>),
-    Denied(&'static [u8])
+    // The response message, and the features the server offers in it
+    Success(Box<[u8]>, Features),
+    Denied(SharedStr),
+    // Waiting for the server to have room, at this place in the queue. Sent again as it
+    // changes, until one of the others.
+    Queued(u16),
 }
 
 #[derive(Debug)]
 pub enum PlayersChanged {
     LoginRequest {
-        channel: oneshot::Sender<(NetworkId, LoginResponse)>,
+        channel: UnboundedSender<(NetworkId, LoginResponse)>,
         username: SharedStr,
+        key: [u8; 16], // see c2s::Hello
     },
     Connected {
         username: SharedStr,
+        skin: u8,
         network_id: NetworkId,
         channels: PlayerChannels,
+        key: [u8; 16],
     },
     Disconnect {
         network_id: NetworkId
@@ -39,39 +48,84 @@ pub enum PlayersChanged {
 pub struct Channels {
     pub player_join: UnboundedReceiver<PlayersChanged>,
     pub chat_recv: UnboundedReceiver<(NetworkId, SharedStr)>,
-    pub player_state_recv: UnboundedReceiver<(NetworkId, u32, PlayerStateMsg)>
+    pub player_state_recv: UnboundedReceiver<(NetworkId, u32, PlayerStateMsg)>,
+    pub authority_recv: UnboundedReceiver<(NetworkId, AuthorityMsg)>,
 }
 
 pub struct NetHandle {
-    thread_handle: JoinHandle<()>,
+    thread_handle: Option<JoinHandle<()>>, // None with the loopback transport
+    stop: Option<oneshot::Sender<()>>,
     pub channels: Channels,
 }
 
 impl NetHandle {
     pub fn closed(&self) -> bool {
-        self.thread_handle.is_finished()
+        self.thread_handle.as_ref().map_or(false, |handle| handle.is_finished())
     }
 
     pub fn poll_joins(&mut self) -> Option<PlayersChanged> {
         self.channels.player_join.try_recv().ok()
     }
+
+    // Closes all connections, telling the clients the server closed, and waits up to
+    // `timeout` for the network thread to finish. If it doesn't, it is left behind.
+    pub fn shutdown(&mut self, timeout: Duration) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(()); // Err if it has already stopped
+        }
+        let Some(thread_handle) = self.thread_handle.take() else {
+            return;
+        };
+
+        let deadline = Instant::now() + timeout;
+        while !thread_handle.is_finished() {
+            if Instant::now() >= deadline {
+                eprintln!("Network thread did not stop within {timeout:?}, not waiting for it");
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        if thread_handle.join().is_err() {
+            eprintln!("Network thread panicked");
+        }
+    }
 }
 
-pub fn init(address: SocketAddr) -> Result<NetHandle> {
+fn make_channels() -> (Channels, NetSideChannels) {
     let (player_join_send, player_join_recv) = unbounded_channel();
     let (chat_send, chat_recv) = unbounded_channel();
     let (player_state_send, player_state_recv) = unbounded_channel();
+    let (authority_send, authority_recv) = unbounded_channel();
 
-
-    let channels = NetSideChannels {
+    let main_side = Channels {
+        player_join: player_join_recv,
+        chat_recv,
+        player_state_recv,
+        authority_recv,
+    };
+    let net_side = NetSideChannels {
         chat_send,
         player_join_send,
-        player_state_send
+        player_state_send,
+        authority_send,
     };
+    (main_side, net_side)
+}
+
+// No network thread: whoever holds the returned `NetSideChannels` stands in for it,
+// e.g. the fake clients in `testing`.
+pub fn loopback() -> (NetHandle, NetSideChannels) {
+    let (main_side, net_side) = make_channels();
+    (NetHandle { thread_handle: None, stop: None, channels: main_side }, net_side)
+}
+
+pub fn init(address: SocketAddr) -> Result<NetHandle> {
+    let (main_side, channels) = make_channels();
 
     let (tx, rx) = oneshot::channel();
+    let (stop_send, stop_recv) = oneshot::channel();
     let thread_handle = std::thread::spawn(move || {
-        network_thread::start(tx, channels, address);
+        network_thread::start(tx, channels, address, stop_recv);
     });
 
     // Don't start loading the server until networking is confirmed to be working
@@ -82,11 +136,8 @@ pub fn init(address: SocketAddr) -> Result<NetHandle> {
     }
 
     Ok(NetHandle {
-        thread_handle,
-        channels: Channels {
-            player_join: player_join_recv,
-            chat_recv,
-            player_state_recv
-        },
+        thread_handle: Some(thread_handle),
+        stop: Some(stop_send),
+        channels: main_side,
     })
 }
\ No newline at end of file
@@ -2,8 +2,8 @@ use std::net::SocketAddr;
 
 use anyhow::Result;
 use flexstr::SharedStr;
-use glam::{Vec3, Vec2};
-use quinn::Incoming;
+use quinn::{Endpoint, Incoming, VarInt};
+use shared::protocol::CLOSE_SERVER_CLOSED;
 use tokio::{
     sync::{
         mpsc::UnboundedSender,
@@ -16,27 +16,26 @@ use crate::{networking::login, components::NetworkId};
 
 use super::PlayersChanged;
 
-#[derive(Debug)]
-pub struct PlayerStateMsg {
-    pub tag: u16,
-    pub delta_pos: Option<Vec3>,
-    pub delta_yaw_pitch: Option<Vec2>,
-}
+pub use shared::protocol::c2s::PlayerInput as PlayerStateMsg;
+pub use shared::protocol::c2s::AuthorityMsg;
+
 #[derive(Clone)]
 pub struct NetSideChannels {
     pub chat_send: UnboundedSender<(NetworkId, SharedStr)>,
     pub player_join_send: UnboundedSender<PlayersChanged>,
-    pub player_state_send: UnboundedSender<(NetworkId, u32, PlayerStateMsg)>
+    pub player_state_send: UnboundedSender<(NetworkId, u32, PlayerStateMsg)>,
+    pub authority_send: UnboundedSender<(NetworkId, AuthorityMsg)>,
 }
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 3)]
 pub async fn start(
     tx: oneshot::Sender<bool>,
     channels: NetSideChannels,
-    address: SocketAddr
+    address: SocketAddr,
+    stop: oneshot::Receiver<()>,
 ) {
-    let incoming = match setup::make_server_endpoint(address) {
-        Ok(incoming) => incoming,
+    let (endpoint, incoming) = match setup::make_server_endpoint(address) {
+        Ok(tuple) => tuple,
         Err(e) => {
             println!("Failed to create server endpoint! Error: {}", e);
             tx.send(false).unwrap();
@@ -45,10 +44,21 @@ pub async fn start(
     };
     tx.send(true).unwrap(); // unwrap(): crashing is probably not a terrible solution on failure
 
-    poll_new_connections(incoming, channels).await;
+    tokio::select!(
+        _ = poll_new_connections(incoming, channels) => {}
+        _ = stop => close_connections(&endpoint).await,
+    );
     println!("Network thread terminating...");
 }
 
+// Clients recognize the code and show that the server closed, rather than that the
+// connection was lost
+async fn close_connections(endpoint: &Endpoint) {
+    println!("Closing all connections...");
+    endpoint.close(VarInt::from_u32(CLOSE_SERVER_CLOSED), b"Server closed");
+    endpoint.wait_idle().await;
+}
+
 async fn poll_new_connections(
     mut incoming: Incoming,
     channels: NetSideChannels
@@ -82,7 +92,7 @@ mod setup {
 
     use super::*;
 
-    pub fn make_server_endpoint(bind_addr: SocketAddr) -> Result<Incoming> {
+    pub fn make_server_endpoint(bind_addr: SocketAddr) -> Result<(Endpoint, Incoming)> {
         let (server_config, _) = configure_server()?;
         let (endpoint, incoming) = Endpoint::server(server_config, bind_addr)?;
 
@@ -90,7 +100,7 @@ mod setup {
             "Network thread listening for connections on {}",
             endpoint.local_addr()?
         );
-        Ok(incoming)
+        Ok((endpoint, incoming))
     }
 
     /// Returns default server configuration along with its certificate.
@@ -0,0 +1,61 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Bytes moved through each channel since launch, including message headers.
+// Written by the network thread, read by e.g. the metrics exporter.
+pub struct ChannelStats {
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+impl ChannelStats {
+    const fn new() -> Self {
+        Self {
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+        }
+    }
+
+    pub fn add_in(&self, bytes: usize) {
+        self.bytes_in.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_out(&self, bytes: usize) {
+        self.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_out(&self) -> u64 {
+        self.bytes_out.load(Ordering::Relaxed)
+    }
+}
+
+pub struct NetStats {
+    pub login: ChannelStats,
+    pub chat: ChannelStats,
+    pub player_state: ChannelStats,
+    pub entity_state: ChannelStats,
+    pub authority: ChannelStats,
+}
+
+impl NetStats {
+    pub fn channels(&self) -> [(&'static str, &ChannelStats); 5] {
+        [
+            ("login", &self.login),
+            ("chat", &self.chat),
+            ("player_state", &self.player_state),
+            ("entity_state", &self.entity_state),
+            ("authority", &self.authority),
+        ]
+    }
+}
+
+pub static NET_STATS: NetStats = NetStats {
+    login: ChannelStats::new(),
+    chat: ChannelStats::new(),
+    player_state: ChannelStats::new(),
+    entity_state: ChannelStats::new(),
+    authority: ChannelStats::new(),
+};
@@ -0,0 +1,476 @@
+// A* over the block grid for mobs. Searches run on a few worker threads: a request
+// copies the solidity of the blocks around the start and goal into a `SolidGrid`, so
+// workers never touch the world itself, and results are picked up at the start of
+// the next Update stage.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    f32::consts::PI,
+};
+
+use crossbeam_channel::{Receiver, Sender};
+use glam::{IVec3, Vec2, Vec3};
+use hecs::Entity;
+
+use crate::{
+    components::{HeadYawPitch, Mob, PlayerId, Position},
+    game_builder::{GameBuilder, Stage},
+    resources::Resources,
+    world::{BlockWorld, WORLD_HEIGHT},
+};
+
+// Horizontal distance beyond which paths aren't even attempted
+pub const MAX_DISTANCE: i32 = 48;
+// How far outside the start and goal's bounding box the search may wander
+const SEARCH_MARGIN: IVec3 = IVec3::new(12, 6, 12);
+const MAX_EXPANDED_NODES: usize = 8192;
+const MAX_FALL: i32 = 3;
+
+// Costs are in tenths of a block walked
+const WALK_COST: u32 = 10;
+const JUMP_COST: u32 = 10;
+const FALL_COST_PER_BLOCK: u32 = 5;
+
+const PLAYER_HEIGHT: i32 = 2;
+const MOB_SPEED: f32 = 3.0; // blocks per second
+
+pub fn plugin(builder: &mut GameBuilder) {
+    builder
+        .insert_resource(Pathfinder::new())
+        .add_system(Stage::Update, receive_paths)
+        .add_system(Stage::Update, follow_paths)
+        .require_op("/path")
+        .require_op("/come")
+        .on_chat(path_commands);
+}
+
+// Attached to mobs that are walking somewhere
+pub struct PathFollower {
+    pub waypoints: Vec<Vec3>,
+    pub next: usize,
+    // Results of older requests are dropped
+    pub request: u32,
+}
+
+enum PathOwner {
+    // `requester` is told if there's no path
+    Mob { entity: Entity, request: u32, requester: Option<PlayerId> },
+    // `/path` debug command
+    Player(PlayerId),
+}
+
+struct PathJob {
+    owner: PathOwner,
+    grid: SolidGrid,
+    start: IVec3,
+    goal: IVec3,
+    height: i32,
+}
+
+struct PathResult {
+    owner: PathOwner,
+    goal: IVec3,
+    path: Option<Path>,
+}
+
+pub struct Path {
+    // Block positions the entity's feet pass through, start and goal included
+    pub nodes: Vec<IVec3>,
+    pub cost: u32,
+}
+
+pub struct Pathfinder {
+    jobs: Sender<PathJob>,
+    results: Receiver<PathResult>,
+    next_request: u32,
+}
+
+impl Pathfinder {
+    fn new() -> Self {
+        let (job_send, job_recv) = crossbeam_channel::unbounded::<PathJob>();
+        let (result_send, result_recv) = crossbeam_channel::unbounded();
+
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get() / 2).clamp(1, 4);
+        for i in 0..workers {
+            let (jobs, results) = (job_recv.clone(), result_send.clone());
+            std::thread::Builder::new()
+                .name(format!("pathfinder-{i}"))
+                .spawn(move || {
+                    for job in jobs {
+                        let path = find_path(&job.grid, job.start, job.goal, job.height);
+                        if results.send(PathResult { owner: job.owner, goal: job.goal, path }).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .expect("failed to spawn pathfinder thread");
+        }
+
+        Self {
+            jobs: job_send,
+            results: result_recv,
+            next_request: 0,
+        }
+    }
+}
+
+// Solidity of a box of blocks. Anything below the world counts as solid so that there's
+// something to stand on even where the world is empty, and anything else outside of the
+// box counts as solid so that the search stays inside.
+pub struct SolidGrid {
+    min: IVec3,
+    size: IVec3,
+    solid: Vec<bool>,
+}
+
+impl SolidGrid {
+    pub fn copy_from(world: &BlockWorld, min: IVec3, max: IVec3) -> Self {
+        let size = (max - min + 1).max(IVec3::ZERO);
+        let mut solid = Vec::with_capacity((size.x * size.y * size.z) as usize);
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    solid.push(world.is_solid(IVec3::new(x, y, z)));
+                }
+            }
+        }
+        Self { min, size, solid }
+    }
+
+    pub fn is_solid(&self, pos: IVec3) -> bool {
+        if pos.y < 0 {
+            return true;
+        }
+        let local = pos - self.min;
+        if local.cmplt(IVec3::ZERO).any() || local.cmpge(self.size).any() {
+            return true;
+        }
+        self.solid[((local.y * self.size.z + local.z) * self.size.x + local.x) as usize]
+    }
+
+    fn is_passable(&self, feet: IVec3, height: i32) -> bool {
+        (0..height).all(|i| !self.is_solid(feet + IVec3::Y * i))
+    }
+
+    fn is_walkable(&self, feet: IVec3, height: i32) -> bool {
+        self.is_passable(feet, height) && self.is_solid(feet - IVec3::Y)
+    }
+
+    // The closest walkable position at most MAX_FALL blocks below `feet`
+    fn ground_below(&self, feet: IVec3, height: i32) -> Option<IVec3> {
+        (0..=MAX_FALL)
+            .map(|d| feet - IVec3::Y * d)
+            .take_while(|&pos| self.is_passable(pos, height))
+            .find(|&pos| self.is_walkable(pos, height))
+    }
+}
+
+pub fn find_path(grid: &SolidGrid, start: IVec3, goal: IVec3, height: i32) -> Option<Path> {
+    let start = grid.ground_below(start, height)?;
+    let goal = grid.ground_below(goal, height)?;
+
+    let heuristic = |pos: IVec3| {
+        let d = (goal - pos).abs();
+        (d.x + d.z) as u32 * WALK_COST
+    };
+
+    // position -> (cost so far, previous position)
+    let mut visited = HashMap::<IVec3, (u32, IVec3)>::new();
+    let mut open = BinaryHeap::new();
+    visited.insert(start, (0, start));
+    open.push(Reverse((heuristic(start), 0u32, start.to_array())));
+
+    let mut expanded = 0;
+    while let Some(Reverse((_, cost, pos))) = open.pop() {
+        let pos = IVec3::from_array(pos);
+        if pos == goal {
+            let mut nodes = vec![goal];
+            let mut current = goal;
+            while current != start {
+                current = visited[&current].1;
+                nodes.push(current);
+            }
+            nodes.reverse();
+            return Some(Path { nodes, cost });
+        }
+        if cost > visited[&pos].0 {
+            continue; // Stale entry, a cheaper way here was found after this was pushed
+        }
+
+        expanded += 1;
+        if expanded > MAX_EXPANDED_NODES {
+            return None;
+        }
+
+        for dir in [IVec3::X, -IVec3::X, IVec3::Z, -IVec3::Z] {
+            let Some((next, step_cost)) = step(grid, pos, dir, height) else {
+                continue;
+            };
+            let next_cost = cost + step_cost;
+            if visited.get(&next).map_or(true, |&(old, _)| next_cost < old) {
+                visited.insert(next, (next_cost, pos));
+                open.push(Reverse((next_cost + heuristic(next), next_cost, next.to_array())));
+            }
+        }
+    }
+    None
+}
+
+// Walking, jumping up one block, or walking off an edge and falling at most MAX_FALL blocks
+fn step(grid: &SolidGrid, from: IVec3, dir: IVec3, height: i32) -> Option<(IVec3, u32)> {
+    let to = from + dir;
+    if grid.is_walkable(to, height) {
+        return Some((to, WALK_COST));
+    }
+    if grid.is_walkable(to + IVec3::Y, height) && !grid.is_solid(from + IVec3::Y * height) {
+        return Some((to + IVec3::Y, WALK_COST + JUMP_COST));
+    }
+    let landing = grid.ground_below(to, height)?;
+    Some((landing, WALK_COST + FALL_COST_PER_BLOCK * (to.y - landing.y) as u32))
+}
+
+// Queues a search from the block containing `feet` to the block containing `goal`.
+// Returns false if the goal is too far away.
+fn queue_path(res: &mut Resources, owner: PathOwner, feet: Vec3, goal: Vec3, height: i32) -> bool {
+    // `/path` takes any coordinates, so the distance is checked before anything is in
+    // blocks, where it could overflow
+    let d = (goal - feet).abs();
+    if !(d.x <= MAX_DISTANCE as f32 && d.z <= MAX_DISTANCE as f32) {
+        return false;
+    }
+    // Just above or below the world is as unreachable as further away
+    let to_block = |pos: Vec3| {
+        let block = pos.floor().as_ivec3();
+        IVec3::new(block.x, block.y.clamp(-1, WORLD_HEIGHT), block.z)
+    };
+    let (start, goal) = (to_block(feet), to_block(goal));
+
+    // The start and goal's span plus SEARCH_MARGIN, in Y only what's inside the world
+    let mut min = start.min(goal) - SEARCH_MARGIN;
+    let mut max = start.max(goal) + SEARCH_MARGIN;
+    min.y = min.y.max(0);
+    max.y = max.y.min(WORLD_HEIGHT - 1);
+    let grid = SolidGrid::copy_from(&res.blocks, min, max);
+
+    let Some(pathfinder) = res.extra.get::<Pathfinder>() else {
+        return false;
+    };
+    pathfinder.jobs.send(PathJob { owner, grid, start, goal, height }).is_ok()
+}
+
+// Makes the mob walk to `goal` once a path has been found. Replaces any path it's
+// currently following. Returns false if the goal is too far away. If no path is found,
+// the mob stays where it is and `requester`, if any, is told.
+pub fn request_path(res: &mut Resources, mob: Entity, goal: Vec3, requester: Option<PlayerId>) -> bool {
+    let Ok((&Position(position), &Mob(kind))) = res.main_world.query_one_mut::<(&Position, &Mob)>(mob) else {
+        return false;
+    };
+    let half_extents = kind.half_extents();
+    let feet = position - Vec3::Y * half_extents.y;
+    let height = (half_extents.y * 2.0).ceil() as i32;
+
+    let Some(pathfinder) = res.extra.get_mut::<Pathfinder>() else {
+        return false;
+    };
+    let request = pathfinder.next_request;
+    pathfinder.next_request = request.wrapping_add(1);
+
+    if !queue_path(res, PathOwner::Mob { entity: mob, request, requester }, feet, goal, height) {
+        return false;
+    }
+    let follower = PathFollower { waypoints: Vec::new(), next: 0, request };
+    let _ = res.main_world.insert_one(mob, follower);
+    true
+}
+
+fn receive_paths(res: &mut Resources) -> anyhow::Result<()> {
+    let Some(pathfinder) = res.extra.get::<Pathfinder>() else {
+        return Ok(());
+    };
+    let results = pathfinder.results.try_iter().collect::<Vec<_>>();
+
+    for PathResult { owner, goal, path } in results {
+        match owner {
+            PathOwner::Mob { entity, request, requester } => {
+                let Ok((follower, &Mob(kind))) = res.main_world.query_one_mut::<(&mut PathFollower, &Mob)>(entity) else {
+                    continue; // Despawned, or stopped following
+                };
+                if follower.request != request {
+                    continue;
+                }
+                let Some(path) = path else {
+                    let _ = res.main_world.remove_one::<PathFollower>(entity);
+                    if let Some(player) = requester {
+                        res.net.send_chat(player, format!("A {} found no way to {goal}", kind.name()).into());
+                    }
+                    continue;
+                };
+                // Waypoints are where the center of the mob should be
+                let offset = Vec3::new(0.5, kind.half_extents().y, 0.5);
+                follower.waypoints = path.nodes.iter().map(|node| node.as_vec3() + offset).collect();
+                follower.next = 1; // The first node is where the mob already stands
+            }
+            PathOwner::Player(player) => {
+                res.net.send_chat(player, describe_path(goal, path.as_ref()).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+// Steers mobs along their paths in straight lines between waypoints, facing where
+// they're going. Jumps and falls are just diagonal moves for now.
+fn follow_paths(res: &mut Resources) -> anyhow::Result<()> {
+    let step = MOB_SPEED * shared::TICK_DURATION.as_secs_f32();
+
+    let mut finished = Vec::new();
+    for (entity, (follower, Position(position), head_rotation))
+        in res.main_world.query_mut::<(&mut PathFollower, &mut Position, &mut HeadYawPitch)>() {
+
+        let Some(&target) = follower.waypoints.get(follower.next) else {
+            // Empty while the search is still running
+            if !follower.waypoints.is_empty() {
+                finished.push(entity);
+            }
+            continue;
+        };
+
+        let to_target = target - *position;
+        let distance = to_target.length();
+        if distance <= step {
+            *position = target;
+            follower.next += 1;
+        } else {
+            *position += to_target * (step / distance);
+        }
+
+        if to_target.x != 0.0 || to_target.z != 0.0 {
+            let yaw = to_target.z.atan2(to_target.x);
+            // Shortest way around
+            let delta = (yaw - head_rotation.value.x + PI).rem_euclid(2.0 * PI) - PI;
+            let delta = Vec2::new(delta, -head_rotation.value.y);
+            head_rotation.value += delta;
+            head_rotation.delta += delta;
+        }
+    }
+
+    for entity in finished {
+        let _ = res.main_world.remove_one::<PathFollower>(entity);
+    }
+    Ok(())
+}
+
+fn describe_path(goal: IVec3, path: Option<&Path>) -> String {
+    let Some(path) = path else {
+        return format!("No path to {goal}");
+    };
+    // Only list the nodes where the direction changes
+    let mut corners = vec![path.nodes[0]];
+    for window in path.nodes.windows(3) {
+        if window[1] - window[0] != window[2] - window[1] {
+            corners.push(window[1]);
+        }
+    }
+    if path.nodes.len() > 1 {
+        corners.push(*path.nodes.last().unwrap());
+    }
+
+    let corners = corners.iter().map(|pos| format!("({} {} {})", pos.x, pos.y, pos.z)).collect::<Vec<_>>();
+    format!(
+        "Path to {goal}: {} blocks, cost {:.1}, via {}",
+        path.nodes.len() - 1,
+        path.cost as f32 / WALK_COST as f32,
+        corners.join(" -> ")
+    )
+}
+
+// `/path <x y z>` finds a path from the sender to the position and prints it,
+// `/come` makes every mob in range walk to the sender.
+fn path_commands(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    let (command, args) = message.split_once(' ').unwrap_or((message, ""));
+    if command != "/path" && command != "/come" {
+        return false;
+    }
+    let Ok((&player_id, &Position(position))) = res.main_world.query_one_mut::<(&PlayerId, &Position)>(sender) else {
+        return true;
+    };
+
+    if command == "/come" {
+        let mobs = res.main_world.query_mut::<(&Position, &Mob)>()
+            .into_iter()
+            .filter(|(_, (Position(mob_pos), _))| {
+                let d = (*mob_pos - position).abs();
+                d.x <= MAX_DISTANCE as f32 && d.z <= MAX_DISTANCE as f32
+            })
+            .map(|(entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        let count = mobs.into_iter().filter(|&mob| request_path(res, mob, position, Some(player_id))).count();
+        res.net.send_chat(player_id, format!("{count} mobs on their way").into());
+        return true;
+    }
+
+    let coords = args.split_whitespace().map(str::parse::<f32>).collect::<Result<Vec<_>, _>>();
+    let Ok(&[x, y, z]) = coords.as_deref() else {
+        res.net.send_chat(player_id, "Usage: /path <x y z>".into());
+        return true;
+    };
+    // Players don't have a hitbox on the server, assume the position is at their feet
+    if !queue_path(res, PathOwner::Player(player_id), position, Vec3::new(x, y, z), PLAYER_HEIGHT) {
+        res.net.send_chat(player_id, format!("Too far away, the limit is {MAX_DISTANCE} blocks").into());
+    }
+    true
+}
+
+mod tests {
+    #[test]
+    fn test_find_path() {
+        use glam::{ivec3, IVec3};
+        use super::{find_path, SolidGrid, WALK_COST};
+        use crate::world::BlockWorld;
+
+        // A wall 3 blocks high along z at `x`, with a gap at `gap_z`
+        fn wall(world: &mut BlockWorld, x: i32, length: i32, gap_z: i32) {
+            for z in (0..length).filter(|&z| z != gap_z) {
+                for y in 0..3 {
+                    world.set_block(ivec3(x, y, z), 1);
+                }
+            }
+        }
+
+        // An empty world has the bottom of the world to walk on
+        let mut world = BlockWorld::new();
+        let grid = SolidGrid::copy_from(&world, IVec3::ZERO, ivec3(15, 3, 15));
+        let path = find_path(&grid, ivec3(1, 0, 1), ivec3(6, 0, 4), 2).unwrap();
+        assert_eq!((path.nodes[0], *path.nodes.last().unwrap()), (ivec3(1, 0, 1), ivec3(6, 0, 4)));
+        assert_eq!(path.nodes.len(), 9);
+        assert_eq!(path.cost, 8 * WALK_COST);
+
+        // Around a wall, through the gap
+        wall(&mut world, 4, 16, 12);
+        let grid = SolidGrid::copy_from(&world, IVec3::ZERO, ivec3(15, 3, 15));
+        let path = find_path(&grid, ivec3(1, 0, 1), ivec3(6, 0, 4), 2).unwrap();
+        assert!(path.nodes.contains(&ivec3(4, 0, 12)));
+        assert!(path.nodes.windows(2).all(|step| {
+            let d = (step[1] - step[0]).abs();
+            d.x + d.y + d.z == 1
+        }));
+
+        // Walled off entirely
+        wall(&mut world, 4, 16, -1);
+        let grid = SolidGrid::copy_from(&world, IVec3::ZERO, ivec3(15, 3, 15));
+        assert!(find_path(&grid, ivec3(1, 0, 1), ivec3(6, 0, 4), 2).is_none());
+
+        // Reachable, but only by a detour so long that the search gives up first
+        let mut world = BlockWorld::new();
+        wall(&mut world, 100, 200, 199);
+        let grid = SolidGrid::copy_from(&world, IVec3::ZERO, ivec3(199, 3, 199));
+        assert!(find_path(&grid, ivec3(95, 0, 0), ivec3(105, 0, 0), 2).is_none());
+        // The same distance with the gap close by is fine
+        let mut world = BlockWorld::new();
+        wall(&mut world, 100, 200, 5);
+        let grid = SolidGrid::copy_from(&world, IVec3::ZERO, ivec3(199, 3, 199));
+        assert!(find_path(&grid, ivec3(95, 0, 0), ivec3(105, 0, 0), 2).is_some());
+    }
+}
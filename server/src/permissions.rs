@@ -0,0 +1,661 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use flexstr::{SharedStr, ToSharedStr};
+use hecs::Entity;
+use shared::TICKS_PER_SECOND;
+
+use crate::{
+    components::{Op, PlayerId, Username},
+    config::ServerConfig,
+    game_builder::GameBuilder,
+    resources::Resources,
+};
+
+// How often the files are checked for changes made by hand, in ticks. New identities are
+// written out as often.
+const RELOAD_INTERVAL: u32 = 2 * TICKS_PER_SECOND;
+// Usernames tied to a key at most. Past that, newcomers' names stay unclaimed, and can't
+// be made operators, until lines are removed from the identities file.
+const MAX_IDENTITIES: usize = 100_000;
+
+// Operators, banned and whitelisted players, and the key each username is tied to (see
+// c2s::Hello). Stored in TOML files of `"username" = value` lines (see
+// `ServerConfig::ops_file`, `bans_file`, `whitelist_file` and `identities_file`) that are
+// reloaded when edited, so that the server needn't be restarted.
+//
+// Usernames are only as trustworthy as the key check at login, so operators are stored
+// with the key they had when made one: whoever joins under that name with another key
+// isn't one, and a name that has never joined can't be made one.
+pub struct Permissions {
+    ops: HashMap<SharedStr, [u8; 16]>,
+    bans: HashMap<SharedStr, SharedStr>, // username -> reason, possibly empty
+    whitelist: HashSet<SharedStr>,
+    whitelist_enabled: bool,
+    identities: HashMap<SharedStr, [u8; 16]>,
+    // Claimed since the identities file was last written
+    unsaved_identities: Vec<(SharedStr, [u8; 16])>,
+    ops_file: Option<StoredFile>,
+    bans_file: Option<StoredFile>,
+    whitelist_file: Option<StoredFile>,
+    identities_file: Option<StoredFile>,
+}
+
+struct StoredFile {
+    path: PathBuf,
+    modified: Option<SystemTime>, // as of the last load or save
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    let config = builder.resource::<ServerConfig>();
+    let stored = |path: Option<&PathBuf>| path.map(|path| StoredFile { path: path.clone(), modified: None });
+    let mut permissions = Permissions {
+        ops: HashMap::new(),
+        bans: HashMap::new(),
+        whitelist: HashSet::new(),
+        whitelist_enabled: config.map_or(false, |config| config.whitelist),
+        identities: HashMap::new(),
+        unsaved_identities: Vec::new(),
+        ops_file: stored(config.and_then(|config| config.ops_file.as_ref())),
+        bans_file: stored(config.and_then(|config| config.bans_file.as_ref())),
+        whitelist_file: stored(config.and_then(|config| config.whitelist_file.as_ref())),
+        identities_file: stored(config.and_then(|config| config.identities_file.as_ref())),
+    };
+    permissions.reload_changed();
+    builder.scheduler().every_from(RELOAD_INTERVAL, RELOAD_INTERVAL, reload_if_changed);
+
+    builder
+        .insert_resource(permissions)
+        .on_player_join(grant_op_on_join)
+        .require_op("/op")
+        .require_op("/deop")
+        .require_op("/ban")
+        .require_op("/pardon")
+        .require_op("/whitelist")
+        .on_chat(commands_from_chat)
+        .on_console_command(commands_from_console)
+        .on_shutdown(save_new_identities);
+}
+
+// What a banned player is told when trying to log in, None if not banned
+pub fn ban_message(res: &Resources, username: &str) -> Option<SharedStr> {
+    let reason = res.extra.get::<Permissions>()?.bans.get(username)?;
+    Some(match reason.is_empty() {
+        true => "You are banned from this server".into(),
+        false => format!("You are banned from this server: {reason}").into(),
+    })
+}
+
+// What a player missing from the whitelist is told when trying to log in, None if they may
+// join. Operators needn't be on it.
+pub fn whitelist_message(res: &Resources, username: &str) -> Option<SharedStr> {
+    let permissions = res.extra.get::<Permissions>()?;
+    let allowed = !permissions.whitelist_enabled
+        || permissions.whitelist.contains(username)
+        || permissions.is_op(username);
+    (!allowed).then(|| "You are not whitelisted on this server".into())
+}
+
+// What someone joining with a username tied to another key is told, None if the key
+// matches or the username is free. See `claim_identity`.
+pub fn identity_message(res: &Resources, username: &str, key: [u8; 16]) -> Option<SharedStr> {
+    let known = res.extra.get::<Permissions>()?.identities.get(username)?;
+    (*known != key).then(|| "Someone else already plays as this username on this server".into())
+}
+
+// Ties a free username to the key of the player who has just finished joining with it, so
+// that names are only claimed by logins that got all the way in. Returns the same as
+// `identity_message`, for whoever got the name in the meantime.
+pub fn claim_identity(res: &mut Resources, username: &SharedStr, key: [u8; 16]) -> Option<SharedStr> {
+    let permissions = res.extra.get_mut::<Permissions>()?;
+    match permissions.identities.get(username) {
+        Some(known) if *known == key => None,
+        Some(_) => Some("Someone else already plays as this username on this server".into()),
+        None if permissions.identities.len() >= MAX_IDENTITIES => {
+            eprintln!("Not tying {username} to a key: already {MAX_IDENTITIES} usernames are");
+            None
+        }
+        None => {
+            permissions.identities.insert(username.clone(), key);
+            permissions.unsaved_identities.push((username.clone(), key));
+            None
+        }
+    }
+}
+
+// See `reload`. Like editing the files, turning the whitelist on kicks whoever isn't on it.
+// The files are checked for changes right away rather than at the next interval.
+pub fn reconfigure(res: &mut Resources, config: &ServerConfig) {
+    let Some(permissions) = res.extra.get_mut::<Permissions>() else {
+        return;
+    };
+    permissions.whitelist_enabled = config.whitelist;
+    for path in permissions.reload_changed() {
+        println!("Reloaded {}", path.display());
+    }
+    apply_to_online_players(res);
+}
+
+// Only while joined with the key the player was made an operator with
+pub fn is_op(res: &Resources, username: &str) -> bool {
+    res.extra.get::<Permissions>().map_or(false, |permissions| permissions.is_op(username))
+}
+
+impl Permissions {
+    fn is_op(&self, username: &str) -> bool {
+        matches!((self.ops.get(username), self.identities.get(username)), (Some(op), Some(known)) if op == known)
+    }
+
+    // Returns what was reloaded, for logging
+    fn reload_changed(&mut self) -> Vec<PathBuf> {
+        let mut reloaded = Vec::new();
+        if let Some((path, text)) = self.ops_file.as_mut().and_then(StoredFile::read_if_changed) {
+            self.ops = parse_entries(&path, &text, Value::into_key).collect();
+            reloaded.push(path);
+        }
+        if let Some((path, text)) = self.bans_file.as_mut().and_then(StoredFile::read_if_changed) {
+            self.bans = parse_entries(&path, &text, Value::into_reason).collect();
+            reloaded.push(path);
+        }
+        if let Some((path, text)) = self.whitelist_file.as_mut().and_then(StoredFile::read_if_changed) {
+            self.whitelist = parse_entries(&path, &text, Value::into_flag).map(|(name, _)| name).collect();
+            reloaded.push(path);
+        }
+        if let Some((path, text)) = self.identities_file.as_mut().and_then(StoredFile::read_if_changed) {
+            self.identities = parse_entries(&path, &text, Value::into_key).collect();
+            // Not in the file yet, but taken all the same
+            for (name, key) in &self.unsaved_identities {
+                self.identities.entry(name.clone()).or_insert(*key);
+            }
+            reloaded.push(path);
+        }
+        reloaded
+    }
+
+    fn save_ops(&mut self) {
+        let entries = self.ops.iter().map(|(name, key)| (name, format!("\"{}\"", hex(key))));
+        if let Some(file) = &mut self.ops_file {
+            file.write(&format!("# Operators, with the key each had when made one\n{}", toml_lines(entries)));
+        }
+    }
+
+    fn save_whitelist(&mut self) {
+        let entries = self.whitelist.iter().map(|name| (name, "true".to_owned()));
+        if let Some(file) = &mut self.whitelist_file {
+            file.write(&format!("# Players allowed to join when the whitelist is on\n{}", toml_lines(entries)));
+        }
+    }
+
+    fn save_bans(&mut self) {
+        let entries = self.bans.iter().map(|(name, reason)| (name, toml_string(reason)));
+        if let Some(file) = &mut self.bans_file {
+            file.write(&format!("# Banned players and why, if anyone said\n{}", toml_lines(entries)));
+        }
+    }
+
+    fn save_identities(&mut self) {
+        self.unsaved_identities.clear();
+        let entries = self.identities.iter().map(|(name, key)| (name, format!("\"{}\"", hex(key))));
+        if let Some(file) = &mut self.identities_file {
+            file.write(&format!(
+                "# The key each username first joined with. Removing a line lets the next one to join claim it.\n{}",
+                toml_lines(entries),
+            ));
+        }
+    }
+}
+
+impl StoredFile {
+    // None if unchanged since the last load or save. A missing file counts as empty.
+    fn read_if_changed(&mut self) -> Option<(PathBuf, String)> {
+        let modified = match fs::metadata(&self.path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => Some(modified),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => {
+                eprintln!("Failed to check {}: {e}", self.path.display());
+                return None;
+            }
+        };
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        match fs::read_to_string(&self.path) {
+            Ok(text) => Some((self.path.clone(), text)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Some((self.path.clone(), String::new())),
+            Err(e) => {
+                eprintln!("Failed to read {}: {e}", self.path.display());
+                None
+            }
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        if let Err(e) = fs::write(&self.path, text) {
+            eprintln!("Failed to write {}: {e}", self.path.display());
+            return;
+        }
+        // Not to be reloaded as if edited by hand
+        self.modified = fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok();
+    }
+}
+
+// The right-hand sides the files use: TOML strings and booleans
+enum Value {
+    String(String),
+    Bool(bool),
+}
+
+impl Value {
+    fn into_key(self) -> Option<[u8; 16]> {
+        let Value::String(hex) = self else {
+            return None;
+        };
+        let mut key = [0; 16];
+        if hex.len() != 2 * key.len() || !hex.is_ascii() {
+            return None;
+        }
+        for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+        }
+        Some(key)
+    }
+
+    fn into_reason(self) -> Option<SharedStr> {
+        match self {
+            Value::String(reason) => Some(reason.to_shared_str()),
+            Value::Bool(true) => Some(SharedStr::from("")),
+            Value::Bool(false) => None,
+        }
+    }
+
+    fn into_flag(self) -> Option<()> {
+        matches!(self, Value::Bool(true)).then_some(())
+    }
+}
+
+// The `key = value` lines of `text`, keeping those that `convert` accepts. The rest of TOML
+// (tables, arrays, numbers...) isn't needed here, and is ignored with a complaint.
+fn parse_entries<'a, T: 'a>(
+    path: &'a Path,
+    text: &'a str,
+    convert: fn(Value) -> Option<T>,
+) -> impl Iterator<Item = (SharedStr, T)> + 'a {
+    text.lines().enumerate().filter_map(move |(line_no, line)| {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return None;
+        }
+        let entry = parse_entry(trimmed).and_then(|(name, value)| Some((name.to_shared_str(), convert(value)?)));
+        if entry.is_none() {
+            eprintln!("{}:{}: ignoring '{trimmed}'", path.display(), line_no + 1);
+        }
+        entry
+    })
+}
+
+fn parse_entry(line: &str) -> Option<(String, Value)> {
+    let (name, rest) = match line.strip_prefix('"') {
+        Some(quoted) => parse_string(quoted)?,
+        None => {
+            let end = line.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))?;
+            (line[..end].to_owned(), &line[end..])
+        }
+    };
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    let (value, rest) = if let Some(quoted) = rest.strip_prefix('"') {
+        let (string, rest) = parse_string(quoted)?;
+        (Value::String(string), rest)
+    } else if let Some(rest) = rest.strip_prefix("true") {
+        (Value::Bool(true), rest)
+    } else {
+        (Value::Bool(false), rest.strip_prefix("false")?)
+    };
+    let rest = rest.trim_start();
+    (!name.is_empty() && (rest.is_empty() || rest.starts_with('#'))).then_some((name, value))
+}
+
+// A basic string, after the opening quote. Returns it and what follows the closing quote.
+fn parse_string(text: &str) -> Option<(String, &str)> {
+    let mut string = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((string, &text[i + 1..])),
+            '\\' => string.push(match chars.next()?.1 {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                '"' => '"',
+                '\\' => '\\',
+                'u' => {
+                    let digits = (0..4).map(|_| chars.next().map(|(_, c)| c)).collect::<Option<String>>()?;
+                    char::from_u32(u32::from_str_radix(&digits, 16).ok()?)?
+                }
+                _ => return None,
+            }),
+            c if c.is_control() => return None,
+            c => string.push(c),
+        }
+    }
+    None
+}
+
+fn toml_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// Sorted by username, which is always quoted, since TOML's bare keys are ASCII only
+fn toml_lines<'a>(entries: impl Iterator<Item = (&'a SharedStr, String)>) -> String {
+    let mut entries = entries.collect::<Vec<_>>();
+    entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    entries.into_iter().map(|(name, value)| format!("{} = {value}\n", toml_string(name))).collect()
+}
+
+fn hex(key: &[u8; 16]) -> String {
+    key.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn reload_if_changed(res: &mut Resources) {
+    let Some(permissions) = res.extra.get_mut::<Permissions>() else {
+        return;
+    };
+    let reloaded = permissions.reload_changed();
+    if !permissions.unsaved_identities.is_empty() {
+        permissions.save_identities();
+    }
+    if reloaded.is_empty() {
+        return;
+    }
+    for path in reloaded {
+        println!("Reloaded {}", path.display());
+    }
+    apply_to_online_players(res);
+}
+
+fn save_new_identities(res: &mut Resources) {
+    if let Some(permissions) = res.extra.get_mut::<Permissions>() {
+        if !permissions.unsaved_identities.is_empty() {
+            permissions.save_identities();
+        }
+    }
+}
+
+fn grant_op_on_join(res: &mut Resources, player: Entity) {
+    let Ok(username) = res.main_world.get::<&Username>(player).map(|username| username.0.clone()) else {
+        return;
+    };
+    if is_op(res, &username) {
+        let _ = res.main_world.insert_one(player, Op);
+    }
+}
+
+// Gives and takes away `Op` to match the lists, and kicks whoever is now banned or no longer
+// whitelisted
+fn apply_to_online_players(res: &mut Resources) {
+    let players = res.main_world.query_mut::<(&PlayerId, &Username, Option<&Op>)>()
+        .into_iter()
+        .map(|(entity, (&player_id, Username(name), op))| (entity, player_id, name.clone(), op.is_some()))
+        .collect::<Vec<_>>();
+
+    for (entity, player_id, name, was_op) in players {
+        let op = is_op(res, &name);
+        if op && !was_op {
+            let _ = res.main_world.insert_one(entity, Op);
+        } else if !op && was_op {
+            let _ = res.main_world.remove_one::<Op>(entity);
+        }
+        if let Some(message) = ban_message(res, &name).or_else(|| whitelist_message(res, &name)) {
+            res.net.kick(player_id, message);
+        }
+    }
+}
+
+// The commands, shared between chat and the console. Returns the reply.
+fn run_command(res: &mut Resources, command: &str, args: &str) -> String {
+    if command == "whitelist" {
+        return run_whitelist_command(res, args);
+    }
+    let (name, reason) = args.trim().split_once(char::is_whitespace).unwrap_or((args.trim(), ""));
+    if name.is_empty() {
+        return match command {
+            "ban" => "Usage: ban <player> [reason]".to_owned(),
+            _ => format!("Usage: {command} <player>"),
+        };
+    }
+    let Some(permissions) = res.extra.get_mut::<Permissions>() else {
+        return "Permissions aren't available".to_owned();
+    };
+    let name = name.to_shared_str();
+
+    let (reply, notice) = match command {
+        // Whoever has joined as them, which is who they'll have to keep joining as
+        "op" => match permissions.identities.get(&name).copied() {
+            None => (format!("{name} has to join once before being made an operator"), None),
+            Some(key) => match permissions.ops.insert(name.clone(), key) {
+                Some(old) if old == key => (format!("{name} is already an operator"), None),
+                _ => {
+                    permissions.save_ops();
+                    (format!("Made {name} an operator"), Some("You are now an operator"))
+                }
+            },
+        },
+        "deop" => match permissions.ops.remove(&name) {
+            Some(_) => {
+                permissions.save_ops();
+                (format!("{name} is no longer an operator"), Some("You are no longer an operator"))
+            }
+            None => (format!("{name} isn't an operator"), None),
+        },
+        "ban" => {
+            permissions.bans.insert(name.clone(), reason.trim().to_shared_str());
+            permissions.save_bans();
+            (format!("Banned {name}"), None)
+        }
+        _ => match permissions.bans.remove(&name) {
+            Some(_) => {
+                permissions.save_bans();
+                (format!("Pardoned {name}"), None)
+            }
+            None => (format!("{name} isn't banned"), None),
+        },
+    };
+    println!("{reply}");
+    apply_to_online_players(res);
+
+    // Let them know, if they're online
+    let player_id = res.main_world.query_mut::<(&Username, &PlayerId)>()
+        .into_iter()
+        .find(|(_, (username, _))| username.0 == name)
+        .map(|(_, (_, &player_id))| player_id);
+    if let (Some(player_id), Some(notice)) = (player_id, notice) {
+        res.net.send_chat(player_id, notice.into());
+    }
+    reply
+}
+
+// `whitelist add <player>` and `whitelist remove <player>`
+fn run_whitelist_command(res: &mut Resources, args: &str) -> String {
+    let (action, name) = args.trim().split_once(char::is_whitespace).unwrap_or((args.trim(), ""));
+    let name = name.trim().to_shared_str();
+    let Some(permissions) = res.extra.get_mut::<Permissions>() else {
+        return "Permissions aren't available".to_owned();
+    };
+
+    let reply = match action {
+        _ if name.is_empty() => "Usage: whitelist add|remove <player>".to_owned(),
+        "add" => match permissions.whitelist.insert(name.clone()) {
+            true => {
+                permissions.save_whitelist();
+                format!("Added {name} to the whitelist")
+            }
+            false => format!("{name} is already whitelisted"),
+        },
+        "remove" => match permissions.whitelist.remove(&name) {
+            true => {
+                permissions.save_whitelist();
+                format!("Removed {name} from the whitelist")
+            }
+            false => format!("{name} isn't whitelisted"),
+        },
+        _ => "Usage: whitelist add|remove <player>".to_owned(),
+    };
+    println!("{reply}");
+    apply_to_online_players(res);
+    reply
+}
+
+// `/op <player>`, `/deop <player>`, `/ban <player> [reason]`, `/pardon <player>` and
+// `/whitelist add|remove <player>`, for ops only
+fn commands_from_chat(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    let (command, args) = message.split_once(' ').unwrap_or((message, ""));
+    let Some(command) = command.strip_prefix('/').filter(|command| matches!(*command, "op" | "deop" | "ban" | "pardon" | "whitelist")) else {
+        return false;
+    };
+    let Ok(&player_id) = res.main_world.get::<&PlayerId>(sender).as_deref() else {
+        return true;
+    };
+    let reply = run_command(res, command, args);
+    res.net.send_chat(player_id, reply.into());
+    true
+}
+
+fn commands_from_console(res: &mut Resources, command: &str, args: &str) -> bool {
+    if !matches!(command, "op" | "deop" | "ban" | "pardon" | "whitelist") {
+        return false;
+    }
+    run_command(res, command, args);
+    true
+}
+
+mod tests {
+    #[test]
+    fn test_toml_entries() {
+        use std::path::Path;
+        use flexstr::SharedStr;
+        use super::{parse_entries, toml_lines, Value};
+
+        // Written sorted, so read back in the same order
+        let mut names = ["alice", "Ääkkönen", "quote\" back\\slash", "tab\tnew\nline", "\u{7}bell"].map(SharedStr::from);
+        names.sort_unstable();
+        let text = toml_lines(names.iter().map(|name| (name, "true".to_owned())));
+        let parsed = parse_entries(Path::new("test"), &text, Value::into_flag).map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(parsed, names);
+
+        let text = "# comment\nbare-name_1 = \"some reason\" # trailing\n\"x\" = true\n\"y\" = false\n[table]\nz = 5\n\"unterminated = true\nw = \"\\u00e4\"\n";
+        let bans = parse_entries(Path::new("test"), text, Value::into_reason).collect::<Vec<_>>();
+        assert_eq!(bans, [
+            (SharedStr::from("bare-name_1"), SharedStr::from("some reason")),
+            (SharedStr::from("x"), SharedStr::from("")),
+            (SharedStr::from("w"), SharedStr::from("ä")),
+        ]);
+
+        let key = (0..16).collect::<Vec<u8>>().try_into().unwrap();
+        let text = format!("a = \"{}\"\nb = \"0011\"\nc = \"zz{}\"\n", super::hex(&key), "0".repeat(30));
+        assert_eq!(parse_entries(Path::new("test"), &text, Value::into_key).collect::<Vec<_>>(), [(SharedStr::from("a"), key)]);
+    }
+
+    #[test]
+    fn test_permissions() {
+        use crate::{game_builder, networking::LoginResponse, permissions, testing::TestServer};
+
+        let mut server = TestServer::new();
+        let alice = server.connect("alice");
+        let bob = server.connect("bob");
+        server.tick();
+        server.received_chat(alice);
+
+        server.send_chat(alice, "/ban bob");
+        server.tick();
+        assert!(server.received_chat(alice).iter().any(|(_, message)| message.as_str() == "You don't have permission to use /ban"));
+        assert!(server.kicked(bob).is_none());
+
+        assert!(game_builder::dispatch_console_command(&mut server.res, "op", "alice"));
+        server.tick();
+        assert!(server.received_chat(alice).iter().any(|(_, message)| message.as_str() == "You are now an operator"));
+
+        server.send_chat(alice, "/ban bob griefing");
+        server.tick();
+        assert_eq!(server.kicked(bob).as_deref(), Some("You are banned from this server: griefing"));
+
+        let mut response = server.request_login("bob");
+        server.tick();
+        let Ok((_, LoginResponse::Denied(reason))) = response.try_recv() else {
+            panic!("banned player wasn't denied");
+        };
+        assert_eq!(reason.as_str(), "You are banned from this server: griefing");
+
+        server.send_chat(alice, "/pardon bob");
+        server.tick();
+        assert!(server.received_chat(alice).iter().any(|(_, message)| message.as_str() == "Pardoned bob"));
+        assert!(server.connect("bob") > bob);
+
+        // Usernames belong to the key they first joined with
+        let mut response = server.request_login_with_key("alice", [0xA5; 16]);
+        server.tick();
+        let Ok((_, LoginResponse::Denied(reason))) = response.try_recv() else {
+            panic!("alice joined with someone else's key");
+        };
+        assert_eq!(reason.as_str(), "Someone else already plays as this username on this server");
+
+        // Nobody has joined as carol, so whoever first does couldn't be trusted with it
+        server.send_chat(alice, "/op carol");
+        server.tick();
+        assert!(server.received_chat(alice).iter().any(|(_, message)| message.as_str() == "carol has to join once before being made an operator"));
+        assert!(!permissions::is_op(&server.res, "carol"));
+
+        // Only logins that get all the way in claim a name, not ones that are let in and
+        // then go away
+        let mut response = server.request_login_with_key("carol", [0xA5; 16]);
+        server.tick();
+        assert!(matches!(response.try_recv(), Ok((_, LoginResponse::Success(..)))));
+        server.connect("carol");
+        assert!(game_builder::dispatch_console_command(&mut server.res, "op", "carol"));
+        assert!(permissions::is_op(&server.res, "carol"));
+    }
+
+    #[test]
+    fn test_whitelist() {
+        use crate::{config::ServerConfig, game_builder, networking::LoginResponse, testing::TestServer};
+
+        let mut server = TestServer::with_config(ServerConfig { whitelist: true, ..TestServer::config() });
+        let mut response = server.request_login("alice");
+        server.tick();
+        let Ok((_, LoginResponse::Denied(reason))) = response.try_recv() else {
+            panic!("player not on the whitelist wasn't denied");
+        };
+        assert_eq!(reason.as_str(), "You are not whitelisted on this server");
+
+        assert!(game_builder::dispatch_console_command(&mut server.res, "whitelist", "add alice"));
+        let alice = server.connect("alice");
+        // Operators may stay regardless
+        assert!(game_builder::dispatch_console_command(&mut server.res, "op", "alice"));
+        assert!(game_builder::dispatch_console_command(&mut server.res, "whitelist", "remove alice"));
+        server.tick();
+        assert!(server.kicked(alice).is_none());
+
+        assert!(game_builder::dispatch_console_command(&mut server.res, "deop", "alice"));
+        server.tick();
+        assert_eq!(server.kicked(alice).as_deref(), Some("You are not whitelisted on this server"));
+    }
+}
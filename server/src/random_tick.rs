@@ -0,0 +1,71 @@
+use shared::coords::LocalPos;
+
+use crate::{
+    config::ServerConfig,
+    game_builder::{self, GameBuilder, Stage},
+    resources::Resources,
+    world::{AIR, CHUNK_VOLUME},
+};
+
+// Slow, ambient block changes (grass spreading, crops growing...): every tick, a few
+// random blocks of each chunk are picked, and those that have a handler registered with
+// `GameBuilder::on_random_tick()` get to act. On average a block is visited once every
+// CHUNK_VOLUME / random_tick_speed ticks.
+pub struct RandomTicks {
+    per_chunk: u32,
+    rng: u64,
+}
+
+impl RandomTicks {
+    // xorshift64*; doesn't need to be any good, only cheap
+    fn next(&mut self) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    let config = builder.resource::<ServerConfig>();
+    let per_chunk = config.map_or(ServerConfig::default().random_tick_speed, |config| config.random_tick_speed);
+    let seed = config.map_or(0, |config| config.world_seed);
+
+    builder
+        .insert_resource(RandomTicks { per_chunk, rng: seed | 1 })
+        .add_system(Stage::Update, random_tick);
+}
+
+// See `reload`
+pub fn reconfigure(res: &mut Resources, config: &ServerConfig) {
+    if let Some(ticks) = res.extra.get_mut::<RandomTicks>() {
+        ticks.per_chunk = config.random_tick_speed;
+    }
+}
+
+fn random_tick(res: &mut Resources) -> anyhow::Result<()> {
+    let Some(ticks) = res.extra.get_mut::<RandomTicks>() else {
+        return Ok(());
+    };
+    if ticks.per_chunk == 0 || !res.handlers.has_random_tick_handlers() {
+        return Ok(());
+    }
+
+    // Picked up front: handlers may add chunks by placing blocks
+    let mut picked = Vec::new();
+    for chunk_pos in res.blocks.chunk_positions() {
+        for _ in 0..ticks.per_chunk {
+            let random = ticks.next();
+            let local = LocalPos::from_index(random as usize % CHUNK_VOLUME);
+            picked.push(chunk_pos.block(local).0);
+        }
+    }
+
+    for pos in picked {
+        let block = res.blocks.block_at(pos);
+        if block != AIR {
+            game_builder::dispatch_random_tick(res, pos, block);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use std::path::Path;
+
+use hecs::Entity;
+
+use crate::{
+    afk, block_updates, chat,
+    components::PlayerId,
+    config::{ServerConfig, CONFIG_PATH},
+    fluids,
+    game_builder::GameBuilder,
+    join_queue, moderation, permissions, random_tick, savefile, shutdown,
+    resources::Resources,
+};
+
+// `/reload` (or `reload` in the console) reads `server.cfg` again and applies what changed
+// without anyone having to reconnect. Settings that can't change while the server runs keep
+// their old values until a restart, and the reply says which ones those are.
+
+pub fn plugin(builder: &mut GameBuilder) {
+    builder
+        .require_op("/reload")
+        .on_chat(reload_from_chat)
+        .on_console_command(reload_from_console);
+}
+
+fn reload_from_chat(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    if message.trim_end() != "/reload" {
+        return false;
+    }
+    let Ok(&player_id) = res.main_world.get::<&PlayerId>(sender).as_deref() else {
+        return true;
+    };
+    let reply = reload(res, Path::new(CONFIG_PATH));
+    println!("{reply}");
+    res.net.send_chat(player_id, reply.into());
+    true
+}
+
+fn reload_from_console(res: &mut Resources, command: &str, _args: &str) -> bool {
+    if command != "reload" {
+        return false;
+    }
+    println!("{}", reload(res, Path::new(CONFIG_PATH)));
+    true
+}
+
+// A summary of what happened, for whoever asked. An invalid file changes nothing.
+pub fn reload(res: &mut Resources, path: &Path) -> String {
+    match ServerConfig::load(path) {
+        Ok(config) => describe(&apply(res, config)),
+        Err(e) => format!("Config not reloaded: {e}"),
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct Changes {
+    pub applied: Vec<&'static str>,
+    pub need_restart: Vec<&'static str>,
+}
+
+// Makes `config` the server's settings as far as possible, and returns the names of the
+// settings that differed from the current ones
+pub fn apply(res: &mut Resources, mut config: ServerConfig) -> Changes {
+    let Some(old) = res.extra.get::<ServerConfig>().cloned() else {
+        return Changes::default();
+    };
+    let mut changes = Changes::default();
+
+    let mut live = |name, changed: bool| if changed {
+        changes.applied.push(name);
+    };
+    live("chat_history", old.chat_history != config.chat_history);
+    live("chat_filter", old.chat_filters != config.chat_filters);
+    live("random_tick_speed", old.random_tick_speed != config.random_tick_speed);
+    live("fluid_updates_per_tick", old.fluid_updates_per_tick != config.fluid_updates_per_tick);
+    live("block_updates_per_tick", old.block_updates_per_tick != config.block_updates_per_tick);
+    live("shutdown_countdown", old.shutdown_countdown != config.shutdown_countdown);
+    live("afk_after", old.afk_after != config.afk_after);
+    live("afk_kick_after", old.afk_kick_after != config.afk_kick_after);
+    live("whitelist", old.whitelist != config.whitelist);
+    live("max_players", old.max_players != config.max_players);
+    live("join_queue_size", old.join_queue_size != config.join_queue_size);
+    live("autosave_interval", old.autosave_interval != config.autosave_interval);
+
+    // Sockets, files held open and what clients were told on login. These keep the value
+    // in effect, so that the ServerConfig resource stays true to what the server does.
+    macro_rules! restart_only {
+        ($($field:ident),*) => {$(
+            if old.$field != config.$field {
+                changes.need_restart.push(stringify!($field));
+                config.$field = old.$field.clone();
+            }
+        )*};
+    }
+    restart_only!(bind_address, metrics_address, chat_log, world_seed, ops_file, bans_file, whitelist_file, identities_file, world_file, compression);
+
+    afk::reconfigure(res, &config);
+    chat::reconfigure(res, &config);
+    moderation::reconfigure(res, &config);
+    permissions::reconfigure(res, &config);
+    random_tick::reconfigure(res, &config);
+    fluids::reconfigure(res, &config);
+    block_updates::reconfigure(res, &config);
+    join_queue::reconfigure(res, &config);
+    shutdown::reconfigure(res, &config);
+    savefile::reconfigure(res, &config);
+    res.extra.insert(config);
+    changes
+}
+
+fn describe(changes: &Changes) -> String {
+    let mut summary = match changes.applied.as_slice() {
+        [] => "Config reloaded, nothing to apply".to_owned(),
+        applied => format!("Config reloaded, applied {}", applied.join(", ")),
+    };
+    if !changes.need_restart.is_empty() {
+        summary += &format!(". Restart to apply {}", changes.need_restart.join(", "));
+    }
+    summary
+}
+
+mod tests {
+    #[test]
+    fn test_reload_config() {
+        use crate::{config::ServerConfig, reload, testing::TestServer};
+
+        let mut server = TestServer::new();
+        let alice = server.connect("alice");
+        let path = std::env::temp_dir().join(format!("reload-test-{}.cfg", std::process::id()));
+
+        // Unparseable: nothing changes
+        std::fs::write(&path, "max_players = lots\n").unwrap();
+        let reply = reload::reload(&mut server.res, &path);
+        assert!(reply.starts_with("Config not reloaded"), "{reply}");
+        assert_eq!(server.res.extra.get::<ServerConfig>().unwrap().max_players, ServerConfig::default().max_players);
+
+        std::fs::write(&path, "ops_file =\nbans_file =\nwhitelist_file =\nidentities_file =\nworld_file =\nmax_players = 3\nworld_seed = 5\nwhitelist = true\n").unwrap();
+        let reply = reload::reload(&mut server.res, &path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reply, "Config reloaded, applied whitelist, max_players. Restart to apply chat_log, world_seed");
+
+        // Restart-only settings keep their values until then
+        let config = server.res.extra.get::<ServerConfig>().unwrap();
+        assert_eq!((config.max_players, config.whitelist), (3, true));
+        assert_eq!((config.world_seed, config.chat_log.as_ref()), (0, None));
+
+        // Alice isn't on the whitelist
+        server.tick();
+        assert_eq!(server.kicked(alice).as_deref(), Some("You are not whitelisted on this server"));
+    }
+}
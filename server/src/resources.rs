@@ -3,15 +3,26 @@
 // Should preferably be imported from here for consistency and convenience,
 // although in practice there is no difference.
 
+use std::{any::{Any, TypeId}, collections::HashMap};
+
 use hecs::World;
 
-use crate::net::Network;
+use crate::{net::Network, dimensions::Dimensions, game_builder::{Handlers, Stage}, scheduler::Scheduler, world::BlockWorld};
 
 pub struct Resources {
     pub net: Network,
     pub main_world: World,
+    // Of the overworld, see `dimensions`
+    pub blocks: BlockWorld,
+    pub dimensions: Dimensions,
     pub time: Time,
     pub current_tick: u32,
+    // Ticks that ran Stage::Update. Falls behind `current_tick` while frozen, see `tick_control`.
+    pub simulated_ticks: u32,
+    pub handlers: Handlers,
+    pub scheduler: Scheduler,
+    // Anything inserted by plugins through `GameBuilder::insert_resource()`
+    pub extra: ResourceMap,
 }
 
 pub struct Time {
@@ -19,4 +30,36 @@ pub struct Time {
     pub now: std::time::Instant,       // updated at the very start of each frame
     pub ms_u32: u32,
     pub secs_f32: f32,
+    pub stage_durations: [std::time::Duration; Stage::COUNT], // of the previous tick, except for stages already run
+}
+
+#[derive(Default)]
+pub struct ResourceMap {
+    map: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl ResourceMap {
+    pub fn insert<T: 'static>(&mut self, resource: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(resource))
+            .map(|old| *old.downcast::<T>().unwrap())
+    }
+
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .map(|old| *old.downcast::<T>().unwrap())
+    }
+
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.map.get(&TypeId::of::<T>()).map(|r| r.downcast_ref::<T>().unwrap())
+    }
+
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.map.get_mut(&TypeId::of::<T>()).map(|r| r.downcast_mut::<T>().unwrap())
+    }
+
+    pub fn extend(&mut self, other: ResourceMap) {
+        self.map.extend(other.map);
+    }
 }
\ No newline at end of file
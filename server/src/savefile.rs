@@ -0,0 +1,342 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{bail, Context};
+use shared::{
+    bits_and_bytes::ByteReader,
+    chunk_format::{self, WORLD_MAGIC as MAGIC},
+    TICKS_PER_SECOND,
+};
+
+use crate::{
+    config::ServerConfig,
+    game_builder::{GameBuilder, Stage},
+    resources::Resources,
+    scheduler::{self, TaskHandle},
+    world::BlockWorld,
+};
+
+// The blocks of the world are saved to one file (see `ServerConfig::world_file`): MAGIC and
+// the format version, then the body in that version's format. Changing the format means
+// bumping the version by appending a migration from the previous one to MIGRATIONS. Older
+// files are copied aside and migrated one version at a time as they are loaded, and written
+// in the current format on the next save.
+//
+// Version 1 body: the chunks as a list, see shared::chunk_format. Tools such as
+// tools/schem read the current version with that module, which has to know it too.
+
+// Turns the body of a version N file into that of version N + 1
+pub type Migration = fn(&[u8]) -> anyhow::Result<Vec<u8>>;
+
+// MIGRATIONS[0] goes from version 1 to 2, and so on
+pub const MIGRATIONS: &[Migration] = &[];
+pub const FORMAT_VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+const _: () = assert!(FORMAT_VERSION == chunk_format::WORLD_VERSION, "update chunk_format along with the world format");
+
+pub struct WorldSave {
+    path: PathBuf,
+    autosave: Option<TaskHandle>, // None if only saved on shutdown
+    unsaved_changes: bool,
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    let Some(config) = builder.resource::<ServerConfig>() else {
+        return;
+    };
+    let Some(path) = config.world_file.clone() else {
+        return;
+    };
+    let interval = autosave_ticks(config);
+    let autosave = interval.map(|interval| builder.scheduler().every_from(interval, interval, autosave));
+
+    builder
+        .insert_resource(WorldSave { path, autosave, unsaved_changes: false })
+        // Before the changes are cleared, see `server::plugin`
+        .add_system(Stage::PostTick, note_changes)
+        .on_shutdown(save)
+        .on_console_command(save_from_console);
+}
+
+// See `reload`. The next autosave is counted from now.
+pub fn reconfigure(res: &mut Resources, config: &ServerConfig) {
+    let Some(world_save) = res.extra.get_mut::<WorldSave>() else {
+        return;
+    };
+    if let Some(autosave) = world_save.autosave.take() {
+        res.scheduler.cancel(autosave);
+    }
+    let autosave = autosave_ticks(config).map(|interval| scheduler::every(res, interval, autosave));
+    if let Some(world_save) = res.extra.get_mut::<WorldSave>() {
+        world_save.autosave = autosave;
+    }
+}
+
+fn autosave_ticks(config: &ServerConfig) -> Option<u32> {
+    let ticks = config.autosave_interval.saturating_mul(TICKS_PER_SECOND as u64);
+    (ticks > 0).then(|| ticks.min(u32::MAX as u64) as u32)
+}
+
+// An empty world if there is no file yet. Fails on files from newer servers rather than
+// risk overwriting them with something they can't read.
+pub fn load_world(path: &Path) -> anyhow::Result<BlockWorld> {
+    load_world_with(path, MIGRATIONS)
+}
+
+// `load_world()`, as if `migrations` were MIGRATIONS
+pub fn load_world_with(path: &Path, migrations: &[Migration]) -> anyhow::Result<BlockWorld> {
+    let current = migrations.len() as u32 + 1;
+    let file = match fs::read(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            println!("No world at {}, starting a new one", path.display());
+            return Ok(BlockWorld::new());
+        }
+        Err(e) => bail!("Failed to read {}: {e}", path.display()),
+    };
+    let Some((version, body)) = split_header(&file) else {
+        bail!("{} is not a world file", path.display());
+    };
+    if version == 0 || version > current {
+        bail!(
+            "{} is in world format version {version}, but this server only knows versions 1 to {current}. \
+             Was it saved by a newer server?",
+            path.display()
+        );
+    }
+
+    let body = if version < current {
+        let backup = backup_path(path, version);
+        fs::copy(path, &backup).with_context(|| format!("Failed to back up {} before migrating it", path.display()))?;
+        println!(
+            "Migrating {} from world format version {version} to {current}, the original is kept as {}",
+            path.display(),
+            backup.display()
+        );
+        migrate(body, version, migrations)?
+    } else {
+        body.to_vec()
+    };
+    decode_world(&body).with_context(|| format!("{} is corrupt", path.display()))
+}
+
+// Written next to `path` first, so that a crash halfway through leaves the old save intact
+pub fn save_world(path: &Path, blocks: &BlockWorld) -> anyhow::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, encode_world(blocks)).with_context(|| format!("Failed to write {}", Path::new(&temp).display()))?;
+    fs::rename(&temp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+// E.g. world.dat.v1.bak
+pub fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{version}.bak"));
+    PathBuf::from(backup)
+}
+
+fn split_header(file: &[u8]) -> Option<(u32, &[u8])> {
+    let (magic, rest) = file.split_at(MAGIC.len().min(file.len()));
+    if magic != MAGIC || rest.len() < 4 {
+        return None;
+    }
+    let (version, body) = rest.split_at(4);
+    Some((u32::from_le_bytes(version.try_into().unwrap()), body))
+}
+
+fn migrate(body: &[u8], version: u32, migrations: &[Migration]) -> anyhow::Result<Vec<u8>> {
+    let mut body = body.to_vec();
+    for (i, migration) in migrations.iter().enumerate().skip(version as usize - 1) {
+        let from = i + 1;
+        body = migration(&body).with_context(|| format!("Failed to migrate from world format version {from} to {}", from + 1))?;
+    }
+    Ok(body)
+}
+
+fn encode_world(blocks: &BlockWorld) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.extend(FORMAT_VERSION.to_le_bytes());
+    let chunks = blocks.chunk_positions().map(|pos| (pos, blocks.chunk(pos).unwrap())).collect();
+    chunk_format::write_chunks(&mut out, chunks);
+    out
+}
+
+fn decode_world(body: &[u8]) -> anyhow::Result<BlockWorld> {
+    let mut reader = ByteReader::new(body);
+    let mut world = BlockWorld::new();
+    chunk_format::read_chunks(&mut reader, |pos, blocks| world.insert_chunk(pos, blocks))?;
+    if reader.bytes_remaining() > 0 {
+        bail!("{} unexpected bytes at the end", reader.bytes_remaining());
+    }
+    Ok(world)
+}
+
+fn note_changes(res: &mut Resources) -> anyhow::Result<()> {
+    let changed = !res.blocks.changed_blocks().is_empty();
+    if let Some(world_save) = res.extra.get_mut::<WorldSave>() {
+        world_save.unsaved_changes |= changed;
+    }
+    Ok(())
+}
+
+fn autosave(res: &mut Resources) {
+    if res.extra.get::<WorldSave>().map_or(false, |world_save| world_save.unsaved_changes) {
+        save(res);
+    }
+}
+
+fn save(res: &mut Resources) {
+    let Some(world_save) = res.extra.get_mut::<WorldSave>() else {
+        return;
+    };
+    let start = Instant::now();
+    match save_world(&world_save.path, &res.blocks) {
+        Ok(()) => {
+            world_save.unsaved_changes = false;
+            println!("Saved the world to {} in {:?}", world_save.path.display(), start.elapsed());
+        }
+        Err(e) => eprintln!("Failed to save the world: {e:#}"),
+    }
+}
+
+fn save_from_console(res: &mut Resources, command: &str, _args: &str) -> bool {
+    if command != "save" {
+        return false;
+    }
+    if res.extra.get::<WorldSave>().is_none() {
+        println!("The world isn't saved to a file, see world_file in server.cfg");
+    } else {
+        save(res);
+    }
+    true
+}
+
+mod tests {
+    // One file per historical format version, all holding the same world: air except for
+    // block 1 at (1, 2, 3) and block 2 at (-16, 17, 0). Add the new version whenever the
+    // format changes, and keep the old ones.
+    fn savefile_fixtures() -> Vec<(u32, Vec<u8>)> {
+        let le = |xs: &[u16]| xs.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>();
+        let v1 = [
+            &b"VXWD"[..],
+            &1u32.to_le_bytes(),
+            &2u32.to_le_bytes(),
+            // Chunk (-1, 1, 0): (0, 1, 0) within
+            &(-1i32).to_le_bytes(), &1i32.to_le_bytes(), &0i32.to_le_bytes(),
+            &le(&[3, 256, 0, 1, 2, 3839, 0]),
+            // Chunk (0, 0, 0): (1, 2, 3) within, at index (2 * 16 + 3) * 16 + 1
+            &0i32.to_le_bytes(), &0i32.to_le_bytes(), &0i32.to_le_bytes(),
+            &le(&[3, 561, 0, 1, 1, 3534, 0]),
+        ]
+        .concat();
+        vec![(1, v1)]
+    }
+
+    fn savefile_test_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("savefile-test-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_savefile_roundtrip() {
+        use glam::ivec3;
+        use shared::coords::ChunkPos;
+        use super::{load_world, save_world};
+        use crate::world::BlockWorld;
+
+        let path = savefile_test_path("roundtrip");
+        let mut world = BlockWorld::new();
+        world.set_block(ivec3(1, 2, 3), 1);
+        world.set_block(ivec3(-40, 255, 7), 9);
+        world.set_block(ivec3(-40, 254, 7), 9);
+        save_world(&path, &world).unwrap();
+
+        let loaded = load_world(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut positions = loaded.chunk_positions().collect::<Vec<_>>();
+        positions.sort_unstable_by_key(|pos| pos.0.to_array());
+        assert_eq!(positions, [ChunkPos::new(-3, 15, 0), ChunkPos::new(0, 0, 0)]);
+        for pos in positions {
+            assert_eq!(loaded.chunk(pos), world.chunk(pos));
+        }
+        assert!(loaded.changed_blocks().is_empty());
+
+        // Nothing there yet
+        assert_eq!(load_world(&path).unwrap().chunk_positions().count(), 0);
+    }
+
+    #[test]
+    fn test_savefile_fixtures() {
+        use glam::ivec3;
+        use super::{backup_path, load_world, FORMAT_VERSION};
+
+        let fixtures = savefile_fixtures();
+        assert_eq!(fixtures.len() as u32, FORMAT_VERSION, "no fixture for the current format version");
+        for (version, bytes) in fixtures {
+            let path = savefile_test_path(&format!("fixture-v{version}"));
+            std::fs::write(&path, &bytes).unwrap();
+            let world = load_world(&path).unwrap_or_else(|e| panic!("version {version}: {e:#}"));
+
+            assert_eq!(world.block_at(ivec3(1, 2, 3)), 1, "version {version}");
+            assert_eq!(world.block_at(ivec3(-16, 17, 0)), 2, "version {version}");
+            assert_eq!(world.block_at(ivec3(1, 2, 4)), 0, "version {version}");
+            assert_eq!(world.chunk_positions().count(), 2, "version {version}");
+
+            // Migrated files are backed up as they were
+            let backup = backup_path(&path, version);
+            if version < FORMAT_VERSION {
+                assert_eq!(std::fs::read(&backup).unwrap(), bytes);
+                std::fs::remove_file(&backup).unwrap();
+            }
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_savefile_migrations() {
+        use glam::ivec3;
+        use super::{backup_path, load_world, load_world_with, Migration};
+
+        // Pretends that there are versions 2 (with a byte in front of the body) and 3
+        fn v1_to_v2(body: &[u8]) -> anyhow::Result<Vec<u8>> {
+            Ok([&[0xAB][..], body].concat())
+        }
+        fn v2_to_v3(body: &[u8]) -> anyhow::Result<Vec<u8>> {
+            match body.split_first() {
+                Some((&0xAB, rest)) => Ok(rest.to_vec()),
+                _ => anyhow::bail!("not a version 2 body"),
+            }
+        }
+        let migrations: [Migration; 2] = [v1_to_v2, v2_to_v3];
+
+        let (_, v1) = savefile_fixtures().swap_remove(0);
+        let path = savefile_test_path("migrations");
+        std::fs::write(&path, &v1).unwrap();
+        let world = load_world_with(&path, &migrations).unwrap();
+        assert_eq!(world.block_at(ivec3(1, 2, 3)), 1);
+        let backup = backup_path(&path, 1);
+        assert_eq!(std::fs::read(&backup).unwrap(), v1);
+        std::fs::remove_file(&backup).unwrap();
+
+        // A failed migration says which one failed
+        let e = load_world_with(&path, &[v2_to_v3]).unwrap_err();
+        assert!(format!("{e:#}").contains("version 1 to 2"), "{e:#}");
+        std::fs::remove_file(&backup).unwrap();
+
+        // Files from the future are left alone
+        let mut v3 = v1.clone();
+        v3[4..8].copy_from_slice(&3u32.to_le_bytes());
+        std::fs::write(&path, &v3).unwrap();
+        let e = load_world(&path).unwrap_err();
+        assert!(format!("{e:#}").contains("newer server"), "{e:#}");
+        assert!(load_world_with(&path, &migrations).is_ok());
+        std::fs::remove_file(&path).unwrap();
+
+        std::fs::write(&path, b"not a world").unwrap();
+        assert!(load_world(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+use std::mem;
+
+use crate::{
+    game_builder::{GameBuilder, Stage},
+    resources::Resources,
+};
+
+// Closures run at a later tick, once or every so many ticks, for timers that would
+// otherwise each keep a counter or an Instant of their own and check it every tick. Due
+// tasks run during Stage::NetIn, after what came in from the network, so that whatever
+// they change goes out the same tick. Ticks are `Resources::current_tick`, which keeps
+// counting while the simulation is frozen (see `tick_control`).
+//
+// Scheduling returns a handle for cancelling the task, which also works from within a
+// task, including a repeating one cancelling itself.
+pub type Task = Box<dyn FnMut(&mut Resources)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TaskHandle(u64);
+
+struct ScheduledTask {
+    handle: TaskHandle,
+    due: u32,
+    // Ticks between runs, if repeating
+    interval: Option<u32>,
+    task: Task,
+}
+
+#[derive(Default)]
+pub struct Scheduler {
+    // Not sorted, there are a few per player at most
+    tasks: Vec<ScheduledTask>,
+    // Those running this tick that haven't yet, last first
+    due: Vec<ScheduledTask>,
+    running: Option<TaskHandle>,
+    running_cancelled: bool,
+    next_handle: u64,
+}
+
+impl Scheduler {
+    // Runs `task` once during `tick`, or the next run of the scheduler if that has passed
+    pub fn at(&mut self, tick: u32, task: impl FnMut(&mut Resources) + 'static) -> TaskHandle {
+        self.push(tick, None, Box::new(task))
+    }
+
+    // Runs `task` during `first_tick` and every `interval` ticks after that
+    pub fn every_from(&mut self, first_tick: u32, interval: u32, task: impl FnMut(&mut Resources) + 'static) -> TaskHandle {
+        self.push(first_tick, Some(interval.max(1)), Box::new(task))
+    }
+
+    // Returns false if the task already ran (if not repeating) or was cancelled
+    pub fn cancel(&mut self, handle: TaskHandle) -> bool {
+        if self.running == Some(handle) {
+            let cancelled = !self.running_cancelled;
+            self.running_cancelled = true;
+            return cancelled;
+        }
+        let count = self.tasks.len() + self.due.len();
+        self.tasks.retain(|task| task.handle != handle);
+        self.due.retain(|task| task.handle != handle);
+        self.tasks.len() + self.due.len() < count
+    }
+
+    fn push(&mut self, due: u32, interval: Option<u32>, task: Task) -> TaskHandle {
+        let handle = TaskHandle(self.next_handle);
+        self.next_handle += 1;
+        self.tasks.push(ScheduledTask { handle, due, interval, task });
+        handle
+    }
+}
+
+// `ticks` from now. Scheduled with zero ticks from within a task, it runs the next tick.
+pub fn after(res: &mut Resources, ticks: u32, task: impl FnMut(&mut Resources) + 'static) -> TaskHandle {
+    let tick = res.current_tick + ticks;
+    res.scheduler.at(tick, task)
+}
+
+// Every `interval` ticks, starting `interval` ticks from now
+pub fn every(res: &mut Resources, interval: u32, task: impl FnMut(&mut Resources) + 'static) -> TaskHandle {
+    let tick = res.current_tick + interval.max(1);
+    res.scheduler.every_from(tick, interval, task)
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    builder.add_system(Stage::NetIn, run_due_tasks);
+}
+
+fn run_due_tasks(res: &mut Resources) -> anyhow::Result<()> {
+    let now = res.current_tick;
+    let scheduler = &mut res.scheduler;
+    let (mut due, pending): (Vec<_>, Vec<_>) = mem::take(&mut scheduler.tasks).into_iter().partition(|task| task.due <= now);
+    // In the order they were due, then scheduled
+    due.sort_unstable_by_key(|task| std::cmp::Reverse((task.due, task.handle.0)));
+    scheduler.tasks = pending;
+    scheduler.due = due;
+
+    while let Some(mut task) = res.scheduler.due.pop() {
+        res.scheduler.running = Some(task.handle);
+        (task.task)(res);
+
+        let scheduler = &mut res.scheduler;
+        scheduler.running = None;
+        let cancelled = mem::take(&mut scheduler.running_cancelled);
+        if let Some(interval) = task.interval {
+            if !cancelled {
+                task.due = now + interval;
+                scheduler.tasks.push(task);
+            }
+        }
+    }
+    Ok(())
+}
+
+mod tests {
+    #[test]
+    fn test_scheduler() {
+        use std::{cell::Cell, rc::Rc};
+        use crate::{scheduler, testing::TestServer};
+
+        let mut server = TestServer::new();
+        let (once, repeating, cancelled) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(0)), Rc::new(Cell::new(0)));
+
+        let counter = once.clone();
+        scheduler::after(&mut server.res, 3, move |_| counter.set(counter.get() + 1));
+        let counter = cancelled.clone();
+        let handle = scheduler::after(&mut server.res, 3, move |_| counter.set(counter.get() + 1));
+        assert!(server.res.scheduler.cancel(handle));
+        assert!(!server.res.scheduler.cancel(handle));
+
+        // Cancels itself on the third run
+        let counter = repeating.clone();
+        let handle = Rc::new(Cell::new(None));
+        let own_handle = handle.clone();
+        handle.set(Some(scheduler::every(&mut server.res, 2, move |res| {
+            counter.set(counter.get() + 1);
+            if counter.get() == 3 {
+                assert!(res.scheduler.cancel(own_handle.get().unwrap()));
+            }
+        })));
+
+        server.run_ticks(3);
+        assert_eq!((once.get(), repeating.get()), (0, 1));
+        server.tick();
+        assert_eq!((once.get(), repeating.get()), (1, 1));
+        server.run_ticks(20);
+        assert_eq!((once.get(), repeating.get(), cancelled.get()), (1, 3, 0));
+        assert!(!server.res.scheduler.cancel(handle.get().unwrap()));
+    }
+}
@@ -0,0 +1,190 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{bail, Context};
+use glam::IVec3;
+use hecs::Entity;
+use shared::chunk_format::Schematic;
+
+use crate::{
+    components::{PlayerId, Position},
+    game_builder::GameBuilder,
+    resources::Resources,
+    world::BlockWorld,
+};
+
+// Copying boxes of blocks to files in SCHEMATICS_DIR and pasting them elsewhere, for building
+// test scenes and sharing structures. The files are in shared::chunk_format, which
+// tools/schem also reads, e.g. to cut schematics out of a world file offline.
+//
+//   /schem export <name> <x1 y1 z1> <x2 y2 z2>   the box between the two corners, inclusive
+//   /schem import <name> [<x y z>]               minimum corner at the position, or the player
+//
+// The console takes the same without the slash; there, import needs the position.
+
+pub const SCHEMATICS_DIR: &str = "schematics";
+// Blocks, per export or import. Imports all happen in one tick and go out to clients as
+// block changes.
+pub const MAX_VOLUME: i64 = 1 << 20;
+
+const USAGE: &str = "Usage: /schem export <name> <x1 y1 z1> <x2 y2 z2>, /schem import <name> [<x y z>]";
+
+pub fn plugin(builder: &mut GameBuilder) {
+    builder
+        .require_op("/schem")
+        .on_chat(schem_from_chat)
+        .on_console_command(schem_from_console);
+}
+
+// The box between `a` and `b`, both inclusive. None if it's bigger than MAX_VOLUME.
+pub fn export(blocks: &BlockWorld, a: IVec3, b: IVec3) -> Option<Schematic> {
+    let (min, max) = (a.min(b), a.max(b));
+    let size = max - min + 1;
+    if volume(size) > MAX_VOLUME {
+        return None;
+    }
+    let mut schematic = Schematic::new(size)?;
+    for y in 0..size.y {
+        for z in 0..size.z {
+            for x in 0..size.x {
+                let pos = IVec3::new(x, y, z);
+                schematic.set_block(pos, blocks.block_at(min + pos));
+            }
+        }
+    }
+    Some(schematic)
+}
+
+// Overwrites the box with its minimum corner at `origin`, air included. Blocks above or below
+// the world are left out. Returns how many blocks changed.
+pub fn import(blocks: &mut BlockWorld, schematic: &Schematic, origin: IVec3) -> usize {
+    let size = schematic.size();
+    let mut changed = 0;
+    for y in 0..size.y {
+        for z in 0..size.z {
+            for x in 0..size.x {
+                let (pos, block) = (origin + IVec3::new(x, y, z), schematic.block_at(IVec3::new(x, y, z)));
+                if blocks.block_at(pos) != block && blocks.set_block(pos, block) {
+                    changed += 1;
+                }
+            }
+        }
+    }
+    changed
+}
+
+fn volume(size: IVec3) -> i64 {
+    size.x as i64 * size.y as i64 * size.z as i64
+}
+
+pub fn path_of(name: &str) -> anyhow::Result<PathBuf> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        bail!("Schematic names are up to 64 letters, digits, '_' and '-'");
+    }
+    Ok(PathBuf::from(SCHEMATICS_DIR).join(format!("{name}.schem")))
+}
+
+fn schem_from_chat(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    let (command, args) = message.split_once(' ').unwrap_or((message, ""));
+    if command != "/schem" {
+        return false;
+    }
+    let Ok((&player_id, &Position(position))) = res.main_world.query_one_mut::<(&PlayerId, &Position)>(sender) else {
+        return true;
+    };
+    let reply = match run(res, args, Some(position.floor().as_ivec3())) {
+        Ok(reply) => reply,
+        Err(e) => format!("{e:#}"),
+    };
+    res.net.send_chat(player_id, reply.into());
+    true
+}
+
+fn schem_from_console(res: &mut Resources, command: &str, args: &str) -> bool {
+    if command != "schem" {
+        return false;
+    }
+    match run(res, args, None) {
+        Ok(reply) => println!("{reply}"),
+        Err(e) => println!("{e:#}"),
+    }
+    true
+}
+
+// `here`: where imports go without a position, if anywhere
+fn run(res: &mut Resources, args: &str, here: Option<IVec3>) -> anyhow::Result<String> {
+    let mut args = args.split_whitespace();
+    let (Some(action), Some(name)) = (args.next(), args.next()) else {
+        bail!(USAGE);
+    };
+    let path = path_of(name)?;
+    let coords = args.map(str::parse::<i32>).collect::<Result<Vec<_>, _>>().ok();
+
+    match (action, coords.as_deref()) {
+        ("export", Some(&[x1, y1, z1, x2, y2, z2])) => {
+            let Some(schematic) = export(&res.blocks, IVec3::new(x1, y1, z1), IVec3::new(x2, y2, z2)) else {
+                bail!("Too big, the limit is {MAX_VOLUME} blocks");
+            };
+            fs::create_dir_all(SCHEMATICS_DIR).with_context(|| format!("Failed to create {SCHEMATICS_DIR}"))?;
+            fs::write(&path, schematic.encode()).with_context(|| format!("Failed to write {}", path.display()))?;
+            let size = schematic.size();
+            Ok(format!("Exported {}x{}x{} blocks to {}", size.x, size.y, size.z, path.display()))
+        }
+        ("import", Some(coords)) => {
+            let origin = match *coords {
+                [x, y, z] => IVec3::new(x, y, z),
+                [] => here.context(USAGE)?,
+                _ => bail!(USAGE),
+            };
+            let file = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            let schematic = Schematic::decode(&file).with_context(|| format!("{} is corrupt", path.display()))?;
+            if volume(schematic.size()) > MAX_VOLUME {
+                bail!("{} is too big, the limit is {MAX_VOLUME} blocks", path.display());
+            }
+            let changed = import(&mut res.blocks, &schematic, origin);
+            Ok(format!("Imported {name} at {origin}, {changed} blocks changed"))
+        }
+        _ => bail!(USAGE),
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_schematics() {
+        use glam::ivec3;
+        use shared::chunk_format::Schematic;
+        use super::{export, import, path_of};
+        use crate::world::BlockWorld;
+
+        let mut world = BlockWorld::new();
+        world.set_block(ivec3(-1, 10, 3), 1);
+        world.set_block(ivec3(2, 12, 20), 2);
+        world.set_block(ivec3(5, 10, 5), 3); // outside the box
+
+        // Corners in any order
+        let schematic = export(&world, ivec3(2, 12, 20), ivec3(-1, 10, 3)).unwrap();
+        assert_eq!(schematic.size(), ivec3(4, 3, 18));
+        let schematic = Schematic::decode(&schematic.encode()).unwrap();
+
+        // Air in the box overwrites what was there
+        world.set_block(ivec3(101, 50, 101), 4);
+        world.clear_changes();
+        assert_eq!(import(&mut world, &schematic, ivec3(100, 50, 100)), 3);
+        assert_eq!(world.block_at(ivec3(100, 50, 100)), 1);
+        assert_eq!(world.block_at(ivec3(103, 52, 117)), 2);
+        assert_eq!(world.block_at(ivec3(101, 50, 101)), 0);
+        assert_eq!(world.changed_blocks().len(), 3);
+        // Nothing to change the second time
+        assert_eq!(import(&mut world, &schematic, ivec3(100, 50, 100)), 0);
+
+        // Whatever sticks out of the world is cut off
+        assert_eq!(import(&mut world, &schematic, ivec3(0, -2, 0)), 1);
+        assert_eq!(world.block_at(ivec3(3, 0, 17)), 2);
+
+        assert!(export(&world, ivec3(0, 0, 0), ivec3(1023, 255, 1023)).is_none());
+        assert!(path_of("castle_2").is_ok());
+        assert!(path_of("../server").is_err());
+    }
+}
@@ -0,0 +1,477 @@
+// Server-side Lua scripts. Every `*.lua` file in the scripts directory is run in its own
+// sandboxed environment, and may define any of these hooks as global functions:
+//
+//   on_chat(player, message) -> bool             return true to stop the message from being broadcast
+//   on_player_join(player)
+//   on_block_place(player, x, y, z, block) -> bool   return true to cancel
+//
+// Scripts can call:
+//
+//   send_message(player, text)    player = nil broadcasts to everybody
+//   set_block(x, y, z, block)
+//   break_block(x, y, z)          like set_block() to air, but drops the block as an item
+//
+// Blocks are set and broken in the dimension of the player the hook was called for, or the
+// overworld when there is no such player, as when the script is loaded.
+//
+// What each script is allowed to do is decided by the server owner in `capabilities.cfg`, one
+// script per line: `<script name> = chat, broadcast, players, blocks`. Scripts not listed there
+// get no capabilities. Use the `scripts reload` console command to reload after editing.
+//
+// Loading a script and each hook call get SCRIPT_TIME_LIMIT, after which the script errors out
+// as if it had called error(), so that an endless loop can't hang the server. All scripts
+// together get SCRIPT_MEMORY_LIMIT. pcall and xpcall aren't available, so neither error can be
+// caught by the script.
+
+use std::{
+    cell::{Cell, RefCell},
+    ops::BitOr,
+    path::PathBuf,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use glam::IVec3;
+use hecs::Entity;
+use mlua::{FromLuaMulti, Function, HookTriggers, Lua, RegistryKey, Table, ToLuaMulti, Value, Variadic};
+use shared::dimension::DimensionId;
+
+use crate::{
+    chat,
+    components::{PlayerId, Username},
+    dimensions,
+    items,
+    game_builder::GameBuilder,
+    resources::Resources,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Capabilities(u8);
+
+impl Capabilities {
+    pub const NONE: Self = Self(0);
+    pub const CHAT: Self = Self(1 << 0);      // on_chat, send_message() to a single player
+    pub const BROADCAST: Self = Self(1 << 1); // send_message() to everybody
+    pub const PLAYERS: Self = Self(1 << 2);   // on_player_join
+    pub const BLOCKS: Self = Self(1 << 3);    // on_block_place, set_block(), break_block()
+
+    const NAMES: [(&'static str, Self); 4] = [
+        ("chat", Self::CHAT),
+        ("broadcast", Self::BROADCAST),
+        ("players", Self::PLAYERS),
+        ("blocks", Self::BLOCKS),
+    ];
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter().find(|(n, _)| *n == name).map(|(_, c)| *c)
+    }
+
+    pub fn name(self) -> &'static str {
+        Self::NAMES.iter().find(|(_, c)| *c == self).map_or("<multiple>", |(n, _)| *n)
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+// Globals copied into each script's environment. Notably excludes io, os, require, load & co,
+// and pcall/xpcall, which would let a script catch the time and memory limit errors.
+const SAFE_GLOBALS: [&str; 9] = [
+    "assert", "error", "ipairs", "next", "pairs", "select", "tonumber", "tostring", "type",
+];
+// Copied table by table, so that a script replacing e.g. string.format only affects itself
+const SAFE_LIBRARIES: [&str; 4] = ["math", "string", "table", "utf8"];
+
+const SCRIPT_TIME_LIMIT: Duration = Duration::from_millis(20);
+const SCRIPT_MEMORY_LIMIT: usize = 16 << 20;
+// How often the time limit is checked
+const INSTRUCTIONS_PER_CHECK: u32 = 1000;
+
+// Scripts can't touch `Resources` directly; API calls are queued and applied once the hook returns
+enum ScriptAction {
+    SendMessage { to: Option<String>, text: String },
+    SetBlock { dimension: DimensionId, pos: IVec3, block: u16 },
+    BreakBlock { dimension: DimensionId, pos: IVec3 },
+}
+
+struct Script {
+    name: String,
+    capabilities: Capabilities,
+    env: RegistryKey,
+}
+
+pub struct ScriptHost {
+    dir: PathBuf,
+    lua: Lua,
+    scripts: Vec<Script>,
+    actions: Rc<RefCell<Vec<ScriptAction>>>,
+    // Where set_block() and break_block() act, see `with_host`
+    dimension: Rc<Cell<DimensionId>>,
+    // Of whatever the scripts are running, see SCRIPT_TIME_LIMIT
+    deadline: Rc<Cell<Instant>>,
+}
+
+impl ScriptHost {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            lua: Lua::new(),
+            scripts: Vec::new(),
+            actions: Rc::new(RefCell::new(Vec::new())),
+            dimension: Rc::new(Cell::new(DimensionId::OVERWORLD)),
+            deadline: Rc::new(Cell::new(Instant::now())),
+        }
+    }
+
+    // Throws away all script state and loads everything from disk again. Scripts that
+    // fail to load are skipped.
+    pub fn reload(&mut self) -> anyhow::Result<()> {
+        self.scripts.clear();
+        self.actions.borrow_mut().clear();
+        self.lua = Lua::new();
+        self.lua.set_memory_limit(SCRIPT_MEMORY_LIMIT)?;
+        let deadline = self.deadline.clone();
+        let triggers = HookTriggers { every_nth_instruction: Some(INSTRUCTIONS_PER_CHECK), ..Default::default() };
+        self.lua.set_hook(triggers, move |_, _| match Instant::now() > deadline.get() {
+            true => Err(mlua::Error::RuntimeError(format!("took longer than {SCRIPT_TIME_LIMIT:?}"))),
+            false => Ok(()),
+        })?;
+
+        if !self.dir.is_dir() {
+            println!("No scripts directory at {}, not loading scripts", self.dir.display());
+            return Ok(());
+        }
+
+        let capabilities = match std::fs::read_to_string(self.dir.join("capabilities.cfg")) {
+            Ok(config) => parse_capabilities(&config),
+            Err(_) => Vec::new(),
+        };
+
+        let mut paths = std::fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().map_or(false, |ext| ext == "lua"))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let caps = capabilities.iter()
+                .find(|(n, _)| n == name)
+                .map_or(Capabilities::NONE, |(_, c)| *c);
+
+            let source = match std::fs::read_to_string(&path) {
+                Ok(source) => source,
+                Err(e) => {
+                    eprintln!("Failed to read script {}: {e}", path.display());
+                    continue;
+                }
+            };
+
+            match self.load_script(name, &source, caps) {
+                Ok(env) => {
+                    println!("Loaded script '{name}'");
+                    self.scripts.push(Script {
+                        name: name.to_owned(),
+                        capabilities: caps,
+                        env,
+                    });
+                }
+                Err(e) => eprintln!("Failed to load script '{name}': {e}"),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn script_names(&self) -> impl Iterator<Item = (&str, Capabilities)> {
+        self.scripts.iter().map(|s| (s.name.as_str(), s.capabilities))
+    }
+
+    fn load_script(&self, name: &str, source: &str, caps: Capabilities) -> mlua::Result<RegistryKey> {
+        let lua = &self.lua;
+        let globals = lua.globals();
+        let env = lua.create_table()?;
+        for global in SAFE_GLOBALS {
+            env.set(global, globals.get::<_, Value>(global)?)?;
+        }
+        for library in SAFE_LIBRARIES {
+            let copy = lua.create_table()?;
+            for pair in globals.get::<_, Table>(library)?.pairs::<Value, Value>() {
+                let (key, value) = pair?;
+                copy.set(key, value)?;
+            }
+            env.set(library, copy)?;
+        }
+
+        let script_name = name.to_owned();
+        env.set("print", lua.create_function(move |_, args: Variadic<String>| {
+            println!("[{script_name}] {}", args.join("\t"));
+            Ok(())
+        })?)?;
+
+        let actions = self.actions.clone();
+        env.set("send_message", lua.create_function(move |_, (to, text): (Option<String>, String)| {
+            require(caps, if to.is_some() { Capabilities::CHAT } else { Capabilities::BROADCAST }, "send_message")?;
+            actions.borrow_mut().push(ScriptAction::SendMessage { to, text });
+            Ok(())
+        })?)?;
+
+        let (actions, dimension) = (self.actions.clone(), self.dimension.clone());
+        env.set("set_block", lua.create_function(move |_, (x, y, z, block): (i32, i32, i32, u16)| {
+            require(caps, Capabilities::BLOCKS, "set_block")?;
+            actions.borrow_mut().push(ScriptAction::SetBlock { dimension: dimension.get(), pos: IVec3::new(x, y, z), block });
+            Ok(())
+        })?)?;
+
+        let (actions, dimension) = (self.actions.clone(), self.dimension.clone());
+        env.set("break_block", lua.create_function(move |_, (x, y, z): (i32, i32, i32)| {
+            require(caps, Capabilities::BLOCKS, "break_block")?;
+            actions.borrow_mut().push(ScriptAction::BreakBlock { dimension: dimension.get(), pos: IVec3::new(x, y, z) });
+            Ok(())
+        })?)?;
+
+        self.deadline.set(Instant::now() + SCRIPT_TIME_LIMIT);
+        lua.load(source)
+            .set_name(name)?
+            .set_environment(env.clone())?
+            .exec()?;
+
+        lua.create_registry_value(env)
+    }
+
+    pub fn on_chat(&self, player: &str, message: &str) -> bool {
+        self.scripts.iter()
+            .filter(|s| s.capabilities.contains(Capabilities::CHAT))
+            .any(|s| self.call_hook::<_, Option<bool>>(s, "on_chat", (player, message)).flatten().unwrap_or(false))
+    }
+
+    pub fn on_player_join(&self, player: &str) {
+        for script in self.scripts.iter().filter(|s| s.capabilities.contains(Capabilities::PLAYERS)) {
+            self.call_hook::<_, ()>(script, "on_player_join", player);
+        }
+    }
+
+    pub fn on_block_place(&self, player: &str, pos: IVec3, block: u16) -> bool {
+        self.scripts.iter()
+            .filter(|s| s.capabilities.contains(Capabilities::BLOCKS))
+            .any(|s| self.call_hook::<_, Option<bool>>(s, "on_block_place", (player, pos.x, pos.y, pos.z, block))
+                .flatten()
+                .unwrap_or(false))
+    }
+
+    // Returns None if the script doesn't define the hook or the hook threw an error
+    fn call_hook<'lua, A, R>(&'lua self, script: &Script, hook: &str, args: A) -> Option<R>
+    where
+        A: ToLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        self.deadline.set(Instant::now() + SCRIPT_TIME_LIMIT);
+        let result = self.lua.registry_value::<Table>(&script.env)
+            .and_then(|env| env.get::<_, Option<Function>>(hook))
+            .and_then(|hook| match hook {
+                Some(hook) => hook.call::<A, R>(args).map(Some),
+                None => Ok(None),
+            });
+
+        match result {
+            Ok(ret) => ret,
+            Err(e) => {
+                eprintln!("Script '{}': error in {hook}: {e}", script.name);
+                None
+            }
+        }
+    }
+
+    fn apply_actions(&self, res: &mut Resources) {
+        for action in self.actions.borrow_mut().drain(..) {
+            match action {
+                ScriptAction::SendMessage { to: None, text } => chat::broadcast(res, text.into()),
+                ScriptAction::SendMessage { to: Some(to), text } => {
+                    let target = res.main_world.query_mut::<(&Username, &PlayerId)>()
+                        .into_iter()
+                        .find(|(_, (name, _))| name.0.as_str() == to)
+                        .map(|(_, (_, &id))| id);
+                    match target {
+                        Some(id) => res.net.send_chat(id, text.into()),
+                        None => eprintln!("send_message: no player named '{to}'"),
+                    }
+                }
+                ScriptAction::SetBlock { dimension, pos, block } => {
+                    if !dimensions::blocks_mut(res, dimension).set_block(pos, block) {
+                        eprintln!("set_block({pos}, {block}): outside of the world");
+                    }
+                }
+                ScriptAction::BreakBlock { dimension, pos } => {
+                    items::break_block(res, dimension, pos);
+                }
+            }
+        }
+    }
+}
+
+fn require(caps: Capabilities, needed: Capabilities, function: &str) -> mlua::Result<()> {
+    if caps.contains(needed) {
+        Ok(())
+    } else {
+        Err(mlua::Error::RuntimeError(format!("{function}: script lacks the '{}' capability", needed.name())))
+    }
+}
+
+fn parse_capabilities(config: &str) -> Vec<(String, Capabilities)> {
+    let mut result = Vec::new();
+    for line in config.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let Some((name, caps)) = line.split_once('=') else {
+            eprintln!("capabilities.cfg: expected '<script> = <capability>, ...', got '{line}'");
+            continue;
+        };
+
+        let mut capabilities = Capabilities::NONE;
+        for cap in caps.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            match Capabilities::from_name(cap) {
+                Some(c) => capabilities = capabilities | c,
+                None => eprintln!("capabilities.cfg: unknown capability '{cap}'"),
+            }
+        }
+        result.push((name.trim().to_owned(), capabilities));
+    }
+    result
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    let mut host = ScriptHost::new(PathBuf::from("scripts"));
+    if let Err(e) = host.reload() {
+        eprintln!("Failed to load scripts: {e}");
+    }
+
+    builder
+        .insert_resource(host)
+        .on_chat(on_chat)
+        .on_player_join(on_player_join)
+        .on_block_place(on_block_place)
+        .on_console_command(on_console_command);
+}
+
+// The host is taken out of `res` for the duration of the call so that the queued
+// actions can be applied to everything else. Blocks are changed in `dimension`.
+fn with_host<R>(res: &mut Resources, dimension: DimensionId, f: impl FnOnce(&mut ScriptHost) -> R) -> Option<R> {
+    let mut host = res.extra.remove::<ScriptHost>()?;
+    host.dimension.set(dimension);
+    let ret = f(&mut host);
+    host.apply_actions(res);
+    res.extra.insert(host);
+    Some(ret)
+}
+
+fn username(res: &Resources, player: Entity) -> Option<String> {
+    res.main_world.get::<&Username>(player).ok().map(|name| name.0.to_string())
+}
+
+fn on_chat(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    let Some(player) = username(res, sender) else {
+        return false;
+    };
+    let dimension = dimensions::of(res, sender);
+    with_host(res, dimension, |host| host.on_chat(&player, message)).unwrap_or(false)
+}
+
+fn on_player_join(res: &mut Resources, player: Entity) {
+    if let Some(name) = username(res, player) {
+        let dimension = dimensions::of(res, player);
+        with_host(res, dimension, |host| host.on_player_join(&name));
+    }
+}
+
+fn on_block_place(res: &mut Resources, player: Entity, pos: IVec3, block: u16) -> bool {
+    let Some(name) = username(res, player) else {
+        return false;
+    };
+    let dimension = dimensions::of(res, player);
+    with_host(res, dimension, |host| host.on_block_place(&name, pos, block)).unwrap_or(false)
+}
+
+fn on_console_command(res: &mut Resources, command: &str, args: &str) -> bool {
+    if command != "scripts" {
+        return false;
+    }
+
+    with_host(res, DimensionId::OVERWORLD, |host| match args {
+        "reload" => match host.reload() {
+            Ok(()) => println!("Reloaded {} script(s)", host.scripts.len()),
+            Err(e) => eprintln!("Failed to reload scripts: {e}"),
+        },
+        "list" => {
+            for (name, caps) in host.script_names() {
+                let caps = Capabilities::NAMES.iter()
+                    .filter(|(_, c)| caps.contains(*c))
+                    .map(|(n, _)| *n)
+                    .collect::<Vec<_>>();
+                println!("{name}: {}", caps.join(", "));
+            }
+        }
+        _ => println!("Usage: scripts <reload|list>"),
+    });
+    true
+}
+
+mod tests {
+    #[test]
+    fn test_script_sandbox() {
+        use std::time::Instant;
+        use super::{ScriptHost, SCRIPT_TIME_LIMIT};
+
+        let dir = std::env::temp_dir().join(format!("script-sandbox-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("capabilities.cfg"), "a = chat\nb = chat\n").unwrap();
+        // Breaks its own copy of the string library, then hangs
+        std::fs::write(dir.join("a.lua"), "string.upper = nil\nfunction on_chat(player, message) while true do end end\n").unwrap();
+        std::fs::write(dir.join("b.lua"), "function on_chat(player, message) return string.upper(message) == 'HI' end\n").unwrap();
+        let mut host = ScriptHost::new(dir.clone());
+        host.reload().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(host.script_names().count(), 2);
+
+        let start = Instant::now();
+        assert!(host.on_chat("alice", "hi"));
+        assert!(start.elapsed() < SCRIPT_TIME_LIMIT * 10);
+    }
+
+    #[test]
+    fn test_script_limits_uncatchable() {
+        use std::time::Instant;
+        use super::{ScriptHost, SCRIPT_TIME_LIMIT};
+
+        let dir = std::env::temp_dir().join(format!("script-limits-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("capabilities.cfg"), "a = chat\nb = chat\n").unwrap();
+        // Both would return true if they could catch the error
+        std::fs::write(dir.join("a.lua"), "function on_chat(player, message) pcall(function() while true do end end) return true end\n").unwrap();
+        std::fs::write(dir.join("b.lua"), "function on_chat(player, message) pcall(string.rep, 'x', 1 << 30) return true end\n").unwrap();
+        let mut host = ScriptHost::new(dir.clone());
+        host.reload().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let start = Instant::now();
+        assert!(!host.on_chat("alice", "hi"));
+        assert!(start.elapsed() < SCRIPT_TIME_LIMIT * 10);
+
+        // Without pcall
+        let dir = std::env::temp_dir().join(format!("script-limits-test-2-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("capabilities.cfg"), "c = chat\n").unwrap();
+        std::fs::write(dir.join("c.lua"), "function on_chat(player, message) local s = string.rep('x', 1 << 30) return true end\n").unwrap();
+        let mut host = ScriptHost::new(dir.clone());
+        host.reload().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(!host.on_chat("alice", "hi"));
+    }
+}
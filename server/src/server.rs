@@ -1,21 +1,43 @@
-use std::{time::Instant, net::SocketAddr};
+use std::time::{Instant, Duration};
 
-use crate::{resources::{Resources, Time}, net, components::{Position, OldPosition, HeadYawPitch}};
+use crate::{
+    resources::{Resources, Time, ResourceMap},
+    net::{self, Network}, afk, authority, block_updates, chat, console, scripting, metrics, spawning, pathfinding, stress, teleport, moderation, permissions, random_tick, reload, fluids, items, join_queue, shutdown, savefile, schematics, tick_control,
+    config::ServerConfig,
+    world::BlockWorld,
+    dimensions::{self, Dimensions},
+    components::{Position, OldPosition, HeadYawPitch, Metadata},
+    game_builder::{GameBuilder, Stage, TickSchedule, Handlers, self},
+    scheduler::{self, Scheduler},
+};
 
 use anyhow::Result;
 use glam::Vec2;
 use hecs::World;
-use shared::protocol;
+use shared::{dimension::{self, DimensionId}, protocol};
 
-pub fn tick(res: &mut Resources) -> anyhow::Result<()> {
-    let now = Instant::now();
+pub fn plugin(builder: &mut GameBuilder) {
+    builder
+        .add_system(Stage::PostTick, round_movement_deltas)
+        .add_system(Stage::PostTick, clear_metadata_changes)
+        .add_system(Stage::PostTick, clear_block_changes);
+}
+
+pub fn tick(res: &mut Resources, schedule: &TickSchedule) -> anyhow::Result<()> {
+    tick_at(res, schedule, Instant::now())
+}
+
+// For running with a simulated clock (see `testing`)
+pub fn tick_at(res: &mut Resources, schedule: &TickSchedule, now: Instant) -> anyhow::Result<()> {
     let time_res = &mut res.time;
     time_res.now = now;
     time_res.secs_f32 = (now - time_res.at_launch).as_secs_f32();
     time_res.ms_u32 = (now - time_res.at_launch).as_millis() as u32;
 
-    net::tick(res)?;
+    schedule.run(res)
+}
 
+fn round_movement_deltas(res: &mut Resources) -> anyhow::Result<()> {
     // TODO: This could probably be done only just before an entity moves, assuming
     // entity moves is handled in few places.
     for (_, (&Position(new_pos), OldPosition(old_pos), head_rot)) 
@@ -27,27 +49,108 @@ pub fn tick(res: &mut Resources) -> anyhow::Result<()> {
 
         *old_pos += protocol::round_velocity(new_pos - *old_pos);
     }
+    Ok(())
+}
 
+// Changes were sent out during Stage::NetOut
+fn clear_metadata_changes(res: &mut Resources) -> anyhow::Result<()> {
+    for (_, metadata) in res.main_world.query_mut::<&mut Metadata>() {
+        metadata.clear_changed();
+    }
+    Ok(())
+}
 
+// Changes were sent out during Stage::NetOut, and noted by `savefile`
+fn clear_block_changes(res: &mut Resources) -> anyhow::Result<()> {
+    res.blocks.clear_changes();
+    for (_, blocks) in res.dimensions.others_mut() {
+        blocks.clear_changes();
+    }
     Ok(())
 }
 
-pub fn shutdown(res: Resources) {
-    
+// How long closing the connections may take before the server stops regardless
+const NETWORK_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Call once the main loop has stopped, whether or not the countdown (see `shutdown`) ran
+pub fn shutdown(mut res: Resources) {
+    game_builder::dispatch_shutdown(&mut res);
+    res.net.shutdown(NETWORK_SHUTDOWN_TIMEOUT);
 }
 
-pub fn init(address: SocketAddr) -> Result<Resources> {
+pub fn init(config: ServerConfig) -> Result<(Resources, TickSchedule)> {
+    // Before binding the socket, so that nobody gets to connect to a server that won't start
+    let mut blocks = match &config.world_file {
+        Some(path) => savefile::load_world(path)?,
+        None => BlockWorld::new(),
+    };
+    let world_seed = config.world_seed;
+    let net = net::init(config.bind_address)?;
+    let (mut res, schedule) = init_with_network(config, net);
+    // The same terrain as clients generate. Left out of init_with_network(), so that tests
+    // start out with nothing but air.
+    blocks.set_terrain_seed(Some(dimension::seed(world_seed, DimensionId::OVERWORLD)));
+    res.blocks = blocks;
+    for (dimension, blocks) in res.dimensions.others_mut() {
+        blocks.set_terrain_seed(Some(dimension::seed(world_seed, dimension)));
+    }
+    Ok((res, schedule))
+}
+
+pub fn init_with_network(config: ServerConfig, net: Network) -> (Resources, TickSchedule) {
     let now = Instant::now();
 
-    Ok(Resources {
-        net: crate::net::init(address)?,
+    let mut res = Resources {
+        net,
         main_world: World::new(),
+        blocks: BlockWorld::new(),
+        dimensions: Dimensions::new(),
         time: Time {
             at_launch: now,
             now,
             ms_u32: 0,
             secs_f32: 0.0,
+            stage_durations: [Duration::ZERO; Stage::COUNT],
         },
         current_tick: 0,
-    })
+        simulated_ticks: 0,
+        handlers: Handlers::default(),
+        scheduler: Scheduler::default(),
+        extra: ResourceMap::default(),
+    };
+
+    let mut builder = GameBuilder::new();
+    builder
+        .insert_resource(config)
+        .add_plugin(net::plugin)
+        .add_plugin(join_queue::plugin)
+        .add_plugin(console::plugin)
+        // Before any chat commands, see `afk::plugin`
+        .add_plugin(afk::plugin)
+        .add_plugin(scripting::plugin)
+        .add_plugin(spawning::plugin)
+        .add_plugin(stress::plugin)
+        .add_plugin(pathfinding::plugin)
+        .add_plugin(teleport::plugin)
+        .add_plugin(dimensions::plugin)
+        .add_plugin(authority::plugin)
+        .add_plugin(moderation::plugin)
+        .add_plugin(permissions::plugin)
+        .add_plugin(random_tick::plugin)
+        .add_plugin(fluids::plugin)
+        .add_plugin(block_updates::plugin)
+        .add_plugin(items::plugin)
+        .add_plugin(chat::plugin)
+        .add_plugin(metrics::plugin)
+        .add_plugin(shutdown::plugin)
+        .add_plugin(savefile::plugin)
+        .add_plugin(schematics::plugin)
+        .add_plugin(reload::plugin)
+        .add_plugin(tick_control::plugin)
+        // After everything else in NetIn, see `scheduler`
+        .add_plugin(scheduler::plugin)
+        .add_plugin(plugin);
+
+    let schedule = builder.into_tick_schedule(&mut res);
+    (res, schedule)
 }
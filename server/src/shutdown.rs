@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    chat,
+    config::ServerConfig,
+    game_builder::{GameBuilder, Stage},
+    resources::Resources,
+};
+
+// Stopping the server gracefully: players are warned in chat while the countdown runs,
+// new logins are refused, and once it is over the main loop ends and `server::shutdown()`
+// flushes whatever needs flushing and closes the connections.
+pub struct Shutdown {
+    countdown: Duration,
+    ends_at: Option<Instant>,
+    last_announced: u64, // seconds remaining
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    let countdown = builder.resource::<ServerConfig>().map_or(0, |config| config.shutdown_countdown);
+
+    builder
+        .insert_resource(Shutdown {
+            countdown: Duration::from_secs(countdown),
+            ends_at: None,
+            last_announced: 0,
+        })
+        .add_system(Stage::Update, announce_countdown)
+        .on_console_command(stop_from_console);
+}
+
+// See `reload`. A countdown already running isn't affected.
+pub fn reconfigure(res: &mut Resources, config: &ServerConfig) {
+    if let Some(shutdown) = res.extra.get_mut::<Shutdown>() {
+        shutdown.countdown = Duration::from_secs(config.shutdown_countdown);
+    }
+}
+
+// Starts the countdown. Does nothing if it has already started.
+pub fn begin(res: &mut Resources) {
+    let now = res.time.now;
+    let Some(shutdown) = res.extra.get_mut::<Shutdown>() else {
+        return;
+    };
+    if shutdown.ends_at.is_some() {
+        return;
+    }
+    shutdown.ends_at = Some(now + shutdown.countdown);
+    shutdown.last_announced = shutdown.countdown.as_secs();
+
+    let secs = shutdown.countdown.as_secs();
+    println!("Shutting down in {secs} second(s)");
+    if secs > 0 {
+        chat::broadcast_notice(res, format!("Server shutting down in {secs} second(s)").into());
+    }
+}
+
+pub fn in_progress(res: &Resources) -> bool {
+    res.extra.get::<Shutdown>().map_or(false, |shutdown| shutdown.ends_at.is_some())
+}
+
+// True once the main loop should stop
+pub fn countdown_over(res: &Resources) -> bool {
+    res.extra.get::<Shutdown>()
+        .and_then(|shutdown| shutdown.ends_at)
+        .map_or(false, |ends_at| res.time.now >= ends_at)
+}
+
+fn announce_countdown(res: &mut Resources) -> anyhow::Result<()> {
+    let now = res.time.now;
+    let Some(shutdown) = res.extra.get_mut::<Shutdown>() else {
+        return Ok(());
+    };
+    let Some(ends_at) = shutdown.ends_at else {
+        return Ok(());
+    };
+
+    // Rounded up, so that "1 second" is shown for the whole last second
+    let remaining = ends_at.saturating_duration_since(now);
+    let secs = remaining.as_secs() + (remaining.subsec_nanos() > 0) as u64;
+    if secs == 0 || secs >= shutdown.last_announced {
+        return Ok(());
+    }
+    shutdown.last_announced = secs;
+    if secs <= 5 || secs % 10 == 0 {
+        chat::broadcast_notice(res, format!("Server shutting down in {secs} second(s)").into());
+    }
+    Ok(())
+}
+
+fn stop_from_console(res: &mut Resources, command: &str, _args: &str) -> bool {
+    if command != "stop" {
+        return false;
+    }
+    if in_progress(res) {
+        println!("Already shutting down");
+    } else {
+        begin(res);
+    }
+    true
+}
+
+mod tests {
+    #[test]
+    fn test_shutdown() {
+        use crate::{config::ServerConfig, networking::LoginResponse, shutdown, testing::TestServer};
+
+        let mut server = TestServer::with_config(ServerConfig { shutdown_countdown: 2, ..TestServer::config() });
+        let alice = server.connect("alice");
+        server.received_chat(alice);
+
+        shutdown::begin(&mut server.res);
+        server.tick();
+        let messages = server.received_chat(alice);
+        assert!(messages.iter().any(|(_, message)| message.as_str() == "Server shutting down in 2 second(s)"));
+
+        let mut response = server.request_login("bob");
+        server.tick();
+        assert!(matches!(response.try_recv(), Ok((_, LoginResponse::Denied(_)))));
+
+        server.run_ticks(shared::TICKS_PER_SECOND);
+        assert!(server.received_chat(alice).iter().any(|(_, message)| message.as_str() == "Server shutting down in 1 second(s)"));
+        assert!(!shutdown::countdown_over(&server.res));
+        server.run_ticks(shared::TICKS_PER_SECOND);
+        assert!(shutdown::countdown_over(&server.res));
+    }
+}
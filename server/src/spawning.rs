@@ -0,0 +1,103 @@
+use std::fmt::Display;
+
+use glam::Vec3;
+use hecs::Entity;
+
+use crate::{
+    components::{self, Mob, MobKind, PlayerId, Position},
+    game_builder::GameBuilder,
+    resources::Resources,
+    world::WORLD_HEIGHT,
+};
+
+pub fn plugin(builder: &mut GameBuilder) {
+    builder
+        .require_op("/summon")
+        .on_chat(summon_from_chat)
+        .on_console_command(summon_from_console);
+}
+
+#[derive(Debug)]
+pub enum SpawnError {
+    OutOfWorld,
+    InsideBlocks,
+}
+
+impl Display for SpawnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpawnError::OutOfWorld => write!(f, "position is outside of the world"),
+            SpawnError::InsideBlocks => write!(f, "position is inside blocks"),
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {}
+
+// Spawns a mob at `position` (the center of its hitbox). Trackers pick it up during
+// the next NetOut stage, which sends EntityAdded to every player near enough.
+pub fn spawn_mob(res: &mut Resources, kind: MobKind, position: Vec3) -> Result<Entity, SpawnError> {
+    let half_extents = kind.half_extents();
+    if position.y - half_extents.y < 0.0 || position.y + half_extents.y > WORLD_HEIGHT as f32 {
+        return Err(SpawnError::OutOfWorld);
+    }
+    // Touching a block is fine, overlapping isn't
+    let margin = Vec3::splat(0.001);
+    if res.blocks.collides(position - half_extents + margin, position + half_extents - margin) {
+        return Err(SpawnError::InsideBlocks);
+    }
+
+    let nid = res.net.allocate_network_id();
+    let entity = components::spawn_mob(&mut res.main_world, nid, kind, position);
+    if let Err(e) = res.net.track_entity_add(entity, nid) {
+        // Shouldn't happen, the id was just allocated
+        eprintln!("spawn_mob: {e}");
+    }
+    Ok(entity)
+}
+
+// `/summon <entity> [x y z]`, at the player's position by default
+fn summon_from_chat(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    let Some(args) = message.strip_prefix("/summon").filter(|rest| rest.is_empty() || rest.starts_with(' ')) else {
+        return false;
+    };
+    let Ok(&player_id) = res.main_world.get::<&PlayerId>(sender).as_deref() else {
+        return true;
+    };
+    let default_pos = res.main_world.get::<&Position>(sender).map_or(Vec3::ZERO, |pos| pos.0);
+
+    let reply = summon(res, args.trim(), default_pos);
+    res.net.send_chat(player_id, reply.into());
+    true
+}
+
+// `summon <entity> [x y z]`, at the origin by default
+fn summon_from_console(res: &mut Resources, command: &str, args: &str) -> bool {
+    if command != "summon" {
+        return false;
+    }
+    println!("{}", summon(res, args, Vec3::ZERO));
+    true
+}
+
+fn summon(res: &mut Resources, args: &str, default_pos: Vec3) -> String {
+    const USAGE: &str = "Usage: summon <entity> [x y z]";
+
+    let mut args = args.split_whitespace();
+    let Some(kind) = args.next().and_then(MobKind::from_name) else {
+        let kinds = MobKind::ALL.map(MobKind::name).join(", ");
+        return format!("{USAGE} (entities: {kinds})");
+    };
+
+    let coords = args.map(str::parse::<f32>).collect::<Result<Vec<_>, _>>();
+    let position = match coords.as_deref() {
+        Ok([]) => default_pos,
+        Ok(&[x, y, z]) => Vec3::new(x, y, z),
+        _ => return USAGE.to_owned(),
+    };
+
+    match spawn_mob(res, kind, position) {
+        Ok(_) => format!("Summoned {} at {position:.1}", kind.name()),
+        Err(e) => format!("Can't summon {} at {position:.1}: {e}", kind.name()),
+    }
+}
@@ -0,0 +1,240 @@
+use std::f32::consts::{PI, TAU};
+
+use glam::{Vec2, Vec3};
+use hecs::Entity;
+
+use crate::{
+    components::{self, HeadYawPitch, MobKind, NetworkId, PlayerId, Position},
+    game_builder::{GameBuilder, Stage},
+    resources::Resources,
+};
+
+// `/stress entities <n>` fills the area with mobs that move about on their own, for load
+// testing entity tracking, bandwidth and the client's entity rendering without a separate
+// harness. They don't collide with anything or path find, just follow their pattern.
+
+// Network ids run out at 32767, which players and items need some of too
+const MAX_DUMMIES: usize = 10_000;
+// Area per dummy, so that the density stays about the same however many there are
+const AREA_PER_DUMMY: f32 = 16.0;
+
+#[derive(Clone, Copy)]
+enum Pattern {
+    Circle,    // around the center
+    BackForth, // through the center along a line
+    Figure8,   // around the center, crossing it
+}
+
+struct Dummy {
+    pattern: Pattern,
+    center: Vec3,
+    radius: f32,
+    direction: f32, // yaw of the line or the figure
+    speed: f32,     // cycles per second
+    phase: f32,
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    builder
+        .add_system(Stage::Update, move_dummies)
+        .require_op("/stress")
+        .on_chat(stress_from_chat)
+        .on_console_command(stress_from_console);
+}
+
+fn stress_from_chat(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    let Some(args) = message.strip_prefix("/stress").filter(|rest| rest.is_empty() || rest.starts_with(' ')) else {
+        return false;
+    };
+    let Ok(&player_id) = res.main_world.get::<&PlayerId>(sender).as_deref() else {
+        return true;
+    };
+    let center = res.main_world.get::<&Position>(sender).map_or(Vec3::ZERO, |pos| pos.0);
+
+    let reply = stress(res, args.trim(), center);
+    res.net.send_chat(player_id, reply.into());
+    true
+}
+
+// Around the origin, as there's no player to center them on
+fn stress_from_console(res: &mut Resources, command: &str, args: &str) -> bool {
+    if command != "stress" {
+        return false;
+    }
+    println!("{}", stress(res, args, Vec3::ZERO));
+    true
+}
+
+fn stress(res: &mut Resources, args: &str, center: Vec3) -> String {
+    const USAGE: &str = "Usage: stress entities <n> | stress clear";
+
+    let args = args.split_whitespace().collect::<Vec<_>>();
+    match args.as_slice() {
+        ["entities", n] => {
+            let Ok(n) = n.parse::<usize>() else {
+                return USAGE.to_owned();
+            };
+            let existing = dummy_count(res);
+            let n = n.min(MAX_DUMMIES - existing);
+            spawn_dummies(res, n, center, existing);
+            format!("Spawned {n} dummies, {} in total", existing + n)
+        }
+        ["clear"] => format!("Removed {} dummies", clear_dummies(res)),
+        _ => USAGE.to_owned(),
+    }
+}
+
+fn dummy_count(res: &mut Resources) -> usize {
+    res.main_world.query_mut::<&Dummy>().into_iter().count()
+}
+
+// Spread evenly over a disc around `center`, with the pattern and its parameters varying
+// from one to the next. `first` keeps new ones from repeating earlier ones exactly.
+fn spawn_dummies(res: &mut Resources, n: usize, center: Vec3, first: usize) {
+    let disc_radius = (n as f32 * AREA_PER_DUMMY / PI).sqrt();
+    for i in 0..n {
+        let seed = (first + i) as u32;
+        let mut random = Hash(seed);
+        // Golden angle spiral: even coverage without a grid's straight lines
+        let angle = i as f32 * PI * (3.0 - 5f32.sqrt());
+        let distance = disc_radius * ((i as f32 + 0.5) / n as f32).sqrt();
+        let offset = Vec3::new(angle.cos() * distance, random.next() * 4.0, angle.sin() * distance);
+
+        let dummy = Dummy {
+            pattern: [Pattern::Circle, Pattern::BackForth, Pattern::Figure8][seed as usize % 3],
+            center: center + offset,
+            radius: 2.0 + random.next() * 6.0,
+            direction: random.next() * TAU,
+            speed: 0.05 + random.next() * 0.25,
+            phase: random.next(),
+        };
+        let position = dummy.position_at(0.0);
+        let kind = MobKind::ALL[seed as usize % MobKind::ALL.len()];
+
+        let nid = res.net.allocate_network_id();
+        let entity = components::spawn_mob(&mut res.main_world, nid, kind, position);
+        let _ = res.main_world.insert_one(entity, dummy);
+        if let Err(e) = res.net.track_entity_add(entity, nid) {
+            eprintln!("spawn_dummies: {e}");
+        }
+    }
+}
+
+fn clear_dummies(res: &mut Resources) -> usize {
+    let dummies = res.main_world.query_mut::<(&NetworkId, &Dummy)>()
+        .into_iter()
+        .map(|(_, (&nid, _))| nid)
+        .collect::<Vec<_>>();
+    for &nid in &dummies {
+        match res.net.remove_entity(nid) {
+            Ok(entity) => {
+                let _ = res.main_world.despawn(entity);
+            }
+            Err(e) => eprintln!("clear_dummies: {e}"),
+        }
+    }
+    dummies.len()
+}
+
+fn move_dummies(res: &mut Resources) -> anyhow::Result<()> {
+    let secs = res.time.secs_f32;
+    for (_, (dummy, Position(position), head_rotation))
+        in res.main_world.query_mut::<(&Dummy, &mut Position, &mut HeadYawPitch)>() {
+
+        let new_position = dummy.position_at(secs);
+        let to_new = new_position - *position;
+        *position = new_position;
+
+        if to_new.x != 0.0 || to_new.z != 0.0 {
+            let yaw = to_new.z.atan2(to_new.x);
+            let delta = (yaw - head_rotation.value.x + PI).rem_euclid(2.0 * PI) - PI;
+            let delta = Vec2::new(delta, -head_rotation.value.y);
+            head_rotation.value += delta;
+            head_rotation.delta += delta;
+        }
+    }
+    Ok(())
+}
+
+impl Dummy {
+    fn position_at(&self, secs: f32) -> Vec3 {
+        let t = (secs * self.speed + self.phase) * TAU;
+        let (along, across) = match self.pattern {
+            Pattern::Circle => (t.cos(), t.sin()),
+            Pattern::BackForth => (t.sin(), 0.0),
+            Pattern::Figure8 => (t.sin(), (2.0 * t).sin() * 0.5),
+        };
+        let (sin, cos) = self.direction.sin_cos();
+        let along_dir = Vec3::new(cos, 0.0, sin);
+        let across_dir = Vec3::new(-sin, 0.0, cos);
+        self.center + (along_dir * along + across_dir * across) * self.radius
+    }
+}
+
+// Cheap deterministic numbers in 0..1, so that the same command gives the same dummies
+struct Hash(u32);
+
+impl Hash {
+    fn next(&mut self) -> f32 {
+        self.0 = self.0.wrapping_mul(0x9E37_79B9).wrapping_add(0x7F4A_7C15);
+        let mut x = self.0;
+        x ^= x >> 16;
+        x = x.wrapping_mul(0x85EB_CA6B);
+        x ^= x >> 13;
+        (x >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_stress_entities() {
+        use shared::protocol::s2c::EntityStateMsg;
+        use crate::{components::{Mob, Position}, game_builder, testing::TestServer};
+
+        let mut server = TestServer::new();
+        let alice = server.connect("alice");
+        assert!(game_builder::dispatch_console_command(&mut server.res, "op", "alice"));
+        server.tick();
+        server.received_chat(alice);
+
+        server.send_chat(alice, "/stress entities 40");
+        server.tick();
+        assert!(server.received_chat(alice).iter().any(|(_, message)| message.as_str() == "Spawned 40 dummies, 40 in total"));
+        let positions = |server: &mut TestServer| {
+            let mut positions = server.res.main_world.query_mut::<(&Mob, &Position)>()
+                .into_iter()
+                .map(|(entity, (_, pos))| (entity, pos.0))
+                .collect::<Vec<_>>();
+            positions.sort_by_key(|(entity, _)| entity.id());
+            positions
+        };
+        let mut before = positions(&mut server);
+        assert_eq!(before.len(), 40);
+
+        // They all keep moving, and close ones get sent to Alice
+        let mut moved = vec![false; before.len()];
+        for _ in 0..10 {
+            server.tick();
+            let after = positions(&mut server);
+            for (moved, ((_, a), (_, b))) in moved.iter_mut().zip(before.iter().zip(&after)) {
+                *moved |= a.distance(*b) > 1e-4;
+            }
+            before = after;
+        }
+        assert!(moved.iter().all(|&moved| moved));
+        let added = server.received_entity_states(alice).into_iter().flat_map(|state| state.changes)
+            .filter(|(_, msg)| matches!(msg, EntityStateMsg::EntityAdded { .. }))
+            .count();
+        assert!(added > 0);
+
+        server.send_chat(alice, "/stress clear");
+        server.tick();
+        assert!(server.received_chat(alice).iter().any(|(_, message)| message.as_str() == "Removed 40 dummies"));
+        assert_eq!(server.res.main_world.query_mut::<&Mob>().into_iter().count(), 0);
+        server.tick();
+        let removed = server.received_entity_states(alice).into_iter().flat_map(|state| state.changes)
+            .filter(|(_, msg)| matches!(msg, EntityStateMsg::EntityRemoved { .. }))
+            .count();
+        assert_eq!(removed, added);
+    }
+}
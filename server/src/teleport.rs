@@ -0,0 +1,127 @@
+use glam::Vec3;
+use hecs::Entity;
+use shared::{protocol::s2c::TeleportFlags, worldgen::{self, GenSettings}};
+
+use crate::{
+    components::{PlayerId, Position, Username, YawPitch},
+    dimensions,
+    game_builder::GameBuilder,
+    net,
+    resources::Resources,
+};
+
+pub fn plugin(builder: &mut GameBuilder) {
+    builder
+        .require_op("/tp")
+        .require_op("/cave")
+        .on_chat(tp_from_chat)
+        .on_chat(cave_from_chat)
+        .on_console_command(tp_from_console);
+}
+
+// `/tp <x y z>` or `/tp <player>`. Coordinates can be relative, as in `~ ~10 ~-2.5`.
+fn tp_from_chat(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    let Some(args) = message.strip_prefix("/tp").filter(|rest| rest.is_empty() || rest.starts_with(' ')) else {
+        return false;
+    };
+    let Ok(&player_id) = res.main_world.get::<&PlayerId>(sender).as_deref() else {
+        return true;
+    };
+
+    let args = args.split_whitespace().collect::<Vec<_>>();
+    let reply = tp(res, sender, &args).unwrap_or_else(|e| e);
+    res.net.send_chat(player_id, reply.into());
+    true
+}
+
+// `/cave`: teleports to the nearest large cave, for checking what the generator carves out
+fn cave_from_chat(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    if message.trim_end() != "/cave" {
+        return false;
+    }
+    let Ok((&player_id, &Position(pos))) = res.main_world.query_one_mut::<(&PlayerId, &Position)>(sender) else {
+        return true;
+    };
+
+    const SEARCH_RADIUS: i32 = 128;
+    let seed = dimensions::seed(res, dimensions::of(res, sender));
+    let reply = match worldgen::find_cavity(seed, &GenSettings::default(), pos.floor().as_ivec3(), SEARCH_RADIUS) {
+        Some(cavity) => {
+            let target = cavity.as_vec3() + Vec3::new(0.5, 0.0, 0.5);
+            let flags = TeleportFlags::ABSOLUTE | TeleportFlags::RELATIVE_ROTATION;
+            match net::teleport(res, sender, target, YawPitch::ZERO, flags) {
+                Ok(()) => format!("Teleported to a cave at {cavity}"),
+                Err(e) => e.to_string(),
+            }
+        }
+        None => format!("No large caves within {SEARCH_RADIUS} blocks"),
+    };
+    res.net.send_chat(player_id, reply.into());
+    true
+}
+
+// `tp <player> <x y z>` or `tp <player> <other player>`
+fn tp_from_console(res: &mut Resources, command: &str, args: &str) -> bool {
+    if command != "tp" {
+        return false;
+    }
+    let args = args.split_whitespace().collect::<Vec<_>>();
+    let reply = match args.split_first() {
+        Some((name, rest)) => match find_player(res, name) {
+            Some(player) => tp(res, player, rest).unwrap_or_else(|e| e),
+            None => format!("No player named '{name}'"),
+        },
+        None => "Usage: tp <player> <x y z | player>".to_owned(),
+    };
+    println!("{reply}");
+    true
+}
+
+fn tp(res: &mut Resources, player: Entity, args: &[&str]) -> Result<String, String> {
+    let (pos, flags) = match args {
+        &[name] => {
+            let target = find_player(res, name).ok_or_else(|| format!("No player named '{name}'"))?;
+            let Ok(&Position(pos)) = res.main_world.get::<&Position>(target).as_deref() else {
+                return Err(format!("No player named '{name}'"));
+            };
+            (pos, TeleportFlags::ABSOLUTE)
+        }
+        &[x, y, z] => {
+            let mut flags = TeleportFlags::ABSOLUTE;
+            let mut coord = |s: &str, relative_flag| {
+                let (s, relative) = match s.strip_prefix('~') {
+                    Some(rest) => (rest, true),
+                    None => (s, false),
+                };
+                if relative {
+                    flags = flags | relative_flag;
+                }
+                match s {
+                    "" if relative => Ok(0.0),
+                    _ => s.parse::<f32>().map_err(|_| format!("Invalid coordinate '{s}'")),
+                }
+            };
+            let pos = Vec3::new(
+                coord(x, TeleportFlags::RELATIVE_X)?,
+                coord(y, TeleportFlags::RELATIVE_Y)?,
+                coord(z, TeleportFlags::RELATIVE_Z)?,
+            );
+            (pos, flags)
+        }
+        _ => return Err("Usage: /tp <x y z | player>".to_owned()),
+    };
+
+    // Keep looking wherever the player is looking
+    let flags = flags | TeleportFlags::RELATIVE_ROTATION;
+    net::teleport(res, player, pos, YawPitch::ZERO, flags).map_err(|e| e.to_string())?;
+
+    let pos = res.main_world.get::<&Position>(player).map_or(pos, |pos| pos.0);
+    Ok(format!("Teleported to {pos:.1}"))
+}
+
+fn find_player(res: &mut Resources, name: &str) -> Option<Entity> {
+    res.main_world.query_mut::<(&Username, &PlayerId)>()
+        .into_iter()
+        .find(|(_, (username, _))| username.0.as_str() == name)
+        .map(|(entity, _)| entity)
+}
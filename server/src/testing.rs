@@ -0,0 +1,309 @@
+use flexstr::SharedStr;
+use glam::{Vec2, Vec3};
+use hecs::Entity;
+use shared::{movement::MovementFlags, protocol::{c2s::AuthorityMsg, s2c::{ChatFlags, MetadataKey, MetadataValue}, NetworkId}, TICK_DURATION};
+use tokio::sync::{mpsc::{unbounded_channel, UnboundedReceiver}, oneshot};
+
+use crate::{
+    components::{Metadata, PlayerId, Position},
+    config::ServerConfig,
+    game_builder::TickSchedule,
+    net::{self, PlayerChannels},
+    networking::{self, client_connection::entity_state::EntityStateOut, network_thread::{NetSideChannels, PlayerStateMsg}, LoginResponse, PlayersChanged},
+    resources::Resources,
+    server,
+};
+
+// An in-process server for integration tests. Fake clients talk to it over the loopback
+// transport, in place of the network thread, and the clock only advances by one tick
+// per `tick()`, so runs are deterministic and never sleep.
+pub struct TestServer {
+    pub res: Resources,
+    schedule: TickSchedule,
+    net_side: NetSideChannels,
+    clients: Vec<FakeClient>,
+}
+
+// Index into the server's clients, as returned by `TestServer::connect()`
+pub type ClientId = usize;
+
+pub struct FakeClient {
+    pub username: SharedStr,
+    pub network_id: NetworkId,
+    pub entity: Entity,
+    next_input_tag: u16,
+    chat: UnboundedReceiver<(ChatFlags, SharedStr)>,
+    entity_state: UnboundedReceiver<EntityStateOut>,
+    kick: oneshot::Receiver<SharedStr>,
+}
+
+impl TestServer {
+    pub fn new() -> Self {
+        Self::with_config(Self::config())
+    }
+
+    // The defaults, minus every file the server would read or write in the working directory.
+    // Tests that need other settings should start from this.
+    pub fn config() -> ServerConfig {
+        ServerConfig {
+            chat_log: None,
+            ops_file: None,
+            bans_file: None,
+            whitelist_file: None,
+            identities_file: None,
+            world_file: None,
+            ..ServerConfig::default()
+        }
+    }
+
+    pub fn with_config(config: ServerConfig) -> Self {
+        let (handle, net_side) = networking::loopback();
+        let (res, schedule) = server::init_with_network(config, net::with_handle(handle));
+        Self { res, schedule, net_side, clients: Vec::new() }
+    }
+
+    pub fn tick(&mut self) {
+        let now = self.res.time.at_launch + self.res.current_tick * TICK_DURATION;
+        if let Err(e) = server::tick_at(&mut self.res, &self.schedule, now) {
+            panic!("Error while ticking server: {e}");
+        }
+        self.res.current_tick += 1;
+    }
+
+    pub fn run_ticks(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.tick();
+        }
+    }
+
+    // Logs in the way the network thread would, ticking until the player has spawned
+    pub fn connect(&mut self, username: &str) -> ClientId {
+        let mut response = self.request_login(username);
+        self.tick();
+
+        let (network_id, response) = response.try_recv().expect("no login response after a tick");
+        assert!(matches!(response, LoginResponse::Success(..)), "login denied: {response:?}");
+        self.finish_login(username, network_id)
+    }
+
+    // The first half of `connect()`: the server's responses come through the returned channel
+    pub fn request_login(&mut self, username: &str) -> UnboundedReceiver<(NetworkId, LoginResponse)> {
+        self.request_login_with_key(username, key_for(username))
+    }
+
+    pub fn request_login_with_key(&mut self, username: &str, key: [u8; 16]) -> UnboundedReceiver<(NetworkId, LoginResponse)> {
+        let (channel, response) = unbounded_channel();
+        self.net_side.player_join_send
+            .send(PlayersChanged::LoginRequest { channel, username: SharedStr::from(username), key })
+            .unwrap();
+        response
+    }
+
+    // The second half of `connect()`, once the login has succeeded
+    pub fn finish_login(&mut self, username: &str, network_id: NetworkId) -> ClientId {
+        let key = key_for(username);
+        let username = SharedStr::from(username);
+        let (chat_send, chat) = unbounded_channel();
+        let (entity_state_send, entity_state) = unbounded_channel();
+        let (kick_send, kick) = oneshot::channel();
+        self.net_side.player_join_send
+            .send(PlayersChanged::Connected {
+                username: username.clone(),
+                skin: 0,
+                network_id,
+                channels: PlayerChannels { chat_send, entity_state: entity_state_send, kick: kick_send },
+                key,
+            })
+            .unwrap();
+        self.tick();
+
+        let entity = self.res.net.entity_of(network_id).expect("player was not spawned");
+        self.clients.push(FakeClient {
+            username,
+            network_id,
+            entity,
+            next_input_tag: 0,
+            chat,
+            entity_state,
+            kick,
+        });
+        self.clients.len() - 1
+    }
+
+    pub fn disconnect(&mut self, client: ClientId) {
+        let network_id = self.clients[client].network_id;
+        self.net_side.player_join_send.send(PlayersChanged::Disconnect { network_id }).unwrap();
+        self.tick();
+    }
+
+    // What the network thread reports when a client that was let in goes away before connecting
+    pub fn abandon_login(&mut self, network_id: NetworkId) {
+        self.net_side.player_join_send.send(PlayersChanged::Disconnect { network_id }).unwrap();
+    }
+
+    pub fn client(&self, client: ClientId) -> &FakeClient {
+        &self.clients[client]
+    }
+
+    pub fn send_chat(&mut self, client: ClientId, message: &str) {
+        let network_id = self.clients[client].network_id;
+        self.net_side.chat_send.send((network_id, message.into())).unwrap();
+    }
+
+    // One tick worth of input. Inputs are applied one per tick after the usual jitter delay.
+    pub fn send_input(&mut self, client: ClientId, delta_pos: Vec3, delta_yaw_pitch: Vec2, movement: MovementFlags) {
+        let client = &mut self.clients[client];
+        let input = PlayerStateMsg {
+            tag: client.next_input_tag,
+            delta_pos: (delta_pos != Vec3::ZERO).then_some(delta_pos),
+            delta_yaw_pitch: (delta_yaw_pitch != Vec2::ZERO).then_some(delta_yaw_pitch),
+            movement,
+        };
+        client.next_input_tag = client.next_input_tag.wrapping_add(1);
+        self.net_side.player_state_send.send((client.network_id, 0, input)).unwrap();
+    }
+
+    // Handled during the next tick
+    pub fn send_authority(&mut self, client: ClientId, msg: AuthorityMsg) {
+        let network_id = self.clients[client].network_id;
+        self.net_side.authority_send.send((network_id, msg)).unwrap();
+    }
+
+    // Everything sent to the client since the last call
+    pub fn received_chat(&mut self, client: ClientId) -> Vec<(ChatFlags, SharedStr)> {
+        let mut messages = Vec::new();
+        while let Ok(message) = self.clients[client].chat.try_recv() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    pub fn received_entity_states(&mut self, client: ClientId) -> Vec<EntityStateOut> {
+        let mut states = Vec::new();
+        while let Ok(state) = self.clients[client].entity_state.try_recv() {
+            states.push(state);
+        }
+        states
+    }
+
+    // The reason, if the server has kicked the client
+    pub fn kicked(&mut self, client: ClientId) -> Option<SharedStr> {
+        self.clients[client].kick.try_recv().ok()
+    }
+
+    pub fn position(&self, client: ClientId) -> Vec3 {
+        self.res.main_world.get::<&Position>(self.clients[client].entity).unwrap().0
+    }
+
+    pub fn metadata(&self, client: ClientId, key: MetadataKey) -> Option<MetadataValue> {
+        self.res.main_world.get::<&Metadata>(self.clients[client].entity).ok()?.get(key).cloned()
+    }
+
+    // Whether `client` has been told about `other`
+    pub fn is_tracking(&self, client: ClientId, other: ClientId) -> bool {
+        let player_id = *self.res.main_world.get::<&PlayerId>(self.clients[client].entity).unwrap();
+        self.res.net.is_tracking(player_id, self.clients[other].entity)
+    }
+}
+
+impl Default for TestServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The key a test client logs in with, always the same for a username. See c2s::Hello.
+fn key_for(username: &str) -> [u8; 16] {
+    let mut key = [0; 16];
+    key.iter_mut().zip(username.bytes()).for_each(|(byte, name_byte)| *byte = name_byte);
+    key
+}
+
+mod tests {
+    #[test]
+    fn test_login() {
+        use shared::protocol::s2c::ChatFlags;
+        use super::TestServer;
+
+        let mut server = TestServer::new();
+        let alice = server.connect("alice");
+        let bob = server.connect("bob");
+        server.tick();
+
+        assert_ne!(server.client(alice).network_id, server.client(bob).network_id);
+        assert!(server.is_tracking(alice, bob));
+        assert!(server.is_tracking(bob, alice));
+        assert!(server.received_chat(alice).iter().any(|(flags, message)| {
+            message.as_str() == "bob joined" && flags.contains(ChatFlags::NOTICE)
+        }));
+
+        server.disconnect(bob);
+        assert!(server.res.net.entity_of(server.client(bob).network_id).is_none());
+        assert!(server.received_chat(alice).iter().any(|(_, message)| message.as_str() == "bob disconnected"));
+    }
+
+    #[test]
+    fn test_chat_broadcast() {
+        use super::TestServer;
+
+        let mut server = TestServer::new();
+        let alice = server.connect("alice");
+        let bob = server.connect("bob");
+        server.received_chat(alice);
+        server.received_chat(bob);
+
+        server.send_chat(alice, "hello");
+        server.tick();
+        for client in [alice, bob] {
+            let messages = server.received_chat(client);
+            assert_eq!(messages.len(), 1);
+            assert_eq!(messages[0].1.as_str(), "alice: hello");
+        }
+    }
+
+    #[test]
+    fn test_movement_validation() {
+        use glam::{vec3, Vec2};
+        use shared::{movement::MovementFlags, protocol::s2c::{MetadataKey, MetadataValue}};
+        use super::TestServer;
+
+        let mut server = TestServer::new();
+        let player = server.connect("runner");
+
+        for _ in 0..4 {
+            server.send_input(player, vec3(0.5, 0.0, 0.0), Vec2::ZERO, MovementFlags::SPRINTING);
+        }
+        // Way faster than sprinting: ignored
+        server.send_input(player, vec3(0.0, 0.0, 50.0), Vec2::ZERO, MovementFlags::NONE);
+        server.run_ticks(8);
+
+        assert_eq!(server.position(player), vec3(2.0, 0.0, 0.0));
+        assert!(matches!(server.metadata(player, MetadataKey::Sprinting), Some(MetadataValue::Bool(false))));
+        let last_tag = server.received_entity_states(player).iter().rev().find_map(|state| state.player_input_tag);
+        assert_eq!(last_tag, Some(4));
+    }
+
+    #[test]
+    fn test_interest_management() {
+        use glam::{vec3, Vec2};
+        use shared::protocol::s2c::TeleportFlags;
+        use super::TestServer;
+        use crate::net;
+
+        let mut server = TestServer::new();
+        let near = server.connect("near");
+        let far = server.connect("far");
+        server.tick();
+        assert!(server.is_tracking(near, far));
+
+        let entity = server.client(far).entity;
+        net::teleport(&mut server.res, entity, vec3(1000.0, 0.0, 0.0), Vec2::ZERO, TeleportFlags::ABSOLUTE).unwrap();
+        server.tick();
+        assert!(!server.is_tracking(near, far));
+        assert!(!server.is_tracking(far, near));
+
+        net::teleport(&mut server.res, entity, vec3(10.0, 0.0, 0.0), Vec2::ZERO, TeleportFlags::ABSOLUTE).unwrap();
+        server.tick();
+        assert!(server.is_tracking(near, far));
+    }
+}
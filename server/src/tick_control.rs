@@ -0,0 +1,215 @@
+use hecs::Entity;
+use shared::TICKS_PER_SECOND;
+
+use crate::{
+    components::PlayerId,
+    game_builder::{GameBuilder, Stage},
+    resources::Resources,
+};
+
+// Debug commands over the passage of time. Freezing stops Stage::Update, which is where
+// the simulation (mobs, items, water, block updates, the time of day...) happens, while
+// the other stages keep logins, chat, player movement and the network going. Stepping then
+// runs the Update stage for a given number of ticks, one per server tick, so that physics
+// and AI can be followed frame by frame from the client.
+//
+//   /tick [freeze | unfreeze | step [<ticks>]]
+//   /time [set <ticks | day | noon | night | midnight> | add <ticks>]
+//
+// The console takes the same without the slash.
+
+pub const DAY_LENGTH: u64 = 20 * 60 * TICKS_PER_SECOND as u64;
+const MAX_STEP: u32 = 60 * TICKS_PER_SECOND;
+
+pub struct TickControl {
+    frozen: bool,
+    // Update stages left to run while frozen
+    steps: u32,
+}
+
+// Ticks simulated since the world began; the time of day is `ticks % DAY_LENGTH`, with 0
+// at sunrise. Not shown by clients yet.
+pub struct WorldTime {
+    pub ticks: u64,
+}
+
+impl WorldTime {
+    pub fn time_of_day(&self) -> u64 {
+        self.ticks % DAY_LENGTH
+    }
+}
+
+pub fn plugin(builder: &mut GameBuilder) {
+    builder
+        .insert_resource(TickControl { frozen: false, steps: 0 })
+        .insert_resource(WorldTime { ticks: 0 })
+        .add_system(Stage::Update, advance_world_time)
+        .require_op("/tick")
+        .require_op("/time")
+        .on_chat(commands_from_chat)
+        .on_console_command(commands_from_console);
+}
+
+// Called by `TickSchedule::run()` before each tick: whether its Update stage runs. Uses up
+// a step if frozen.
+pub fn begin_tick(res: &mut Resources) -> bool {
+    let Some(control) = res.extra.get_mut::<TickControl>() else {
+        return true;
+    };
+    if !control.frozen {
+        return true;
+    }
+    if control.steps == 0 {
+        return false;
+    }
+    control.steps -= 1;
+    true
+}
+
+fn advance_world_time(res: &mut Resources) -> anyhow::Result<()> {
+    if let Some(time) = res.extra.get_mut::<WorldTime>() {
+        time.ticks += 1;
+    }
+    Ok(())
+}
+
+fn commands_from_chat(res: &mut Resources, sender: Entity, message: &str) -> bool {
+    let (command, args) = message.split_once(' ').unwrap_or((message, ""));
+    let reply = match command {
+        "/tick" => tick_command(res, args),
+        "/time" => time_command(res, args),
+        _ => return false,
+    };
+    if let Ok(&player_id) = res.main_world.get::<&PlayerId>(sender).as_deref() {
+        res.net.send_chat(player_id, reply.into());
+    }
+    true
+}
+
+fn commands_from_console(res: &mut Resources, command: &str, args: &str) -> bool {
+    let reply = match command {
+        "tick" => tick_command(res, args),
+        "time" => time_command(res, args),
+        _ => return false,
+    };
+    println!("{reply}");
+    true
+}
+
+fn tick_command(res: &mut Resources, args: &str) -> String {
+    let simulated = res.simulated_ticks;
+    let Some(control) = res.extra.get_mut::<TickControl>() else {
+        return "Tick control is disabled".to_owned();
+    };
+    let args = args.split_whitespace().collect::<Vec<_>>();
+    match args.as_slice() {
+        [] if control.frozen => format!("Frozen at tick {simulated}, {} steps to go", control.steps),
+        [] => format!("Running, at tick {simulated}"),
+        ["freeze"] => {
+            control.frozen = true;
+            control.steps = 0;
+            format!("Frozen at tick {simulated}")
+        }
+        ["unfreeze"] => {
+            control.frozen = false;
+            control.steps = 0;
+            format!("Running from tick {simulated}")
+        }
+        ["step", rest @ ..] => {
+            if !control.frozen {
+                return "Only while frozen, see /tick freeze".to_owned();
+            }
+            let steps = match rest {
+                [] => Some(1),
+                [count] => count.parse::<u32>().ok(),
+                _ => None,
+            };
+            match steps {
+                Some(steps @ 1..=MAX_STEP) => {
+                    control.steps = control.steps.saturating_add(steps);
+                    format!("Stepping {steps} ticks from tick {simulated}")
+                }
+                _ => format!("Usage: /tick step [1 to {MAX_STEP}]"),
+            }
+        }
+        _ => "Usage: /tick [freeze | unfreeze | step [<ticks>]]".to_owned(),
+    }
+}
+
+fn time_command(res: &mut Resources, args: &str) -> String {
+    let Some(time) = res.extra.get_mut::<WorldTime>() else {
+        return "World time is disabled".to_owned();
+    };
+    let args = args.split_whitespace().collect::<Vec<_>>();
+    let day_start = time.ticks - time.time_of_day();
+    match args.as_slice() {
+        [] => {}
+        ["set", value] => {
+            let time_of_day = match *value {
+                "day" => 0,
+                "noon" => DAY_LENGTH / 4,
+                "night" => DAY_LENGTH / 2,
+                "midnight" => DAY_LENGTH * 3 / 4,
+                ticks => match ticks.parse::<u64>() {
+                    Ok(ticks) if ticks < DAY_LENGTH => ticks,
+                    _ => return format!("Usage: /time set <0 to {} | day | noon | night | midnight>", DAY_LENGTH - 1),
+                },
+            };
+            // Forwards only, to later today or tomorrow
+            time.ticks = match time_of_day >= time.time_of_day() {
+                true => day_start + time_of_day,
+                false => day_start + DAY_LENGTH + time_of_day,
+            };
+        }
+        ["add", ticks] => match ticks.parse::<u64>() {
+            Ok(ticks) => time.ticks += ticks,
+            Err(_) => return "Usage: /time add <ticks>".to_owned(),
+        },
+        _ => return "Usage: /time [set <ticks | day | noon | night | midnight> | add <ticks>]".to_owned(),
+    }
+    format!("Day {}, time {} of {DAY_LENGTH}", time.ticks / DAY_LENGTH + 1, time.time_of_day())
+}
+
+mod tests {
+    #[test]
+    fn test_tick_freeze() {
+        use glam::Vec3;
+        use shared::dimension::DimensionId;
+        use crate::{components::Position, game_builder, items, tick_control::{WorldTime, DAY_LENGTH}, testing::TestServer};
+
+        let mut server = TestServer::new();
+        let alice = server.connect("alice");
+        let item = items::drop_item(&mut server.res, DimensionId::OVERWORLD, 1, Vec3::new(0.0, 100.0, 0.0), Vec3::ZERO);
+        let height = |server: &TestServer| server.res.main_world.get::<&Position>(item).unwrap().0.y;
+        let world_time = |server: &TestServer| server.res.extra.get::<WorldTime>().unwrap().ticks;
+
+        assert!(game_builder::dispatch_console_command(&mut server.res, "tick", "freeze"));
+        let (frozen_at, time) = (height(&server), world_time(&server));
+        server.run_ticks(10);
+        assert_eq!(height(&server), frozen_at);
+        assert_eq!(world_time(&server), time);
+
+        // Chat goes on, for ops to step with
+        assert!(game_builder::dispatch_console_command(&mut server.res, "op", "alice"));
+        server.tick();
+        server.received_chat(alice);
+        server.send_chat(alice, "/tick step 3");
+        server.run_ticks(10);
+        assert!(server.received_chat(alice).iter().any(|(_, message)| message.starts_with("Stepping 3 ticks")));
+        assert_eq!(world_time(&server), time + 3);
+        let stepped = height(&server);
+        assert!(stepped < frozen_at);
+        server.run_ticks(10);
+        assert_eq!(height(&server), stepped);
+
+        assert!(game_builder::dispatch_console_command(&mut server.res, "tick", "unfreeze"));
+        server.tick();
+        assert!(height(&server) < stepped);
+
+        // Time only goes forwards
+        assert!(game_builder::dispatch_console_command(&mut server.res, "time", "set night"));
+        assert_eq!(world_time(&server), DAY_LENGTH / 2);
+        assert!(game_builder::dispatch_console_command(&mut server.res, "time", "set day"));
+        assert_eq!(world_time(&server), DAY_LENGTH);
+    }
+}
@@ -0,0 +1,177 @@
+use std::{cell::RefCell, collections::{hash_map::Entry, HashMap}};
+
+use glam::{IVec3, Vec3};
+use shared::{coords::{BlockPos, ChunkPos}, fluid, worldgen::structures};
+
+pub type BlockId = u16;
+pub const AIR: BlockId = 0;
+
+pub const CHUNK_SIZE: i32 = 16;
+pub const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+pub const WORLD_HEIGHT: i32 = 256;
+
+// Generated chunks that weren't written to are kept around for reading up to this many,
+// then all dropped. 8 KiB each.
+const MAX_GENERATED_CHUNKS: usize = 4096;
+
+// The server's view of the blocks in the world. Sparse: chunks that were never written to
+// are what shared::worldgen generates from the terrain seed, the same terrain as clients
+// generate, or all air without one. Those are generated when first read and only kept
+// once written to.
+#[derive(Default)]
+pub struct BlockWorld {
+    chunks: HashMap<ChunkPos, Box<[BlockId; CHUNK_VOLUME]>>,
+    terrain_seed: Option<u64>,
+    generated: RefCell<HashMap<ChunkPos, Box<[BlockId; CHUNK_VOLUME]>>>,
+    // Blocks changed this tick, for sending to clients and saving once those exist
+    changed: Vec<IVec3>,
+}
+
+impl BlockWorld {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The seed of the dimension, see shared::dimension::seed(), or None for an empty world
+    pub fn set_terrain_seed(&mut self, seed: Option<u64>) {
+        self.terrain_seed = seed;
+        self.generated.get_mut().clear();
+    }
+
+    pub fn block_at(&self, pos: IVec3) -> BlockId {
+        let block_pos = BlockPos(pos);
+        if let Some(chunk) = self.chunks.get(&block_pos.chunk()) {
+            return chunk[block_pos.local().index()];
+        }
+        let Some(seed) = self.terrain_seed else {
+            return AIR;
+        };
+        if pos.y < 0 || pos.y >= WORLD_HEIGHT {
+            return AIR;
+        }
+        let mut generated = self.generated.borrow_mut();
+        if generated.len() >= MAX_GENERATED_CHUNKS && !generated.contains_key(&block_pos.chunk()) {
+            generated.clear();
+        }
+        let chunk = generated
+            .entry(block_pos.chunk())
+            .or_insert_with(|| structures::generate_standalone_chunk(seed, block_pos.chunk().0));
+        chunk[block_pos.local().index()]
+    }
+
+    // Returns false if `pos` is outside of the world's height limits
+    pub fn set_block(&mut self, pos: IVec3, block: BlockId) -> bool {
+        if pos.y < 0 || pos.y >= WORLD_HEIGHT {
+            return false;
+        }
+        let block_pos = BlockPos(pos);
+        let chunk_pos = block_pos.chunk();
+        // Written to for the first time: starts out as generated
+        let chunk = match self.chunks.entry(chunk_pos) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let generated = self.generated.get_mut().remove(&chunk_pos);
+                entry.insert(generated.unwrap_or_else(|| match self.terrain_seed {
+                    Some(seed) => structures::generate_standalone_chunk(seed, chunk_pos.0),
+                    None => Box::new([AIR; CHUNK_VOLUME]),
+                }))
+            }
+        };
+        let old = std::mem::replace(&mut chunk[block_pos.local().index()], block);
+        if old != block {
+            self.changed.push(pos);
+        }
+        true
+    }
+
+    // Of the chunks that have been written to, in no particular order
+    pub fn chunk_positions(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    // None if never written to, which means as generated. Indexed with LocalPos::index().
+    pub fn chunk(&self, pos: ChunkPos) -> Option<&[BlockId; CHUNK_VOLUME]> {
+        self.chunks.get(&pos).map(|chunk| &**chunk)
+    }
+
+    // Replaces the whole chunk without recording changes, for loading saved worlds
+    pub fn insert_chunk(&mut self, pos: ChunkPos, blocks: Box<[BlockId; CHUNK_VOLUME]>) {
+        self.chunks.insert(pos, blocks);
+        self.generated.get_mut().remove(&pos);
+    }
+
+    // Positions may repeat if a block changed more than once
+    pub fn changed_blocks(&self) -> &[IVec3] {
+        &self.changed
+    }
+
+    // The blocks changed this tick as they are now, each position once
+    pub fn changes(&self) -> Vec<(IVec3, BlockId)> {
+        let mut positions = self.changed.clone();
+        positions.sort_unstable_by_key(|pos| pos.to_array());
+        positions.dedup();
+        positions.into_iter().map(|pos| (pos, self.block_at(pos))).collect()
+    }
+
+    pub fn clear_changes(&mut self) {
+        self.changed.clear();
+    }
+
+    pub fn is_solid(&self, pos: IVec3) -> bool {
+        let block = self.block_at(pos);
+        block != AIR && fluid::water_level(block).is_none()
+    }
+
+    // Whether any solid block overlaps the box
+    pub fn collides(&self, min: Vec3, max: Vec3) -> bool {
+        let (min, max) = (min.floor().as_ivec3(), max.ceil().as_ivec3() - 1);
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    if self.is_solid(IVec3::new(x, y, z)) {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_generated_terrain() {
+        use glam::{ivec3, IVec3};
+        use shared::{blocks, dimension::{self, DimensionId}, protocol::c2s::{AuthorityMsg, BlockAction}, worldgen::{self, AIR, STONE}, TICKS_PER_SECOND};
+        use crate::{components::{DroppedItem, Position}, testing::TestServer};
+
+        let mut server = TestServer::new();
+        let player = server.connect("miner");
+        let seed = dimension::seed(0, DimensionId::OVERWORLD);
+        server.res.blocks.set_terrain_seed(Some(seed));
+
+        // Stone with stone below, within reach of the origin
+        let pos = (1..8)
+            .map(|y| ivec3(2, y, 0))
+            .find(|&pos| server.res.blocks.block_at(pos) == STONE && server.res.blocks.block_at(pos - IVec3::Y) == STONE)
+            .expect("no stone next to the player");
+        assert_eq!(server.res.blocks.chunk_positions().count(), 0);
+        let chunk = worldgen::structures::generate_standalone_chunk(seed, ivec3(0, 0, 0));
+        assert_eq!(server.res.blocks.block_at(ivec3(5, 0, 5)), chunk[worldgen::block_index(5, 0, 5)]);
+
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::StartBreaking { pos }));
+        server.tick();
+        server.run_ticks(blocks::break_ticks(STONE).unwrap());
+        server.send_authority(player, AuthorityMsg::Block(BlockAction::FinishBreaking { pos }));
+        server.tick();
+        assert_eq!(server.res.blocks.block_at(pos), AIR);
+        // The rest of the chunk stays as generated once written to
+        assert_eq!(server.res.blocks.block_at(pos - IVec3::Y), STONE);
+        assert_eq!(server.res.blocks.block_at(ivec3(5, 0, 5)), chunk[worldgen::block_index(5, 0, 5)]);
+
+        server.run_ticks(2 * TICKS_PER_SECOND);
+        let items = server.res.main_world.query_mut::<(&DroppedItem, &Position)>();
+        let (_, (_, item_pos)) = items.into_iter().next().expect("the item fell out of the world");
+        assert!(item_pos.0.y >= pos.y as f32, "item at {}", item_pos.0);
+    }
+}
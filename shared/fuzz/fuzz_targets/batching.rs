@@ -0,0 +1,51 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shared::{
+    bits_and_bytes::ByteReader,
+    protocol::{batching::{Batcher, Unbatcher, MAX_FRAME_LEN}, Features},
+};
+
+const MAX_BATCH_LEN: usize = 1 << 16;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&first, data)) = data.split_first() else {
+        return;
+    };
+    let features = if first & 1 != 0 { Features::COMPRESSION } else { Features::NONE };
+
+    // Anything received decodes or fails, and a compressed batch stays within its limit
+    let mut unbatcher = Unbatcher::new(features, MAX_BATCH_LEN);
+    if unbatcher.unpack(data).is_ok() {
+        let mut total = 0;
+        while unbatcher.has_batched() {
+            match unbatcher.next_in_batch() {
+                Ok(message) => total += message.len(),
+                Err(_) => break,
+            }
+        }
+        assert!(total <= MAX_BATCH_LEN);
+    }
+
+    // Messages batched up come out the same on the other end. Zeros separate them.
+    let messages: Vec<&[u8]> = data.split(|&b| b == 0).filter(|m| m.len() < MAX_FRAME_LEN).collect();
+    let mut batcher = Batcher::new(features);
+    for message in &messages {
+        batcher.push(message);
+    }
+    let batch_len = batcher.len();
+    let stream = batcher.finish().to_vec();
+
+    let mut received = Vec::new();
+    let mut unbatcher = Unbatcher::new(features, batch_len);
+    let mut reader = ByteReader::new(&stream);
+    while reader.bytes_remaining() > 0 {
+        let len = reader.try_read_varint15().unwrap() as usize;
+        let frame = reader.try_read_slice(len).unwrap();
+        received.push(unbatcher.unpack(frame).unwrap().to_vec());
+        while unbatcher.has_batched() {
+            received.push(unbatcher.next_in_batch().unwrap().to_vec());
+        }
+    }
+    assert_eq!(received, messages);
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shared::bits_and_bytes::BitReader;
+
+// BitReader reads zeros past the end of the buffer, so it must accept anything.
+// The read widths come from the input itself.
+fuzz_target!(|data: &[u8]| {
+    let mut reader = BitReader::new(data);
+    let mut widths = BitReader::new(data);
+    for _ in 0..data.len() * 2 {
+        let bits = widths.uint(5) + 1;
+        let value = reader.uint(bits);
+        assert!(bits == 32 || value < 1 << bits);
+        reader.int(bits.max(2));
+        reader.bool();
+    }
+});
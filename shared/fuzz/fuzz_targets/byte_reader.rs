@@ -0,0 +1,45 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shared::bits_and_bytes::ByteReader;
+
+// The first byte tells how many of the following bytes are a "script" of reads to
+// perform on the rest. None of the checked reads may panic, whatever the input.
+fuzz_target!(|data: &[u8]| {
+    let (num_ops, rest) = match data.split_first() {
+        Some((&n, rest)) => (n as usize, rest),
+        None => return,
+    };
+    let (ops, src) = rest.split_at(num_ops.min(rest.len()));
+
+    let mut reader = ByteReader::new(src);
+    let mut out = Vec::new();
+    for &op in ops {
+        let arg = (op >> 4) as usize;
+        let _ = match op & 15 {
+            0 => reader.try_read_u8().map(drop),
+            1 => reader.try_read_u16().map(drop),
+            2 => reader.try_read_u32().map(drop),
+            3 => reader.try_read_u64().map(drop),
+            4 => reader.try_read_i16().map(drop),
+            5 => reader.try_read_f32().map(drop),
+            6 => reader.try_read_f64().map(drop),
+            7 => reader.try_read_bool().map(drop),
+            8 => reader.try_read_varint15().map(drop),
+            9 => reader.try_read_varint_u32().map(drop),
+            10 => reader.try_read_varint_u64().map(drop),
+            11 => reader.try_read_varint_i64().map(drop),
+            12 => reader.try_read_str(arg).map(drop),
+            13 => reader.try_skip(arg),
+            14 => reader.try_read_slice(usize::MAX - arg).map(drop),
+            _ => {
+                out.clear();
+                let max_count = arg << 12;
+                let result = reader.try_read_delta_rle(max_count, &mut out);
+                assert!(out.len() <= max_count);
+                result
+            }
+        };
+        assert!(reader.bytes_read() <= reader.total_src_size());
+    }
+});
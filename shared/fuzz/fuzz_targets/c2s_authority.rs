@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shared::{bits_and_bytes::{ByteReader, ByteWriter}, protocol::c2s::AuthorityMsg};
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = ByteReader::new(data);
+    if let Ok(msg) = AuthorityMsg::read(&mut reader) {
+        // Each message has one encoding
+        let mut buf = [0u8; 64];
+        let mut writer = ByteWriter::new(&mut buf);
+        msg.write(&mut writer);
+        let len = writer.bytes_written();
+        assert_eq!(&buf[..len], &data[..reader.bytes_read()]);
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shared::{bits_and_bytes::{ByteReader, ByteWriter}, protocol::c2s::Hello};
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = ByteReader::new(data);
+    if let Ok(hello) = Hello::read(&mut reader) {
+        // Anything that decodes must survive a re-encode. Not necessarily byte-for-byte,
+        // the varint length may have been overlong.
+        let mut buf = vec![0u8; data.len()];
+        let mut writer = ByteWriter::new(&mut buf);
+        hello.write(&mut writer);
+        let len = writer.bytes_written();
+        assert!(len <= reader.bytes_read());
+        assert_eq!(Hello::read(&mut ByteReader::new(&buf[..len])).unwrap(), hello);
+    }
+});
@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shared::protocol::c2s::read_player_state;
+
+fuzz_target!(|data: &[u8]| {
+    let (prev_tag, datagram) = match data {
+        [a, b, rest @ ..] => (u16::from_le_bytes([*a, *b]), rest),
+        _ => return,
+    };
+
+    let mut out = Vec::new();
+    let tag = read_player_state(datagram, prev_tag, &mut out);
+    // Never more inputs than there are missing ticks
+    assert!(out.len() <= tag.wrapping_sub(prev_tag) as usize);
+    for (i, input) in out.iter().enumerate() {
+        assert_eq!(input.tag, tag.wrapping_sub(i as u16));
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shared::{bits_and_bytes::ByteReader, chunk_format::{read_chunks, Schematic}};
+
+fuzz_target!(|data: &[u8]| {
+    // A world file's body; errors are fine, panics aren't
+    let _ = read_chunks(&mut ByteReader::new(data), |_, _| {});
+
+    // Encoding drops chunks of air and sorts the rest, so a decoded schematic only has to
+    // encode the same after another round
+    if let Ok(schematic) = Schematic::decode(data) {
+        let encoded = schematic.encode();
+        assert_eq!(Schematic::decode(&encoded).unwrap().encode(), encoded);
+    }
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shared::{bits_and_bytes::ByteReader, protocol::s2c::read_entity_state};
+
+fuzz_target!(|data: &[u8]| {
+    let (mut prev_tag, msg) = match data {
+        [a, b, rest @ ..] => (u16::from_le_bytes([*a, *b]), rest),
+        _ => return,
+    };
+
+    let mut out = Vec::new();
+    let mut reader = ByteReader::new(msg);
+    if read_entity_state(&mut reader, &mut prev_tag, &mut out).is_ok() {
+        assert_eq!(reader.bytes_remaining(), 0);
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shared::{bits_and_bytes::ByteReader, protocol::s2c::LoginResponse};
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = ByteReader::new(data);
+    if LoginResponse::read(&mut reader).is_ok() {
+        assert_eq!(reader.bytes_read(), 30);
+    }
+});
@@ -0,0 +1,43 @@
+#![no_main]
+
+use glam::vec3;
+use libfuzzer_sys::fuzz_target;
+use shared::{
+    bits_and_bytes::{ByteReader, ByteWriter},
+    dimension::DimensionId,
+    protocol::{s2c::{read_entity_state, write_input_tag, write_teleport, EntityStateMsg, TeleportFlags}, MessageError},
+};
+
+// A Teleport built from the input must decode back to itself, dimension and all
+fuzz_target!(|data: &[u8]| {
+    let mut reader = ByteReader::new(data);
+    let fields = (|| -> Result<_, MessageError> {
+        let (tag, flags) = (reader.try_read_u16()?, reader.try_read_u8()?);
+        let pos = vec3(reader.try_read_f32()?, reader.try_read_f32()?, reader.try_read_f32()?);
+        let (yaw, pitch) = (reader.try_read_f32()?, reader.try_read_f32()?);
+        let dimension = match reader.try_read_bool()? {
+            true => Some((DimensionId(reader.try_read_u8()?), reader.try_read_u64()?)),
+            false => None,
+        };
+        Ok((tag, flags, pos, yaw, pitch, dimension))
+    })();
+    let Ok((tag, flags, pos, yaw, pitch, dimension)) = fields else {
+        return;
+    };
+    if !pos.is_finite() || !yaw.is_finite() || !pitch.is_finite() {
+        return;
+    }
+    // The top bit of the flags on the wire says whether a dimension follows
+    let flags = TeleportFlags(flags & 0x7F);
+
+    let mut buf = [0u8; 64];
+    let mut writer = ByteWriter::new(&mut buf);
+    write_input_tag(&mut writer, tag);
+    write_teleport(&mut writer, pos, yaw, pitch, flags, dimension);
+    let len = writer.bytes_written();
+
+    let mut out = Vec::new();
+    let mut prev_tag = tag;
+    read_entity_state(&mut ByteReader::new(&buf[..len]), &mut prev_tag, &mut out).unwrap();
+    assert_eq!(out, [EntityStateMsg::Teleport { tag, pos, yaw, pitch, flags, dimension }]);
+});
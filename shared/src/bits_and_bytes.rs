@@ -1,3 +1,4 @@
+use crate::protocol::MessageError;
 
 #[inline]
 pub fn f32_to_fixed(f: f32, fractional_bits: u32) -> u32 {
@@ -14,6 +15,38 @@ pub fn round_to_frac_bits(f: f32, fractional_bits: u32) -> f32 {
     fixed_to_f32(f32_to_fixed(f, fractional_bits), fractional_bits)
 }
 
+// Maps signed integers to unsigned so that small magnitudes stay small:
+// 0 => 0, -1 => 1, 1 => 2, -2 => 3, ...
+#[inline]
+pub const fn zigzag_encode_32(x: i32) -> u32 {
+    ((x << 1) ^ (x >> 31)) as u32
+}
+
+#[inline]
+pub const fn zigzag_decode_32(x: u32) -> i32 {
+    ((x >> 1) as i32) ^ -((x & 1) as i32)
+}
+
+#[inline]
+pub const fn zigzag_encode_64(x: i64) -> u64 {
+    ((x << 1) ^ (x >> 63)) as u64
+}
+
+#[inline]
+pub const fn zigzag_decode_64(x: u64) -> i64 {
+    ((x >> 1) as i64) ^ -((x & 1) as i64)
+}
+
+// Number of bytes `write_varint_u64(x)` will write (1..=10)
+#[inline]
+pub const fn varint_len(x: u64) -> usize {
+    let bits = 64 - (x | 1).leading_zeros() as usize;
+    bits.div_ceil(7)
+}
+
+pub const MAX_VARINT32_LEN: usize = 5;
+pub const MAX_VARINT64_LEN: usize = 10;
+
 
 pub struct ByteReader<'a> {
     src: &'a [u8],
@@ -83,6 +116,65 @@ impl<'a> ByteReader<'a> {
         }
     }    
 
+    // LEB128: 7 bits per byte, least significant group first, MSB set = more bytes follow
+    pub fn read_varint_u64(&mut self) -> u64 {
+        let mut result = 0;
+        for i in 0..MAX_VARINT64_LEN {
+            let b = self.read_u8();
+            result |= ((b & 127) as u64) << (7 * i);
+            if (b & 128) == 0 {
+                break;
+            }
+        }
+        result
+    }
+
+    pub fn read_varint_u32(&mut self) -> u32 {
+        let mut result = 0;
+        for i in 0..MAX_VARINT32_LEN {
+            let b = self.read_u8();
+            result |= ((b & 127) as u32) << (7 * i);
+            if (b & 128) == 0 {
+                break;
+            }
+        }
+        result
+    }
+
+    pub fn read_varint_i32(&mut self) -> i32 {
+        zigzag_decode_32(self.read_varint_u32())
+    }
+
+    pub fn read_varint_i64(&mut self) -> i64 {
+        zigzag_decode_64(self.read_varint_u64())
+    }
+
+    // Counterpart of ByteWriter::write_delta_rle(). Appends to `out`.
+    pub fn read_delta_rle(&mut self, out: &mut Vec<u32>) {
+        let count = self.read_varint_u32() as usize;
+        if count == 0 {
+            return;
+        }
+        // Each value takes at least a byte on the wire, unless run-length encoded,
+        // so don't trust `count` blindly for the allocation
+        out.reserve(count.min(self.bytes_remaining() + 1));
+
+        let end = out.len() + count;
+        let mut value = self.read_varint_u32();
+        out.push(value);
+        while out.len() < end {
+            let delta = self.read_varint_i32();
+            let run = (self.read_varint_u32() as usize).min(end - out.len());
+            if run == 0 {
+                break; // Malformed, would never terminate
+            }
+            for _ in 0..run {
+                value = value.wrapping_add(delta as u32);
+                out.push(value);
+            }
+        }
+    }
+
     pub fn read_u8(&mut self) -> u8 {
         let p = self.pos;
         self.pos += 1;
@@ -199,6 +291,131 @@ impl<'a> ByteReader<'a> {
     }
 }
 
+// Bounds-checked variants of the above. The unchecked readers are only for buffers we
+// filled ourselves; anything that came from the network goes through these.
+impl<'a> ByteReader<'a> {
+    fn try_take<const N: usize>(&mut self) -> Result<[u8; N], MessageError> {
+        let bytes = self.try_read_slice(N)?;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    pub fn try_read_slice(&mut self, len: usize) -> Result<&'a [u8], MessageError> {
+        let end = self.pos.checked_add(len).ok_or(MessageError::NotEnoughData)?;
+        let bytes = self.src.get(self.pos..end).ok_or(MessageError::NotEnoughData)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn try_skip(&mut self, n: usize) -> Result<(), MessageError> {
+        self.try_read_slice(n).map(|_| ())
+    }
+
+    pub fn try_read_varint15(&mut self) -> Result<u16, MessageError> {
+        let b1 = self.try_read_u8()?;
+        if (b1 & 128) != 0 {
+            Ok((b1 as u16 & 127) | ((self.try_read_u8()? as u16) << 7))
+        } else {
+            Ok(b1 as u16)
+        }
+    }
+
+    pub fn try_read_varint_u64(&mut self) -> Result<u64, MessageError> {
+        let mut result = 0;
+        for i in 0..MAX_VARINT64_LEN {
+            let b = self.try_read_u8()?;
+            result |= ((b & 127) as u64) << (7 * i);
+            if (b & 128) == 0 {
+                return Ok(result);
+            }
+        }
+        Err(MessageError::Malformed)
+    }
+
+    pub fn try_read_varint_u32(&mut self) -> Result<u32, MessageError> {
+        let x = self.try_read_varint_u64()?;
+        u32::try_from(x).map_err(|_| MessageError::Malformed)
+    }
+
+    pub fn try_read_varint_i32(&mut self) -> Result<i32, MessageError> {
+        Ok(zigzag_decode_32(self.try_read_varint_u32()?))
+    }
+
+    pub fn try_read_varint_i64(&mut self) -> Result<i64, MessageError> {
+        Ok(zigzag_decode_64(self.try_read_varint_u64()?))
+    }
+
+    // Malformed if there are more than `max_count` values. A few bytes can run-length encode
+    // billions of them, so the caller has to say how many make sense.
+    pub fn try_read_delta_rle(&mut self, max_count: usize, out: &mut Vec<u32>) -> Result<(), MessageError> {
+        let count = self.try_read_varint_u32()? as usize;
+        if count > max_count {
+            return Err(MessageError::Malformed);
+        }
+        if count == 0 {
+            return Ok(());
+        }
+        out.reserve(count.min(self.bytes_remaining() + 1));
+
+        let end = out.len() + count;
+        let mut value = self.try_read_varint_u32()?;
+        out.push(value);
+        while out.len() < end {
+            let delta = self.try_read_varint_i32()?;
+            let run = self.try_read_varint_u32()? as usize;
+            if run == 0 || run > end - out.len() {
+                return Err(MessageError::Malformed);
+            }
+            for _ in 0..run {
+                value = value.wrapping_add(delta as u32);
+                out.push(value);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn try_read_u8(&mut self) -> Result<u8, MessageError> {
+        Ok(u8::from_le_bytes(self.try_take()?))
+    }
+
+    pub fn try_read_u16(&mut self) -> Result<u16, MessageError> {
+        Ok(u16::from_le_bytes(self.try_take()?))
+    }
+
+    pub fn try_read_u32(&mut self) -> Result<u32, MessageError> {
+        Ok(u32::from_le_bytes(self.try_take()?))
+    }
+
+    pub fn try_read_u64(&mut self) -> Result<u64, MessageError> {
+        Ok(u64::from_le_bytes(self.try_take()?))
+    }
+
+    pub fn try_read_i16(&mut self) -> Result<i16, MessageError> {
+        Ok(i16::from_le_bytes(self.try_take()?))
+    }
+
+    pub fn try_read_i32(&mut self) -> Result<i32, MessageError> {
+        Ok(i32::from_le_bytes(self.try_take()?))
+    }
+
+    pub fn try_read_f32(&mut self) -> Result<f32, MessageError> {
+        Ok(f32::from_bits(self.try_read_u32()?))
+    }
+
+    pub fn try_read_f64(&mut self) -> Result<f64, MessageError> {
+        Ok(f64::from_bits(self.try_read_u64()?))
+    }
+
+    // Invalid UTF-8 is Malformed rather than NotEnoughData
+    pub fn try_read_str(&mut self, len: usize) -> Result<&'a str, MessageError> {
+        let bytes = self.try_read_slice(len)?;
+        std::str::from_utf8(bytes).map_err(|_| MessageError::Malformed)
+    }
+
+    pub fn try_read_bool(&mut self) -> Result<bool, MessageError> {
+        Ok(self.try_read_u8()? != 0)
+    }
+}
+
 
 pub struct ByteWriter<'a> {
     dst: &'a mut [u8],
@@ -273,6 +490,53 @@ impl<'a> ByteWriter<'a> {
         }
     }
 
+    pub fn write_varint_u64(&mut self, mut x: u64) {
+        while x >= 128 {
+            self.write_u8(x as u8 | 128);
+            x >>= 7;
+        }
+        self.write_u8(x as u8);
+    }
+
+    pub fn write_varint_u32(&mut self, x: u32) {
+        self.write_varint_u64(x as u64);
+    }
+
+    pub fn write_varint_i32(&mut self, x: i32) {
+        self.write_varint_u32(zigzag_encode_32(x));
+    }
+
+    pub fn write_varint_i64(&mut self, x: i64) {
+        self.write_varint_u64(zigzag_encode_64(x));
+    }
+
+    // Writes the values as a varint count, the first value, and then (delta, run length)
+    // pairs where each run repeats the same delta. Sorted ID lists and other arithmetic-ish
+    // sequences collapse to a handful of bytes; anything else still costs ~2 bytes per value.
+    pub fn write_delta_rle(&mut self, values: &[u32]) {
+        self.write_varint_u32(values.len() as u32);
+        let (first, rest) = match values.split_first() {
+            Some((&first, rest)) => (first, rest),
+            None => return,
+        };
+        self.write_varint_u32(first);
+
+        let mut prev = first;
+        let mut i = 0;
+        while i < rest.len() {
+            let delta = rest[i].wrapping_sub(prev) as i32;
+            let mut run = 1;
+            prev = rest[i];
+            while i + run < rest.len() && rest[i + run].wrapping_sub(prev) as i32 == delta {
+                prev = rest[i + run];
+                run += 1;
+            }
+            self.write_varint_i32(delta);
+            self.write_varint_u32(run as u32);
+            i += run;
+        }
+    }
+
     pub fn write_u8(&mut self, x: u8) {
         debug_assert!(self.dst.len() - self.pos as usize >= 1);
 
@@ -591,4 +855,233 @@ mod tests {
         assert_eq!(reader.uint(32), 0);
         assert_eq!(reader.uint(32), 0);
     }
+    // Cheap deterministic generator so the "fuzz" tests are reproducible
+    #[cfg(test)]
+    fn xorshift(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    pub fn test_varint_u32_exhaustive_u16() {
+        use super::{ByteReader, ByteWriter, varint_len};
+
+        let mut buf = [0u8; 5];
+        for x in 0..=u16::MAX as u32 {
+            let mut writer = ByteWriter::new(&mut buf);
+            writer.write_varint_u32(x);
+            let len = writer.bytes_written();
+            assert_eq!(len, varint_len(x as u64));
+
+            let mut reader = ByteReader::new(&buf[..len]);
+            assert_eq!(reader.read_varint_u32(), x);
+            assert_eq!(reader.bytes_remaining(), 0);
+        }
+    }
+
+    #[test]
+    pub fn test_varint_boundaries() {
+        use super::{ByteReader, ByteWriter, varint_len, MAX_VARINT32_LEN, MAX_VARINT64_LEN};
+
+        let mut buf = [0u8; 10];
+        for shift in 0..64 {
+            let p = 1u64 << shift;
+            for x in [p - 1, p, p + 1, u64::MAX >> (63 - shift)] {
+                let mut writer = ByteWriter::new(&mut buf);
+                writer.write_varint_u64(x);
+                let len = writer.bytes_written();
+                assert_eq!(len, varint_len(x));
+                assert!(len <= MAX_VARINT64_LEN);
+
+                let mut reader = ByteReader::new(&buf[..len]);
+                assert_eq!(reader.read_varint_u64(), x);
+                assert_eq!(reader.bytes_remaining(), 0);
+
+                if x <= u32::MAX as u64 {
+                    assert!(len <= MAX_VARINT32_LEN);
+                    let mut reader = ByteReader::new(&buf[..len]);
+                    assert_eq!(reader.read_varint_u32(), x as u32);
+                    assert_eq!(reader.bytes_remaining(), 0);
+                }
+            }
+        }
+        assert_eq!(varint_len(0), 1);
+        assert_eq!(varint_len(127), 1);
+        assert_eq!(varint_len(128), 2);
+        assert_eq!(varint_len(u32::MAX as u64), 5);
+        assert_eq!(varint_len(u64::MAX), 10);
+    }
+
+    #[test]
+    pub fn test_varint_fuzz() {
+        use super::{ByteReader, ByteWriter};
+
+        let mut state = 0x2545F4914F6CDD1D;
+        let mut buf = vec![0u8; 30 * 1000];
+        let mut values = Vec::with_capacity(1000);
+
+        for _ in 0..100 {
+            values.clear();
+            let mut writer = ByteWriter::new(&mut buf);
+            for _ in 0..1000 {
+                // Random magnitude so all encoded lengths get exercised
+                let r = xorshift(&mut state);
+                let x = r >> (r % 64);
+                values.push(x);
+                writer.write_varint_u64(x);
+                writer.write_varint_u32(x as u32);
+                writer.write_varint_i32(x as i32);
+                writer.write_varint_i64(x as i64);
+            }
+            let len = writer.bytes_written();
+
+            let mut reader = ByteReader::new(&buf[..len]);
+            for &x in &values {
+                assert_eq!(reader.read_varint_u64(), x);
+                assert_eq!(reader.read_varint_u32(), x as u32);
+                assert_eq!(reader.read_varint_i32(), x as i32);
+                assert_eq!(reader.read_varint_i64(), x as i64);
+            }
+            assert_eq!(reader.bytes_remaining(), 0);
+        }
+    }
+
+    #[test]
+    pub fn test_zigzag() {
+        use super::{zigzag_encode_32, zigzag_decode_32, zigzag_encode_64, zigzag_decode_64};
+
+        assert_eq!(zigzag_encode_32(0), 0);
+        assert_eq!(zigzag_encode_32(-1), 1);
+        assert_eq!(zigzag_encode_32(1), 2);
+        assert_eq!(zigzag_encode_32(-2), 3);
+        assert_eq!(zigzag_encode_32(i32::MAX), u32::MAX - 1);
+        assert_eq!(zigzag_encode_32(i32::MIN), u32::MAX);
+        assert_eq!(zigzag_encode_64(i64::MIN), u64::MAX);
+
+        for x in i16::MIN as i32..=i16::MAX as i32 {
+            assert_eq!(zigzag_decode_32(zigzag_encode_32(x)), x);
+            assert_eq!(zigzag_decode_64(zigzag_encode_64(x as i64)), x as i64);
+            // Magnitude-preserving: |x| <= n => encoded <= 2n
+            assert!(zigzag_encode_32(x) <= 2 * x.unsigned_abs());
+        }
+
+        let mut state = 0x9E3779B97F4A7C15;
+        for _ in 0..100_000 {
+            let x = xorshift(&mut state);
+            assert_eq!(zigzag_decode_32(zigzag_encode_32(x as i32)), x as i32);
+            assert_eq!(zigzag_decode_64(zigzag_encode_64(x as i64)), x as i64);
+        }
+    }
+
+    #[test]
+    pub fn test_delta_rle() {
+        use super::{ByteReader, ByteWriter};
+
+        fn roundtrip(values: &[u32]) -> usize {
+            let mut buf = vec![0u8; 5 + values.len() * 10];
+            let mut writer = ByteWriter::new(&mut buf);
+            writer.write_delta_rle(values);
+            let len = writer.bytes_written();
+
+            let mut out = vec![123]; // Should append, not overwrite
+            let mut reader = ByteReader::new(&buf[..len]);
+            reader.read_delta_rle(&mut out);
+            assert_eq!(reader.bytes_remaining(), 0);
+            assert_eq!(out[0], 123);
+            assert_eq!(&out[1..], values);
+
+            out.clear();
+            let mut reader = ByteReader::new(&buf[..len]);
+            reader.try_read_delta_rle(values.len(), &mut out).unwrap();
+            assert_eq!(reader.bytes_remaining(), 0);
+            assert_eq!(out, values);
+            for end in 0..len {
+                assert!(ByteReader::new(&buf[..end]).try_read_delta_rle(values.len(), &mut out).is_err());
+            }
+            if let Some(max_count) = values.len().checked_sub(1) {
+                assert!(ByteReader::new(&buf[..len]).try_read_delta_rle(max_count, &mut out).is_err());
+            }
+            len
+        }
+
+        assert_eq!(roundtrip(&[]), 1);
+        assert_eq!(roundtrip(&[5]), 2);
+        // Contiguous ranges collapse to count + first + a single (delta, run) pair
+        assert_eq!(roundtrip(&(0..1000).collect::<Vec<_>>()), 2 + 1 + 1 + 2);
+        assert_eq!(roundtrip(&[7, 7, 7, 7]), 1 + 1 + 1 + 1);
+        roundtrip(&[0, u32::MAX, 0, u32::MAX, 1]);
+        roundtrip(&[10, 20, 30, 25, 20, 15, 100, 101, 102, 103, 0]);
+
+        let mut state = 0xDEADBEEFCAFEBABE;
+        for _ in 0..1000 {
+            let n = (xorshift(&mut state) % 200) as usize;
+            let mut values = Vec::with_capacity(n);
+            let mut v = xorshift(&mut state) as u32;
+            for _ in 0..n {
+                // Mix of runs and random jumps
+                let r = xorshift(&mut state);
+                v = match r % 4 {
+                    0 => r as u32,
+                    1 => v.wrapping_sub((r >> 32) as u32 % 16),
+                    _ => v.wrapping_add(1),
+                };
+                values.push(v);
+            }
+            roundtrip(&values);
+        }
+
+        // A dozen bytes claiming 4 billion values, nearly all in one run
+        let huge = [0xFF, 0xFF, 0xFF, 0xFF, 0x0F, 0, 2, 0xFE, 0xFF, 0xFF, 0xFF, 0x0F];
+        let mut out = Vec::new();
+        assert!(ByteReader::new(&huge).try_read_delta_rle(1 << 16, &mut out).is_err());
+        assert!(out.capacity() < 1 << 16);
+    }
+    #[test]
+    pub fn test_checked_reader() {
+        use super::{ByteReader, ByteWriter};
+        use crate::protocol::MessageError;
+
+        let mut buf = [0u8; 32];
+        let mut writer = ByteWriter::new(&mut buf);
+        writer.write_u16(0xBEEF);
+        writer.write_f32(1.5);
+        writer.write_varint_u32(300);
+        writer.write(b"hi");
+        let len = writer.bytes_written();
+
+        let mut reader = ByteReader::new(&buf[..len]);
+        assert_eq!(reader.try_read_u16().unwrap(), 0xBEEF);
+        assert_eq!(reader.try_read_f32().unwrap(), 1.5);
+        assert_eq!(reader.try_read_varint_u32().unwrap(), 300);
+        // Failed reads don't consume anything
+        assert!(matches!(reader.try_read_u32(), Err(MessageError::NotEnoughData)));
+        assert!(matches!(reader.try_read_str(3), Err(MessageError::NotEnoughData)));
+        assert!(matches!(reader.try_read_slice(usize::MAX), Err(MessageError::NotEnoughData)));
+        assert_eq!(reader.try_read_str(2).unwrap(), "hi");
+        assert!(matches!(reader.try_read_u8(), Err(MessageError::NotEnoughData)));
+
+        // Every truncation of a valid message must fail cleanly rather than read out of bounds
+        for end in 0..len {
+            let mut reader = ByteReader::new(&buf[..end]);
+            let result = (|| -> Result<(), MessageError> {
+                reader.try_read_u16()?;
+                reader.try_read_f32()?;
+                reader.try_read_varint_u32()?;
+                reader.try_read_str(2)?;
+                Ok(())
+            })();
+            assert!(matches!(result, Err(MessageError::NotEnoughData)));
+        }
+
+        let mut reader = ByteReader::new(&[0xFF, 0xFE]);
+        assert!(matches!(reader.try_read_str(2), Err(MessageError::Malformed)));
+
+        // Overlong varints
+        let mut reader = ByteReader::new(&[0xFF; 11]);
+        assert!(matches!(reader.try_read_varint_u64(), Err(MessageError::Malformed)));
+        let mut reader = ByteReader::new(&[0xFF, 0xFF, 0xFF, 0xFF, 0x7F]);
+        assert!(matches!(reader.try_read_varint_u32(), Err(MessageError::Malformed)));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,53 @@
+// Block properties that the client and the server need to agree on. The client has its own
+// BlockId type for rendering (see client::world::block); this goes by the raw ids.
+
+use crate::{fluid::WATER, worldgen::{BlockId, AIR, LEAVES, LOG, STONE, TORCH}};
+
+// The data bits above are the same block as far as these are concerned
+const ID_MASK: BlockId = (1 << 10) - 1;
+
+// Whether this is a block there is, data bits aside. Ids from a newer or a modified client
+// aren't, and the server turns them away.
+pub fn is_known(block: BlockId) -> bool {
+    matches!(block & ID_MASK, AIR | STONE | TORCH | LOG | LEAVES | WATER)
+}
+
+// How many ticks it takes to break the block by hand, or None if it can't be, or isn't a
+// block there is (see is_known()). The server checks that a client took about this long,
+// see c2s::BlockAction::FinishBreaking.
+pub fn break_ticks(block: BlockId) -> Option<u32> {
+    match block & ID_MASK {
+        AIR | WATER => None,
+        TORCH => Some(0),
+        LEAVES => Some(8),
+        STONE => Some(48),
+        LOG => Some(64),
+        _ => None,
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_break_ticks() {
+        use super::break_ticks;
+        use crate::{fluid, worldgen::{AIR, STONE, TORCH}};
+
+        assert_eq!(break_ticks(AIR), None);
+        assert_eq!(break_ticks(fluid::water(0)), None);
+        assert_eq!(break_ticks(fluid::water(fluid::MAX_LEVEL)), None);
+        assert_eq!(break_ticks(TORCH), Some(0));
+        assert!(break_ticks(STONE).unwrap() > 0);
+        assert_eq!(break_ticks(0x3FF), None);
+    }
+
+    #[test]
+    fn test_is_known() {
+        use super::is_known;
+        use crate::{fluid, worldgen::{AIR, LEAVES, STONE}};
+
+        assert!(is_known(AIR) && is_known(STONE) && is_known(LEAVES));
+        assert!(is_known(fluid::water(3)));
+        assert!(!is_known(LEAVES + 100));
+        assert!(!is_known(0xFFFF));
+    }
+}
@@ -0,0 +1,224 @@
+// How blocks are stored on disk, both in the server's world file (see server::savefile) and
+// in schematics, so that tools can read and write either without the server.
+//
+// A list of chunks, little-endian: u32 chunk count, then per chunk its position in chunks
+// (3 x i32) and its blocks in `LocalPos::index()` order as runs: u16 run count, then
+// (u16 length, u16 block) for each.
+//
+// A schematic is a box of blocks cut out of the world, positioned relative to its minimum
+// corner: SCHEMATIC_MAGIC, u32 SCHEMATIC_VERSION, its size (3 x i32), then its blocks as a
+// list of chunks. Chunks that are all air are left out.
+
+use std::collections::HashMap;
+
+use anyhow::bail;
+use glam::IVec3;
+
+use crate::{
+    bits_and_bytes::ByteReader,
+    coords::{BlockPos, ChunkPos},
+    worldgen::{BlockId, AIR, CHUNK_SIZE, CHUNK_VOLUME, WORLD_HEIGHT},
+};
+
+pub const WORLD_MAGIC: &[u8; 4] = b"VXWD";
+// The world file version whose body is a list of chunks as above. The server migrates older
+// files on load; tools only read this one.
+pub const WORLD_VERSION: u32 = 1;
+
+pub const SCHEMATIC_MAGIC: &[u8; 4] = b"VXSC";
+pub const SCHEMATIC_VERSION: u32 = 1;
+// Per axis. Keeps a corrupt header from asking for gigabytes.
+pub const MAX_SCHEMATIC_SIZE: i32 = 1024;
+
+pub type Chunk = Box<[BlockId; CHUNK_VOLUME]>;
+
+// Sorted by position, so that the same blocks always encode the same
+pub fn write_chunks(out: &mut Vec<u8>, mut chunks: Vec<(ChunkPos, &[BlockId; CHUNK_VOLUME])>) {
+    chunks.sort_unstable_by_key(|(pos, _)| pos.0.to_array());
+    out.extend((chunks.len() as u32).to_le_bytes());
+    for (pos, blocks) in chunks {
+        for coord in pos.0.to_array() {
+            out.extend(coord.to_le_bytes());
+        }
+        let mut runs: Vec<(u16, BlockId)> = Vec::new();
+        for &block in blocks.iter() {
+            match runs.last_mut() {
+                Some((len, last)) if *last == block => *len += 1,
+                _ => runs.push((1, block)),
+            }
+        }
+        out.extend((runs.len() as u16).to_le_bytes());
+        for (len, block) in runs {
+            out.extend(len.to_le_bytes());
+            out.extend(block.to_le_bytes());
+        }
+    }
+}
+
+// Hands each chunk to `insert` as it's read
+pub fn read_chunks(reader: &mut ByteReader, mut insert: impl FnMut(ChunkPos, Chunk)) -> anyhow::Result<()> {
+    for _ in 0..reader.try_read_u32()? {
+        let pos = IVec3::new(reader.try_read_i32()?, reader.try_read_i32()?, reader.try_read_i32()?);
+        if pos.y < 0 || pos.y >= WORLD_HEIGHT / CHUNK_SIZE {
+            bail!("chunk {pos} is outside of the world");
+        }
+        let mut blocks = Box::new([AIR; CHUNK_VOLUME]);
+        let mut filled = 0;
+        for _ in 0..reader.try_read_u16()? {
+            let (len, block) = (reader.try_read_u16()? as usize, reader.try_read_u16()?);
+            let Some(run) = blocks.get_mut(filled..filled + len) else {
+                bail!("chunk {pos} has more than {CHUNK_VOLUME} blocks");
+            };
+            run.fill(block);
+            filled += len;
+        }
+        if filled != CHUNK_VOLUME {
+            bail!("chunk {pos} has {filled} blocks instead of {CHUNK_VOLUME}");
+        }
+        insert(ChunkPos(pos), blocks);
+    }
+    Ok(())
+}
+
+pub struct Schematic {
+    size: IVec3,
+    chunks: HashMap<ChunkPos, Chunk>,
+}
+
+impl Schematic {
+    // All air. None if a side is empty or longer than MAX_SCHEMATIC_SIZE, or if it's taller
+    // than the world.
+    pub fn new(size: IVec3) -> Option<Self> {
+        let valid = size.cmpgt(IVec3::ZERO).all()
+            && size.cmple(IVec3::splat(MAX_SCHEMATIC_SIZE)).all()
+            && size.y <= WORLD_HEIGHT;
+        valid.then(|| Self { size, chunks: HashMap::new() })
+    }
+
+    pub fn size(&self) -> IVec3 {
+        self.size
+    }
+
+    pub fn contains(&self, pos: IVec3) -> bool {
+        pos.cmpge(IVec3::ZERO).all() && pos.cmplt(self.size).all()
+    }
+
+    // `pos` relative to the minimum corner; air outside
+    pub fn block_at(&self, pos: IVec3) -> BlockId {
+        let pos = BlockPos(pos);
+        match self.chunks.get(&pos.chunk()) {
+            Some(chunk) if self.contains(pos.0) => chunk[pos.local().index()],
+            _ => AIR,
+        }
+    }
+
+    // Ignored outside
+    pub fn set_block(&mut self, pos: IVec3, block: BlockId) {
+        if !self.contains(pos) || (block == AIR && self.block_at(pos) == AIR) {
+            return;
+        }
+        let pos = BlockPos(pos);
+        let chunk = self.chunks.entry(pos.chunk()).or_insert_with(|| Box::new([AIR; CHUNK_VOLUME]));
+        chunk[pos.local().index()] = block;
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = SCHEMATIC_MAGIC.to_vec();
+        out.extend(SCHEMATIC_VERSION.to_le_bytes());
+        for coord in self.size.to_array() {
+            out.extend(coord.to_le_bytes());
+        }
+        let chunks = self.chunks.iter()
+            .filter(|(_, blocks)| blocks.iter().any(|&block| block != AIR))
+            .map(|(&pos, blocks)| (pos, &**blocks))
+            .collect();
+        write_chunks(&mut out, chunks);
+        out
+    }
+
+    pub fn decode(file: &[u8]) -> anyhow::Result<Self> {
+        let (magic, rest) = file.split_at(SCHEMATIC_MAGIC.len().min(file.len()));
+        if magic != SCHEMATIC_MAGIC {
+            bail!("not a schematic");
+        }
+        let mut reader = ByteReader::new(rest);
+        let version = reader.try_read_u32()?;
+        if version != SCHEMATIC_VERSION {
+            bail!("schematic version {version}, only version {SCHEMATIC_VERSION} is supported");
+        }
+        let size = IVec3::new(reader.try_read_i32()?, reader.try_read_i32()?, reader.try_read_i32()?);
+        let Some(mut schematic) = Self::new(size) else {
+            bail!("invalid size {size}");
+        };
+
+        let size_in_chunks = (size + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        let mut out_of_bounds = None;
+        read_chunks(&mut reader, |pos, blocks| {
+            if pos.0.cmplt(IVec3::ZERO).any() || pos.0.cmpge(size_in_chunks).any() {
+                out_of_bounds = Some(pos);
+            }
+            schematic.chunks.insert(pos, blocks);
+        })?;
+        if let Some(pos) = out_of_bounds {
+            bail!("chunk {} is outside of the schematic", pos.0);
+        }
+        if reader.bytes_remaining() > 0 {
+            bail!("{} unexpected bytes at the end", reader.bytes_remaining());
+        }
+        Ok(schematic)
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_schematic_roundtrip() {
+        use glam::{ivec3, IVec3};
+        use super::{Schematic, MAX_SCHEMATIC_SIZE};
+
+        let mut schematic = Schematic::new(ivec3(20, 3, 40)).unwrap();
+        schematic.set_block(ivec3(0, 0, 0), 1);
+        schematic.set_block(ivec3(19, 2, 39), 2);
+        schematic.set_block(ivec3(17, 1, 5), 3);
+        // Outside, ignored
+        schematic.set_block(ivec3(20, 0, 0), 4);
+        schematic.set_block(ivec3(-1, 0, 0), 4);
+
+        let decoded = Schematic::decode(&schematic.encode()).unwrap();
+        assert_eq!(decoded.size(), ivec3(20, 3, 40));
+        for (pos, block) in [(ivec3(0, 0, 0), 1), (ivec3(19, 2, 39), 2), (ivec3(17, 1, 5), 3), (ivec3(20, 0, 0), 0), (ivec3(1, 0, 0), 0)] {
+            assert_eq!(decoded.block_at(pos), block, "{pos}");
+        }
+        assert_eq!(decoded.encode(), schematic.encode());
+
+        // Air only takes the header and an empty list of chunks
+        assert_eq!(Schematic::new(IVec3::ONE).unwrap().encode().len(), 4 + 4 + 12 + 4);
+        assert!(Schematic::new(ivec3(0, 1, 1)).is_none());
+        assert!(Schematic::new(ivec3(1, 1, MAX_SCHEMATIC_SIZE + 1)).is_none());
+    }
+
+    #[test]
+    fn test_schematic_rejects_corrupt_files() {
+        use glam::ivec3;
+        use super::Schematic;
+
+        let mut schematic = Schematic::new(ivec3(4, 4, 4)).unwrap();
+        schematic.set_block(ivec3(1, 1, 1), 5);
+        let file = schematic.encode();
+
+        // Cut short anywhere, or with anything after
+        for end in 0..file.len() {
+            assert!(Schematic::decode(&file[..end]).is_err(), "{end}");
+        }
+        assert!(Schematic::decode(&[&file[..], &[0]].concat()).is_err());
+
+        // A chunk beyond the size
+        let mut far = file.clone();
+        far[24..28].copy_from_slice(&1i32.to_le_bytes());
+        assert!(Schematic::decode(&far).is_err());
+
+        // A world file isn't a schematic
+        let mut world = file;
+        world[..4].copy_from_slice(b"VXWD");
+        assert!(Schematic::decode(&world).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+// Integer positions in the world come in three kinds, and mixing them up is an easy mistake
+// to make when they are all IVec3s: a block in the world (`BlockPos`), a chunk (`ChunkPos`),
+// and a block within its chunk (`LocalPos`). These keep them apart; the conversions between
+// them, and from float positions, live here and nowhere else.
+
+use glam::{IVec3, Vec3};
+
+use crate::worldgen::{CHUNK_SIZE, CHUNK_SIZE_LOG2, CHUNK_VOLUME};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BlockPos(pub IVec3);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ChunkPos(pub IVec3);
+
+// Each coordinate in 0..CHUNK_SIZE
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct LocalPos {
+    x: u8,
+    y: u8,
+    z: u8,
+}
+
+impl BlockPos {
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Self(IVec3::new(x, y, z))
+    }
+
+    // The block the point is in. Rounds towards negative infinity, so -0.5 is in block -1.
+    pub fn containing(pos: Vec3) -> Self {
+        Self(pos.floor().as_ivec3())
+    }
+
+    pub fn chunk(self) -> ChunkPos {
+        ChunkPos(self.0 >> CHUNK_SIZE_LOG2)
+    }
+
+    pub fn local(self) -> LocalPos {
+        let local = self.0 & (CHUNK_SIZE - 1);
+        LocalPos { x: local.x as u8, y: local.y as u8, z: local.z as u8 }
+    }
+
+    // The corner with the smallest coordinates
+    pub fn min_corner(self) -> Vec3 {
+        self.0.as_vec3()
+    }
+
+    pub fn center(self) -> Vec3 {
+        self.0.as_vec3() + 0.5
+    }
+}
+
+impl ChunkPos {
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Self(IVec3::new(x, y, z))
+    }
+
+    pub fn containing(pos: Vec3) -> Self {
+        BlockPos::containing(pos).chunk()
+    }
+
+    // The block at the chunk's corner with the smallest coordinates
+    pub fn origin(self) -> BlockPos {
+        BlockPos(self.0 << CHUNK_SIZE_LOG2)
+    }
+
+    pub fn block(self, local: LocalPos) -> BlockPos {
+        BlockPos(self.origin().0 + local.as_ivec3())
+    }
+
+    pub fn center(self) -> Vec3 {
+        (self.0.as_vec3() + 0.5) * CHUNK_SIZE as f32
+    }
+}
+
+impl LocalPos {
+    // None if any coordinate is outside of 0..CHUNK_SIZE
+    pub fn new(x: i32, y: i32, z: i32) -> Option<Self> {
+        let range = 0..CHUNK_SIZE;
+        (range.contains(&x) && range.contains(&y) && range.contains(&z))
+            .then_some(Self { x: x as u8, y: y as u8, z: z as u8 })
+    }
+
+    pub fn x(self) -> i32 {
+        self.x as i32
+    }
+
+    pub fn y(self) -> i32 {
+        self.y as i32
+    }
+
+    pub fn z(self) -> i32 {
+        self.z as i32
+    }
+
+    pub fn as_ivec3(self) -> IVec3 {
+        IVec3::new(self.x(), self.y(), self.z())
+    }
+
+    // Into the chunk arrays of worldgen and the server's BlockWorld: x fastest, then z, then y
+    pub fn index(self) -> usize {
+        crate::worldgen::block_index(self.x(), self.y(), self.z())
+    }
+
+    pub fn from_index(index: usize) -> Self {
+        debug_assert!(index < CHUNK_VOLUME);
+        let size = CHUNK_SIZE as usize;
+        Self {
+            x: (index % size) as u8,
+            y: (index / (size * size)) as u8,
+            z: (index / size % size) as u8,
+        }
+    }
+
+    // Every position in the chunk, in index order
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0..CHUNK_VOLUME).map(Self::from_index)
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_block_to_chunk_and_local() {
+        use super::{BlockPos, ChunkPos, LocalPos};
+        use crate::worldgen::CHUNK_SIZE;
+
+        // Every block of a span of chunks on each axis, across zero
+        for c in -3 * CHUNK_SIZE..3 * CHUNK_SIZE {
+            for pos in [BlockPos::new(c, 0, 0), BlockPos::new(0, c, 0), BlockPos::new(0, 0, c), BlockPos::new(c, -c, c)] {
+                let (chunk, local) = (pos.chunk(), pos.local());
+                assert_eq!(chunk.block(local), pos, "{pos:?}");
+                assert_eq!(chunk.origin().chunk(), chunk);
+                assert!(LocalPos::new(local.x(), local.y(), local.z()).is_some());
+                // Floor division, not truncation
+                let floor_div = |c: i32| c.div_euclid(CHUNK_SIZE);
+                assert_eq!(chunk, ChunkPos::new(floor_div(pos.0.x), floor_div(pos.0.y), floor_div(pos.0.z)));
+            }
+        }
+        assert_eq!(BlockPos::new(-1, 0, 0).chunk(), ChunkPos::new(-1, 0, 0));
+        assert_eq!(BlockPos::new(-1, 0, 0).local(), LocalPos::new(CHUNK_SIZE - 1, 0, 0).unwrap());
+        assert_eq!(BlockPos::new(-CHUNK_SIZE, 0, 0).chunk(), ChunkPos::new(-1, 0, 0));
+        assert_eq!(BlockPos::new(-CHUNK_SIZE - 1, 0, 0).chunk(), ChunkPos::new(-2, 0, 0));
+    }
+
+    #[test]
+    fn test_from_float() {
+        use super::{BlockPos, ChunkPos};
+        use glam::vec3;
+
+        assert_eq!(BlockPos::containing(vec3(0.5, 0.0, 0.999)), BlockPos::new(0, 0, 0));
+        assert_eq!(BlockPos::containing(vec3(-0.5, -0.0, -1.0)), BlockPos::new(-1, 0, -1));
+        assert_eq!(BlockPos::containing(vec3(-1.001, 15.9, 16.0)), BlockPos::new(-2, 15, 16));
+        assert_eq!(ChunkPos::containing(vec3(-0.01, 15.99, 16.0)), ChunkPos::new(-1, 0, 1));
+
+        for pos in [BlockPos::new(3, -7, 100), BlockPos::new(-16, 0, 15)] {
+            assert_eq!(BlockPos::containing(pos.center()), pos);
+            assert_eq!(BlockPos::containing(pos.min_corner()), pos);
+        }
+        let chunk = ChunkPos::new(-2, 3, 0);
+        assert_eq!(ChunkPos::containing(chunk.center()), chunk);
+        assert_eq!(BlockPos::containing(chunk.center()).chunk(), chunk);
+    }
+
+    #[test]
+    fn test_local_index() {
+        use super::LocalPos;
+        use crate::worldgen::{block_index, CHUNK_SIZE, CHUNK_VOLUME};
+
+        let all = LocalPos::all().collect::<Vec<_>>();
+        assert_eq!(all.len(), CHUNK_VOLUME);
+        for (index, &local) in all.iter().enumerate() {
+            assert_eq!(local.index(), index);
+            assert_eq!(LocalPos::from_index(index), local);
+            assert_eq!(block_index(local.x(), local.y(), local.z()), index);
+        }
+
+        assert_eq!(LocalPos::new(0, 0, 0).map(LocalPos::index), Some(0));
+        assert_eq!(LocalPos::new(1, 0, 0).map(LocalPos::index), Some(1));
+        assert_eq!(LocalPos::new(0, 0, 1).map(LocalPos::index), Some(CHUNK_SIZE as usize));
+        for (x, y, z) in [(-1, 0, 0), (0, CHUNK_SIZE, 0), (0, 0, 99)] {
+            assert_eq!(LocalPos::new(x, y, z), None);
+        }
+    }
+}
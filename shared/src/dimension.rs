@@ -0,0 +1,49 @@
+// Worlds hosted side by side by one server, each with blocks, entities and terrain of its
+// own. Players are in exactly one at a time.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct DimensionId(pub u8);
+
+impl DimensionId {
+    pub const OVERWORLD: Self = Self(0);
+}
+
+impl std::fmt::Display for DimensionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// What the terrain of `dimension` is generated from, see worldgen. Derived from the world
+// seed, which stays on the server: clients only get the seeds of the dimensions they are in,
+// and anything the server derives from the world seed differently stays hidden from them.
+// Not cryptographically strong, only more work to undo than a single invertible hash.
+pub fn seed(world_seed: u64, dimension: DimensionId) -> u64 {
+    let salted = world_seed ^ (dimension.0 as u64 + 1).wrapping_mul(0xA076_1D64_78BD_642F);
+    mix(salted) ^ mix(salted.rotate_left(29) ^ 0xE703_7ED1_A0B4_28DB)
+}
+
+// splitmix64 finalizer
+fn mix(mut h: u64) -> u64 {
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^ (h >> 31)
+}
+
+mod tests {
+    #[test]
+    fn test_dimension_seeds() {
+        use super::{seed, DimensionId};
+
+        for world_seed in [0, 1, 42, u64::MAX] {
+            let seeds = (0..=255).map(|id| seed(world_seed, DimensionId(id))).collect::<Vec<_>>();
+            let mut unique = seeds.clone();
+            unique.sort_unstable();
+            unique.dedup();
+            assert_eq!(unique.len(), seeds.len(), "world seed {world_seed}: dimensions share a seed");
+            assert!(!seeds.contains(&world_seed), "world seed {world_seed} is given out as is");
+            assert_eq!(seed(world_seed, DimensionId::OVERWORLD), seeds[0]);
+        }
+        assert_ne!(seed(1, DimensionId::OVERWORLD), seed(2, DimensionId::OVERWORLD));
+    }
+}
@@ -0,0 +1,141 @@
+// Water flow rules, shared so that the client can tell water blocks apart the same way the
+// server does. The server runs the simulation (see server::fluids); this is only the rule
+// for a single block.
+
+use glam::IVec3;
+
+use crate::worldgen::{BlockId, AIR};
+
+// The level is kept in the block's data bits, above the 10 bits of the id, so that all of
+// the water is one block type to the client. 0 is a source, 1..=MAX_LEVEL is flowing
+// water that far from where it came from.
+pub const WATER: BlockId = 5;
+pub const MAX_LEVEL: u8 = 7;
+const LEVEL_SHIFT: u16 = 10;
+const ID_MASK: BlockId = (1 << LEVEL_SHIFT) - 1;
+
+const HORIZONTAL: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+
+pub const fn water(level: u8) -> BlockId {
+    WATER | ((level as BlockId) << LEVEL_SHIFT)
+}
+
+pub fn water_level(block: BlockId) -> Option<u8> {
+    (block & ID_MASK == WATER).then_some((block >> LEVEL_SHIFT) as u8)
+}
+
+pub fn is_flowing(block: BlockId) -> bool {
+    water_level(block).is_some_and(|level| level > 0)
+}
+
+// What the block at `pos` turns into next. Only air and flowing water ever change:
+// water falls straight down, and spreads sideways one level weaker from wherever it
+// rests on something other than air or flowing water. Flowing water that nothing feeds
+// anymore climbs past MAX_LEVEL and dries up.
+pub fn next_block(block_at: impl Fn(IVec3) -> BlockId, pos: IVec3) -> BlockId {
+    let current = block_at(pos);
+    if current != AIR && !is_flowing(current) {
+        return current;
+    }
+    if water_level(block_at(pos + IVec3::Y)).is_some() {
+        return water(1);
+    }
+
+    let mut level = None;
+    for dir in HORIZONTAL {
+        let Some(neighbour) = water_level(block_at(pos + dir)) else {
+            continue;
+        };
+        let below = block_at(pos + dir - IVec3::Y);
+        if neighbour < MAX_LEVEL && below != AIR && !is_flowing(below) {
+            level = Some(level.unwrap_or(MAX_LEVEL).min(neighbour + 1));
+        }
+    }
+    level.map_or(AIR, water)
+}
+
+// All positions whose next_block() may depend on the block at `pos`, including itself
+pub fn affected_by(pos: IVec3) -> [IVec3; 7] {
+    [
+        pos,
+        pos + IVec3::Y,
+        pos - IVec3::Y,
+        pos + IVec3::X,
+        pos - IVec3::X,
+        pos + IVec3::Z,
+        pos - IVec3::Z,
+    ]
+}
+
+mod tests {
+    #[test]
+    fn test_water_encoding() {
+        use super::{is_flowing, water, water_level, MAX_LEVEL, WATER};
+        use crate::worldgen::{AIR, STONE};
+
+        for level in 0..=MAX_LEVEL {
+            assert_eq!(water_level(water(level)), Some(level));
+        }
+        assert_eq!(water(0), WATER);
+        assert!(!is_flowing(water(0)));
+        assert!(is_flowing(water(3)));
+        assert_eq!(water_level(AIR), None);
+        assert_eq!(water_level(STONE), None);
+    }
+
+    #[test]
+    fn test_spread_and_dry_up() {
+        use std::collections::HashMap;
+        use glam::{ivec3, IVec3};
+        use super::{next_block, water, water_level, MAX_LEVEL};
+        use crate::worldgen::{BlockId, AIR, STONE};
+
+        // Stone floor at y = 0, the source on a pillar 4 blocks above it
+        let mut world: HashMap<IVec3, BlockId> = HashMap::new();
+        for z in -12..=12 {
+            for x in -12..=12 {
+                world.insert(ivec3(x, 0, z), STONE);
+            }
+        }
+        world.insert(ivec3(0, 4, 0), STONE);
+        world.insert(ivec3(0, 5, 0), water(0));
+
+        let step = |world: &HashMap<IVec3, BlockId>| {
+            let block_at = |pos: IVec3| world.get(&pos).copied().unwrap_or(AIR);
+            let mut next = world.clone();
+            for y in 1..=6 {
+                for z in -11..=11 {
+                    for x in -11..=11 {
+                        let pos = ivec3(x, y, z);
+                        let block = next_block(block_at, pos);
+                        if block == AIR {
+                            next.remove(&pos);
+                        } else {
+                            next.insert(pos, block);
+                        }
+                    }
+                }
+            }
+            next
+        };
+        let level_at = |world: &HashMap<IVec3, BlockId>, pos| world.get(&pos).copied().and_then(water_level);
+
+        for _ in 0..40 {
+            world = step(&world);
+        }
+        // Over the edge of the pillar, down, and out along the floor
+        assert_eq!(level_at(&world, ivec3(1, 5, 0)), Some(1));
+        assert_eq!(level_at(&world, ivec3(2, 5, 0)), None);
+        assert_eq!(level_at(&world, ivec3(1, 4, 0)), Some(1));
+        assert_eq!(level_at(&world, ivec3(1, 1, 0)), Some(1));
+        assert_eq!(level_at(&world, ivec3(3, 1, 0)), Some(3));
+        assert_eq!(level_at(&world, ivec3(1 + MAX_LEVEL as i32, 1, 0)), None);
+        assert_eq!(level_at(&world, ivec3(0, 3, 0)), None);
+
+        world.remove(&ivec3(0, 5, 0));
+        for _ in 0..40 {
+            world = step(&world);
+        }
+        assert!((1..=6).all(|y| (-11..=11).all(|x| level_at(&world, ivec3(x, y, 0)).is_none())));
+    }
+}
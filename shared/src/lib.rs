@@ -2,7 +2,15 @@ use std::time::Duration;
 
 pub mod protocol;
 pub mod bits_and_bytes;
+pub mod blocks;
+pub mod chunk_format;
+pub mod coords;
+pub mod dimension;
+pub mod fluid;
 pub mod jitter_prevention;
+pub mod movement;
+pub mod prediction;
+pub mod worldgen;
 
 pub const TICKS_PER_SECOND : u32 = 32;
 pub const TICK_DURATION : Duration = Duration::from_nanos(1_000_000_000 / TICKS_PER_SECOND as u64);
\ No newline at end of file
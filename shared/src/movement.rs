@@ -0,0 +1,113 @@
+// Movement rules shared by the client (which predicts its own movement) and the server
+// (which validates it).
+
+use std::ops::BitOr;
+
+use glam::Vec3;
+
+use crate::{protocol::c2s::PlayerInput, TICKS_PER_SECOND};
+
+// Sent with every input, so that the server and other clients know how the player moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MovementFlags(pub u8);
+
+impl MovementFlags {
+    pub const NONE: Self = Self(0);
+    pub const SPRINTING: Self = Self(1 << 0);
+    pub const SNEAKING: Self = Self(1 << 1);
+
+    // Number of bits used on the wire
+    pub const BITS: u32 = 2;
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn with(self, other: Self, enabled: bool) -> Self {
+        if enabled { Self(self.0 | other.0) } else { Self(self.0 & !other.0) }
+    }
+}
+
+impl BitOr for MovementFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+pub const WALK_MAX_SPEED: f32 = 20.0; // blocks per second
+const SPRINT_MULTIPLIER: f32 = 1.3;
+const SNEAK_MULTIPLIER: f32 = 0.3;
+
+// Sneaking wins over sprinting. Once there is collision, sneaking should also keep the
+// player from walking off edges.
+fn speed_multiplier(flags: MovementFlags) -> f32 {
+    if flags.contains(MovementFlags::SNEAKING) {
+        SNEAK_MULTIPLIER
+    } else if flags.contains(MovementFlags::SPRINTING) {
+        SPRINT_MULTIPLIER
+    } else {
+        1.0
+    }
+}
+
+pub fn acceleration(flags: MovementFlags) -> f32 {
+    speed_multiplier(flags)
+}
+
+pub fn max_horizontal_speed(flags: MovementFlags) -> f32 {
+    WALK_MAX_SPEED * speed_multiplier(flags)
+}
+
+// Limits the horizontal part of `velocity`, leaving vertical movement alone
+pub fn clamp_horizontal_velocity(velocity: Vec3, flags: MovementFlags) -> Vec3 {
+    let max = max_horizontal_speed(flags);
+    let horizontal = Vec3::new(velocity.x, 0.0, velocity.z).clamp_length_max(max);
+    Vec3::new(horizontal.x, velocity.y, horizontal.z)
+}
+
+// Whether a single input's horizontal movement is plausible. Checked against the sprinting
+// speed no matter the flags, since the player keeps some speed for a moment after they
+// stop sprinting or start sneaking. Lenient, because the client's frames don't line up
+// exactly with ticks.
+pub fn is_plausible_delta(delta_pos: Vec3) -> bool {
+    const TOLERANCE: f32 = 1.5;
+    let max_per_tick = max_horizontal_speed(MovementFlags::SPRINTING) / TICKS_PER_SECOND as f32;
+    Vec3::new(delta_pos.x, 0.0, delta_pos.z).length() <= max_per_tick * TOLERANCE
+}
+
+// How the server moves a player by one input. Returns false if the movement was rejected.
+pub fn apply_input(position: &mut Vec3, input: &PlayerInput) -> bool {
+    match input.delta_pos {
+        Some(delta) if !is_plausible_delta(delta) => false,
+        Some(delta) => {
+            *position += delta;
+            true
+        }
+        None => true,
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_sneaking_overrides_sprinting() {
+        use super::{max_horizontal_speed, MovementFlags, WALK_MAX_SPEED};
+
+        let both = MovementFlags::SPRINTING | MovementFlags::SNEAKING;
+        assert_eq!(max_horizontal_speed(both), max_horizontal_speed(MovementFlags::SNEAKING));
+        assert!(max_horizontal_speed(MovementFlags::SPRINTING) > WALK_MAX_SPEED);
+        assert!(max_horizontal_speed(MovementFlags::SNEAKING) < WALK_MAX_SPEED);
+    }
+
+    #[test]
+    fn test_clamp_keeps_vertical_velocity() {
+        use glam::vec3;
+        use super::{clamp_horizontal_velocity, MovementFlags, WALK_MAX_SPEED};
+
+        let clamped = clamp_horizontal_velocity(vec3(100.0, -50.0, 0.0), MovementFlags::NONE);
+        assert!(clamped.abs_diff_eq(vec3(WALK_MAX_SPEED, -50.0, 0.0), 1e-4));
+        let slow = vec3(1.0, 2.0, 3.0);
+        assert_eq!(clamp_horizontal_velocity(slow, MovementFlags::NONE), slow);
+    }
+}
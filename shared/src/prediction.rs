@@ -0,0 +1,181 @@
+// Client-side prediction of the player's own movement. Per-frame movement is accumulated
+// into per-tick inputs, rounded exactly like the network encoding rounds them, so that
+// the server ends up at the very same position when it applies the inputs (see
+// movement::apply_input). The tests below check that it really does.
+
+use glam::{DVec2, DVec3, Vec2, Vec3};
+
+use crate::{
+    movement::MovementFlags,
+    protocol::{self, c2s::PlayerInput, wrap_angles},
+    TICKS_PER_SECOND,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct InputSnapshot {
+    pub tag: u16,
+    pub delta_position: Vec3, // also goes by 'velocity'
+    pub delta_rotation: Vec2,
+    pub movement: MovementFlags,
+
+    pub client_pos: Vec3,
+}
+
+impl InputSnapshot {
+    pub fn to_input(&self) -> PlayerInput {
+        PlayerInput {
+            tag: self.tag,
+            delta_pos: (self.delta_position != Vec3::ZERO).then_some(self.delta_position),
+            delta_yaw_pitch: (self.delta_rotation != Vec2::ZERO).then_some(self.delta_rotation),
+            movement: self.movement,
+        }
+    }
+}
+
+pub struct Integrator {
+    // Position and rotation after the latest full tick. Public so that they can be
+    // corrected when the server disagrees.
+    pub vel_origin: Vec3,
+    prev_vel: DVec3,
+    vel_accum: DVec3,
+
+    pub angle_origin: Vec2,
+    prev_angle: DVec2,
+    angle_accum: DVec2,
+
+    time_accum: f64,
+    prev_dt: f64,
+}
+
+impl Integrator {
+    pub fn new(origin: Vec3) -> Self {
+        Self {
+            vel_origin: origin,
+            prev_vel: DVec3::ZERO,
+            vel_accum: DVec3::ZERO,
+            angle_origin: Vec2::ZERO,
+            angle_accum: DVec2::ZERO,
+            prev_angle: DVec2::ZERO,
+            time_accum: 0.0,
+            prev_dt: 0.0,
+        }
+    }
+
+    // `vel` should be premultiplied by dt. Angles are never multiplied by dt.
+    // Returns the predicted position and (yaw, pitch) for this frame.
+    pub fn step(
+        &mut self,
+        vel: DVec3,
+        yaw_pitch: DVec2,
+        dt_secs: f64,
+        movement: MovementFlags,
+        mut input_id: u16,
+        snapshots_out: &mut Vec<InputSnapshot>,
+    ) -> (Vec3, Vec2) {
+        const NW_TICK: f64 = 1.0 / TICKS_PER_SECOND as f64;
+
+        self.time_accum += self.prev_dt;
+        while self.time_accum >= NW_TICK {
+            let k = (self.time_accum - NW_TICK) / self.prev_dt;
+            let carry_v = self.prev_vel * k;
+            let carry_a = self.prev_angle * k;
+
+            let total_v = protocol::round_velocity((self.vel_accum - carry_v).as_vec3());
+            let total_a = wrap_angles(protocol::round_angles((self.angle_accum - carry_a).as_vec2()));
+
+            self.time_accum -= NW_TICK;
+            self.vel_accum = carry_v;
+            self.angle_accum = carry_a;
+            self.vel_origin += total_v;
+            self.angle_origin = wrap_angles(self.angle_origin + total_a);
+
+            snapshots_out.push(InputSnapshot {
+                tag: input_id, 
+                delta_position: total_v,
+                delta_rotation: total_a,
+                movement,
+                client_pos: self.vel_origin 
+            });
+            input_id += 1;
+        }
+
+        let mut yaw_pitch = yaw_pitch; // mutable
+        const EPS: f64 = 0.001;
+        const PI: f64 = std::f64::consts::PI;
+        if self.angle_origin.y as f64 + self.angle_accum.y + yaw_pitch.y >= PI / 2.0 - EPS {
+            yaw_pitch.y = PI / 2.0 - EPS - self.angle_origin.y as f64 - self.angle_accum.y;
+        }
+        if self.angle_origin.y as f64 + self.angle_accum.y + yaw_pitch.y <= -PI / 2.0 + EPS {
+            yaw_pitch.y = -PI / 2.0 + EPS - self.angle_origin.y as f64 - self.angle_accum.y;
+        }
+
+        self.vel_accum += vel;
+        self.angle_accum += yaw_pitch;
+        self.prev_vel = vel;
+        self.prev_angle = yaw_pitch;
+        self.prev_dt = dt_secs;
+
+        let pos = self.vel_origin + protocol::round_velocity(self.vel_accum.as_vec3());
+        let angles = self.angle_origin + protocol::round_angles(self.angle_accum.as_vec2());
+        (pos, angles)
+    }
+}
+
+mod tests {
+    // Replays a pseudo-randomly generated session of frames through the client's
+    // prediction, sends the resulting inputs through the wire format and applies them the
+    // way the server does. Any drift between the two means rubber-banding in game.
+    #[test]
+    fn test_prediction_matches_server() {
+        use glam::{vec3, DVec2, DVec3};
+        use super::Integrator;
+        use crate::bits_and_bytes::BitWriter;
+        use crate::movement::{self, MovementFlags};
+        use crate::protocol::c2s::{read_player_state, write_player_state};
+
+        let mut state = 0x2545F4914F6CDD1Du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for start in [vec3(0.0, 0.0, 0.0), vec3(12.5, 80.0, -300.25), vec3(-40000.0, 200.0, 123456.0)] {
+            let mut integrator = Integrator::new(start);
+            let mut snapshots = Vec::new();
+            for _ in 0..20_000 {
+                let mut f = || (next() % 20001) as f64 / 10000.0 - 1.0; // -1..=1
+                // 1 to 41 ms frames, at up to walking speed in any direction
+                let dt = 0.021 + f() * 0.02;
+                let speed = movement::WALK_MAX_SPEED as f64 / 2.0;
+                let vel = DVec3::new(f(), f(), f()) * speed * dt;
+                let yaw_pitch = DVec2::new(f(), f()) * 0.05;
+                let flags = MovementFlags((next() % 4) as u8);
+                integrator.step(vel, yaw_pitch, dt, flags, snapshots.len() as u16, &mut snapshots);
+            }
+            assert!(snapshots.len() > 10_000);
+
+            let mut server_pos = start;
+            let mut prev_tag = u16::MAX;
+            let mut buf = [0u8; 64];
+            let mut received = Vec::new();
+            for snapshot in &snapshots {
+                let mut writer = BitWriter::new(&mut buf);
+                write_player_state(&mut writer, &snapshot.to_input(), std::iter::empty());
+                let len = writer.compute_bytes_written();
+
+                received.clear();
+                prev_tag = read_player_state(&buf[..len], prev_tag, &mut received);
+                assert_eq!(received.len(), 1);
+
+                assert!(movement::apply_input(&mut server_pos, &received[0]));
+                assert_eq!(
+                    server_pos.to_array().map(f32::to_bits),
+                    snapshot.client_pos.to_array().map(f32::to_bits),
+                    "drifted at input {}", snapshot.tag,
+                );
+            }
+        }
+    }
+}
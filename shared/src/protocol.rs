@@ -2,11 +2,62 @@ use std::f32::consts::{PI, TAU};
 
 use glam::{Vec2, Vec3, vec3, vec2};
 
-pub const PROTOCOL_VERSION: u16 = 0;
+pub mod batching;
+pub mod c2s;
+pub mod s2c;
+
+pub const PROTOCOL_VERSION: u16 = 13;
 pub const PROTOCOL_MAGIC: u16 = 0xB7C1;
 
 pub const MAX_ONLINE_PLAYERS: u16 = 64;
 
+// Application error codes the server closes connections with
+pub const CLOSE_INVALID_LOGIN: u32 = 1;
+pub const CLOSE_LOGIN_DENIED: u32 = 2;
+pub const CLOSE_SERVER_CLOSED: u32 = 3;
+// The reason is shown to the player
+pub const CLOSE_KICKED: u32 = 4;
+
+// Optional parts of the protocol. The client lists what it supports in c2s::Hello, the
+// server in s2c::LoginResponse, and the connection uses what both support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Features(pub u8);
+
+impl Features {
+    pub const NONE: Self = Self(0);
+    // LZ4-compressed batches of messages, see `batching`
+    pub const COMPRESSION: Self = Self(1 << 0);
+    // The server's tick number in entity state messages, for syncing the client's clock to
+    // it. See s2c::write_server_tick().
+    pub const CLOCK_SYNC: Self = Self(1 << 1);
+    // Clients can be given authority over entities, and send c2s::AuthorityMsg on a
+    // stream of their own. See MetadataKey::Owner.
+    pub const AUTHORITY: Self = Self(1 << 2);
+    // Every so often, a checksum of the entities the server thinks the client has, see
+    // s2c::write_entity_checksum(). On a mismatch, the client sends c2s::AuthorityMsg::Resync,
+    // so this needs AUTHORITY too.
+    pub const ENTITY_CHECKSUM: Self = Self(1 << 3);
+    // Blocks are broken by holding the button on them for a while, with the client sending
+    // c2s::BlockAction::StartBreaking and FinishBreaking. Needs AUTHORITY too, whose
+    // stream they go on.
+    pub const BLOCK_BREAKING: Self = Self(1 << 4);
+    // Blocks are placed by the server, with the client sending c2s::BlockAction::Place.
+    // Needs AUTHORITY too.
+    pub const BLOCK_PLACING: Self = Self(1 << 5);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn common(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
 pub type RawNetworkId = u16;
 
 // A per-entity unique identifier shared with all connected clients to identify entities.
@@ -37,6 +88,17 @@ pub enum MessageError {
     Malformed, // = kick player
 }
 
+impl std::fmt::Display for MessageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageError::NotEnoughData => f.write_str("message ended unexpectedly"),
+            MessageError::Malformed => f.write_str("malformed message"),
+        }
+    }
+}
+
+impl std::error::Error for MessageError {}
+
 // wrap angle into [-PI, PI] range
 pub fn wrap_angle(angle: f32) -> f32 {
     let mut angle = angle % TAU; // [-2PI, 2PI]
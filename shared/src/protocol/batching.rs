@@ -0,0 +1,190 @@
+use super::{Features, MessageError};
+use crate::bits_and_bytes::ByteReader;
+
+// Messages on a stream are sent in frames: a varint15 length, then the message. Senders
+// coalesce whatever is queued into one write, which doesn't change the format. With
+// `Features::COMPRESSION`, every frame instead starts with a byte telling what follows:
+// either one message as is, or an LZ4 block (with the size prepended) that decompresses
+// into more frames of the first kind.
+
+const FRAME_PLAIN: u8 = 0;
+const FRAME_LZ4: u8 = 1;
+
+// Batches smaller than this go uncompressed, there'd be little to gain
+pub const COMPRESSION_THRESHOLD: usize = 256;
+// Longest frame the varint15 length allows
+pub const MAX_FRAME_LEN: usize = (1 << 15) - 1;
+
+pub struct Batcher {
+    compression: bool,
+    frames: Vec<u8>, // as they would be sent uncompressed
+    out: Vec<u8>,
+}
+
+impl Batcher {
+    pub fn new(features: Features) -> Self {
+        Self {
+            compression: features.contains(Features::COMPRESSION),
+            frames: Vec::new(),
+            out: Vec::new(),
+        }
+    }
+
+    // `message` without the length, which gets added here
+    pub fn push(&mut self, message: &[u8]) {
+        let len = message.len() + self.compression as usize;
+        debug_assert!(len <= MAX_FRAME_LEN, "message too long ({len} bytes)");
+        push_varint15(&mut self.frames, len as u16);
+        if self.compression {
+            self.frames.push(FRAME_PLAIN);
+        }
+        self.frames.extend_from_slice(message);
+    }
+
+    // Uncompressed, including the framing
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // The bytes to write to the stream. Empties the batch for the next one.
+    pub fn finish(&mut self) -> &[u8] {
+        self.out.clear();
+        if self.compression && self.frames.len() >= COMPRESSION_THRESHOLD {
+            let compressed = lz4::block::compress(&self.frames, None, true).ok()
+                .filter(|compressed| compressed.len() + 1 < self.frames.len().min(MAX_FRAME_LEN));
+            if let Some(compressed) = compressed {
+                push_varint15(&mut self.out, compressed.len() as u16 + 1);
+                self.out.push(FRAME_LZ4);
+                self.out.extend_from_slice(&compressed);
+                self.frames.clear();
+                return &self.out;
+            }
+        }
+        std::mem::swap(&mut self.frames, &mut self.out);
+        self.frames.clear();
+        &self.out
+    }
+}
+
+// The receiving end of a `Batcher`
+pub struct Unbatcher {
+    compression: bool,
+    max_batch_len: usize, // decompressed, so that a tiny frame can't claim to hold gigabytes
+    batch: Vec<u8>,
+    pos: usize,
+}
+
+impl Unbatcher {
+    pub fn new(features: Features, max_batch_len: usize) -> Self {
+        Self {
+            compression: features.contains(Features::COMPRESSION),
+            max_batch_len,
+            batch: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    // Whether messages of a compressed batch are left. These must be taken with
+    // `next_in_batch()` before receiving the next frame.
+    pub fn has_batched(&self) -> bool {
+        self.pos < self.batch.len()
+    }
+
+    pub fn next_in_batch(&mut self) -> Result<&[u8], MessageError> {
+        let mut reader = ByteReader::new(&self.batch[self.pos..]);
+        let len = reader.try_read_varint15()? as usize;
+        let start = self.pos + reader.bytes_read();
+        let frame = self.batch.get(start..start + len).ok_or(MessageError::NotEnoughData)?;
+        self.pos = start + len;
+        // No batches within batches
+        match frame.split_first() {
+            Some((&FRAME_PLAIN, message)) => Ok(message),
+            _ => Err(MessageError::Malformed),
+        }
+    }
+
+    // A frame as received, without the length. Returns the message in it, or the first one
+    // if it's a compressed batch.
+    pub fn unpack<'a>(&'a mut self, frame: &'a [u8]) -> Result<&'a [u8], MessageError> {
+        if !self.compression {
+            return Ok(frame);
+        }
+        match frame.split_first() {
+            Some((&FRAME_PLAIN, message)) => Ok(message),
+            Some((&FRAME_LZ4, block)) => {
+                let size = ByteReader::new(block).try_read_u32()? as usize;
+                if size == 0 || size > self.max_batch_len {
+                    return Err(MessageError::Malformed);
+                }
+                self.batch = lz4::block::decompress(&block[4..], Some(size as i32))
+                    .map_err(|_| MessageError::Malformed)?;
+                self.pos = 0;
+                self.next_in_batch()
+            }
+            _ => Err(MessageError::Malformed),
+        }
+    }
+}
+
+fn push_varint15(dst: &mut Vec<u8>, x: u16) {
+    if x < 128 {
+        dst.push(x as u8);
+    } else {
+        dst.extend_from_slice(&[(x & 127) as u8 | 128, (x >> 7) as u8]);
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_batching_roundtrip() {
+        use super::{Batcher, Unbatcher, COMPRESSION_THRESHOLD};
+        use crate::{bits_and_bytes::ByteReader, protocol::Features};
+
+        let messages: Vec<Vec<u8>> = vec![b"hi".to_vec(), vec![], vec![7; 300], b"the end".to_vec()];
+        for features in [Features::NONE, Features::COMPRESSION] {
+            let mut batcher = Batcher::new(features);
+            for message in &messages {
+                batcher.push(message);
+            }
+            assert!(batcher.len() >= COMPRESSION_THRESHOLD);
+            let sent = batcher.finish().to_vec();
+            assert!(batcher.is_empty());
+            if features == Features::COMPRESSION {
+                assert!(sent.len() < 100, "{} bytes, not compressed", sent.len());
+            }
+
+            // Split into frames the way the network threads do
+            let mut unbatcher = Unbatcher::new(features, 4096);
+            let mut reader = ByteReader::new(&sent);
+            let mut received = Vec::new();
+            while reader.bytes_remaining() > 0 {
+                let len = reader.try_read_varint15().unwrap() as usize;
+                let frame = reader.try_read_slice(len).unwrap();
+                received.push(unbatcher.unpack(frame).unwrap().to_vec());
+                while unbatcher.has_batched() {
+                    received.push(unbatcher.next_in_batch().unwrap().to_vec());
+                }
+            }
+            assert_eq!(received, messages);
+        }
+    }
+
+    #[test]
+    fn test_batching_rejects_oversized() {
+        use super::{Batcher, Unbatcher};
+        use crate::{bits_and_bytes::ByteReader, protocol::Features};
+
+        let mut batcher = Batcher::new(Features::COMPRESSION);
+        batcher.push(&[0; 1000]);
+        let sent = batcher.finish().to_vec();
+        let mut reader = ByteReader::new(&sent);
+        let len = reader.try_read_varint15().unwrap() as usize;
+        let frame = reader.try_read_slice(len).unwrap();
+        assert!(Unbatcher::new(Features::COMPRESSION, 100).unpack(frame).is_err());
+        assert!(Unbatcher::new(Features::COMPRESSION, 2000).unpack(frame).is_ok());
+    }
+}
@@ -0,0 +1,379 @@
+// Client -> server messages. Everything decoded here comes from an untrusted
+// client, so all reads must be bounds-checked.
+
+use glam::{IVec3, Vec2, Vec3, vec3, vec2, ivec3};
+
+use crate::{bits_and_bytes::{BitReader, BitWriter, ByteReader, ByteWriter}, movement::MovementFlags};
+
+use super::{Features, MessageError, NetworkId, decode_angle_rad, decode_velocity, encode_angle_rad, encode_velocity, wrap_angle};
+
+// First message on the login stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hello<'a> {
+    pub magic: u16,
+    pub version: u16,
+    pub username: &'a str,
+    pub skin: u8,
+    pub features: Features,
+    // Made up by the client for each server and username. The server ties the username
+    // to the first key it sees, and turns away anyone joining with it later using another.
+    pub key: [u8; 16],
+}
+
+impl<'a> Hello<'a> {
+    pub fn write(&self, writer: &mut ByteWriter) {
+        writer.write_u16(self.magic);
+        writer.write_u16(self.version);
+        writer.write_varint_u32(self.username.len() as u32);
+        writer.write(self.username.as_bytes());
+        writer.write_u8(self.skin);
+        writer.write_u8(self.features.0);
+        writer.write(&self.key);
+    }
+
+    pub fn read(reader: &mut ByteReader<'a>) -> Result<Self, MessageError> {
+        let magic = reader.try_read_u16()?;
+        let version = reader.try_read_u16()?;
+        let username_len = reader.try_read_varint_u32()? as usize;
+        let username = reader.try_read_str(username_len)?;
+        let skin = reader.try_read_u8()?;
+        let features = Features(reader.try_read_u8()?);
+        let key = reader.try_read_slice(16)?.try_into().unwrap();
+        Ok(Self { magic, version, username, skin, features, key })
+    }
+}
+
+// One tick worth of player input. `None` = didn't change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerInput {
+    pub tag: u16,
+    pub delta_pos: Option<Vec3>,
+    pub delta_yaw_pitch: Option<Vec2>,
+    pub movement: MovementFlags,
+}
+
+impl PlayerInput {
+    fn write_body(&self, writer: &mut BitWriter) {
+        if let Some(delta) = self.delta_pos {
+            writer.bool(true);
+            writer.uint(encode_velocity(delta.x), 16);
+            writer.uint(encode_velocity(delta.y), 16);
+            writer.uint(encode_velocity(delta.z), 16);
+        } else {
+            writer.bool(false);
+        }
+        if let Some(delta) = self.delta_yaw_pitch {
+            writer.bool(true);
+            writer.uint(encode_angle_rad(wrap_angle(delta.x)) as u32, 16);
+            writer.uint(encode_angle_rad(wrap_angle(delta.y)) as u32, 16);
+        } else {
+            writer.bool(false);
+        }
+        writer.uint(self.movement.0 as u32, MovementFlags::BITS);
+    }
+
+    fn read_body(reader: &mut BitReader, tag: u16) -> Self {
+        Self {
+            tag,
+            delta_pos: reader.bool().then(|| vec3(
+                decode_velocity(reader.uint(16)),
+                decode_velocity(reader.uint(16)),
+                decode_velocity(reader.uint(16)),
+            )),
+            delta_yaw_pitch: reader.bool().then(|| vec2(
+                decode_angle_rad(reader.uint(16) as u16),
+                decode_angle_rad(reader.uint(16) as u16),
+            )),
+            movement: MovementFlags(reader.uint(MovementFlags::BITS) as u8),
+        }
+    }
+}
+
+// Player state datagram: the latest input, followed by older inputs (newest first) so
+// that the server can recover from lost datagrams. Tags of the older inputs are implicit
+// (latest.tag - 1, latest.tag - 2, ...).
+pub fn write_player_state(writer: &mut BitWriter, latest: &PlayerInput, older: impl Iterator<Item = PlayerInput>) {
+    writer.uint(latest.tag as u32, 16);
+    latest.write_body(writer);
+
+    // NOTE reverse order. Latest snapshot is first (above). This is so that
+    // if no previous snapshots are missing, then there is no need to parse all of the
+    // snapshots just to get to the needed (latest) snapshot.
+    for input in older {
+        writer.bool(true); // has next
+        input.write_body(writer);
+    }
+    writer.bool(false); // doesn't have next
+    writer.flush_partials();
+}
+
+// Decodes a player state datagram into `out` (latest first), stopping once the inputs
+// are no newer than `prev_tag`. Returns the latest tag; if it equals `prev_tag`, nothing
+// is pushed. Never fails: BitReader reads zeros past the end of the buffer.
+pub fn read_player_state(buf: &[u8], prev_tag: u16, out: &mut Vec<PlayerInput>) -> u16 {
+    let mut reader = BitReader::new(buf);
+    let latest_tag = reader.uint(16) as u16;
+    let latest = PlayerInput::read_body(&mut reader, latest_tag);
+    if latest_tag == prev_tag {
+        return latest_tag;
+    }
+    out.push(latest);
+
+    let mut tag = latest_tag;
+    let mut num_missing = latest_tag.wrapping_sub(prev_tag);
+    while num_missing > 1 && reader.bool() {
+        tag = tag.wrapping_sub(1);
+        num_missing -= 1;
+        out.push(PlayerInput::read_body(&mut reader, tag));
+    }
+    latest_tag
+}
+
+// Authority over entities, see Features::AUTHORITY. The server answers a claim or release
+// that it accepts by changing the entity's MetadataKey::Owner, and ignores the rest; a move
+// it doesn't accept gets the entity teleported back for everybody, owner included.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuthorityMsg {
+    Claim { id: NetworkId },
+    Release { id: NetworkId },
+    // The state of an entity the client has authority over, as it predicted it
+    Move { id: NetworkId, position: Vec3, head_rotation: Vec2 },
+    // Not about authority: the client's entities didn't match an s2c::EntityStateMsg::EntityChecksum.
+    // It has dropped all of them, and the server sends them again as if newly in range.
+    Resync,
+    // Nor is this, but it's the stream the client tells the server what it does on
+    Block(BlockAction),
+}
+
+// Breaking and placing blocks, see Features::BLOCK_BREAKING and BLOCK_PLACING. The server
+// answers by changing the block for everybody, or not at all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockAction {
+    // Starting on another block replaces the one before. The server breaks the block if it's
+    // still the same one and at least shared::blocks::break_ticks() have passed since
+    // starting.
+    StartBreaking { pos: IVec3 },
+    CancelBreaking,
+    FinishBreaking { pos: IVec3 },
+    // The server places it if `pos` is within reach and empty, unless a plugin or script
+    // cancels it
+    Place { pos: IVec3, block: u16 },
+}
+
+impl AuthorityMsg {
+    const CLAIM: u8 = 0;
+    const RELEASE: u8 = 1;
+    const MOVE: u8 = 2;
+    const RESYNC: u8 = 3;
+
+    // Upper bound of what write() writes, without the message length
+    pub const MAX_LEN: usize = 1 + 2 + 5 * 4;
+
+    pub fn write(&self, writer: &mut ByteWriter) {
+        match *self {
+            AuthorityMsg::Claim { id } => {
+                writer.write_u8(Self::CLAIM);
+                writer.write_u16(id.raw());
+            }
+            AuthorityMsg::Release { id } => {
+                writer.write_u8(Self::RELEASE);
+                writer.write_u16(id.raw());
+            }
+            AuthorityMsg::Move { id, position, head_rotation } => {
+                writer.write_u8(Self::MOVE);
+                writer.write_u16(id.raw());
+                writer.write_f32(position.x);
+                writer.write_f32(position.y);
+                writer.write_f32(position.z);
+                writer.write_f32(head_rotation.x);
+                writer.write_f32(head_rotation.y);
+            }
+            AuthorityMsg::Resync => writer.write_u8(Self::RESYNC),
+            AuthorityMsg::Block(action) => action.write(writer),
+        }
+    }
+
+    pub fn read(reader: &mut ByteReader) -> Result<Self, MessageError> {
+        let kind = reader.try_read_u8()?;
+        match kind {
+            Self::CLAIM => Ok(AuthorityMsg::Claim { id: NetworkId::from_raw(reader.try_read_u16()?) }),
+            Self::RELEASE => Ok(AuthorityMsg::Release { id: NetworkId::from_raw(reader.try_read_u16()?) }),
+            Self::MOVE => {
+                let id = NetworkId::from_raw(reader.try_read_u16()?);
+                let position = vec3(reader.try_read_f32()?, reader.try_read_f32()?, reader.try_read_f32()?);
+                let head_rotation = vec2(reader.try_read_f32()?, reader.try_read_f32()?);
+                if !position.is_finite() || !head_rotation.is_finite() {
+                    return Err(MessageError::Malformed);
+                }
+                Ok(AuthorityMsg::Move { id, position, head_rotation })
+            }
+            Self::RESYNC => Ok(AuthorityMsg::Resync),
+            _ => BlockAction::read_body(kind, reader).map(AuthorityMsg::Block),
+        }
+    }
+}
+
+impl BlockAction {
+    // After AuthorityMsg's kinds
+    const START_BREAKING: u8 = 4;
+    const CANCEL_BREAKING: u8 = 5;
+    const FINISH_BREAKING: u8 = 6;
+    const PLACE: u8 = 7;
+
+    fn write(&self, writer: &mut ByteWriter) {
+        match *self {
+            BlockAction::StartBreaking { pos } => {
+                writer.write_u8(Self::START_BREAKING);
+                write_block_pos(writer, pos);
+            }
+            BlockAction::CancelBreaking => writer.write_u8(Self::CANCEL_BREAKING),
+            BlockAction::FinishBreaking { pos } => {
+                writer.write_u8(Self::FINISH_BREAKING);
+                write_block_pos(writer, pos);
+            }
+            BlockAction::Place { pos, block } => {
+                writer.write_u8(Self::PLACE);
+                write_block_pos(writer, pos);
+                writer.write_u16(block);
+            }
+        }
+    }
+
+    fn read_body(kind: u8, reader: &mut ByteReader) -> Result<Self, MessageError> {
+        match kind {
+            Self::START_BREAKING => Ok(BlockAction::StartBreaking { pos: read_block_pos(reader)? }),
+            Self::CANCEL_BREAKING => Ok(BlockAction::CancelBreaking),
+            Self::FINISH_BREAKING => Ok(BlockAction::FinishBreaking { pos: read_block_pos(reader)? }),
+            Self::PLACE => Ok(BlockAction::Place { pos: read_block_pos(reader)?, block: reader.try_read_u16()? }),
+            _ => Err(MessageError::Malformed),
+        }
+    }
+}
+
+fn write_block_pos(writer: &mut ByteWriter, pos: IVec3) {
+    writer.write_i32(pos.x);
+    writer.write_i32(pos.y);
+    writer.write_i32(pos.z);
+}
+
+fn read_block_pos(reader: &mut ByteReader) -> Result<IVec3, MessageError> {
+    Ok(ivec3(reader.try_read_i32()?, reader.try_read_i32()?, reader.try_read_i32()?))
+}
+
+mod tests {
+    #[test]
+    fn test_hello_roundtrip() {
+        use super::Hello;
+        use crate::{bits_and_bytes::{ByteReader, ByteWriter}, protocol::Features};
+
+        for username in ["abc", "Player_1234567", "ääkkösiä", ""] {
+            let hello = Hello { magic: 0xB7C1, version: 3, username, skin: username.len() as u8, features: Features::COMPRESSION, key: [username.len() as u8; 16] };
+            let mut buf = [0u8; 64];
+            let mut writer = ByteWriter::new(&mut buf);
+            hello.write(&mut writer);
+            let len = writer.bytes_written();
+
+            let mut reader = ByteReader::new(&buf[..len]);
+            assert_eq!(Hello::read(&mut reader).unwrap(), hello);
+            assert_eq!(reader.bytes_remaining(), 0);
+
+            for end in 0..len {
+                assert!(Hello::read(&mut ByteReader::new(&buf[..end])).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_authority_roundtrip() {
+        use glam::{ivec3, vec2, vec3};
+        use super::{AuthorityMsg, BlockAction};
+        use crate::{bits_and_bytes::{ByteReader, ByteWriter}, protocol::NetworkId};
+
+        let id = NetworkId::from_raw(1234);
+        for msg in [
+            AuthorityMsg::Claim { id },
+            AuthorityMsg::Release { id },
+            AuthorityMsg::Move { id, position: vec3(1.5, -64.25, 1e6), head_rotation: vec2(3.0, -1.5) },
+            AuthorityMsg::Resync,
+            AuthorityMsg::Block(BlockAction::StartBreaking { pos: ivec3(-12, 64, 1 << 20) }),
+            AuthorityMsg::Block(BlockAction::CancelBreaking),
+            AuthorityMsg::Block(BlockAction::FinishBreaking { pos: ivec3(0, -1, i32::MIN) }),
+            AuthorityMsg::Block(BlockAction::Place { pos: ivec3(i32::MAX, 255, -3), block: 0xFFFF }),
+        ] {
+            let mut buf = [0u8; AuthorityMsg::MAX_LEN];
+            let mut writer = ByteWriter::new(&mut buf);
+            msg.write(&mut writer);
+            let len = writer.bytes_written();
+
+            let mut reader = ByteReader::new(&buf[..len]);
+            assert_eq!(AuthorityMsg::read(&mut reader).unwrap(), msg);
+            assert_eq!(reader.bytes_remaining(), 0);
+
+            for end in 0..len {
+                assert!(AuthorityMsg::read(&mut ByteReader::new(&buf[..end])).is_err());
+            }
+        }
+
+        // NaN positions would poison the server's physics
+        let mut buf = [0u8; AuthorityMsg::MAX_LEN];
+        let mut writer = ByteWriter::new(&mut buf);
+        AuthorityMsg::Move { id, position: vec3(f32::NAN, 0.0, 0.0), head_rotation: vec2(0.0, 0.0) }.write(&mut writer);
+        assert!(AuthorityMsg::read(&mut ByteReader::new(&buf)).is_err());
+        buf[0] = 0xFF;
+        assert!(AuthorityMsg::read(&mut ByteReader::new(&buf)).is_err());
+    }
+
+    #[test]
+    fn test_player_state_roundtrip() {
+        use glam::{vec2, vec3};
+        use super::{PlayerInput, read_player_state, write_player_state};
+        use crate::bits_and_bytes::BitWriter;
+        use crate::movement::MovementFlags;
+        use crate::protocol::{round_angles, round_velocity};
+
+        let mut state = 0x853C49E6748FEA9Bu64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let mut random_input = |tag: u16| {
+            let mut f = || (next() % 20001) as f32 / 1000.0 - 10.0;
+            let delta_pos = (f() > 0.0).then(|| round_velocity(vec3(f(), f(), f())));
+            let delta_yaw_pitch = (f() > 0.0).then(|| round_angles(vec2(f(), f())));
+            let movement = MovementFlags((next() % 4) as u8);
+            PlayerInput { tag, delta_pos, delta_yaw_pitch, movement }
+        };
+
+        let mut out = Vec::new();
+        for i in 0..2000u32 {
+            let latest_tag = (i * 7919) as u16;
+            let num_older = (i % 7) as u16;
+            let inputs = (0..=num_older)
+                .map(|n| random_input(latest_tag.wrapping_sub(n)))
+                .collect::<Vec<_>>();
+
+            let mut buf = [0u8; 260];
+            let mut writer = BitWriter::new(&mut buf);
+            write_player_state(&mut writer, &inputs[0], inputs[1..].iter().copied());
+            let len = writer.compute_bytes_written();
+
+            // All older inputs needed
+            out.clear();
+            let prev_tag = latest_tag.wrapping_sub(num_older + 1);
+            assert_eq!(read_player_state(&buf[..len], prev_tag, &mut out), latest_tag);
+            assert_eq!(out, inputs);
+
+            // Only the latest is new
+            out.clear();
+            read_player_state(&buf[..len], latest_tag.wrapping_sub(1), &mut out);
+            assert_eq!(out, inputs[..1]);
+
+            // Already seen
+            out.clear();
+            read_player_state(&buf[..len], latest_tag, &mut out);
+            assert!(out.is_empty());
+        }
+    }
+}
@@ -0,0 +1,796 @@
+// Server -> client messages.
+
+use glam::{IVec3, Vec2, Vec3, ivec3, vec3, vec2};
+
+use crate::{bits_and_bytes::{ByteReader, ByteWriter}, dimension::DimensionId};
+
+use super::{Features, MessageError, NetworkId, decode_angle_rad, decode_velocity, encode_angle_rad, encode_velocity, wrap_angle};
+
+// Replies to c2s::Hello, on the same stream: `Queued` whenever the place in the queue changes
+// (and now and then regardless) while the server is full, then `Accepted` once in. Being
+// denied closes the connection instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoginStatus {
+    Queued { position: u16 }, // 1 is next in line
+    Accepted(LoginResponse),
+}
+
+impl LoginStatus {
+    const QUEUED: u8 = 0;
+    const ACCEPTED: u8 = 1;
+
+    pub fn write(&self, writer: &mut ByteWriter) {
+        match self {
+            LoginStatus::Queued { position } => {
+                writer.write_u8(Self::QUEUED);
+                writer.write_u16(*position);
+            }
+            LoginStatus::Accepted(response) => {
+                writer.write_u8(Self::ACCEPTED);
+                response.write(writer);
+            }
+        }
+    }
+
+    pub fn read(reader: &mut ByteReader) -> Result<Self, MessageError> {
+        match reader.try_read_u8()? {
+            Self::QUEUED => Ok(LoginStatus::Queued { position: reader.try_read_u16()? }),
+            Self::ACCEPTED => Ok(LoginStatus::Accepted(LoginResponse::read(reader)?)),
+            _ => Err(MessageError::Malformed),
+        }
+    }
+}
+
+// Sent in LoginStatus::Accepted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoginResponse {
+    pub nid: NetworkId,
+    pub position: Vec3,
+    pub head_rotation: Vec2, // yaw, pitch
+    pub dimension: DimensionId,
+    // Of `dimension`, for generating its terrain (see dimension::seed()). Not the world seed.
+    pub seed: u64,
+    pub features: Features, // supported by the server
+}
+
+impl LoginResponse {
+    pub fn write(&self, writer: &mut ByteWriter) {
+        writer.write_u16(self.nid.raw());
+        writer.write_f32(self.position.x);
+        writer.write_f32(self.position.y);
+        writer.write_f32(self.position.z);
+        writer.write_f32(self.head_rotation.x);
+        writer.write_f32(self.head_rotation.y);
+        writer.write_u8(self.dimension.0);
+        writer.write_u64(self.seed);
+        writer.write_u8(self.features.0);
+    }
+
+    pub fn read(reader: &mut ByteReader) -> Result<Self, MessageError> {
+        Ok(Self {
+            nid: NetworkId::from_raw(reader.try_read_u16()?),
+            position: read_vec3(reader)?,
+            head_rotation: read_vec2(reader)?,
+            dimension: DimensionId(reader.try_read_u8()?),
+            seed: reader.try_read_u64()?,
+            features: Features(reader.try_read_u8()?),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ChatFlags(pub u8);
+
+impl ChatFlags {
+    pub const NONE: Self = Self(0);
+    // Sent before the player joined, as context. Shown dimmed, no notifications.
+    pub const HISTORY: Self = Self(1 << 0);
+    // Joins, leaves and such: also shown as a notification by the client
+    pub const NOTICE: Self = Self(1 << 1);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+// Chat message layout: flags u8, then the message as UTF-8 until the end
+pub fn write_chat(writer: &mut ByteWriter, flags: ChatFlags, message: &str) {
+    writer.write_u8(flags.0);
+    writer.write(message.as_bytes());
+}
+
+pub fn read_chat<'a>(reader: &mut ByteReader<'a>) -> Result<(ChatFlags, &'a str), MessageError> {
+    let flags = ChatFlags(reader.try_read_u8()?);
+    let message = reader.try_read_str(reader.bytes_remaining())?;
+    Ok((flags, message))
+}
+
+// Entity metadata synced to clients as key -> value pairs. Each key has a fixed value
+// type, but the type is sent along so that clients can skip keys they don't know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataKey {
+    Username = 0,
+    Crouching = 1,
+    Skin = 2,
+    Sprinting = 3,
+    Afk = 4,
+    Item = 5, // Uint: the block id of a dropped item
+    // Uint: the network id of the player whose client has authority over the entity, 0 if
+    // the server does (same as not set). See c2s::AuthorityMsg.
+    Owner = 6,
+}
+
+impl MetadataKey {
+    pub const ALL: [MetadataKey; 7] = [Self::Username, Self::Crouching, Self::Skin, Self::Sprinting, Self::Afk, Self::Item, Self::Owner];
+
+    pub fn from_raw(raw: u32) -> Option<Self> {
+        Self::ALL.get(raw as usize).copied()
+    }
+
+    pub const fn raw(self) -> u32 {
+        self as u32
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataValue {
+    Bool(bool),
+    Uint(u32),
+    F32(f32),
+    Str(Box<str>),
+}
+
+impl MetadataValue {
+    const fn type_tag(&self) -> u32 {
+        match self {
+            MetadataValue::Bool(_) => 0,
+            MetadataValue::Uint(_) => 1,
+            MetadataValue::F32(_) => 2,
+            MetadataValue::Str(_) => 3,
+        }
+    }
+
+    fn write(&self, writer: &mut ByteWriter) {
+        match self {
+            MetadataValue::Bool(b) => writer.write_bool(*b),
+            MetadataValue::Uint(n) => writer.write_varint_u32(*n),
+            MetadataValue::F32(f) => writer.write_f32(*f),
+            MetadataValue::Str(s) => {
+                writer.write_varint_u32(s.len() as u32);
+                writer.write(s.as_bytes());
+            }
+        }
+    }
+
+    fn read(reader: &mut ByteReader, type_tag: u32) -> Result<Self, MessageError> {
+        Ok(match type_tag {
+            0 => MetadataValue::Bool(reader.try_read_bool()?),
+            1 => MetadataValue::Uint(reader.try_read_varint_u32()?),
+            2 => MetadataValue::F32(reader.try_read_f32()?),
+            3 => {
+                let len = reader.try_read_varint_u32()? as usize;
+                MetadataValue::Str(reader.try_read_str(len)?.into())
+            }
+            _ => return Err(MessageError::Malformed),
+        })
+    }
+}
+
+// Which components of a Teleport are relative to the player's current (predicted)
+// position and rotation rather than absolute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TeleportFlags(pub u8);
+
+impl TeleportFlags {
+    pub const ABSOLUTE: Self = Self(0);
+    pub const RELATIVE_X: Self = Self(1 << 0);
+    pub const RELATIVE_Y: Self = Self(1 << 1);
+    pub const RELATIVE_Z: Self = Self(1 << 2);
+    pub const RELATIVE_YAW: Self = Self(1 << 3);
+    pub const RELATIVE_PITCH: Self = Self(1 << 4);
+    pub const RELATIVE_POSITION: Self = Self(0b00111);
+    pub const RELATIVE_ROTATION: Self = Self(0b11000);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for TeleportFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntityStateMsg {
+    EntityAdded {
+        id: NetworkId,
+        position: Vec3,
+        head_rotation: Vec2
+    },
+    EntityRemoved {
+        id: NetworkId,
+    },
+    EntityMoved {
+        id: NetworkId,
+        delta_pos: Vec3,
+        delta_head_rotation: Vec2,
+    },
+    MetadataChanged {
+        id: NetworkId,
+        key: MetadataKey,
+        value: MetadataValue,
+    },
+    InputValidated {
+        tag: u16,
+        packets_lost: u8,
+        server_pos: Vec3,
+        server_head_rot: Vec2,
+    },
+    // Another entity jumped further than EntityMoved can express; no interpolation
+    EntityTeleported {
+        id: NetworkId,
+        position: Vec3,
+        head_rotation: Vec2,
+    },
+    // The player itself was moved by the server. Applies on top of the state after input
+    // `tag` (not sent, it's the tag in the message header), so that inputs the server
+    // hasn't seen yet still apply after the teleport on both sides. With a dimension (and
+    // its seed), into that dimension: nothing of the old one carries over.
+    Teleport {
+        tag: u16,
+        pos: Vec3,
+        yaw: f32,
+        pitch: f32,
+        flags: TeleportFlags,
+        dimension: Option<(DimensionId, u64)>,
+    },
+    // Block ids as in worldgen, with the block's data in the upper bits (see fluid)
+    BlocksChanged {
+        changes: Vec<(IVec3, u16)>,
+    },
+    // The server tick this message was sent on. Only with Features::CLOCK_SYNC.
+    ServerTick {
+        tick: u32,
+    },
+    // Of the entities the client should have at this point, itself excluded, see
+    // entity_checksum(). Only with Features::ENTITY_CHECKSUM.
+    EntityChecksum {
+        count: u32,
+        checksum: u32,
+    },
+}
+
+// Entity state message layout:
+//   input tag u16
+//   if the tag differs from the previous message's: packets lost u8, position 3 x f32, head rotation 2 x f32
+//   changes until the end of the message, each starting with a varint:
+//     (id << 2) | 0b00  => added:    position 3 x f32, head rotation 2 x f32
+//     (id << 4) | 0b0010 => removed
+//     (0 << 4)  | 0b0010 => blocks changed: varint count, then per block: position 3 x varint i32, block u16
+//     (id << 4) | 0b1010 => teleported: position 3 x f32, head rotation 2 x f32
+//     (0 << 4)  | 0b1010 => the player teleported: flags u8, position 3 x f32, yaw f32, pitch f32,
+//                           then if flags & TELEPORT_DIMENSION: dimension u8, seed u64
+//     (id << 3) | 0b110 => metadata: varint (key << 2) | value type, value
+//     (0 << 3)  | 0b110 => server tick: varint tick (only with Features::CLOCK_SYNC)
+//     (id << 1) | 0b1   => moved:    delta position 3 x u16, delta head rotation 2 x u16
+//     (0 << 1)  | 0b1   => entity checksum: varint count, checksum u32 (only with Features::ENTITY_CHECKSUM)
+// Moves are by far the most common, so they get the shortest tag.
+
+// The tag was already validated; the client will know there is no associated data
+pub fn write_input_tag(writer: &mut ByteWriter, tag: u16) {
+    writer.write_u16(tag);
+}
+
+pub fn write_input_validated(writer: &mut ByteWriter, tag: u16, packets_lost: u8, server_pos: Vec3, server_head_rot: Vec2) {
+    writer.write_u16(tag);
+    writer.write_u8(packets_lost);
+    writer.write_f32(server_pos.x);
+    writer.write_f32(server_pos.y);
+    writer.write_f32(server_pos.z);
+    writer.write_f32(server_head_rot.x);
+    writer.write_f32(server_head_rot.y);
+}
+
+pub fn write_entity_added(writer: &mut ByteWriter, id: NetworkId, position: Vec3, head_rotation: Vec2) {
+    writer.write_varint_u32((id.raw() as u32) << 2);
+    writer.write_f32(position.x);
+    writer.write_f32(position.y);
+    writer.write_f32(position.z);
+    writer.write_f32(head_rotation.x);
+    writer.write_f32(head_rotation.y);
+}
+
+pub fn write_entity_removed(writer: &mut ByteWriter, id: NetworkId) {
+    debug_assert!(id != NetworkId::INVALID);
+    writer.write_varint_u32(((id.raw() as u32) << 4) | 0b0010);
+}
+
+pub fn write_entity_teleported(writer: &mut ByteWriter, id: NetworkId, position: Vec3, head_rotation: Vec2) {
+    debug_assert!(id != NetworkId::INVALID);
+    writer.write_varint_u32(((id.raw() as u32) << 4) | 0b1010);
+    writer.write_f32(position.x);
+    writer.write_f32(position.y);
+    writer.write_f32(position.z);
+    writer.write_f32(head_rotation.x);
+    writer.write_f32(head_rotation.y);
+}
+
+// Set in the flags of a Teleport on the wire when a dimension follows. Not a TeleportFlags
+// constant, as it isn't about what is relative.
+const TELEPORT_DIMENSION: u8 = 1 << 7;
+
+pub fn write_teleport(writer: &mut ByteWriter, pos: Vec3, yaw: f32, pitch: f32, flags: TeleportFlags, dimension: Option<(DimensionId, u64)>) {
+    writer.write_varint_u32(0b1010);
+    writer.write_u8(flags.0 | if dimension.is_some() { TELEPORT_DIMENSION } else { 0 });
+    writer.write_f32(pos.x);
+    writer.write_f32(pos.y);
+    writer.write_f32(pos.z);
+    writer.write_f32(yaw);
+    writer.write_f32(pitch);
+    if let Some((dimension, seed)) = dimension {
+        writer.write_u8(dimension.0);
+        writer.write_u64(seed);
+    }
+}
+
+pub fn write_blocks_changed(writer: &mut ByteWriter, changes: &[(IVec3, u16)]) {
+    writer.write_varint_u32(0b0010);
+    writer.write_varint_u32(changes.len() as u32);
+    for &(pos, block) in changes {
+        writer.write_varint_i32(pos.x);
+        writer.write_varint_i32(pos.y);
+        writer.write_varint_i32(pos.z);
+        writer.write_u16(block);
+    }
+}
+
+// Upper bound of what write_blocks_changed() writes
+pub const fn blocks_changed_max_len(count: usize) -> usize {
+    2 * 5 + count * (3 * 5 + 2)
+}
+
+// Every few ticks, not every message: see Features::CLOCK_SYNC
+pub fn write_server_tick(writer: &mut ByteWriter, tick: u32) {
+    writer.write_varint_u32(0b110);
+    writer.write_varint_u32(tick);
+}
+
+// Every few seconds, see Features::ENTITY_CHECKSUM
+pub fn write_entity_checksum(writer: &mut ByteWriter, count: u32, checksum: u32) {
+    writer.write_varint_u32(0b1);
+    writer.write_varint_u32(count);
+    writer.write_u32(checksum);
+}
+
+// (count, checksum) of a set of entities, the same whatever order they come in
+pub fn entity_checksum(ids: impl IntoIterator<Item = NetworkId>) -> (u32, u32) {
+    ids.into_iter().fold((0, 0), |(count, sum), id| {
+        let mut x = (id.raw() as u32).wrapping_mul(0x9E37_79B1);
+        x ^= x >> 15;
+        x = x.wrapping_mul(0x85EB_CA77);
+        x ^= x >> 13;
+        (count + 1, sum.wrapping_add(x))
+    })
+}
+
+pub fn write_entity_metadata(writer: &mut ByteWriter, id: NetworkId, key: MetadataKey, value: &MetadataValue) {
+    writer.write_varint_u32(((id.raw() as u32) << 3) | 0b110);
+    writer.write_varint_u32((key.raw() << 2) | value.type_tag());
+    value.write(writer);
+}
+
+pub fn write_entity_moved(writer: &mut ByteWriter, id: NetworkId, delta_pos: Vec3, delta_head_rotation: Vec2) {
+    debug_assert!(id != NetworkId::INVALID);
+    writer.write_varint_u32(((id.raw() as u32) << 1) | 0b1);
+    writer.write_u16(encode_velocity(delta_pos.x) as u16);
+    writer.write_u16(encode_velocity(delta_pos.y) as u16);
+    writer.write_u16(encode_velocity(delta_pos.z) as u16);
+    writer.write_u16(encode_angle_rad(wrap_angle(delta_head_rotation.x)));
+    writer.write_u16(encode_angle_rad(wrap_angle(delta_head_rotation.y)));
+}
+
+impl EntityStateMsg {
+    // For debugging tools, e.g. the client's packet inspector
+    pub const fn name(&self) -> &'static str {
+        match self {
+            EntityStateMsg::EntityAdded { .. } => "EntityAdded",
+            EntityStateMsg::EntityRemoved { .. } => "EntityRemoved",
+            EntityStateMsg::EntityMoved { .. } => "EntityMoved",
+            EntityStateMsg::MetadataChanged { .. } => "MetadataChanged",
+            EntityStateMsg::InputValidated { .. } => "InputValidated",
+            EntityStateMsg::EntityTeleported { .. } => "EntityTeleported",
+            EntityStateMsg::Teleport { .. } => "Teleport",
+            EntityStateMsg::BlocksChanged { .. } => "BlocksChanged",
+            EntityStateMsg::ServerTick { .. } => "ServerTick",
+            EntityStateMsg::EntityChecksum { .. } => "EntityChecksum",
+        }
+    }
+}
+
+// Decodes a whole entity state message into `out`. `prev_tag` is the input tag of the
+// previous message and gets updated.
+pub fn read_entity_state(reader: &mut ByteReader, prev_tag: &mut u16, out: &mut Vec<EntityStateMsg>) -> Result<(), MessageError> {
+    read_entity_state_inner(reader, prev_tag, out, None)
+}
+
+// Same, also pushing the encoded size of each message pushed to `out` to `sizes`. The input
+// tag counts towards InputValidated, if any; metadata with unknown keys isn't counted.
+pub fn read_entity_state_sized(
+    reader: &mut ByteReader,
+    prev_tag: &mut u16,
+    out: &mut Vec<EntityStateMsg>,
+    sizes: &mut Vec<u32>,
+) -> Result<(), MessageError> {
+    read_entity_state_inner(reader, prev_tag, out, Some(sizes))
+}
+
+fn read_entity_state_inner(
+    reader: &mut ByteReader,
+    prev_tag: &mut u16,
+    out: &mut Vec<EntityStateMsg>,
+    mut sizes: Option<&mut Vec<u32>>,
+) -> Result<(), MessageError> {
+    // Where the message being read started, counting from the end
+    let mut msg_start = reader.bytes_remaining();
+
+    let tag = reader.try_read_u16()?;
+    if tag != *prev_tag {
+        out.push(EntityStateMsg::InputValidated {
+            tag,
+            packets_lost: reader.try_read_u8()?,
+            server_pos: read_vec3(reader)?,
+            server_head_rot: read_vec2(reader)?,
+        });
+        *prev_tag = tag;
+        if let Some(sizes) = &mut sizes {
+            sizes.push((msg_start - reader.bytes_remaining()) as u32);
+        }
+    }
+    msg_start = reader.bytes_remaining();
+
+    while reader.bytes_remaining() > 0 {
+        let start = reader.try_read_varint_u32()?;
+        let msg = match start & 0b111 {
+            0b000 | 0b100 => EntityStateMsg::EntityAdded {
+                id: read_id(start >> 2)?,
+                position: read_vec3(reader)?,
+                head_rotation: read_vec2(reader)?,
+            },
+            0b010 if start == 0b0010 => {
+                let count = reader.try_read_varint_u32()? as usize;
+                // Every change takes at least 5 bytes; don't trust the count with the allocation
+                let mut changes = Vec::with_capacity(count.min(reader.bytes_remaining() / 5));
+                for _ in 0..count {
+                    let pos = ivec3(reader.try_read_varint_i32()?, reader.try_read_varint_i32()?, reader.try_read_varint_i32()?);
+                    changes.push((pos, reader.try_read_u16()?));
+                }
+                EntityStateMsg::BlocksChanged { changes }
+            }
+            0b010 if start & 0b1000 == 0 => EntityStateMsg::EntityRemoved {
+                id: read_id(start >> 4)?,
+            },
+            0b010 if start >> 4 == 0 => {
+                let flags = reader.try_read_u8()?;
+                let (pos, yaw, pitch) = (read_vec3(reader)?, read_f32(reader)?, read_f32(reader)?);
+                let dimension = match flags & TELEPORT_DIMENSION {
+                    0 => None,
+                    _ => Some((DimensionId(reader.try_read_u8()?), reader.try_read_u64()?)),
+                };
+                EntityStateMsg::Teleport {
+                    tag: *prev_tag,
+                    pos,
+                    yaw,
+                    pitch,
+                    flags: TeleportFlags(flags & !TELEPORT_DIMENSION),
+                    dimension,
+                }
+            }
+            0b010 => EntityStateMsg::EntityTeleported {
+                id: read_id(start >> 4)?,
+                position: read_vec3(reader)?,
+                head_rotation: read_vec2(reader)?,
+            },
+            0b110 if start == 0b110 => EntityStateMsg::ServerTick {
+                tick: reader.try_read_varint_u32()?,
+            },
+            0b110 => {
+                let id = read_id(start >> 3)?;
+                let key_and_type = reader.try_read_varint_u32()?;
+                let value = MetadataValue::read(reader, key_and_type & 0b11)?;
+                match MetadataKey::from_raw(key_and_type >> 2) {
+                    Some(key) => EntityStateMsg::MetadataChanged { id, key, value },
+                    None => {
+                        // From a newer server, skip
+                        msg_start = reader.bytes_remaining();
+                        continue;
+                    }
+                }
+            }
+            0b001 if start == 0b1 => EntityStateMsg::EntityChecksum {
+                count: reader.try_read_varint_u32()?,
+                checksum: reader.try_read_u32()?,
+            },
+            _ => EntityStateMsg::EntityMoved {
+                id: read_id(start >> 1)?,
+                delta_pos: vec3(
+                    decode_velocity(reader.try_read_u16()? as u32),
+                    decode_velocity(reader.try_read_u16()? as u32),
+                    decode_velocity(reader.try_read_u16()? as u32),
+                ),
+                delta_head_rotation: vec2(
+                    decode_angle_rad(reader.try_read_u16()?),
+                    decode_angle_rad(reader.try_read_u16()?),
+                ),
+            },
+        };
+        out.push(msg);
+        if let Some(sizes) = &mut sizes {
+            sizes.push((msg_start - reader.bytes_remaining()) as u32);
+        }
+        msg_start = reader.bytes_remaining();
+    }
+    Ok(())
+}
+
+// Positions and angles. NaN or infinity would spread to everything computed from them, so
+// they are Malformed like anything else the client can't make sense of.
+fn read_f32(reader: &mut ByteReader) -> Result<f32, MessageError> {
+    let value = reader.try_read_f32()?;
+    value.is_finite().then_some(value).ok_or(MessageError::Malformed)
+}
+
+fn read_vec3(reader: &mut ByteReader) -> Result<Vec3, MessageError> {
+    Ok(vec3(read_f32(reader)?, read_f32(reader)?, read_f32(reader)?))
+}
+
+fn read_vec2(reader: &mut ByteReader) -> Result<Vec2, MessageError> {
+    Ok(vec2(read_f32(reader)?, read_f32(reader)?))
+}
+
+fn read_id(raw: u32) -> Result<NetworkId, MessageError> {
+    u16::try_from(raw).map(NetworkId::from_raw).map_err(|_| MessageError::Malformed)
+}
+
+mod tests {
+    #[test]
+    fn test_login_response_roundtrip() {
+        use glam::{vec2, vec3};
+        use super::LoginResponse;
+        use crate::{bits_and_bytes::{ByteReader, ByteWriter}, dimension::DimensionId, protocol::{Features, NetworkId}};
+
+        let response = LoginResponse {
+            nid: NetworkId::from_raw(4321),
+            position: vec3(1.0, -2.5, 1e6),
+            head_rotation: vec2(0.25, -1.5),
+            dimension: DimensionId(3),
+            seed: 0xDEAD_BEEF_0123_4567,
+            features: Features::COMPRESSION,
+        };
+        let mut buf = [0u8; 64];
+        let mut writer = ByteWriter::new(&mut buf);
+        response.write(&mut writer);
+        let len = writer.bytes_written();
+
+        let mut reader = ByteReader::new(&buf[..len]);
+        assert_eq!(LoginResponse::read(&mut reader).unwrap(), response);
+        assert_eq!(reader.bytes_remaining(), 0);
+
+        for end in 0..len {
+            assert!(LoginResponse::read(&mut ByteReader::new(&buf[..end])).is_err());
+        }
+    }
+
+    #[test]
+    fn test_login_status_roundtrip() {
+        use glam::{vec2, vec3};
+        use super::{LoginResponse, LoginStatus};
+        use crate::{bits_and_bytes::{ByteReader, ByteWriter}, dimension::DimensionId, protocol::{Features, NetworkId}};
+
+        let accepted = LoginStatus::Accepted(LoginResponse {
+            nid: NetworkId::from_raw(7),
+            position: vec3(0.5, 64.0, -3.0),
+            head_rotation: vec2(1.0, 0.0),
+            dimension: DimensionId::OVERWORLD,
+            seed: 42,
+            features: Features::NONE,
+        });
+        for status in [LoginStatus::Queued { position: 1 }, LoginStatus::Queued { position: 300 }, accepted] {
+            let mut buf = [0u8; 64];
+            let mut writer = ByteWriter::new(&mut buf);
+            status.write(&mut writer);
+            let len = writer.bytes_written();
+
+            let mut reader = ByteReader::new(&buf[..len]);
+            assert_eq!(LoginStatus::read(&mut reader).unwrap(), status);
+            assert_eq!(reader.bytes_remaining(), 0);
+            for end in 0..len {
+                assert!(LoginStatus::read(&mut ByteReader::new(&buf[..end])).is_err());
+            }
+        }
+        assert!(LoginStatus::read(&mut ByteReader::new(&[2, 0, 0])).is_err());
+    }
+
+    #[test]
+    fn test_chat_roundtrip() {
+        use super::{ChatFlags, read_chat, write_chat};
+        use crate::bits_and_bytes::{ByteReader, ByteWriter};
+
+        for (flags, message) in [(ChatFlags::NONE, "hello"), (ChatFlags::HISTORY, "ääkkösiä"), (ChatFlags::HISTORY, "")] {
+            let mut buf = [0u8; 64];
+            let mut writer = ByteWriter::new(&mut buf);
+            write_chat(&mut writer, flags, message);
+            let len = writer.bytes_written();
+
+            let mut reader = ByteReader::new(&buf[..len]);
+            assert_eq!(read_chat(&mut reader).unwrap(), (flags, message));
+            assert_eq!(reader.bytes_remaining(), 0);
+        }
+        assert!(read_chat(&mut ByteReader::new(&[])).is_err());
+    }
+
+    #[test]
+    fn test_entity_state_roundtrip() {
+        use glam::{ivec3, vec2, vec3};
+        use super::*;
+        use crate::{bits_and_bytes::{ByteReader, ByteWriter}, protocol::{NetworkId, round_angles, round_velocity}};
+
+        let mut state = 0xA0761D6478BD642Fu64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let mut prev_tag = u16::MAX;
+        let mut out = Vec::new();
+        let mut buf = vec![0u8; 4096];
+        for i in 0..1000u16 {
+            let mut expected = Vec::new();
+            let mut writer = ByteWriter::new(&mut buf);
+
+            let mut f = || (next() % 20001) as f32 / 1000.0 - 10.0;
+            let tag = if i % 3 == 0 { prev_tag } else { i };
+            if i % 3 == 0 {
+                write_input_tag(&mut writer, prev_tag);
+            } else {
+                let (tag, packets_lost) = (i, (i % 5) as u8);
+                let (server_pos, server_head_rot) = (vec3(f(), f(), f()), vec2(f(), f()));
+                write_input_validated(&mut writer, tag, packets_lost, server_pos, server_head_rot);
+                expected.push(EntityStateMsg::InputValidated { tag, packets_lost, server_pos, server_head_rot });
+            }
+
+            for j in 0..(i % 40) {
+                let id = NetworkId::from_raw(i.wrapping_mul(31).wrapping_add(j * 1013));
+                let msg = match j % 8 {
+                    0 => EntityStateMsg::EntityAdded { id, position: vec3(f(), f(), f()), head_rotation: vec2(f(), f()) },
+                    1 => EntityStateMsg::EntityRemoved { id },
+                    2 => EntityStateMsg::MetadataChanged { id, key: MetadataKey::Crouching, value: MetadataValue::Bool(f() > 0.0) },
+                    3 => EntityStateMsg::MetadataChanged { id, key: MetadataKey::Username, value: MetadataValue::Str(format!("player{j}").into()) },
+                    4 => EntityStateMsg::EntityMoved {
+                        id,
+                        delta_pos: round_velocity(vec3(f(), f(), f())),
+                        delta_head_rotation: round_angles(vec2(f(), f())),
+                    },
+                    5 => EntityStateMsg::EntityTeleported {
+                        id: NetworkId::from_raw(id.raw().max(1)),
+                        position: vec3(f(), f(), f()),
+                        head_rotation: vec2(f(), f()),
+                    },
+                    6 => {
+                        let dimension = (i % 2 == 0).then_some((DimensionId(j as u8), (i as u64) << 40 | 0xABCD));
+                        EntityStateMsg::Teleport { tag, pos: vec3(f(), f(), f()), yaw: f(), pitch: f(), flags: TeleportFlags(j as u8 & 0b11111), dimension }
+                    }
+                    _ => EntityStateMsg::BlocksChanged {
+                        changes: (0..j % 5).map(|k| (ivec3((f() * 1e5) as i32, (f() * 10.0) as i32, -(f() * 1e5) as i32), k * 0x1401)).collect(),
+                    },
+                };
+                match &msg {
+                    &EntityStateMsg::EntityAdded { id, position, head_rotation } => write_entity_added(&mut writer, id, position, head_rotation),
+                    &EntityStateMsg::EntityRemoved { id } => write_entity_removed(&mut writer, id),
+                    &EntityStateMsg::EntityMoved { id, delta_pos, delta_head_rotation } => write_entity_moved(&mut writer, id, delta_pos, delta_head_rotation),
+                    EntityStateMsg::MetadataChanged { id, key, value } => write_entity_metadata(&mut writer, *id, *key, value),
+                    &EntityStateMsg::EntityTeleported { id, position, head_rotation } => write_entity_teleported(&mut writer, id, position, head_rotation),
+                    &EntityStateMsg::Teleport { pos, yaw, pitch, flags, dimension, .. } => write_teleport(&mut writer, pos, yaw, pitch, flags, dimension),
+                    EntityStateMsg::BlocksChanged { changes } => write_blocks_changed(&mut writer, changes),
+                    EntityStateMsg::InputValidated { .. } | EntityStateMsg::ServerTick { .. } | EntityStateMsg::EntityChecksum { .. } => unreachable!(),
+                }
+                expected.push(msg);
+            }
+            if i % 4 == 0 {
+                let tick = (i as u32) << 12;
+                write_server_tick(&mut writer, tick);
+                expected.push(EntityStateMsg::ServerTick { tick });
+            }
+            if i % 7 == 0 {
+                let (count, checksum) = (i as u32 % 300, (i as u32).wrapping_mul(0xDEAD_BEEF));
+                write_entity_checksum(&mut writer, count, checksum);
+                expected.push(EntityStateMsg::EntityChecksum { count, checksum });
+            }
+            let len = writer.bytes_written();
+
+            let (mut sized_prev_tag, mut sized_out, mut sizes) = (prev_tag, Vec::new(), Vec::new());
+            read_entity_state_sized(&mut ByteReader::new(&buf[..len]), &mut sized_prev_tag, &mut sized_out, &mut sizes).unwrap();
+            assert_eq!(sizes.len(), expected.len());
+            // Without an InputValidated, the tag isn't counted
+            let tag_len = if i % 3 == 0 { 2 } else { 0 };
+            assert_eq!(sizes.iter().sum::<u32>() as usize + tag_len, len);
+
+            out.clear();
+            let mut reader = ByteReader::new(&buf[..len]);
+            read_entity_state(&mut reader, &mut prev_tag, &mut out).unwrap();
+            assert_eq!(out, expected);
+            assert_eq!(sized_out, expected);
+        }
+    }
+
+    #[test]
+    fn test_entity_checksum() {
+        use super::entity_checksum;
+        use crate::protocol::NetworkId;
+
+        let ids = [3, 17, 4096, 1, 65535].map(NetworkId::from_raw);
+        let (count, checksum) = entity_checksum(ids);
+        assert_eq!(count, 5);
+        assert_eq!(entity_checksum(ids.into_iter().rev()), (count, checksum));
+        assert_eq!(entity_checksum([]), (0, 0));
+        // A ghost, a missing entity or one under another id should all show
+        assert_ne!(entity_checksum(ids.into_iter().chain([NetworkId::from_raw(9)])).1, checksum);
+        assert_ne!(entity_checksum(ids.into_iter().skip(1)).1, checksum);
+        assert_ne!(entity_checksum(ids.map(|id| NetworkId::from_raw(id.raw() ^ 0x100))).1, checksum);
+    }
+
+    #[test]
+    fn test_unknown_metadata_key_is_skipped() {
+        use super::*;
+        use crate::{bits_and_bytes::{ByteReader, ByteWriter}, protocol::NetworkId};
+
+        let id = NetworkId::from_raw(7);
+        let mut buf = [0u8; 64];
+        let mut writer = ByteWriter::new(&mut buf);
+        write_input_tag(&mut writer, 5);
+        // Key 31 with a string value, as a future server might send
+        writer.write_varint_u32(((id.raw() as u32) << 3) | 0b110);
+        writer.write_varint_u32((31 << 2) | 3);
+        writer.write_varint_u32(3);
+        writer.write(b"abc");
+        write_entity_removed(&mut writer, id);
+        let len = writer.bytes_written();
+
+        let mut out = Vec::new();
+        read_entity_state(&mut ByteReader::new(&buf[..len]), &mut 5, &mut out).unwrap();
+        assert_eq!(out, [EntityStateMsg::EntityRemoved { id }]);
+
+        let (mut out, mut sizes) = (Vec::new(), Vec::new());
+        read_entity_state_sized(&mut ByteReader::new(&buf[..len]), &mut 5, &mut out, &mut sizes).unwrap();
+        assert_eq!(sizes, [1]);
+    }
+
+    #[test]
+    fn test_non_finite_values_are_malformed() {
+        use glam::{Vec2, Vec3};
+        use super::*;
+        use crate::{bits_and_bytes::{ByteReader, ByteWriter}, protocol::{MessageError, NetworkId}};
+
+        let id = NetworkId::from_raw(7);
+        for bad in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let mut buf = [0u8; 64];
+            let mut writer = ByteWriter::new(&mut buf);
+            write_input_tag(&mut writer, 5);
+            write_entity_added(&mut writer, id, Vec3::new(1.0, bad, 2.0), Vec2::ZERO);
+            let len = writer.bytes_written();
+            let result = read_entity_state(&mut ByteReader::new(&buf[..len]), &mut 5, &mut Vec::new());
+            assert!(matches!(result, Err(MessageError::Malformed)), "{bad}");
+
+            let mut writer = ByteWriter::new(&mut buf);
+            write_input_validated(&mut writer, 6, 0, Vec3::ZERO, Vec2::new(bad, 0.0));
+            let len = writer.bytes_written();
+            let result = read_entity_state(&mut ByteReader::new(&buf[..len]), &mut 5, &mut Vec::new());
+            assert!(matches!(result, Err(MessageError::Malformed)), "{bad}");
+        }
+    }
+}
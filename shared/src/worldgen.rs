@@ -0,0 +1,262 @@
+// Terrain generation as a pure function of (world seed, chunk position): the same chunk
+// comes out no matter who generates it, in which order, or on which CPU. That is why
+// this uses integer hashing and plain float arithmetic instead of the SIMD noise.
+
+use glam::IVec3;
+
+pub mod structures;
+
+pub const CHUNK_SIZE_LOG2: i32 = 4;
+pub const CHUNK_SIZE: i32 = 1 << CHUNK_SIZE_LOG2;
+pub const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+pub const WORLD_HEIGHT: i32 = 256;
+
+pub type BlockId = u16;
+pub const AIR: BlockId = 0;
+pub const STONE: BlockId = 1;
+pub const TORCH: BlockId = 2; // not generated
+pub const LOG: BlockId = 3;
+pub const LEAVES: BlockId = 4;
+
+pub const SEA_LEVEL: i32 = 64;
+
+const CAVE_SALT: u64 = 0x4341_5645_5341_4C54;
+// Solid blocks kept between caves and the surface, so that the surface (and whatever
+// is built on it) never collapses into a cave
+const CAVE_ROOF: i32 = 4;
+
+// Everybody generating the same world must use the same settings
+#[derive(Clone, Copy, Debug)]
+pub struct GenSettings {
+    // How much of the underground is carved out. 0 = no caves, 1 = the default
+    pub cave_density: f32,
+}
+
+impl Default for GenSettings {
+    fn default() -> Self {
+        Self { cave_density: 1.0 }
+    }
+}
+
+// Terrain of the chunk at `chunk_pos` (in chunks), indexed with `block_index()`.
+// See structures::StructureQueue for the structures on top.
+pub fn generate_chunk(seed: u64, chunk_pos: IVec3) -> Box<[BlockId; CHUNK_VOLUME]> {
+    generate_chunk_with(seed, chunk_pos, &GenSettings::default())
+}
+
+pub fn generate_chunk_with(seed: u64, chunk_pos: IVec3, settings: &GenSettings) -> Box<[BlockId; CHUNK_VOLUME]> {
+    let mut blocks = Box::new([AIR; CHUNK_VOLUME]);
+    let origin = chunk_pos * CHUNK_SIZE;
+    for z in 0..CHUNK_SIZE {
+        for x in 0..CHUNK_SIZE {
+            let height = terrain_height(seed, origin.x + x, origin.z + z);
+            for y in 0..CHUNK_SIZE.min(height - origin.y) {
+                if !is_carved(seed, settings, origin + IVec3::new(x, y, z), height) {
+                    blocks[block_index(x, y, z)] = STONE;
+                }
+            }
+        }
+    }
+    blocks
+}
+
+// Same layout as the server's BlockWorld; coordinates are local to the chunk
+pub fn block_index(x: i32, y: i32, z: i32) -> usize {
+    ((y * CHUNK_SIZE + z) * CHUNK_SIZE + x) as usize
+}
+
+// Y of the lowest air block of the column
+pub fn terrain_height(seed: u64, x: i32, z: i32) -> i32 {
+    let mut height = 0.0f32;
+    let mut amplitude = 24.0;
+    // Wavelengths 128, 64, 32, 16
+    for octave in 0..4 {
+        height += value_noise(seed.wrapping_add(octave), x, z, 7 - octave as u32) * amplitude;
+        amplitude *= 0.5;
+    }
+    SEA_LEVEL + height.floor() as i32
+}
+
+// Whether the block at `pos`, below the surface at `height`, is part of a cave. Caves
+// are tunnels where two independent noise fields are both close to zero.
+pub fn is_carved(seed: u64, settings: &GenSettings, pos: IVec3, height: i32) -> bool {
+    let threshold = 0.08 * settings.cave_density;
+    if threshold <= 0.0 || pos.y < 1 || pos.y >= height - CAVE_ROOF {
+        return false;
+    }
+    value_noise_3d(seed ^ CAVE_SALT, pos, 5).abs() < threshold
+        && value_noise_3d(seed ^ CAVE_SALT.rotate_left(17), pos, 5).abs() < threshold
+}
+
+// Nearest carved out block within `radius` blocks (horizontally) of `near` that has at
+// least two blocks of cave around it in every direction. Only every 4th block is
+// looked at, so this is cheap enough for a debug command.
+pub fn find_cavity(seed: u64, settings: &GenSettings, near: IVec3, radius: i32) -> Option<IVec3> {
+    const STEP: usize = 4;
+    const CLEARANCE: i32 = 2;
+    let carved = |pos: IVec3| is_carved(seed, settings, pos, terrain_height(seed, pos.x, pos.z));
+
+    let mut nearest: Option<(i32, IVec3)> = None;
+    for dz in (-radius..=radius).step_by(STEP) {
+        for dx in (-radius..=radius).step_by(STEP) {
+            let (x, z) = (near.x + dx, near.z + dz);
+            for y in (1..terrain_height(seed, x, z)).step_by(STEP) {
+                let pos = IVec3::new(x, y, z);
+                let offset = pos - near;
+                let distance_sq = offset.dot(offset);
+                if nearest.is_some_and(|(nearest_sq, _)| nearest_sq <= distance_sq) {
+                    continue;
+                }
+                let directions = [IVec3::ZERO, IVec3::X, -IVec3::X, IVec3::Y, -IVec3::Y, IVec3::Z, -IVec3::Z];
+                if directions.iter().all(|&dir| carved(pos + dir * CLEARANCE)) {
+                    nearest = Some((distance_sq, pos));
+                }
+            }
+        }
+    }
+    nearest.map(|(_, pos)| pos)
+}
+
+// Smoothly interpolated random values at a grid of 2^shift blocks, in [-1, 1]
+fn value_noise(seed: u64, x: i32, z: i32, shift: u32) -> f32 {
+    let (cell_x, cell_z) = (x >> shift, z >> shift);
+    let scale = 1.0 / (1 << shift) as f32;
+    let fx = smoothstep((x - (cell_x << shift)) as f32 * scale);
+    let fz = smoothstep((z - (cell_z << shift)) as f32 * scale);
+
+    let v00 = lattice_value(seed, cell_x, cell_z);
+    let v10 = lattice_value(seed, cell_x + 1, cell_z);
+    let v01 = lattice_value(seed, cell_x, cell_z + 1);
+    let v11 = lattice_value(seed, cell_x + 1, cell_z + 1);
+
+    let v0 = v00 + (v10 - v00) * fx;
+    let v1 = v01 + (v11 - v01) * fx;
+    v0 + (v1 - v0) * fz
+}
+
+// 3D version of `value_noise()`
+fn value_noise_3d(seed: u64, pos: IVec3, shift: u32) -> f32 {
+    let cell = pos >> shift as i32;
+    let scale = 1.0 / (1 << shift) as f32;
+    let fx = smoothstep((pos.x - (cell.x << shift)) as f32 * scale);
+    let fy = smoothstep((pos.y - (cell.y << shift)) as f32 * scale);
+    let fz = smoothstep((pos.z - (cell.z << shift)) as f32 * scale);
+
+    let value = |dx: i32, dy: i32, dz: i32| {
+        let layer_seed = seed ^ ((cell.y + dy) as u32 as u64).wrapping_mul(0xD6E8_FEB8_6659_FD93);
+        lattice_value(layer_seed, cell.x + dx, cell.z + dz)
+    };
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let bottom = lerp(lerp(value(0, 0, 0), value(1, 0, 0), fx), lerp(value(0, 0, 1), value(1, 0, 1), fx), fz);
+    let top = lerp(lerp(value(0, 1, 0), value(1, 1, 0), fx), lerp(value(0, 1, 1), value(1, 1, 1), fx), fz);
+    lerp(bottom, top, fy)
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lattice_value(seed: u64, x: i32, z: i32) -> f32 {
+    let bits = hash(seed, x, z) >> 40; // 24 bits, exactly representable
+    bits as f32 * (2.0 / (1 << 24) as f32) - 1.0
+}
+
+// splitmix64 finalizer over the seed and coordinates
+fn hash(seed: u64, x: i32, z: i32) -> u64 {
+    let mut h = seed
+        ^ (x as u32 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (z as u32 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^ (h >> 31)
+}
+
+mod tests {
+    // If this fails after an intentional change to generation, update the hashes.
+    // Existing worlds will look different, so it shouldn't happen by accident.
+    #[test]
+    fn test_generation_snapshots() {
+        use glam::ivec3;
+        use super::generate_chunk;
+
+        // FNV-1a over the block ids
+        let hash_chunk = |blocks: &[u16]| {
+            let mut hash = 0xCBF2_9CE4_8422_2325u64;
+            for byte in blocks.iter().flat_map(|block| block.to_le_bytes()) {
+                hash = (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3);
+            }
+            hash
+        };
+
+        let snapshots = [
+            (0, ivec3(0, 2, 0), 0xDAD2_A392_57F3_FE8D),
+            (0, ivec3(-3, 3, 7), 0x8904_E9B0_B014_8A2C),
+            (0xDEAD_BEEF_0123_4567, ivec3(0, 5, 0), 0x1075_6A88_522D_84B4),
+            (0xDEAD_BEEF_0123_4567, ivec3(12, 3, -9), 0xD545_3F29_F375_916D),
+            (0xDEAD_BEEF_0123_4567, ivec3(-1000, 4, 2500), 0xF5BC_FD5C_CCC3_DD15),
+            (42, ivec3(5, 0, 5), 0x0B92_6FF3_4173_6325), // all stone
+            (42, ivec3(5, 8, 5), 0xB9D1_03FD_6854_A325), // all air
+        ];
+        for (seed, chunk_pos, expected) in snapshots {
+            let hash = hash_chunk(&generate_chunk(seed, chunk_pos)[..]);
+            assert_eq!(hash, expected, "seed {seed:#X}, chunk {chunk_pos}: got {hash:#018X}");
+        }
+    }
+
+    #[test]
+    fn test_chunks_follow_terrain_height() {
+        use glam::ivec3;
+        use super::{block_index, generate_chunk, is_carved, terrain_height, GenSettings, AIR, CHUNK_SIZE};
+
+        let seed = 0x1234_5678;
+        for (cx, cz) in [(0, 0), (-1, 0), (3, -8)] {
+            for cy in 2..7 {
+                let blocks = generate_chunk(seed, ivec3(cx, cy, cz));
+                for z in 0..CHUNK_SIZE {
+                    for x in 0..CHUNK_SIZE {
+                        let height = terrain_height(seed, cx * CHUNK_SIZE + x, cz * CHUNK_SIZE + z);
+                        for y in 0..CHUNK_SIZE {
+                            let pos = ivec3(cx, cy, cz) * CHUNK_SIZE + ivec3(x, y, z);
+                            let solid = blocks[block_index(x, y, z)] != AIR;
+                            let carved = is_carved(seed, &GenSettings::default(), pos, height);
+                            assert_eq!(solid, pos.y < height && !carved);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_cave_density() {
+        use glam::{ivec3, IVec3};
+        use super::{find_cavity, generate_chunk_with, GenSettings, AIR};
+
+        let seed = 0xCAFE;
+        let count_air = |density: f32| {
+            let settings = GenSettings { cave_density: density };
+            let mut air = 0;
+            for x in -4..4 {
+                for z in -4..4 {
+                    // Fully below the surface everywhere
+                    let blocks = generate_chunk_with(seed, ivec3(x, 0, z), &settings);
+                    air += blocks.iter().filter(|&&block| block == AIR).count();
+                }
+            }
+            air
+        };
+        assert_eq!(count_air(0.0), 0);
+        let (normal, dense) = (count_air(1.0), count_air(2.0));
+        assert!(normal > 0 && dense > normal, "normal {normal}, dense {dense}");
+
+        let settings = GenSettings::default();
+        let cavity = find_cavity(seed, &settings, ivec3(0, 20, 0), 128).expect("no caves nearby");
+        for dir in [IVec3::X, IVec3::Y, IVec3::Z] {
+            for pos in [cavity + dir * 2, cavity - dir * 2] {
+                let chunk = generate_chunk_with(seed, pos >> 4, &settings);
+                let local = pos & 15;
+                assert_eq!(chunk[super::block_index(local.x, local.y, local.z)], AIR);
+            }
+        }
+    }
+}
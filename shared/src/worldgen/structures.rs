@@ -0,0 +1,318 @@
+// Trees and boulders on top of the terrain. Structures may cross chunk borders: the
+// blocks (pieces) that fall outside of the chunk being generated are queued for their
+// own chunk in a `StructureQueue`. Where structures start only depends on the seed and
+// the terrain, and overlapping pieces are resolved with a fixed priority, so the world
+// comes out the same no matter in which order chunks are generated.
+
+use std::collections::{HashMap, HashSet};
+
+use glam::IVec3;
+
+use crate::{bits_and_bytes::{ByteReader, ByteWriter}, coords::BlockPos, protocol::MessageError};
+
+use super::{
+    hash, terrain_height, BlockId, AIR, CHUNK_SIZE, CHUNK_VOLUME, LEAVES, LOG, STONE,
+    WORLD_HEIGHT,
+};
+
+const STRUCTURE_SALT: u64 = 0x5354_5255_4354_5552;
+// Chances to start a structure per chunk column
+const ATTEMPTS_PER_COLUMN: u64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Piece {
+    pub pos: IVec3, // in blocks
+    pub block: BlockId,
+}
+
+// Which block stays when pieces overlap each other or the terrain. Being a strict order,
+// the result is the same no matter in which order pieces are merged in.
+fn priority(block: BlockId) -> u8 {
+    match block {
+        AIR => 0,
+        LEAVES => 1,
+        LOG => 2,
+        STONE => 3,
+        _ => 4,
+    }
+}
+
+pub fn merge(existing: BlockId, incoming: BlockId) -> BlockId {
+    if priority(incoming) > priority(existing) { incoming } else { existing }
+}
+
+pub fn chunk_of(pos: IVec3) -> IVec3 {
+    BlockPos(pos).chunk().0
+}
+
+// Pieces of all structures that start in the chunk at `chunk_pos`, including the ones
+// that end up in other chunks.
+pub fn structure_pieces(seed: u64, chunk_pos: IVec3) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    for attempt in 0..ATTEMPTS_PER_COLUMN {
+        // Every chunk of the column must come to the same conclusion, so each attempt
+        // gets its own random numbers
+        let mut rng = Rng(hash(seed ^ STRUCTURE_SALT ^ attempt, chunk_pos.x, chunk_pos.z));
+        let kind = rng.below(8);
+        let x = chunk_pos.x * CHUNK_SIZE + rng.below(CHUNK_SIZE as u32) as i32;
+        let z = chunk_pos.z * CHUNK_SIZE + rng.below(CHUNK_SIZE as u32) as i32;
+        let base = IVec3::new(x, terrain_height(seed, x, z), z);
+        if chunk_of(base).y != chunk_pos.y {
+            continue;
+        }
+
+        match kind {
+            0..=2 => tree(&mut rng, base, &mut pieces),
+            3 => boulder(&mut rng, base, &mut pieces),
+            _ => {}
+        }
+    }
+    pieces.retain(|piece| (0..WORLD_HEIGHT).contains(&piece.pos.y));
+    pieces
+}
+
+fn tree(rng: &mut Rng, base: IVec3, out: &mut Vec<Piece>) {
+    let trunk_height = 4 + rng.below(3) as i32;
+    for dy in 0..trunk_height {
+        out.push(Piece { pos: base + IVec3::new(0, dy, 0), block: LOG });
+    }
+
+    let top = base + IVec3::new(0, trunk_height, 0);
+    for dy in -2..=1 {
+        let radius: i32 = if dy < 0 { 2 } else { 1 };
+        for dz in -radius..=radius {
+            for dx in -radius..=radius {
+                // Some corners are cut off so that the crowns aren't all perfect boxes
+                if dx.abs() == radius && dz.abs() == radius && (radius == 1 || rng.below(2) == 0) {
+                    continue;
+                }
+                out.push(Piece { pos: top + IVec3::new(dx, dy, dz), block: LEAVES });
+            }
+        }
+    }
+}
+
+fn boulder(rng: &mut Rng, base: IVec3, out: &mut Vec<Piece>) {
+    let radius = 1 + rng.below(2) as i32;
+    for dy in -radius..=radius {
+        for dz in -radius..=radius {
+            for dx in -radius..=radius {
+                if dx * dx + dy * dy + dz * dz <= radius * radius + 1 {
+                    out.push(Piece { pos: base + IVec3::new(dx, dy, dz), block: STONE });
+                }
+            }
+        }
+    }
+}
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = hash(self.0, 0x2545_F491, 0x4F6C_DD1D);
+        self.0
+    }
+
+    fn below(&mut self, n: u32) -> u32 {
+        ((self.next() >> 32) % n as u64) as u32
+    }
+}
+
+// Terrain and structures of the chunk at `chunk_pos` on its own, the same as what a
+// StructureQueue ends up with once the chunks around it are generated too. Regenerates the
+// structures of the neighboring chunks to find the pieces that reach into this one, so it
+// costs more than generating with a queue, but needs no state.
+pub fn generate_standalone_chunk(seed: u64, chunk_pos: IVec3) -> Box<[BlockId; CHUNK_VOLUME]> {
+    let mut blocks = super::generate_chunk(seed, chunk_pos);
+    // Structures are smaller than a chunk, so only the adjacent ones reach in
+    for dy in -1..=1 {
+        for dz in -1..=1 {
+            for dx in -1..=1 {
+                for piece in structure_pieces(seed, chunk_pos + IVec3::new(dx, dy, dz)) {
+                    if chunk_of(piece.pos) == chunk_pos {
+                        let idx = BlockPos(piece.pos).local().index();
+                        blocks[idx] = merge(blocks[idx], piece.block);
+                    }
+                }
+            }
+        }
+    }
+    blocks
+}
+
+// Pieces waiting for their chunk to be generated. A chunk is meant to be generated only
+// once per world (after that it is loaded from disk), so the queue is saved together
+// with the world.
+#[derive(Default)]
+pub struct StructureQueue {
+    pending: HashMap<IVec3, Vec<Piece>>,
+    generated: HashSet<IVec3>,
+}
+
+impl StructureQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Terrain and structures of the chunk at `chunk_pos`. Also returns the pieces that
+    // belong to chunks that were generated earlier: the caller should merge those into
+    // the chunks with `merge()`.
+    pub fn generate_chunk(&mut self, seed: u64, chunk_pos: IVec3) -> (Box<[BlockId; CHUNK_VOLUME]>, Vec<Piece>) {
+        let mut blocks = super::generate_chunk(seed, chunk_pos);
+        let mut late_pieces = Vec::new();
+
+        self.generated.insert(chunk_pos);
+        let queued = self.pending.remove(&chunk_pos).unwrap_or_default();
+        for piece in queued.into_iter().chain(structure_pieces(seed, chunk_pos)) {
+            let target = chunk_of(piece.pos);
+            if target == chunk_pos {
+                let idx = BlockPos(piece.pos).local().index();
+                blocks[idx] = merge(blocks[idx], piece.block);
+            } else if self.generated.contains(&target) {
+                late_pieces.push(piece);
+            } else {
+                self.pending.entry(target).or_default().push(piece);
+            }
+        }
+        (blocks, late_pieces)
+    }
+
+    pub fn pending_chunks(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn save(&self) -> Vec<u8> {
+        let num_pieces = self.pending.values().map(Vec::len).sum::<usize>();
+        let mut buf = vec![0u8; 8 + self.generated.len() * 12 + num_pieces * 14];
+        let mut writer = ByteWriter::new(&mut buf);
+
+        writer.write_u32(self.generated.len() as u32);
+        for pos in &self.generated {
+            writer.write_i32(pos.x);
+            writer.write_i32(pos.y);
+            writer.write_i32(pos.z);
+        }
+        writer.write_u32(num_pieces as u32);
+        for piece in self.pending.values().flatten() {
+            writer.write_i32(piece.pos.x);
+            writer.write_i32(piece.pos.y);
+            writer.write_i32(piece.pos.z);
+            writer.write_u16(piece.block);
+        }
+        buf
+    }
+
+    pub fn load(bytes: &[u8]) -> Result<Self, MessageError> {
+        let mut reader = ByteReader::new(bytes);
+        let mut queue = Self::new();
+
+        for _ in 0..reader.try_read_u32()? {
+            let pos = IVec3::new(reader.try_read_i32()?, reader.try_read_i32()?, reader.try_read_i32()?);
+            queue.generated.insert(pos);
+        }
+        for _ in 0..reader.try_read_u32()? {
+            let pos = IVec3::new(reader.try_read_i32()?, reader.try_read_i32()?, reader.try_read_i32()?);
+            let block = reader.try_read_u16()?;
+            queue.pending.entry(chunk_of(pos)).or_default().push(Piece { pos, block });
+        }
+        Ok(queue)
+    }
+}
+
+mod tests {
+    // Generates the same area in different orders, saving and reloading the queue halfway
+    // through once, and checks that every chunk comes out identical.
+    #[test]
+    fn test_generation_order_does_not_matter() {
+        use std::collections::HashMap;
+        use glam::{ivec3, IVec3};
+        use super::{chunk_of, generate_standalone_chunk, merge, StructureQueue};
+        use crate::worldgen::{block_index, BlockId, CHUNK_SIZE, CHUNK_VOLUME, LEAVES, LOG};
+
+        let seed = 0xC0FF_EE00_1234;
+        let mut positions = Vec::new();
+        for x in -3..3 {
+            for z in -3..3 {
+                for y in 2..8 {
+                    positions.push(ivec3(x, y, z));
+                }
+            }
+        }
+
+        let generate_all = |order: &[IVec3], reload_at: Option<usize>| {
+            let mut queue = StructureQueue::new();
+            let mut chunks = HashMap::<IVec3, Box<[BlockId; CHUNK_VOLUME]>>::new();
+            for (i, &pos) in order.iter().enumerate() {
+                if reload_at == Some(i) {
+                    queue = StructureQueue::load(&queue.save()).unwrap();
+                }
+                let (blocks, late_pieces) = queue.generate_chunk(seed, pos);
+                chunks.insert(pos, blocks);
+                for piece in late_pieces {
+                    let chunk = chunks.get_mut(&chunk_of(piece.pos)).unwrap();
+                    let local = piece.pos & (CHUNK_SIZE - 1);
+                    let idx = block_index(local.x, local.y, local.z);
+                    chunk[idx] = merge(chunk[idx], piece.block);
+                }
+            }
+            chunks
+        };
+
+        let reference = generate_all(&positions, None);
+        assert!(reference.values().any(|blocks| blocks.contains(&LOG)));
+        assert!(reference.values().any(|blocks| blocks.contains(&LEAVES)));
+
+        let mut reversed = positions.clone();
+        reversed.reverse();
+        let mut shuffled = positions.clone();
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        for i in (1..shuffled.len()).rev() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            shuffled.swap(i, (state % (i as u64 + 1)) as usize);
+        }
+
+        for (order, reload_at) in [(&reversed, None), (&shuffled, None), (&shuffled, Some(shuffled.len() / 2))] {
+            let chunks = generate_all(order, reload_at);
+            for pos in &positions {
+                assert!(chunks[pos][..] == reference[pos][..], "chunk {pos} differs");
+            }
+        }
+
+        // Without a queue, for the chunks that had all of their neighbors generated
+        let inner = positions.iter().filter(|pos| {
+            let (min, max) = (ivec3(-3, 2, -3), ivec3(2, 7, 2));
+            pos.cmpgt(min).all() && pos.cmplt(max).all()
+        });
+        for pos in inner {
+            assert!(generate_standalone_chunk(seed, *pos)[..] == reference[pos][..], "standalone chunk {pos} differs");
+        }
+    }
+
+    #[test]
+    fn test_queue_save_roundtrip() {
+        use glam::ivec3;
+        use super::StructureQueue;
+
+        let mut queue = StructureQueue::new();
+        for x in 0..4 {
+            queue.generate_chunk(7, ivec3(x, 4, 0));
+        }
+        let loaded = StructureQueue::load(&queue.save()).unwrap();
+        assert_eq!(loaded.generated, queue.generated);
+        assert_eq!(loaded.pending.len(), queue.pending.len());
+        for (pos, pieces) in &queue.pending {
+            let mut expected = pieces.clone();
+            let mut got = loaded.pending[pos].clone();
+            expected.sort_by_key(|piece| (piece.pos.to_array(), piece.block));
+            got.sort_by_key(|piece| (piece.pos.to_array(), piece.block));
+            assert_eq!(got, expected);
+        }
+
+        let bytes = queue.save();
+        for end in 0..bytes.len() {
+            assert!(StructureQueue::load(&bytes[..end]).is_err());
+        }
+    }
+}
@@ -1,42 +1,400 @@
+use std::{path::{Path, PathBuf}, fs::File, io::{self, BufReader, BufWriter, Read, Write}};
 
-use std::{path::Path, fs::File};
-
-use anyhow::Result;
+use anyhow::{Result, bail};
 use lz4::block::CompressionMode;
 
+const USAGE: &str = "\
+Usage `./compress [options] <filepath> [output path]`
+
+Options:
+  -r, --recursive       Compress every file under <filepath>, mirroring the directory tree into [output path]
+  -s, --stream          Use the frame-based streaming format instead of one block (for files that don't fit in memory)
+  -v, --verify          Decompress the output again and compare checksums with the input
+  --zstd[=level]        Use zstd instead of lz4 (level defaults to 19)
+  --dict <path>         Use a zstd dictionary for (de)compression
+  --train-dict <path>   Train a zstd dictionary from the input files and write it to <path>";
+
+const LZ4_HC_LEVEL: u32 = 12;
+#[cfg(feature = "zstd")]
+const ZSTD_DEFAULT_LEVEL: i32 = 19;
+#[cfg(feature = "zstd")]
+const ZSTD_DICT_MAX_SIZE: usize = 112_640; // zstd's own default (110 KiB)
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Codec {
+    Lz4,
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+struct Options {
+    input: PathBuf,
+    output: Option<PathBuf>,
+    recursive: bool,
+    stream: bool,
+    verify: bool,
+    codec: Codec,
+    dict: Vec<u8>, // empty = no dictionary
+    train_dict: Option<PathBuf>,
+}
+
 fn main() -> Result<()> {
     let args: Vec<String> = std::env::args().skip(1).collect();
     if args.is_empty() {
-        println!("Usage `./compress <filepath> [output path]");
+        println!("{USAGE}");
         return Ok(());
     }
-    
-    let path = Path::new(&args[0]);
+
+    let opts = match parse_args(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            println!("{e}");
+            println!("{USAGE}");
+            return Ok(());
+        }
+    };
+
+    let path = opts.input.as_path();
     if !path.exists() {
         println!("Path doesn't exist");
         return Ok(());
     }
-    if !path.is_file() {
-        println!("Not a file");
+    if !opts.recursive && !path.is_file() {
+        println!("Not a file (use --recursive for directories)");
+        return Ok(());
+    }
+    if opts.recursive && !path.is_dir() {
+        println!("Not a directory");
         return Ok(());
     }
 
-    let file = std::fs::read(path)?;
-    let compressed = lz4::block::compress(&file, Some(CompressionMode::HIGHCOMPRESSION(12)), true)?;
-    println!("Original size: {}", file.len());
-    println!("Compressed size: {}", compressed.len());
-    println!("Compression ratio: {:3}", file.len() as f32 / compressed.len() as f32);
-
-    if args.len() >= 2 {
-        let out_path = Path::new(&args[1]);
-        if out_path.is_dir() {
+    let mut files = Vec::new();
+    if opts.recursive {
+        collect_files(path, &mut files)?;
+    } else {
+        files.push(path.to_path_buf());
+    }
+
+    if let Some(dict_out) = &opts.train_dict {
+        return train_dictionary(&files, dict_out);
+    }
+
+    if let Some(out_path) = &opts.output {
+        if opts.recursive && out_path.is_file() {
+            println!("Output path must be a directory in recursive mode!");
+            return Ok(());
+        }
+        if !opts.recursive && out_path.is_dir() {
             println!("Output path is a directory!");
             return Ok(());
         }
+    } else if opts.stream && opts.verify {
+        println!("--verify needs an output path in streaming mode");
+        return Ok(());
+    }
+
+    let mut total_in = 0;
+    let mut total_out = 0;
+    for file in &files {
+        let out_path = match &opts.output {
+            Some(out) if opts.recursive => Some(out.join(file.strip_prefix(path)?)),
+            Some(out) => Some(out.clone()),
+            None => None,
+        };
+
+        if let Some(parent) = out_path.as_ref().and_then(|p| p.parent()) {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        if files.len() > 1 {
+            println!("{}:", file.display());
+        }
+
+        let stats = if opts.stream {
+            compress_streaming(file, out_path.as_deref(), &opts)?
+        } else {
+            compress_block(file, out_path.as_deref(), &opts)?
+        };
+
+        println!("Original size: {}", stats.original_size);
+        println!("Compressed size: {}", stats.compressed_size);
+        println!("Compression ratio: {:3}", stats.original_size as f32 / stats.compressed_size as f32);
+        if let Some(out_path) = &out_path {
+            println!("Wrote to {}", out_path.display());
+        }
+
+        total_in += stats.original_size;
+        total_out += stats.compressed_size;
+    }
+
+    if files.len() > 1 {
+        println!("Total: {} files, {} -> {} bytes (ratio {:3})",
+            files.len(), total_in, total_out, total_in as f32 / total_out as f32
+        );
+    }
+
+    Ok(())
+}
+
+fn parse_args(args: &[String]) -> Result<Options> {
+    let mut opts = Options {
+        input: PathBuf::new(),
+        output: None,
+        recursive: false,
+        stream: false,
+        verify: false,
+        codec: Codec::Lz4,
+        dict: Vec::new(),
+        train_dict: None,
+    };
+
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-r" | "--recursive" => opts.recursive = true,
+            "-s" | "--stream" => opts.stream = true,
+            "-v" | "--verify" => opts.verify = true,
+            #[cfg(feature = "zstd")]
+            "--zstd" => opts.codec = Codec::Zstd(ZSTD_DEFAULT_LEVEL),
+            #[cfg(feature = "zstd")]
+            level if level.starts_with("--zstd=") => {
+                let level = &level["--zstd=".len()..];
+                match level.parse() {
+                    Ok(level) => opts.codec = Codec::Zstd(level),
+                    Err(_) => bail!("Invalid zstd level '{level}'"),
+                }
+            }
+            "--dict" | "--train-dict" => {
+                let Some(path) = iter.next() else {
+                    bail!("{arg} needs a path");
+                };
+                if cfg!(not(feature = "zstd")) {
+                    bail!("{arg} requires the compressor to be built with `--features zstd`");
+                }
+                match arg.as_str() {
+                    "--dict" => opts.dict = std::fs::read(path)?,
+                    _ => opts.train_dict = Some(PathBuf::from(path)),
+                }
+            }
+            #[cfg(not(feature = "zstd"))]
+            zstd if zstd.starts_with("--zstd") => {
+                bail!("{zstd} requires the compressor to be built with `--features zstd`");
+            }
+            flag if flag.starts_with('-') => bail!("Unknown option '{flag}'"),
+            _ => positional.push(arg),
+        }
+    }
+
+    match positional[..] {
+        [input] => opts.input = PathBuf::from(input),
+        [input, output] => {
+            opts.input = PathBuf::from(input);
+            opts.output = Some(PathBuf::from(output));
+        }
+        [] => bail!("No input path given"),
+        _ => bail!("Too many arguments"),
+    }
+
+    if !opts.dict.is_empty() && opts.codec == Codec::Lz4 {
+        bail!("--dict only works with --zstd");
+    }
+
+    Ok(opts)
+}
+
+fn collect_files(dir: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.path()); // deterministic output order
+
+    for entry in entries {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, out)?;
+        } else if path.is_file() {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+struct Stats {
+    original_size: u64,
+    compressed_size: u64,
+}
+
+fn compress_block(path: &Path, out_path: Option<&Path>, opts: &Options) -> Result<Stats> {
+    let file = std::fs::read(path)?;
+    let compressed = match opts.codec {
+        Codec::Lz4 => lz4::block::compress(&file, Some(CompressionMode::HIGHCOMPRESSION(LZ4_HC_LEVEL as i32)), true)?,
+        #[cfg(feature = "zstd")]
+        Codec::Zstd(level) => zstd::bulk::Compressor::with_dictionary(level, &opts.dict)?.compress(&file)?,
+    };
+
+    if opts.verify {
+        let decompressed = match opts.codec {
+            Codec::Lz4 => lz4::block::decompress(&compressed, None)?,
+            #[cfg(feature = "zstd")]
+            Codec::Zstd(_) => zstd::bulk::Decompressor::with_dictionary(&opts.dict)?.decompress(&compressed, file.len())?,
+        };
+        verify_checksums(
+            Checksum::of(&file),
+            Checksum::of(&decompressed)
+        )?;
+    }
 
+    if let Some(out_path) = out_path {
         std::fs::write(out_path, &compressed)?;
-        println!("Wrote to {}", out_path.to_str().unwrap());
     }
 
+    Ok(Stats {
+        original_size: file.len() as u64,
+        compressed_size: compressed.len() as u64,
+    })
+}
+
+fn compress_streaming(path: &Path, out_path: Option<&Path>, opts: &Options) -> Result<Stats> {
+    let mut reader = HashingReader {
+        inner: BufReader::new(File::open(path)?),
+        checksum: Checksum::new(),
+    };
+
+    let writer: Box<dyn Write> = match out_path {
+        Some(out_path) => Box::new(BufWriter::new(File::create(out_path)?)),
+        None => Box::new(io::sink()),
+    };
+    let mut writer = CountingWriter { inner: writer, bytes_written: 0 };
+
+    match opts.codec {
+        Codec::Lz4 => {
+            let mut encoder = lz4::EncoderBuilder::new()
+                .level(LZ4_HC_LEVEL)
+                .build(&mut writer)?;
+            io::copy(&mut reader, &mut encoder)?;
+            let (_, result) = encoder.finish();
+            result?;
+        }
+        #[cfg(feature = "zstd")]
+        Codec::Zstd(level) => {
+            let mut encoder = zstd::stream::Encoder::with_dictionary(&mut writer, level, &opts.dict)?;
+            io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?;
+        }
+    }
+    writer.flush()?;
+
+    let stats = Stats {
+        original_size: reader.checksum.len,
+        compressed_size: writer.bytes_written,
+    };
+    drop(writer); // close the output file before reading it back
+
+    if let (true, Some(out_path)) = (opts.verify, out_path) {
+        let compressed = BufReader::new(File::open(out_path)?);
+        let mut decompressed = Checksum::new();
+        match opts.codec {
+            Codec::Lz4 => {
+                io::copy(&mut lz4::Decoder::new(compressed)?, &mut decompressed)?;
+            }
+            #[cfg(feature = "zstd")]
+            Codec::Zstd(_) => {
+                io::copy(&mut zstd::stream::Decoder::with_dictionary(compressed, &opts.dict)?, &mut decompressed)?;
+            }
+        }
+        verify_checksums(reader.checksum, decompressed)?;
+    }
+
+    Ok(stats)
+}
+
+#[cfg(feature = "zstd")]
+fn train_dictionary(samples: &[PathBuf], out_path: &Path) -> Result<()> {
+    println!("Training dictionary from {} samples...", samples.len());
+    let dict = zstd::dict::from_files(samples, ZSTD_DICT_MAX_SIZE)?;
+    std::fs::write(out_path, &dict)?;
+    println!("Wrote {} byte dictionary to {}", dict.len(), out_path.display());
+    Ok(())
+}
+
+#[cfg(not(feature = "zstd"))]
+fn train_dictionary(_: &[PathBuf], _: &Path) -> Result<()> {
+    unreachable!("--train-dict is rejected by parse_args() without the zstd feature")
+}
+
+fn verify_checksums(original: Checksum, roundtrip: Checksum) -> Result<()> {
+    if original != roundtrip {
+        bail!("Verification FAILED: input {:016x} ({} bytes), decompressed {:016x} ({} bytes)",
+            original.hash, original.len, roundtrip.hash, roundtrip.len
+        );
+    }
+    println!("Verified: checksum {:016x} matches", original.hash);
     Ok(())
 }
+
+// 64-bit FNV-1a. Not cryptographic, only meant to catch broken round-trips.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Checksum {
+    hash: u64,
+    len: u64,
+}
+
+impl Checksum {
+    fn new() -> Self {
+        Self { hash: 0xcbf2_9ce4_8422_2325, len: 0 }
+    }
+
+    fn of(bytes: &[u8]) -> Self {
+        let mut checksum = Self::new();
+        checksum.update(bytes);
+        checksum
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.hash ^= b as u64;
+            self.hash = self.hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        self.len += bytes.len() as u64;
+    }
+}
+
+impl Write for Checksum {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct HashingReader<R> {
+    inner: R,
+    checksum: Checksum,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.checksum.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+struct CountingWriter<W> {
+    inner: W,
+    bytes_written: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes_written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,162 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use glam::IVec3;
+use shared::{
+    bits_and_bytes::ByteReader,
+    chunk_format::{self, Chunk, Schematic, WORLD_MAGIC, WORLD_VERSION},
+    coords::{BlockPos, ChunkPos},
+    worldgen::{BlockId, AIR, CHUNK_VOLUME, WORLD_HEIGHT},
+};
+
+const USAGE: &str = "\
+Usage `./schem <command>`
+
+Works on schematics (as written by the server's /schem export) and world files offline. Stop the
+server before pasting into its world, or it will overwrite the change on its next save.
+
+Commands:
+  info <schematic>                                  Size and block counts
+  extract <world> <x1 y1 z1> <x2 y2 z2> <schematic> Cut the box between two corners out of a world
+  paste <schematic> <world> <x y z>                 Paste with the minimum corner at the position,
+                                                    creating the world file if there is none";
+
+type Chunks = HashMap<ChunkPos, Chunk>;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let coords = |args: &[&str]| -> Result<IVec3> {
+        match args.iter().map(|arg| arg.parse()).collect::<Result<Vec<i32>, _>>().as_deref() {
+            Ok(&[x, y, z]) => Ok(IVec3::new(x, y, z)),
+            _ => bail!("Expected x y z, got '{}'", args.join(" ")),
+        }
+    };
+
+    match args.as_slice() {
+        ["info", schematic] => info(Path::new(schematic)),
+        ["extract", world, corners @ .., schematic] if corners.len() == 6 => {
+            let (a, b) = (coords(&corners[..3])?, coords(&corners[3..])?);
+            extract(Path::new(world), a, b, Path::new(schematic))
+        }
+        ["paste", schematic, world, origin @ ..] if origin.len() == 3 => {
+            paste(Path::new(schematic), Path::new(world), coords(origin)?)
+        }
+        _ => {
+            println!("{USAGE}");
+            Ok(())
+        }
+    }
+}
+
+fn info(path: &Path) -> Result<()> {
+    let schematic = read_schematic(path)?;
+    let size = schematic.size();
+    let mut counts = HashMap::<BlockId, u64>::new();
+    for y in 0..size.y {
+        for z in 0..size.z {
+            for x in 0..size.x {
+                *counts.entry(schematic.block_at(IVec3::new(x, y, z))).or_default() += 1;
+            }
+        }
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_unstable_by_key(|&(block, count)| (std::cmp::Reverse(count), block));
+
+    println!("{}: {}x{}x{} blocks", path.display(), size.x, size.y, size.z);
+    for (block, count) in counts {
+        let name = if block == AIR { " (air)" } else { "" };
+        println!("  block {block}{name}: {count}");
+    }
+    Ok(())
+}
+
+fn extract(world_path: &Path, a: IVec3, b: IVec3, out: &Path) -> Result<()> {
+    let world = read_world(world_path)?;
+    let (min, max) = (a.min(b), a.max(b));
+    let size = max - min + 1;
+    let Some(mut schematic) = Schematic::new(size) else {
+        bail!("A schematic can't be {}x{}x{} blocks", size.x, size.y, size.z);
+    };
+    for y in 0..size.y {
+        for z in 0..size.z {
+            for x in 0..size.x {
+                let pos = IVec3::new(x, y, z);
+                let world_pos = BlockPos(min + pos);
+                let block = world.get(&world_pos.chunk()).map_or(AIR, |chunk| chunk[world_pos.local().index()]);
+                schematic.set_block(pos, block);
+            }
+        }
+    }
+    fs::write(out, schematic.encode()).with_context(|| format!("Failed to write {}", out.display()))?;
+    println!("Wrote {}x{}x{} blocks from {} to {}", size.x, size.y, size.z, world_path.display(), out.display());
+    Ok(())
+}
+
+fn paste(schematic_path: &Path, world_path: &Path, origin: IVec3) -> Result<()> {
+    let schematic = read_schematic(schematic_path)?;
+    let mut world = match world_path.exists() {
+        true => read_world(world_path)?,
+        false => Chunks::new(),
+    };
+    let size = schematic.size();
+    let mut changed = 0;
+    for y in 0..size.y {
+        for z in 0..size.z {
+            for x in 0..size.x {
+                let pos = BlockPos(origin + IVec3::new(x, y, z));
+                if pos.0.y < 0 || pos.0.y >= WORLD_HEIGHT {
+                    continue;
+                }
+                let block = schematic.block_at(IVec3::new(x, y, z));
+                let chunk = world.entry(pos.chunk()).or_insert_with(|| Box::new([AIR; CHUNK_VOLUME]));
+                let old = std::mem::replace(&mut chunk[pos.local().index()], block);
+                changed += (old != block) as u64;
+            }
+        }
+    }
+
+    let mut out = WORLD_MAGIC.to_vec();
+    out.extend(WORLD_VERSION.to_le_bytes());
+    chunk_format::write_chunks(&mut out, world.iter().map(|(&pos, chunk)| (pos, &**chunk)).collect());
+    // Same as the server: next to it first, so that a failed write leaves the old world intact
+    let mut temp = world_path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, out).with_context(|| format!("Failed to write {}", Path::new(&temp).display()))?;
+    fs::rename(&temp, world_path).with_context(|| format!("Failed to replace {}", world_path.display()))?;
+    println!("Pasted {} into {} at {origin}, {changed} blocks changed", schematic_path.display(), world_path.display());
+    Ok(())
+}
+
+fn read_schematic(path: &Path) -> Result<Schematic> {
+    let file = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Schematic::decode(&file).with_context(|| format!("{} is not a valid schematic", path.display()))
+}
+
+// Only the current version: older ones are migrated by loading them in the server once
+fn read_world(path: &Path) -> Result<Chunks> {
+    let file = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let (magic, rest) = file.split_at(WORLD_MAGIC.len().min(file.len()));
+    if magic != WORLD_MAGIC || rest.len() < 4 {
+        bail!("{} is not a world file", path.display());
+    }
+    let (version, body) = rest.split_at(4);
+    let version = u32::from_le_bytes(version.try_into().unwrap());
+    if version != WORLD_VERSION {
+        bail!(
+            "{} is in world format version {version}, but this tool only reads version {WORLD_VERSION}. \
+             Start the server with it once to migrate it, or update the tool.",
+            path.display()
+        );
+    }
+    let mut reader = ByteReader::new(body);
+    let mut chunks = Chunks::new();
+    chunk_format::read_chunks(&mut reader, |pos, chunk| {
+        chunks.insert(pos, chunk);
+    })
+    .with_context(|| format!("{} is corrupt", path.display()))?;
+    if reader.bytes_remaining() > 0 {
+        bail!("{} is corrupt: {} unexpected bytes at the end", path.display(), reader.bytes_remaining());
+    }
+    Ok(chunks)
+}
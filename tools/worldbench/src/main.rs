@@ -0,0 +1,260 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::HashMap,
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
+use glam::{IVec3, Vec2, Vec3};
+use shared::{
+    coords::BlockPos,
+    worldgen::{self, BlockId, AIR, CHUNK_SIZE, CHUNK_VOLUME, WORLD_HEIGHT},
+};
+
+const USAGE: &str = "\
+Usage `./worldbench [options]`
+
+Generates and meshes every chunk within a radius of the origin, headless, and reports how fast
+that went and how big the meshes are.
+
+The mesher is a placeholder, a quad per exposed block face, since the client doesn't mesh chunks
+yet. The meshing numbers say how fast that goes, not what the game will do.
+
+Options:
+  -r, --radius <chunks>  Horizontal radius in chunks, all the way from bottom to top (default 8)
+  -s, --seed <seed>      World seed (default 0)
+  --csv <path>           Also append the results as a row to <path>, for comparing runs over time";
+
+const DEFAULT_RADIUS: i32 = 8;
+const CSV_HEADER: &str = "unix_time,seed,radius,chunks,gen_chunks_per_sec,mesh_chunks_per_sec,\
+    mesh_bytes_per_chunk,allocations,allocated_bytes,peak_bytes";
+
+// Counts what goes through the allocator, which is the benchmark's business as much as the time
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+static PEAK_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        let live = LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK_BYTES.fetch_max(live, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct AllocStats {
+    allocations: usize,
+    allocated_bytes: usize,
+    peak_bytes: usize, // most in use at once since the stats were reset
+}
+
+impl AllocStats {
+    fn reset() {
+        PEAK_BYTES.store(LIVE_BYTES.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    // Since the last reset, given the counts from back then
+    fn since(start: AllocStats) -> Self {
+        Self {
+            allocations: ALLOCATIONS.load(Ordering::Relaxed) - start.allocations,
+            allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed) - start.allocated_bytes,
+            peak_bytes: PEAK_BYTES.load(Ordering::Relaxed),
+        }
+    }
+
+    fn now() -> Self {
+        Self {
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+            allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+            peak_bytes: PEAK_BYTES.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Laid out like the client's terrain vertex (renderer/passes/terrain_pass.rs), so that the mesh
+// sizes reported here are what would be uploaded
+#[derive(Clone, Copy)]
+#[repr(C)]
+#[allow(dead_code)] // only the size matters here
+struct Vertex {
+    pos: Vec3,
+    col: Vec3,
+    uv: Vec2,
+}
+
+struct Options {
+    radius: i32,
+    seed: u64,
+    csv: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let opts = match parse_args(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            println!("{e}");
+            println!("{USAGE}");
+            return Ok(());
+        }
+    };
+
+    let height = WORLD_HEIGHT / CHUNK_SIZE;
+    let meshed = (-opts.radius..=opts.radius)
+        .flat_map(|z| (-opts.radius..=opts.radius).map(move |x| (x, z)))
+        .filter(|&(x, z)| x * x + z * z <= opts.radius * opts.radius)
+        .flat_map(|(x, z)| (0..height).map(move |y| IVec3::new(x, y, z)))
+        .collect::<Vec<_>>();
+    println!("Radius {} chunks, seed {}: {} chunks", opts.radius, opts.seed, meshed.len());
+
+    // Meshing a chunk needs its neighbors, so a ring of chunks one wider than the meshed area
+    // gets generated too. Only the meshed ones count towards the generation rate.
+    AllocStats::reset();
+    let start = AllocStats::now();
+    let gen_start = Instant::now();
+    let mut chunks = HashMap::with_capacity(meshed.len());
+    for &pos in &meshed {
+        chunks.insert(pos, worldgen::generate_chunk(opts.seed, pos));
+    }
+    let gen_time = gen_start.elapsed();
+    let gen_allocs = AllocStats::since(start);
+    for &pos in &meshed {
+        for dir in [IVec3::X, -IVec3::X, IVec3::Z, -IVec3::Z] {
+            let neighbor = pos + dir;
+            chunks.entry(neighbor).or_insert_with(|| worldgen::generate_chunk(opts.seed, neighbor));
+        }
+    }
+
+    AllocStats::reset();
+    let start = AllocStats::now();
+    let mesh_start = Instant::now();
+    let (mut vertices, mut indices, mut mesh_bytes, mut empty) = (Vec::new(), Vec::new(), 0, 0);
+    for &pos in &meshed {
+        vertices.clear();
+        indices.clear();
+        mesh_chunk(&chunks, pos, &mut vertices, &mut indices);
+        mesh_bytes += vertices.len() * std::mem::size_of::<Vertex>() + indices.len() * std::mem::size_of::<u32>();
+        empty += vertices.is_empty() as usize;
+    }
+    let mesh_time = mesh_start.elapsed();
+    let mesh_allocs = AllocStats::since(start);
+
+    let rate = |time: Duration| meshed.len() as f64 / time.as_secs_f64();
+    let bytes_per_chunk = mesh_bytes / meshed.len().max(1);
+    println!("Generation: {:.1?}, {:.0} chunks/s", gen_time, rate(gen_time));
+    println!("  {} allocations, {} bytes allocated, peak {} bytes in use", gen_allocs.allocations, gen_allocs.allocated_bytes, gen_allocs.peak_bytes);
+    println!("Meshing: {:.1?}, {:.0} chunks/s", mesh_time, rate(mesh_time));
+    println!("  {} allocations, {} bytes allocated, peak {} bytes in use", mesh_allocs.allocations, mesh_allocs.allocated_bytes, mesh_allocs.peak_bytes);
+    println!("Mesh size: {bytes_per_chunk} bytes per chunk on average, {empty} chunks without faces");
+
+    if let Some(path) = &opts.csv {
+        let new_file = !path.exists();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if new_file {
+            writeln!(file, "{CSV_HEADER}")?;
+        }
+        let unix_time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+        writeln!(file, "{unix_time},{},{},{},{:.1},{:.1},{bytes_per_chunk},{},{},{}",
+            opts.seed, opts.radius, meshed.len(), rate(gen_time), rate(mesh_time),
+            gen_allocs.allocations + mesh_allocs.allocations,
+            gen_allocs.allocated_bytes + mesh_allocs.allocated_bytes,
+            gen_allocs.peak_bytes.max(mesh_allocs.peak_bytes),
+        )?;
+        println!("Appended to {}", path.display());
+    }
+    Ok(())
+}
+
+fn parse_args(args: &[String]) -> Result<Options> {
+    let mut opts = Options { radius: DEFAULT_RADIUS, seed: 0, csv: None };
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-r" | "--radius" => match iter.next().map(|radius| radius.parse()) {
+                Some(Ok(radius)) if radius >= 0 => opts.radius = radius,
+                _ => bail!("--radius needs a number of chunks"),
+            },
+            "-s" | "--seed" => match iter.next().map(|seed| seed.parse()) {
+                Some(Ok(seed)) => opts.seed = seed,
+                _ => bail!("--seed needs a number"),
+            },
+            "--csv" => match iter.next() {
+                Some(path) => opts.csv = Some(PathBuf::from(path)),
+                None => bail!("--csv needs a path"),
+            },
+            flag => bail!("Unknown option '{flag}'"),
+        }
+    }
+    Ok(opts)
+}
+
+// Solid blocks next to air get a quad on that side. The client doesn't mesh chunks yet, so
+// this is the simplest mesher that gives it something to compare against; replace it with
+// the client's once there is one.
+fn mesh_chunk(chunks: &HashMap<IVec3, Box<[BlockId; CHUNK_VOLUME]>>, pos: IVec3, vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>) {
+    let blocks = &chunks[&pos];
+    let block_at = |local: IVec3| -> BlockId {
+        if local.cmpge(IVec3::ZERO).all() && local.cmplt(IVec3::splat(CHUNK_SIZE)).all() {
+            return blocks[worldgen::block_index(local.x, local.y, local.z)];
+        }
+        let world = BlockPos(pos * CHUNK_SIZE + local);
+        if world.0.y < 0 {
+            return BlockId::MAX; // never seen from below
+        }
+        chunks.get(&world.chunk().0).map_or(AIR, |chunk| chunk[world.local().index()])
+    };
+
+    // Per side: the normal, then two edges of the face, so that the corners are
+    // origin, +u, +u+v, +v in counter-clockwise order seen from outside
+    let sides = [
+        (IVec3::X, Vec3::Y, Vec3::Z, 0.8),
+        (-IVec3::X, Vec3::Z, Vec3::Y, 0.8),
+        (IVec3::Y, Vec3::Z, Vec3::X, 1.0),
+        (-IVec3::Y, Vec3::X, Vec3::Z, 0.5),
+        (IVec3::Z, Vec3::X, Vec3::Y, 0.65),
+        (-IVec3::Z, Vec3::Y, Vec3::X, 0.65),
+    ];
+    let origin = (pos * CHUNK_SIZE).as_vec3();
+    for y in 0..CHUNK_SIZE {
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let local = IVec3::new(x, y, z);
+                if blocks[worldgen::block_index(x, y, z)] == AIR {
+                    continue;
+                }
+                for (normal, u, v, shade) in sides {
+                    if block_at(local + normal) != AIR {
+                        continue;
+                    }
+                    // The corner of the face the edges start from
+                    let center = origin + local.as_vec3() + 0.5;
+                    let corner = center + normal.as_vec3() * 0.5 - (u + v) * 0.5;
+                    let first = vertices.len() as u32;
+                    for (offset, uv) in [(Vec3::ZERO, Vec2::ZERO), (u, Vec2::X), (u + v, Vec2::ONE), (v, Vec2::Y)] {
+                        vertices.push(Vertex { pos: corner + offset, col: Vec3::splat(shade), uv });
+                    }
+                    indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+                }
+            }
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use erupt::{vk, InstanceLoader};
-use gpu_alloc::{GpuAllocator, MemoryBlock, Request};
+use gpu_alloc::{GpuAllocator, MemoryBlock, MemoryPropertyFlags, Request};
 
-use crate::Device;
+use crate::{
+    registry::{Resource, ResourceRegistry},
+    Device,
+};
 use anyhow::{bail, Result};
 use gpu_alloc_erupt::{device_properties, EruptMemoryDevice};
 
@@ -15,6 +18,7 @@ pub struct Image {
     pub layers: u32,
     pub mip_levels: u32,
     pub mem: Option<MemoryBlock<vk::DeviceMemory>>,
+    pub tag: MemoryTag,
 }
 
 impl Image {
@@ -30,6 +34,7 @@ impl Image {
                 height: 0,
             },
             mem: None,
+            tag: MemoryTag::Other,
         }
     }
 }
@@ -38,6 +43,7 @@ pub struct Buffer {
     pub handle: vk::Buffer,
     pub size: u64,
     pub mem: Option<MemoryBlock<vk::DeviceMemory>>,
+    pub tag: MemoryTag,
 }
 
 impl Default for Buffer {
@@ -52,12 +58,101 @@ impl Buffer {
             handle: vk::Buffer::null(),
             size: 0,
             mem: None,
+            tag: MemoryTag::Other,
         }
     }
 }
 
+// What an allocation is used for. Only affects the statistics.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemoryTag {
+    Framebuffer,
+    Texture,
+    Uniform,
+    Staging,
+    Mesh,
+    Ui,
+    Text,
+    Debug,
+    Other,
+}
+
+impl MemoryTag {
+    pub const COUNT: usize = 9;
+    pub const ALL: [MemoryTag; Self::COUNT] = [
+        Self::Framebuffer,
+        Self::Texture,
+        Self::Uniform,
+        Self::Staging,
+        Self::Mesh,
+        Self::Ui,
+        Self::Text,
+        Self::Debug,
+        Self::Other,
+    ];
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MemoryUsage {
+    pub bytes: u64,
+    pub allocations: u32,
+}
+
+impl MemoryUsage {
+    fn add(&mut self, bytes: u64) {
+        self.bytes += bytes;
+        self.allocations += 1;
+    }
+
+    fn sub(&mut self, bytes: u64) {
+        self.bytes -= bytes;
+        self.allocations -= 1;
+    }
+}
+
+// Live allocations, as requested from the allocator (i.e. not counting the unused
+// parts of the memory blocks gpu_alloc has reserved from the driver).
+// Memory that is both device-local and host-visible (integrated GPUs, resizable BAR)
+// is counted in both.
+#[derive(Clone, Default, Debug)]
+pub struct AllocatorStats {
+    pub device_local: MemoryUsage,
+    pub host_visible: MemoryUsage,
+    pub by_tag: [MemoryUsage; MemoryTag::COUNT],
+}
+
+impl AllocatorStats {
+    pub fn of(&self, tag: MemoryTag) -> MemoryUsage {
+        self.by_tag[tag as usize]
+    }
+
+    fn on_alloc(&mut self, tag: MemoryTag, mem: &MemoryBlock<vk::DeviceMemory>) {
+        let size = mem.size();
+        if mem.props().contains(MemoryPropertyFlags::DEVICE_LOCAL) {
+            self.device_local.add(size);
+        }
+        if mem.props().contains(MemoryPropertyFlags::HOST_VISIBLE) {
+            self.host_visible.add(size);
+        }
+        self.by_tag[tag as usize].add(size);
+    }
+
+    fn on_dealloc(&mut self, tag: MemoryTag, mem: &MemoryBlock<vk::DeviceMemory>) {
+        let size = mem.size();
+        if mem.props().contains(MemoryPropertyFlags::DEVICE_LOCAL) {
+            self.device_local.sub(size);
+        }
+        if mem.props().contains(MemoryPropertyFlags::HOST_VISIBLE) {
+            self.host_visible.sub(size);
+        }
+        self.by_tag[tag as usize].sub(size);
+    }
+}
+
 pub struct VkAllocator {
     handle: VulkanAllocator,
+    stats: AllocatorStats,
+    registry: ResourceRegistry,
 }
 
 impl VkAllocator{
@@ -68,11 +163,53 @@ impl VkAllocator{
             VulkanAllocator::new(gpu_alloc::Config::i_am_prototyping(), props)
         };
 
-        Ok(VkAllocator { handle: allocator })
+        Ok(VkAllocator {
+            handle: allocator,
+            stats: AllocatorStats::default(),
+            registry: ResourceRegistry::default(),
+        })
+    }
+
+    pub fn stats(&self) -> &AllocatorStats {
+        &self.stats
+    }
+
+    // Prints a report of every buffer and image that hasn't been freed. Returns the number of leaks.
+    pub fn report_leaks(&self) -> usize {
+        self.registry.report_leaks()
+    }
+
+    pub fn report(&self) {
+        const MIB: f64 = 1024.0 * 1024.0;
+        let stats = &self.stats;
+        println!("GPU memory usage:");
+        println!(
+            "  device-local: {:.2} MiB in {} allocations",
+            stats.device_local.bytes as f64 / MIB,
+            stats.device_local.allocations
+        );
+        println!(
+            "  host-visible: {:.2} MiB in {} allocations",
+            stats.host_visible.bytes as f64 / MIB,
+            stats.host_visible.allocations
+        );
+        for tag in MemoryTag::ALL {
+            let usage = stats.of(tag);
+            if usage.allocations > 0 {
+                println!(
+                    "  {:?}: {:.2} MiB in {} allocations",
+                    tag,
+                    usage.bytes as f64 / MIB,
+                    usage.allocations
+                );
+            }
+        }
     }
 
     pub fn deallocate_image(&mut self, image: &mut Image, device: &Device) -> Result<()> {
         if let Some(mem) = image.mem.take() {
+            self.stats.on_dealloc(image.tag, &mem);
+            self.registry.unregister(Resource::Image(image.handle));
             unsafe {
                 device.destroy_image_view(image.view, None);
                 device.destroy_image(image.handle, None);
@@ -88,6 +225,8 @@ impl VkAllocator{
 
     pub fn deallocate_buffer(&mut self, buffer: &mut Buffer, device: &Device) -> Result<()> {
         if let Some(mem) = buffer.mem.take() {
+            self.stats.on_dealloc(buffer.tag, &mem);
+            self.registry.unregister(Resource::Buffer(buffer.handle));
             unsafe {
                 device.destroy_buffer(buffer.handle, None);
                 self.handle.dealloc(EruptMemoryDevice::wrap(device), mem);
@@ -131,6 +270,8 @@ impl VkAllocator{
         };
 
         let mem = unsafe { self.handle.alloc(EruptMemoryDevice::wrap(device), request) }?;
+        self.stats.on_alloc(alloc.tag, &mem);
+        self.registry.register(Resource::Buffer(buf), alloc.tag, mem.size());
 
         println!(
             "Allocated {} bytes of memory with alignment of {} and memory type {}. Offset: {}",
@@ -150,6 +291,7 @@ impl VkAllocator{
             handle: buf,
             size: request.size,
             mem: Some(mem),
+            tag: alloc.tag,
         })
     }
 
@@ -190,6 +332,8 @@ impl VkAllocator{
                 },
             )
         }?;
+        self.stats.on_alloc(alloc.tag, &img_mem);
+        self.registry.register(Resource::Image(img), alloc.tag, img_mem.size());
 
         unsafe {
             device
@@ -232,6 +376,7 @@ impl VkAllocator{
             layers: alloc.layers,
             mip_levels: alloc.mip_levels,
             mem: Some(img_mem),
+            tag: alloc.tag,
         })
     }
 }
@@ -242,6 +387,7 @@ pub struct BufferAllocation {
     pub size: usize,
     pub usage: UsageFlags,
     pub vk_usage: vk::BufferUsageFlags,
+    pub tag: MemoryTag,
 }
 
 pub struct ImageAllocation {
@@ -252,4 +398,5 @@ pub struct ImageAllocation {
     pub usage: UsageFlags,
     pub flags: vk::ImageAspectFlags,
     pub vk_usage: vk::ImageUsageFlags,
+    pub tag: MemoryTag,
 }
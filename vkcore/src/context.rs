@@ -1,10 +1,10 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use erupt::{vk, EntryLoader, InstanceLoader};
 use smallvec::SmallVec;
 use winit::window::Window;
 
 use crate::{
-    debug, pipeline::GraphicsPipelineBuilder, Device, FrameData, RenderPass,
+    debug, pipeline::{ComputePipelineBuilder, GraphicsPipelineBuilder}, Device, FrameData, RenderPass,
     RenderPassDescriptor, Swapchain, Uploader, VkAllocator,
 };
 
@@ -38,13 +38,25 @@ pub enum Validation {
     Enabled(DebugMsgType, DebugMsgSeverity),
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DisplayMode {
+    Sdr,
+    Hdr10, // Needs an HDR display; falls back to SDR
+    ScRgb, // Same, but 16-bit float with linear encoding
+}
+
 pub struct VkConfig<'a> {
     pub device: DeviceConfig<'a>,
     pub frames_in_flight: u32,
     pub present_mode: vk::PresentModeKHR,
+    pub display_mode: DisplayMode,
     /// vk::make_api_version(0, 1, 2, 0) for 1.2
     pub vulkan_api_version: u32,
     pub validation: Validation,
+    /// Overrides the automatic GPU choice: an index in the order the devices are listed
+    /// at startup, or (part of) a device name. Falls back to the automatic choice if
+    /// nothing suitable matches.
+    pub gpu: Option<&'a str>,
 }
 
 impl<'a> Default for VkConfig<'a> {
@@ -53,11 +65,13 @@ impl<'a> Default for VkConfig<'a> {
             device: Default::default(),
             frames_in_flight: 2,
             present_mode: vk::PresentModeKHR::FIFO_KHR,
+            display_mode: DisplayMode::Sdr,
             vulkan_api_version: vk::make_api_version(0, 1, 2, 0),
             validation: Validation::Enabled(
                 DebugMsgType::all(),
                 DebugMsgSeverity::WARN | DebugMsgSeverity::ERR | DebugMsgSeverity::INFO,
             ),
+            gpu: None,
         }
     }
 }
@@ -74,6 +88,7 @@ pub struct VkContext {
     pub frames: SmallVec<[FrameData; 3]>,
 
     pub present_mode: vk::PresentModeKHR,
+    pub display_mode: DisplayMode,
     pub frames_in_flight: u32,
 }
 
@@ -103,7 +118,7 @@ impl<'a> VkContext {
             .context("create_surface")?;
 
         debug!(validation, "4/5 Creating device");
-        let device = crate::init::device::create_device(&instance, surface, validation)
+        let device = crate::init::device::create_device(&instance, surface, &config)
             .context("create_device")?;
 
         debug!(validation, "5/5 Creating swapchain");
@@ -112,6 +127,7 @@ impl<'a> VkContext {
             &device,
             surface,
             config.present_mode,
+            config.display_mode,
             vk::SwapchainKHR::null(),
         )
         .context("create_swapchain")?;
@@ -132,6 +148,7 @@ impl<'a> VkContext {
             uploader,
             frames,
             present_mode: config.present_mode,
+            display_mode: config.display_mode,
             frames_in_flight: config.frames_in_flight,
         })
     }
@@ -144,6 +161,10 @@ impl<'a> VkContext {
         GraphicsPipelineBuilder::default(self)
     }
 
+    pub fn compute_pipeline_builder(&self) -> ComputePipelineBuilder {
+        ComputePipelineBuilder::default(self)
+    }
+
     pub fn recreate_swapchain(&mut self) -> Result<()> {
         unsafe {
             self.swapchain.destroy_self(&self.device);
@@ -154,6 +175,7 @@ impl<'a> VkContext {
             &self.device,
             self.swapchain.surface.handle,
             self.present_mode,
+            self.display_mode,
             vk::SwapchainKHR::null(),
         )
         .context("create_swapchain")?;
@@ -165,6 +187,10 @@ impl<'a> VkContext {
         self.uploader
             .destroy_self(&self.device, &mut self.allocator)?;
 
+        // Everything allocated should be gone by now. Tear down the rest anyway, the
+        // driver will reclaim the memory.
+        let leaks = self.allocator.report_leaks();
+
         unsafe {
             for frame in &self.frames {
                 frame.destroy_self(&self.device);
@@ -181,6 +207,10 @@ impl<'a> VkContext {
 
             self.instance.destroy_instance(None);
         }
+
+        if leaks > 0 {
+            bail!("{} buffers/images were not freed before destroying the context", leaks);
+        }
         Ok(())
     }
 }
@@ -0,0 +1,199 @@
+use erupt::vk;
+use smallvec::SmallVec;
+
+use crate::Device;
+
+use anyhow::Result;
+
+/*
+Descriptor sets without having to size a pool up front. Sets come from the newest pool; when
+it runs out, another one twice the size is created and the allocation retried there. Pools
+are only freed all at once, by `destroy_self()`, along with every set allocated from them.
+*/
+pub struct DescriptorAllocator {
+    pools: Vec<vk::DescriptorPool>,
+    next_pool_sets: u32,
+}
+
+impl DescriptorAllocator {
+    // Descriptors of each type per set, when sizing a pool. Most sets need fewer.
+    const POOL_RATIOS: [(vk::DescriptorType, u32); 5] = [
+        (vk::DescriptorType::UNIFORM_BUFFER, 2),
+        (vk::DescriptorType::STORAGE_BUFFER, 2),
+        (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4),
+        (vk::DescriptorType::INPUT_ATTACHMENT, 1),
+        (vk::DescriptorType::STORAGE_IMAGE, 1),
+    ];
+    const MAX_POOL_SETS: u32 = 4096;
+
+    pub fn new(initial_sets: u32) -> Self {
+        Self { pools: Vec::new(), next_pool_sets: initial_sets.max(1) }
+    }
+
+    pub fn allocate(&mut self, device: &Device, layout: vk::DescriptorSetLayout) -> Result<vk::DescriptorSet> {
+        Ok(self.allocate_many(device, &[layout])?[0])
+    }
+
+    // One set per layout, in the same order
+    pub fn allocate_many(
+        &mut self,
+        device: &Device,
+        layouts: &[vk::DescriptorSetLayout],
+    ) -> Result<SmallVec<[vk::DescriptorSet; 8]>> {
+        if let Some(&pool) = self.pools.last() {
+            match Self::allocate_from(device, pool, layouts) {
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {}
+                result => return Ok(result?),
+            }
+        }
+        let pool = self.grow(device, layouts.len() as u32)?;
+        Ok(Self::allocate_from(device, pool, layouts)?)
+    }
+
+    fn allocate_from(
+        device: &Device,
+        pool: vk::DescriptorPool,
+        layouts: &[vk::DescriptorSetLayout],
+    ) -> Result<SmallVec<[vk::DescriptorSet; 8]>, vk::Result> {
+        unsafe {
+            device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfoBuilder::new()
+                    .descriptor_pool(pool)
+                    .set_layouts(layouts),
+            )
+        }
+        .result()
+    }
+
+    fn grow(&mut self, device: &Device, min_sets: u32) -> Result<vk::DescriptorPool> {
+        let sets = self.next_pool_sets.max(min_sets);
+        let sizes = Self::POOL_RATIOS.map(|(ty, per_set)| {
+            vk::DescriptorPoolSizeBuilder::new()._type(ty).descriptor_count(per_set * sets)
+        });
+        let pool = unsafe {
+            device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfoBuilder::new().max_sets(sets).pool_sizes(&sizes),
+                None,
+            )
+        }
+        .result()?;
+
+        self.pools.push(pool);
+        self.next_pool_sets = (sets * 2).min(Self::MAX_POOL_SETS);
+        Ok(pool)
+    }
+
+    pub fn pool_count(&self) -> usize {
+        self.pools.len()
+    }
+
+    pub fn destroy_self(&mut self, device: &Device) {
+        for pool in self.pools.drain(..) {
+            unsafe {
+                device.destroy_descriptor_pool(pool, None);
+            }
+        }
+    }
+}
+
+// Bindings are numbered in the order they're added, from 0
+#[derive(Default)]
+pub struct DescriptorLayoutBuilder {
+    bindings: SmallVec<[vk::DescriptorSetLayoutBindingBuilder<'static>; 4]>,
+}
+
+impl DescriptorLayoutBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn binding(mut self, ty: vk::DescriptorType, stages: vk::ShaderStageFlags) -> Self {
+        self.bindings.push(
+            vk::DescriptorSetLayoutBindingBuilder::new()
+                .binding(self.bindings.len() as u32)
+                .descriptor_count(1)
+                .descriptor_type(ty)
+                .stage_flags(stages),
+        );
+        self
+    }
+
+    pub fn build(&self, device: &Device) -> Result<vk::DescriptorSetLayout> {
+        let layout = unsafe {
+            device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&self.bindings),
+                None,
+            )
+        }
+        .result()?;
+        Ok(layout)
+    }
+}
+
+// Collects descriptor writes, then makes them all with one vkUpdateDescriptorSets
+#[derive(Default)]
+pub struct DescriptorWriter {
+    images: SmallVec<[(vk::DescriptorSet, u32, vk::DescriptorType, vk::DescriptorImageInfoBuilder<'static>); 4]>,
+    buffers: SmallVec<[(vk::DescriptorSet, u32, vk::DescriptorType, vk::DescriptorBufferInfoBuilder<'static>); 4]>,
+}
+
+impl DescriptorWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Combined image sampler, in SHADER_READ_ONLY_OPTIMAL layout
+    pub fn sampled_image(mut self, set: vk::DescriptorSet, binding: u32, view: vk::ImageView, sampler: vk::Sampler) -> Self {
+        let info = vk::DescriptorImageInfoBuilder::new()
+            .image_view(view)
+            .sampler(sampler)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        self.images.push((set, binding, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, info));
+        self
+    }
+
+    // Storage image, in GENERAL layout, for compute shaders to write to
+    pub fn storage_image(mut self, set: vk::DescriptorSet, binding: u32, view: vk::ImageView) -> Self {
+        let info = vk::DescriptorImageInfoBuilder::new()
+            .image_view(view)
+            .sampler(vk::Sampler::null())
+            .image_layout(vk::ImageLayout::GENERAL);
+        self.images.push((set, binding, vk::DescriptorType::STORAGE_IMAGE, info));
+        self
+    }
+
+    // From the start of the buffer; `range` can be vk::WHOLE_SIZE
+    pub fn buffer(
+        mut self,
+        set: vk::DescriptorSet,
+        binding: u32,
+        ty: vk::DescriptorType,
+        buffer: vk::Buffer,
+        range: vk::DeviceSize,
+    ) -> Self {
+        let info = vk::DescriptorBufferInfoBuilder::new().buffer(buffer).offset(0).range(range);
+        self.buffers.push((set, binding, ty, info));
+        self
+    }
+
+    pub fn write(&self, device: &Device) {
+        let images = self.images.iter().map(|(set, binding, ty, info)| {
+            vk::WriteDescriptorSetBuilder::new()
+                .dst_set(*set)
+                .dst_binding(*binding)
+                .descriptor_type(*ty)
+                .image_info(std::slice::from_ref(info))
+        });
+        let buffers = self.buffers.iter().map(|(set, binding, ty, info)| {
+            vk::WriteDescriptorSetBuilder::new()
+                .dst_set(*set)
+                .dst_binding(*binding)
+                .descriptor_type(*ty)
+                .buffer_info(std::slice::from_ref(info))
+        });
+        let writes = images.chain(buffers).collect::<SmallVec<[_; 8]>>();
+        unsafe {
+            device.update_descriptor_sets(&writes, &[]);
+        }
+    }
+}
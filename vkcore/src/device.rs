@@ -6,6 +6,12 @@ pub struct Device {
     pub logical: Arc<erupt::DeviceLoader>,
     pub physical: vk::PhysicalDevice,
     pub integrated: bool,
+    pub name: String,
+    // Formatted the way the vendor does, see init::device
+    pub driver_version: String,
+    // Optional features that were available and got enabled
+    pub enabled_features: vk::PhysicalDeviceFeatures,
+    pub caps: DeviceCaps,
 
     pub queue: Queue,
 }
@@ -18,6 +24,41 @@ impl Deref for Device {
     }
 }
 
+// What the device supports beyond what is required of it. Optional features are enabled
+// when available, and anything using them should check here and do without otherwise,
+// rather than fail.
+#[derive(Clone, Copy, Debug)]
+pub struct DeviceCaps {
+    pub wireframe: bool, // fillModeNonSolid
+    pub wide_lines: bool,
+    pub max_anisotropy: f32, // 1.0 without anisotropic filtering
+    // Sample counts usable for both color and depth attachments
+    pub msaa_samples: vk::SampleCountFlags,
+    // A queue family with transfer but no graphics, for uploading in the background
+    pub transfer_queue_family: Option<u32>,
+}
+
+impl DeviceCaps {
+    pub fn max_msaa_samples(&self) -> u32 {
+        1 << (31 - self.msaa_samples.bits().max(1).leading_zeros())
+    }
+}
+
+impl std::fmt::Display for DeviceCaps {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        write!(
+            f,
+            "wireframe: {}, wide lines: {}, anisotropy: {}x, MSAA: up to {}x, transfer queue: {}",
+            yes_no(self.wireframe),
+            yes_no(self.wide_lines),
+            self.max_anisotropy,
+            self.max_msaa_samples(),
+            self.transfer_queue_family.map_or("shared".to_owned(), |idx| format!("family {idx}")),
+        )
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Queue {
     pub(crate) handle: vk::Queue, // deref to get access
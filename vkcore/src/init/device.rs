@@ -3,7 +3,7 @@ use std::{ffi::CStr, sync::Arc};
 use anyhow::{bail, Context, Result};
 use smallvec::SmallVec;
 
-use crate::{debug, Device, Queue, Validation};
+use crate::{debug, Device, DeviceCaps, Queue, Validation, VkConfig};
 
 use erupt::{self, vk, DeviceLoader, InstanceLoader};
 
@@ -11,21 +11,30 @@ struct GraphicsDeviceDetails {
     queue_idx: u32,
     physical_device: vk::PhysicalDevice,
     properties: vk::PhysicalDeviceProperties,
+    features: vk::PhysicalDeviceFeatures,
     extensions: SmallVec<[*const i8; 1]>,
 }
 
 pub(crate) fn create_device(
     instance: &InstanceLoader,
     surface: vk::SurfaceKHR,
-    validation: Validation,
+    config: &VkConfig,
 ) -> Result<Device> {
-    let gpu_details = pick_suitable_gpu(instance, surface)?;
+    let validation = config.validation;
+    let gpu_details = pick_suitable_gpu(instance, surface, config)?;
 
     let queue_info = &[vk::DeviceQueueCreateInfoBuilder::new()
         .queue_family_index(gpu_details.queue_idx)
         .queue_priorities(&[1.0])];
 
-    let features = vk::PhysicalDeviceFeaturesBuilder::new().fill_mode_non_solid(true);
+    let caps = detect_caps(instance, &gpu_details);
+    println!("Device capabilities: {caps}");
+
+    // None of these are required, see DeviceCaps
+    let features = vk::PhysicalDeviceFeaturesBuilder::new()
+        .fill_mode_non_solid(caps.wireframe)
+        .wide_lines(caps.wide_lines)
+        .sampler_anisotropy(caps.max_anisotropy > 1.0);
 
     let device_info = vk::DeviceCreateInfoBuilder::new()
         .queue_create_infos(queue_info)
@@ -45,32 +54,120 @@ pub(crate) fn create_device(
         logical: Arc::new(device),
         physical: gpu_details.physical_device,
         queue: graphics_queue,
-        integrated: gpu_details.properties.device_type != vk::PhysicalDeviceType::DISCRETE_GPU
+        integrated: gpu_details.properties.device_type != vk::PhysicalDeviceType::DISCRETE_GPU,
+        name: device_name(&gpu_details.properties),
+        driver_version: driver_version(&gpu_details.properties),
+        enabled_features: *features,
+        caps,
     })
 }
 
+fn detect_caps(instance: &InstanceLoader, details: &GraphicsDeviceDetails) -> DeviceCaps {
+    let (features, limits) = (&details.features, &details.properties.limits);
+    let queue_families =
+        unsafe { instance.get_physical_device_queue_family_properties(details.physical_device, None) };
+    let transfer_queue_family = queue_families
+        .iter()
+        .position(|props| {
+            props.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                && !props.queue_flags.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+        })
+        .map(|idx| idx as u32);
+
+    DeviceCaps {
+        wireframe: features.fill_mode_non_solid != 0,
+        wide_lines: features.wide_lines != 0,
+        max_anisotropy: if features.sampler_anisotropy != 0 { limits.max_sampler_anisotropy } else { 1.0 },
+        msaa_samples: limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts,
+        transfer_queue_family,
+    }
+}
+
+// Lists every GPU, since which one got picked (and why) is the first thing to know
+// from a bug report
 fn pick_suitable_gpu(
     instance: &InstanceLoader,
     surface: vk::SurfaceKHR,
+    config: &VkConfig,
 ) -> Result<GraphicsDeviceDetails> {
-    let opt = unsafe { instance.enumerate_physical_devices(None) }
+    let phys_devices = unsafe { instance.enumerate_physical_devices(None) }
         .map_err(|e| e)
-        .context("enumerate_physical_devices")?
-        .into_iter()
-        .filter_map(|phys_device| get_gpu_details_if_suitable(phys_device, instance, surface))
-        .max_by_key(rank_graphics_device);
-
-    match opt {
-        Some(details) => Ok(details),
-        None => bail!("Could not find a suitable GPU! (Is one installed?)"),
+        .context("enumerate_physical_devices")?;
+
+    let mut suitable = Vec::new();
+    for (idx, &phys_device) in phys_devices.iter().enumerate() {
+        let properties = unsafe { instance.get_physical_device_properties(phys_device) };
+        let details = get_gpu_details_if_suitable(phys_device, instance, surface)
+            .filter(|details| details.properties.api_version >= config.vulkan_api_version);
+        println!(
+            "GPU {idx}: {} ({:?}){}",
+            device_name(&properties),
+            properties.device_type,
+            if details.is_some() { "" } else { ", not suitable" }
+        );
+        suitable.extend(details.map(|details| (idx, details)));
     }
+
+    let chosen = match config.gpu {
+        Some(wanted) => {
+            let wanted_idx = wanted.parse::<usize>().ok();
+            let wanted_name = wanted.to_lowercase();
+            let found = suitable.iter().position(|(idx, details)| match wanted_idx {
+                Some(wanted_idx) => *idx == wanted_idx,
+                None => device_name(&details.properties).to_lowercase().contains(&wanted_name),
+            });
+            if found.is_none() {
+                eprintln!("No suitable GPU matches '{wanted}', picking one automatically");
+            }
+            found
+        }
+        None => None,
+    };
+    let chosen = chosen.or_else(|| {
+        (0..suitable.len()).max_by_key(|&i| rank_graphics_device(&suitable[i].1))
+    });
+
+    let Some(chosen) = chosen else {
+        bail!("Could not find a suitable GPU! (Is one installed?)");
+    };
+    let (idx, details) = suitable.swap_remove(chosen);
+    let properties = &details.properties;
+    println!(
+        "Using GPU {idx}: {}, driver {}, Vulkan {}.{}.{}",
+        device_name(properties),
+        driver_version(properties),
+        vk::api_version_major(properties.api_version),
+        vk::api_version_minor(properties.api_version),
+        vk::api_version_patch(properties.api_version),
+    );
+    Ok(details)
 }
 
+// Discrete over integrated over anything else; among those, one that can draw the
+// wireframe debug view
 fn rank_graphics_device(graphics_device: &GraphicsDeviceDetails) -> i32 {
-    match graphics_device.properties.device_type {
+    let device_type = match graphics_device.properties.device_type {
         vk::PhysicalDeviceType::DISCRETE_GPU => 2,
         vk::PhysicalDeviceType::INTEGRATED_GPU => 1,
         _ => 0,
+    };
+    device_type * 10 + (graphics_device.features.fill_mode_non_solid != 0) as i32
+}
+
+fn device_name(properties: &vk::PhysicalDeviceProperties) -> String {
+    unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }.to_string_lossy().into_owned()
+}
+
+// Vendors pack the driver version each their own way
+fn driver_version(properties: &vk::PhysicalDeviceProperties) -> String {
+    const NVIDIA: u32 = 0x10DE;
+    const INTEL: u32 = 0x8086;
+
+    let v = properties.driver_version;
+    match properties.vendor_id {
+        NVIDIA => format!("{}.{}.{}.{}", v >> 22, (v >> 14) & 0xFF, (v >> 6) & 0xFF, v & 0x3F),
+        INTEL if cfg!(windows) => format!("{}.{}", v >> 14, v & 0x3FFF),
+        _ => format!("{}.{}.{}", vk::api_version_major(v), vk::api_version_minor(v), vk::api_version_patch(v)),
     }
 }
 
@@ -79,7 +176,6 @@ fn get_gpu_details_if_suitable(
     instance: &InstanceLoader,
     surface: vk::SurfaceKHR,
 ) -> Option<GraphicsDeviceDetails> {
-    println!("Getting GPU details...");
     // 1. It has to support a) graphics and presentation and b) transfer. Might be in the same queue.
     // Noteworthy: graphics and compute imply transfer even if transfer bit is not set.
     let queue_family_props =
@@ -92,6 +188,7 @@ fn get_gpu_details_if_suitable(
 
     // 2. It has to support the desired features
     let properties = unsafe { instance.get_physical_device_properties(phys_device) };
+    let features = unsafe { instance.get_physical_device_features(phys_device) };
 
     // 3. It has to support the desired extensions
     // (this allocation could be moved outside the function, whatever)
@@ -119,6 +216,7 @@ fn get_gpu_details_if_suitable(
         queue_idx,
         physical_device: phys_device,
         properties,
+        features,
         extensions: desired_device_extensions,
     })
 }
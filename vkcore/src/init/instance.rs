@@ -1,11 +1,11 @@
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 
 use erupt::{cstr, EntryLoader, vk, InstanceLoader, SmallVec};
 
 use anyhow::{Result, Context};
 use winit::{window::Window};
 
-use crate::{VkConfig, Validation, temp_helper};
+use crate::{DisplayMode, VkConfig, Validation, temp_helper};
 
 pub(crate) fn create_instance(entry: &EntryLoader, window: &Window, config: &VkConfig) -> Result<InstanceLoader> {
     let app_name = CString::new("AVulkanApp")?;
@@ -28,6 +28,17 @@ pub(crate) fn create_instance(entry: &EntryLoader, window: &Window, config: &VkC
         instance_layers.push(cstr!("VK_LAYER_KHRONOS_validation"));
     }
 
+    // Needed for the HDR color spaces to be reported at all
+    if config.display_mode != DisplayMode::Sdr {
+        let available = unsafe { entry.enumerate_instance_extension_properties(None, None) }
+            .result()
+            .context("enumerate_instance_extension_properties")?;
+        let colorspace_ext = unsafe { CStr::from_ptr(vk::EXT_SWAPCHAIN_COLORSPACE_EXTENSION_NAME) };
+        if available.iter().any(|ext| unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) } == colorspace_ext) {
+            instance_extensions.push(vk::EXT_SWAPCHAIN_COLORSPACE_EXTENSION_NAME);
+        }
+    }
+
     let instance_info = vk::InstanceCreateInfoBuilder::new()
         .application_info(&app_info)
         .enabled_extension_names(&instance_extensions)
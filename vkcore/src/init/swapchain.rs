@@ -3,7 +3,28 @@ use erupt::{self, vk, InstanceLoader};
 use anyhow::{Result, Context, bail};
 use smallvec::SmallVec;
 
-use crate::{swapchain::Swapchain, Device, Surface};
+use crate::{swapchain::Swapchain, Device, DisplayMode, OutputTransfer, Surface};
+
+type FormatChoice = (vk::Format, vk::ColorSpaceKHR, OutputTransfer);
+
+// In order of preference
+const SDR_FORMATS: &[FormatChoice] = &[
+    (vk::Format::B8G8R8A8_UNORM, vk::ColorSpaceKHR::SRGB_NONLINEAR_KHR, OutputTransfer::None),
+    (vk::Format::R8G8B8A8_UNORM, vk::ColorSpaceKHR::SRGB_NONLINEAR_KHR, OutputTransfer::None),
+    (vk::Format::A2B10G10R10_UNORM_PACK32, vk::ColorSpaceKHR::SRGB_NONLINEAR_KHR, OutputTransfer::None),
+    (vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR_KHR, OutputTransfer::Linear),
+    (vk::Format::R8G8B8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR_KHR, OutputTransfer::Linear),
+];
+
+// Only reported if VK_EXT_swapchain_colorspace is enabled
+const HDR10_FORMATS: &[FormatChoice] = &[
+    (vk::Format::A2B10G10R10_UNORM_PACK32, vk::ColorSpaceKHR::HDR10_ST2084_EXT, OutputTransfer::Pq),
+    (vk::Format::A2R10G10B10_UNORM_PACK32, vk::ColorSpaceKHR::HDR10_ST2084_EXT, OutputTransfer::Pq),
+];
+
+const SCRGB_FORMATS: &[FormatChoice] = &[
+    (vk::Format::R16G16B16A16_SFLOAT, vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT, OutputTransfer::ScRgb),
+];
 
 // Errors if:
 //  1. No suitable surface format/present mode is found
@@ -16,9 +37,10 @@ pub(crate) fn create_swapchain(
     device: &Device,
     surface: vk::SurfaceKHR,
     desired_present_mode: vk::PresentModeKHR,
+    display_mode: DisplayMode,
     old_swapchain: vk::SwapchainKHR,
 ) -> Result<Swapchain> {
-    let surface_format = select_surface_format(instance, device, surface)?;
+    let (surface_format, transfer) = select_surface_format(instance, device, surface, display_mode)?;
     let present_mode = select_present_mode(instance, device, surface, desired_present_mode)?;
 
     let surface_capabilities =
@@ -79,6 +101,7 @@ pub(crate) fn create_swapchain(
         surface: Surface {
             handle: surface,
             format: surface_format,
+            transfer,
             extent: surface_capabilities.current_extent,
         },
         present_mode,
@@ -115,26 +138,36 @@ fn select_surface_format(
     instance: &InstanceLoader,
     device: &Device,
     surface: vk::SurfaceKHR,
-) -> Result<vk::SurfaceFormatKHR> {
+    display_mode: DisplayMode,
+) -> Result<(vk::SurfaceFormatKHR, OutputTransfer)> {
     let formats =
         unsafe { instance.get_physical_device_surface_formats_khr(device.physical, surface, None) }
             .map_err(|e| e).context("get_physical_device_surface_formats_khr")?;
 
-    let res = formats
-        .iter()
-        .find(|surface_format| {
-            println!("Found format {surface_format:?}");
-            surface_format.format == vk::Format::B8G8R8A8_UNORM
-                && surface_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR_KHR
-        });
-        //.or_else(|| formats.get(0));
+    for surface_format in &formats {
+        println!("Found format {surface_format:?}");
+    }
+
+    // HDR falls back to SDR if the display (or driver) doesn't support it
+    let preferred = match display_mode {
+        DisplayMode::Sdr => &[][..],
+        DisplayMode::Hdr10 => HDR10_FORMATS,
+        DisplayMode::ScRgb => SCRGB_FORMATS,
+    };
+
+    let res = preferred.iter().chain(SDR_FORMATS).find(|&&(format, color_space, _)| {
+        formats.iter().any(|f| f.format == format && f.color_space == color_space)
+    });
 
     match res {
-        Some(format) => {
-            println!("{format:?}");
-            Ok(*format)
+        Some(&(format, color_space, transfer)) => {
+            if display_mode != DisplayMode::Sdr && !preferred.iter().any(|&(f, _, _)| f == format) {
+                println!("{display_mode:?} not supported by the surface, falling back to SDR");
+            }
+            println!("Using {format:?} {color_space:?} ({transfer:?})");
+            Ok((vk::SurfaceFormatKHR { format, color_space }, transfer))
         }
-        None => bail!("select_surface_format: No surface formats found!")
+        None => bail!("select_surface_format: No supported surface formats found!")
     }
 }
 
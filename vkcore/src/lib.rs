@@ -1,7 +1,9 @@
 mod debug;
 mod init;
+mod registry;
 
 pub mod context;
+pub mod descriptors;
 pub mod device;
 pub mod render_pass;
 pub mod swapchain;
@@ -10,6 +12,7 @@ pub mod allocator;
 pub mod uploader;
 
 pub use context::*;
+pub use descriptors::*;
 pub use device::*;
 pub use render_pass::*;
 pub use swapchain::*;
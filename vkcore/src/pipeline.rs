@@ -37,6 +37,8 @@ pub struct GraphicsPipelineBuilder<'a> {
     scissor: vk::Rect2DBuilder<'a>,
     rasterizer: vk::PipelineRasterizationStateCreateInfoBuilder<'a>,
     color_blend_attachment: vk::PipelineColorBlendAttachmentStateBuilder<'a>,
+    blend_constants: [f32; 4],
+    frag_constants: &'a [u32],
     multisampling: vk::PipelineMultisampleStateCreateInfoBuilder<'a>,
     layout: vk::PipelineLayoutCreateInfoBuilder<'a>,
     depth_stencil: vk::PipelineDepthStencilStateCreateInfoBuilder<'a>,
@@ -70,6 +72,8 @@ impl<'a> GraphicsPipelineBuilder<'a> {
                 .extent(wnd_extent),
             rasterizer: Default::default(),
             color_blend_attachment: Default::default(),
+            blend_constants: [0.0; 4],
+            frag_constants: &[],
             multisampling: vk::PipelineMultisampleStateCreateInfoBuilder::new()
                 .sample_shading_enable(false)
                 .rasterization_samples(vk::SampleCountFlagBits::_1),
@@ -92,6 +96,12 @@ impl<'a> GraphicsPipelineBuilder<'a> {
         self
     }
 
+    // Fragment shader specialization constants, with constant_id = index
+    pub fn fragment_constants(&mut self, constants: &'a [u32]) -> &mut Self {
+        self.frag_constants = constants;
+        self
+    }
+
     pub fn scissor(&mut self, scissor: vk::Rect2DBuilder<'a>) -> &mut Self {
         self.scissor = scissor;
         self
@@ -161,6 +171,12 @@ impl<'a> GraphicsPipelineBuilder<'a> {
         self
     }
 
+    // Used by the CONSTANT_COLOR / CONSTANT_ALPHA blend factors
+    pub fn blend_constants(&mut self, constants: [f32; 4]) -> &mut Self {
+        self.blend_constants = constants;
+        self
+    }
+
     pub fn multisampling(
         &mut self,
         multisampling: vk::PipelineMultisampleStateCreateInfoBuilder<'a>,
@@ -181,6 +197,20 @@ impl<'a> GraphicsPipelineBuilder<'a> {
         let vert_shader = create_shader_module(self.vert_shader_code.unwrap(), device);
         let frag_shader = create_shader_module(self.frag_shader_code.unwrap(), device);
 
+        let frag_map_entries = (0..self.frag_constants.len() as u32)
+            .map(|id| vk::SpecializationMapEntry {
+                constant_id: id,
+                offset: id * 4,
+                size: 4,
+            })
+            .collect::<Vec<_>>();
+        let frag_specialization = vk::SpecializationInfo {
+            map_entry_count: frag_map_entries.len() as u32,
+            p_map_entries: frag_map_entries.as_ptr(),
+            data_size: self.frag_constants.len() * 4,
+            p_data: self.frag_constants.as_ptr() as *const _,
+        };
+
         let shader_stages = &[
             vk::PipelineShaderStageCreateInfoBuilder::new()
                 .stage(vk::ShaderStageFlagBits::VERTEX)
@@ -189,7 +219,8 @@ impl<'a> GraphicsPipelineBuilder<'a> {
             vk::PipelineShaderStageCreateInfoBuilder::new()
                 .stage(vk::ShaderStageFlagBits::FRAGMENT)
                 .module(frag_shader)
-                .name(&entry_point),
+                .name(&entry_point)
+                .specialization_info(&frag_specialization),
         ];
 
         let vertex_input = self.input_info;
@@ -204,7 +235,8 @@ impl<'a> GraphicsPipelineBuilder<'a> {
         let attachments = &[self.color_blend_attachment];
         let color_blending = vk::PipelineColorBlendStateCreateInfoBuilder::new()
             .logic_op_enable(false)
-            .attachments(attachments);
+            .attachments(attachments)
+            .blend_constants(self.blend_constants);
 
         let pipeline_layout =
             unsafe { device.create_pipeline_layout(&self.layout, None) }.result()?;
@@ -240,20 +272,21 @@ impl<'a> GraphicsPipelineBuilder<'a> {
     }
 }
 
+// Bound with vk::PipelineBindPoint::COMPUTE. Compute runs on the graphics queue, which
+// every device picked has to support compute on (see init::device).
 pub struct ComputePipelineBuilder<'a> {
     shader_code: Option<&'a [u8]>,
+    constants: &'a [u32],
     layout: vk::PipelineLayoutCreateInfoBuilder<'a>,
 
     vulkan: &'a VkContext,
 }
 
-#[allow(unused)]
 impl<'a> ComputePipelineBuilder<'a> {
     pub fn default(vk: &'a VkContext) -> ComputePipelineBuilder<'a> {
-        let wnd_extent = vk.swapchain.surface.extent;
-
         ComputePipelineBuilder {
             shader_code: None,
+            constants: &[],
             layout: Default::default(),
 
             vulkan: vk,
@@ -265,44 +298,87 @@ impl<'a> ComputePipelineBuilder<'a> {
         self
     }
 
+    // Specialization constants, with constant_id = index. Handy for the local size:
+    // `layout(local_size_x_id = 0) in;`
+    pub fn constants(&mut self, constants: &'a [u32]) -> &mut Self {
+        self.constants = constants;
+        self
+    }
+
     pub fn layout(&mut self, layout: vk::PipelineLayoutCreateInfoBuilder<'a>) -> &mut Self {
         self.layout = layout;
         self
     }
 
-    pub fn build(&self) -> Pipeline {
+    pub fn build(&self) -> Result<Pipeline> {
         let device = &self.vulkan.device;
 
-        let entry_point = CString::new("main").unwrap();
-        let shader = create_shader_module(self.shader_code.unwrap(), device);
-
-        let pipeline_layout = unsafe { device.create_pipeline_layout(&self.layout, None) }.unwrap();
+        let code = match self.shader_code {
+            Some(code) => code,
+            None => anyhow::bail!("compute pipeline without a shader"),
+        };
+        let entry_point = CString::new("main")?;
+        let shader = create_shader_module(code, device);
+
+        let map_entries = (0..self.constants.len() as u32)
+            .map(|id| vk::SpecializationMapEntry {
+                constant_id: id,
+                offset: id * 4,
+                size: 4,
+            })
+            .collect::<Vec<_>>();
+        let specialization = vk::SpecializationInfo {
+            map_entry_count: map_entries.len() as u32,
+            p_map_entries: map_entries.as_ptr(),
+            data_size: self.constants.len() * 4,
+            p_data: self.constants.as_ptr() as *const _,
+        };
+
+        let pipeline_layout = match unsafe { device.create_pipeline_layout(&self.layout, None) }.result() {
+            Ok(layout) => layout,
+            Err(e) => {
+                unsafe { device.destroy_shader_module(shader, None) };
+                return Err(e.into());
+            }
+        };
 
         let pipeline_infos = &[vk::ComputePipelineCreateInfoBuilder::new()
             .stage(
                 *vk::PipelineShaderStageCreateInfoBuilder::new()
                     .stage(vk::ShaderStageFlagBits::COMPUTE)
                     .module(shader)
-                    .name(&entry_point),
+                    .name(&entry_point)
+                    .specialization_info(&specialization),
             )
             .layout(pipeline_layout)];
 
         let pipeline = unsafe {
             device.create_compute_pipelines(vk::PipelineCache::null(), pipeline_infos, None)
         }
-        .unwrap()[0];
+        .result();
 
         unsafe {
             device.destroy_shader_module(shader, None);
         }
 
-        Pipeline {
-            handle: pipeline,
-            layout: pipeline_layout,
+        match pipeline {
+            Ok(pipelines) => Ok(Pipeline {
+                handle: pipelines[0],
+                layout: pipeline_layout,
+            }),
+            Err(e) => {
+                unsafe { device.destroy_pipeline_layout(pipeline_layout, None) };
+                Err(e.into())
+            }
         }
     }
 }
 
+// Workgroups needed to cover `items` with `local_size` invocations per group
+pub const fn group_count(items: u32, local_size: u32) -> u32 {
+    (items + local_size - 1) / local_size
+}
+
 fn create_shader_module(code: &[u8], device: &DeviceLoader) -> vk::ShaderModule {
     let decoded = erupt::utils::decode_spv(code).unwrap();
     let create_info = vk::ShaderModuleCreateInfoBuilder::new().code(&decoded);
@@ -0,0 +1,63 @@
+use std::{backtrace::Backtrace, collections::HashMap};
+
+use erupt::vk;
+
+use crate::MemoryTag;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum Resource {
+    Buffer(vk::Buffer),
+    Image(vk::Image),
+}
+
+struct LiveResource {
+    tag: MemoryTag,
+    size: u64,
+    // Only captured in debug builds; capturing is slow
+    backtrace: Option<Backtrace>,
+}
+
+// Every buffer and image allocated through VkAllocator that hasn't been freed yet.
+// Checked when the context is destroyed, so that a forgotten `destroy_self` shows up
+// as a leak report instead of a validation error (or nothing at all).
+#[derive(Default)]
+pub(crate) struct ResourceRegistry {
+    live: HashMap<Resource, LiveResource>,
+}
+
+impl ResourceRegistry {
+    pub fn register(&mut self, resource: Resource, tag: MemoryTag, size: u64) {
+        let backtrace = cfg!(debug_assertions).then(Backtrace::force_capture);
+        self.live.insert(resource, LiveResource { tag, size, backtrace });
+    }
+
+    pub fn unregister(&mut self, resource: Resource) {
+        if self.live.remove(&resource).is_none() {
+            eprintln!("[registry.rs]: {:?} was freed but never registered", resource);
+        }
+    }
+
+    // Prints everything still alive, grouped by tag. Returns the number of leaks.
+    pub fn report_leaks(&self) -> usize {
+        if self.live.is_empty() {
+            return 0;
+        }
+
+        eprintln!("[registry.rs]: {} GPU resources were not freed:", self.live.len());
+        for tag in MemoryTag::ALL {
+            let mut leaks = self.live.iter().filter(|(_, res)| res.tag == tag).peekable();
+            if leaks.peek().is_none() {
+                continue;
+            }
+            eprintln!("  {:?}:", tag);
+            for (resource, res) in leaks {
+                eprintln!("    {:?} ({} bytes)", resource, res.size);
+                match &res.backtrace {
+                    Some(backtrace) => eprintln!("      allocated at:\n{}", backtrace),
+                    None => eprintln!("      (build in debug mode to see where it was allocated)"),
+                }
+            }
+        }
+        self.live.len()
+    }
+}
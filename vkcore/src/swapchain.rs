@@ -8,9 +8,21 @@ use crate::{
 
 use anyhow::{Context, Result};
 
+// How the passes that write to the swapchain must encode their output, which is
+// sRGB-encoded to begin with. Given to the shaders as specialization constant 0.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum OutputTransfer {
+    None = 0,   // UNORM format, sRGB color space: write as-is
+    Linear = 1, // *_SRGB format: decode, the hardware encodes it again
+    ScRgb = 2,  // Extended linear sRGB, 1.0 = 80 nits
+    Pq = 3,     // HDR10: BT.2020 primaries, SMPTE ST 2084 curve
+}
+
 pub struct Surface {
     pub handle: vk::SurfaceKHR,
     pub format: vk::SurfaceFormatKHR,
+    pub transfer: OutputTransfer,
     pub extent: vk::Extent2D,
 }
 
@@ -1,12 +1,42 @@
+use std::collections::VecDeque;
+
 use erupt::vk;
 
 use anyhow::{bail, Result};
 use gpu_alloc::UsageFlags;
 use gpu_alloc_erupt::EruptMemoryDevice;
 
-use crate::{Buffer, BufferAllocation, Device, Image, VkAllocator};
+use crate::{Buffer, BufferAllocation, Device, Image, MemoryTag, VkAllocator};
 
 const STAGING_BUFFER_SIZE: usize = 1 << 24; // 16 MiB (same as Sodium)
+const DEFAULT_FRAME_BUDGET: usize = 1 << 22; // 4 MiB
+
+// Order in which queued uploads get their share of the per-frame budget. Uploads made
+// directly with the `upload_*` functions skip the queue and are always first in line.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum UploadPriority {
+    Ui,
+    NearChunks,
+    FarChunks,
+}
+
+impl UploadPriority {
+    pub const COUNT: usize = 3;
+    pub const ALL: [UploadPriority; Self::COUNT] = [Self::Ui, Self::NearChunks, Self::FarChunks];
+}
+
+struct QueuedUpload {
+    data: Vec<u8>,
+    dst: vk::Buffer,
+    dst_offset: u32,
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct UploadStats {
+    pub queued: [usize; UploadPriority::COUNT], // by priority
+    pub queued_bytes: usize,
+    pub last_frame_bytes: usize, // staged during the last frame, queued or not
+}
 
 #[derive(Clone, Copy)]
 enum MemCopyOp {
@@ -42,6 +72,10 @@ pub struct Uploader {
     pending_copy_ops: Vec<MemCopyOp>,
     pending_mip_gens: Vec<MipGenData>,
 
+    queues: [VecDeque<QueuedUpload>; UploadPriority::COUNT],
+    frame_budget: usize,
+    last_frame_bytes: usize,
+
     wait_needed: bool,
 }
 
@@ -67,6 +101,7 @@ impl Uploader {
                 size: STAGING_BUFFER_SIZE,
                 usage: UsageFlags::UPLOAD,
                 vk_usage: vk::BufferUsageFlags::TRANSFER_SRC,
+                tag: MemoryTag::Staging,
             },
         )?;
 
@@ -78,6 +113,9 @@ impl Uploader {
             staging_buffer_head: 0,
             pending_copy_ops: Vec::new(),
             pending_mip_gens: Vec::new(),
+            queues: Default::default(),
+            frame_budget: DEFAULT_FRAME_BUDGET,
+            last_frame_bytes: 0,
             wait_needed: false,
         })
     }
@@ -178,6 +216,102 @@ impl Uploader {
             return Ok(());
         }
 
+        self.stage_buffer_copy(device, data, dst_buf.handle, dst_buf_offset)
+    }
+
+    // Like `upload_bytes_to_buffer()`, but the copy is deferred until `process_queue()` finds
+    // room for it in a frame's budget. The buffer must not be freed before then, see
+    // `cancel_queued_uploads()`.
+    pub fn queue_upload(
+        &mut self,
+        device: &Device,
+        priority: UploadPriority,
+        data: Vec<u8>,
+        dst_buf: &mut Buffer,
+        dst_buf_offset: u32,
+    ) -> Result<()> {
+        if data.len() >= STAGING_BUFFER_SIZE {
+            bail!("Upload of {} bytes can never fit in the staging buffer", data.len());
+        }
+        let mem = match dst_buf.mem {
+            Some(ref mut mem) => mem,
+            None => {
+                bail!("Tried to queue upload to unallocated buffer!");
+            }
+        };
+        if mem
+            .props()
+            .contains(gpu_alloc::MemoryPropertyFlags::HOST_VISIBLE)
+        {
+            // Doesn't go through the staging buffer, so nothing to wait for
+            unsafe { mem.write_bytes(EruptMemoryDevice::wrap(device), dst_buf_offset as _, &data) }?;
+            return Ok(());
+        }
+
+        self.queues[priority as usize].push_back(QueuedUpload {
+            data,
+            dst: dst_buf.handle,
+            dst_offset: dst_buf_offset,
+        });
+        Ok(())
+    }
+
+    // Drops the uploads still queued for the buffer, to be called before freeing it
+    pub fn cancel_queued_uploads(&mut self, dst_buf: &Buffer) {
+        for queue in &mut self.queues {
+            queue.retain(|upload| upload.dst != dst_buf.handle);
+        }
+    }
+
+    pub fn set_frame_budget(&mut self, bytes: usize) {
+        self.frame_budget = bytes.min(STAGING_BUFFER_SIZE);
+    }
+
+    // Once per frame, after submitting it: stages queued uploads by priority until the budget
+    // (minus what was uploaded directly this frame) runs out, and submits everything staged.
+    // Whatever doesn't fit waits for the next frame. One upload always goes through if nothing
+    // else did, so that an upload bigger than the budget can't get stuck.
+    pub fn process_queue(&mut self, device: &Device) -> Result<()> {
+        let mut budget = self.frame_budget.saturating_sub(self.staging_buffer_head as usize);
+        let mut staged_any = self.staging_buffer_head > 0;
+        'queues: for priority in 0..UploadPriority::COUNT {
+            while let Some(len) = self.queues[priority].front().map(|upload| upload.data.len()) {
+                if len > budget && staged_any {
+                    break 'queues;
+                }
+                let upload = self.queues[priority].pop_front().unwrap();
+                self.stage_buffer_copy(device, &upload.data, upload.dst, upload.dst_offset)?;
+                budget = budget.saturating_sub(len);
+                staged_any = true;
+            }
+        }
+
+        self.last_frame_bytes = self.staging_buffer_head as usize;
+        if self.pending_copy_ops.is_empty() && self.pending_mip_gens.is_empty() {
+            return Ok(());
+        }
+        self.flush_staged(device)
+    }
+
+    pub fn stats(&self) -> UploadStats {
+        let mut stats = UploadStats {
+            last_frame_bytes: self.last_frame_bytes,
+            ..Default::default()
+        };
+        for (i, queue) in self.queues.iter().enumerate() {
+            stats.queued[i] = queue.len();
+            stats.queued_bytes += queue.iter().map(|upload| upload.data.len()).sum::<usize>();
+        }
+        stats
+    }
+
+    fn stage_buffer_copy(
+        &mut self,
+        device: &Device,
+        data: &[u8],
+        dst: vk::Buffer,
+        dst_offset: u32,
+    ) -> Result<()> {
         if self.staging_buffer_head as u64 + data.len() as u64 >= self.staging_buffer.size {
             bail!(
                 "Staging buffer ran out of space! Uploaded {} bytes, head was at {}/{}",
@@ -196,9 +330,9 @@ impl Uploader {
         }?;
 
         self.pending_copy_ops.push(MemCopyOp::Buf2Buffer {
-            dst: dst_buf.handle,
+            dst,
             src_offset: self.staging_buffer_head,
-            dst_offset: dst_buf_offset,
+            dst_offset,
             size: data.len() as _,
         });
         self.staging_buffer_head += data.len() as u32;